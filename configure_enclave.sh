@@ -115,8 +115,8 @@ echo "Instance will be named: $FINAL_INSTANCE_NAME"
 # Read endpoints from allowed_endpoints.yaml
 #########################################
 if [ -f "src/nautilus-server/allowed_endpoints.yaml" ]; then
-    # Use a small Python snippet to parse the YAML and emit space-separated endpoints
-    ENDPOINTS=$(yq e '.endpoints | join(" ")' src/nautilus-server/allowed_endpoints.yaml 2>/dev/null)
+    # Emit space-separated hosts; entries are bare hosts or maps with a host and its health probe
+    ENDPOINTS=$(yq e '[.endpoints[] | select(tag == "!!str")] + [.endpoints[] | select(tag == "!!map") | .host] | join(" ")' src/nautilus-server/allowed_endpoints.yaml 2>/dev/null)
     if [ -n "$ENDPOINTS" ]; then
         echo "Endpoints found in src/nautilus-server/allowed_endpoints.yaml (before region patching):"
         echo "$ENDPOINTS"
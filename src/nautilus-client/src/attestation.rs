@@ -0,0 +1,209 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parsing and checking of AWS Nitro attestation documents.
+//!
+//! The document is a COSE_Sign1 structure whose payload is a CBOR map. These checks bind
+//! the document to the enclave key and expected measurements; validating the COSE
+//! signature against the AWS Nitro root certificate chain is left to the caller.
+
+use crate::types::AttestationRef;
+use crate::ClientError;
+use base64::Engine;
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+
+/// Length of the build metadata hash the server attests in front of a caller's user data.
+pub const BUILD_HASH_LENGTH: usize = 32;
+/// Length of the truncated SHA3-256 hashes in an [AttestationRef].
+pub const ATTESTATION_REF_HASH_LENGTH: usize = 16;
+
+/// Decoded attestation document payload.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttestationDocument {
+    pub module_id: String,
+    pub digest: String,
+    pub timestamp: u64,
+    pub pcrs: BTreeMap<u32, ByteBuf>,
+    pub certificate: ByteBuf,
+    pub cabundle: Vec<ByteBuf>,
+    pub public_key: Option<ByteBuf>,
+    pub user_data: Option<ByteBuf>,
+    pub nonce: Option<ByteBuf>,
+}
+
+/// Expectations an attestation document must satisfy.
+#[derive(Debug, Clone, Default)]
+pub struct AttestationPolicy {
+    /// Expected PCR values by index.
+    pub pcrs: BTreeMap<u32, Vec<u8>>,
+    /// Expected Ed25519 public key the enclave signs responses with.
+    pub public_key: Option<Vec<u8>>,
+    /// Expected nonce, for challenge bound attestations.
+    pub nonce: Option<Vec<u8>>,
+}
+
+/// Decode the hex or base64 encoded document returned by `/get_attestation`.
+pub fn decode_document_bytes(encoded: &str) -> Result<Vec<u8>, ClientError> {
+    hex::decode(encoded)
+        .or_else(|_| base64::engine::general_purpose::STANDARD.decode(encoded))
+        .map_err(|_| ClientError::Attestation("Attestation document is neither hex nor base64".to_string()))
+}
+
+/// Parse a COSE_Sign1 attestation document and return its payload.
+pub fn parse_attestation_document(bytes: &[u8]) -> Result<AttestationDocument, ClientError> {
+    let cose: serde_cbor::Value = serde_cbor::from_slice(bytes)
+        .map_err(|e| ClientError::Attestation(format!("Invalid CBOR: {}", e)))?;
+    let cose = match cose {
+        serde_cbor::Value::Tag(_, inner) => *inner,
+        other => other,
+    };
+    let payload = match cose {
+        serde_cbor::Value::Array(items) if items.len() == 4 => match &items[2] {
+            serde_cbor::Value::Bytes(payload) => payload.clone(),
+            _ => return Err(ClientError::Attestation("COSE payload is not a byte string".to_string())),
+        },
+        _ => return Err(ClientError::Attestation("Document is not a COSE_Sign1 structure".to_string())),
+    };
+    serde_cbor::from_slice(&payload).map_err(|e| ClientError::Attestation(format!("Invalid attestation payload: {}", e)))
+}
+
+/// Check a parsed document against a policy.
+pub fn verify_attestation(document: &AttestationDocument, policy: &AttestationPolicy) -> Result<(), ClientError> {
+    for (index, expected) in &policy.pcrs {
+        match document.pcrs.get(index) {
+            Some(actual) if actual.as_slice() == expected.as_slice() => {}
+            Some(actual) => {
+                return Err(ClientError::Attestation(format!(
+                    "PCR{} mismatch: expected {}, got {}",
+                    index,
+                    hex::encode(expected),
+                    hex::encode(actual)
+                )))
+            }
+            None => return Err(ClientError::Attestation(format!("PCR{} missing from document", index))),
+        }
+    }
+    if let Some(expected) = &policy.public_key {
+        if document.public_key.as_deref().map(|pk| pk.as_slice()) != Some(expected.as_slice()) {
+            return Err(ClientError::Attestation(
+                "Attested public key does not match the enclave signing key".to_string(),
+            ));
+        }
+    }
+    if let Some(expected) = &policy.nonce {
+        if document.nonce.as_deref().map(|n| n.as_slice()) != Some(expected.as_slice()) {
+            return Err(ClientError::Attestation("Attestation nonce does not match".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Check that a fresh attestation returned with a signed response commits to it: the
+/// document `user_data` after the build metadata hash is the SHA3-256 of `signed_bytes`,
+/// the BCS intent message the response signature covers. Check the nonce and key with
+/// [verify_attestation].
+pub fn verify_response_binding(document: &AttestationDocument, signed_bytes: &[u8]) -> Result<(), ClientError> {
+    let user_data = document.user_data.as_deref().map(|d| d.as_slice()).unwrap_or_default();
+    let digest: [u8; 32] = Sha3_256::digest(signed_bytes).into();
+    if user_data.len() != BUILD_HASH_LENGTH + digest.len() || user_data[BUILD_HASH_LENGTH..] != digest {
+        return Err(ClientError::Attestation(
+            "Attestation user data does not commit to the signed response".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a signed response's `attestation_ref` names the boot attestation with
+/// `document` bytes (the decoded `attestationDocument` of `/boot_attestation`) and `pcr0`.
+pub fn verify_attestation_ref(reference: &AttestationRef, document: &[u8], pcr0: &[u8]) -> Result<(), ClientError> {
+    let short_hash = |bytes: &[u8]| hex::encode(&Sha3_256::digest(bytes)[..ATTESTATION_REF_HASH_LENGTH]);
+    if reference.document_hash != short_hash(document) || reference.pcr0_hash != short_hash(pcr0) {
+        return Err(ClientError::Attestation(
+            "Attestation reference does not match the boot attestation".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_cbor::Value;
+
+    fn document(public_key: &[u8], pcr0: &[u8]) -> Vec<u8> {
+        let mut pcrs = BTreeMap::new();
+        pcrs.insert(Value::Integer(0), Value::Bytes(pcr0.to_vec()));
+        let mut payload = BTreeMap::new();
+        payload.insert(Value::Text("module_id".into()), Value::Text("i-123-enc456".into()));
+        payload.insert(Value::Text("digest".into()), Value::Text("SHA384".into()));
+        payload.insert(Value::Text("timestamp".into()), Value::Integer(1744038900000));
+        payload.insert(Value::Text("pcrs".into()), Value::Map(pcrs));
+        payload.insert(Value::Text("certificate".into()), Value::Bytes(vec![1, 2, 3]));
+        payload.insert(Value::Text("cabundle".into()), Value::Array(vec![Value::Bytes(vec![4])]));
+        payload.insert(Value::Text("public_key".into()), Value::Bytes(public_key.to_vec()));
+        payload.insert(Value::Text("user_data".into()), Value::Null);
+        payload.insert(Value::Text("nonce".into()), Value::Null);
+        let payload = serde_cbor::to_vec(&Value::Map(payload)).unwrap();
+        serde_cbor::to_vec(&Value::Array(vec![
+            Value::Bytes(vec![]),
+            Value::Map(BTreeMap::new()),
+            Value::Bytes(payload),
+            Value::Bytes(vec![0; 96]),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_verify() {
+        let bytes = document(&[9; 32], &[7; 48]);
+        let encoded = hex::encode(&bytes);
+        let doc = parse_attestation_document(&decode_document_bytes(&encoded).unwrap()).unwrap();
+        assert_eq!(doc.module_id, "i-123-enc456");
+        assert_eq!(doc.pcrs[&0].as_slice(), &[7; 48]);
+
+        let mut policy = AttestationPolicy {
+            public_key: Some(vec![9; 32]),
+            ..Default::default()
+        };
+        policy.pcrs.insert(0, vec![7; 48]);
+        assert!(verify_attestation(&doc, &policy).is_ok());
+
+        policy.pcrs.insert(0, vec![8; 48]);
+        assert!(verify_attestation(&doc, &policy).is_err());
+
+        policy.pcrs.clear();
+        policy.public_key = Some(vec![1; 32]);
+        assert!(verify_attestation(&doc, &policy).is_err());
+    }
+
+    #[test]
+    fn test_response_binding() {
+        let mut doc = parse_attestation_document(&document(&[9; 32], &[7; 48])).unwrap();
+        assert!(verify_response_binding(&doc, b"signed").is_err());
+
+        let digest: [u8; 32] = Sha3_256::digest(b"signed").into();
+        doc.user_data = Some(ByteBuf::from([[5u8; 32], digest].concat()));
+        assert!(verify_response_binding(&doc, b"signed").is_ok());
+        assert!(verify_response_binding(&doc, b"other").is_err());
+    }
+
+    #[test]
+    fn test_attestation_ref() {
+        let reference = AttestationRef {
+            document_hash: hex::encode(&Sha3_256::digest(b"document")[..16]),
+            pcr0_hash: hex::encode(&Sha3_256::digest([7; 48])[..16]),
+        };
+        assert!(verify_attestation_ref(&reference, b"document", &[7; 48]).is_ok());
+        assert!(verify_attestation_ref(&reference, b"document", &[8; 48]).is_err());
+        assert!(verify_attestation_ref(&reference, b"other", &[7; 48]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_cose() {
+        assert!(parse_attestation_document(&serde_cbor::to_vec(&Value::Integer(1)).unwrap()).is_err());
+        assert!(decode_document_bytes("not an attestation!").is_err());
+    }
+}
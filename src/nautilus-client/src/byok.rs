@@ -0,0 +1,95 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decryption of payload fields the server stored encrypted to a client supplied key
+//! (`encryption_public_key` on ingest). Values are sealed with an ephemeral X25519 key
+//! agreement, HKDF-SHA256 and AES-256-GCM.
+
+use crate::ClientError;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Algorithm identifier of [SealedValue].
+pub const SEALED_VALUE_ALGORITHM: &str = "x25519-hkdf-sha256-aes256gcm";
+/// Payload field holding the sealed message text.
+pub const MESSAGE_CIPHERTEXT_FIELD: &str = "message_ciphertext";
+const HKDF_INFO: &[u8] = b"nautilus-byok-v1";
+
+/// A value encrypted to a client's X25519 public key. Byte fields are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedValue {
+    pub alg: String,
+    /// Ephemeral public key of the sender
+    pub epk: String,
+    pub nonce: String,
+    /// AES-GCM output with the 16 byte tag appended
+    pub ciphertext: String,
+}
+
+/// X25519 public key of `secret`, hex encoded as `encryption_public_key` expects.
+pub fn public_key(secret: &[u8; 32]) -> String {
+    hex::encode(MontgomeryPoint::mul_base_clamped(*secret).to_bytes())
+}
+
+fn decode<const N: usize>(field: &str, value: &str) -> Result<[u8; N], ClientError> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| ClientError::Decode(format!("Invalid sealed value {}", field)))
+}
+
+/// Decrypt a sealed value with the X25519 secret key it was encrypted to.
+pub fn open(secret: &[u8; 32], sealed: &SealedValue) -> Result<String, ClientError> {
+    if sealed.alg != SEALED_VALUE_ALGORITHM {
+        return Err(ClientError::Decode(format!("Unsupported sealed value algorithm {}", sealed.alg)));
+    }
+    let epk: [u8; 32] = decode("epk", &sealed.epk)?;
+    let nonce: [u8; 12] = decode("nonce", &sealed.nonce)?;
+    let ciphertext = hex::decode(&sealed.ciphertext)
+        .map_err(|_| ClientError::Decode("Invalid sealed value ciphertext".to_string()))?;
+
+    let shared = MontgomeryPoint(epk).mul_clamped(*secret);
+    let recipient = MontgomeryPoint::mul_base_clamped(*secret);
+    let salt = [epk, recipient.to_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(HKDF_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    let plaintext = Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| ClientError::Verification("Sealed value does not decrypt with this key".to_string()))?;
+    String::from_utf8(plaintext).map_err(|e| ClientError::Decode(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sealed by the task's utils/payload-encryption.js
+    fn task_vector() -> SealedValue {
+        SealedValue {
+            alg: SEALED_VALUE_ALGORITHM.to_string(),
+            epk: "ef50cdf650f4fea5b20519a02652efc2a48b3be97fd178c4f38c41ecf08abc34".to_string(),
+            nonce: "28fb60d9c1ff40a7c6af3219".to_string(),
+            ciphertext: "2cda01d241fef15f8e57a87c40e4ad66eee4b1cc1e44b1a84f250f20b8c722c56b7f163ca27d4f3a0029"
+                .to_string(),
+        }
+    }
+
+    #[test]
+    fn test_open_task_vector() {
+        let secret = [7u8; 32];
+        assert_eq!(public_key(&secret), "13be4feaeaf204c7fd3358fc9c00721881d174278128227ec674f37f7fe97b6d");
+        assert_eq!(open(&secret, &task_vector()).unwrap(), "gm, see you at the standup");
+
+        assert!(matches!(open(&[8u8; 32], &task_vector()), Err(ClientError::Verification(_))));
+        let mut tampered = task_vector();
+        tampered.alg = "rsa".to_string();
+        assert!(matches!(open(&secret, &tampered), Err(ClientError::Decode(_))));
+    }
+}
@@ -0,0 +1,480 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed Rust client for the Nautilus server API, with response signature and
+//! attestation verification.
+
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+
+pub mod attestation;
+pub mod byok;
+pub mod stream;
+pub mod types;
+pub mod verify;
+
+use attestation::{AttestationDocument, AttestationPolicy};
+use ed25519_dalek::VerifyingKey;
+use types::*;
+use verify::VerifiedIntentMessage;
+
+/// Client errors.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Transport level failure.
+    Http(reqwest::Error),
+    /// The server answered with an error envelope.
+    /// `code` is the envelope's machine readable `error.code`, when the body had one.
+    Api { status: u16, code: Option<String>, message: String },
+    /// A response could not be decoded.
+    Decode(String),
+    /// A signature or signed message failed verification.
+    Verification(String),
+    /// The attestation document is invalid or does not satisfy the policy.
+    Attestation(String),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "HTTP error: {}", e),
+            ClientError::Api { status, code: Some(code), message } => {
+                write!(f, "API error ({} {}): {}", status, code, message)
+            }
+            ClientError::Api { status, message, .. } => write!(f, "API error ({}): {}", status, message),
+            ClientError::Decode(e) => write!(f, "Decode error: {}", e),
+            ClientError::Verification(e) => write!(f, "Verification failed: {}", e),
+            ClientError::Attestation(e) => write!(f, "Attestation check failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl ClientError {
+    /// Whether retrying the request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Http(e) => e.is_connect() || e.is_timeout(),
+            ClientError::Api { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// Exponential backoff for transient failures (connection errors, timeouts, 429 and 5xx).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the given retry (1 based).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Client for a single Nautilus server.
+#[derive(Debug, Clone)]
+pub struct NautilusClient {
+    base_url: String,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl NautilusClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Use a custom HTTP client, e.g. with timeouts or proxies configured.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    async fn with_retries<T, F, Fut>(&self, mut call: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, ClientError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(e) if e.is_retryable() && attempt < self.retry.max_attempts => {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn decode_envelope<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ClientError> {
+        Self::decode_envelope_with(response, StatusCode::is_success).await
+    }
+
+    /// Decode an envelope whose data is also returned with the statuses `accept`s.
+    async fn decode_envelope_with<T: DeserializeOwned>(
+        response: reqwest::Response,
+        accept: fn(&StatusCode) -> bool,
+    ) -> Result<T, ClientError> {
+        let status = response.status();
+        let envelope: ApiResponse<T> = response
+            .json()
+            .await
+            .map_err(|e| ClientError::Decode(format!("Invalid response envelope (HTTP {}): {}", status, e)))?;
+        match (envelope.data, envelope.error) {
+            (Some(data), None) if accept(&status) => Ok(data),
+            (_, Some(error)) => Err(ClientError::Api {
+                status: status.as_u16(),
+                code: Some(error.code).filter(|code| !code.is_empty()),
+                message: error.message,
+            }),
+            (_, None) => Err(ClientError::Api {
+                status: status.as_u16(),
+                code: None,
+                message: "missing data".to_string(),
+            }),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
+        self.with_retries(|| async {
+            let response = self.http.get(format!("{}{}", self.base_url, path)).send().await?;
+            Self::decode_envelope(response).await
+        })
+        .await
+    }
+
+    async fn admin_get<T: DeserializeOwned>(&self, path: &str, admin_token: &str) -> Result<T, ClientError> {
+        self.with_retries(|| async {
+            let response = self
+                .http
+                .get(format!("{}{}", self.base_url, path))
+                .bearer_auth(admin_token)
+                .send()
+                .await?;
+            Self::decode_envelope(response).await
+        })
+        .await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, payload: &B) -> Result<T, ClientError> {
+        let body = ProcessDataRequest { payload };
+        self.with_retries(|| async {
+            let response = self
+                .http
+                .post(format!("{}{}", self.base_url, path))
+                .json(&body)
+                .send()
+                .await?;
+            Self::decode_envelope(response).await
+        })
+        .await
+    }
+
+    /// Health report of the server. A degraded or down server answers 503 with the report,
+    /// which is returned as is rather than retried; check `overall`.
+    pub async fn health_check(&self) -> Result<HealthCheckResponse, ClientError> {
+        self.with_retries(|| async {
+            let response = self.http.get(format!("{}/health_check", self.base_url)).send().await?;
+            Self::decode_envelope_with(response, |status| {
+                status.is_success() || *status == StatusCode::SERVICE_UNAVAILABLE
+            })
+            .await
+        })
+        .await
+    }
+
+    /// Health report signed by the enclave, fetched in BCS mode and verified with the given
+    /// enclave key, so that a remote monitor can tell it from a report forged by a proxy.
+    /// Like [Client::health_check], a degraded or down server's report is returned as is;
+    /// check `timestamp_ms` for freshness.
+    pub async fn health_check_verified(&self, public_key: &VerifyingKey) -> Result<VerifiedIntentMessage, ClientError> {
+        let bytes = self
+            .with_retries(|| async {
+                let response = self
+                    .http
+                    .get(format!("{}/health_check?signed=true", self.base_url))
+                    .header(reqwest::header::ACCEPT, "application/bcs")
+                    .send()
+                    .await?;
+                let status = response.status();
+                if status != StatusCode::OK && status != StatusCode::SERVICE_UNAVAILABLE {
+                    return Err(ClientError::Api {
+                        status: status.as_u16(),
+                        code: None,
+                        message: response.text().await.unwrap_or_default(),
+                    });
+                }
+                Ok(response.bytes().await?)
+            })
+            .await?;
+        let message = verify::verify_bcs_envelope(public_key, &bytes)?;
+        if message.intent_scope != intent::HEALTH_CHECK {
+            return Err(ClientError::Verification(format!(
+                "Expected a health report, got intent scope {}",
+                message.intent_scope
+            )));
+        }
+        Ok(message)
+    }
+
+    /// Build metadata of the server: commit, features, task bundle hash and Node.js version.
+    pub async fn version(&self) -> Result<VersionResponse, ClientError> {
+        self.get("/version").await
+    }
+
+    pub async fn get_attestation(&self) -> Result<GetAttestationResponse, ClientError> {
+        self.get("/get_attestation").await
+    }
+
+    /// Attestation taken at boot, referenced by the `attestation_ref` of signed task responses.
+    pub async fn get_boot_attestation(&self) -> Result<BootAttestation, ClientError> {
+        self.get("/boot_attestation").await
+    }
+
+    /// Current and recently rotated out signing keys, to look up the `key_id` of a response.
+    pub async fn signing_keys(&self) -> Result<SigningKeysResponse, ClientError> {
+        self.get("/keys").await
+    }
+
+    /// Attestation bound to a caller chosen `nonce` and `user_data`.
+    pub async fn get_attestation_with(
+        &self,
+        nonce: Option<&[u8]>,
+        user_data: Option<&[u8]>,
+    ) -> Result<GetAttestationResponse, ClientError> {
+        let params: Vec<String> = [("nonce", nonce), ("user_data", user_data)]
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|v| format!("{}={}", name, hex::encode(v))))
+            .collect();
+        if params.is_empty() {
+            return self.get_attestation().await;
+        }
+        self.get(&format!("/get_attestation?{}", params.join("&"))).await
+    }
+
+    pub async fn process_data(&self, request: &TaskRequest) -> Result<SignedTaskResponse, ClientError> {
+        self.post("/process_data", request).await
+    }
+
+    /// Queue an embedding ingest job. The returned job is still queued; follow it with
+    /// [Self::wait_for_job] or [Self::get_job] and fetch the response with [Self::get_job_result].
+    pub async fn embedding_ingest(&self, request: &EmbeddingIngestRequest) -> Result<JobRecord, ClientError> {
+        self.post("/embedding_ingest", request).await
+    }
+
+    /// Ingest several blobs and wait for all of them, with a result per blob.
+    pub async fn embedding_ingest_batch(
+        &self,
+        request: &EmbeddingIngestBatchRequest,
+    ) -> Result<EmbeddingIngestBatchResponse, ClientError> {
+        self.post("/embedding_ingest_batch", request).await
+    }
+
+    pub async fn retrieve_messages_by_blob_ids(
+        &self,
+        request: &MessageBlobRetrievalRequest,
+    ) -> Result<SignedTaskResponse, ClientError> {
+        self.post("/retrieve_messages_by_blob_ids", request).await
+    }
+
+    /// Similarity search over messages matching the request's sender, chat and date filters.
+    pub async fn retrieve_messages_filtered(
+        &self,
+        request: &FilteredRetrievalRequest,
+    ) -> Result<SignedTaskResponse, ClientError> {
+        self.post("/retrieve_messages_filtered", request).await
+    }
+
+    /// Mark returned results as relevant or irrelevant for a query.
+    pub async fn submit_feedback(&self, request: &FeedbackRequest) -> Result<FeedbackResponse, ClientError> {
+        self.post("/feedback", request).await
+    }
+
+    /// Aggregated precision over all feedback received by the server.
+    pub async fn feedback_metrics(&self) -> Result<FeedbackMetrics, ClientError> {
+        self.get("/feedback/metrics").await
+    }
+
+    /// Retrieval profiles with their traffic share, latency and feedback precision.
+    pub async fn experiments(&self) -> Result<Vec<ProfileReport>, ClientError> {
+        self.get("/experiments").await
+    }
+
+    pub async fn get_job(&self, job_id: &str) -> Result<JobRecord, ClientError> {
+        self.get(&format!("/jobs/{}", job_id)).await
+    }
+
+    /// Task response of a succeeded job. Fails while the job is queued or running.
+    pub async fn get_job_result(&self, job_id: &str) -> Result<SignedTaskResponse, ClientError> {
+        self.get(&format!("/jobs/{}/result", job_id)).await
+    }
+
+    /// Long-poll a job until it finishes or `timeout_secs` elapses on the server.
+    pub async fn wait_for_job(&self, job_id: &str, timeout_secs: u64) -> Result<JobWaitResponse, ClientError> {
+        self.get(&format!("/jobs/{}/wait?timeout={}", job_id, timeout_secs)).await
+    }
+
+    /// Crash reports stored by the server. Requires the server's `ADMIN_TOKEN`.
+    pub async fn crash_reports(&self, admin_token: &str) -> Result<CrashReportsResponse, ClientError> {
+        self.admin_get("/admin/crash_reports", admin_token).await
+    }
+
+    /// Latest requests handled by the server, newest first. Requires the server's `ADMIN_TOKEN`.
+    pub async fn recent_requests(
+        &self,
+        admin_token: &str,
+        limit: Option<usize>,
+    ) -> Result<RecentRequestsResponse, ClientError> {
+        let path = match limit {
+            Some(limit) => format!("/admin/requests?limit={}", limit),
+            None => "/admin/requests".to_string(),
+        };
+        self.admin_get(&path, admin_token).await
+    }
+
+    /// Run `/process_data` in BCS mode and verify the signature with the given enclave key.
+    pub async fn process_data_verified(
+        &self,
+        request: &TaskRequest,
+        public_key: &VerifyingKey,
+    ) -> Result<VerifiedIntentMessage, ClientError> {
+        let body = ProcessDataRequest { payload: request };
+        let bytes = self
+            .with_retries(|| async {
+                let response = self
+                    .http
+                    .post(format!("{}/process_data", self.base_url))
+                    .header(reqwest::header::ACCEPT, "application/bcs")
+                    .json(&body)
+                    .send()
+                    .await?;
+                let status = response.status();
+                if status != StatusCode::OK {
+                    return Err(ClientError::Api {
+                        status: status.as_u16(),
+                        code: None,
+                        message: response.text().await.unwrap_or_default(),
+                    });
+                }
+                Ok(response.bytes().await?)
+            })
+            .await?;
+        verify::verify_bcs_envelope(public_key, &bytes)
+    }
+
+    /// Fetch the enclave public key and attestation, and check that the attestation binds
+    /// that key and satisfies `policy`. The attestation is requested with the policy's
+    /// nonce, if any. Returns the verified key for signature checks.
+    pub async fn verify_enclave(
+        &self,
+        policy: &AttestationPolicy,
+    ) -> Result<(VerifyingKey, AttestationDocument), ClientError> {
+        let health = self.health_check().await?;
+        let public_key = verify::parse_public_key(&health.pk)?;
+        let attestation = self.get_attestation_with(policy.nonce.as_deref(), None).await?;
+        let bytes = attestation::decode_document_bytes(&attestation.attestation.attestationDocument)?;
+        let document = attestation::parse_attestation_document(&bytes)?;
+
+        let mut policy = policy.clone();
+        policy.public_key = Some(public_key.as_bytes().to_vec());
+        attestation::verify_attestation(&document, &policy)?;
+        Ok((public_key, document))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let api = |status| ClientError::Api {
+            status,
+            code: None,
+            message: String::new(),
+        };
+        assert!(api(503).is_retryable());
+        assert!(api(429).is_retryable());
+        assert!(!api(400).is_retryable());
+        assert!(!ClientError::Verification("bad".to_string()).is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_after_max_attempts() {
+        let client = NautilusClient::new("http://localhost").with_retry(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        });
+        let mut calls = 0;
+        let result: Result<(), ClientError> = client
+            .with_retries(|| {
+                calls += 1;
+                async {
+                    Err(ClientError::Api {
+                        status: 503,
+                        code: None,
+                        message: String::new(),
+                    })
+                }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+}
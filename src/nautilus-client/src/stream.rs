@@ -0,0 +1,162 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of streamed responses closed by a signed chunk Merkle root.
+//! For SSE every frame is a chunk; for NDJSON retrievals every record line, with its
+//! newline, is a chunk and the `summary` record parses as a [StreamSignatureFrame].
+
+use crate::verify::{check_scheme, verify_signed_json};
+use crate::ClientError;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+/// Intent scope the enclave uses for stream summaries.
+pub const STREAM_SUMMARY_INTENT: u8 = 1;
+
+/// Signed summary of a completed stream.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StreamSummary {
+    pub chunk_count: u64,
+    pub total_bytes: u64,
+    pub merkle_root: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedIntentMessage<T> {
+    pub intent: u8,
+    pub timestamp_ms: u64,
+    pub data: T,
+}
+
+/// Final frame of a streamed response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamSignatureFrame {
+    pub response: SignedIntentMessage<StreamSummary>,
+    pub signature: String,
+    /// ID of the signing key, missing from older servers
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Sui flag of the signature scheme, 0 from older servers
+    #[serde(default)]
+    pub scheme: u8,
+}
+
+fn leaf_hash(chunk: &[u8]) -> [u8; 32] {
+    Sha3_256::new().chain_update([0x00]).chain_update(chunk).finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha3_256::new()
+        .chain_update([0x01])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Merkle root over chunk leaf hashes, matching the server's tree layout.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return Sha3_256::digest(b"").into();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Hashes chunks as they arrive and checks them against the signed final frame.
+#[derive(Debug, Default)]
+pub struct StreamVerifier {
+    leaves: Vec<[u8; 32]>,
+    total_bytes: u64,
+}
+
+impl StreamVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a chunk exactly as it was received.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.leaves.push(leaf_hash(chunk));
+        self.total_bytes += chunk.len() as u64;
+    }
+
+    /// Verify the final frame's signature and that it covers exactly the chunks received.
+    pub fn verify(&self, public_key: &VerifyingKey, frame: &StreamSignatureFrame) -> Result<(), ClientError> {
+        check_scheme(frame.scheme)?;
+        verify_signed_json(public_key, &frame.response, &frame.signature)?;
+        if frame.response.intent != STREAM_SUMMARY_INTENT {
+            return Err(ClientError::Verification("Frame is not a stream summary".to_string()));
+        }
+        let summary = &frame.response.data;
+        let root = hex::encode(merkle_root(&self.leaves));
+        if summary.chunk_count != self.leaves.len() as u64
+            || summary.total_bytes != self.total_bytes
+            || summary.merkle_root != root
+        {
+            return Err(ClientError::Verification(format!(
+                "Stream does not match signed summary: received {} chunks / {} bytes with root {}",
+                self.leaves.len(),
+                self.total_bytes,
+                root
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signed_frame(key: &SigningKey, chunks: &[&[u8]]) -> StreamSignatureFrame {
+        let leaves: Vec<_> = chunks.iter().map(|c| leaf_hash(c)).collect();
+        let response = SignedIntentMessage {
+            intent: STREAM_SUMMARY_INTENT,
+            timestamp_ms: 1744038900000,
+            data: StreamSummary {
+                chunk_count: chunks.len() as u64,
+                total_bytes: chunks.iter().map(|c| c.len() as u64).sum(),
+                merkle_root: hex::encode(merkle_root(&leaves)),
+            },
+        };
+        let signature = hex::encode(key.sign(&bcs::to_bytes(&response).unwrap()).to_bytes());
+        StreamSignatureFrame {
+            response,
+            signature,
+            key_id: None,
+            scheme: 0,
+        }
+    }
+
+    #[test]
+    fn test_stream_verification() {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let chunks: [&[u8]; 3] = [b"one", b"two", b"three"];
+        let frame = signed_frame(&key, &chunks);
+
+        let mut verifier = StreamVerifier::new();
+        chunks.iter().for_each(|c| verifier.push(c));
+        assert!(verifier.verify(&key.verifying_key(), &frame).is_ok());
+
+        let mut reordered = StreamVerifier::new();
+        [b"two".as_slice(), b"one", b"three"].iter().for_each(|c| reordered.push(c));
+        assert!(reordered.verify(&key.verifying_key(), &frame).is_err());
+
+        let mut truncated = StreamVerifier::new();
+        truncated.push(b"one");
+        assert!(truncated.verify(&key.verifying_key(), &frame).is_err());
+    }
+}
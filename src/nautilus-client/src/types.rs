@@ -0,0 +1,730 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request and response types mirroring the Nautilus server JSON API.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Envelope returned by every JSON endpoint. Exactly one of `data` and `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiResponse<T> {
+    pub data: Option<T>,
+    pub error: Option<ApiError>,
+    pub request_id: String,
+    pub signature: Option<String>,
+    pub timing: Timing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    /// Machine readable error code, e.g. `bad_request`, `task_failed` or `upstream_unavailable`.
+    #[serde(default)]
+    pub code: String,
+    pub message: String,
+    /// Structured fields of the error, e.g. `exit_code` of a failed task.
+    #[serde(default)]
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+}
+
+/// Wrapper struct containing the request payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessDataRequest<T> {
+    pub payload: T,
+}
+
+/// Intent scopes of signed task results and reports, see the server's `IntentScope`.
+pub mod intent {
+    pub const EMBEDDING_INGEST: u8 = 3;
+    pub const MESSAGE_RETRIEVAL: u8 = 4;
+    pub const BLOB_RETRIEVAL: u8 = 5;
+    pub const PROCESS_DATA: u8 = 6;
+    /// `/health_check?signed=true`
+    pub const HEALTH_CHECK: u8 = 10;
+    /// `/config?signed=true`
+    pub const CONFIG_REPORT: u8 = 11;
+}
+
+/// Message signed by the enclave: intent scope, timestamp and data, BCS serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntentMessage<T> {
+    pub intent: u8,
+    pub timestamp_ms: u64,
+    pub data: T,
+}
+
+/// Signed response: the intent message and the hex encoded enclave signature over its
+/// BCS bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedDataResponse<T> {
+    pub response: T,
+    pub signature: String,
+    /// ID of the signing key, see `Client::signing_keys`; missing from older servers
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Sui flag of the signature scheme, see `verify::check_scheme`; 0 from older servers
+    #[serde(default)]
+    pub scheme: u8,
+    /// Fresh attestation over the request's nonce and this response, see
+    /// `attestation::verify_response_binding`
+    #[serde(default)]
+    pub attestation: Option<GetAttestationResponse>,
+}
+
+/// Signed task response returned by the task endpoints.
+pub type SignedTaskResponse = ProcessedDataResponse<IntentMessage<TaskResponse>>;
+
+/// Scheduling priority of a task request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Recall of a filtered retrieval, traded against its latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchQuality {
+    Fast,
+    #[default]
+    Balanced,
+    Accurate,
+}
+
+/// Payload of `/process_data`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskRequest {
+    pub timeout_secs: Option<u64>,
+    /// Command line arguments of the task, at most 1 MiB in total.
+    pub args: Option<Vec<String>>,
+    /// JSON handed to the task on stdin, for payloads too large or private for `args`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<serde_json::Value>,
+    pub priority: Option<Priority>,
+    pub anchor_receipt: Option<bool>,
+    /// `fresh` returns a new attestation over `attestation_nonce` with the signed response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Also return the undecoded task output in `raw_output`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+}
+
+/// Attestation returned with a signed task response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttestationMode {
+    Fresh,
+}
+
+/// Payload of `/embedding_ingest`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingIngestRequest {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    #[serde(rename = "onChainFileObjId")]
+    pub on_chain_file_obj_id: String,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: String,
+    pub threshold: String,
+    pub timeout_secs: Option<u64>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: Option<u32>,
+    pub priority: Option<Priority>,
+    pub anchor_receipt: Option<bool>,
+    /// Allowlisted Qdrant collection to ingest into instead of the default one.
+    pub collection: Option<String>,
+    /// Walrus end epoch of the blob, after which its vectors are deleted.
+    pub blob_expiry_epoch: Option<u64>,
+    /// Hex encoded X25519 public key (see [crate::byok::public_key]). Message text is then
+    /// stored encrypted to it and can be read with [crate::byok::open].
+    pub encryption_public_key: Option<String>,
+    /// Also return the undecoded task output in `raw_output`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Idempotency key: a retry with the same key and payload gets the job of the first
+    /// request instead of ingesting the blob again.
+    #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// One blob of an `/embedding_ingest_batch` payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchIngestItem {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    #[serde(rename = "onChainFileObjId")]
+    pub on_chain_file_obj_id: String,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: String,
+    /// Walrus end epoch of the blob, after which its vectors are deleted.
+    pub blob_expiry_epoch: Option<u64>,
+}
+
+/// Payload of `/embedding_ingest_batch`. The options apply to every item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingIngestBatchRequest {
+    /// At most 100 blobs.
+    pub items: Vec<BatchIngestItem>,
+    pub threshold: String,
+    pub timeout_secs: Option<u64>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: Option<u32>,
+    pub priority: Option<Priority>,
+    pub anchor_receipt: Option<bool>,
+    /// Allowlisted Qdrant collection to ingest into instead of the default one.
+    pub collection: Option<String>,
+    /// Hex encoded X25519 public key, see [EmbeddingIngestRequest::encryption_public_key].
+    pub encryption_public_key: Option<String>,
+    /// Also return the undecoded task output in `raw_output`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Items ingested at once, 4 by default and at most 16.
+    pub parallelism: Option<usize>,
+}
+
+/// Outcome of one item of `/embedding_ingest_batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchIngestItemResult {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    pub success: bool,
+    /// Signed task response, set whenever the task ran.
+    pub response: Option<SignedTaskResponse>,
+    pub error: Option<String>,
+}
+
+/// Response of `/embedding_ingest_batch`, items in request order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingIngestBatchResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchIngestItemResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobFileIdPair {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    #[serde(rename = "onChainFileObjId")]
+    pub on_chain_file_obj_id: String,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: String,
+    #[serde(rename = "messageIndices")]
+    pub message_indices: Option<Vec<u32>>,
+}
+
+/// Payload of `/retrieve_messages_by_blob_ids`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBlobRetrievalRequest {
+    #[serde(rename = "blobFilePairs")]
+    pub blob_file_pairs: Vec<BlobFileIdPair>,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: Option<String>,
+    pub threshold: String,
+    pub timeout_secs: Option<u64>,
+    pub priority: Option<Priority>,
+    pub anchor_receipt: Option<bool>,
+    /// Include an `explain` section in the result data.
+    pub explain: Option<bool>,
+    /// Query identifier used to assign a retrieval profile and attribute feedback.
+    pub query_id: Option<String>,
+    /// Retrieval profile to run instead of the assigned one.
+    pub profile: Option<String>,
+    /// Allowlisted Qdrant collection to query instead of the default one.
+    pub collection: Option<String>,
+    /// `fresh` returns a new attestation over `attestation_nonce` with the signed response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Also return the undecoded task output in `raw_output`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+}
+
+/// Payload filters of `/retrieve_messages_filtered`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageFilters {
+    /// Telegram user ID of the sender.
+    pub sender: Option<serde_json::Value>,
+    pub chat_id: Option<serde_json::Value>,
+    /// Earliest message date, Unix seconds.
+    pub date_from: Option<u64>,
+    /// Latest message date, Unix seconds.
+    pub date_to: Option<u64>,
+}
+
+/// Payload of `/retrieve_messages_filtered`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilteredRetrievalRequest {
+    /// Text of the semantic query.
+    pub query: String,
+    pub filters: MessageFilters,
+    /// Results to return, at most 100.
+    pub limit: Option<u32>,
+    pub timeout_secs: Option<u64>,
+    pub priority: Option<Priority>,
+    pub anchor_receipt: Option<bool>,
+    /// Include an `explain` section in the result data.
+    pub explain: Option<bool>,
+    /// Query identifier used to assign a retrieval profile and attribute feedback.
+    pub query_id: Option<String>,
+    /// Retrieval profile to run instead of the assigned one.
+    pub profile: Option<String>,
+    /// Allowlisted Qdrant collection to query instead of the default one.
+    pub collection: Option<String>,
+    /// `fresh` returns a new attestation over `attestation_nonce` with the signed response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Also return the undecoded task output in `raw_output`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Recall traded for latency, the collection's search parameters when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_quality: Option<SearchQuality>,
+}
+
+/// Result of a Node task execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResponse {
+    pub status: String,
+    /// Task result. Signed BCS messages carry it as its canonical JSON string.
+    #[serde(with = "bcs_json")]
+    pub data: serde_json::Value,
+    pub stderr: String,
+    pub exit_code: i32,
+    pub execution_time_ms: u64,
+    /// Walrus blob ID of the signed execution receipt, if one was anchored.
+    pub receipt_blob_id: Option<String>,
+    /// CPU time and peak memory used by the Node.js process.
+    pub resource_usage: Option<ResourceUsage>,
+    /// Milliseconds spent in each phase of the request.
+    pub timeline: Option<Timeline>,
+    /// Boot attestation of the enclave that signed the response.
+    #[serde(default)]
+    pub attestation_ref: Option<AttestationRef>,
+    /// Undecoded task output, returned when the request set `raw`.
+    #[serde(default)]
+    pub raw_output: Option<RawOutput>,
+    /// Hex SHA3-256 of the canonical (RFC 8785) JSON of the request payload, binding the
+    /// signed result to the request.
+    #[serde(default)]
+    pub request_hash: Option<String>,
+}
+
+/// Base64 encoded stdout and stderr of a task, as it wrote them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Truncated hashes of the boot attestation document and PCR0, see
+/// [crate::attestation::verify_attestation_ref].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationRef {
+    pub document_hash: String,
+    pub pcr0_hash: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Phase breakdown of a request. Task phases are summed across concurrent work, so
+/// together they can exceed `task_ms`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeline {
+    pub queue_wait_ms: u64,
+    pub attestation_ms: u64,
+    pub blob_fetch_ms: Option<u64>,
+    pub decrypt_ms: Option<u64>,
+    pub parse_ms: Option<u64>,
+    pub embed_ms: Option<u64>,
+    pub upsert_ms: Option<u64>,
+    pub task_ms: u64,
+    pub sign_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct AttestationInfo {
+    pub enclaveId: String,
+    pub attestationDocument: String,
+}
+
+/// Response of `/boot_attestation`, the attestation signed task responses reference.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootAttestation {
+    pub attestation: AttestationInfo,
+    /// When the document was produced, in milliseconds; `None` from servers that predate it
+    #[serde(default)]
+    pub generated_at_ms: Option<u64>,
+    /// Hex PCR0
+    pub pcr0: String,
+    pub reference: AttestationRef,
+}
+
+/// Key signed responses may carry the `key_id` of.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub key_id: String,
+    /// Hex Ed25519 public key
+    pub public_key: String,
+    /// Hex compressed secp256k1 public key; missing from older servers
+    #[serde(default)]
+    pub secp256k1_public_key: Option<String>,
+    pub created_at_ms: u64,
+    /// Whether new responses are signed with this key
+    pub current: bool,
+    pub retired_at_ms: Option<u64>,
+    /// Until when responses signed with a rotated out key should still be accepted
+    pub valid_until_ms: Option<u64>,
+}
+
+/// Response of `/keys`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKeysResponse {
+    /// The current key first
+    pub keys: Vec<KeyInfo>,
+    /// Sui flag of the signature scheme responses are signed with
+    #[serde(default)]
+    pub scheme: u8,
+    pub overlap_secs: u64,
+}
+
+/// Response of `/get_attestation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetAttestationResponse {
+    pub success: bool,
+    pub attestation: AttestationInfo,
+    /// When the document was produced, in milliseconds; `None` from servers that predate it
+    #[serde(default)]
+    pub generated_at_ms: Option<u64>,
+    /// Hex nonce the attestation was requested with
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Hex user data the attestation was requested with, attested after the 32 byte build
+    /// metadata hash in the document `user_data`
+    #[serde(default)]
+    pub user_data: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigInfo {
+    pub move_package_id: String,
+    pub walrus_aggregator_url: String,
+    pub walrus_publisher_url: String,
+    pub walrus_epochs: String,
+    pub sui_secret_key_configured: bool,
+    pub ruby_nodes_api_key_configured: bool,
+    #[serde(default)]
+    pub internal_encryption_secret_key_configured: bool,
+    /// Where the keys of the server's encrypted stores come from.
+    #[serde(default)]
+    pub encryption_key_sources: Option<EncryptionKeySources>,
+    /// Settings as last updated on `POST /admin/config`.
+    #[serde(default)]
+    pub runtime: Option<RuntimeConfig>,
+}
+
+/// Server settings that can be updated without a restart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    pub embedding_batch_size: u32,
+    pub vector_batch_size: u32,
+    pub ollama_model: String,
+}
+
+/// Origin of an encryption key of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Dedicated,
+    /// Derived from the internal encryption secret key.
+    Internal,
+    /// Random per boot.
+    Random,
+    Unset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionKeySources {
+    pub crash_reports: KeySource,
+    pub payload_encryption: KeySource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigStatus {
+    pub config_valid: bool,
+    pub config_info: ConfigInfo,
+}
+
+/// Build metadata of a running server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    pub build_timestamp: u64,
+    pub features: Vec<String>,
+    pub task_bundle_hash: Option<String>,
+    pub node_version: Option<String>,
+}
+
+/// Response of `/version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub build: BuildInfo,
+    /// Hex attestation `user_data`, the canonical JSON SHA3-256 of `build`.
+    pub attestation_user_data: String,
+}
+
+/// Response of `/health_check`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheckResponse {
+    /// Hex encoded Ed25519 public key of the enclave.
+    pub pk: String,
+    /// Hex encoded compressed secp256k1 public key; `None` from servers that predate it.
+    #[serde(default)]
+    pub secp256k1_pk: Option<String>,
+    /// Sui flag of the scheme responses are signed with, 0 for Ed25519.
+    #[serde(default)]
+    pub signature_scheme: u8,
+    /// Health from the critical dependencies; `None` from servers that predate it.
+    #[serde(default)]
+    pub overall: Option<OverallHealth>,
+    pub endpoints_status: HashMap<String, bool>,
+    /// When the endpoints were probed; the server reuses results for a short while.
+    #[serde(default)]
+    pub endpoints_probed_at_ms: Option<u64>,
+    /// Whether the server could load its `allowed_endpoints.yaml`.
+    #[serde(default)]
+    pub endpoints_config: Option<EndpointsStatus>,
+    pub config_status: ConfigStatus,
+}
+
+/// Overall health reported by `/health_check`, served with 503 unless healthy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverallHealth {
+    Healthy,
+    Degraded,
+    Down,
+}
+
+/// State of the server's endpoints file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointsStatus {
+    pub path: String,
+    pub valid: bool,
+    /// Endpoints in use, from the last valid file.
+    pub endpoints: usize,
+    pub loaded_at_ms: Option<u64>,
+    /// Why the last load failed.
+    pub error: Option<String>,
+}
+
+/// Relevance judgment for one returned result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultJudgment {
+    pub result_id: String,
+    pub relevant: bool,
+    /// Qdrant point ID of the result, used for recommendation re-queries.
+    pub point_id: Option<String>,
+}
+
+/// Payload of `/feedback`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRequest {
+    pub query_id: String,
+    pub judgments: Vec<ResultJudgment>,
+    /// Retrieval profile the results came from (`data.retrieval_profile`).
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrecisionStats {
+    pub judged: usize,
+    pub relevant: usize,
+    pub precision: Option<f64>,
+}
+
+/// Response of `/feedback`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackResponse {
+    /// Masked query ID under which the feedback was stored.
+    pub query_hash: String,
+    pub recorded: usize,
+    pub query: PrecisionStats,
+    pub positive_point_ids: Vec<String>,
+    pub negative_point_ids: Vec<String>,
+}
+
+/// Retrieval parameters under A/B evaluation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalProfile {
+    pub name: String,
+    pub percent: u8,
+    pub model: Option<String>,
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub rerank: bool,
+    pub fusion_weights: Option<std::collections::BTreeMap<String, f64>>,
+}
+
+/// Entry of `/experiments`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub name: String,
+    pub percent: u8,
+    pub profile: Option<RetrievalProfile>,
+    pub requests: u64,
+    pub failures: u64,
+    pub mean_latency_ms: Option<f64>,
+    pub feedback: PrecisionStats,
+}
+
+/// Response of `/feedback/metrics`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackMetrics {
+    pub queries: usize,
+    pub overall: PrecisionStats,
+    pub mean_query_precision: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub operation: String,
+    pub status: JobStatus,
+    pub created_at_ms: u64,
+    pub updated_at_ms: u64,
+    pub result: Option<TaskResponse>,
+    pub error: Option<String>,
+    /// Optimizer wait and warm-up searches run after a large ingest
+    #[serde(default)]
+    pub warmup: Option<WarmupReport>,
+}
+
+/// Outcome of the warm-up run after a large ingest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmupReport {
+    pub collection: String,
+    /// Qdrant status of the collection when the wait ended
+    pub status: String,
+    /// The collection was optimized within the server's warm-up timeout
+    pub optimized: bool,
+    pub wait_ms: u64,
+    pub searches: u32,
+    /// Why the warm-up stopped early
+    pub error: Option<String>,
+}
+
+/// Response of `/jobs/:id/wait`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobWaitResponse {
+    pub job: JobRecord,
+    pub timed_out: bool,
+}
+
+/// Crash report written by the server's panic hook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub timestamp_ms: u64,
+    pub request_id: Option<String>,
+    pub thread: Option<String>,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: String,
+    pub recent_logs: Vec<String>,
+    pub git_commit: String,
+}
+
+/// Response of `/admin/crash_reports`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReportsResponse {
+    /// Reports decrypted with the server's current key, newest first.
+    pub reports: Vec<CrashReport>,
+    /// Reports that could not be decrypted.
+    pub unreadable: usize,
+}
+
+/// Request recorded in the server's request log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestLogEntry {
+    pub request_id: Option<String>,
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub path: String,
+    /// Hex SHA3-256 of the server's ID mask salt and the body.
+    pub payload_hash: Option<String>,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+/// Response of `/admin/requests`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentRequestsResponse {
+    pub capacity: usize,
+    /// Newest first.
+    pub requests: Vec<RequestLogEntry>,
+}
+
+/// Serde adapter matching the server's encoding of JSON values in BCS messages: a JSON
+/// string in binary formats, the plain value otherwise. Serializing to BCS uses
+/// `serde_json` key order rather than canonical JSON, so only decoding is exact.
+mod bcs_json {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            value.to_string().serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        if deserializer.is_human_readable() {
+            Value::deserialize(deserializer)
+        } else {
+            serde_json::from_str(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of signed `IntentMessage`s produced by the enclave.
+
+use crate::ClientError;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+/// Sui flag of Ed25519 signatures, the only scheme verified here.
+pub const SCHEME_ED25519: u8 = 0;
+/// Sui flag of secp256k1 signatures, made by servers with `SIGNATURE_SCHEME=secp256k1`.
+pub const SCHEME_SECP256K1: u8 = 1;
+
+/// BCS envelope returned for `Accept: application/bcs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BcsSignedEnvelope {
+    /// Exact BCS bytes of the signed `IntentMessage`.
+    pub intent_message: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// ID of the signing key, see [key_id].
+    pub key_id: String,
+    /// Sui flag of the signature scheme
+    pub scheme: u8,
+}
+
+/// An intent message whose signature has been checked.
+#[derive(Debug, Clone)]
+pub struct VerifiedIntentMessage {
+    pub intent_scope: u8,
+    pub timestamp_ms: u64,
+    /// BCS bytes of the signed data, following the intent header.
+    pub data_bcs: Vec<u8>,
+}
+
+impl VerifiedIntentMessage {
+    /// Decode the signed data into a BCS compatible type.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, ClientError> {
+        bcs::from_bytes(&self.data_bcs).map_err(|e| ClientError::Verification(format!("Failed to decode data: {}", e)))
+    }
+}
+
+/// Parse a hex encoded Ed25519 public key, as reported by `/health_check`.
+pub fn parse_public_key(pk_hex: &str) -> Result<VerifyingKey, ClientError> {
+    let bytes = hex::decode(pk_hex.trim_start_matches("0x"))
+        .map_err(|e| ClientError::Verification(format!("Public key is not valid hex: {}", e)))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| ClientError::Verification("Public key must be 32 bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| ClientError::Verification(format!("Invalid public key: {}", e)))
+}
+
+/// ID the server gives a signing key in `key_id`: the hex SHA3-256 of the public key,
+/// truncated to 8 bytes.
+pub fn key_id(public_key: &VerifyingKey) -> String {
+    hex::encode(&Sha3_256::digest(public_key.as_bytes())[..8])
+}
+
+/// Verify a signature over the BCS bytes of an `IntentMessage` and split off its header.
+pub fn verify_intent_message(
+    public_key: &VerifyingKey,
+    intent_message: &[u8],
+    signature: &[u8],
+) -> Result<VerifiedIntentMessage, ClientError> {
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| ClientError::Verification(format!("Invalid signature encoding: {}", e)))?;
+    public_key
+        .verify(intent_message, &signature)
+        .map_err(|_| ClientError::Verification("Signature does not match the enclave public key".to_string()))?;
+
+    // IntentMessage layout: intent scope (u8), timestamp_ms (u64 little endian), data.
+    if intent_message.len() < 9 {
+        return Err(ClientError::Verification("Intent message is too short".to_string()));
+    }
+    let mut timestamp = [0u8; 8];
+    timestamp.copy_from_slice(&intent_message[1..9]);
+    Ok(VerifiedIntentMessage {
+        intent_scope: intent_message[0],
+        timestamp_ms: u64::from_le_bytes(timestamp),
+        data_bcs: intent_message[9..].to_vec(),
+    })
+}
+
+/// Decode a BCS response body and verify its signature.
+pub fn verify_bcs_envelope(public_key: &VerifyingKey, body: &[u8]) -> Result<VerifiedIntentMessage, ClientError> {
+    let envelope: BcsSignedEnvelope = bcs::from_bytes(body)
+        .map_err(|e| ClientError::Verification(format!("Body is not a BCS signed envelope: {}", e)))?;
+    check_scheme(envelope.scheme)?;
+    verify_intent_message(public_key, &envelope.intent_message, &envelope.signature)
+}
+
+/// Fail for signatures of another scheme than Ed25519, which need the secp256k1 public key
+/// from `/health_check` or `/keys` and a secp256k1 verifier.
+pub fn check_scheme(scheme: u8) -> Result<(), ClientError> {
+    match scheme {
+        SCHEME_ED25519 => Ok(()),
+        SCHEME_SECP256K1 => Err(ClientError::Verification(
+            "Response is signed with secp256k1, only Ed25519 signatures are verified".to_string(),
+        )),
+        other => Err(ClientError::Verification(format!("Unknown signature scheme {}", other))),
+    }
+}
+
+/// Verify a JSON response whose hex `signature` covers the BCS serialization of `message`.
+pub fn verify_signed_json<T: Serialize>(
+    public_key: &VerifyingKey,
+    message: &T,
+    signature_hex: &str,
+) -> Result<VerifiedIntentMessage, ClientError> {
+    let bytes = bcs::to_bytes(message)
+        .map_err(|e| ClientError::Verification(format!("Failed to serialize message: {}", e)))?;
+    let signature = hex::decode(signature_hex)
+        .map_err(|e| ClientError::Verification(format!("Signature is not valid hex: {}", e)))?;
+    verify_intent_message(public_key, &bytes, &signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[derive(Serialize)]
+    struct IntentMessage<T> {
+        intent: u8,
+        timestamp_ms: u64,
+        data: T,
+    }
+
+    #[test]
+    fn test_verify_bcs_envelope() {
+        let signing_key = SigningKey::from_bytes(&[1u8; 32]);
+        let public_key = parse_public_key(&hex::encode(signing_key.verifying_key().as_bytes())).unwrap();
+        let message = IntentMessage {
+            intent: 0,
+            timestamp_ms: 1744038900000,
+            data: "hello".to_string(),
+        };
+        let intent_message = bcs::to_bytes(&message).unwrap();
+        let signature = signing_key.sign(&intent_message).to_bytes().to_vec();
+
+        let body = bcs::to_bytes(&BcsSignedEnvelope {
+            intent_message: intent_message.clone(),
+            signature: signature.clone(),
+            key_id: key_id(&public_key),
+            scheme: SCHEME_ED25519,
+        })
+        .unwrap();
+        let verified = verify_bcs_envelope(&public_key, &body).unwrap();
+        assert_eq!(verified.intent_scope, 0);
+        assert_eq!(verified.timestamp_ms, 1744038900000);
+        assert_eq!(verified.decode::<String>().unwrap(), "hello");
+
+        let verified = verify_signed_json(&public_key, &message, &hex::encode(&signature)).unwrap();
+        assert_eq!(verified.timestamp_ms, 1744038900000);
+
+        let other = SigningKey::from_bytes(&[2u8; 32]).verifying_key();
+        assert!(verify_bcs_envelope(&other, &body).is_err());
+    }
+
+    #[test]
+    fn test_decode_task_response() {
+        let signing_key = SigningKey::from_bytes(&[1u8; 32]);
+        let message = IntentMessage {
+            intent: 0,
+            timestamp_ms: 1744038900000,
+            data: crate::types::TaskResponse {
+                status: "success".to_string(),
+                data: serde_json::json!({"score": 0.5}),
+                stderr: String::new(),
+                exit_code: 0,
+                execution_time_ms: 10,
+                receipt_blob_id: None,
+                resource_usage: None,
+                timeline: None,
+                attestation_ref: None,
+                raw_output: None,
+                request_hash: None,
+            },
+        };
+        let intent_message = bcs::to_bytes(&message).unwrap();
+        let signature = signing_key.sign(&intent_message).to_bytes();
+        let verified = verify_intent_message(&signing_key.verifying_key(), &intent_message, &signature).unwrap();
+        let response: crate::types::TaskResponse = verified.decode().unwrap();
+        assert_eq!(response.data["score"], 0.5);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request rate limits per address. The signing limits of [crate::key_usage] are shared by
+//! every caller, so a single client acting for many addresses can use them up alone and
+//! starve the backends for everyone else. `ADDRESS_RATE_LIMITS` caps the ingest and
+//! retrieval requests made for each address, the Seal policy object gating the data, per
+//! minute. Both limits apply: the address limit before the task runs, the signing limit
+//! before its response is signed. Requests are counted per operation on `/metrics`, without
+//! address labels.
+
+use crate::EnclaveError;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Window over which address rate limits are counted.
+pub const ADDRESS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Operations limited per address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AddressOperation {
+    /// `/embedding_ingest`, its streaming variant and `/embedding_ingest_batch`
+    Ingest,
+    /// `/retrieve_messages_by_blob_ids`
+    Retrieval,
+}
+
+impl AddressOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressOperation::Ingest => "ingest",
+            AddressOperation::Retrieval => "retrieval",
+        }
+    }
+}
+
+impl fmt::Display for AddressOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AddressOperation {
+    type Err = EnclaveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ingest" => Ok(AddressOperation::Ingest),
+            "retrieval" => Ok(AddressOperation::Retrieval),
+            other => Err(EnclaveError::BadRequest(format!(
+                "Unknown operation {}, expected ingest or retrieval",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct OperationUsage {
+    allowed: u64,
+    rejected: u64,
+    /// Request times within the window by address, only kept for limited operations
+    recent: HashMap<String, VecDeque<Instant>>,
+}
+
+/// Request counters and optional per-operation limits per address.
+#[derive(Debug, Clone, Default)]
+pub struct AddressLimits {
+    /// Requests allowed per address and [ADDRESS_WINDOW] by operation
+    limits: BTreeMap<AddressOperation, u32>,
+    usage: Arc<Mutex<BTreeMap<AddressOperation, OperationUsage>>>,
+}
+
+impl AddressLimits {
+    pub fn new(limits: BTreeMap<AddressOperation, u32>) -> Self {
+        Self {
+            limits,
+            usage: Default::default(),
+        }
+    }
+
+    /// Parse `ADDRESS_RATE_LIMITS`: comma separated `operation=requests_per_minute`, e.g.
+    /// `ingest=30,retrieval=300`.
+    pub fn parse_limits(value: &str) -> Result<BTreeMap<AddressOperation, u32>, EnclaveError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (operation, limit) = entry.split_once('=').ok_or_else(|| {
+                    EnclaveError::BadRequest(format!("Expected operation=limit, got {}", entry))
+                })?;
+                let limit = limit.trim().parse::<u32>().map_err(|e| {
+                    EnclaveError::BadRequest(format!("Invalid limit for {}: {}", operation, e))
+                })?;
+                Ok((operation.trim().parse()?, limit))
+            })
+            .collect()
+    }
+
+    /// Record a request for `addresses`, or reject it with [EnclaveError::Overloaded] when
+    /// any of them reached the limit of `operation`. A request counts once per distinct
+    /// address, and a rejected one counts against none.
+    pub fn acquire<'a>(
+        &self,
+        operation: AddressOperation,
+        addresses: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), EnclaveError> {
+        self.acquire_at(operation, addresses, Instant::now())
+    }
+
+    fn acquire_at<'a>(
+        &self,
+        operation: AddressOperation,
+        addresses: impl IntoIterator<Item = &'a str>,
+        now: Instant,
+    ) -> Result<(), EnclaveError> {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(operation).or_default();
+        if let Some(&limit) = self.limits.get(&operation) {
+            // Forget addresses idle for a whole window so the map stays bounded
+            entry.recent.retain(|_, times| {
+                while times.front().is_some_and(|at| now.duration_since(*at) >= ADDRESS_WINDOW) {
+                    times.pop_front();
+                }
+                !times.is_empty()
+            });
+            let addresses: BTreeSet<String> = addresses.into_iter().map(str::to_ascii_lowercase).collect();
+            let full = addresses.iter().find_map(|address| {
+                let times = entry.recent.get(address)?;
+                (times.len() >= limit as usize).then(|| (address, times.front().copied().unwrap_or(now)))
+            });
+            if let Some((address, oldest)) = full {
+                warn!("Address rate limit of {} per minute reached for {} by {}", limit, operation, address);
+                entry.rejected += 1;
+                let retry_after = ADDRESS_WINDOW.saturating_sub(now.duration_since(oldest));
+                return Err(EnclaveError::Overloaded {
+                    message: format!("Rate limit reached for {} on {}", address, operation),
+                    retry_after_secs: retry_after.as_secs().max(1),
+                });
+            }
+            for address in addresses {
+                entry.recent.entry(address).or_default().push_back(now);
+            }
+        }
+        entry.allowed += 1;
+        Ok(())
+    }
+
+    /// Render the counters and limits in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let usage = self.usage.lock().unwrap();
+        let mut out = String::new();
+        let series = [
+            ("nautilus_address_requests_total", "Requests allowed by the address rate limit.", false),
+            ("nautilus_address_requests_rejected_total", "Requests refused by the address rate limit.", true),
+        ];
+        for (name, help, rejected) in series {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (operation, u) in usage.iter() {
+                let value = if rejected { u.rejected } else { u.allowed };
+                let _ = writeln!(out, "{}{{operation=\"{}\"}} {}", name, operation, value);
+            }
+        }
+        let name = "nautilus_address_rate_limit";
+        let _ = writeln!(out, "# HELP {} Requests allowed per address and minute.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (operation, limit) in &self.limits {
+            let _ = writeln!(out, "{}{{operation=\"{}\"}} {}", name, operation, limit);
+        }
+        let name = "nautilus_address_rate_limited_addresses";
+        let _ = writeln!(out, "# HELP {} Addresses with requests in the current window.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (operation, u) in usage.iter().filter(|(operation, _)| self.limits.contains_key(operation)) {
+            let _ = writeln!(out, "{}{{operation=\"{}\"}} {}", name, operation, u.recent.len());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = AddressLimits::parse_limits("ingest=2, retrieval=10").unwrap();
+        assert_eq!(limits[&AddressOperation::Ingest], 2);
+        assert_eq!(limits[&AddressOperation::Retrieval], 10);
+        assert!(AddressLimits::parse_limits("").unwrap().is_empty());
+        assert!(AddressLimits::parse_limits("ingest").is_err());
+        assert!(AddressLimits::parse_limits("process_data=1").is_err());
+        assert!(AddressLimits::parse_limits("ingest=-1").is_err());
+    }
+
+    #[test]
+    fn test_limit_per_address() {
+        let limits = AddressLimits::new(AddressLimits::parse_limits("ingest=2").unwrap());
+        let start = Instant::now();
+        assert!(limits.acquire_at(AddressOperation::Ingest, ["0xa"], start).is_ok());
+        // Addresses are compared case-insensitively and count once per request
+        assert!(limits.acquire_at(AddressOperation::Ingest, ["0xA", "0xa"], start + Duration::from_secs(20)).is_ok());
+        let err = limits
+            .acquire_at(AddressOperation::Ingest, ["0xb", "0xa"], start + Duration::from_secs(30))
+            .unwrap_err();
+        assert_eq!(err.retry_after_secs(), Some(30));
+        // The rejected request did not count against 0xb, and other addresses are unaffected
+        assert!(limits.acquire_at(AddressOperation::Ingest, ["0xb"], start + Duration::from_secs(30)).is_ok());
+        assert!(limits.acquire_at(AddressOperation::Ingest, ["0xb"], start + Duration::from_secs(30)).is_ok());
+        // Unlimited operations are only counted
+        assert!(limits.acquire_at(AddressOperation::Retrieval, ["0xa"], start).is_ok());
+        // The first request leaves the window
+        assert!(limits.acquire_at(AddressOperation::Ingest, ["0xa"], start + Duration::from_secs(60)).is_ok());
+
+        let text = limits.render();
+        assert!(text.contains("nautilus_address_requests_total{operation=\"ingest\"} 5"));
+        assert!(text.contains("nautilus_address_requests_rejected_total{operation=\"ingest\"} 1"));
+        assert!(text.contains("nautilus_address_requests_total{operation=\"retrieval\"} 1"));
+        assert!(text.contains("nautilus_address_rate_limit{operation=\"ingest\"} 2"));
+        assert!(text.contains("nautilus_address_rate_limited_addresses{operation=\"ingest\"} 2"));
+        assert!(!text.contains("nautilus_address_rate_limited_addresses{operation=\"retrieval\"}"));
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::common::{current_timestamp_ms, ConfigResponse, GetAttestationResponse, HealthCheckResponse, SignedTaskResponse};
+use crate::jobs::JobRecord;
+use crate::EnclaveError;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Header used to pass a caller supplied request ID and to return the effective one.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// ==== API RESPONSE ENVELOPE ====
+
+/// Uniform envelope returned by every JSON endpoint. Exactly one of `data` and
+/// `error` is set. All envelope fields use camelCase.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[aliases(
+    TaskEnvelope = ApiResponse<SignedTaskResponse>,
+    JobEnvelope = ApiResponse<JobRecord>,
+    AttestationEnvelope = ApiResponse<GetAttestationResponse>,
+    HealthCheckEnvelope = ApiResponse<HealthCheckResponse>,
+    ConfigEnvelope = ApiResponse<ConfigResponse>
+)]
+pub struct ApiResponse<T> {
+    pub data: Option<T>,
+    pub error: Option<ApiError>,
+    pub request_id: String,
+    /// Hex encoded enclave signature over the BCS bytes of the signed intent message in
+    /// `data`, for signed endpoints.
+    pub signature: Option<String>,
+    pub timing: Timing,
+    #[serde(skip)]
+    status: StatusCode,
+    #[serde(skip)]
+    retry_after_secs: Option<u64>,
+}
+
+/// Error details carried in the envelope.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiError {
+    /// Machine readable error code such as `bad_request` or `task_failed`
+    pub code: String,
+    pub message: String,
+    /// Structured fields of the error, e.g. the exit code of a failed task
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Request timing information.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+}
+
+impl<T> ApiResponse<T> {
+    /// Attach a signature to the envelope.
+    pub fn with_signature(mut self, signature: String) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+
+    /// Serve the envelope with a status other than the default.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// HTTP status the envelope is served with.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl ApiResponse<()> {
+    /// Envelope for an error raised outside of a [RequestContext].
+    pub fn from_error(error: EnclaveError) -> Self {
+        RequestContext::new(None).error(error)
+    }
+}
+
+impl<T: Serialize> IntoResponse for ApiResponse<T> {
+    fn into_response(self) -> Response {
+        let status = self.status;
+        let request_id = HeaderValue::from_str(&self.request_id).ok();
+        let retry_after_secs = self.retry_after_secs;
+        let mut response = (status, Json(self)).into_response();
+        if let Some(request_id) = request_id {
+            response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+        }
+        if let Some(secs) = retry_after_secs {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// Per request context extracted by handlers, used to build [ApiResponse] envelopes.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub request_id: String,
+    started_at_ms: u64,
+    started: Instant,
+}
+
+impl RequestContext {
+    /// Create a context, reusing the caller supplied request ID when present.
+    pub fn new(request_id: Option<String>) -> Self {
+        Self {
+            request_id: request_id
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            started_at_ms: current_timestamp_ms(),
+            started: Instant::now(),
+        }
+    }
+
+    fn timing(&self) -> Timing {
+        Timing {
+            started_at_ms: self.started_at_ms,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Successful envelope.
+    pub fn ok<T>(&self, data: T) -> ApiResponse<T> {
+        ApiResponse {
+            data: Some(data),
+            error: None,
+            request_id: self.request_id.clone(),
+            signature: None,
+            timing: self.timing(),
+            status: StatusCode::OK,
+            retry_after_secs: None,
+        }
+    }
+
+    /// Error envelope, served with the status code of the error.
+    pub fn error<T>(&self, error: EnclaveError) -> ApiResponse<T> {
+        let retry_after_secs = error.retry_after_secs();
+        let code = error.code().to_string();
+        let details = error.details();
+        let (status, message) = error.status_and_message();
+        ApiResponse {
+            data: None,
+            error: Some(ApiError { code, message, details }),
+            request_id: self.request_id.clone(),
+            signature: None,
+            timing: self.timing(),
+            status,
+            retry_after_secs,
+        }
+    }
+
+    /// Envelope for a handler result.
+    pub fn respond<T>(&self, result: Result<T, EnclaveError>) -> ApiResponse<T> {
+        match result {
+            Ok(data) => self.ok(data),
+            Err(e) => self.error(e),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let request_id = parts
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        Ok(RequestContext::new(request_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_casing() {
+        let ctx = RequestContext::new(Some("req-1".to_string()));
+        let value = serde_json::to_value(ctx.ok("hello")).unwrap();
+        assert_eq!(value["data"], "hello");
+        assert_eq!(value["requestId"], "req-1");
+        assert!(value["error"].is_null());
+        assert!(value["signature"].is_null());
+        assert!(value["timing"]["startedAtMs"].is_u64());
+        assert!(value["timing"]["durationMs"].is_u64());
+        assert!(value.get("status").is_none());
+    }
+
+    #[test]
+    fn test_error_envelope() {
+        let ctx = RequestContext::new(None);
+        assert!(!ctx.request_id.is_empty());
+
+        let response: ApiResponse<String> =
+            ctx.error(EnclaveError::BadRequest("boom".to_string()));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let value = serde_json::to_value(&response).unwrap();
+        assert!(value["data"].is_null());
+        assert_eq!(value["error"]["message"], "boom");
+        assert_eq!(value["error"]["code"], "bad_request");
+        assert!(value["error"].get("details").is_none());
+
+        let response: ApiResponse<String> = ctx.error(EnclaveError::TaskFailed {
+            exit_code: 2,
+            stderr: "Error: Cannot find module 'x'".to_string(),
+        });
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["error"]["code"], "task_failed");
+        assert_eq!(value["error"]["details"]["exitCode"], 2);
+        assert!(value["error"]["message"].as_str().unwrap().starts_with("Task failed with exit code 2"));
+
+        let response: ApiResponse<String> = ctx.error(EnclaveError::upstream("qdrant", "connection refused"));
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["error"]["code"], "upstream_unavailable");
+        assert_eq!(value["error"]["details"]["service"], "qdrant");
+    }
+
+    #[test]
+    fn test_overloaded_sets_retry_after() {
+        let ctx = RequestContext::new(None);
+        let overloaded = || EnclaveError::Overloaded {
+            message: "Task queue is full".to_string(),
+            retry_after_secs: 30,
+        };
+        let value = serde_json::to_value(&ctx.error::<()>(overloaded())).unwrap();
+        assert_eq!(value["error"]["details"]["retryAfterSecs"], 30);
+
+        let response = ctx.error::<()>(overloaded()).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+    }
+}
@@ -2,14 +2,45 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::common::IntentMessage;
+use crate::experiments::DEFAULT_PROFILE;
+use crate::feedback::mask_id;
+use crate::collections::SearchQuality;
 use crate::common::{to_signed_response, IntentScope, ProcessDataRequest, ProcessedDataResponse, get_attestation};
-use crate::task_runner::{NodeTaskRunner, TaskConfig};
+use crate::common::{current_timestamp_ms, fetch_attestation, AttestationRef, to_bcs_response, wants_bcs};
+use crate::common::{attest_signed_message, fresh_attestation_nonce, AttestationMode};
+use crate::address_limits::AddressOperation;
+use crate::api_response::{ApiResponse, JobEnvelope, RequestContext, TaskEnvelope};
+use crate::common::{
+    EmbeddingIngestProcessDataRequest, FilteredRetrievalProcessDataRequest, MessageBlobRetrievalProcessDataRequest,
+    TaskProcessDataRequest,
+};
+use crate::crash_reports::{current_request_id, inherit_request_id};
+use crate::idempotency::Claim;
+use crate::key_manager::SigningKey;
+use crate::jobs::{JobRecord, JobStatus};
+use crate::receipts::ReceiptContext;
+use crate::retrieval_stream::{retrieve_messages_ndjson, wants_ndjson};
+use crate::scheduler::Priority;
+use crate::authorization::authorize;
+use crate::seal_policy::precheck_policies;
+use crate::task_audit::TaskInvocation;
+use crate::task_env::{Operation, REQUEST_ID_ENV};
+use crate::task_result::{parse_result, EmbeddingResult, FilteredRetrievalResult, ResultStatus, RetrievalResult};
+use crate::timeline::{timed, Timeline};
+use crate::validation::{check_address, check_blob_id, check_threshold, FieldError, FieldErrors, ValidJson, Validate};
+use crate::walrus::precheck_blob;
+use crate::warmup;
+use crate::task_runner::{
+    NodeTaskRunner, OutputSink, RawOutput, ResourceUsage, TaskConfig, TaskOutput, TaskTimedOut, MAX_TASK_ARGS_BYTES,
+};
 use crate::AppState;
 use crate::EnclaveError;
 use axum::extract::State;
-use axum::Json;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use utoipa::ToSchema;
 use tower_http::cors::{CorsLayer, AllowOrigin, AllowHeaders};
 use std::env;
 use axum::routing::{get, post};
@@ -17,18 +48,15 @@ use axum::Router;
 use axum::http::{HeaderValue, Method, header::{CONTENT_TYPE, AUTHORIZATION, ACCEPT, ORIGIN, REFERER, USER_AGENT}};
 use crate::common::{health_check};
 
-// Helper function to extract task result from stdout using delimiters
-fn extract_task_result(stdout: &str) -> Option<serde_json::Value> {
-    let start_marker = "===TASK_RESULT_START===";
-    let end_marker = "===TASK_RESULT_END===";
-    
-    let start_pos = stdout.find(start_marker)?;
-    let start_pos = start_pos + start_marker.len();
-    
-    let end_pos = stdout[start_pos..].find(end_marker)?;
-    let json_str = stdout[start_pos..start_pos + end_pos].trim();
-    
-    serde_json::from_str(json_str).ok()
+/// Validate a client supplied X25519 public key, returning it as lowercase hex.
+pub fn parse_encryption_public_key(key: &str) -> Result<String, EnclaveError> {
+    let hex = key.trim_start_matches("0x").to_ascii_lowercase();
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(EnclaveError::BadRequest(
+            "encryption_public_key must be a hex encoded 32 byte X25519 public key".to_string(),
+        ));
+    }
+    Ok(hex)
 }
 
 /// ====
@@ -37,23 +65,61 @@ fn extract_task_result(stdout: &str) -> Option<serde_json::Value> {
 /// ====
 
 /// Inner type T for IntentMessage<T>
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct TaskResponse {
     pub status: String,
+    /// Task result, signed as its canonical JSON string
+    #[serde(with = "crate::canonical::bcs_json")]
+    #[schema(value_type = Object)]
     pub data: serde_json::Value,
     pub stderr: String,
     pub exit_code: i32,
     pub execution_time_ms: u64,
+    /// Walrus blob ID of the signed execution receipt, when anchoring is enabled
+    pub receipt_blob_id: Option<String>,
+    /// CPU time used by the Node.js process
+    pub resource_usage: Option<ResourceUsage>,
+    /// Milliseconds spent in each phase of the request
+    pub timeline: Option<Timeline>,
+    /// Boot attestation of the enclave that signed the response, see `/boot_attestation`
+    #[serde(default)]
+    pub attestation_ref: Option<AttestationRef>,
+    /// Undecoded task output, when the request set `raw`
+    #[serde(default)]
+    pub raw_output: Option<RawOutput>,
+    /// Hex SHA3-256 of the canonical JSON of the request payload, so the signature commits
+    /// to what was asked as well as to the answer
+    #[serde(default)]
+    pub request_hash: Option<String>,
 }
 
 /// Inner type T for ProcessDataRequest<T>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct TaskRequest {
     pub timeout_secs: Option<u64>,
+    /// Command line arguments, at most MAX_TASK_ARGS_BYTES in total
     pub args: Option<Vec<String>>,
+    /// JSON handed to the task in the protocol request on stdin, for payloads too large or
+    /// too private for `args`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub input: Option<serde_json::Value>,
+    /// Scheduling priority, defaults to normal
+    pub priority: Option<Priority>,
+    /// Anchor a signed execution receipt to Walrus, defaults to ANCHOR_RECEIPTS
+    pub anchor_receipt: Option<bool>,
+    /// `fresh` returns a new attestation over `attestation_nonce` and the signed response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Also return the task output undecoded, base64 encoded in `raw_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct EmbeddingIngestRequest {
     #[serde(rename = "walrusBlobId")]
     pub walrus_blob_id: String,
@@ -65,9 +131,53 @@ pub struct EmbeddingIngestRequest {
     pub timeout_secs: Option<u64>,
     #[serde(rename = "batchSize")]
     pub batch_size: Option<u32>,
+    /// Scheduling priority, defaults to normal
+    pub priority: Option<Priority>,
+    /// Anchor a signed execution receipt to Walrus, defaults to ANCHOR_RECEIPTS
+    pub anchor_receipt: Option<bool>,
+    /// Qdrant collection to ingest into, one of QDRANT_COLLECTIONS
+    pub collection: Option<String>,
+    /// Walrus end epoch of the source blob; its vectors are reaped once it has expired
+    pub blob_expiry_epoch: Option<u64>,
+    /// Hex encoded X25519 public key; message text is stored encrypted to it
+    pub encryption_public_key: Option<String>,
+    /// Also return the task output undecoded, base64 encoded in `raw_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Idempotency key, used when the request has no `Idempotency-Key` header
+    #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Validate for TaskRequest {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        let args_bytes: usize = self.args.iter().flatten().map(String::len).sum();
+        if args_bytes > MAX_TASK_ARGS_BYTES {
+            errors.check(
+                "args",
+                Err(format!(
+                    "total {} bytes, more than {}; send large payloads in input",
+                    args_bytes, MAX_TASK_ARGS_BYTES
+                )),
+            );
+        }
+        errors.into_vec()
+    }
+}
+
+impl Validate for EmbeddingIngestRequest {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.check("walrusBlobId", check_blob_id(&self.walrus_blob_id));
+        errors.check("onChainFileObjId", check_address(&self.on_chain_file_obj_id));
+        errors.check("policyObjectId", check_address(&self.policy_object_id));
+        errors.check("threshold", check_threshold(&self.threshold));
+        errors.into_vec()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct BlobFileIdPair {
     #[serde(rename = "walrusBlobId")]
     pub walrus_blob_id: String,
@@ -81,7 +191,7 @@ pub struct BlobFileIdPair {
     pub message_indices: Option<Vec<u32>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct MessageBlobRetrievalRequest {
     #[serde(rename = "blobFilePairs")]
     pub blob_file_pairs: Vec<BlobFileIdPair>,
@@ -89,9 +199,145 @@ pub struct MessageBlobRetrievalRequest {
     pub policy_object_id: Option<String>, // Now optional since each pair has its own policy ID
     pub threshold: String,
     pub timeout_secs: Option<u64>,
+    /// Scheduling priority, defaults to normal
+    pub priority: Option<Priority>,
+    /// Anchor a signed execution receipt to Walrus, defaults to ANCHOR_RECEIPTS
+    pub anchor_receipt: Option<bool>,
+    /// Include an `explain` section describing how the messages were resolved
+    pub explain: Option<bool>,
+    /// `fresh` returns a new attestation over `attestation_nonce` and the signed response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Identifier of the query, used to assign a retrieval profile and to attribute feedback
+    pub query_id: Option<String>,
+    /// Retrieval profile to run instead of the assigned one
+    pub profile: Option<String>,
+    /// Qdrant collection to query, one of QDRANT_COLLECTIONS
+    pub collection: Option<String>,
+    /// Also return the task output undecoded, base64 encoded in `raw_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+}
+
+impl MessageBlobRetrievalRequest {
+    /// Addresses the request is made for, the policy objects of its blobs.
+    pub fn addresses(&self) -> impl Iterator<Item = &str> {
+        self.blob_file_pairs
+            .iter()
+            .map(|pair| pair.policy_object_id.as_str())
+            .chain(self.policy_object_id.as_deref())
+    }
+}
+
+impl Validate for MessageBlobRetrievalRequest {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        for (i, pair) in self.blob_file_pairs.iter().enumerate() {
+            errors.check(format!("blobFilePairs[{}].walrusBlobId", i), check_blob_id(&pair.walrus_blob_id));
+            errors.check(format!("blobFilePairs[{}].onChainFileObjId", i), check_address(&pair.on_chain_file_obj_id));
+            errors.check(format!("blobFilePairs[{}].policyObjectId", i), check_address(&pair.policy_object_id));
+        }
+        if let Some(policy_object_id) = &self.policy_object_id {
+            errors.check("policyObjectId", check_address(policy_object_id));
+        }
+        errors.check("threshold", check_threshold(&self.threshold));
+        errors.into_vec()
+    }
+}
+
+/// Most results `/retrieve_messages_filtered` returns.
+pub const MAX_FILTERED_RESULTS: u32 = 100;
+/// Results returned when neither the request nor its retrieval profile sets a limit.
+pub const DEFAULT_FILTERED_RESULTS: u32 = 10;
+
+/// Payload filters applied by Qdrant together with the similarity query, so only matching
+/// messages are ranked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct MessageFilters {
+    /// Telegram user ID of the sender, as stored in the `from_id` payload field
+    #[schema(value_type = Option<Object>)]
+    pub sender: Option<serde_json::Value>,
+    /// Chat of the message, as stored in the `chat_id` payload field
+    #[schema(value_type = Option<Object>)]
+    pub chat_id: Option<serde_json::Value>,
+    /// Earliest message date, Unix seconds
+    pub date_from: Option<u64>,
+    /// Latest message date, Unix seconds
+    pub date_to: Option<u64>,
+}
+
+impl MessageFilters {
+    /// Qdrant filter of the set fields, `None` when no filter is set. Dates match the
+    /// `message_date` payload field, so points ingested before it was stored never match a
+    /// date range.
+    pub fn qdrant_filter(&self) -> Result<Option<serde_json::Value>, EnclaveError> {
+        let mut must = Vec::new();
+        if let Some(sender) = &self.sender {
+            must.push(serde_json::json!({ "key": "from_id", "match": { "value": sender } }));
+        }
+        if let Some(chat_id) = &self.chat_id {
+            must.push(serde_json::json!({ "key": "chat_id", "match": { "value": chat_id } }));
+        }
+        if let (Some(from), Some(to)) = (self.date_from, self.date_to) {
+            if from > to {
+                return Err(EnclaveError::BadRequest("date_from is after date_to".to_string()));
+            }
+        }
+        if self.date_from.is_some() || self.date_to.is_some() {
+            let mut range = serde_json::Map::new();
+            if let Some(from) = self.date_from {
+                range.insert("gte".to_string(), from.into());
+            }
+            if let Some(to) = self.date_to {
+                range.insert("lte".to_string(), to.into());
+            }
+            must.push(serde_json::json!({ "key": "message_date", "range": range }));
+        }
+        Ok((!must.is_empty()).then(|| serde_json::json!({ "must": must })))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FilteredRetrievalRequest {
+    /// Text of the semantic query
+    pub query: String,
+    #[serde(default)]
+    pub filters: MessageFilters,
+    /// Results to return, at most MAX_FILTERED_RESULTS
+    pub limit: Option<u32>,
+    pub timeout_secs: Option<u64>,
+    /// Scheduling priority, defaults to normal
+    pub priority: Option<Priority>,
+    /// Anchor a signed execution receipt to Walrus, defaults to ANCHOR_RECEIPTS
+    pub anchor_receipt: Option<bool>,
+    /// Include an `explain` section with the filter, search parameters and raw scores
+    pub explain: Option<bool>,
+    /// `fresh` returns a new attestation over `attestation_nonce` and the signed response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationMode>,
+    /// Hex nonce of a fresh attestation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_nonce: Option<String>,
+    /// Identifier of the query, used to assign a retrieval profile and to attribute feedback
+    pub query_id: Option<String>,
+    /// Retrieval profile to run instead of the assigned one
+    pub profile: Option<String>,
+    /// Qdrant collection to query, one of QDRANT_COLLECTIONS
+    pub collection: Option<String>,
+    /// Also return the task output undecoded, base64 encoded in `raw_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Recall traded for latency, defaults to `balanced` (the collection's search parameters)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_quality: Option<SearchQuality>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl Validate for FilteredRetrievalRequest {}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ProcessedData {
     #[serde(rename = "walrusUrl")]
     pub walrus_url: String,
@@ -103,290 +349,763 @@ pub struct ProcessedData {
     pub blob_id: Option<String>,
 }
 
-pub async fn process_data(
-    State(state): State<Arc<AppState>>,
-    Json(request): Json<ProcessDataRequest<TaskRequest>>,
-) -> Result<Json<TaskResponse>, EnclaveError> {
-    // get attestation
-    let attestation_info = get_attestation(State(state.clone())).await?;
-
-    // Get the absolute path to nodejs-task
-    let current_dir = std::env::current_dir().unwrap();
-    let task_path = current_dir.join("nodejs-task").to_string_lossy().into_owned();
+/// Run a task once any crash backoff has passed, on a warm worker when the pool is
+/// enabled, recording its outcome in the runtime health tracker and metrics. Tasks whose
+/// output is streamed to `output` always run in their own process, as workers only return
+/// their output once the task is done.
+async fn run_task(
+    state: &AppState,
+    operation: &str,
+    mut task_config: TaskConfig,
+    output: Option<OutputSink>,
+) -> anyhow::Result<TaskOutput> {
+    if let Some(request_id) = current_request_id() {
+        task_config.env_vars.insert(REQUEST_ID_ENV.to_string(), request_id);
+    }
+    let delay = state.runtime_health.spawn_delay();
+    if !delay.is_zero() {
+        tracing::warn!("Delaying {} task by {:?} after recent task crashes", operation, delay);
+        tokio::time::sleep(delay).await;
+    }
+    let started = std::time::Instant::now();
+    let args = task_config.args.clone();
+    // The worker pool only runs the default task bundle
+    let pooled = state.worker_pool.as_ref().filter(|_| {
+        std::path::Path::new(&task_config.task_path) == state.task_bundles.default_bundle().path
+    });
+    let task_output = match (pooled, output) {
+        (_, Some(sink)) => NodeTaskRunner::new(task_config).with_output(sink).run().await,
+        (Some(pool), None) => pool.run(&task_config).await,
+        (None, None) => NodeTaskRunner::new(task_config).run().await,
+    };
+    let result = task_output
+        .as_ref()
+        .ok()
+        .and_then(|output| crate::task_result::extract(&output.stdout_text()))
+        .and_then(Result::ok);
+    state.task_audit.record(
+        &*state.keys.current(),
+        state.id_mask_salt(),
+        TaskInvocation {
+            operation,
+            args: &args,
+            caller: current_request_id(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            exit_code: task_output.as_ref().ok().map(|output| output.exit_code),
+            result: result.as_ref(),
+        },
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to record the task audit entry: {:?}", e))?;
+    crate::telemetry::record_task(
+        operation,
+        task_output.as_ref().ok().map(|output| output.exit_code),
+        started.elapsed(),
+    );
+    let task_output = task_output?;
+    state.runtime_health.record(operation, &task_output);
+    state.metrics.observe_task_output(operation, &task_output);
+    Ok(task_output)
+}
 
-    // Prepare environment variables from AppState
-    let mut env_vars = std::collections::HashMap::new();
+/// `response` with the reference to the attestation of `key`, the key about to sign it.
+pub(crate) fn with_attestation_ref(state: &AppState, key: &SigningKey, mut response: TaskResponse) -> TaskResponse {
+    match state.key_attestation(key) {
+        Ok(boot) => response.attestation_ref = Some(boot.reference.clone()),
+        Err(e) => tracing::warn!("Signing task response without an attestation reference: {:?}", e),
+    }
+    response
+}
 
-    // Core blockchain configuration
-    env_vars.insert("MOVE_PACKAGE_ID".to_string(), state.move_package_id().to_string());
-    env_vars.insert("SUI_SECRET_KEY".to_string(), state.sui_secret_key().to_string());
-    env_vars.insert("RUBY_NODES_API_KEY".to_string(), state.ruby_nodes_api_key().to_string());
-    env_vars.insert("WALRUS_AGGREGATOR_URL".to_string(), state.walrus_aggregator_url().to_string());
-    env_vars.insert("WALRUS_PUBLISHER_URL".to_string(), state.walrus_publisher_url().to_string());
-    env_vars.insert("WALRUS_EPOCHS".to_string(), state.walrus_epochs_str().to_string());
+/// Error for a task that could not be run, [EnclaveError::Timeout] when it ran out of time.
+fn task_run_error(task: &str, e: anyhow::Error) -> EnclaveError {
+    match e.downcast_ref::<TaskTimedOut>() {
+        Some(timed_out) => EnclaveError::Timeout(timed_out.to_string()),
+        None => EnclaveError::Internal(format!("Failed to execute {}: {}", task, e)),
+    }
+}
 
-    // Ollama embedding service configuration
-    env_vars.insert("OLLAMA_API_URL".to_string(), state.ollama_api_url().to_string());
-    env_vars.insert("OLLAMA_MODEL".to_string(), state.ollama_model().to_string());
+/// Serve a task result signed with the enclave key under `scope`, either as a BCS envelope
+/// (when requested via `Accept`) or as a [ProcessedDataResponse] in the standard JSON
+/// envelope, whose `signature` then repeats the signature over `data.response`.
+pub(crate) fn respond_task(
+    ctx: &RequestContext,
+    state: &AppState,
+    headers: &HeaderMap,
+    scope: IntentScope,
+    result: Result<TaskResponse, EnclaveError>,
+) -> Response {
+    let key = state.keys.current();
+    let result = result.and_then(|response| {
+        state.key_usage.acquire(scope)?;
+        Ok(with_attestation_ref(state, &key, response))
+    });
+    let result = match result {
+        Ok(response) if wants_bcs(headers) => to_bcs_response(&*key, response, current_timestamp_ms(), scope),
+        Ok(response) => to_signed_response(&*key, response, current_timestamp_ms(), scope).map(|signed| {
+            let signature = signed.signature.clone();
+            ctx.ok(signed).with_signature(signature).into_response()
+        }),
+        Err(e) => Err(e),
+    };
+    result.unwrap_or_else(|e| ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response())
+}
 
-    // Azure open ai embedding configuration
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_ENDPOINT".to_string(), state.azure_text_embedding_api_endpoint().to_string());
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_KEY".to_string(), state.azure_text_embedding_api_key().to_string());
+/// [respond_task] for requests with `attestation: "fresh"`: the JSON envelope also carries
+/// a new attestation over `nonce` that commits to the signed response.
+pub(crate) async fn respond_task_attested(
+    ctx: &RequestContext,
+    state: &AppState,
+    scope: IntentScope,
+    result: Result<TaskResponse, EnclaveError>,
+    nonce: Vec<u8>,
+) -> Response {
+    let attested = async {
+        let response = result?;
+        state.key_usage.acquire(scope)?;
+        let key = state.keys.current();
+        let response = with_attestation_ref(state, &key, response);
+        let mut signed = to_signed_response(&*key, response, current_timestamp_ms(), scope)?;
+        signed.attestation = Some(attest_signed_message(state, &key.keypair, &signed.response, nonce).await?);
+        Ok(signed)
+    }
+    .await;
+    match attested {
+        Ok(signed) => {
+            let signature = signed.signature.clone();
+            ctx.ok(signed).with_signature(signature).into_response()
+        }
+        Err(e) => ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response(),
+    }
+}
 
-    // Qdrant vector database configuration
-    env_vars.insert("QDRANT_URL".to_string(), state.qdrant_url().to_string());
-    env_vars.insert("QDRANT_COLLECTION_NAME".to_string(), state.qdrant_collection_name().to_string());
-    if let Some(api_key) = state.qdrant_api_key() {
-        env_vars.insert("QDRANT_API_KEY".to_string(), api_key.to_string());
+/// Run the Node.js task with `args` and return its signed output.
+#[utoipa::path(
+    post,
+    path = "/process_data",
+    request_body = TaskProcessDataRequest,
+    responses(
+        (status = 200, description = "Signed task response, BCS encoded with `Accept: application/bcs`", body = TaskEnvelope),
+        (status = 400, description = "Malformed JSON or an invalid option, e.g. an unknown collection"),
+        (status = 403, description = "Refused by the authorization hook"),
+        (status = 413, description = "Request body over MAX_REQUEST_BODY_BYTES"),
+        (status = 422, description = "Invalid payload fields, or the task exited with a non-zero code"),
+        (status = 429, description = "Task queue full or signing rate limit reached"),
+        (status = 502, description = "Walrus, Sui, Qdrant or the embedding service failed, or the task reported an invalid result"),
+        (status = 504, description = "The task ran out of time")
+    )
+)]
+pub async fn process_data(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<ProcessDataRequest<TaskRequest>>,
+) -> Response {
+    let payload = request.payload;
+    let nonce = match fresh_attestation_nonce(payload.attestation, payload.attestation_nonce.as_deref(), &headers) {
+        Ok(nonce) => nonce,
+        Err(e) => return ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response(),
+    };
+    let receipt = ReceiptContext::start(&state, "process_data", &payload, payload.anchor_receipt);
+    let result = execute_process_data(&state, payload, None).await;
+    let result = receipt.attach(&state, result).await;
+    match nonce {
+        Some(nonce) => respond_task_attested(&ctx, &state, IntentScope::ProcessData, result, nonce).await,
+        None => respond_task(&ctx, &state, &headers, IntentScope::ProcessData, result),
     }
+}
 
-    // Task processing configuration
-    env_vars.insert("EMBEDDING_BATCH_SIZE".to_string(), state.embedding_batch_size_str().to_string());
-    env_vars.insert("VECTOR_BATCH_SIZE".to_string(), state.vector_batch_size_str().to_string());
+/// Run the process_data task, sending its output lines to `output` as they are read.
+pub async fn execute_process_data(
+    state: &AppState,
+    payload: TaskRequest,
+    output: Option<OutputSink>,
+) -> Result<TaskResponse, EnclaveError> {
+    // Refuse to run tasks whose dependencies failed the allowlist check
+    state.dependency_status.ensure_allowed()?;
+    authorize(state, Operation::ProcessData, [], state.qdrant_collection_name()).await?;
 
-    // Social truth telegram bot configuration
-    env_vars.insert("TELEGRAM_SOCIAL_TRUTH_BOT_ID".to_string(), state.telegram_social_truth_bot_id().to_string());
+    // get attestation
+    let (attestation_info, attestation_ms) = timed(fetch_attestation(state)).await;
+    let attestation_info = attestation_info?;
+
+    // The operation's task bundle, with the variables its env policy allows
+    let bundle = state.task_bundles.for_operation(Operation::ProcessData);
+    let task_path = bundle.path.to_string_lossy().into_owned();
 
-    // ID mask salt configuration
-    env_vars.insert("ID_MASK_SALT".to_string(), state.id_mask_salt().to_string());
+    let env_vars = state.task_env_vars(Operation::ProcessData, state.qdrant_collection_name());
 
     // Configure task runner
-    let mut args = request.payload.args.unwrap_or_default();
+    let mut args = payload.args.unwrap_or_default();
     args.push(attestation_info.attestation.enclaveId.clone());
 
     let task_config = TaskConfig {
         task_path,
-        timeout_secs: request.payload.timeout_secs.unwrap_or(900),
+        timeout_secs: payload.timeout_secs.unwrap_or(900),
         args,
-        env_vars,
+        env_vars: bundle.filter_env(env_vars),
+        scheduling: state.task_scheduling.clone(),
+        node_flags: state.task_node_flags.for_operation("process_data"),
+        retry: state.task_retry.clone(),
+        input: payload.input.unwrap_or_default(),
     };
 
-    // Create and run the task
-    let task_runner = NodeTaskRunner::new(task_config);
-    let task_output = task_runner.run().await.map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to execute Node.js task: {}", e))
-    })?;
+    // Wait for a free task slot, then create and run the task
+    let (permit, queue_wait_ms) =
+        timed(state.scheduler.acquire(payload.priority.unwrap_or_default())).await;
+    let _permit = permit?;
+    let task_output = run_task(state, "process_data", task_config, output)
+        .await
+        .map_err(|e| task_run_error("Node.js task", e))?;
+    let mut timeline = Timeline::from_task_output(&task_output);
+    timeline.queue_wait_ms = queue_wait_ms;
+    timeline.attestation_ms = attestation_ms;
 
     // If task failed, return error
     if task_output.exit_code != 0 {
-        return Err(EnclaveError::GenericError(format!(
-            "Task failed with exit code {}: stderr={}. stdout={}",
-            task_output.exit_code,
-            task_output.stderr,
-            task_output.stdout
-        )));
+        return Err(EnclaveError::TaskFailed {
+            exit_code: task_output.exit_code,
+            stderr: task_output.stderr_text().into_owned(),
+        });
     }
 
-    // Extract JSON result from stdout using delimiters
-    let json_data: serde_json::Value = extract_task_result(&task_output.stdout)
-        .unwrap_or_else(|| serde_json::json!({
-            "status": "failed",
-            "operation": "default",
-            "error": "Failed to extract task result from output",
-            "raw_output": task_output.stdout
-        }));
+    let (_, json_data) = parse_result::<serde_json::Value>(&task_output)?;
 
-    Ok(Json(TaskResponse {
+    let raw_output = payload.raw.unwrap_or(false).then(|| task_output.raw());
+    Ok(TaskResponse {
         status: "success".to_string(),
         data: json_data,
-        stderr: task_output.stderr,
+        stderr: task_output.stderr_text().into_owned(),
         exit_code: task_output.exit_code,
         execution_time_ms: task_output.execution_time_ms,
-    }))
+        receipt_blob_id: None,
+        resource_usage: task_output.resource_usage,
+        timeline: Some(timeline),
+        attestation_ref: None,
+        raw_output,
+        request_hash: None,
+    })
 }
 
+/// Queue an embedding ingest job and return it immediately with `202 Accepted`. The task
+/// runs in the background; follow it with `/jobs/:id` or `/jobs/:id/wait` and fetch the
+/// response from `/jobs/:id/result`. A request repeating the idempotency key of an earlier
+/// one gets that request's job instead, see [crate::idempotency].
+#[utoipa::path(
+    post,
+    path = "/embedding_ingest",
+    request_body = EmbeddingIngestProcessDataRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Key under which the job is remembered for IDEMPOTENCY_TTL_SECS, overriding the payload's requestId")
+    ),
+    responses(
+        (status = 200, description = "Succeeded job of an earlier request with the same idempotency key", body = JobEnvelope),
+        (status = 202, description = "Queued ingest job, or the unfinished job of an earlier request with the same idempotency key", body = JobEnvelope),
+        (status = 400, description = "Malformed JSON, an invalid option, e.g. an unknown collection, or an idempotency key used with a different payload"),
+        (status = 403, description = "Seal policy the enclave cannot decrypt under, or refused by the authorization hook"),
+        (status = 404, description = "Walrus blob not served by the aggregator"),
+        (status = 413, description = "Request body over MAX_REQUEST_BODY_BYTES, or blob over WALRUS_MAX_INGEST_BLOB_BYTES"),
+        (status = 422, description = "Invalid payload fields"),
+        (status = 429, description = "Task queue full or address rate limit reached")
+    )
+)]
 pub async fn embedding_ingest(
+    ctx: RequestContext,
     State(state): State<Arc<AppState>>,
-    Json(request): Json<ProcessDataRequest<EmbeddingIngestRequest>>,
-) -> Result<Json<TaskResponse>, EnclaveError> {
-    // get attestation
-    let attestation_info = get_attestation(State(state.clone())).await?;
-
-    // Get the absolute path to nodejs-task
-    let current_dir = std::env::current_dir().unwrap();
-    let task_path = current_dir.join("nodejs-task").to_string_lossy().into_owned();
-
-    // Prepare environment variables from AppState
-    let mut env_vars = std::collections::HashMap::new();
-
-    // Core blockchain configuration
-    env_vars.insert("MOVE_PACKAGE_ID".to_string(), state.move_package_id().to_string());
-    env_vars.insert("SUI_SECRET_KEY".to_string(), state.sui_secret_key().to_string());
-    env_vars.insert("RUBY_NODES_API_KEY".to_string(), state.ruby_nodes_api_key().to_string());
-    env_vars.insert("WALRUS_AGGREGATOR_URL".to_string(), state.walrus_aggregator_url().to_string());
-    env_vars.insert("WALRUS_PUBLISHER_URL".to_string(), state.walrus_publisher_url().to_string());
-    env_vars.insert("WALRUS_EPOCHS".to_string(), state.walrus_epochs_str().to_string());
-
-    // Ollama embedding service configuration
-    env_vars.insert("OLLAMA_API_URL".to_string(), state.ollama_api_url().to_string());
-    env_vars.insert("OLLAMA_MODEL".to_string(), state.ollama_model().to_string());
-
-    // Azure open ai embedding configuration
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_ENDPOINT".to_string(), state.azure_text_embedding_api_endpoint().to_string());
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_KEY".to_string(), state.azure_text_embedding_api_key().to_string());
-    
-    // Qdrant vector database configuration
-    env_vars.insert("QDRANT_URL".to_string(), state.qdrant_url().to_string());
-    env_vars.insert("QDRANT_COLLECTION_NAME".to_string(), state.qdrant_collection_name().to_string());
-    if let Some(api_key) = state.qdrant_api_key() {
-        env_vars.insert("QDRANT_API_KEY".to_string(), api_key.to_string());
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<ProcessDataRequest<EmbeddingIngestRequest>>,
+) -> ApiResponse<JobRecord> {
+    let payload = request.payload;
+    let key = match state.idempotency.key(&headers, payload.request_id.as_deref()) {
+        Ok(key) => key,
+        Err(e) => return ctx.error(e),
+    };
+    // Reject up front rather than failing the job once it is queued
+    let collection = match state.qdrant_collection(payload.collection.as_deref()) {
+        Ok(collection) => collection.to_string(),
+        Err(e) => return ctx.error(e),
+    };
+    let admitted = match admit_embedding_ingest(&state, &payload, &collection).await {
+        Ok(admitted) => admitted,
+        Err(e) => return ctx.error(e),
+    };
+    let start = || {
+        state
+            .scheduler
+            .check_capacity()
+            .and_then(|_| state.address_limits.acquire(AddressOperation::Ingest, [payload.policy_object_id.as_str()]))
+            .map(|_| state.jobs.create("embedding_ingest"))
+    };
+    let claim = match &key {
+        Some(key) => state.idempotency.claim(&state.jobs, key, &payload, start),
+        None => start().map(Claim::Started),
+    };
+    let job = match claim {
+        Ok(Claim::Started(job)) => job,
+        Ok(Claim::Replayed(job)) => {
+            let status = if job.status == JobStatus::Succeeded { StatusCode::OK } else { StatusCode::ACCEPTED };
+            return ctx.ok(job).with_status(status);
+        }
+        Err(e) => return ctx.error(e),
+    };
+    let receipt = ReceiptContext::start(&state, "embedding_ingest", &payload, payload.anchor_receipt);
+
+    let job_id = job.id.clone();
+    tokio::spawn(inherit_request_id(async move {
+        state.jobs.mark_running(&job_id);
+        let result = execute_embedding_ingest(&state, payload, Some(admitted), None).await;
+        let result = receipt.attach(&state, result).await;
+        match &result {
+            // Large ingests stay running until the collection is optimized and warmed up
+            Ok(response) => {
+                if let Some(report) = warmup::after_ingest(&state, &collection, &response.data).await {
+                    state.jobs.set_warmup(&job_id, report);
+                }
+            }
+            Err(e) => tracing::warn!("Embedding ingest job {} failed: {:?}", job_id, e),
+        }
+        state.jobs.complete(&job_id, result);
+        if let Some(key) = key {
+            state.idempotency.finish(&key, state.jobs.get(&job_id));
+        }
+    }));
+
+    ctx.ok(job).with_status(StatusCode::ACCEPTED)
+}
+
+/// Proof that a request passed the authorization hook and the pre-checks of its operation in
+/// its handler, so the task it starts does not repeat them. Only the `admit_*` functions
+/// create one.
+#[derive(Debug)]
+pub struct Admitted(());
+
+/// Authorize an ingest into `collection` and pre-check its Seal policy and Walrus blob.
+pub async fn admit_embedding_ingest(
+    state: &AppState,
+    payload: &EmbeddingIngestRequest,
+    collection: &str,
+) -> Result<Admitted, EnclaveError> {
+    authorize(state, Operation::EmbeddingIngest, [payload.policy_object_id.as_str()], collection).await?;
+    precheck_policies(state, [payload.policy_object_id.as_str()]).await?;
+    precheck_blob(state, &payload.walrus_blob_id).await?;
+    Ok(Admitted(()))
+}
+
+/// Run the embedding task, sending its output lines to `output` as they are read. Without
+/// `admitted` the request is authorized and pre-checked first.
+pub async fn execute_embedding_ingest(
+    state: &AppState,
+    payload: EmbeddingIngestRequest,
+    admitted: Option<Admitted>,
+    output: Option<OutputSink>,
+) -> Result<TaskResponse, EnclaveError> {
+    // Refuse to run tasks whose dependencies failed the allowlist check
+    state.dependency_status.ensure_allowed()?;
+    let collection = state.qdrant_collection(payload.collection.as_deref())?;
+    if admitted.is_none() {
+        admit_embedding_ingest(state, &payload, collection).await?;
     }
+    let encryption_public_key = payload.encryption_public_key.as_deref().map(parse_encryption_public_key).transpose()?;
+
+    // get attestation
+    let (attestation_info, attestation_ms) = timed(fetch_attestation(state)).await;
+    let attestation_info = attestation_info?;
 
-    // Task processing configuration
-    env_vars.insert("EMBEDDING_BATCH_SIZE".to_string(), state.embedding_batch_size_str().to_string());
-    env_vars.insert("VECTOR_BATCH_SIZE".to_string(), state.vector_batch_size_str().to_string());
+    // The operation's task bundle, with the variables its env policy allows
+    let bundle = state.task_bundles.for_operation(Operation::EmbeddingIngest);
+    let task_path = bundle.path.to_string_lossy().into_owned();
 
-    // Social truth telegram bot configuration
-    env_vars.insert("TELEGRAM_SOCIAL_TRUTH_BOT_ID".to_string(), state.telegram_social_truth_bot_id().to_string());
+    let mut env_vars = state.task_env_vars(Operation::EmbeddingIngest, collection);
 
-    // ID mask salt configuration
-    env_vars.insert("ID_MASK_SALT".to_string(), state.id_mask_salt().to_string());
+    // Message text is stored encrypted under the enclave's payload key, unless the client
+    // brought its own
+    if let (Some(keyring), None) = (&state.config.payload_keys, &encryption_public_key) {
+        env_vars.extend(keyring.task_env());
+    }
     
     // Configure task runner for embedding operation
     let mut args = vec![
         "--operation".to_string(),
         "embedding".to_string(),
         "--walrus-blob-id".to_string(),
-        request.payload.walrus_blob_id.clone(),
+        payload.walrus_blob_id.clone(),
         "--on-chain-file-obj-id".to_string(),
-        request.payload.on_chain_file_obj_id.clone(),
+        payload.on_chain_file_obj_id.clone(),
         "--policy-object-id".to_string(),
-        request.payload.policy_object_id.clone(),
+        payload.policy_object_id.clone(),
         "--threshold".to_string(),
-        request.payload.threshold.clone(),
+        payload.threshold.clone(),
     ];
 
     // Add batch size if provided
-    if let Some(batch_size) = request.payload.batch_size {
+    if let Some(batch_size) = payload.batch_size {
         args.push("--batch-size".to_string());
         args.push(batch_size.to_string());
     }
 
+    if let Some(expiry_epoch) = payload.blob_expiry_epoch {
+        args.push("--blob-expiry-epoch".to_string());
+        args.push(expiry_epoch.to_string());
+    }
+
+    if let Some(public_key) = encryption_public_key {
+        args.push("--encryption-public-key".to_string());
+        args.push(public_key);
+    }
+
     args.push(attestation_info.attestation.enclaveId.clone());
 
     let task_config = TaskConfig {
         task_path,
-        timeout_secs: request.payload.timeout_secs.unwrap_or(360), // 6 minutes default for embedding
+        timeout_secs: payload.timeout_secs.unwrap_or(360), // 6 minutes default for embedding
         args,
-        env_vars,
+        env_vars: bundle.filter_env(env_vars),
+        scheduling: state.task_scheduling.clone(),
+        node_flags: state.task_node_flags.for_operation("embedding_ingest"),
+        retry: state.task_retry.clone(),
+        input: serde_json::Value::Null,
     };
 
-    // Create and run the task
-    let task_runner = NodeTaskRunner::new(task_config);
-    let task_output = task_runner.run().await.map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to execute embedding ingest task: {}", e))
-    })?;
-
-    // Extract JSON result from stdout using delimiters
-    let json_data: serde_json::Value = extract_task_result(&task_output.stdout)
-        .unwrap_or_else(|| serde_json::json!({
-            "status": "failed",
-            "operation": "embedding",
-            "error": "Failed to extract task result from output",
-            "raw_output": task_output.stdout
-        }));
-
-    Ok(Json(TaskResponse {
+    // Wait for a free task slot, then create and run the task
+    let (permit, queue_wait_ms) =
+        timed(state.scheduler.acquire(payload.priority.unwrap_or_default())).await;
+    let _permit = permit?;
+    let task_output = run_task(state, "embedding_ingest", task_config, output)
+        .await
+        .map_err(|e| task_run_error("embedding ingest task", e))?;
+    let mut timeline = Timeline::from_task_output(&task_output);
+    timeline.queue_wait_ms = queue_wait_ms;
+    timeline.attestation_ms = attestation_ms;
+
+    let (result, json_data) = parse_result::<EmbeddingResult>(&task_output)?;
+
+    // The task creates a missing collection on first ingest and reports its parameters
+    if let Some(created) = result.collection_created.filter(|c| c.is_object()) {
+        state.audit_log.record("collection_created", collection, created);
+    }
+
+    let raw_output = payload.raw.unwrap_or(false).then(|| task_output.raw());
+    Ok(TaskResponse {
         status: "success".to_string(),
         data: json_data,
-        stderr: task_output.stderr,
+        stderr: task_output.stderr_text().into_owned(),
         exit_code: task_output.exit_code,
         execution_time_ms: task_output.execution_time_ms,
-    }))
+        receipt_blob_id: None,
+        resource_usage: task_output.resource_usage,
+        timeline: Some(timeline),
+        attestation_ref: None,
+        raw_output,
+        request_hash: None,
+    })
 }
 
+/// Decrypt the messages of the given Walrus blobs and return them signed, or stream them
+/// per blob with `Accept: application/x-ndjson`, see [crate::retrieval_stream].
+#[utoipa::path(
+    post,
+    path = "/retrieve_messages_by_blob_ids",
+    request_body = MessageBlobRetrievalProcessDataRequest,
+    responses(
+        (status = 200, description = "Signed task response, BCS encoded with `Accept: application/bcs`, or one record per blob and a signed summary with `Accept: application/x-ndjson`", body = TaskEnvelope),
+        (status = 400, description = "Malformed JSON or an invalid option, e.g. an unknown collection"),
+        (status = 403, description = "Seal policy the enclave cannot decrypt under, or refused by the authorization hook"),
+        (status = 413, description = "Request body over MAX_REQUEST_BODY_BYTES"),
+        (status = 422, description = "Invalid payload fields, or the task exited with a non-zero code"),
+        (status = 429, description = "Task queue full, or signing or address rate limit reached"),
+        (status = 502, description = "Walrus, Sui, Qdrant or the embedding service failed, or the task reported an invalid result"),
+        (status = 504, description = "The task ran out of time")
+    )
+)]
 pub async fn retrieve_messages_by_blob_ids(
+    ctx: RequestContext,
     State(state): State<Arc<AppState>>,
-    Json(request): Json<ProcessDataRequest<MessageBlobRetrievalRequest>>,
-) -> Result<Json<TaskResponse>, EnclaveError> {
-    // get attestation
-    let attestation_info = get_attestation(State(state.clone())).await?;
-
-    // Get the absolute path to nodejs-task
-    let current_dir = std::env::current_dir().unwrap();
-    let task_path = current_dir.join("nodejs-task").to_string_lossy().into_owned();
-
-    // Prepare environment variables from AppState
-    let mut env_vars = std::collections::HashMap::new();
-
-    // Core blockchain configuration
-    env_vars.insert("MOVE_PACKAGE_ID".to_string(), state.move_package_id().to_string());
-    env_vars.insert("SUI_SECRET_KEY".to_string(), state.sui_secret_key().to_string());
-    env_vars.insert("RUBY_NODES_API_KEY".to_string(), state.ruby_nodes_api_key().to_string());
-    env_vars.insert("WALRUS_AGGREGATOR_URL".to_string(), state.walrus_aggregator_url().to_string());
-    env_vars.insert("WALRUS_PUBLISHER_URL".to_string(), state.walrus_publisher_url().to_string());
-    env_vars.insert("WALRUS_EPOCHS".to_string(), state.walrus_epochs_str().to_string());
-
-    // Ollama embedding service configuration (not needed but kept for consistency)
-    env_vars.insert("OLLAMA_API_URL".to_string(), state.ollama_api_url().to_string());
-    env_vars.insert("OLLAMA_MODEL".to_string(), state.ollama_model().to_string());
-
-    // Azure open ai embedding configuration
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_ENDPOINT".to_string(), state.azure_text_embedding_api_endpoint().to_string());
-    env_vars.insert("AZURE_TEXT_EMBEDDING_API_KEY".to_string(), state.azure_text_embedding_api_key().to_string());
-    
-    // Qdrant vector database configuration (not needed but kept for consistency)
-    env_vars.insert("QDRANT_URL".to_string(), state.qdrant_url().to_string());
-    env_vars.insert("QDRANT_COLLECTION_NAME".to_string(), state.qdrant_collection_name().to_string());
-    if let Some(api_key) = state.qdrant_api_key() {
-        env_vars.insert("QDRANT_API_KEY".to_string(), api_key.to_string());
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<ProcessDataRequest<MessageBlobRetrievalRequest>>,
+) -> Response {
+    let payload = request.payload;
+    let nonce = match fresh_attestation_nonce(payload.attestation, payload.attestation_nonce.as_deref(), &headers) {
+        Ok(nonce) => nonce,
+        Err(e) => return ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response(),
+    };
+    // Checked before a stream starts, while a refusal can still be a 403
+    let admitted = match state.qdrant_collection(payload.collection.as_deref()) {
+        Ok(collection) => admit_retrieval(&state, &payload, collection).await,
+        Err(e) => Err(e),
+    };
+    let admitted = match admitted {
+        Ok(admitted) => admitted,
+        Err(e) => return ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response(),
+    };
+    if let Err(e) = state.address_limits.acquire(AddressOperation::Retrieval, payload.addresses()) {
+        return ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response();
+    }
+    if wants_ndjson(&headers) {
+        return retrieve_messages_ndjson(&ctx, state, payload, admitted);
+    }
+    let receipt = ReceiptContext::start(&state, "retrieve_messages_by_blob_ids", &payload, payload.anchor_receipt);
+    let result = execute_retrieve_messages_by_blob_ids(&state, payload, Some(admitted), None).await;
+    let result = receipt.attach(&state, result).await;
+    match nonce {
+        Some(nonce) => respond_task_attested(&ctx, &state, IntentScope::BlobRetrieval, result, nonce).await,
+        None => respond_task(&ctx, &state, &headers, IntentScope::BlobRetrieval, result),
     }
+}
 
-    // Task processing configuration
-    env_vars.insert("EMBEDDING_BATCH_SIZE".to_string(), state.embedding_batch_size_str().to_string());
-    env_vars.insert("VECTOR_BATCH_SIZE".to_string(), state.vector_batch_size_str().to_string());
+/// Authorize a retrieval from `collection` and pre-check its Seal policies.
+pub async fn admit_retrieval(
+    state: &AppState,
+    payload: &MessageBlobRetrievalRequest,
+    collection: &str,
+) -> Result<Admitted, EnclaveError> {
+    authorize(state, Operation::RetrieveMessagesByBlobIds, payload.addresses(), collection).await?;
+    precheck_policies(state, payload.addresses()).await?;
+    Ok(Admitted(()))
+}
 
-    // Social truth telegram bot configuration
-    env_vars.insert("TELEGRAM_SOCIAL_TRUTH_BOT_ID".to_string(), state.telegram_social_truth_bot_id().to_string());
+/// Without `admitted` the request is authorized and pre-checked first.
+pub async fn execute_retrieve_messages_by_blob_ids(
+    state: &AppState,
+    payload: MessageBlobRetrievalRequest,
+    admitted: Option<Admitted>,
+    output: Option<OutputSink>,
+) -> Result<TaskResponse, EnclaveError> {
+    // Refuse to run tasks whose dependencies failed the allowlist check
+    state.dependency_status.ensure_allowed()?;
+    let collection = state.qdrant_collection(payload.collection.as_deref())?;
+    if admitted.is_none() {
+        admit_retrieval(state, &payload, collection).await?;
+    }
 
-    // ID mask salt configuration
-    env_vars.insert("ID_MASK_SALT".to_string(), state.id_mask_salt().to_string());
-    
-    // Serialize blob file pairs to JSON
-    let blob_file_pairs_json = serde_json::to_string(&request.payload.blob_file_pairs)
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to serialize blob file pairs: {}", e)))?;
+    // Pick the retrieval profile before doing any work so unknown profiles fail fast
+    let query_hash = payload.query_id.as_deref().map(|id| mask_id(state.id_mask_salt(), id));
+    let profile = state
+        .experiments
+        .select(payload.profile.as_deref(), query_hash.as_deref())?
+        .cloned();
+    let profile_name = profile.as_ref().map_or(DEFAULT_PROFILE, |p| p.name.as_str()).to_string();
 
-    // Configure task runner for blob ID retrieval operation
+    // get attestation
+    let (attestation_info, attestation_ms) = timed(fetch_attestation(state)).await;
+    let attestation_info = attestation_info?;
+
+    // The operation's task bundle, with the variables its env policy allows
+    let bundle = state.task_bundles.for_operation(Operation::RetrieveMessagesByBlobIds);
+    let task_path = bundle.path.to_string_lossy().into_owned();
+
+    let env_vars = state.task_env_vars(Operation::RetrieveMessagesByBlobIds, collection);
+
+    // Configure task runner for blob ID retrieval operation. The blob file pairs go in the
+    // protocol request on stdin, argv is too small for large batches and visible to ps.
     let mut args = vec![
         "--operation".to_string(),
         "retrieve-by-blob-ids".to_string(),
-        "--blob-file-pairs".to_string(),
-        blob_file_pairs_json,
         "--threshold".to_string(),
-        request.payload.threshold.clone(),
+        payload.threshold.clone(),
     ];
 
+    if payload.explain.unwrap_or(false) {
+        args.push("--explain".to_string());
+    }
+
+    args.push(attestation_info.attestation.enclaveId.clone());
+
+    let input = serde_json::json!({
+        "blobFilePairs": payload.blob_file_pairs,
+        "retrievalProfile": profile,
+    });
+
+    let task_config = TaskConfig {
+        task_path,
+        timeout_secs: payload.timeout_secs.unwrap_or(120),
+        args,
+        env_vars: bundle.filter_env(env_vars),
+        scheduling: state.task_scheduling.clone(),
+        node_flags: state.task_node_flags.for_operation("retrieve_messages_by_blob_ids"),
+        retry: state.task_retry.clone(),
+        input,
+    };
+
+    // Wait for a free task slot, then create and run the task
+    let (permit, queue_wait_ms) =
+        timed(state.scheduler.acquire(payload.priority.unwrap_or_default())).await;
+    let _permit = permit?;
+    let task_output = run_task(state, "retrieve_messages_by_blob_ids", task_config, output)
+        .await
+        .map_err(|e| task_run_error("blob ID retrieval task", e))?;
+    let mut timeline = Timeline::from_task_output(&task_output);
+    timeline.queue_wait_ms = queue_wait_ms;
+    timeline.attestation_ms = attestation_ms;
+
+    // Record the retrieval under its profile and tell the client which profile served it,
+    // so feedback on the results can be attributed
+    let parsed = parse_result::<RetrievalResult>(&task_output);
+    let success = task_output.exit_code == 0
+        && matches!(&parsed, Ok((result, _)) if result.status == ResultStatus::Success);
+    state.experiments.observe(&profile_name, task_output.execution_time_ms, success);
+    let (_, mut json_data) = parsed?;
+    if let Some(data) = json_data.as_object_mut() {
+        data.insert("retrieval_profile".to_string(), serde_json::Value::String(profile_name));
+    }
+
+    let raw_output = payload.raw.unwrap_or(false).then(|| task_output.raw());
+    Ok(TaskResponse {
+        status: "success".to_string(),
+        data: json_data,
+        stderr: task_output.stderr_text().into_owned(),
+        exit_code: task_output.exit_code,
+        execution_time_ms: task_output.execution_time_ms,
+        receipt_blob_id: None,
+        resource_usage: task_output.resource_usage,
+        timeline: Some(timeline),
+        attestation_ref: None,
+        raw_output,
+        request_hash: None,
+    })
+}
+
+/// Semantic search over the ingested messages, restricted by `filters`, returned signed.
+#[utoipa::path(
+    post,
+    path = "/retrieve_messages_filtered",
+    request_body = FilteredRetrievalProcessDataRequest,
+    responses(
+        (status = 200, description = "Signed task response, BCS encoded with `Accept: application/bcs`", body = TaskEnvelope),
+        (status = 400, description = "Malformed JSON or an invalid option, e.g. an unknown collection"),
+        (status = 403, description = "Refused by the authorization hook"),
+        (status = 413, description = "Request body over MAX_REQUEST_BODY_BYTES"),
+        (status = 422, description = "Invalid payload fields, or the task exited with a non-zero code"),
+        (status = 429, description = "Task queue full or signing rate limit reached"),
+        (status = 502, description = "Walrus, Sui, Qdrant or the embedding service failed, or the task reported an invalid result"),
+        (status = 504, description = "The task ran out of time")
+    )
+)]
+pub async fn retrieve_messages_filtered(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<ProcessDataRequest<FilteredRetrievalRequest>>,
+) -> Response {
+    let payload = request.payload;
+    let nonce = match fresh_attestation_nonce(payload.attestation, payload.attestation_nonce.as_deref(), &headers) {
+        Ok(nonce) => nonce,
+        Err(e) => return ctx.error::<ProcessedDataResponse<IntentMessage<TaskResponse>>>(e).into_response(),
+    };
+    let receipt = ReceiptContext::start(&state, "retrieve_messages_filtered", &payload, payload.anchor_receipt);
+    let result = execute_retrieve_messages_filtered(&state, payload).await;
+    let result = receipt.attach(&state, result).await;
+    match nonce {
+        Some(nonce) => respond_task_attested(&ctx, &state, IntentScope::MessageRetrieval, result, nonce).await,
+        None => respond_task(&ctx, &state, &headers, IntentScope::MessageRetrieval, result),
+    }
+}
+
+/// Embed the query and search the collection with the payload filters in the task, so
+/// query vectors get the same privacy projection as stored ones.
+pub async fn execute_retrieve_messages_filtered(
+    state: &AppState,
+    payload: FilteredRetrievalRequest,
+) -> Result<TaskResponse, EnclaveError> {
+    state.dependency_status.ensure_allowed()?;
+
+    if payload.query.trim().is_empty() {
+        return Err(EnclaveError::BadRequest("query must not be empty".to_string()));
+    }
+    let filter = payload.filters.qdrant_filter()?;
+    let query_hash = payload.query_id.as_deref().map(|id| mask_id(state.id_mask_salt(), id));
+    let profile = state
+        .experiments
+        .select(payload.profile.as_deref(), query_hash.as_deref())?
+        .cloned();
+    let profile_name = profile.as_ref().map_or(DEFAULT_PROFILE, |p| p.name.as_str()).to_string();
+    let limit = payload
+        .limit
+        .or_else(|| profile.as_ref().and_then(|p| p.top_k))
+        .unwrap_or(DEFAULT_FILTERED_RESULTS);
+    if limit == 0 || limit > MAX_FILTERED_RESULTS {
+        return Err(EnclaveError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_FILTERED_RESULTS
+        )));
+    }
+    let collection = state.qdrant_collection(payload.collection.as_deref())?;
+    authorize(state, Operation::RetrieveMessagesFiltered, [], collection).await?;
+
+    let (attestation_info, attestation_ms) = timed(fetch_attestation(state)).await;
+    let attestation_info = attestation_info?;
+
+    // The operation's task bundle, with the variables its env policy allows
+    let bundle = state.task_bundles.for_operation(Operation::RetrieveMessagesFiltered);
+    let task_path = bundle.path.to_string_lossy().into_owned();
+    let mut env_vars = state.task_env_vars(Operation::RetrieveMessagesFiltered, collection);
+    let search_quality = payload.search_quality.unwrap_or_default();
+    if search_quality != SearchQuality::Balanced {
+        let search_params = search_quality.search_params(state.collection_tuning.get(collection));
+        let search_params = serde_json::to_string(&search_params).expect("search parameters serialize to JSON");
+        env_vars.insert("QDRANT_SEARCH_PARAMS".to_string(), search_params);
+    }
+
+    // The query text and filter are user data, they go in the protocol request on stdin
+    let mut args = vec![
+        "--operation".to_string(),
+        "retrieve-filtered".to_string(),
+        "--limit".to_string(),
+        limit.to_string(),
+    ];
+    if payload.explain.unwrap_or(false) {
+        args.push("--explain".to_string());
+    }
     args.push(attestation_info.attestation.enclaveId.clone());
+    let input = serde_json::json!({
+        "query": payload.query,
+        "filter": filter,
+        "retrievalProfile": profile,
+    });
 
     let task_config = TaskConfig {
         task_path,
-        timeout_secs: request.payload.timeout_secs.unwrap_or(120),
+        timeout_secs: payload.timeout_secs.unwrap_or(120),
         args,
-        env_vars,
+        env_vars: bundle.filter_env(env_vars),
+        scheduling: state.task_scheduling.clone(),
+        node_flags: state.task_node_flags.for_operation("retrieve_messages_filtered"),
+        retry: state.task_retry.clone(),
+        input,
     };
 
-    // Create and run the task
-    let task_runner = NodeTaskRunner::new(task_config);
-    let task_output = task_runner.run().await.map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to execute blob ID retrieval task: {}", e))
-    })?;
-
-    // Extract JSON result from stdout using delimiters
-    let json_data: serde_json::Value = extract_task_result(&task_output.stdout)
-        .unwrap_or_else(|| serde_json::json!({
-            "status": "failed",
-            "operation": "retrieve-by-blob-ids",
-            "error": "Failed to extract task result from output",
-            "raw_output": task_output.stdout
-        }));
-
-    Ok(Json(TaskResponse {
+    let (permit, queue_wait_ms) =
+        timed(state.scheduler.acquire(payload.priority.unwrap_or_default())).await;
+    let _permit = permit?;
+    let task_output = run_task(state, "retrieve_messages_filtered", task_config, None)
+        .await
+        .map_err(|e| task_run_error("filtered retrieval task", e))?;
+    let mut timeline = Timeline::from_task_output(&task_output);
+    timeline.queue_wait_ms = queue_wait_ms;
+    timeline.attestation_ms = attestation_ms;
+
+    let parsed = parse_result::<FilteredRetrievalResult>(&task_output);
+    let success = task_output.exit_code == 0
+        && matches!(&parsed, Ok((result, _)) if result.status == ResultStatus::Success);
+    state.experiments.observe(&profile_name, task_output.execution_time_ms, success);
+    let (_, mut json_data) = parsed?;
+    if let Some(data) = json_data.as_object_mut() {
+        data.insert("retrieval_profile".to_string(), serde_json::Value::String(profile_name));
+        data.insert("search_quality".to_string(), serde_json::Value::String(search_quality.to_string()));
+    }
+
+    let raw_output = payload.raw.unwrap_or(false).then(|| task_output.raw());
+    Ok(TaskResponse {
         status: "success".to_string(),
         data: json_data,
-        stderr: task_output.stderr,
+        stderr: task_output.stderr_text().into_owned(),
         exit_code: task_output.exit_code,
         execution_time_ms: task_output.execution_time_ms,
-    }))
+        receipt_blob_id: None,
+        resource_usage: task_output.resource_usage,
+        timeline: Some(timeline),
+        attestation_ref: None,
+        raw_output,
+        request_hash: None,
+    })
 }
 
 #[cfg(test)]
@@ -417,6 +1136,12 @@ mod test {
             stderr: "".to_string(),
             exit_code: 0,
             execution_time_ms: 1500,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
         };
         let timestamp = 1744038900000;
         let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::Generic);
@@ -425,4 +1150,190 @@ mod test {
         // Just ensure serialization works without checking exact bytes since structure changed
         assert!(!signing_payload.is_empty());
     }
+
+    #[test]
+    fn test_parse_encryption_public_key() {
+        let key = "13BE4FEAEAF204C7FD3358FC9C00721881D174278128227EC674F37F7FE97B6D";
+        assert_eq!(parse_encryption_public_key(key).unwrap(), key.to_ascii_lowercase());
+        assert_eq!(parse_encryption_public_key(&format!("0x{}", key)).unwrap(), key.to_ascii_lowercase());
+        assert!(parse_encryption_public_key(&key[2..]).is_err());
+        assert!(parse_encryption_public_key(&key.replace('B', "g")).is_err());
+    }
+
+    #[test]
+    fn test_message_filters() {
+        assert!(MessageFilters::default().qdrant_filter().unwrap().is_none());
+        let filters = MessageFilters {
+            sender: Some(serde_json::json!(7)),
+            chat_id: Some(serde_json::json!(-100)),
+            date_from: Some(1_700_000_000),
+            date_to: None,
+        };
+        assert_eq!(
+            filters.qdrant_filter().unwrap().unwrap(),
+            serde_json::json!({ "must": [
+                { "key": "from_id", "match": { "value": 7 } },
+                { "key": "chat_id", "match": { "value": -100 } },
+                { "key": "message_date", "range": { "gte": 1_700_000_000 } },
+            ]})
+        );
+        let reversed = MessageFilters {
+            date_from: Some(2),
+            date_to: Some(1),
+            ..Default::default()
+        };
+        assert!(reversed.qdrant_filter().is_err());
+    }
+
+    #[test]
+    fn test_retrieval_field_errors() {
+        let request: MessageBlobRetrievalRequest = serde_json::from_value(serde_json::json!({
+            "blobFilePairs": [
+                {
+                    "walrusBlobId": "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk",
+                    "onChainFileObjId": "0x1",
+                    "policyObjectId": "0x2",
+                },
+                { "walrusBlobId": "blob", "onChainFileObjId": "0x1", "policyObjectId": "2" },
+            ],
+            "threshold": "2",
+        }))
+        .unwrap();
+        let fields: Vec<String> = request.field_errors().into_iter().map(|error| error.field).collect();
+        assert_eq!(fields, ["blobFilePairs[1].walrusBlobId", "blobFilePairs[1].policyObjectId"]);
+    }
+
+    #[test]
+    fn test_task_args_limit() {
+        let request = |args: Vec<String>| -> TaskRequest {
+            serde_json::from_value(serde_json::json!({ "args": args, "input": { "rows": [1, 2] } })).unwrap()
+        };
+        assert!(request(vec!["a".repeat(MAX_TASK_ARGS_BYTES)]).field_errors().is_empty());
+        let errors = request(vec!["a".repeat(MAX_TASK_ARGS_BYTES), "b".to_string()]).field_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "args");
+        assert!(errors[0].message.contains("input"));
+    }
+
+    #[tokio::test]
+    async fn test_respond_task_signs_json() {
+        use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+        use fastcrypto::encoding::{Encoding, Hex};
+        use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+
+        let state = crate::test_app_state();
+        let response = TaskResponse {
+            status: "success".to_string(),
+            data: serde_json::json!({"similarity": 0.83}),
+            stderr: "".to_string(),
+            exit_code: 0,
+            execution_time_ms: 10,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        };
+        let ctx = RequestContext::new(None);
+        let http_response = respond_task(&ctx, &state, &HeaderMap::new(), IntentScope::BlobRetrieval, Ok(response));
+        let body = axum::body::to_bytes(http_response.into_body(), usize::MAX).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["signature"], envelope["data"]["signature"]);
+
+        let signed: ProcessedDataResponse<IntentMessage<TaskResponse>> =
+            serde_json::from_value(envelope["data"].clone()).unwrap();
+        assert_eq!(signed.response.intent, IntentScope::BlobRetrieval);
+        assert_eq!(signed.response.data.data["similarity"], 0.83);
+        let boot = state.boot_attestation().unwrap();
+        assert_eq!(signed.response.data.attestation_ref.as_ref(), Some(&boot.reference));
+        assert_eq!(state.key_usage.signed(IntentScope::BlobRetrieval), 1);
+        assert_eq!(boot.reference.document_hash.len(), 2 * crate::common::ATTESTATION_REF_HASH_BYTES);
+        let signature = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+        let key = state.keys.current();
+        assert_eq!(signed.key_id, key.key_id);
+        let public_key: &Ed25519PublicKey = key.keypair.public();
+        public_key
+            .verify(&bcs::to_bytes(&signed.response).unwrap(), &signature)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_respond_task_signs_bcs() {
+        use crate::common::BcsSignedEnvelope;
+        use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+        use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+
+        let state = crate::test_app_state();
+        let response = TaskResponse {
+            status: "success".to_string(),
+            data: serde_json::json!({"similarity": 0.83, "ids": [1, 2]}),
+            stderr: "".to_string(),
+            exit_code: 0,
+            execution_time_ms: 10,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::ACCEPT, "application/bcs".parse().unwrap());
+        let ctx = RequestContext::new(None);
+        let http_response = respond_task(&ctx, &state, &headers, IntentScope::BlobRetrieval, Ok(response));
+        assert_eq!(http_response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(http_response.into_body(), usize::MAX).await.unwrap();
+        let envelope: BcsSignedEnvelope = bcs::from_bytes(&body).unwrap();
+
+        // The floats are signed inside the canonical JSON string of `data`
+        let message: IntentMessage<TaskResponse> = bcs::from_bytes(&envelope.intent_message).unwrap();
+        assert_eq!(message.intent, IntentScope::BlobRetrieval);
+        assert_eq!(message.data.data["similarity"], 0.83);
+        let key = state.keys.current();
+        let public_key: &Ed25519PublicKey = key.keypair.public();
+        let signature = Ed25519Signature::from_bytes(&envelope.signature).unwrap();
+        public_key.verify(&envelope.intent_message, &signature).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_respond_task_with_fresh_attestation() {
+        use fastcrypto::encoding::{Encoding, Hex};
+        use fastcrypto::hash::{HashFunction, Sha3_256};
+
+        let mut bcs_headers = HeaderMap::new();
+        bcs_headers.insert(axum::http::header::ACCEPT, "application/bcs".parse().unwrap());
+        let fresh = Some(AttestationMode::Fresh);
+        assert_eq!(fresh_attestation_nonce(None, Some("ab"), &HeaderMap::new()).unwrap(), None);
+        assert!(fresh_attestation_nonce(fresh, None, &HeaderMap::new()).is_err());
+        assert!(fresh_attestation_nonce(fresh, Some("ab"), &bcs_headers).is_err());
+        let nonce = fresh_attestation_nonce(fresh, Some("0xab01"), &HeaderMap::new()).unwrap().unwrap();
+
+        let state = crate::test_app_state();
+        let response = TaskResponse {
+            status: "success".to_string(),
+            data: serde_json::json!({"similarity": 0.83}),
+            stderr: "".to_string(),
+            exit_code: 0,
+            execution_time_ms: 10,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        };
+        let ctx = RequestContext::new(None);
+        let http_response = respond_task_attested(&ctx, &state, IntentScope::ProcessData, Ok(response), nonce).await;
+        let body = axum::body::to_bytes(http_response.into_body(), usize::MAX).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let signed: ProcessedDataResponse<IntentMessage<TaskResponse>> =
+            serde_json::from_value(envelope["data"].clone()).unwrap();
+
+        // The attestation is over the nonce and commits to the signed bytes
+        let attestation = signed.attestation.unwrap();
+        assert_eq!(attestation.nonce.as_deref(), Some("ab01"));
+        let digest = Sha3_256::digest(bcs::to_bytes(&signed.response).unwrap()).digest;
+        assert_eq!(attestation.user_data, Some(Hex::encode(digest)));
+    }
 }
@@ -0,0 +1,136 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory audit log of changes the server makes to external state or its own
+//! configuration at runtime, such as creating or tuning Qdrant collections. Served on
+//! `/admin/audit`. With `STORAGE_PATH` set, the kept events are also persisted.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::current_timestamp_ms;
+use crate::storage::Storage;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Events kept by default.
+pub const DEFAULT_AUDIT_LOG_SIZE: usize = 1000;
+
+/// One audited change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub timestamp_ms: u64,
+    /// What happened, e.g. `collection_created`
+    pub action: String,
+    /// What it happened to, e.g. the collection name
+    pub subject: String,
+    pub details: serde_json::Value,
+}
+
+/// Fixed-size log of the latest audit events.
+pub struct AuditLog {
+    capacity: usize,
+    events: Mutex<VecDeque<AuditEvent>>,
+    storage: Option<Arc<Storage>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_AUDIT_LOG_SIZE)
+    }
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            storage: None,
+        }
+    }
+
+    /// Load the latest events persisted in `storage` and persist new ones there.
+    pub fn with_storage(mut self, storage: Arc<Storage>) -> Result<Self, EnclaveError> {
+        self.events.get_mut().unwrap().extend(storage.audit_events(self.capacity)?);
+        self.storage = Some(storage);
+        Ok(self)
+    }
+
+    /// Record an event, also logging it so it outlives the buffer.
+    pub fn record(&self, action: &str, subject: &str, details: serde_json::Value) {
+        info!("Audit: {} {} {}", action, subject, details);
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        let event = AuditEvent {
+            timestamp_ms: current_timestamp_ms(),
+            action: action.to_string(),
+            subject: subject.to_string(),
+            details,
+        };
+        if let Some(storage) = &self.storage {
+            storage.append_audit_event(&event, self.capacity);
+        }
+        events.push_back(event);
+    }
+
+    /// Up to `limit` events, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<AuditEvent> {
+        let events = self.events.lock().unwrap();
+        events.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// Query parameters of `/admin/audit`.
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+/// Response of `/admin/audit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditResponse {
+    /// Newest first
+    pub events: Vec<AuditEvent>,
+}
+
+/// Latest audit events, newest first. Requires the admin token.
+pub async fn audit_events(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> ApiResponse<AuditResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.ok(AuditResponse {
+        events: state.audit_log.recent(query.limit.unwrap_or(usize::MAX)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log_keeps_latest() {
+        let log = AuditLog::new(2);
+        log.record("collection_created", "a", serde_json::json!({}));
+        log.record("collection_created", "b", serde_json::json!({}));
+        log.record("collection_tuned", "b", serde_json::json!({ "hnsw_ef": 128 }));
+        let events = log.recent(10);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, "collection_tuned");
+        assert_eq!(events[0].details["hnsw_ef"], 128);
+        assert_eq!(events[1].subject, "b");
+    }
+}
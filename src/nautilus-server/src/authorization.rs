@@ -0,0 +1,183 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Authorization of task operations. Every operation asks [AppState::authorization] before it
+//! runs, with an [AuthorizationRequest] naming the operation, the Seal policy objects of the
+//! data it touches and the Qdrant collection. Deployments with their own rules, such as an
+//! on-chain registry or a Ruby Nodes lookup, implement [AuthorizationHook] and set it in
+//! `main.rs` instead of patching every handler. Two hooks ship:
+//!
+//! - [AllowAll], the default, refusing nothing;
+//! - [OnChainAllowlist], selected by `AUTHORIZATION_ALLOWLIST_OBJECT_ID`, refusing with 403
+//!   policies missing from the `list` or `allowlist` of a Sui object, like Seal's allowlist
+//!   pattern. Unlike the pre-checks it fails closed: when the object cannot be read, the
+//!   operation is refused.
+
+use crate::seal_policy::allowlist;
+use crate::sui::{parse_address, SuiClient, SuiObject};
+use crate::task_env::Operation;
+use crate::AppState;
+use crate::EnclaveError;
+use futures_util::future::{BoxFuture, FutureExt};
+use tracing::warn;
+
+/// Operation about to run, as handed to an [AuthorizationHook].
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest<'a> {
+    pub operation: Operation,
+    /// ID of the HTTP request the operation runs for, also when it runs as a job
+    pub request_id: Option<String>,
+    /// Seal policy objects gating the data the operation reads or ingests, none for
+    /// `process_data` and filtered retrieval
+    pub policies: Vec<&'a str>,
+    /// Qdrant collection the operation reads or writes
+    pub collection: &'a str,
+}
+
+/// Decides whether an operation may run. Refusals are returned as the error the client
+/// gets, usually [EnclaveError::Forbidden].
+pub trait AuthorizationHook: Send + Sync {
+    /// Hook name, for logs.
+    fn name(&self) -> &'static str;
+
+    fn authorize<'a>(
+        &'a self,
+        state: &'a AppState,
+        request: &'a AuthorizationRequest<'a>,
+    ) -> BoxFuture<'a, Result<(), EnclaveError>>;
+}
+
+/// Authorizes every operation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+impl AuthorizationHook for AllowAll {
+    fn name(&self) -> &'static str {
+        "allow-all"
+    }
+
+    fn authorize<'a>(&'a self, _: &'a AppState, _: &'a AuthorizationRequest<'a>) -> BoxFuture<'a, Result<(), EnclaveError>> {
+        futures_util::future::ready(Ok(())).boxed()
+    }
+}
+
+/// Authorizes operations whose Seal policies are all listed by a Sui object. Operations
+/// without policies are authorized.
+#[derive(Debug, Clone)]
+pub struct OnChainAllowlist {
+    pub object_id: String,
+}
+
+impl OnChainAllowlist {
+    pub fn new(object_id: impl Into<String>) -> Self {
+        Self { object_id: object_id.into() }
+    }
+
+    /// Whether every one of `policies` is listed by `object`, the allowlist as read.
+    pub fn check(&self, object: Option<&SuiObject>, policies: &[&str]) -> Result<(), EnclaveError> {
+        let Some(object) = object else {
+            return Err(EnclaveError::ConfigError(format!("Authorization allowlist {} does not exist", self.object_id)));
+        };
+        let Some(allowed) = allowlist(object) else {
+            return Err(EnclaveError::ConfigError(format!(
+                "Authorization allowlist {} has no list or allowlist field",
+                self.object_id
+            )));
+        };
+        for policy in policies {
+            if !allowed.contains(&parse_address(policy)?) {
+                return Err(EnclaveError::Forbidden(format!(
+                    "Seal policy {} is not on the authorization allowlist",
+                    policy
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AuthorizationHook for OnChainAllowlist {
+    fn name(&self) -> &'static str {
+        "onchain-allowlist"
+    }
+
+    fn authorize<'a>(
+        &'a self,
+        state: &'a AppState,
+        request: &'a AuthorizationRequest<'a>,
+    ) -> BoxFuture<'a, Result<(), EnclaveError>> {
+        async move {
+            if request.policies.is_empty() {
+                return Ok(());
+            }
+            // Read through the object cache, so a burst of requests costs one read
+            let object = SuiClient::from_state(state)?.get_object(&self.object_id).await?;
+            self.check(object.as_ref(), &request.policies)
+        }
+        .boxed()
+    }
+}
+
+/// Hook selected by the configuration.
+pub fn from_config(config: &crate::config::Config) -> std::sync::Arc<dyn AuthorizationHook> {
+    match &config.authorization_allowlist_object_id {
+        Some(object_id) => std::sync::Arc::new(OnChainAllowlist::new(object_id.clone())),
+        None => std::sync::Arc::new(AllowAll),
+    }
+}
+
+/// Ask the configured hook whether `operation` may run on `policies` and `collection`.
+pub async fn authorize<'a>(
+    state: &AppState,
+    operation: Operation,
+    policies: impl IntoIterator<Item = &'a str>,
+    collection: &str,
+) -> Result<(), EnclaveError> {
+    let request = AuthorizationRequest {
+        operation,
+        request_id: crate::crash_reports::current_request_id(),
+        policies: policies.into_iter().collect(),
+        collection,
+    };
+    let result = state.authorization.authorize(state, &request).await;
+    if let Err(e) = &result {
+        warn!("{} refused {:?}: {:?}", state.authorization.name(), operation, e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(fields: serde_json::Value) -> SuiObject {
+        let result = json!({ "data": { "objectId": "0xa1", "version": "1", "digest": "d", "content": { "fields": fields } } });
+        SuiObject::from_result(&result).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_onchain_allowlist() {
+        let hook = OnChainAllowlist::new("0xa1");
+        let listed = object(json!({ "list": [format!("0x{:0>64}", "b0b"), "0xc0c"] }));
+        assert!(hook.check(Some(&listed), &["0xb0b", "0xc0c"]).is_ok());
+        assert!(hook.check(Some(&listed), &[]).is_ok());
+        assert!(matches!(hook.check(Some(&listed), &["0xb0b", "0xd0d"]), Err(EnclaveError::Forbidden(_))));
+        assert!(matches!(hook.check(Some(&listed), &["not an address"]), Err(EnclaveError::BadRequest(_))));
+
+        assert!(matches!(hook.check(None, &["0xb0b"]), Err(EnclaveError::ConfigError(_))));
+        let unlisted = object(json!({ "owner": "0xb0b" }));
+        assert!(matches!(hook.check(Some(&unlisted), &["0xb0b"]), Err(EnclaveError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let mut state = crate::test_app_state();
+        assert!(authorize(&state, Operation::ProcessData, [], "messages").await.is_ok());
+        assert!(authorize(&state, Operation::EmbeddingIngest, ["0xb0b"], "messages").await.is_ok());
+
+        // The allowlist is only read for operations on policies
+        state.authorization = std::sync::Arc::new(OnChainAllowlist::new("0xa1"));
+        assert!(authorize(&state, Operation::RetrieveMessagesFiltered, [], "messages").await.is_ok());
+    }
+}
@@ -0,0 +1,448 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Circuit breakers for the upstream services. After `CIRCUIT_BREAKER_FAILURES` calls in a
+//! row fail with an upstream error, the breaker of the service opens and its calls fail at
+//! once for `CIRCUIT_BREAKER_OPEN_SECS`, instead of each waiting for a timeout. Once that has
+//! passed, a single trial call goes through: its success closes the breaker, its failure opens
+//! it again. `GET /admin/breakers` lists every breaker and `POST /admin/breakers/:service/reset`
+//! closes one by hand.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Default failures in a row that open a breaker.
+pub const DEFAULT_CIRCUIT_BREAKER_FAILURES: u32 = 5;
+/// Default time a breaker stays open before a trial call.
+pub const DEFAULT_CIRCUIT_BREAKER_OPEN_SECS: u64 = 30;
+
+/// Services with a breaker, listed before their first call.
+pub const BREAKER_SERVICES: [&str; 3] = ["qdrant", "sui", "walrus"];
+
+/// Window of the recent failure counts.
+const RECENT_FAILURES_WINDOW_MS: u64 = 5 * 60 * 1000;
+/// Recent failures kept per service.
+const MAX_RECENT_FAILURES: usize = 1000;
+
+/// When breakers open and for how long.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakerPolicy {
+    /// Failures in a row that open a breaker, 0 disables the breakers
+    pub failure_threshold: u32,
+    pub open_secs: u64,
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_CIRCUIT_BREAKER_FAILURES,
+            open_secs: DEFAULT_CIRCUIT_BREAKER_OPEN_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    #[default]
+    Closed,
+    /// Calls fail at once until `retry_at_ms`
+    Open,
+    /// A trial call is in flight
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn gauge(self) -> u8 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::Open => 1,
+            BreakerState::HalfOpen => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    /// Failed calls with when they failed, oldest first
+    recent_failures: VecDeque<(u64, String)>,
+    opened_at_ms: Option<u64>,
+    retry_at_ms: Option<u64>,
+    trips: u64,
+    rejected: u64,
+    last_error: Option<String>,
+}
+
+impl Breaker {
+    fn prune(&mut self, now: u64) {
+        while let Some((at, _)) = self.recent_failures.front() {
+            if now.saturating_sub(*at) < RECENT_FAILURES_WINDOW_MS && self.recent_failures.len() <= MAX_RECENT_FAILURES {
+                break;
+            }
+            self.recent_failures.pop_front();
+        }
+    }
+}
+
+/// State of one breaker, as listed on `GET /admin/breakers`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerStatus {
+    pub service: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Failures in the last five minutes
+    pub recent_failures: u64,
+    /// Failures in the last five minutes per call
+    pub recent_failures_by_call: BTreeMap<String, u64>,
+    pub opened_at_ms: Option<u64>,
+    /// When an open breaker lets a trial call through
+    pub next_retry_at_ms: Option<u64>,
+    /// Times the breaker opened
+    pub trips: u64,
+    /// Calls failed at once while open
+    pub rejected: u64,
+    pub last_error: Option<String>,
+}
+
+/// Breakers of the upstream services, shared by every client.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    policy: BreakerPolicy,
+    breakers: Arc<Mutex<BTreeMap<String, Breaker>>>,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(BreakerPolicy::default())
+    }
+}
+
+impl CircuitBreakers {
+    pub fn new(policy: BreakerPolicy) -> Self {
+        let breakers = BREAKER_SERVICES
+            .iter()
+            .map(|service| (service.to_string(), Breaker::default()))
+            .collect();
+        Self {
+            policy,
+            breakers: Arc::new(Mutex::new(breakers)),
+        }
+    }
+
+    pub fn policy(&self) -> &BreakerPolicy {
+        &self.policy
+    }
+
+    /// Allow a call to `service`, or refuse it while the breaker is open or a trial call
+    /// is in flight.
+    pub fn admit(&self, service: &str) -> Result<(), EnclaveError> {
+        if self.policy.failure_threshold == 0 {
+            return Ok(());
+        }
+        let now = current_timestamp_ms();
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(service.to_string()).or_default();
+        match breaker.state {
+            BreakerState::Closed => Ok(()),
+            BreakerState::Open if breaker.retry_at_ms.is_some_and(|retry_at| now >= retry_at) => {
+                breaker.state = BreakerState::HalfOpen;
+                Ok(())
+            }
+            _ => {
+                breaker.rejected += 1;
+                let retry_in = breaker.retry_at_ms.map_or(0, |retry_at| retry_at.saturating_sub(now)).div_ceil(1000);
+                Err(EnclaveError::upstream(
+                    service,
+                    format!("Circuit breaker for {} is open, retrying in {}s", service, retry_in),
+                ))
+            }
+        }
+    }
+
+    /// Record the outcome of a call admitted by [Self::admit]. Only upstream errors count
+    /// as failures; a bad request or a missing object means the service answered.
+    pub fn record<T>(&self, service: &str, call: &str, result: &Result<T, EnclaveError>) {
+        if self.policy.failure_threshold == 0 {
+            return;
+        }
+        let now = current_timestamp_ms();
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers.entry(service.to_string()).or_default();
+        match result {
+            Err(EnclaveError::UpstreamUnavailable { message, .. }) => {
+                breaker.consecutive_failures += 1;
+                breaker.recent_failures.push_back((now, call.to_string()));
+                breaker.prune(now);
+                breaker.last_error = Some(message.clone());
+                if breaker.state == BreakerState::HalfOpen
+                    || (breaker.state == BreakerState::Closed
+                        && breaker.consecutive_failures >= self.policy.failure_threshold)
+                {
+                    breaker.state = BreakerState::Open;
+                    breaker.opened_at_ms = Some(now);
+                    breaker.retry_at_ms = Some(now.saturating_add(self.policy.open_secs.saturating_mul(1000)));
+                    breaker.trips += 1;
+                    tracing::warn!(
+                        "Circuit breaker for {} opened after {} failures: {}",
+                        service,
+                        breaker.consecutive_failures,
+                        message
+                    );
+                }
+            }
+            _ => {
+                if breaker.state != BreakerState::Closed {
+                    tracing::info!("Circuit breaker for {} closed", service);
+                }
+                breaker.state = BreakerState::Closed;
+                breaker.consecutive_failures = 0;
+                breaker.opened_at_ms = None;
+                breaker.retry_at_ms = None;
+            }
+        }
+    }
+
+    /// Run `call` on `service` through its breaker.
+    pub async fn call<T>(
+        &self,
+        service: &str,
+        call: &str,
+        request: impl Future<Output = Result<T, EnclaveError>>,
+    ) -> Result<T, EnclaveError> {
+        self.admit(service)?;
+        let result = request.await;
+        self.record(service, call, &result);
+        result
+    }
+
+    /// Close the breaker of `service`, returning false for an unknown service.
+    pub fn reset(&self, service: &str) -> bool {
+        let mut breakers = self.breakers.lock().unwrap();
+        let Some(breaker) = breakers.get_mut(service) else {
+            return false;
+        };
+        breaker.state = BreakerState::Closed;
+        breaker.consecutive_failures = 0;
+        breaker.opened_at_ms = None;
+        breaker.retry_at_ms = None;
+        true
+    }
+
+    /// State of every breaker, by service.
+    pub fn statuses(&self) -> Vec<BreakerStatus> {
+        let now = current_timestamp_ms();
+        let mut breakers = self.breakers.lock().unwrap();
+        breakers
+            .iter_mut()
+            .map(|(service, breaker)| {
+                breaker.prune(now);
+                let mut by_call = BTreeMap::new();
+                for (_, call) in &breaker.recent_failures {
+                    *by_call.entry(call.clone()).or_insert(0) += 1;
+                }
+                BreakerStatus {
+                    service: service.clone(),
+                    state: breaker.state,
+                    consecutive_failures: breaker.consecutive_failures,
+                    recent_failures: breaker.recent_failures.len() as u64,
+                    recent_failures_by_call: by_call,
+                    opened_at_ms: breaker.opened_at_ms,
+                    next_retry_at_ms: breaker.retry_at_ms,
+                    trips: breaker.trips,
+                    rejected: breaker.rejected,
+                    last_error: breaker.last_error.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Breaker states and counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let breakers = self.breakers.lock().unwrap();
+        let mut out = String::new();
+        let name = "nautilus_circuit_breaker_state";
+        let _ = writeln!(out, "# HELP {} Circuit breaker state: 0 closed, 1 open, 2 half open.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (service, breaker) in breakers.iter() {
+            let _ = writeln!(out, "{}{{service=\"{}\"}} {}", name, service, breaker.state.gauge());
+        }
+        let series = [
+            ("nautilus_circuit_breaker_trips_total", "Times a circuit breaker opened.", false),
+            ("nautilus_circuit_breaker_rejected_total", "Calls refused by an open circuit breaker.", true),
+        ];
+        for (name, help, rejected) in series {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (service, breaker) in breakers.iter() {
+                let value = if rejected { breaker.rejected } else { breaker.trips };
+                let _ = writeln!(out, "{}{{service=\"{}\"}} {}", name, service, value);
+            }
+        }
+        out
+    }
+}
+
+/// Response of `GET /admin/breakers` and `POST /admin/breakers/:service/reset`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreakersResponse {
+    pub breakers: Vec<BreakerStatus>,
+    pub policy: BreakerPolicy,
+}
+
+fn breakers_response(state: &AppState) -> BreakersResponse {
+    BreakersResponse {
+        breakers: state.breakers.statuses(),
+        policy: state.breakers.policy().clone(),
+    }
+}
+
+/// Circuit breaker of every upstream service. Requires the admin token.
+pub async fn list_breakers(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<BreakersResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.ok(breakers_response(&state))
+}
+
+/// Close the circuit breaker of a service now. Requires the admin token.
+pub async fn reset_breaker(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(service): Path<String>,
+) -> ApiResponse<BreakersResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let previous = state.breakers.statuses().into_iter().find(|status| status.service == service);
+    if !state.breakers.reset(&service) {
+        return ctx.error(EnclaveError::NotFound(format!("No circuit breaker for {}", service)));
+    }
+    state.audit_log.record(
+        "circuit_breaker_reset",
+        &service,
+        serde_json::json!({
+            "previousState": previous.as_ref().map(|status| status.state),
+            "consecutiveFailures": previous.as_ref().map(|status| status.consecutive_failures),
+        }),
+    );
+    ctx.ok(breakers_response(&state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    fn failure() -> Result<(), EnclaveError> {
+        Err(EnclaveError::upstream("qdrant", "connection refused"))
+    }
+
+    #[test]
+    fn test_breaker_opens_and_half_opens() {
+        let breakers = CircuitBreakers::new(BreakerPolicy {
+            failure_threshold: 2,
+            open_secs: 0,
+        });
+        breakers.record("qdrant", "search", &failure());
+        // Any other outcome shows the service answered
+        breakers.record("qdrant", "search", &Err::<(), _>(EnclaveError::BadRequest("bad".to_string())));
+        let status = &breakers.statuses()[0];
+        assert_eq!((status.state, status.consecutive_failures), (BreakerState::Closed, 0));
+
+        breakers.record("qdrant", "search", &failure());
+        breakers.record("qdrant", "upsert", &failure());
+        let status = &breakers.statuses()[0];
+        assert_eq!(status.state, BreakerState::Open);
+        assert_eq!(status.recent_failures, 3);
+        assert_eq!(status.recent_failures_by_call["upsert"], 1);
+        assert!(status.next_retry_at_ms.is_some());
+
+        // Past the open time a single trial call goes through, its failure reopens
+        breakers.admit("qdrant").unwrap();
+        assert!(breakers.admit("qdrant").is_err());
+        breakers.record("qdrant", "search", &failure());
+        assert_eq!(breakers.statuses()[0].trips, 2);
+        breakers.admit("qdrant").unwrap();
+        breakers.record("qdrant", "search", &Ok(()));
+        let status = &breakers.statuses()[0];
+        assert_eq!((status.state, status.rejected), (BreakerState::Closed, 1));
+        assert!(breakers.render().contains("nautilus_circuit_breaker_trips_total{service=\"qdrant\"} 2"));
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_fails_fast() {
+        let breakers = CircuitBreakers::new(BreakerPolicy {
+            failure_threshold: 1,
+            open_secs: 60,
+        });
+        assert!(breakers.call("walrus", "get_blob", async { failure() }).await.is_err());
+        let mut called = false;
+        let result = breakers
+            .call("walrus", "get_blob", async {
+                called = true;
+                Ok(())
+            })
+            .await;
+        assert!(!called);
+        let (status, message) = result.unwrap_err().status_and_message();
+        assert_eq!(status, axum::http::StatusCode::BAD_GATEWAY);
+        assert!(message.contains("Circuit breaker for walrus is open"));
+
+        // A disabled breaker never opens
+        let disabled = CircuitBreakers::new(BreakerPolicy {
+            failure_threshold: 0,
+            open_secs: 60,
+        });
+        disabled.record("walrus", "get_blob", &failure());
+        disabled.admit("walrus").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reset_breaker_requires_admin() {
+        let mut state = test_app_state();
+        state.admin_token = Some("secret".to_string());
+        state.breakers = CircuitBreakers::new(BreakerPolicy {
+            failure_threshold: 1,
+            open_secs: 60,
+        });
+        let state = Arc::new(state);
+        state.breakers.record("sui", "sui_getObject", &failure());
+
+        let response = reset_breaker(RequestContext::new(None), State(state.clone()), HeaderMap::new(), Path("sui".to_string())).await;
+        assert!(response.error.is_some());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let unknown =
+            reset_breaker(RequestContext::new(None), State(state.clone()), headers.clone(), Path("nope".to_string())).await;
+        assert!(unknown.error.is_some());
+        let listed = list_breakers(RequestContext::new(None), State(state.clone()), headers.clone()).await.data.unwrap();
+        assert_eq!(listed.breakers.iter().map(|b| b.service.as_str()).collect::<Vec<_>>(), BREAKER_SERVICES);
+        assert_eq!(listed.breakers[1].state, BreakerState::Open);
+
+        let reset = reset_breaker(RequestContext::new(None), State(state.clone()), headers, Path("sui".to_string()))
+            .await
+            .data
+            .unwrap();
+        assert_eq!(reset.breakers[1].state, BreakerState::Closed);
+        // The failure history stays for the dashboard
+        assert_eq!(reset.breakers[1].recent_failures, 1);
+        assert_eq!(state.audit_log.recent(10)[0].action, "circuit_breaker_reset");
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Build and runtime metadata identifying exactly what is running: compile-time values
+//! embedded by `build.rs` plus the task bundle hashes and Node.js version resolved at boot.
+//! Their canonical hash is the attestation `user_data`.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::canonical::canonical_hash_of;
+use crate::task_bundles::TaskBundles;
+use crate::task_runner::node_version;
+use crate::AppState;
+use axum::extract::State;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Directories left out of the task bundle hash. Dependencies are pinned by the lockfile,
+/// which is hashed and checked against the dependency allowlist.
+const BUNDLE_EXCLUDED_DIRS: [&str; 1] = ["node_modules"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_commit: String,
+    /// Unix seconds, `SOURCE_DATE_EPOCH` or the commit time
+    pub build_timestamp: u64,
+    /// Cargo features the server was compiled with
+    pub features: Vec<String>,
+    /// Hex SHA3-256 over the task sources, `None` if the task directory could not be read
+    pub task_bundle_hash: Option<String>,
+    /// The same for each bundle configured in `TASK_BUNDLES`, by bundle name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub bundle_hashes: BTreeMap<String, Option<String>>,
+    /// Output of `node --version`, `None` if Node.js is unavailable
+    pub node_version: Option<String>,
+}
+
+impl BuildInfo {
+    /// Metadata embedded at compile time, without the boot-time fields.
+    pub fn compiled() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("NAUTILUS_GIT_COMMIT").to_string(),
+            build_timestamp: env!("NAUTILUS_BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            features: env!("NAUTILUS_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect(),
+            task_bundle_hash: None,
+            bundle_hashes: BTreeMap::new(),
+            node_version: None,
+        }
+    }
+
+    /// Compile-time metadata completed with the task bundle hashes and Node.js version.
+    pub async fn collect(bundles: &TaskBundles) -> Self {
+        let hash = |path: &Path| {
+            hash_task_bundle(path)
+                .map_err(|e| tracing::warn!("Failed to hash task bundle {}: {}", path.display(), e))
+                .ok()
+        };
+        Self {
+            task_bundle_hash: hash(&bundles.default_bundle().path),
+            bundle_hashes: bundles
+                .configured()
+                .iter()
+                .map(|bundle| (bundle.name.clone(), hash(&bundle.path)))
+                .collect(),
+            node_version: node_version()
+                .await
+                .map_err(|e| tracing::warn!("Failed to read Node.js version: {}", e))
+                .ok(),
+            ..Self::compiled()
+        }
+    }
+
+    /// Canonical hash of the metadata, committed to in the attestation `user_data`.
+    pub fn attestation_user_data(&self) -> [u8; 32] {
+        canonical_hash_of(self).expect("build info serializes to JSON")
+    }
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let excluded = path
+                .file_name()
+                .is_some_and(|name| BUNDLE_EXCLUDED_DIRS.iter().any(|d| name == *d));
+            if !excluded {
+                collect_files(root, &path, files)?;
+            }
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            files.push((relative, path));
+        }
+    }
+    Ok(())
+}
+
+/// SHA3-256 over every file of the task directory in path order, each hashed as its
+/// relative path, a zero byte, its length (u64 little endian) and its contents.
+pub fn hash_task_bundle(task_path: &Path) -> std::io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(task_path, task_path, &mut files)?;
+    files.sort();
+
+    let mut hash = Sha3_256::default();
+    for (relative, path) in files {
+        let content = std::fs::read(&path)?;
+        hash.update(relative.as_bytes());
+        hash.update([0u8]);
+        hash.update((content.len() as u64).to_le_bytes());
+        hash.update(&content);
+    }
+    Ok(Hex::encode(hash.finalize().digest))
+}
+
+/// Response of `/version`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionResponse {
+    pub build: BuildInfo,
+    /// Hex attestation `user_data`, the canonical hash of `build`
+    pub attestation_user_data: String,
+}
+
+/// Build metadata of the running server.
+pub async fn version(ctx: RequestContext, State(state): State<Arc<AppState>>) -> ApiResponse<VersionResponse> {
+    ctx.ok(VersionResponse {
+        attestation_user_data: Hex::encode(state.build_info.attestation_user_data()),
+        build: state.build_info.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled() {
+        let info = BuildInfo::compiled();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_commit.is_empty());
+        assert_eq!(info.task_bundle_hash, None);
+    }
+
+    #[test]
+    fn test_hash_task_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.js"), "console.log(1)").unwrap();
+        std::fs::create_dir_all(dir.path().join("utils")).unwrap();
+        std::fs::write(dir.path().join("utils/a.js"), "a").unwrap();
+        let hash = hash_task_bundle(dir.path()).unwrap();
+
+        // Installed dependencies do not change the hash
+        std::fs::create_dir_all(dir.path().join("node_modules/x")).unwrap();
+        std::fs::write(dir.path().join("node_modules/x/index.js"), "x").unwrap();
+        assert_eq!(hash_task_bundle(dir.path()).unwrap(), hash);
+
+        std::fs::write(dir.path().join("utils/a.js"), "b").unwrap();
+        assert_ne!(hash_task_bundle(dir.path()).unwrap(), hash);
+        assert!(hash_task_bundle(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_attestation_user_data_commits_to_fields() {
+        let info = BuildInfo::compiled();
+        let other = BuildInfo {
+            task_bundle_hash: Some("00".to_string()),
+            ..info.clone()
+        };
+        assert_eq!(info.attestation_user_data(), BuildInfo::compiled().attestation_user_data());
+        assert_ne!(info.attestation_user_data(), other.attestation_user_data());
+
+        // Configured bundles are measured on their own
+        let mut bundled = info.clone();
+        bundled.bundle_hashes.insert("search".to_string(), Some("00".to_string()));
+        assert_ne!(info.attestation_user_data(), bundled.attestation_user_data());
+        assert!(!serde_json::to_string(&info).unwrap().contains("bundle_hashes"));
+    }
+}
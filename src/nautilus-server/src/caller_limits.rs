@@ -0,0 +1,398 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request rate limits per caller. Address and signing limits only apply once a payload is
+//! parsed, so a single client can still keep every task slot busy. `CALLER_RATE_LIMITS` gives
+//! each caller a token bucket per route class, refilled at `requests_per_minute` and holding
+//! up to `burst` requests, and [limit_caller_rate] refuses requests with an empty bucket with
+//! 429 before they reach their handler. Callers are told apart by `CALLER_RATE_LIMIT_KEY`:
+//! the connection's IP, the address the proxy in front of the enclave appends to
+//! `X-Forwarded-For`, or the `X-Api-Key` header. Requests are counted per class on `/metrics`,
+//! without caller labels.
+
+use crate::api_response::{RequestContext, REQUEST_ID_HEADER};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::warn;
+
+/// Header naming the caller when limiting by API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Routes that run a Node.js task, embed or sign, limited as [RouteClass::Task].
+const TASK_ROUTES: &[&str] = &[
+    "/process_data",
+    "/process_data/stream",
+    "/embedding_ingest",
+    "/embedding_ingest/stream",
+    "/embedding_ingest_batch",
+    "/retrieve_messages_by_blob_ids",
+    "/retrieve_messages_filtered",
+    "/decrypt_messages",
+    "/get_attestation",
+];
+
+/// Probes polled by load balancers and scrapers from a single address, never limited.
+const EXEMPT_ROUTES: &[&str] = &["/", "/health_check", "/readyz", "/metrics"];
+
+/// Routes limited together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RouteClass {
+    /// Cheap reads such as `/jobs/:id`, `/config` and the admin endpoints
+    Read,
+    /// Expensive task endpoints, see [TASK_ROUTES]
+    Task,
+}
+
+impl RouteClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteClass::Read => "read",
+            RouteClass::Task => "task",
+        }
+    }
+
+    /// Class of a matched route, `None` for probes that are never limited.
+    pub fn of(route: &str) -> Option<Self> {
+        if EXEMPT_ROUTES.contains(&route) {
+            None
+        } else if TASK_ROUTES.contains(&route) {
+            Some(RouteClass::Task)
+        } else {
+            Some(RouteClass::Read)
+        }
+    }
+}
+
+impl fmt::Display for RouteClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RouteClass {
+    type Err = EnclaveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(RouteClass::Read),
+            "task" => Ok(RouteClass::Task),
+            other => Err(EnclaveError::BadRequest(format!(
+                "Unknown route class {}, expected read or task",
+                other
+            ))),
+        }
+    }
+}
+
+/// How callers are told apart, from `CALLER_RATE_LIMIT_KEY`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallerKey {
+    /// Peer address of the connection
+    #[default]
+    Ip,
+    /// Last address of `X-Forwarded-For`, the one appended by the proxy in front of the
+    /// enclave, falling back to the peer address
+    ForwardedFor,
+    /// `X-Api-Key` header, falling back to the peer address
+    ApiKey,
+}
+
+impl FromStr for CallerKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "ip" => Ok(CallerKey::Ip),
+            "forwarded_for" => Ok(CallerKey::ForwardedFor),
+            "api_key" => Ok(CallerKey::ApiKey),
+            other => Err(format!("unknown caller key {}, expected ip, forwarded_for or api_key", other)),
+        }
+    }
+}
+
+impl fmt::Display for CallerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallerKey::Ip => write!(f, "ip"),
+            CallerKey::ForwardedFor => write!(f, "forwarded_for"),
+            CallerKey::ApiKey => write!(f, "api_key"),
+        }
+    }
+}
+
+impl CallerKey {
+    /// Identity of the caller of a request. API keys are hashed so they are never held or
+    /// logged in the clear.
+    pub fn caller(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+        let peer = || peer.map_or_else(|| "unknown".to_string(), |peer| peer.ip().to_string());
+        match self {
+            CallerKey::Ip => peer(),
+            CallerKey::ForwardedFor => headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .next_back()
+                .map_or_else(peer, str::to_string),
+            CallerKey::ApiKey => match headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+                Some(key) if !key.is_empty() => {
+                    let digest = Sha3_256::digest(key.as_bytes()).digest;
+                    format!("key:{}", Hex::encode(&digest[..8]))
+                }
+                _ => peer(),
+            },
+        }
+    }
+}
+
+/// Token bucket size and refill rate of a route class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketLimit {
+    pub requests_per_minute: u32,
+    /// Requests a caller can make at once after being idle
+    pub burst: u32,
+}
+
+impl FromStr for BucketLimit {
+    type Err = String;
+
+    /// `requests_per_minute` or `requests_per_minute:burst`, the burst defaulting to the rate.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (rate, burst) = value.split_once(':').unwrap_or((value, value));
+        let requests_per_minute = rate.trim().parse::<u32>().map_err(|e| format!("invalid rate {}: {}", rate, e))?;
+        let burst = burst.trim().parse::<u32>().map_err(|e| format!("invalid burst {}: {}", burst, e))?;
+        if requests_per_minute == 0 || burst == 0 {
+            return Err("rate and burst must be positive".to_string());
+        }
+        Ok(Self {
+            requests_per_minute,
+            burst,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Tokens held at `now`, refilled since the last update.
+    fn refill(&mut self, limit: BucketLimit, now: Instant) {
+        let refilled = now.duration_since(self.updated).as_secs_f64() * limit.requests_per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + refilled).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClassUsage {
+    allowed: u64,
+    rejected: u64,
+    /// Buckets of callers below their burst, a full bucket is the same as none
+    buckets: HashMap<String, Bucket>,
+}
+
+/// Request counters and optional token buckets per route class and caller.
+#[derive(Debug, Clone, Default)]
+pub struct CallerLimits {
+    key: CallerKey,
+    limits: BTreeMap<RouteClass, BucketLimit>,
+    usage: Arc<Mutex<BTreeMap<RouteClass, ClassUsage>>>,
+}
+
+impl CallerLimits {
+    pub fn new(key: CallerKey, limits: BTreeMap<RouteClass, BucketLimit>) -> Self {
+        Self {
+            key,
+            limits,
+            usage: Default::default(),
+        }
+    }
+
+    pub fn key(&self) -> CallerKey {
+        self.key
+    }
+
+    /// Parse `CALLER_RATE_LIMITS`: comma separated `class=requests_per_minute[:burst]`, e.g.
+    /// `read=600,task=30:5`.
+    pub fn parse_limits(value: &str) -> Result<BTreeMap<RouteClass, BucketLimit>, EnclaveError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (class, limit) = entry.split_once('=').ok_or_else(|| {
+                    EnclaveError::BadRequest(format!("Expected class=limit, got {}", entry))
+                })?;
+                let limit = limit.parse::<BucketLimit>().map_err(|e| {
+                    EnclaveError::BadRequest(format!("Invalid limit for {}: {}", class, e))
+                })?;
+                Ok((class.trim().parse()?, limit))
+            })
+            .collect()
+    }
+
+    /// Take a token from the bucket of `caller` for `class`, or reject the request with
+    /// [EnclaveError::Overloaded] when it is empty.
+    pub fn acquire(&self, class: RouteClass, caller: &str) -> Result<(), EnclaveError> {
+        self.acquire_at(class, caller, Instant::now())
+    }
+
+    fn acquire_at(&self, class: RouteClass, caller: &str, now: Instant) -> Result<(), EnclaveError> {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(class).or_default();
+        if let Some(&limit) = self.limits.get(&class) {
+            // Forget callers whose bucket refilled so the map stays bounded
+            entry.buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+            let bucket = entry.buckets.entry(caller.to_string()).or_insert(Bucket {
+                tokens: limit.burst as f64,
+                updated: now,
+            });
+            if bucket.tokens < 1.0 {
+                warn!("Caller rate limit of {} per minute reached for {} by {}", limit.requests_per_minute, class, caller);
+                entry.rejected += 1;
+                let retry_after = (1.0 - bucket.tokens) * 60.0 / limit.requests_per_minute as f64;
+                return Err(EnclaveError::Overloaded {
+                    message: format!("Rate limit reached for {} requests", class),
+                    retry_after_secs: (retry_after.ceil() as u64).max(1),
+                });
+            }
+            bucket.tokens -= 1.0;
+        }
+        entry.allowed += 1;
+        Ok(())
+    }
+
+    /// Render the counters and limits in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let usage = self.usage.lock().unwrap();
+        let mut out = String::new();
+        let series = [
+            ("nautilus_caller_requests_total", "Requests allowed by the caller rate limit.", false),
+            ("nautilus_caller_requests_rejected_total", "Requests refused by the caller rate limit.", true),
+        ];
+        for (name, help, rejected) in series {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (class, u) in usage.iter() {
+                let value = if rejected { u.rejected } else { u.allowed };
+                let _ = writeln!(out, "{}{{class=\"{}\"}} {}", name, class, value);
+            }
+        }
+        let name = "nautilus_caller_rate_limit";
+        let _ = writeln!(out, "# HELP {} Requests allowed per caller and minute.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (class, limit) in &self.limits {
+            let _ = writeln!(out, "{}{{class=\"{}\"}} {}", name, class, limit.requests_per_minute);
+        }
+        let name = "nautilus_caller_rate_limited_callers";
+        let _ = writeln!(out, "# HELP {} Callers with a partly used bucket.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (class, u) in usage.iter().filter(|(class, _)| self.limits.contains_key(class)) {
+            let _ = writeln!(out, "{}{{class=\"{}\"}} {}", name, class, u.buckets.len());
+        }
+        out
+    }
+}
+
+/// Middleware applying [AppState::caller_limits] to the matched route. Requests over the
+/// limit get 429 with `Retry-After` without reaching their handler.
+pub async fn limit_caller_rate(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path().to_string(), |path| path.as_str().to_string());
+    let Some(class) = RouteClass::of(&route) else {
+        return next.run(request).await;
+    };
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0);
+    let caller = state.caller_limits.key().caller(request.headers(), peer);
+    if let Err(e) = state.caller_limits.acquire(class, &caller) {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        return RequestContext::new(request_id).error::<()>(e).into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = CallerLimits::parse_limits("read=600, task=30:5").unwrap();
+        assert_eq!(limits[&RouteClass::Read], BucketLimit { requests_per_minute: 600, burst: 600 });
+        assert_eq!(limits[&RouteClass::Task], BucketLimit { requests_per_minute: 30, burst: 5 });
+        assert!(CallerLimits::parse_limits("").unwrap().is_empty());
+        assert!(CallerLimits::parse_limits("task").is_err());
+        assert!(CallerLimits::parse_limits("write=1").is_err());
+        assert!(CallerLimits::parse_limits("task=0").is_err());
+        assert!(CallerLimits::parse_limits("task=10:x").is_err());
+
+        assert_eq!(RouteClass::of("/embedding_ingest"), Some(RouteClass::Task));
+        assert_eq!(RouteClass::of("/jobs/:id"), Some(RouteClass::Read));
+        assert_eq!(RouteClass::of("/health_check"), None);
+    }
+
+    #[test]
+    fn test_caller_key() {
+        let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.2.3.4, 5.6.7.8".parse().unwrap());
+        assert_eq!(CallerKey::Ip.caller(&headers, Some(peer)), "10.0.0.1");
+        assert_eq!(CallerKey::ForwardedFor.caller(&headers, Some(peer)), "5.6.7.8");
+        assert_eq!(CallerKey::ApiKey.caller(&headers, None), "unknown");
+        headers.insert(API_KEY_HEADER, "secret".parse().unwrap());
+        let caller = CallerKey::ApiKey.caller(&headers, Some(peer));
+        assert!(caller.starts_with("key:") && !caller.contains("secret"));
+        assert_eq!("Forwarded_For".parse::<CallerKey>(), Ok(CallerKey::ForwardedFor));
+        assert!("header".parse::<CallerKey>().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_per_caller() {
+        let limits = CallerLimits::new(CallerKey::Ip, CallerLimits::parse_limits("task=6:2").unwrap());
+        let start = Instant::now();
+        assert!(limits.acquire_at(RouteClass::Task, "a", start).is_ok());
+        assert!(limits.acquire_at(RouteClass::Task, "a", start).is_ok());
+        let err = limits.acquire_at(RouteClass::Task, "a", start).unwrap_err();
+        // One request refills every 10 seconds
+        assert_eq!(err.retry_after_secs(), Some(10));
+        // Other callers and unlimited classes are unaffected
+        assert!(limits.acquire_at(RouteClass::Task, "b", start).is_ok());
+        assert!(limits.acquire_at(RouteClass::Read, "a", start).is_ok());
+        assert!(limits.acquire_at(RouteClass::Task, "a", start + Duration::from_secs(10)).is_ok());
+        assert!(limits.acquire_at(RouteClass::Task, "a", start + Duration::from_secs(10)).is_err());
+
+        let text = limits.render();
+        assert!(text.contains("nautilus_caller_requests_total{class=\"task\"} 4"));
+        assert!(text.contains("nautilus_caller_requests_rejected_total{class=\"task\"} 2"));
+        assert!(text.contains("nautilus_caller_requests_total{class=\"read\"} 1"));
+        assert!(text.contains("nautilus_caller_rate_limit{class=\"task\"} 6"));
+        assert!(text.contains("nautilus_caller_rate_limited_callers{class=\"task\"} 1"));
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::EnclaveError;
+use axum::Json;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Number, Value};
+
+/// ==== CANONICAL JSON ====
+///
+/// Canonical serialization used whenever a JSON payload is hashed (request binding,
+/// idempotency keys). Follows RFC 8785 (JCS): object keys sorted, no insignificant
+/// whitespace, and numbers formatted like ECMAScript `Number.prototype.toString`, so
+/// the Node task and client SDKs can reproduce the exact same bytes.
+
+/// Shared test vectors, also consumed by the Node implementation in
+/// `nodejs-task/utils/canonical-json.js`.
+pub const TEST_VECTORS_JSON: &str = include_str!("../canonical_test_vectors.json");
+
+/// Serialize a JSON value into its canonical string form.
+pub fn to_canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+/// SHA3-256 over the canonical serialization of a JSON value.
+pub fn canonical_hash(value: &Value) -> [u8; 32] {
+    Sha3_256::digest(to_canonical_json(value).as_bytes()).digest
+}
+
+/// Hex encoded [canonical_hash].
+pub fn canonical_hash_hex(value: &Value) -> String {
+    Hex::encode(canonical_hash(value))
+}
+
+/// Canonical hash of any serializable payload.
+pub fn canonical_hash_of<T: Serialize>(payload: &T) -> Result<[u8; 32], EnclaveError> {
+    let value = serde_json::to_value(payload).map_err(|e| {
+        EnclaveError::Internal(format!("Failed to serialize payload for hashing: {}", e))
+    })?;
+    Ok(canonical_hash(&value))
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&format_number(n)),
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            // Keys are ordered by UTF-16 code units, as JCS requires.
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    // serde_json escapes exactly the characters JSON.stringify escapes.
+    out.push_str(&serde_json::to_string(s).expect("string serialization should not fail"));
+}
+
+fn format_number(n: &Number) -> String {
+    if let Some(i) = n.as_i64() {
+        return i.to_string();
+    }
+    if let Some(u) = n.as_u64() {
+        return u.to_string();
+    }
+    format_f64(n.as_f64().unwrap_or_default())
+}
+
+/// Format a float like ECMAScript does: integral values without a fraction,
+/// plain decimal notation in [1e-6, 1e21) and exponent notation outside of it.
+fn format_f64(f: f64) -> String {
+    if f == 0.0 {
+        // Covers -0.0 as well.
+        return "0".to_string();
+    }
+    let abs = f.abs();
+    if (1e-6..1e21).contains(&abs) {
+        return format!("{}", f);
+    }
+    let formatted = format!("{:e}", f);
+    match formatted.split_once('e') {
+        Some((mantissa, exp)) if !exp.starts_with('-') => format!("{}e+{}", mantissa, exp),
+        _ => formatted,
+    }
+}
+
+/// A single cross-language test vector.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanonicalTestVector {
+    pub name: String,
+    pub input: Value,
+    pub canonical: String,
+    pub sha3_256: String,
+}
+
+/// Parse the bundled test vectors.
+pub fn test_vectors() -> Vec<CanonicalTestVector> {
+    serde_json::from_str(TEST_VECTORS_JSON).expect("bundled test vectors should be valid")
+}
+
+/// Endpoint that exports the canonical JSON test vectors.
+pub async fn canonical_test_vectors(ctx: RequestContext) -> ApiResponse<Vec<CanonicalTestVector>> {
+    ctx.ok(test_vectors())
+}
+
+/// Request for the canonical verification endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CanonicalVerifyRequest {
+    pub payload: Value,
+    /// Optional hex encoded SHA3-256 hash computed by the client.
+    pub expected_hash: Option<String>,
+}
+
+/// Response for the canonical verification endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CanonicalVerifyResponse {
+    pub canonical: String,
+    pub sha3_256: String,
+    /// Whether `expected_hash` matched, if one was provided.
+    pub matches: Option<bool>,
+}
+
+/// Endpoint that canonicalizes a payload and optionally checks a client computed hash,
+/// so SDK authors can debug mismatches against the enclave.
+pub async fn verify_canonical(
+    ctx: RequestContext,
+    Json(request): Json<CanonicalVerifyRequest>,
+) -> ApiResponse<CanonicalVerifyResponse> {
+    let canonical = to_canonical_json(&request.payload);
+    let sha3_256 = canonical_hash_hex(&request.payload);
+    let matches = request
+        .expected_hash
+        .map(|expected| expected.trim_start_matches("0x").eq_ignore_ascii_case(&sha3_256));
+
+    ctx.ok(CanonicalVerifyResponse {
+        canonical,
+        sha3_256,
+        matches,
+    })
+}
+
+/// Serde adapter for JSON values inside BCS signed messages. BCS has neither floats nor
+/// self-describing values, so binary formats carry the canonical JSON string while
+/// human readable formats keep the value as is.
+pub mod bcs_json {
+    use super::*;
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            to_canonical_json(value).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        if deserializer.is_human_readable() {
+            Value::deserialize(deserializer)
+        } else {
+            serde_json::from_str(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sorted_keys_and_no_whitespace() {
+        let value = json!({"b": 1, "a": {"d": [1, 2], "c": null}});
+        assert_eq!(to_canonical_json(&value), r#"{"a":{"c":null,"d":[1,2]},"b":1}"#);
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        assert_eq!(format_f64(1.0), "1");
+        assert_eq!(format_f64(-0.0), "0");
+        assert_eq!(format_f64(0.1), "0.1");
+        assert_eq!(format_f64(1e21), "1e+21");
+        assert_eq!(format_f64(1e-7), "1e-7");
+        assert_eq!(format_f64(123456789.5), "123456789.5");
+    }
+
+    #[test]
+    fn test_bcs_json_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Message {
+            #[serde(with = "bcs_json")]
+            data: Value,
+        }
+        let message = Message {
+            data: json!({"score": 0.75, "ids": [1, 2]}),
+        };
+        let bytes = bcs::to_bytes(&message).unwrap();
+        assert_eq!(bcs::from_bytes::<String>(&bytes).unwrap(), r#"{"ids":[1,2],"score":0.75}"#);
+        assert_eq!(bcs::from_bytes::<Message>(&bytes).unwrap(), message);
+        assert_eq!(serde_json::to_value(&message).unwrap(), json!({"data": {"ids": [1, 2], "score": 0.75}}));
+    }
+
+    #[test]
+    fn test_vectors_match() {
+        for vector in test_vectors() {
+            assert_eq!(to_canonical_json(&vector.input), vector.canonical, "{}", vector.name);
+            assert_eq!(canonical_hash_hex(&vector.input), vector.sha3_256, "{}", vector.name);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Qdrant collection parameters. A missing collection is created by the embedding task on
+//! first ingest, with the vector dimension of the active embedding model and the distance
+//! and HNSW index parameters configured here. Search-time parameters can be tuned per
+//! collection on `/admin/collections/:name/tune` without recreating the collection, and
+//! traded for latency per request with a [SearchQuality].
+//!
+//! With `EMBEDDING_DIMENSIONS` set, embeddings of models trained with Matryoshka
+//! representation learning are truncated to their leading dimensions before they are stored.
+//! The truncation is recorded in the metadata of the collections created with it
+//! ([EMBEDDING_DIMENSIONS_METADATA]), and searches truncate query vectors the same way.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::config::ApiKey;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+/// Vector distance metric of newly created collections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Distance {
+    #[default]
+    Cosine,
+    Dot,
+    Euclid,
+    Manhattan,
+}
+
+impl fmt::Display for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Distance::Cosine => "Cosine",
+            Distance::Dot => "Dot",
+            Distance::Euclid => "Euclid",
+            Distance::Manhattan => "Manhattan",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Distance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cosine" => Ok(Distance::Cosine),
+            "dot" => Ok(Distance::Dot),
+            "euclid" => Ok(Distance::Euclid),
+            "manhattan" => Ok(Distance::Manhattan),
+            _ => Err(format!(
+                "unknown distance {:?}, expected Cosine, Dot, Euclid or Manhattan",
+                s
+            )),
+        }
+    }
+}
+
+/// Collection metadata key recording the dimensions embeddings are truncated to.
+pub const EMBEDDING_DIMENSIONS_METADATA: &str = "embeddingDimensions";
+
+/// Parameters of collections created on first ingest. Unset HNSW parameters keep the
+/// Qdrant defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionSettings {
+    pub distance: Distance,
+    /// Edges per node in the HNSW graph
+    pub hnsw_m: Option<u32>,
+    /// Neighbours considered while building the HNSW graph
+    pub hnsw_ef_construct: Option<u32>,
+    /// Leading embedding dimensions kept (Matryoshka truncation), all when unset
+    pub embedding_dimensions: Option<u32>,
+}
+
+impl CollectionSettings {
+    /// Environment variables passed to the embedding task.
+    pub fn task_env(&self) -> Vec<(String, String)> {
+        let mut env = vec![("QDRANT_DISTANCE".to_string(), self.distance.to_string())];
+        if let Some(m) = self.hnsw_m {
+            env.push(("QDRANT_HNSW_M".to_string(), m.to_string()));
+        }
+        if let Some(ef_construct) = self.hnsw_ef_construct {
+            env.push(("QDRANT_HNSW_EF_CONSTRUCT".to_string(), ef_construct.to_string()));
+        }
+        env
+    }
+}
+
+/// Embedding inversion mitigation, applied by the task to vectors before they are stored
+/// (see `utils/vector-privacy.js`). Both measures cost some recall and are off by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorPrivacy {
+    /// Random projection to this many dimensions, applied to query vectors too
+    pub projection_dimensions: Option<u32>,
+    /// Secret seed of the projection matrix
+    pub projection_seed: Option<ApiKey>,
+    /// Gaussian noise with an expected norm of this fraction of the vector norm
+    pub noise_scale: f64,
+}
+
+impl VectorPrivacy {
+    /// Environment variables passed to tasks that store or search vectors.
+    pub fn task_env(&self) -> Vec<(String, String)> {
+        let mut env = Vec::new();
+        if let (Some(dimensions), Some(seed)) = (self.projection_dimensions, &self.projection_seed) {
+            env.push(("VECTOR_PROJECTION_DIMENSIONS".to_string(), dimensions.to_string()));
+            env.push(("VECTOR_PROJECTION_SEED".to_string(), seed.expose().to_string()));
+        }
+        if self.noise_scale > 0.0 {
+            env.push(("VECTOR_NOISE_SCALE".to_string(), self.noise_scale.to_string()));
+        }
+        env
+    }
+}
+
+/// Search-time parameters, as Qdrant accepts them in a search request's `params`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchParams {
+    /// Candidates kept during HNSW search, higher is more accurate and slower
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hnsw_ef: Option<u32>,
+    /// Bypass the index and search exhaustively
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exact: Option<bool>,
+}
+
+/// HNSW candidates of a `fast` search.
+pub const FAST_SEARCH_HNSW_EF: u32 = 32;
+
+/// Recall a retrieval request asks for, traded against its latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchQuality {
+    /// Few HNSW candidates, for interactive search
+    Fast,
+    /// The collection's search parameters
+    #[default]
+    Balanced,
+    /// Exhaustive search bypassing the index, exact but slowest
+    Accurate,
+}
+
+impl SearchQuality {
+    /// Search parameters of this quality on a collection whose parameters are `tuned`.
+    /// `fast` never keeps more candidates than the collection does.
+    pub fn search_params(self, tuned: SearchParams) -> SearchParams {
+        match self {
+            SearchQuality::Fast => SearchParams {
+                hnsw_ef: Some(tuned.hnsw_ef.map_or(FAST_SEARCH_HNSW_EF, |ef| ef.min(FAST_SEARCH_HNSW_EF))),
+                exact: Some(false),
+            },
+            SearchQuality::Balanced => tuned,
+            SearchQuality::Accurate => SearchParams {
+                hnsw_ef: None,
+                exact: Some(true),
+            },
+        }
+    }
+}
+
+impl fmt::Display for SearchQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SearchQuality::Fast => "fast",
+            SearchQuality::Balanced => "balanced",
+            SearchQuality::Accurate => "accurate",
+        })
+    }
+}
+
+/// Search parameters of every collection: tuned ones, else the configured default.
+#[derive(Debug, Default)]
+pub struct CollectionTuning {
+    default: SearchParams,
+    tuned: Mutex<HashMap<String, SearchParams>>,
+}
+
+impl CollectionTuning {
+    pub fn new(default: SearchParams) -> Self {
+        Self {
+            default,
+            tuned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, collection: &str) -> SearchParams {
+        self.tuned
+            .lock()
+            .unwrap()
+            .get(collection)
+            .cloned()
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// Search parameters of every tuned collection.
+    pub fn tuned(&self) -> BTreeMap<String, SearchParams> {
+        self.tuned.lock().unwrap().iter().map(|(name, params)| (name.clone(), params.clone())).collect()
+    }
+
+    /// Replace the search parameters of `collection`, returning the previous ones.
+    pub fn set(&self, collection: &str, params: SearchParams) -> SearchParams {
+        let previous = self.tuned.lock().unwrap().insert(collection.to_string(), params);
+        previous.unwrap_or_else(|| self.default.clone())
+    }
+}
+
+/// Response of `/admin/collections/:name/tune`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuneCollectionResponse {
+    pub collection: String,
+    pub previous: SearchParams,
+    pub search_params: SearchParams,
+}
+
+/// Set the search parameters of an allowlisted collection. Requires the admin token.
+pub async fn tune_collection(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(params): Json<SearchParams>,
+) -> ApiResponse<TuneCollectionResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let collection = match state.qdrant_collection(Some(&name)) {
+        Ok(collection) => collection.to_string(),
+        Err(e) => return ctx.error(e),
+    };
+    if params.hnsw_ef == Some(0) {
+        return ctx.error(EnclaveError::BadRequest("hnsw_ef must be positive".to_string()));
+    }
+
+    let previous = state.collection_tuning.set(&collection, params.clone());
+    state.audit_log.record(
+        "collection_tuned",
+        &collection,
+        serde_json::json!({ "previous": previous, "search_params": params }),
+    );
+    ctx.ok(TuneCollectionResponse {
+        collection,
+        previous,
+        search_params: params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_parsing() {
+        assert_eq!("cosine".parse::<Distance>().unwrap(), Distance::Cosine);
+        assert_eq!("Euclid".parse::<Distance>().unwrap(), Distance::Euclid);
+        assert!("hamming".parse::<Distance>().is_err());
+        assert_eq!(Distance::Dot.to_string(), "Dot");
+    }
+
+    #[test]
+    fn test_tuning_falls_back_to_default() {
+        let tuning = CollectionTuning::new(SearchParams {
+            hnsw_ef: Some(64),
+            exact: None,
+        });
+        assert_eq!(tuning.get("messages").hnsw_ef, Some(64));
+
+        let tuned = SearchParams {
+            hnsw_ef: Some(256),
+            exact: Some(false),
+        };
+        assert_eq!(tuning.set("messages", tuned.clone()).hnsw_ef, Some(64));
+        assert_eq!(tuning.get("messages"), tuned);
+        assert_eq!(tuning.get("documents").hnsw_ef, Some(64));
+        assert_eq!(serde_json::to_string(&tuned).unwrap(), r#"{"hnsw_ef":256,"exact":false}"#);
+    }
+
+    #[test]
+    fn test_search_quality_params() {
+        let tuned = SearchParams {
+            hnsw_ef: Some(128),
+            exact: None,
+        };
+        assert_eq!(SearchQuality::Balanced.search_params(tuned.clone()), tuned);
+        assert_eq!(SearchQuality::Fast.search_params(tuned.clone()).hnsw_ef, Some(FAST_SEARCH_HNSW_EF));
+        let low = SearchParams {
+            hnsw_ef: Some(16),
+            exact: None,
+        };
+        assert_eq!(SearchQuality::Fast.search_params(low).hnsw_ef, Some(16));
+        assert_eq!(
+            serde_json::to_string(&SearchQuality::Accurate.search_params(tuned)).unwrap(),
+            r#"{"exact":true}"#
+        );
+        assert_eq!(serde_json::from_str::<SearchQuality>(r#""fast""#).unwrap(), SearchQuality::Fast);
+    }
+
+    #[test]
+    fn test_vector_privacy_env() {
+        assert!(VectorPrivacy::default().task_env().is_empty());
+        let privacy = VectorPrivacy {
+            projection_dimensions: Some(256),
+            projection_seed: Some(ApiKey::new("ab".repeat(32))),
+            noise_scale: 0.05,
+        };
+        let env = privacy.task_env();
+        assert_eq!(env[0], ("VECTOR_PROJECTION_DIMENSIONS".to_string(), "256".to_string()));
+        assert_eq!(env[2], ("VECTOR_NOISE_SCALE".to_string(), "0.05".to_string()));
+    }
+}
@@ -1,15 +1,25 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::api_response::{ApiResponse, AttestationEnvelope, ConfigEnvelope, HealthCheckEnvelope, RequestContext};
+use crate::app::{EmbeddingIngestRequest, FilteredRetrievalRequest, MessageBlobRetrievalRequest, TaskRequest, TaskResponse};
+use crate::endpoints::{probe_client, AllowedEndpoints, EndpointsStatus};
+use crate::http_cache::Freshness;
+use crate::internal_key::EncryptionKeySources;
+use crate::key_manager::{ResponseSigner, SignatureScheme, SigningKey};
+use crate::retrieval_stream::wants_ndjson;
+use crate::runtime_config::RuntimeConfig;
 use crate::AppState;
 use crate::EnclaveError;
-use axum::{extract::State, Json};
-use fastcrypto::traits::Signer;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use fastcrypto::hash::{HashFunction, Sha3_256};
 use fastcrypto::{encoding::Encoding, traits::ToFromBytes};
 use fastcrypto::{encoding::Hex, traits::KeyPair as FcKeyPair};
 use nsm_api::api::{Request as NsmRequest, Response as NsmResponse};
 use nsm_api::driver;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use serde_repr::Deserialize_repr;
@@ -17,16 +27,20 @@ use serde_repr::Serialize_repr;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::info;
+use utoipa::{IntoParams, ToSchema};
 
-use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey};
 /// ==== COMMON TYPES ====
 
 /// Intent message wrapper struct containing the intent scope and timestamp.
 /// This standardizes the serialized payload for signing.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[aliases(TaskIntentMessage = IntentMessage<TaskResponse>)]
 pub struct IntentMessage<T: Serialize> {
+    /// Numeric [IntentScope] of the signed message
+    #[schema(value_type = u8)]
     pub intent: IntentScope,
     pub timestamp_ms: u64,
     pub data: T,
@@ -34,12 +48,91 @@ pub struct IntentMessage<T: Serialize> {
 
 /// Intent scope enum. Add new scope here if needed, each corresponds to a
 /// scope for signing. Replace with your own intent per message type being signed by the enclave.
-#[derive(Serialize_repr, Deserialize_repr, Debug)]
+/// Values are part of the signed bytes and checked on-chain, so never renumber them.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum IntentScope {
-    // Add your own intent scopes here
-    // Example: DataProcessing = 0,
     Generic = 0,
+    /// Signed summary (chunk Merkle root) closing a streamed response.
+    StreamSummary = 1,
+    /// Execution receipt anchored to Walrus.
+    ExecutionReceipt = 2,
+    /// Result of `/embedding_ingest`.
+    EmbeddingIngest = 3,
+    /// Result of `/retrieve_messages_filtered`.
+    MessageRetrieval = 4,
+    /// Result of `/retrieve_messages_by_blob_ids`.
+    BlobRetrieval = 5,
+    /// Result of `/process_data`.
+    ProcessData = 6,
+    /// Job store and cache state a primary sends its warm standby.
+    ReplicationSnapshot = 7,
+    /// Result of `/delete_vectors`.
+    VectorDeletion = 8,
+    /// Entry of the task audit log served on `/audit/tasks`.
+    TaskAudit = 9,
+    /// Report of `/health_check?signed=true`.
+    HealthCheck = 10,
+    /// Report of `/config?signed=true`.
+    ConfigReport = 11,
+}
+
+impl IntentScope {
+    /// Scope signing the results of a task operation, [IntentScope::Generic] for unknown ones.
+    pub fn for_operation(operation: &str) -> Self {
+        match operation {
+            "process_data" => IntentScope::ProcessData,
+            "embedding_ingest" => IntentScope::EmbeddingIngest,
+            "retrieve_messages_by_blob_ids" => IntentScope::BlobRetrieval,
+            "retrieve_messages_filtered" => IntentScope::MessageRetrieval,
+            _ => IntentScope::Generic,
+        }
+    }
+
+    /// Every scope, in discriminant order.
+    pub const ALL: [IntentScope; 12] = [
+        IntentScope::Generic,
+        IntentScope::StreamSummary,
+        IntentScope::ExecutionReceipt,
+        IntentScope::EmbeddingIngest,
+        IntentScope::MessageRetrieval,
+        IntentScope::BlobRetrieval,
+        IntentScope::ProcessData,
+        IntentScope::ReplicationSnapshot,
+        IntentScope::VectorDeletion,
+        IntentScope::TaskAudit,
+        IntentScope::HealthCheck,
+        IntentScope::ConfigReport,
+    ];
+
+    /// Snake case name, used in configuration and metric labels.
+    pub fn name(self) -> &'static str {
+        match self {
+            IntentScope::Generic => "generic",
+            IntentScope::StreamSummary => "stream_summary",
+            IntentScope::ExecutionReceipt => "execution_receipt",
+            IntentScope::EmbeddingIngest => "embedding_ingest",
+            IntentScope::MessageRetrieval => "message_retrieval",
+            IntentScope::BlobRetrieval => "blob_retrieval",
+            IntentScope::ProcessData => "process_data",
+            IntentScope::ReplicationSnapshot => "replication_snapshot",
+            IntentScope::VectorDeletion => "vector_deletion",
+            IntentScope::TaskAudit => "task_audit",
+            IntentScope::HealthCheck => "health_check",
+            IntentScope::ConfigReport => "config_report",
+        }
+    }
+}
+
+impl std::str::FromStr for IntentScope {
+    type Err = EnclaveError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        IntentScope::ALL
+            .into_iter()
+            .find(|scope| scope.name() == name)
+            .ok_or_else(|| EnclaveError::BadRequest(format!("Unknown intent scope: {}", name)))
+    }
 }
 
 impl<T: Serialize + Debug> IntentMessage<T> {
@@ -53,109 +146,577 @@ impl<T: Serialize + Debug> IntentMessage<T> {
 }
 
 /// Wrapper struct containing the response (the intent message) and signature.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+#[aliases(SignedTaskResponse = ProcessedDataResponse<TaskIntentMessage>)]
 pub struct ProcessedDataResponse<T> {
     pub response: T,
     pub signature: String,
+    /// ID of the signing key, see `/keys`
+    pub key_id: String,
+    /// Sui flag of the signature scheme, 0 for Ed25519 and 1 for secp256k1
+    #[serde(default)]
+    #[schema(value_type = u8)]
+    pub scheme: SignatureScheme,
+    /// Attestation bound to the client's nonce and this response, when requested with
+    /// `attestation: "fresh"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<GetAttestationResponse>,
 }
 
 /// Wrapper struct containing the request payload.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[aliases(
+    TaskProcessDataRequest = ProcessDataRequest<TaskRequest>,
+    EmbeddingIngestProcessDataRequest = ProcessDataRequest<EmbeddingIngestRequest>,
+    MessageBlobRetrievalProcessDataRequest = ProcessDataRequest<MessageBlobRetrievalRequest>,
+    FilteredRetrievalProcessDataRequest = ProcessDataRequest<FilteredRetrievalRequest>
+)]
 pub struct ProcessDataRequest<T> {
     pub payload: T,
 }
 
-/// Sign the bcs bytes of the the payload with keypair.
-pub fn to_signed_response<T: Serialize + Clone>(
-    kp: &Ed25519KeyPair,
+/// Sign the bcs bytes of the the payload with the key's scheme. Fails like
+/// [to_bcs_envelope] for payloads BCS cannot encode.
+pub fn to_signed_response<T: Serialize + Clone, S: ResponseSigner + ?Sized>(
+    kp: &S,
     payload: T,
     timestamp_ms: u64,
     intent: IntentScope,
-) -> ProcessedDataResponse<IntentMessage<T>> {
+) -> Result<ProcessedDataResponse<IntentMessage<T>>, EnclaveError> {
     let intent_msg = IntentMessage {
         intent,
         timestamp_ms,
         data: payload.clone(),
     };
 
-    let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
-    let sig = kp.sign(&signing_payload);
-    ProcessedDataResponse {
+    let signing_payload = bcs::to_bytes(&intent_msg).map_err(bcs_error)?;
+    Ok(ProcessedDataResponse {
         response: intent_msg,
-        signature: Hex::encode(sig),
-    }
+        signature: Hex::encode(kp.sign_bytes(&signing_payload)),
+        key_id: kp.key_id(),
+        scheme: kp.scheme(),
+        attestation: None,
+    })
+}
+
+/// Media type clients send in `Accept` to receive BCS encoded signed responses.
+pub const BCS_MEDIA_TYPE: &str = "application/bcs";
+
+/// BCS response envelope. `intent_message` holds the exact bytes that were signed,
+/// so verifiers can check the signature without re-serializing anything.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BcsSignedEnvelope {
+    pub intent_message: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// ID of the signing key, see `/keys`
+    pub key_id: String,
+    /// Sui flag of the signature scheme, 0 for Ed25519 and 1 for secp256k1
+    #[schema(value_type = u8)]
+    pub scheme: SignatureScheme,
+}
+
+/// Returns true if the request asks for a BCS encoded response body.
+pub fn wants_bcs(headers: &HeaderMap) -> bool {
+    accepts(headers, BCS_MEDIA_TYPE)
+}
+
+/// Returns true if the request's `Accept` lists `media_type`.
+pub fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| {
+            v.split(';')
+                .next()
+                .is_some_and(|media| media.trim().eq_ignore_ascii_case(media_type))
+        })
+}
+
+fn bcs_error(e: bcs::Error) -> EnclaveError {
+    EnclaveError::Internal(format!("Failed to BCS encode the response: {}", e))
+}
+
+/// Sign the bcs bytes of the payload and wrap them with the signature in a [BcsSignedEnvelope].
+/// Fails for payloads BCS cannot encode, such as a `serde_json::Value` not encoded with
+/// [crate::canonical::bcs_json].
+pub fn to_bcs_envelope<T: Serialize, S: ResponseSigner + ?Sized>(
+    kp: &S,
+    payload: T,
+    timestamp_ms: u64,
+    intent: IntentScope,
+) -> Result<BcsSignedEnvelope, EnclaveError> {
+    let intent_msg = IntentMessage {
+        intent,
+        timestamp_ms,
+        data: payload,
+    };
+
+    let signing_payload = bcs::to_bytes(&intent_msg).map_err(bcs_error)?;
+    Ok(BcsSignedEnvelope {
+        signature: kp.sign_bytes(&signing_payload),
+        intent_message: signing_payload,
+        key_id: kp.key_id(),
+        scheme: kp.scheme(),
+    })
+}
+
+/// Build an `application/bcs` response carrying the BCS encoded [BcsSignedEnvelope].
+pub fn to_bcs_response<T: Serialize, S: ResponseSigner + ?Sized>(
+    kp: &S,
+    payload: T,
+    timestamp_ms: u64,
+    intent: IntentScope,
+) -> Result<Response, EnclaveError> {
+    let envelope = to_bcs_envelope(kp, payload, timestamp_ms, intent)?;
+    let body = bcs::to_bytes(&envelope).map_err(bcs_error)?;
+    Ok((
+        [(header::CONTENT_TYPE, HeaderValue::from_static(BCS_MEDIA_TYPE))],
+        body,
+    )
+        .into_response())
+}
+
+/// Current unix time in milliseconds, used as the intent message timestamp.
+pub fn current_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
 
 /// ==== HEALTHCHECK, GET ATTESTASTION ENDPOINT IMPL ====
 
+/// Longest caller `nonce` accepted by the NSM.
+pub const MAX_ATTESTATION_NONCE_BYTES: usize = 512;
+/// Longest caller `user_data`: the NSM accepts 512 bytes and the build metadata hash takes 32.
+pub const MAX_ATTESTATION_USER_DATA_BYTES: usize = 480;
+/// Default for `ATTESTATION_CACHE_SECS`.
+pub const DEFAULT_ATTESTATION_CACHE_SECS: u64 = 300;
+
+/// Optional challenge binding of an attestation, hex encoded with or without `0x`. Given
+/// as query parameters of `GET /get_attestation` or as the JSON body of `POST /get_attestation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AttestationRequest {
+    /// Attested as the document `nonce`
+    pub nonce: Option<String>,
+    /// Attested after the build metadata hash in the document `user_data`
+    pub user_data: Option<String>,
+}
+
+/// Decoded [AttestationRequest].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttestationChallenge {
+    pub nonce: Option<Vec<u8>>,
+    pub user_data: Option<Vec<u8>>,
+}
+
+fn decode_challenge_field(name: &str, value: Option<&str>, max_len: usize) -> Result<Option<Vec<u8>>, EnclaveError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let bytes = Hex::decode(value.strip_prefix("0x").unwrap_or(value))
+        .map_err(|_| EnclaveError::BadRequest(format!("{} must be hex encoded", name)))?;
+    if bytes.len() > max_len {
+        return Err(EnclaveError::BadRequest(format!(
+            "{} is {} bytes, at most {} are allowed",
+            name,
+            bytes.len(),
+            max_len
+        )));
+    }
+    Ok(Some(bytes))
+}
+
+impl AttestationRequest {
+    pub fn decode(&self) -> Result<AttestationChallenge, EnclaveError> {
+        Ok(AttestationChallenge {
+            nonce: decode_challenge_field("nonce", self.nonce.as_deref(), MAX_ATTESTATION_NONCE_BYTES)?,
+            user_data: decode_challenge_field("user_data", self.user_data.as_deref(), MAX_ATTESTATION_USER_DATA_BYTES)?,
+        })
+    }
+}
+
 /// Response for get attestation.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GetAttestationResponse {
     pub success: bool,
     pub attestation: AttestationInfo,
+    /// When the NSM produced the document, in milliseconds since the epoch
+    #[serde(default)]
+    pub generated_at_ms: u64,
+    /// Hex `nonce` of the request, as attested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// Hex `user_data` of the request. The document `user_data` is the build metadata hash
+    /// followed by these bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AttestationInfo {
     pub enclaveId: String,
     pub attestationDocument: String,
 }
 /// Endpoint that returns an attestation committed
-/// to the enclave's public key.
+/// to the enclave's public key, and to the caller's nonce and user data if given. Without a
+/// challenge the document is reused for `ATTESTATION_CACHE_SECS` and served with caching
+/// headers, answering 304 to an `If-Modified-Since` no older than it.
+#[utoipa::path(
+    get,
+    path = "/get_attestation",
+    params(
+        AttestationRequest,
+        ("If-Modified-Since" = Option<String>, Header, description = "HTTP date of a copy of the unchallenged attestation")
+    ),
+    responses(
+        (status = 200, description = "Attestation document", body = AttestationEnvelope),
+        (status = 304, description = "The unchallenged attestation did not change since If-Modified-Since"),
+        (status = 400, description = "Nonce or user data not hex, or too long"),
+        (status = 500, description = "The NSM did not return an attestation")
+    )
+)]
 pub async fn get_attestation(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Query(request): Query<AttestationRequest>,
+    headers: HeaderMap,
+) -> Response {
+    let challenge = match request.decode() {
+        Ok(challenge) => challenge,
+        Err(e) => return ctx.error::<GetAttestationResponse>(e).into_response(),
+    };
+    if challenge != AttestationChallenge::default() {
+        // Unique to the challenge, never worth keeping
+        let response = ctx.respond(fetch_attestation_with(&state, &challenge).await);
+        return ([(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))], response).into_response();
+    }
+    let max_age_secs = state.config.attestation_cache_secs;
+    let key = state.keys.current();
+    let recent = key.recent_attestation(Duration::from_secs(max_age_secs), || {
+        attest_key(&state, key.keypair.public(), &challenge)
+    });
+    match recent {
+        Ok(attestation) => Freshness::new(attestation.generated_at_ms, max_age_secs).respond(
+            &headers,
+            current_timestamp_ms(),
+            ctx.ok(attestation),
+        ),
+        Err(e) => ctx.error::<GetAttestationResponse>(e).into_response(),
+    }
+}
+
+/// `get_attestation` with the challenge in a JSON body.
+pub async fn post_attestation(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<AttestationRequest>,
+) -> ApiResponse<GetAttestationResponse> {
+    ctx.respond(attest_request(&state, &request).await)
+}
+
+async fn attest_request(state: &AppState, request: &AttestationRequest) -> Result<GetAttestationResponse, EnclaveError> {
+    let challenge = request.decode()?;
+    fetch_attestation_with(state, &challenge).await
+}
+
+/// Endpoint that returns the boot attestation referenced by signed task responses. The
+/// document lasts as long as the signing key, clients may reuse it for
+/// `ATTESTATION_CACHE_SECS` before checking whether the key rotated.
+pub async fn get_boot_attestation(
+    ctx: RequestContext,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<GetAttestationResponse>, EnclaveError> {
+    headers: HeaderMap,
+) -> Response {
+    match state.boot_attestation() {
+        Ok(boot) => {
+            let now = current_timestamp_ms();
+            let freshness = Freshness {
+                generated_at_ms: boot.generated_at_ms,
+                expires_at_ms: now + state.config.attestation_cache_secs * 1000,
+            };
+            freshness.respond(&headers, now, ctx.ok(boot))
+        }
+        Err(e) => ctx.error::<BootAttestation>(e).into_response(),
+    }
+}
+
+/// Source of attestation documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationProvider {
+    /// Nitro Secure Module, only available inside an enclave
+    Nsm,
+    /// Fixed placeholder document, for running the server outside an enclave with `--dev`
+    Mock,
+}
+
+/// Attestation a task request can ask to be returned with its signed response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AttestationMode {
+    /// A new attestation over the request's `attestation_nonce` and the signed response
+    Fresh,
+}
+
+/// Nonce of the fresh attestation a task request asked for, checked before the task runs.
+pub fn fresh_attestation_nonce(
+    mode: Option<AttestationMode>,
+    nonce: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<Option<Vec<u8>>, EnclaveError> {
+    match mode {
+        None => Ok(None),
+        Some(AttestationMode::Fresh) if wants_bcs(headers) || wants_ndjson(headers) => Err(EnclaveError::BadRequest(
+            "attestation \"fresh\" is only available with JSON responses".to_string(),
+        )),
+        Some(AttestationMode::Fresh) => {
+            let nonce = decode_challenge_field("attestation_nonce", nonce, MAX_ATTESTATION_NONCE_BYTES)?;
+            nonce.filter(|n| !n.is_empty()).map(Some).ok_or_else(|| {
+                EnclaveError::BadRequest("attestation_nonce is required with attestation \"fresh\"".to_string())
+            })
+        }
+    }
+}
+
+/// New attestation over `nonce` whose user data commits to a signed response: the build
+/// metadata hash followed by the SHA3-256 of the signed BCS bytes of `message`, bound to
+/// `key`, the key that signed it.
+pub async fn attest_signed_message<T: Serialize>(
+    state: &AppState,
+    key: &Ed25519KeyPair,
+    message: &IntentMessage<T>,
+    nonce: Vec<u8>,
+) -> Result<GetAttestationResponse, EnclaveError> {
+    let signed_bytes = bcs::to_bytes(message).map_err(bcs_error)?;
+    let challenge = AttestationChallenge {
+        nonce: Some(nonce),
+        user_data: Some(Sha3_256::digest(&signed_bytes).digest.to_vec()),
+    };
+    attest_key(state, key.public(), &challenge)
+}
+
+/// Bytes of the truncated SHA3-256 hashes in an [AttestationRef].
+pub const ATTESTATION_REF_HASH_BYTES: usize = 16;
+
+/// Attestation requested once per signing key, without a challenge. Signed task responses
+/// carry its [AttestationRef], so a verifier checks this document once per key.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BootAttestation {
+    pub attestation: AttestationInfo,
+    /// When the NSM produced the document, in milliseconds since the epoch
+    #[serde(default)]
+    pub generated_at_ms: u64,
+    /// Hex PCR0, the measurement of the enclave image
+    pub pcr0: String,
+    pub reference: AttestationRef,
+}
+
+/// Short reference to the [BootAttestation], signed with each task response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AttestationRef {
+    /// Hex SHA3-256 of the attestation document bytes, truncated to 16 bytes
+    pub document_hash: String,
+    /// Hex SHA3-256 of PCR0, truncated to 16 bytes
+    pub pcr0_hash: String,
+}
+
+impl AttestationRef {
+    pub fn new(document: &[u8], pcr0: &[u8]) -> Self {
+        Self {
+            document_hash: short_hash(document),
+            pcr0_hash: short_hash(pcr0),
+        }
+    }
+}
+
+fn short_hash(bytes: &[u8]) -> String {
+    Hex::encode(&Sha3_256::digest(bytes).digest[..ATTESTATION_REF_HASH_BYTES])
+}
+
+/// Request the boot attestation of signing `key` and PCR0, with the secp256k1 public key of
+/// the key as user data. Callers cache it per key, see [AppState::boot_attestation].
+pub fn request_boot_attestation(state: &AppState, key: &SigningKey) -> Result<BootAttestation, EnclaveError> {
+    let challenge = AttestationChallenge {
+        nonce: None,
+        user_data: Some(key.secp256k1.public().as_bytes().to_vec()),
+    };
+    let attestation = attestation_info(state, key.keypair.public(), &challenge)?;
+    let pcr0 = match state.attestation {
+        AttestationProvider::Nsm => nsm_pcr0()?,
+        AttestationProvider::Mock => vec![0; 48],
+    };
+    // The mock document is not hex, its text is hashed instead
+    let document = Hex::decode(&attestation.attestationDocument)
+        .unwrap_or_else(|_| attestation.attestationDocument.as_bytes().to_vec());
+    Ok(BootAttestation {
+        generated_at_ms: current_timestamp_ms(),
+        reference: AttestationRef::new(&document, &pcr0),
+        pcr0: Hex::encode(&pcr0),
+        attestation,
+    })
+}
+
+/// Request an attestation committed to the enclave's public key.
+pub async fn fetch_attestation(state: &AppState) -> Result<GetAttestationResponse, EnclaveError> {
+    fetch_attestation_with(state, &AttestationChallenge::default()).await
+}
+
+/// Request an attestation committed to the enclave's current public key and to `challenge`.
+pub async fn fetch_attestation_with(
+    state: &AppState,
+    challenge: &AttestationChallenge,
+) -> Result<GetAttestationResponse, EnclaveError> {
+    attest_key(state, state.keys.current().keypair.public(), challenge)
+}
+
+fn attest_key(
+    state: &AppState,
+    pk: &Ed25519PublicKey,
+    challenge: &AttestationChallenge,
+) -> Result<GetAttestationResponse, EnclaveError> {
     info!("get attestation called");
 
-    // let pk = state.eph_kp.public();
-    // let fd = driver::nsm_init();
-
-    // // Send attestation request to NSM driver with public key set.
-    // let request = NsmRequest::Attestation {
-    //     user_data: None,
-    //     nonce: None,
-    //     public_key: Some(ByteBuf::from(pk.as_bytes().to_vec())),
-    // };
-
-    // let response = driver::nsm_process_request(fd, request);
-    // match response {
-    //     NsmResponse::Attestation { document } => {
-    //         driver::nsm_exit(fd);
-    //         Ok(Json(GetAttestationResponse {
-    //             attestation: Hex::encode(document),
-    //         }))
-    //     }
-    //     _ => {
-    //         driver::nsm_exit(fd);
-    //         Err(EnclaveError::GenericError(
-    //             "unexpected response".to_string(),
-    //         ))
-    //     }
-    // }
-
-    let mock_response = GetAttestationResponse {
+    Ok(GetAttestationResponse {
         success: true,
-        attestation: AttestationInfo {
+        attestation: attestation_info(state, pk, challenge)?,
+        generated_at_ms: current_timestamp_ms(),
+        nonce: challenge.nonce.as_ref().map(Hex::encode),
+        user_data: challenge.user_data.as_ref().map(Hex::encode),
+    })
+}
+
+fn attestation_info(
+    state: &AppState,
+    pk: &Ed25519PublicKey,
+    challenge: &AttestationChallenge,
+) -> Result<AttestationInfo, EnclaveError> {
+    match state.attestation {
+        AttestationProvider::Nsm => nsm_attestation(state, pk, challenge),
+        AttestationProvider::Mock => Ok(AttestationInfo {
             enclaveId: "i-0a1b2c3d4e5f6g7h8".to_string(),
             attestationDocument: "mock-base64-attestation-document".to_string(),
-        },
+        }),
+    }
+}
+
+/// Document `user_data`: the build metadata hash, then the caller's bytes.
+pub fn attested_user_data(build_hash: &[u8], challenge: &AttestationChallenge) -> Vec<u8> {
+    [build_hash, challenge.user_data.as_deref().unwrap_or_default()].concat()
+}
+
+/// Attestation from the NSM driver over the public key and the build metadata hash. The
+/// instance ID is not visible inside the enclave, so the enclave is identified by its key.
+fn nsm_attestation(
+    state: &AppState,
+    pk: &Ed25519PublicKey,
+    challenge: &AttestationChallenge,
+) -> Result<AttestationInfo, EnclaveError> {
+    let fd = driver::nsm_init();
+
+    let request = NsmRequest::Attestation {
+        user_data: Some(ByteBuf::from(attested_user_data(
+            &state.build_info.attestation_user_data(),
+            challenge,
+        ))),
+        nonce: challenge.nonce.clone().map(ByteBuf::from),
+        public_key: Some(ByteBuf::from(pk.as_bytes().to_vec())),
     };
 
-    Ok(Json(mock_response))
+    let response = driver::nsm_process_request(fd, request);
+    driver::nsm_exit(fd);
+    match response {
+        NsmResponse::Attestation { document } => Ok(AttestationInfo {
+            enclaveId: Hex::encode(pk.as_bytes()),
+            attestationDocument: Hex::encode(document),
+        }),
+        other => Err(EnclaveError::AttestationError(format!(
+            "Unexpected NSM response: {:?}",
+            other
+        ))),
+    }
+}
+
+/// PCR0 from the NSM driver.
+fn nsm_pcr0() -> Result<Vec<u8>, EnclaveError> {
+    let fd = driver::nsm_init();
+    let response = driver::nsm_process_request(fd, NsmRequest::DescribePCR { index: 0 });
+    driver::nsm_exit(fd);
+    match response {
+        NsmResponse::DescribePCR { data, .. } => Ok(data),
+        other => Err(EnclaveError::AttestationError(format!(
+            "Unexpected NSM response: {:?}",
+            other
+        ))),
+    }
+}
+
+/// Overall health of the enclave, from the state of its critical dependencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OverallHealth {
+    /// Configuration valid and every critical endpoint reachable
+    Healthy,
+    /// Some critical endpoints unreachable
+    Degraded,
+    /// Configuration invalid or no critical endpoint reachable
+    Down,
+}
+
+impl OverallHealth {
+    /// Assess the probe results of `endpoints`. Unreachable non-critical endpoints do not
+    /// affect the outcome.
+    pub fn assess(endpoints: &AllowedEndpoints, endpoints_status: &HashMap<String, bool>, config_valid: bool) -> Self {
+        if !config_valid {
+            return OverallHealth::Down;
+        }
+        let critical: Vec<bool> = endpoints
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.critical)
+            .map(|endpoint| endpoints_status.get(&endpoint.host).copied().unwrap_or(false))
+            .collect();
+        match critical.iter().filter(|reachable| !**reachable).count() {
+            0 => OverallHealth::Healthy,
+            failed if failed == critical.len() => OverallHealth::Down,
+            _ => OverallHealth::Degraded,
+        }
+    }
+
+    /// HTTP status `/health_check` answers with, so load balancers need not read the body.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            OverallHealth::Healthy => StatusCode::OK,
+            OverallHealth::Degraded | OverallHealth::Down => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
 }
 
 /// Health check response.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HealthCheckResponse {
     /// Hex encoded public key booted on enclave.
     pub pk: String,
+    /// Hex encoded compressed secp256k1 public key of the current signing key
+    pub secp256k1_pk: String,
+    /// Sui flag of the scheme responses are signed with, 0 for Ed25519 and 1 for secp256k1
+    #[schema(value_type = u8)]
+    pub signature_scheme: SignatureScheme,
+    pub overall: OverallHealth,
     /// Status of endpoint connectivity checks
     pub endpoints_status: HashMap<String, bool>,
+    /// When the endpoints were probed, results being reused for `HEALTH_CHECK_CACHE_SECS`.
+    /// `None` in fast mode before any probe
+    pub endpoints_probed_at_ms: Option<u64>,
+    /// Whether `allowed_endpoints.yaml` could be loaded, and why not
+    pub endpoints_config: EndpointsStatus,
     /// Configuration status
     pub config_status: ConfigStatus,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ConfigStatus {
     /// Whether all required environment variables are loaded
     pub config_valid: bool,
@@ -163,7 +724,7 @@ pub struct ConfigStatus {
     pub config_info: ConfigInfo,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ConfigInfo {
     pub move_package_id: String,
     pub walrus_aggregator_url: String,
@@ -172,111 +733,156 @@ pub struct ConfigInfo {
 
     pub sui_secret_key_configured: bool,
     pub ruby_nodes_api_key_configured: bool,
+    pub internal_encryption_secret_key_configured: bool,
+    /// Where the keys of the encrypted stores come from
+    pub encryption_key_sources: EncryptionKeySources,
+    /// Settings as last updated on `POST /admin/config`
+    pub runtime: RuntimeConfig,
+}
+
+impl ConfigInfo {
+    fn of(state: &AppState) -> Self {
+        ConfigInfo {
+            move_package_id: state.move_package_id().to_string(),
+            walrus_aggregator_url: state.walrus_aggregator_url().to_string(),
+            walrus_publisher_url: state.walrus_publisher_url().to_string(),
+            walrus_epochs: state.walrus_epochs().to_string(),
+            sui_secret_key_configured: !state.sui_secret_key().is_empty(),
+            ruby_nodes_api_key_configured: !state.ruby_nodes_api_key().is_empty(),
+            internal_encryption_secret_key_configured: state.config.internal_encryption_key.is_some(),
+            encryption_key_sources: EncryptionKeySources {
+                crash_reports: state.crash_reports.key_source(),
+                payload_encryption: state.config.payload_key_source,
+            },
+            runtime: state.runtime_config(),
+        }
+    }
+}
+
+/// Query parameters of `/config`.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// Sign the report with the enclave key, so that a monitor can tell it from a proxy's
+    #[serde(default)]
+    pub signed: bool,
+}
+
+/// Query parameters of `/health_check`.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HealthCheckQuery {
+    /// Sign the report with the enclave key, so that a monitor can tell it from a proxy's
+    #[serde(default)]
+    pub signed: bool,
+    /// Skip the endpoint probes and report the last results, for orchestrator probes with
+    /// short timeouts
+    #[serde(default)]
+    pub fast: bool,
+}
+
+/// Serve a monitoring report as is or, when `signed`, signed under `scope` like task
+/// results: as a BCS envelope (when requested via `Accept`) or as a [ProcessedDataResponse]
+/// whose `signature` the JSON envelope repeats. `status` applies to every form.
+fn respond_report<T: Serialize + Clone>(
+    ctx: &RequestContext,
+    state: &AppState,
+    headers: &HeaderMap,
+    signed: bool,
+    scope: IntentScope,
+    report: T,
+    status: StatusCode,
+) -> Response {
+    if !signed {
+        return ctx.ok(report).with_status(status).into_response();
+    }
+    if let Err(e) = state.key_usage.acquire(scope) {
+        return ctx.error::<ProcessedDataResponse<IntentMessage<T>>>(e).into_response();
+    }
+    let key = state.keys.current();
+    if wants_bcs(headers) {
+        return match to_bcs_response(&*key, report, current_timestamp_ms(), scope) {
+            Ok(response) => (status, response).into_response(),
+            Err(e) => ctx.error::<ProcessedDataResponse<IntentMessage<T>>>(e).into_response(),
+        };
+    }
+    match to_signed_response(&*key, report, current_timestamp_ms(), scope) {
+        Ok(signed) => {
+            let signature = signed.signature.clone();
+            ctx.ok(signed).with_signature(signature).with_status(status).into_response()
+        }
+        Err(e) => ctx.error::<ProcessedDataResponse<IntentMessage<T>>>(e).into_response(),
+    }
 }
 
 /// Endpoint that health checks the enclave connectivity to all
-/// domains and returns the enclave's public key.
+/// domains and returns the enclave's public key. Answers 503 unless the enclave is healthy.
+/// With `signed=true` the report is signed under [IntentScope::HealthCheck], with `fast=true`
+/// endpoints are not probed.
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    params(HealthCheckQuery),
+    responses(
+        (status = 200, description = "Enclave healthy", body = HealthCheckEnvelope),
+        (status = 503, description = "Enclave degraded or down, with the same body", body = HealthCheckEnvelope)
+    )
+)]
 pub async fn health_check(
+    ctx: RequestContext,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<HealthCheckResponse>, EnclaveError> {
-    let pk = state.eph_kp.public();
-
-    // Create HTTP client with timeout
-    let client = Client::builder()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to create HTTP client: {}", e)))?;
-
-    // Load allowed endpoints from YAML file
-    let endpoints_status = match std::fs::read_to_string("allowed_endpoints.yaml") {
-        Ok(yaml_content) => {
-            match serde_yaml::from_str::<serde_yaml::Value>(&yaml_content) {
-                Ok(yaml_value) => {
-                    let mut status_map = HashMap::new();
-
-                    if let Some(endpoints) =
-                        yaml_value.get("endpoints").and_then(|e| e.as_sequence())
-                    {
-                        for endpoint in endpoints {
-                            if let Some(endpoint_str) = endpoint.as_str() {
-                                // Check connectivity to each endpoint
-                                let url = if endpoint_str.contains(".amazonaws.com") {
-                                    format!("https://{}/ping", endpoint_str)
-                                } else {
-                                    format!("https://{}", endpoint_str)
-                                };
-
-                                let is_reachable = match client.get(&url).send().await {
-                                    Ok(response) => {
-                                        if endpoint_str.contains(".amazonaws.com") {
-                                            // For AWS endpoints, check if response body contains "healthy"
-                                            match response.text().await {
-                                                Ok(body) => body.to_lowercase().contains("healthy"),
-                                                Err(e) => {
-                                                    info!(
-                                                        "Failed to read response body from {}: {}",
-                                                        endpoint_str, e
-                                                    );
-                                                    false
-                                                }
-                                            }
-                                        } else {
-                                            // For non-AWS endpoints, check for 200 status
-                                            response.status().is_success()
-                                        }
-                                    }
-                                    Err(e) => {
-                                        info!("Failed to connect to {}: {}", endpoint_str, e);
-                                        false
-                                    }
-                                };
-
-                                status_map.insert(endpoint_str.to_string(), is_reachable);
-                                info!(
-                                    "Checked endpoint {}: reachable = {}",
-                                    endpoint_str, is_reachable
-                                );
-                            }
-                        }
-                    }
-
-                    status_map
-                }
-                Err(e) => {
-                    info!("Failed to parse YAML: {}", e);
-                    HashMap::new()
-                }
-            }
-        }
-        Err(e) => {
-            info!("Failed to read allowed_endpoints.yaml: {}", e);
-            HashMap::new()
+    Query(query): Query<HealthCheckQuery>,
+    headers: HeaderMap,
+) -> Response {
+    match check_health(&state, query.fast).await {
+        Ok(health) => {
+            let status = health.overall.status_code();
+            respond_report(&ctx, &state, &headers, query.signed, IntentScope::HealthCheck, health, status)
         }
+        Err(e) => ctx.error::<HealthCheckResponse>(e).into_response(),
+    }
+}
+
+/// Run the connectivity and configuration checks behind `/health_check`. `fast` skips
+/// the probes and assesses the last results, or only the configuration before any probe.
+pub async fn check_health(state: &AppState, fast: bool) -> Result<HealthCheckResponse, EnclaveError> {
+    let key = state.keys.current();
+    let pk = key.keypair.public();
+
+    let probes = match fast {
+        true => state.endpoints.last_probe(),
+        false => Some(state.endpoints.probe(&probe_client()?).await),
+    };
+    let endpoints = match probes {
+        Some(_) => state.endpoints.current(),
+        None => Arc::new(AllowedEndpoints::default()),
+    };
+    let (endpoints_status, endpoints_probed_at_ms) = match probes {
+        Some(probes) => (probes.status, Some(probes.probed_at_ms)),
+        None => (HashMap::new(), None),
     };
 
     // Check configuration status
     let config_valid = state.validate_config().is_ok();
     let config_status = ConfigStatus {
         config_valid,
-        config_info: ConfigInfo {
-            move_package_id: state.move_package_id().to_string(),
-            walrus_aggregator_url: state.walrus_aggregator_url().to_string(),
-            walrus_publisher_url: state.walrus_publisher_url().to_string(),
-            walrus_epochs: state.walrus_epochs_str().to_string(),
-            sui_secret_key_configured: !state.sui_secret_key().is_empty(),
-            ruby_nodes_api_key_configured: !state.ruby_nodes_api_key().is_empty(),
-        },
+        config_info: ConfigInfo::of(state),
     };
 
-    Ok(Json(HealthCheckResponse {
+    Ok(HealthCheckResponse {
         pk: Hex::encode(pk.as_bytes()),
+        secp256k1_pk: key.secp256k1_public_key_hex(),
+        signature_scheme: key.scheme,
+        overall: OverallHealth::assess(&endpoints, &endpoints_status, config_valid),
         endpoints_status,
+        endpoints_probed_at_ms,
+        endpoints_config: state.endpoints.status(),
         config_status,
-    }))
+    })
 }
 
 /// Configuration endpoint response.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ConfigResponse {
     pub config_valid: bool,
     pub config_info: ConfigInfo,
@@ -284,10 +890,20 @@ pub struct ConfigResponse {
 }
 
 /// Endpoint to check current configuration (for debugging)
-/// Only shows non-sensitive configuration data
+/// Only shows non-sensitive configuration data. With `signed=true` the report is signed
+/// under [IntentScope::ConfigReport].
+#[utoipa::path(
+    get,
+    path = "/config",
+    params(ReportQuery),
+    responses((status = 200, description = "Configuration without secrets", body = ConfigEnvelope))
+)]
 pub async fn get_config(
+    ctx: RequestContext,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<ConfigResponse>, EnclaveError> {
+    Query(query): Query<ReportQuery>,
+    headers: HeaderMap,
+) -> Response {
     let validation_result = state.validate_config();
     let validation_errors = match &validation_result {
         Ok(_) => vec![],
@@ -296,16 +912,226 @@ pub async fn get_config(
 
     let config_response = ConfigResponse {
         config_valid: validation_result.is_ok(),
-        config_info: ConfigInfo {
-            move_package_id: state.move_package_id().to_string(),
-            walrus_aggregator_url: state.walrus_aggregator_url().to_string(),
-            walrus_publisher_url: state.walrus_publisher_url().to_string(),
-            walrus_epochs: state.walrus_epochs_str().to_string(),
-            sui_secret_key_configured: !state.sui_secret_key().is_empty(),
-            ruby_nodes_api_key_configured: !state.ruby_nodes_api_key().is_empty(),
-        },
+        config_info: ConfigInfo::of(&state),
         validation_errors,
     };
 
-    Ok(Json(config_response))
+    respond_report(&ctx, &state, &headers, query.signed, IntentScope::ConfigReport, config_response, StatusCode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+    use fastcrypto::traits::VerifyingKey;
+
+    #[test]
+    fn test_overall_health() {
+        let endpoints = AllowedEndpoints::parse(
+            "endpoints:\n  - a.example.com\n  - b.example.com\n  - host: c.example.com\n    critical: false\n",
+        )
+        .unwrap();
+        let status = |a, b, c| {
+            HashMap::from([
+                ("a.example.com".to_string(), a),
+                ("b.example.com".to_string(), b),
+                ("c.example.com".to_string(), c),
+            ])
+        };
+
+        assert_eq!(OverallHealth::assess(&endpoints, &status(true, true, true), true), OverallHealth::Healthy);
+        assert_eq!(OverallHealth::assess(&endpoints, &status(true, true, false), true), OverallHealth::Healthy);
+        assert_eq!(OverallHealth::assess(&endpoints, &status(true, false, true), true), OverallHealth::Degraded);
+        assert_eq!(OverallHealth::assess(&endpoints, &status(false, false, true), true), OverallHealth::Down);
+        assert_eq!(OverallHealth::assess(&endpoints, &status(true, true, true), false), OverallHealth::Down);
+        assert_eq!(OverallHealth::assess(&AllowedEndpoints::default(), &HashMap::new(), true), OverallHealth::Healthy);
+
+        assert_eq!(OverallHealth::Healthy.status_code(), StatusCode::OK);
+        assert_eq!(OverallHealth::Degraded.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(serde_json::to_value(OverallHealth::Down).unwrap(), "down");
+    }
+
+    #[test]
+    fn test_wants_bcs() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_bcs(&headers));
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        assert!(!wants_bcs(&headers));
+
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json;q=0.5, Application/BCS"),
+        );
+        assert!(wants_bcs(&headers));
+    }
+
+    #[test]
+    fn test_bcs_envelope_verifies() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let timestamp = 1744038900000;
+        let envelope = to_bcs_envelope(&kp, "hello".to_string(), timestamp, IntentScope::Generic).unwrap();
+
+        // The envelope must carry exactly the bytes of the intent message.
+        let expected = bcs::to_bytes(&IntentMessage::new(
+            "hello".to_string(),
+            timestamp,
+            IntentScope::Generic,
+        ))
+        .unwrap();
+        assert_eq!(envelope.intent_message, expected);
+
+        // The envelope round trips through BCS and the signature verifies.
+        let decoded: BcsSignedEnvelope =
+            bcs::from_bytes(&bcs::to_bytes(&envelope).unwrap()).unwrap();
+        let sig = Ed25519Signature::from_bytes(&decoded.signature).unwrap();
+        let pk: &Ed25519PublicKey = kp.public();
+        assert!(pk.verify(&decoded.intent_message, &sig).is_ok());
+        assert_eq!(decoded.key_id, crate::key_manager::key_id(pk));
+        assert_eq!(decoded.scheme, SignatureScheme::Ed25519);
+    }
+
+    #[test]
+    fn test_secp256k1_signed_response_verifies() {
+        use fastcrypto::secp256k1::Secp256k1Signature;
+
+        let key = SigningKey::new(Ed25519KeyPair::generate(&mut rand::thread_rng()), SignatureScheme::Secp256k1);
+        let signed = to_signed_response(&key, "hello".to_string(), 1744038900000, IntentScope::Generic).unwrap();
+        assert_eq!(signed.key_id, key.key_id);
+        assert_eq!(serde_json::to_value(&signed).unwrap()["scheme"], 1);
+
+        let message = bcs::to_bytes(&signed.response).unwrap();
+        let sig = Secp256k1Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+        assert!(key.secp256k1.public().verify(&message, &sig).is_ok());
+
+        let envelope = to_bcs_envelope(&key, "hello".to_string(), 1744038900000, IntentScope::Generic).unwrap();
+        assert_eq!(envelope.signature.len(), 64);
+        assert_eq!(bcs::to_bytes(&envelope).unwrap().last(), Some(&1));
+    }
+
+    #[test]
+    fn test_intent_scope_bcs_stability() {
+        let scopes = [
+            (IntentScope::Generic, 0u8),
+            (IntentScope::StreamSummary, 1),
+            (IntentScope::ExecutionReceipt, 2),
+            (IntentScope::EmbeddingIngest, 3),
+            (IntentScope::MessageRetrieval, 4),
+            (IntentScope::BlobRetrieval, 5),
+            (IntentScope::ProcessData, 6),
+            (IntentScope::ReplicationSnapshot, 7),
+            (IntentScope::VectorDeletion, 8),
+            (IntentScope::TaskAudit, 9),
+            (IntentScope::HealthCheck, 10),
+            (IntentScope::ConfigReport, 11),
+        ];
+        for (scope, byte) in scopes {
+            let message = IntentMessage::new("hello".to_string(), 1744038900000, scope);
+            let bytes = bcs::to_bytes(&message).unwrap();
+            assert_eq!(bytes[0], byte);
+            let decoded: IntentMessage<String> = bcs::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.intent, scope);
+        }
+
+        assert_eq!(IntentScope::for_operation("embedding_ingest"), IntentScope::EmbeddingIngest);
+        assert_eq!(IntentScope::for_operation("retrieve_messages_by_blob_ids"), IntentScope::BlobRetrieval);
+        assert_eq!(IntentScope::for_operation("retrieve_messages_filtered"), IntentScope::MessageRetrieval);
+        assert_eq!(IntentScope::for_operation("process_data"), IntentScope::ProcessData);
+        assert_eq!(IntentScope::for_operation("unknown"), IntentScope::Generic);
+    }
+
+    #[tokio::test]
+    async fn test_signed_config_report() {
+        let state = Arc::new(crate::test_app_state());
+        let report = |signed, headers| {
+            get_config(RequestContext::new(None), State(state.clone()), Query(ReportQuery { signed }), headers)
+        };
+
+        let plain = axum::body::to_bytes(report(false, HeaderMap::new()).await.into_body(), usize::MAX).await.unwrap();
+        let plain: serde_json::Value = serde_json::from_slice(&plain).unwrap();
+        assert!(plain["signature"].is_null());
+        assert!(plain["data"]["config_info"].is_object());
+        assert_eq!(state.key_usage.signed(IntentScope::ConfigReport), 0);
+
+        let body = axum::body::to_bytes(report(true, HeaderMap::new()).await.into_body(), usize::MAX).await.unwrap();
+        let envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["signature"], envelope["data"]["signature"]);
+        let signed: ProcessedDataResponse<IntentMessage<ConfigResponse>> =
+            serde_json::from_value(envelope["data"].clone()).unwrap();
+        assert_eq!(signed.response.intent, IntentScope::ConfigReport);
+        let key = state.keys.current();
+        let pk: &Ed25519PublicKey = key.keypair.public();
+        let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+        assert!(pk.verify(&bcs::to_bytes(&signed.response).unwrap(), &sig).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(BCS_MEDIA_TYPE));
+        let response = report(true, headers).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], BCS_MEDIA_TYPE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let envelope: BcsSignedEnvelope = bcs::from_bytes(&body).unwrap();
+        assert_eq!(envelope.intent_message[0], IntentScope::ConfigReport as u8);
+        let sig = Ed25519Signature::from_bytes(&envelope.signature).unwrap();
+        assert!(pk.verify(&envelope.intent_message, &sig).is_ok());
+        assert_eq!(state.key_usage.signed(IntentScope::ConfigReport), 2);
+    }
+
+    #[tokio::test]
+    async fn test_attestation_challenge() {
+        let request = AttestationRequest {
+            nonce: Some("0xC0FFEE".to_string()),
+            user_data: Some("0102".to_string()),
+        };
+        let challenge = request.decode().unwrap();
+        assert_eq!(challenge.nonce, Some(vec![0xc0, 0xff, 0xee]));
+        assert_eq!(attested_user_data(&[9; 32], &challenge)[30..], [9, 9, 1, 2]);
+        assert_eq!(attested_user_data(&[9; 32], &AttestationChallenge::default()), [9; 32]);
+
+        let too_long = AttestationRequest {
+            user_data: Some("00".repeat(MAX_ATTESTATION_USER_DATA_BYTES + 1)),
+            ..Default::default()
+        };
+        assert!(too_long.decode().is_err());
+        assert!(AttestationRequest { nonce: Some("xyz".to_string()), user_data: None }.decode().is_err());
+
+        let response = fetch_attestation_with(&crate::test_app_state(), &challenge).await.unwrap();
+        assert_eq!(response.nonce.as_deref(), Some("c0ffee"));
+        assert_eq!(response.user_data.as_deref(), Some("0102"));
+        let plain = fetch_attestation(&crate::test_app_state()).await.unwrap();
+        assert!(serde_json::to_value(&plain).unwrap().get("nonce").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_attestation_caching() {
+        let state = Arc::new(crate::test_app_state());
+        let get = |request: AttestationRequest, headers: HeaderMap| {
+            get_attestation(RequestContext::new(None), State(state.clone()), Query(request), headers)
+        };
+        let body = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        // The unchallenged document is reused, with caching headers
+        let first = get(AttestationRequest::default(), HeaderMap::new()).await;
+        assert_eq!(first.headers()[header::CACHE_CONTROL], "public, max-age=300");
+        let last_modified = first.headers()[header::LAST_MODIFIED].clone();
+        let generated_at_ms = body(first).await["data"]["generated_at_ms"].as_u64().unwrap();
+        let second = body(get(AttestationRequest::default(), HeaderMap::new()).await).await;
+        assert_eq!(second["data"]["generated_at_ms"], generated_at_ms);
+
+        let conditional = HeaderMap::from_iter([(header::IF_MODIFIED_SINCE, last_modified)]);
+        let not_modified = get(AttestationRequest::default(), conditional.clone()).await;
+        assert_eq!(not_modified.status(), StatusCode::NOT_MODIFIED);
+
+        // Challenged documents are never cached
+        let challenged = AttestationRequest { nonce: Some("01".to_string()), user_data: None };
+        let response = get(challenged, conditional).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let boot = get_boot_attestation(RequestContext::new(None), State(state.clone()), HeaderMap::new()).await;
+        assert!(boot.headers().contains_key(header::LAST_MODIFIED));
+        assert!(body(boot).await["data"]["generated_at_ms"].as_u64().unwrap() > 0);
+    }
 }
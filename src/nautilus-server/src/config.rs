@@ -0,0 +1,1016 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed server configuration. [Config::from_env] parses every variable up front and
+//! reports all missing or invalid ones together, instead of failing on the first or at
+//! the call site that happens to read it. Required variables and defaults come from
+//! [CONFIG_VARS].
+
+use crate::address_limits::{AddressLimits, AddressOperation};
+use crate::caller_limits::{BucketLimit, CallerKey, CallerLimits, RouteClass};
+use crate::collections::{CollectionSettings, SearchParams, VectorPrivacy};
+use crate::common::IntentScope;
+use crate::config_check::{VarKind, CONFIG_VARS};
+use crate::embeddings::ProviderKind;
+use crate::experiments::{RetrievalExperiments, RetrievalProfile};
+use crate::internal_key::{InternalKey, KeyPurpose, KeySource};
+use crate::key_usage::KeyUsage;
+use crate::leader::LeaseConfig;
+use crate::listener::{ListenConfig, TlsConfig, TlsMode};
+use crate::payload_crypto::PayloadKeyring;
+use crate::replication::{ReplicationConfig, ReplicationRole};
+use crate::breakers::BreakerPolicy;
+use crate::key_manager::SignatureScheme;
+use crate::retention::RetentionPolicy;
+use crate::runtime_health::CrashLoopPolicy;
+use crate::task_bundles::TaskBundles;
+use crate::task_runner::{NodeFlags, NodeFlagsByOperation, SchedulingHints, TaskRetryPolicy};
+use crate::walrus::{StorageBudget, StoreOptions};
+use crate::warmup::WarmupPolicy;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Secret value that is redacted in `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    /// The secret itself, for passing to the service it authenticates with.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKey(******)")
+    }
+}
+
+/// Every missing or invalid variable found by [Config::from_env].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} configuration problem(s):", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Configuration of the services the server and its Node.js tasks talk to.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Sui blockchain configuration
+    pub move_package_id: String,
+    pub sui_secret_key: ApiKey,
+    /// Sui fullnode JSON-RPC URL, used to check blob certification
+    pub sui_rpc_url: Url,
+    /// Fullnodes tried in order when `sui_rpc_url` is unavailable
+    pub sui_rpc_fallback_urls: Vec<Url>,
+    /// How long Sui object reads are cached, 0 disables
+    pub sui_object_cache_secs: u64,
+    /// Gas coins Sui transactions are spread over, each used by one transaction at a time
+    pub sui_gas_lanes: usize,
+    /// Balance of the gas coins split at boot, in MIST
+    pub sui_gas_lane_balance: u64,
+    /// Read Seal policy objects before running tasks, refusing ones the enclave cannot use
+    pub seal_policy_precheck: bool,
+
+    /// Ruby nodes configuration
+    pub ruby_nodes_api_key: ApiKey,
+
+    /// Walrus distributed storage configuration
+    pub walrus_aggregator_url: Url,
+    /// Aggregators blobs are read from in order when `walrus_aggregator_url` is unavailable
+    pub walrus_aggregator_fallback_urls: Vec<Url>,
+    pub walrus_publisher_url: Url,
+    pub walrus_epochs: u32,
+    /// Walrus system object on Sui, read for the current epoch
+    pub walrus_system_object_id: String,
+    /// Check ingested blobs on the aggregator before running tasks on them
+    pub walrus_blob_precheck: bool,
+    /// Largest blob accepted for ingest, unlimited when unset
+    pub walrus_max_ingest_blob_bytes: Option<u64>,
+
+    /// Embedding backend used by Node tasks and by the server itself
+    pub embedding_provider: ProviderKind,
+
+    /// Ollama embedding service configuration
+    pub ollama_api_url: Url,
+    pub ollama_model: String,
+
+    /// Azure OpenAI embedding configuration
+    pub azure_text_embedding_api_endpoint: Url,
+    pub azure_text_embedding_api_key: ApiKey,
+
+    /// Qdrant vector database configuration
+    pub qdrant_url: Url,
+    pub qdrant_api_key: Option<ApiKey>,
+    pub qdrant_collection_name: String,
+    /// Collections requests may target, the default collection included
+    pub qdrant_collections: Vec<String>,
+    /// Distance and HNSW parameters of collections created on first ingest
+    pub qdrant_collection_settings: CollectionSettings,
+    /// Search parameters of collections not tuned on `/admin/collections/:name/tune`
+    pub qdrant_search_params: SearchParams,
+
+    /// Epochs a vector outlives its expired source blob before it is reaped
+    pub vector_ttl_grace_epochs: u64,
+    /// Interval between reaper runs, 0 disables the reaper
+    pub vector_reaper_interval_secs: u64,
+    /// How long soft deleted vectors can be restored before the reaper purges them
+    pub vector_restore_window_secs: u64,
+    /// Projection and noise applied to stored vectors
+    pub vector_privacy: VectorPrivacy,
+    /// Optimizer wait and warm-up searches after large ingests
+    pub ingest_warmup: WarmupPolicy,
+
+    /// Finished jobs kept in memory
+    pub job_retention: RetentionPolicy,
+    /// Interval between job cleanups, 0 only cleans up on `POST /admin/retention`
+    pub job_cleanup_interval_secs: u64,
+    /// SQLite database persisting jobs, idempotency keys and audit logs, in memory only when unset
+    pub storage_path: Option<PathBuf>,
+    /// How long ingest idempotency keys are remembered, 0 ignores them
+    pub idempotency_ttl_secs: u64,
+    /// Interval between signing key rotations, 0 only rotates on `POST /admin/keys/rotate`
+    pub key_rotation_interval_secs: u64,
+    /// How long a rotated out signing key stays listed for verification
+    pub key_rotation_overlap_secs: u64,
+    /// Scheme signed responses use
+    pub signature_scheme: SignatureScheme,
+    /// How long an attestation without a challenge is reused and cached by clients
+    pub attestation_cache_secs: u64,
+    /// How long `/health_check` reuses endpoint probe results
+    pub health_check_cache_secs: u64,
+    /// Sui object listing the Seal policies operations may use, all allowed when unset
+    pub authorization_allowlist_object_id: Option<String>,
+    /// When the upstream circuit breakers open and for how long
+    pub breaker_policy: BreakerPolicy,
+    /// OTLP collector spans are exported to, with the `otel` feature
+    pub otel_exporter_otlp_endpoint: Option<String>,
+
+    /// Task processing configuration
+    pub embedding_batch_size: u32,
+    pub vector_batch_size: u32,
+
+    /// Node.js tasks running at once
+    pub max_concurrent_tasks: usize,
+    /// Queue wait that raises a task's priority by one level
+    pub priority_aging_secs: u64,
+    /// Requests waiting for a task slot before new ones get 429
+    pub max_queued_tasks: usize,
+    pub task_queue_timeout_secs: u64,
+    /// When a failed task is run again
+    pub task_retry: TaskRetryPolicy,
+    /// CPU affinity and nice value of task processes
+    pub task_scheduling: SchedulingHints,
+    /// Node.js flags, server-wide and per operation
+    pub task_node_flags: NodeFlagsByOperation,
+    /// Task directory of each operation, relative to the working directory
+    pub task_bundles: TaskBundles,
+    /// A/B retrieval parameter profiles
+    pub retrieval_profiles: Vec<RetrievalProfile>,
+    /// Warm Node.js workers, 0 spawns a process per task
+    pub task_worker_pool_size: usize,
+    pub task_worker_health_check_secs: u64,
+    pub task_worker_max_tasks: u64,
+    /// When task crashes mark the runtime unready and how long spawns back off
+    pub crash_loop_policy: CrashLoopPolicy,
+
+    /// Certification wait of blobs stored by the server
+    pub walrus_store: StoreOptions,
+    /// Limits on what the server may store
+    pub walrus_budget: StorageBudget,
+    /// Store a signed receipt of every task on Walrus
+    pub anchor_receipts: bool,
+
+    /// Requests kept for `/admin/requests`
+    pub request_log_size: usize,
+    /// Task invocations kept for `/audit/tasks`
+    pub task_audit_log_size: usize,
+
+    /// Signatures per minute by intent scope
+    pub signing_rate_limits: BTreeMap<IntentScope, u32>,
+    /// Ingest and retrieval requests per minute by address
+    pub address_rate_limits: BTreeMap<AddressOperation, u32>,
+    /// Requests per minute and burst per caller by route class
+    pub caller_rate_limits: BTreeMap<RouteClass, BucketLimit>,
+    pub caller_rate_limit_key: CallerKey,
+
+    /// Most verbose level logged
+    pub log_level: tracing::Level,
+    /// Encrypted crash reports and the log lines they keep
+    pub crash_report_dir: PathBuf,
+    pub crash_report_key: Option<ApiKey>,
+    pub crash_report_log_lines: usize,
+
+    /// Bearer token of `/admin` endpoints, which are disabled when unset
+    pub admin_token: Option<ApiKey>,
+
+    /// Social truth telegram bot configuration
+    pub telegram_social_truth_bot_id: String,
+
+    /// Salt for masking user and message IDs
+    pub id_mask_salt: ApiKey,
+
+    /// Master key the keys of encrypted stores are derived from when they have none of their own
+    pub internal_encryption_key: Option<InternalKey>,
+
+    /// Keys encrypting stored message text, unset stores no text
+    pub payload_keys: Option<PayloadKeyring>,
+    pub payload_key_source: KeySource,
+
+    /// Warm standby replication, off unless `REPLICATION_ROLE` is set
+    pub replication: Option<ReplicationConfig>,
+
+    /// Lease electing the instance that runs periodic jobs, off unless `LEADER_LEASE_SECS` is set
+    pub leader_lease: Option<LeaseConfig>,
+
+    /// Address, port and TLS of the HTTP listener
+    pub listen: ListenConfig,
+
+    /// Largest request body accepted, larger ones are refused with 413
+    pub max_request_body_bytes: usize,
+}
+
+/// Reads variables through a lookup function, collecting problems instead of stopping.
+struct EnvReader<'a> {
+    env: &'a dyn Fn(&str) -> Option<String>,
+    /// Substitute placeholders for missing required variables
+    relaxed: bool,
+    problems: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl EnvReader<'_> {
+    /// Value of `name`, falling back to its schema default. Missing required variables are
+    /// recorded as problems, or as warnings with a placeholder value when relaxed.
+    fn value(&mut self, name: &str) -> Option<String> {
+        let var = CONFIG_VARS.iter().find(|v| v.name == name);
+        let value = (self.env)(name)
+            .filter(|v| !v.is_empty())
+            .or_else(|| var.and_then(|v| v.default).map(str::to_string));
+        match var {
+            Some(var) if value.is_none() && var.required && self.relaxed => {
+                let placeholder = match var.kind {
+                    VarKind::Url => "http://localhost",
+                    VarKind::UnsignedInteger | VarKind::Integer => "1",
+                    _ => "dev",
+                };
+                self.warnings
+                    .push(format!("{} is not set, using placeholder {:?}", name, placeholder));
+                Some(placeholder.to_string())
+            }
+            Some(var) if value.is_none() && var.required => {
+                self.problems.push(format!("{} is required", name));
+                None
+            }
+            _ => value,
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let value = self.value(name)?;
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.problems.push(format!("{} is invalid: {}", name, e));
+                None
+            }
+        }
+    }
+
+    fn url(&mut self, name: &str) -> Option<Url> {
+        let url: Url = self.parse(name)?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            self.problems
+                .push(format!("{} is invalid: unsupported URL scheme {}", name, url.scheme()));
+            return None;
+        }
+        Some(url)
+    }
+
+    /// `true` or `false`, ignoring case.
+    fn boolean(&mut self, name: &str) -> Option<bool> {
+        let value = self.value(name)?;
+        match value.to_ascii_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => {
+                self.problems.push(format!("{} is invalid: expected true or false, got {:?}", name, value));
+                None
+            }
+        }
+    }
+
+    /// Comma separated URLs, skipping empty entries.
+    fn url_list(&mut self, name: &str) -> Vec<Url> {
+        let Some(list) = self.value(name) else {
+            return Vec::new();
+        };
+        let mut urls = Vec::new();
+        for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            match entry.parse::<Url>() {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => urls.push(url),
+                Ok(url) => self
+                    .problems
+                    .push(format!("{} is invalid: unsupported URL scheme {}", name, url.scheme())),
+                Err(e) => self.problems.push(format!("{} is invalid: {}", name, e)),
+            }
+        }
+        urls
+    }
+
+    fn api_key(&mut self, name: &str) -> Option<ApiKey> {
+        self.value(name).map(ApiKey)
+    }
+
+    /// Value of `name` read with a parser of its own, for lists and JSON.
+    fn parse_with<T, E: fmt::Display>(&mut self, name: &str, parse: impl FnOnce(&str) -> Result<T, E>) -> Option<T> {
+        let value = self.value(name)?;
+        match parse(&value) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                self.problems.push(format!("{} is invalid: {:#}", name, e));
+                None
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from the process environment.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(&|name| std::env::var(name).ok())
+    }
+
+    /// Load the configuration through `env`, reporting every problem at once.
+    pub fn from_lookup(env: &dyn Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        Self::load(env, false).map(|(config, _)| config)
+    }
+
+    /// Load the configuration for `--dev`: missing required variables get placeholders and
+    /// are returned as warnings. Invalid values are still errors.
+    pub fn from_lookup_relaxed(
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<(Self, Vec<String>), ConfigError> {
+        Self::load(env, true)
+    }
+
+    fn load(env: &dyn Fn(&str) -> Option<String>, relaxed: bool) -> Result<(Self, Vec<String>), ConfigError> {
+        let mut reader = EnvReader {
+            env,
+            relaxed,
+            problems: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let move_package_id = reader.value("MOVE_PACKAGE_ID");
+        let sui_secret_key = reader.api_key("SUI_SECRET_KEY");
+        let sui_rpc_url = reader.url("SUI_RPC_URL");
+        let sui_rpc_fallback_urls = reader.url_list("SUI_RPC_FALLBACK_URLS");
+        let sui_object_cache_secs = reader.parse("SUI_OBJECT_CACHE_SECS");
+        let sui_gas_lanes = reader.parse("SUI_GAS_LANES");
+        let sui_gas_lane_balance = reader.parse("SUI_GAS_LANE_BALANCE");
+        let seal_policy_precheck = reader.boolean("SEAL_POLICY_PRECHECK");
+        let ruby_nodes_api_key = reader.api_key("RUBY_NODES_API_KEY");
+        let walrus_aggregator_url = reader.url("WALRUS_AGGREGATOR_URL");
+        let walrus_aggregator_fallback_urls = reader.url_list("WALRUS_AGGREGATOR_FALLBACK_URLS");
+        let walrus_publisher_url = reader.url("WALRUS_PUBLISHER_URL");
+        let walrus_epochs = reader.parse("WALRUS_EPOCHS");
+        let walrus_system_object_id = reader.value("WALRUS_SYSTEM_OBJECT_ID");
+        let walrus_blob_precheck = reader.boolean("WALRUS_BLOB_PRECHECK");
+        let walrus_max_ingest_blob_bytes = reader.parse("WALRUS_MAX_INGEST_BLOB_BYTES");
+        let embedding_provider = reader.parse("EMBEDDING_PROVIDER");
+        let ollama_api_url = reader.url("OLLAMA_API_URL");
+        let ollama_model = reader.value("OLLAMA_MODEL");
+        let azure_text_embedding_api_endpoint = reader.url("AZURE_TEXT_EMBEDDING_API_ENDPOINT");
+        let azure_text_embedding_api_key = reader.api_key("AZURE_TEXT_EMBEDDING_API_KEY");
+        let qdrant_url = reader.url("QDRANT_URL");
+        let qdrant_api_key = reader.api_key("QDRANT_API_KEY");
+        let qdrant_collection_name = reader.value("QDRANT_COLLECTION_NAME");
+        let mut qdrant_collections: Vec<String> = reader
+            .value("QDRANT_COLLECTIONS")
+            .map(|list| list.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect())
+            .unwrap_or_default();
+        if let Some(default) = &qdrant_collection_name {
+            if !qdrant_collections.contains(default) {
+                qdrant_collections.insert(0, default.clone());
+            }
+        }
+        let qdrant_distance = reader.parse("QDRANT_DISTANCE");
+        let qdrant_hnsw_m = reader.parse("QDRANT_HNSW_M");
+        let qdrant_hnsw_ef_construct = reader.parse("QDRANT_HNSW_EF_CONSTRUCT");
+        let qdrant_search_hnsw_ef = reader.parse("QDRANT_SEARCH_HNSW_EF");
+        let embedding_dimensions = reader.parse::<u32>("EMBEDDING_DIMENSIONS").filter(|dimensions| {
+            if *dimensions == 0 {
+                reader.problems.push("EMBEDDING_DIMENSIONS must be positive".to_string());
+            }
+            *dimensions > 0
+        });
+        let vector_ttl_grace_epochs = reader.parse("VECTOR_TTL_GRACE_EPOCHS");
+        let vector_reaper_interval_secs = reader.parse("VECTOR_REAPER_INTERVAL_SECS");
+        let vector_restore_window_secs = reader.parse("VECTOR_RESTORE_WINDOW_SECS");
+        let ingest_warmup_min_points = reader.parse::<u64>("INGEST_WARMUP_MIN_POINTS");
+        let ingest_warmup_timeout_secs = reader.parse("INGEST_WARMUP_TIMEOUT_SECS");
+        let ingest_warmup_queries = reader.parse("INGEST_WARMUP_QUERIES");
+        let job_retention_secs = reader.parse("JOB_RETENTION_SECS");
+        let job_retention_max_count = reader.parse::<usize>("JOB_RETENTION_MAX_COUNT");
+        let job_retention_max_bytes = reader.parse::<u64>("JOB_RETENTION_MAX_BYTES");
+        let job_cleanup_interval_secs = reader.parse("JOB_CLEANUP_INTERVAL_SECS");
+        let storage_path = reader.value("STORAGE_PATH").map(PathBuf::from);
+        let idempotency_ttl_secs = reader.parse("IDEMPOTENCY_TTL_SECS");
+        let key_rotation_interval_secs = reader.parse("KEY_ROTATION_INTERVAL_SECS");
+        let key_rotation_overlap_secs = reader.parse("KEY_ROTATION_OVERLAP_SECS");
+        let signature_scheme = reader.parse("SIGNATURE_SCHEME");
+        let attestation_cache_secs = reader.parse("ATTESTATION_CACHE_SECS");
+        let health_check_cache_secs = reader.parse("HEALTH_CHECK_CACHE_SECS");
+        let authorization_allowlist_object_id = reader.value("AUTHORIZATION_ALLOWLIST_OBJECT_ID");
+        if let Some(object_id) = &authorization_allowlist_object_id {
+            if crate::sui::parse_address(object_id).is_err() {
+                reader
+                    .problems
+                    .push(format!("AUTHORIZATION_ALLOWLIST_OBJECT_ID is not a Sui object ID: {}", object_id));
+            }
+        }
+        let circuit_breaker_failures = reader.parse("CIRCUIT_BREAKER_FAILURES");
+        let circuit_breaker_open_secs = reader.parse("CIRCUIT_BREAKER_OPEN_SECS");
+        let otel_exporter_otlp_endpoint = reader.value("OTEL_EXPORTER_OTLP_ENDPOINT");
+        let vector_projection_dimensions = reader.parse("VECTOR_PROJECTION_DIMENSIONS").filter(|d| *d > 0);
+        let vector_projection_seed = reader.api_key("VECTOR_PROJECTION_SEED");
+        if vector_projection_dimensions.is_some() && vector_projection_seed.is_none() {
+            reader
+                .problems
+                .push("VECTOR_PROJECTION_SEED is required with VECTOR_PROJECTION_DIMENSIONS".to_string());
+        }
+        // Projection applies to the truncated embedding, so it has to reduce it further
+        if let (Some(projection), Some(embedding)) = (vector_projection_dimensions, embedding_dimensions) {
+            if projection >= embedding {
+                reader
+                    .problems
+                    .push("VECTOR_PROJECTION_DIMENSIONS must be below EMBEDDING_DIMENSIONS".to_string());
+            }
+        }
+        let vector_noise_scale = reader.parse::<f64>("VECTOR_NOISE_SCALE").filter(|scale| {
+            let valid = scale.is_finite() && *scale >= 0.0;
+            if !valid {
+                reader
+                    .problems
+                    .push("VECTOR_NOISE_SCALE is invalid: must be a non-negative number".to_string());
+            }
+            valid
+        });
+        let embedding_batch_size = reader.parse("EMBEDDING_BATCH_SIZE");
+        let vector_batch_size = reader.parse("VECTOR_BATCH_SIZE");
+        let max_concurrent_tasks = reader.parse("MAX_CONCURRENT_TASKS");
+        let priority_aging_secs = reader.parse("PRIORITY_AGING_SECS");
+        let max_queued_tasks = reader.parse("MAX_QUEUED_TASKS");
+        let task_queue_timeout_secs = reader.parse("TASK_QUEUE_TIMEOUT_SECS");
+        let task_retry_max_attempts = reader.parse::<u32>("TASK_RETRY_MAX_ATTEMPTS");
+        let task_retry_backoff_ms = reader.parse("TASK_RETRY_BACKOFF_MS");
+        let task_retry_exit_codes = reader.parse_with("TASK_RETRY_EXIT_CODES", TaskRetryPolicy::parse_exit_codes);
+        let task_retry_error_classes: Vec<String> = reader
+            .value("TASK_RETRY_ERROR_CLASSES")
+            .map(|list| list.split(',').map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default();
+        let task_cpu_affinity = reader.parse_with("TASK_CPU_AFFINITY", SchedulingHints::parse_cpu_list);
+        let task_nice = reader.parse::<i32>("TASK_NICE");
+        // TASK_NODE_OPTIONS applies to every operation, TASK_NODE_OPTIONS_<OPERATION> overrides it
+        let mut task_node_flags = NodeFlagsByOperation {
+            default: reader.parse_with("TASK_NODE_OPTIONS", NodeFlags::parse).unwrap_or_default(),
+            ..Default::default()
+        };
+        for operation in [
+            "process_data",
+            "embedding_ingest",
+            "retrieve_messages_by_blob_ids",
+            "retrieve_messages_filtered",
+        ] {
+            let name = format!("TASK_NODE_OPTIONS_{}", operation.to_uppercase());
+            if let Some(flags) = reader.parse_with(&name, NodeFlags::parse) {
+                task_node_flags.operations.insert(operation.to_string(), flags);
+            }
+        }
+        // Bundle paths are resolved against the working directory, like nodejs-task
+        let task_bundles = match std::env::current_dir() {
+            Ok(root) => Some(
+                reader
+                    .parse_with("TASK_BUNDLES", |json| TaskBundles::from_json(&root, json))
+                    .unwrap_or_else(|| TaskBundles::single(&root)),
+            ),
+            Err(e) => {
+                reader.problems.push(format!("Cannot resolve task bundles: {}", e));
+                None
+            }
+        };
+        let retrieval_profiles = reader
+            .parse_with("RETRIEVAL_PROFILES", RetrievalExperiments::from_json)
+            .map(|experiments| experiments.profiles().to_vec())
+            .unwrap_or_default();
+        let task_worker_pool_size = reader.parse("TASK_WORKER_POOL_SIZE");
+        let task_worker_health_check_secs = reader.parse("TASK_WORKER_HEALTH_CHECK_SECS");
+        let task_worker_max_tasks = reader.parse("TASK_WORKER_MAX_TASKS");
+        let crash_loop_threshold = reader.parse("TASK_CRASH_LOOP_THRESHOLD");
+        let crash_loop_window_secs = reader.parse("TASK_CRASH_LOOP_WINDOW_SECS");
+        let crash_backoff_max_secs = reader.parse("TASK_CRASH_BACKOFF_MAX_SECS");
+        let walrus_wait_for_certification = reader.boolean("WALRUS_WAIT_FOR_CERTIFICATION");
+        let walrus_certification_timeout_secs = reader.parse("WALRUS_CERTIFICATION_TIMEOUT_SECS");
+        let walrus_max_epochs = reader.parse("WALRUS_MAX_EPOCHS");
+        let walrus_max_store_byte_epochs = reader.parse("WALRUS_MAX_STORE_BYTE_EPOCHS");
+        let anchor_receipts = reader.boolean("ANCHOR_RECEIPTS");
+        let request_log_size = reader.parse("REQUEST_LOG_SIZE");
+        let task_audit_log_size = reader.parse("TASK_AUDIT_LOG_SIZE");
+        let signing_rate_limits = reader
+            .parse_with("SIGNING_RATE_LIMITS", |limits| {
+                KeyUsage::parse_limits(limits).map_err(|e| e.status_and_message().1)
+            })
+            .unwrap_or_default();
+        let address_rate_limits = reader
+            .parse_with("ADDRESS_RATE_LIMITS", |limits| {
+                AddressLimits::parse_limits(limits).map_err(|e| e.status_and_message().1)
+            })
+            .unwrap_or_default();
+        let caller_rate_limits = reader
+            .parse_with("CALLER_RATE_LIMITS", |limits| {
+                CallerLimits::parse_limits(limits).map_err(|e| e.status_and_message().1)
+            })
+            .unwrap_or_default();
+        let caller_rate_limit_key = reader.parse("CALLER_RATE_LIMIT_KEY");
+        // `--dev` logs at debug unless LOG_LEVEL says otherwise
+        let log_level = match (env)("LOG_LEVEL").filter(|level| !level.is_empty()) {
+            None if relaxed => Some(tracing::Level::DEBUG),
+            _ => reader.parse("LOG_LEVEL"),
+        };
+        let crash_report_dir = reader.value("CRASH_REPORT_DIR").map(PathBuf::from);
+        let crash_report_key = reader.parse_with("CRASH_REPORT_KEY", |key| {
+            crate::crash_reports::parse_hex_key(key)
+                .map(|_| ApiKey::new(key))
+                .map_err(|e| e.status_and_message().1)
+        });
+        let crash_report_log_lines = reader.parse("CRASH_REPORT_LOG_LINES");
+        let admin_token = reader.api_key("ADMIN_TOKEN");
+        let telegram_social_truth_bot_id = reader.value("TELEGRAM_SOCIAL_TRUTH_BOT_ID");
+        let id_mask_salt = reader.api_key("ID_MASK_SALT");
+        let internal_encryption_key = reader.value("INTERNAL_ENCRYPTION_SECRET_KEY").and_then(|key| {
+            InternalKey::from_hex(&key)
+                .map_err(|e| reader.problems.push(format!("INTERNAL_ENCRYPTION_SECRET_KEY is invalid: {}", e.status_and_message().1)))
+                .ok()
+        });
+        // Without a key of its own, stored text is encrypted under the internal key. Text stored
+        // that way still decrypts once a dedicated key is set.
+        let internal_payload_key = internal_encryption_key
+            .as_ref()
+            .map(|key| key.derive_hex(KeyPurpose::PayloadEncryption));
+        let (payload_master, payload_key_source) = match (reader.value("PAYLOAD_ENCRYPTION_KEY"), &internal_payload_key) {
+            (Some(active), _) => (Some(active), KeySource::Dedicated),
+            (None, Some(internal)) => (Some(internal.clone()), KeySource::Internal),
+            (None, None) => (None, KeySource::Unset),
+        };
+        let payload_keys = payload_master.and_then(|active| {
+            let mut previous = reader.value("PAYLOAD_ENCRYPTION_PREVIOUS_KEYS").unwrap_or_default();
+            if let (KeySource::Dedicated, Some(internal)) = (payload_key_source, &internal_payload_key) {
+                previous = format!("{},{}", previous, internal);
+            }
+            PayloadKeyring::from_hex(&active, &previous)
+                .map_err(|e| reader.problems.push(format!("PAYLOAD_ENCRYPTION_KEY is invalid: {}", e.status_and_message().1)))
+                .ok()
+        });
+        let replication_role = reader.parse::<ReplicationRole>("REPLICATION_ROLE");
+        let replication_peer_url = reader.url("REPLICATION_PEER_URL");
+        let replication_interval_secs = reader.parse::<u64>("REPLICATION_INTERVAL_SECS");
+        if replication_role.is_some() && replication_peer_url.is_none() {
+            reader
+                .problems
+                .push("REPLICATION_PEER_URL is required with REPLICATION_ROLE".to_string());
+        }
+        let leader_lease_secs = reader.parse::<u64>("LEADER_LEASE_SECS").filter(|secs| *secs > 0);
+        let leader_lease_collection = reader.value("LEADER_LEASE_COLLECTION");
+        let max_request_body_bytes = reader.parse("MAX_REQUEST_BODY_BYTES");
+        let bind_addr = reader.parse("BIND_ADDR");
+        let port = reader.parse("PORT");
+        let tls = match reader.parse("TLS_MODE") {
+            Some(TlsMode::Files) => match (reader.value("TLS_CERT_PATH"), reader.value("TLS_KEY_PATH")) {
+                (Some(cert_path), Some(key_path)) => Some(TlsConfig::Files {
+                    cert_path: cert_path.into(),
+                    key_path: key_path.into(),
+                }),
+                _ => {
+                    reader
+                        .problems
+                        .push("TLS_CERT_PATH and TLS_KEY_PATH are required with TLS_MODE files".to_string());
+                    None
+                }
+            },
+            Some(TlsMode::SelfSigned) => Some(TlsConfig::SelfSigned),
+            Some(TlsMode::Off) | None => None,
+        };
+
+        if !reader.problems.is_empty() {
+            return Err(ConfigError {
+                problems: reader.problems,
+            });
+        }
+        // Required and defaulted values are all present once no problem was recorded
+        let config = Config {
+            move_package_id: move_package_id.unwrap(),
+            sui_secret_key: sui_secret_key.unwrap(),
+            sui_rpc_url: sui_rpc_url.unwrap(),
+            sui_rpc_fallback_urls,
+            sui_object_cache_secs: sui_object_cache_secs.unwrap(),
+            sui_gas_lanes: sui_gas_lanes.unwrap(),
+            sui_gas_lane_balance: sui_gas_lane_balance.unwrap(),
+            seal_policy_precheck: seal_policy_precheck.unwrap(),
+            ruby_nodes_api_key: ruby_nodes_api_key.unwrap(),
+            walrus_aggregator_url: walrus_aggregator_url.unwrap(),
+            walrus_aggregator_fallback_urls,
+            walrus_publisher_url: walrus_publisher_url.unwrap(),
+            walrus_epochs: walrus_epochs.unwrap(),
+            walrus_system_object_id: walrus_system_object_id.unwrap(),
+            walrus_blob_precheck: walrus_blob_precheck.unwrap(),
+            walrus_max_ingest_blob_bytes,
+            embedding_provider: embedding_provider.unwrap(),
+            ollama_api_url: ollama_api_url.unwrap(),
+            ollama_model: ollama_model.unwrap(),
+            azure_text_embedding_api_endpoint: azure_text_embedding_api_endpoint.unwrap(),
+            azure_text_embedding_api_key: azure_text_embedding_api_key.unwrap(),
+            qdrant_url: qdrant_url.unwrap(),
+            qdrant_api_key,
+            qdrant_collection_name: qdrant_collection_name.unwrap(),
+            qdrant_collections,
+            qdrant_collection_settings: CollectionSettings {
+                distance: qdrant_distance.unwrap(),
+                hnsw_m: qdrant_hnsw_m,
+                hnsw_ef_construct: qdrant_hnsw_ef_construct,
+                embedding_dimensions,
+            },
+            qdrant_search_params: SearchParams {
+                hnsw_ef: qdrant_search_hnsw_ef,
+                exact: None,
+            },
+            vector_ttl_grace_epochs: vector_ttl_grace_epochs.unwrap(),
+            vector_reaper_interval_secs: vector_reaper_interval_secs.unwrap(),
+            vector_restore_window_secs: vector_restore_window_secs.unwrap(),
+            ingest_warmup: WarmupPolicy {
+                min_points: ingest_warmup_min_points.filter(|points| *points > 0),
+                timeout_secs: ingest_warmup_timeout_secs.unwrap(),
+                queries: ingest_warmup_queries.unwrap(),
+            },
+            job_retention: RetentionPolicy {
+                max_age_secs: job_retention_secs.unwrap(),
+                max_count: job_retention_max_count.filter(|count| *count > 0),
+                max_bytes: job_retention_max_bytes.filter(|bytes| *bytes > 0),
+            },
+            job_cleanup_interval_secs: job_cleanup_interval_secs.unwrap(),
+            storage_path,
+            idempotency_ttl_secs: idempotency_ttl_secs.unwrap(),
+            key_rotation_interval_secs: key_rotation_interval_secs.unwrap(),
+            key_rotation_overlap_secs: key_rotation_overlap_secs.unwrap(),
+            signature_scheme: signature_scheme.unwrap(),
+            attestation_cache_secs: attestation_cache_secs.unwrap(),
+            health_check_cache_secs: health_check_cache_secs.unwrap(),
+            authorization_allowlist_object_id,
+            breaker_policy: BreakerPolicy {
+                failure_threshold: circuit_breaker_failures.unwrap(),
+                open_secs: circuit_breaker_open_secs.unwrap(),
+            },
+            otel_exporter_otlp_endpoint,
+            vector_privacy: VectorPrivacy {
+                projection_dimensions: vector_projection_dimensions,
+                projection_seed: vector_projection_seed,
+                noise_scale: vector_noise_scale.unwrap(),
+            },
+            embedding_batch_size: embedding_batch_size.unwrap(),
+            vector_batch_size: vector_batch_size.unwrap(),
+            max_concurrent_tasks: max_concurrent_tasks.unwrap(),
+            priority_aging_secs: priority_aging_secs.unwrap(),
+            max_queued_tasks: max_queued_tasks.unwrap(),
+            task_queue_timeout_secs: task_queue_timeout_secs.unwrap(),
+            task_retry: TaskRetryPolicy {
+                max_attempts: task_retry_max_attempts.unwrap().max(1),
+                initial_backoff_ms: task_retry_backoff_ms.unwrap(),
+                retry_exit_codes: task_retry_exit_codes.unwrap_or_default(),
+                retry_error_classes: task_retry_error_classes,
+            },
+            task_scheduling: SchedulingHints {
+                cpu_affinity: task_cpu_affinity,
+                nice: task_nice,
+            },
+            task_node_flags,
+            task_bundles: task_bundles.unwrap(),
+            retrieval_profiles,
+            task_worker_pool_size: task_worker_pool_size.unwrap(),
+            task_worker_health_check_secs: task_worker_health_check_secs.unwrap(),
+            task_worker_max_tasks: task_worker_max_tasks.unwrap(),
+            crash_loop_policy: CrashLoopPolicy {
+                threshold: crash_loop_threshold.unwrap(),
+                window: Duration::from_secs(crash_loop_window_secs.unwrap()),
+                max_backoff: Duration::from_secs(crash_backoff_max_secs.unwrap()),
+                ..Default::default()
+            },
+            walrus_store: StoreOptions {
+                wait_for_certification: walrus_wait_for_certification.unwrap(),
+                certification_timeout: Duration::from_secs(walrus_certification_timeout_secs.unwrap()),
+                ..Default::default()
+            },
+            walrus_budget: StorageBudget {
+                max_epochs: walrus_max_epochs.unwrap(),
+                max_byte_epochs: walrus_max_store_byte_epochs,
+            },
+            anchor_receipts: anchor_receipts.unwrap(),
+            request_log_size: request_log_size.unwrap(),
+            task_audit_log_size: task_audit_log_size.unwrap(),
+            signing_rate_limits,
+            address_rate_limits,
+            caller_rate_limits,
+            caller_rate_limit_key: caller_rate_limit_key.unwrap(),
+            log_level: log_level.unwrap(),
+            crash_report_dir: crash_report_dir.unwrap(),
+            crash_report_key,
+            crash_report_log_lines: crash_report_log_lines.unwrap(),
+            admin_token,
+            telegram_social_truth_bot_id: telegram_social_truth_bot_id.unwrap(),
+            id_mask_salt: id_mask_salt.unwrap(),
+            internal_encryption_key,
+            payload_keys,
+            payload_key_source,
+            replication: replication_role.map(|role| ReplicationConfig {
+                role,
+                peer_url: replication_peer_url.unwrap(),
+                interval: Duration::from_secs(replication_interval_secs.unwrap().max(1)),
+            }),
+            leader_lease: leader_lease_secs.map(|secs| LeaseConfig {
+                collection: leader_lease_collection.unwrap(),
+                ttl: Duration::from_secs(secs),
+            }),
+            listen: ListenConfig {
+                bind_addr: bind_addr.unwrap(),
+                port: port.unwrap(),
+                tls,
+            },
+            max_request_body_bytes: max_request_body_bytes.unwrap(),
+        };
+        Ok((config, reader.warnings))
+    }
+}
+
+/// URL as given to services and Node.js tasks, without the trailing slash `Url` adds to
+/// bare hosts so paths can be appended.
+pub fn url_str(url: &Url) -> &str {
+    url.as_str().trim_end_matches('/')
+}
+
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    let env = std::collections::HashMap::from([
+        ("MOVE_PACKAGE_ID", "0x1234567890abcdef"),
+        ("SUI_SECRET_KEY", "suiprivkey1qtest"),
+        ("RUBY_NODES_API_KEY", "ABC123"),
+        ("WALRUS_AGGREGATOR_URL", "https://aggregator.walrus-testnet.walrus.space"),
+        ("WALRUS_PUBLISHER_URL", "https://publisher.walrus-testnet.walrus.space"),
+        ("WALRUS_EPOCHS", "5"),
+        ("AZURE_TEXT_EMBEDDING_API_ENDPOINT", "https://example.com"),
+        ("AZURE_TEXT_EMBEDDING_API_KEY", "test-key"),
+        ("TELEGRAM_SOCIAL_TRUTH_BOT_ID", "123456789"),
+        ("ID_MASK_SALT", "test-salt"),
+    ]);
+    Config::from_lookup(&|name| env.get(name).map(|v| v.to_string())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_defaults_and_types() {
+        let config = test_config();
+        assert_eq!(config.walrus_epochs, 5);
+        assert_eq!(config.embedding_batch_size, 10);
+        assert_eq!(config.vector_batch_size, 100);
+        assert_eq!(url_str(&config.qdrant_url), "http://localhost:6333");
+        assert_eq!(url_str(&config.walrus_aggregator_url), "https://aggregator.walrus-testnet.walrus.space");
+        assert!(config.walrus_aggregator_fallback_urls.is_empty());
+        assert!(config.qdrant_api_key.is_none());
+        assert_eq!(config.qdrant_collections, vec!["messages"]);
+        assert_eq!(config.qdrant_collection_settings, CollectionSettings::default());
+        assert!(!format!("{:?}", config).contains("test-key"));
+        assert_eq!(config.listen, ListenConfig::default());
+        assert_eq!(config.max_request_body_bytes, crate::validation::DEFAULT_MAX_REQUEST_BODY_BYTES);
+        assert_eq!(config.job_retention, crate::retention::RetentionPolicy::default());
+        assert_eq!(config.ingest_warmup, WarmupPolicy::default());
+        assert_eq!(config.storage_path, None);
+        assert_eq!(config.breaker_policy, crate::breakers::BreakerPolicy::default());
+        assert_eq!(config.signature_scheme, SignatureScheme::Ed25519);
+        assert_eq!(config.attestation_cache_secs, crate::common::DEFAULT_ATTESTATION_CACHE_SECS);
+        assert_eq!(config.health_check_cache_secs, crate::endpoints::DEFAULT_HEALTH_CHECK_CACHE_SECS);
+        assert!(config.seal_policy_precheck);
+        assert!(config.walrus_blob_precheck);
+        assert_eq!(config.walrus_max_ingest_blob_bytes, None);
+        assert_eq!(config.authorization_allowlist_object_id, None);
+        assert_eq!(config.otel_exporter_otlp_endpoint, None);
+        assert_eq!(config.max_concurrent_tasks, crate::scheduler::DEFAULT_MAX_CONCURRENT_TASKS);
+        assert_eq!(config.task_retry, crate::task_runner::TaskRetryPolicy::default());
+        assert_eq!(config.task_scheduling, SchedulingHints::default());
+        assert_eq!(config.task_node_flags, NodeFlagsByOperation::default());
+        assert!(config.task_bundles.configured().is_empty());
+        assert!(config.retrieval_profiles.is_empty());
+        assert!(config.signing_rate_limits.is_empty());
+        assert_eq!(config.caller_rate_limit_key, CallerKey::Ip);
+        assert_eq!(config.log_level, tracing::Level::INFO);
+        assert_eq!(config.crash_report_dir, PathBuf::from(crate::crash_reports::DEFAULT_CRASH_REPORT_DIR));
+        assert_eq!(config.crash_report_log_lines, crate::logging::DEFAULT_LOG_BUFFER_LINES);
+        assert!(config.admin_token.is_none());
+        assert_eq!(config.task_worker_pool_size, 0);
+        assert_eq!(config.crash_loop_policy, CrashLoopPolicy::default());
+        assert!(!config.walrus_store.wait_for_certification);
+        assert_eq!(config.walrus_budget.max_epochs, crate::walrus::DEFAULT_MAX_EPOCHS);
+        assert!(!config.anchor_receipts);
+        assert_eq!(config.request_log_size, crate::request_log::DEFAULT_REQUEST_LOG_SIZE);
+    }
+
+    #[test]
+    fn test_invalid_task_settings_fail() {
+        let env = HashMap::from([
+            ("MAX_CONCURRENT_TASKS", "four"),
+            ("TASK_WORKER_POOL_SIZE", "-1"),
+            ("WALRUS_WAIT_FOR_CERTIFICATION", "yes"),
+            ("REQUEST_LOG_SIZE", "1e3"),
+        ]);
+        let err = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+        assert_eq!(err.problems.len(), 4, "{:?}", err.problems);
+        assert!(err.problems[0].starts_with("MAX_CONCURRENT_TASKS is invalid"), "{:?}", err.problems);
+    }
+
+    #[test]
+    fn test_task_limit_and_log_settings() {
+        let env = HashMap::from([
+            ("TASK_CPU_AFFINITY", "1-2"),
+            ("TASK_NICE", "5"),
+            ("TASK_NODE_OPTIONS_PROCESS_DATA", "--max-old-space-size=512"),
+            ("TASK_RETRY_EXIT_CODES", "75, 137"),
+            ("SIGNING_RATE_LIMITS", "process_data=120"),
+            ("CALLER_RATE_LIMIT_KEY", "api_key"),
+            ("ADMIN_TOKEN", "secret"),
+        ]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.task_scheduling.cpu_affinity, Some(vec![1, 2]));
+        assert_eq!(config.task_scheduling.nice, Some(5));
+        assert_eq!(config.task_node_flags.for_operation("process_data").max_old_space_size_mb, Some(512));
+        assert_eq!(config.task_retry.retry_exit_codes, vec![75, 137]);
+        assert_eq!(config.signing_rate_limits.get(&IntentScope::ProcessData), Some(&120));
+        assert_eq!(config.caller_rate_limit_key, CallerKey::ApiKey);
+        assert_eq!(config.admin_token.as_ref().map(ApiKey::expose), Some("secret"));
+        // `--dev` logs at debug unless LOG_LEVEL is set
+        assert_eq!(config.log_level, tracing::Level::DEBUG);
+
+        let env = HashMap::from([
+            ("TASK_CPU_AFFINITY", "3-1"),
+            ("TASK_NICE", "low"),
+            ("TASK_NODE_OPTIONS", "max-old-space-size=512"),
+            ("TASK_NODE_OPTIONS_EMBEDDING_INGEST", "--stack-size=big"),
+            ("TASK_RETRY_EXIT_CODES", "75,x"),
+            ("TASK_BUNDLES", "[{"),
+            ("RETRIEVAL_PROFILES", "{}"),
+            ("SIGNING_RATE_LIMITS", "process_data"),
+            ("ADDRESS_RATE_LIMITS", "ingest=many"),
+            ("CALLER_RATE_LIMITS", "task=often"),
+            ("CALLER_RATE_LIMIT_KEY", "cookie"),
+            ("CRASH_REPORT_KEY", "1234"),
+            ("CRASH_REPORT_LOG_LINES", "-5"),
+            ("LOG_LEVEL", "loud"),
+        ]);
+        let err = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+        assert_eq!(err.problems.len(), env.len(), "{:?}", err.problems);
+        for name in env.keys() {
+            assert!(err.problems.iter().any(|p| p.starts_with(&format!("{} is invalid", name))), "{} not reported", name);
+        }
+    }
+
+    #[test]
+    fn test_listen_config() {
+        let env = HashMap::from([("BIND_ADDR", "::1"), ("PORT", "8443"), ("TLS_MODE", "self_signed")]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.listen.socket_addr().to_string(), "[::1]:8443");
+        assert_eq!(config.listen.tls, Some(TlsConfig::SelfSigned));
+
+        let env = HashMap::from([("PORT", "70000"), ("TLS_MODE", "files"), ("TLS_CERT_PATH", "cert.pem")]);
+        let err = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{:?}", err.problems);
+    }
+
+    #[test]
+    fn test_collection_allowlist_includes_default() {
+        let env = HashMap::from([("QDRANT_COLLECTION_NAME", "telegram"), ("QDRANT_COLLECTIONS", "documents, ,telegram")]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.qdrant_collections, vec!["documents", "telegram"]);
+    }
+
+    #[test]
+    fn test_sui_fallback_urls() {
+        let env = HashMap::from([("SUI_RPC_FALLBACK_URLS", "https://a.example, ,http://b.example:9000")]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        let urls: Vec<_> = config.sui_rpc_fallback_urls.iter().map(url_str).collect();
+        assert_eq!(urls, ["https://a.example", "http://b.example:9000"]);
+
+        let env = HashMap::from([("SUI_RPC_FALLBACK_URLS", "https://a.example,ftp://b.example")]);
+        let err = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+        assert!(err.problems[0].contains("SUI_RPC_FALLBACK_URLS"), "{:?}", err.problems);
+    }
+
+    #[test]
+    fn test_embedding_dimensions() {
+        let env = HashMap::from([("EMBEDDING_DIMENSIONS", "256")]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.qdrant_collection_settings.embedding_dimensions, Some(256));
+
+        for env in [
+            HashMap::from([("EMBEDDING_DIMENSIONS", "0")]),
+            HashMap::from([
+                ("EMBEDDING_DIMENSIONS", "256"),
+                ("VECTOR_PROJECTION_DIMENSIONS", "256"),
+                ("VECTOR_PROJECTION_SEED", "11"),
+            ]),
+        ] {
+            let err = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+            assert!(err.problems[0].contains("EMBEDDING_DIMENSIONS"), "{:?}", err.problems);
+        }
+    }
+
+    #[test]
+    fn test_reports_all_problems() {
+        let env = HashMap::from([
+            ("WALRUS_EPOCHS", "five"),
+            ("QDRANT_URL", "ftp://qdrant"),
+            ("VECTOR_BATCH_SIZE", "-1"),
+            ("REPLICATION_ROLE", "standby"),
+        ]);
+        let err = Config::from_lookup(&|name| env.get(name).map(|v| v.to_string())).unwrap_err();
+        let problems = err.problems.join("\n");
+        for name in ["MOVE_PACKAGE_ID", "SUI_SECRET_KEY", "ID_MASK_SALT", "WALRUS_EPOCHS", "QDRANT_URL", "VECTOR_BATCH_SIZE", "REPLICATION_PEER_URL"] {
+            assert!(problems.contains(name), "{} not reported in\n{}", name, problems);
+        }
+        assert_eq!(err.problems.len(), 13);
+    }
+
+    #[test]
+    fn test_payload_keys_from_internal_key() {
+        let internal = "11".repeat(32);
+        let dedicated = "22".repeat(32);
+        let config = test_config();
+        assert!(config.internal_encryption_key.is_none());
+        assert!(config.payload_keys.is_none());
+        assert_eq!(config.payload_key_source, KeySource::Unset);
+
+        let env = HashMap::from([("INTERNAL_ENCRYPTION_SECRET_KEY", internal.as_str())]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.payload_key_source, KeySource::Internal);
+        let internal_keys = config.payload_keys.unwrap();
+        let stored = internal_keys.active().encrypt("hello", "1");
+
+        // Text stored under the internal key still decrypts after moving to a dedicated key
+        let env = HashMap::from([
+            ("INTERNAL_ENCRYPTION_SECRET_KEY", internal.as_str()),
+            ("PAYLOAD_ENCRYPTION_KEY", dedicated.as_str()),
+        ]);
+        let (config, _) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.payload_key_source, KeySource::Dedicated);
+        let keys = config.payload_keys.unwrap();
+        assert_ne!(keys.active().kid(), internal_keys.active().kid());
+        assert_eq!(keys.decrypt(&stored, "1").unwrap(), "hello");
+        assert!(!format!("{:?}", config.internal_encryption_key).contains(&internal));
+
+        let invalid = HashMap::from([("INTERNAL_ENCRYPTION_SECRET_KEY", "1234")]);
+        assert!(Config::from_lookup_relaxed(&|name| invalid.get(name).map(|v| v.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_relaxed_fills_missing_required() {
+        let env = HashMap::from([("WALRUS_EPOCHS", "3")]);
+        let (config, warnings) = Config::from_lookup_relaxed(&|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.walrus_epochs, 3);
+        assert_eq!(url_str(&config.walrus_publisher_url), "http://localhost");
+        assert_eq!(warnings.len(), 9);
+
+        let invalid = HashMap::from([("QDRANT_URL", "not a url")]);
+        assert!(Config::from_lookup_relaxed(&|name| invalid.get(name).map(|v| v.to_string())).is_err());
+    }
+}
@@ -0,0 +1,619 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configuration schema and the `--check-config` run that lets deployment pipelines
+//! validate secrets before launching the enclave. [CONFIG_VARS] lists every environment
+//! variable the server reads and is printed by `--config-schema`.
+
+use crate::collections::Distance;
+use crate::embeddings::ProviderKind;
+use crate::dependency_allowlist::{check_dependencies, DependencyStatus, DEFAULT_ALLOWLIST_FILE};
+use crate::experiments::RetrievalExperiments;
+use crate::address_limits::AddressLimits;
+use crate::caller_limits::{CallerKey, CallerLimits};
+use crate::key_usage::KeyUsage;
+use crate::leader::DEFAULT_LEASE_COLLECTION;
+use crate::key_manager::SignatureScheme;
+use crate::listener::TlsMode;
+use crate::replication::ReplicationRole;
+use crate::task_bundles::TaskBundles;
+use crate::task_runner::{NodeFlags, SchedulingHints, TaskRetryPolicy};
+use crate::walrus::{StorageBudget, DEFAULT_MAX_EPOCHS};
+use fastcrypto::encoding::{Encoding, Hex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Value type of a variable, deciding how it is validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarKind {
+    Text,
+    /// http or https URL
+    Url,
+    UnsignedInteger,
+    Integer,
+    /// `true` or `false`, case insensitive
+    Boolean,
+    /// CPU list such as `1-3,5`
+    CpuList,
+    /// Node.js heap and stack flags
+    NodeOptions,
+    /// JSON array of retrieval profiles
+    RetrievalProfiles,
+    /// JSON array of task bundles
+    TaskBundles,
+    /// Non-negative decimal number
+    Decimal,
+    /// Comma separated `scope=signatures_per_minute`
+    SigningRateLimits,
+    /// Comma separated `operation=requests_per_minute`
+    AddressRateLimits,
+    /// Comma separated `class=requests_per_minute[:burst]`
+    CallerRateLimits,
+    /// `ip`, `forwarded_for` or `api_key`
+    CallerKey,
+    /// Hex encoded 32 bytes
+    HexKey,
+    /// Comma separated list of hex encoded 32 byte keys
+    HexKeyList,
+    /// Comma separated list of http or https URLs
+    UrlList,
+    /// Comma separated process exit codes
+    ExitCodes,
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    LogLevel,
+    /// Qdrant distance: `Cosine`, `Dot`, `Euclid` or `Manhattan`
+    Distance,
+    /// `ollama` or `azure`
+    EmbeddingProvider,
+    /// `primary` or `standby`
+    ReplicationRole,
+    /// IPv4 or IPv6 address
+    IpAddress,
+    /// `off`, `files` or `self_signed`
+    TlsMode,
+    /// `ed25519` or `secp256k1`
+    SignatureScheme,
+}
+
+/// Environment variable read by the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigVar {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub kind: VarKind,
+    pub required: bool,
+    /// Never printed, only reported as configured or not
+    pub secret: bool,
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+const fn required(name: &'static str, kind: VarKind, secret: bool, description: &'static str) -> ConfigVar {
+    ConfigVar {
+        name,
+        kind,
+        required: true,
+        secret,
+        default: None,
+        description,
+    }
+}
+
+const fn optional(
+    name: &'static str,
+    kind: VarKind,
+    default: Option<&'static str>,
+    description: &'static str,
+) -> ConfigVar {
+    ConfigVar {
+        name,
+        kind,
+        required: false,
+        secret: false,
+        default,
+        description,
+    }
+}
+
+const fn optional_secret(name: &'static str, kind: VarKind, description: &'static str) -> ConfigVar {
+    ConfigVar {
+        name,
+        kind,
+        required: false,
+        secret: true,
+        default: None,
+        description,
+    }
+}
+
+/// Every environment variable read by the server.
+pub const CONFIG_VARS: &[ConfigVar] = &[
+    required("MOVE_PACKAGE_ID", VarKind::Text, false, "Sui Move package of the enclave contracts"),
+    required("SUI_SECRET_KEY", VarKind::Text, true, "Sui key used by tasks to sign transactions"),
+    required("RUBY_NODES_API_KEY", VarKind::Text, true, "Ruby nodes RPC API key"),
+    required("WALRUS_AGGREGATOR_URL", VarKind::Url, false, "Walrus aggregator"),
+    required("WALRUS_PUBLISHER_URL", VarKind::Url, false, "Walrus publisher"),
+    required("WALRUS_EPOCHS", VarKind::UnsignedInteger, false, "Storage epochs for blobs stored by the server"),
+    required("AZURE_TEXT_EMBEDDING_API_ENDPOINT", VarKind::Url, false, "Azure OpenAI embedding endpoint"),
+    required("AZURE_TEXT_EMBEDDING_API_KEY", VarKind::Text, true, "Azure OpenAI embedding key"),
+    required("TELEGRAM_SOCIAL_TRUTH_BOT_ID", VarKind::Text, false, "Telegram bot whose messages are social truth"),
+    required("ID_MASK_SALT", VarKind::Text, true, "Salt for masking user and message IDs"),
+    optional("EMBEDDING_PROVIDER", VarKind::EmbeddingProvider, Some("azure"), "Embedding backend, ollama or azure"),
+    optional("OLLAMA_API_URL", VarKind::Url, Some("http://localhost:11434"), "Ollama embedding service"),
+    optional("OLLAMA_MODEL", VarKind::Text, Some("nomic-embed-text"), "Ollama embedding model"),
+    optional("QDRANT_URL", VarKind::Url, Some("http://localhost:6333"), "Qdrant vector database"),
+    optional_secret("QDRANT_API_KEY", VarKind::Text, "Qdrant API key"),
+    optional("QDRANT_COLLECTION_NAME", VarKind::Text, Some("messages"), "Qdrant collection"),
+    optional(
+        "QDRANT_COLLECTIONS",
+        VarKind::Text,
+        None,
+        "Comma separated collections requests may target, besides QDRANT_COLLECTION_NAME",
+    ),
+    optional("QDRANT_DISTANCE", VarKind::Distance, Some("Cosine"), "Distance of collections created on first ingest"),
+    optional("QDRANT_HNSW_M", VarKind::UnsignedInteger, None, "HNSW edges per node of created collections"),
+    optional("QDRANT_HNSW_EF_CONSTRUCT", VarKind::UnsignedInteger, None, "HNSW build-time ef of created collections"),
+    optional(
+        "EMBEDDING_DIMENSIONS",
+        VarKind::UnsignedInteger,
+        None,
+        "Leading embedding dimensions stored and searched, for Matryoshka models",
+    ),
+    optional("QDRANT_SEARCH_HNSW_EF", VarKind::UnsignedInteger, None, "Search-time ef of collections not tuned at runtime"),
+    optional("EMBEDDING_BATCH_SIZE", VarKind::UnsignedInteger, Some("10"), "Texts per embedding request"),
+    optional("VECTOR_BATCH_SIZE", VarKind::UnsignedInteger, Some("100"), "Points per Qdrant upsert"),
+    optional("MAX_CONCURRENT_TASKS", VarKind::UnsignedInteger, Some("4"), "Node.js tasks running at once"),
+    optional("PRIORITY_AGING_SECS", VarKind::UnsignedInteger, Some("30"), "Queue wait that raises a task's priority by one level"),
+    optional("MAX_QUEUED_TASKS", VarKind::UnsignedInteger, Some("32"), "Requests waiting for a task slot before new ones get 429"),
+    optional("TASK_QUEUE_TIMEOUT_SECS", VarKind::UnsignedInteger, Some("120"), "Wait for a task slot before a request gets 429"),
+    optional("TASK_CPU_AFFINITY", VarKind::CpuList, None, "vCPUs task processes may run on"),
+    optional("TASK_NICE", VarKind::Integer, None, "Nice value of task processes"),
+    optional("TASK_NODE_OPTIONS", VarKind::NodeOptions, None, "Node.js flags for every operation"),
+    optional("TASK_NODE_OPTIONS_PROCESS_DATA", VarKind::NodeOptions, None, "Node.js flags for process_data"),
+    optional("TASK_NODE_OPTIONS_EMBEDDING_INGEST", VarKind::NodeOptions, None, "Node.js flags for embedding_ingest"),
+    optional(
+        "TASK_NODE_OPTIONS_RETRIEVE_MESSAGES_BY_BLOB_IDS",
+        VarKind::NodeOptions,
+        None,
+        "Node.js flags for retrieve_messages_by_blob_ids",
+    ),
+    optional(
+        "TASK_NODE_OPTIONS_RETRIEVE_MESSAGES_FILTERED",
+        VarKind::NodeOptions,
+        None,
+        "Node.js flags for retrieve_messages_filtered",
+    ),
+    optional("TASK_BUNDLES", VarKind::TaskBundles, None, "Task directories of operations not run by nodejs-task"),
+    optional("TASK_WORKER_POOL_SIZE", VarKind::UnsignedInteger, Some("0"), "Warm Node.js workers, 0 spawns a process per task"),
+    optional("TASK_WORKER_HEALTH_CHECK_SECS", VarKind::UnsignedInteger, Some("30"), "Interval between worker health checks"),
+    optional("TASK_WORKER_MAX_TASKS", VarKind::UnsignedInteger, Some("100"), "Tasks a worker runs before it is replaced"),
+    optional("TASK_RETRY_MAX_ATTEMPTS", VarKind::UnsignedInteger, Some("1"), "Runs of a failed task at most, 1 disables retries"),
+    optional("TASK_RETRY_BACKOFF_MS", VarKind::UnsignedInteger, Some("500"), "Delay before rerunning a failed task, doubled per retry"),
+    optional("TASK_RETRY_EXIT_CODES", VarKind::ExitCodes, None, "Comma separated task exit codes that are retried"),
+    optional(
+        "TASK_RETRY_ERROR_CLASSES",
+        VarKind::Text,
+        Some("transient"),
+        "Comma separated errorClass values of task results that are retried",
+    ),
+    optional("TASK_CRASH_LOOP_THRESHOLD", VarKind::UnsignedInteger, Some("5"), "Task crashes in the window that mark the runtime unready"),
+    optional("TASK_CRASH_LOOP_WINDOW_SECS", VarKind::UnsignedInteger, Some("60"), "Crash loop detection window"),
+    optional("TASK_CRASH_BACKOFF_MAX_SECS", VarKind::UnsignedInteger, Some("30"), "Longest delay before spawning after crashes"),
+    optional("RETRIEVAL_PROFILES", VarKind::RetrievalProfiles, None, "A/B retrieval parameter profiles"),
+    optional(
+        "WALRUS_AGGREGATOR_FALLBACK_URLS",
+        VarKind::UrlList,
+        None,
+        "Aggregators blobs are read from in order when WALRUS_AGGREGATOR_URL is unavailable",
+    ),
+    optional("WALRUS_WAIT_FOR_CERTIFICATION", VarKind::Boolean, Some("false"), "Wait for stored blobs to be certified"),
+    optional("WALRUS_CERTIFICATION_TIMEOUT_SECS", VarKind::UnsignedInteger, Some("60"), "Blob certification timeout"),
+    optional("WALRUS_MAX_EPOCHS", VarKind::UnsignedInteger, Some("53"), "Largest accepted storage epochs"),
+    optional("WALRUS_MAX_STORE_BYTE_EPOCHS", VarKind::UnsignedInteger, None, "Largest size x epochs of a store"),
+    optional("SUI_RPC_URL", VarKind::Url, Some("https://fullnode.mainnet.sui.io:443"), "Sui fullnode JSON-RPC"),
+    optional("SUI_RPC_FALLBACK_URLS", VarKind::UrlList, None, "Fullnodes tried in order when SUI_RPC_URL is unavailable"),
+    optional("SUI_OBJECT_CACHE_SECS", VarKind::UnsignedInteger, Some("5"), "How long Sui object reads are cached, 0 disables"),
+    optional("SUI_GAS_LANES", VarKind::UnsignedInteger, Some("1"), "Gas coins Sui transactions are spread over, at most 32"),
+    optional("SUI_GAS_LANE_BALANCE", VarKind::UnsignedInteger, Some("200000000"), "MIST of each gas coin split at boot"),
+    optional("SEAL_POLICY_PRECHECK", VarKind::Boolean, Some("true"), "Refuse tasks for Seal policies the enclave cannot decrypt under"),
+    optional(
+        "WALRUS_SYSTEM_OBJECT_ID",
+        VarKind::Text,
+        Some("0x2134d52768ea07e8c43570ef975eb3e4c27a39fa6396bef985b5abc58d03ddd2"),
+        "Walrus system object, read for the current epoch",
+    ),
+    optional("WALRUS_BLOB_PRECHECK", VarKind::Boolean, Some("true"), "Refuse ingests of blobs the aggregator does not serve"),
+    optional("WALRUS_MAX_INGEST_BLOB_BYTES", VarKind::UnsignedInteger, None, "Largest blob accepted for ingest"),
+    optional("VECTOR_TTL_GRACE_EPOCHS", VarKind::UnsignedInteger, Some("1"), "Epochs vectors outlive their expired source blob"),
+    optional("VECTOR_REAPER_INTERVAL_SECS", VarKind::UnsignedInteger, Some("3600"), "Interval between expired vector reaps, 0 disables"),
+    optional("VECTOR_RESTORE_WINDOW_SECS", VarKind::UnsignedInteger, Some("604800"), "How long deleted messages can be restored"),
+    optional("INGEST_WARMUP_MIN_POINTS", VarKind::UnsignedInteger, Some("0"), "Points an ingest job has to store to wait for Qdrant optimization and run warm-up searches, 0 disables"),
+    optional("INGEST_WARMUP_TIMEOUT_SECS", VarKind::UnsignedInteger, Some("120"), "Longest wait for Qdrant optimization after a large ingest"),
+    optional("INGEST_WARMUP_QUERIES", VarKind::UnsignedInteger, Some("8"), "Warm-up searches run after a large ingest"),
+    optional("JOB_RETENTION_SECS", VarKind::UnsignedInteger, Some("3600"), "How long finished jobs and their results are kept"),
+    optional("JOB_RETENTION_MAX_COUNT", VarKind::UnsignedInteger, Some("10000"), "Most jobs kept, 0 is unlimited"),
+    optional("JOB_RETENTION_MAX_BYTES", VarKind::UnsignedInteger, Some("268435456"), "Most bytes of job records kept, 0 is unlimited"),
+    optional("JOB_CLEANUP_INTERVAL_SECS", VarKind::UnsignedInteger, Some("60"), "Interval between job cleanups, 0 disables"),
+    optional(
+        "STORAGE_PATH",
+        VarKind::Text,
+        None,
+        "SQLite database persisting jobs, idempotency keys and audit logs; in memory only when unset",
+    ),
+    optional("IDEMPOTENCY_TTL_SECS", VarKind::UnsignedInteger, Some("86400"), "How long ingest idempotency keys are remembered, 0 disables"),
+    optional("KEY_ROTATION_INTERVAL_SECS", VarKind::UnsignedInteger, Some("0"), "Interval between signing key rotations, 0 disables"),
+    optional("KEY_ROTATION_OVERLAP_SECS", VarKind::UnsignedInteger, Some("3600"), "How long a rotated out signing key stays valid for verification"),
+    optional("SIGNATURE_SCHEME", VarKind::SignatureScheme, Some("ed25519"), "ed25519, or secp256k1 for Move contracts verifying secp256k1 signatures"),
+    optional("ATTESTATION_CACHE_SECS", VarKind::UnsignedInteger, Some("300"), "How long an attestation without a challenge is reused, 0 disables"),
+    optional("HEALTH_CHECK_CACHE_SECS", VarKind::UnsignedInteger, Some("10"), "How long /health_check reuses endpoint probe results, 0 disables"),
+    optional(
+        "AUTHORIZATION_ALLOWLIST_OBJECT_ID",
+        VarKind::Text,
+        None,
+        "Sui object listing the Seal policies operations may use, all allowed when unset",
+    ),
+    optional("CIRCUIT_BREAKER_FAILURES", VarKind::UnsignedInteger, Some("5"), "Upstream failures in a row that open its circuit breaker, 0 disables"),
+    optional("CIRCUIT_BREAKER_OPEN_SECS", VarKind::UnsignedInteger, Some("30"), "How long an open circuit breaker fails calls before a trial call"),
+    optional(
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        VarKind::Url,
+        None,
+        "OTLP collector traces are exported to, with the otel feature; off when unset",
+    ),
+    optional("VECTOR_PROJECTION_DIMENSIONS", VarKind::UnsignedInteger, None, "Random projection of stored vectors, off when unset"),
+    optional_secret("VECTOR_PROJECTION_SEED", VarKind::HexKey, "Secret seed of the vector projection"),
+    optional("VECTOR_NOISE_SCALE", VarKind::Decimal, Some("0"), "Noise added to stored vectors, relative to their norm"),
+    optional("SIGNING_RATE_LIMITS", VarKind::SigningRateLimits, None, "Signatures per minute allowed by intent scope"),
+    optional("ADDRESS_RATE_LIMITS", VarKind::AddressRateLimits, None, "Ingest and retrieval requests per minute allowed by address"),
+    optional("CALLER_RATE_LIMITS", VarKind::CallerRateLimits, None, "Requests per minute and burst allowed per caller by route class"),
+    optional("CALLER_RATE_LIMIT_KEY", VarKind::CallerKey, Some("ip"), "How callers are told apart for CALLER_RATE_LIMITS"),
+    optional("ANCHOR_RECEIPTS", VarKind::Boolean, Some("false"), "Store a signed receipt of every task on Walrus"),
+    optional("DEPENDENCY_ALLOWLIST_PUBKEY", VarKind::HexKey, None, "Signer of the task dependency allowlist"),
+    optional("DEPENDENCY_ALLOWLIST_PATH", VarKind::Text, None, "Dependency allowlist, default in the task directory"),
+    optional_secret(
+        "INTERNAL_ENCRYPTION_SECRET_KEY",
+        VarKind::HexKey,
+        "Master key of encrypted stores without a key of their own",
+    ),
+    optional_secret(
+        "PAYLOAD_ENCRYPTION_KEY",
+        VarKind::HexKey,
+        "Encrypts stored message text, else the internal key; no text is stored without either",
+    ),
+    optional_secret(
+        "PAYLOAD_ENCRYPTION_PREVIOUS_KEYS",
+        VarKind::HexKeyList,
+        "Comma separated earlier PAYLOAD_ENCRYPTION_KEY values, still decrypted",
+    ),
+    optional("LEADER_LEASE_SECS", VarKind::UnsignedInteger, None, "Lease electing the instance running periodic jobs, off when unset"),
+    optional("LEADER_LEASE_COLLECTION", VarKind::Text, Some(DEFAULT_LEASE_COLLECTION), "Qdrant collection holding the leader lease"),
+    optional("REPLICATION_ROLE", VarKind::ReplicationRole, None, "primary or standby, replication is off when unset"),
+    optional("REPLICATION_PEER_URL", VarKind::Url, None, "Standby of a primary, or primary of a standby"),
+    optional("REPLICATION_INTERVAL_SECS", VarKind::UnsignedInteger, Some("5"), "Interval between snapshots sent by a primary"),
+    optional("BIND_ADDR", VarKind::IpAddress, Some("0.0.0.0"), "Address the server listens on"),
+    optional("PORT", VarKind::UnsignedInteger, Some("3000"), "Port the server listens on"),
+    optional("MAX_REQUEST_BODY_BYTES", VarKind::UnsignedInteger, Some("2097152"), "Largest request body accepted"),
+    optional("TLS_MODE", VarKind::TlsMode, Some("off"), "off, files or self_signed for a certificate of the ephemeral key"),
+    optional("TLS_CERT_PATH", VarKind::Text, None, "PEM certificate chain, with TLS_MODE files"),
+    optional("TLS_KEY_PATH", VarKind::Text, None, "PEM private key, with TLS_MODE files"),
+    optional("LOG_LEVEL", VarKind::LogLevel, Some("info"), "Most verbose level logged"),
+    optional("CRASH_REPORT_DIR", VarKind::Text, Some("crash_reports"), "Directory of encrypted crash reports"),
+    optional_secret(
+        "CRASH_REPORT_KEY",
+        VarKind::HexKey,
+        "Crash report encryption key, else the internal key, else random per boot",
+    ),
+    optional("CRASH_REPORT_LOG_LINES", VarKind::UnsignedInteger, Some("200"), "Log lines kept for crash reports"),
+    optional_secret("ADMIN_TOKEN", VarKind::Text, "Bearer token of /admin endpoints, disabled when unset"),
+    optional("REQUEST_LOG_SIZE", VarKind::UnsignedInteger, Some("500"), "Requests kept for /admin/requests"),
+    optional("TASK_AUDIT_LOG_SIZE", VarKind::UnsignedInteger, Some("10000"), "Task invocations kept for /audit/tasks"),
+];
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// Optional and unset, the default applies
+    Default,
+    Missing,
+    Invalid,
+    Unreachable,
+}
+
+impl CheckStatus {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, CheckStatus::Missing | CheckStatus::Invalid | CheckStatus::Unreachable)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigCheck {
+    /// Variable, endpoint or check name
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: Option<String>,
+}
+
+impl ConfigCheck {
+    fn new(name: &str, status: CheckStatus, message: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message,
+        }
+    }
+}
+
+/// Machine-readable result of `--check-config`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigReport {
+    pub valid: bool,
+    pub checks: Vec<ConfigCheck>,
+    /// Reachability of the allowed endpoints, when requested
+    pub connectivity: Option<Vec<ConfigCheck>>,
+}
+
+impl ConfigReport {
+    fn update_valid(&mut self) {
+        self.valid = !self
+            .checks
+            .iter()
+            .chain(self.connectivity.iter().flatten())
+            .any(|check| check.status.is_failure());
+    }
+
+    /// Add endpoint reachability, e.g. from [crate::endpoints::check_endpoints].
+    pub fn add_connectivity(&mut self, endpoints: HashMap<String, bool>) {
+        let mut checks: Vec<ConfigCheck> = endpoints
+            .into_iter()
+            .map(|(endpoint, reachable)| {
+                let status = if reachable { CheckStatus::Ok } else { CheckStatus::Unreachable };
+                ConfigCheck::new(&endpoint, status, None)
+            })
+            .collect();
+        checks.sort_by(|a, b| a.name.cmp(&b.name));
+        self.connectivity = Some(checks);
+        self.update_valid();
+    }
+
+    /// Report an endpoints file that could not be loaded, so no endpoint was checked.
+    pub fn add_endpoints_error(&mut self, file: &str, error: String) {
+        self.connectivity = Some(vec![ConfigCheck::new(file, CheckStatus::Invalid, Some(error))]);
+        self.update_valid();
+    }
+}
+
+fn validate(kind: VarKind, value: &str) -> Result<(), String> {
+    match kind {
+        VarKind::Text => Ok(()),
+        VarKind::Url => match reqwest::Url::parse(value) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+            Ok(url) => Err(format!("unsupported URL scheme {}", url.scheme())),
+            Err(e) => Err(format!("invalid URL: {}", e)),
+        },
+        VarKind::UnsignedInteger => value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::Integer => value.parse::<i64>().map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::Boolean => match value.to_ascii_lowercase().as_str() {
+            "true" | "false" => Ok(()),
+            _ => Err("must be true or false".to_string()),
+        },
+        VarKind::CpuList => SchedulingHints::parse_cpu_list(value).map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::NodeOptions => NodeFlags::parse(value).map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::RetrievalProfiles => RetrievalExperiments::from_json(value)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        VarKind::TaskBundles => TaskBundles::from_json(Path::new("."), value)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        VarKind::SigningRateLimits => KeyUsage::parse_limits(value)
+            .map(|_| ())
+            .map_err(|e| e.status_and_message().1),
+        VarKind::AddressRateLimits => AddressLimits::parse_limits(value)
+            .map(|_| ())
+            .map_err(|e| e.status_and_message().1),
+        VarKind::CallerRateLimits => CallerLimits::parse_limits(value)
+            .map(|_| ())
+            .map_err(|e| e.status_and_message().1),
+        VarKind::CallerKey => value.parse::<CallerKey>().map(|_| ()),
+        VarKind::Decimal => match value.parse::<f64>() {
+            Ok(number) if number.is_finite() && number >= 0.0 => Ok(()),
+            Ok(_) => Err("must be a non-negative number".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        VarKind::HexKey => match Hex::decode(value) {
+            Ok(bytes) if bytes.len() == 32 => Ok(()),
+            Ok(bytes) => Err(format!("must be 32 bytes, got {}", bytes.len())),
+            Err(e) => Err(format!("invalid hex: {}", e)),
+        },
+        VarKind::HexKeyList => value
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .try_for_each(|key| validate(VarKind::HexKey, key)),
+        VarKind::UrlList => value
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .try_for_each(|url| validate(VarKind::Url, url)),
+        VarKind::ExitCodes => TaskRetryPolicy::parse_exit_codes(value).map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::LogLevel => value.parse::<tracing::Level>().map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::Distance => value.parse::<Distance>().map(|_| ()),
+        VarKind::EmbeddingProvider => value.parse::<ProviderKind>().map(|_| ()),
+        VarKind::ReplicationRole => value.parse::<ReplicationRole>().map(|_| ()),
+        VarKind::IpAddress => value.parse::<std::net::IpAddr>().map(|_| ()).map_err(|e| e.to_string()),
+        VarKind::TlsMode => value.parse::<TlsMode>().map(|_| ()),
+        VarKind::SignatureScheme => value.parse::<SignatureScheme>().map(|_| ()),
+    }
+}
+
+/// Check a single variable.
+pub fn check_var(var: &ConfigVar, value: Option<&str>) -> ConfigCheck {
+    match value.filter(|v| !v.is_empty()) {
+        None if var.required => ConfigCheck::new(var.name, CheckStatus::Missing, Some("required".to_string())),
+        None => ConfigCheck::new(var.name, CheckStatus::Default, var.default.map(|d| format!("default {}", d))),
+        Some(value) => match validate(var.kind, value) {
+            Ok(()) => ConfigCheck::new(var.name, CheckStatus::Ok, None),
+            Err(e) => ConfigCheck::new(var.name, CheckStatus::Invalid, Some(e)),
+        },
+    }
+}
+
+/// Validate every variable in [CONFIG_VARS] read through `env`, plus the checks that span
+/// variables: Walrus epochs within budget and the task dependency allowlist.
+pub fn check_config(env: &dyn Fn(&str) -> Option<String>, task_path: &Path) -> ConfigReport {
+    let mut checks: Vec<ConfigCheck> = CONFIG_VARS
+        .iter()
+        .map(|var| check_var(var, env(var.name).as_deref()))
+        .collect();
+
+    let max_epochs = env("WALRUS_MAX_EPOCHS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_EPOCHS);
+    if let Some(epochs) = env("WALRUS_EPOCHS").and_then(|v| v.parse::<u32>().ok()) {
+        let budget = StorageBudget {
+            max_epochs,
+            ..Default::default()
+        };
+        if let Err(e) = budget.check_epochs(epochs) {
+            if let Some(check) = checks.iter_mut().find(|c| c.name == "WALRUS_EPOCHS") {
+                *check = ConfigCheck::new("WALRUS_EPOCHS", CheckStatus::Invalid, Some(e.status_and_message().1));
+            }
+        }
+    }
+
+    let allowlist_path = env("DEPENDENCY_ALLOWLIST_PATH")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| task_path.join(DEFAULT_ALLOWLIST_FILE));
+    let public_key = env("DEPENDENCY_ALLOWLIST_PUBKEY");
+    let dependency_check = |name: &str, status: DependencyStatus| match status {
+        DependencyStatus::Disabled => ConfigCheck::new(name, CheckStatus::Default, Some("not enforced".to_string())),
+        DependencyStatus::Verified { packages } => {
+            ConfigCheck::new(name, CheckStatus::Ok, Some(format!("{} packages verified", packages)))
+        }
+        DependencyStatus::Rejected { reason } => ConfigCheck::new(name, CheckStatus::Invalid, Some(reason)),
+    };
+    checks.push(dependency_check(
+        "dependency_allowlist",
+        check_dependencies(task_path, &allowlist_path, public_key.as_deref()),
+    ));
+    // Bundles from TASK_BUNDLES live next to the default one, each with its own allowlist
+    let root = task_path.parent().unwrap_or(task_path);
+    if let Some(bundles) = env("TASK_BUNDLES").and_then(|json| TaskBundles::from_json(root, &json).ok()) {
+        for bundle in bundles.configured() {
+            let allowlist_path = bundle.path.join(DEFAULT_ALLOWLIST_FILE);
+            checks.push(dependency_check(
+                &format!("dependency_allowlist:{}", bundle.name),
+                check_dependencies(&bundle.path, &allowlist_path, public_key.as_deref()),
+            ));
+        }
+    }
+
+    let mut report = ConfigReport {
+        valid: true,
+        checks,
+        connectivity: None,
+    };
+    report.update_valid();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_env() -> HashMap<&'static str, &'static str> {
+        HashMap::from([
+            ("MOVE_PACKAGE_ID", "0x1"),
+            ("SUI_SECRET_KEY", "suiprivkey1q"),
+            ("RUBY_NODES_API_KEY", "key"),
+            ("WALRUS_AGGREGATOR_URL", "https://aggregator.example.com"),
+            ("WALRUS_PUBLISHER_URL", "https://publisher.example.com"),
+            ("WALRUS_EPOCHS", "5"),
+            ("AZURE_TEXT_EMBEDDING_API_ENDPOINT", "https://azure.example.com"),
+            ("AZURE_TEXT_EMBEDDING_API_KEY", "key"),
+            ("TELEGRAM_SOCIAL_TRUTH_BOT_ID", "1"),
+            ("ID_MASK_SALT", "salt"),
+        ])
+    }
+
+    fn run(env: &HashMap<&'static str, &'static str>) -> ConfigReport {
+        let dir = tempfile::tempdir().unwrap();
+        check_config(&|name| env.get(name).map(|v| v.to_string()), dir.path())
+    }
+
+    fn status(report: &ConfigReport, name: &str) -> CheckStatus {
+        report.checks.iter().find(|c| c.name == name).unwrap().status
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let report = run(&valid_env());
+        assert!(report.valid, "{:?}", report.checks);
+        assert_eq!(status(&report, "QDRANT_URL"), CheckStatus::Default);
+        assert_eq!(status(&report, "dependency_allowlist"), CheckStatus::Default);
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let mut env = valid_env();
+        env.remove("ID_MASK_SALT");
+        env.insert("WALRUS_EPOCHS", "500");
+        env.insert("QDRANT_URL", "localhost:6333");
+        env.insert("ANCHOR_RECEIPTS", "yes");
+        env.insert("TASK_CPU_AFFINITY", "3-1");
+        env.insert("TASK_BUNDLES", r#"[{"name": "scoring", "path": "scoring-task", "operations": ["scoring"]}]"#);
+        let report = run(&env);
+        assert!(!report.valid);
+        assert_eq!(status(&report, "ID_MASK_SALT"), CheckStatus::Missing);
+        assert_eq!(status(&report, "WALRUS_EPOCHS"), CheckStatus::Invalid);
+        assert_eq!(status(&report, "QDRANT_URL"), CheckStatus::Invalid);
+        assert_eq!(status(&report, "ANCHOR_RECEIPTS"), CheckStatus::Invalid);
+        assert_eq!(status(&report, "TASK_CPU_AFFINITY"), CheckStatus::Invalid);
+        assert_eq!(status(&report, "TASK_BUNDLES"), CheckStatus::Invalid);
+    }
+
+    #[test]
+    fn test_connectivity_failures_invalidate_report() {
+        let mut report = run(&valid_env());
+        report.add_connectivity(HashMap::from([("a.example.com".to_string(), true)]));
+        assert!(report.valid);
+        report.add_connectivity(HashMap::from([("b.example.com".to_string(), false)]));
+        assert!(!report.valid);
+    }
+
+    #[test]
+    fn test_schema_defaults_match_constants() {
+        let default = |name: &str| CONFIG_VARS.iter().find(|v| v.name == name).unwrap().default.unwrap();
+        assert_eq!(default("MAX_CONCURRENT_TASKS"), crate::scheduler::DEFAULT_MAX_CONCURRENT_TASKS.to_string());
+        assert_eq!(default("MAX_QUEUED_TASKS"), crate::scheduler::DEFAULT_MAX_QUEUED_TASKS.to_string());
+        assert_eq!(default("TASK_QUEUE_TIMEOUT_SECS"), crate::scheduler::DEFAULT_TASK_QUEUE_TIMEOUT_SECS.to_string());
+        assert_eq!(default("WALRUS_MAX_EPOCHS"), DEFAULT_MAX_EPOCHS.to_string());
+        assert_eq!(default("SUI_RPC_URL"), crate::walrus::DEFAULT_SUI_RPC_URL);
+        assert_eq!(default("SUI_OBJECT_CACHE_SECS"), crate::sui::DEFAULT_SUI_OBJECT_CACHE_SECS.to_string());
+        assert_eq!(default("SUI_GAS_LANES"), crate::tx_sequencer::DEFAULT_SUI_GAS_LANES.to_string());
+        assert_eq!(default("SUI_GAS_LANE_BALANCE"), crate::tx_sequencer::DEFAULT_SUI_GAS_LANE_BALANCE.to_string());
+        assert_eq!(default("WALRUS_SYSTEM_OBJECT_ID"), crate::walrus::DEFAULT_WALRUS_SYSTEM_OBJECT_ID);
+        assert_eq!(default("VECTOR_RESTORE_WINDOW_SECS"), crate::soft_delete::DEFAULT_RESTORE_WINDOW_SECS.to_string());
+        assert_eq!(default("INGEST_WARMUP_TIMEOUT_SECS"), crate::warmup::DEFAULT_INGEST_WARMUP_TIMEOUT_SECS.to_string());
+        assert_eq!(default("INGEST_WARMUP_QUERIES"), crate::warmup::DEFAULT_INGEST_WARMUP_QUERIES.to_string());
+        assert_eq!(default("JOB_RETENTION_SECS"), crate::retention::DEFAULT_JOB_RETENTION_SECS.to_string());
+        assert_eq!(default("IDEMPOTENCY_TTL_SECS"), crate::idempotency::DEFAULT_IDEMPOTENCY_TTL_SECS.to_string());
+        assert_eq!(default("KEY_ROTATION_OVERLAP_SECS"), crate::key_manager::DEFAULT_KEY_ROTATION_OVERLAP_SECS.to_string());
+        assert_eq!(default("ATTESTATION_CACHE_SECS"), crate::common::DEFAULT_ATTESTATION_CACHE_SECS.to_string());
+        assert_eq!(default("HEALTH_CHECK_CACHE_SECS"), crate::endpoints::DEFAULT_HEALTH_CHECK_CACHE_SECS.to_string());
+        assert_eq!(default("CIRCUIT_BREAKER_FAILURES"), crate::breakers::DEFAULT_CIRCUIT_BREAKER_FAILURES.to_string());
+        assert_eq!(default("CIRCUIT_BREAKER_OPEN_SECS"), crate::breakers::DEFAULT_CIRCUIT_BREAKER_OPEN_SECS.to_string());
+        assert_eq!(default("SIGNATURE_SCHEME"), SignatureScheme::default().to_string());
+        assert_eq!(default("REQUEST_LOG_SIZE"), crate::request_log::DEFAULT_REQUEST_LOG_SIZE.to_string());
+        assert_eq!(default("TASK_RETRY_BACKOFF_MS"), crate::task_runner::DEFAULT_TASK_RETRY_BACKOFF_MS.to_string());
+        assert_eq!(default("TASK_RETRY_ERROR_CLASSES"), crate::task_runner::TRANSIENT_ERROR_CLASS);
+        assert_eq!(default("TASK_AUDIT_LOG_SIZE"), crate::task_audit::DEFAULT_TASK_AUDIT_LOG_SIZE.to_string());
+        assert_eq!(default("TASK_WORKER_MAX_TASKS"), crate::task_runner::DEFAULT_WORKER_MAX_TASKS.to_string());
+        assert_eq!(default("CRASH_REPORT_DIR"), crate::crash_reports::DEFAULT_CRASH_REPORT_DIR);
+        assert_eq!(default("BIND_ADDR"), crate::listener::DEFAULT_BIND_ADDR.to_string());
+        assert_eq!(default("PORT"), crate::listener::DEFAULT_PORT.to_string());
+    }
+}
@@ -0,0 +1,426 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Panic handling. The panic hook writes a crash report (panic message, location,
+//! backtrace, request ID and recent log lines) to local storage encrypted with
+//! AES-256-GCM, and [panic_response] turns a panicking handler into a 500 envelope that
+//! names the request and the crash report. Reports are read back on `/admin/crash_reports`.
+
+use crate::api_response::{ApiResponse, RequestContext, REQUEST_ID_HEADER};
+use crate::internal_key::{InternalKey, KeyPurpose, KeySource};
+use crate::logging::LogBuffer;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{Generate, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+use typenum::U12;
+
+/// Directory crash reports are written to by default.
+pub const DEFAULT_CRASH_REPORT_DIR: &str = "crash_reports";
+
+/// Extension of encrypted crash report files.
+const REPORT_EXTENSION: &str = "crash";
+
+/// Length of the AES-GCM nonce stored in front of each report.
+const IV_LENGTH: usize = 12;
+
+tokio::task_local! {
+    /// Request ID of the request being handled by the current task.
+    static REQUEST_ID: String;
+}
+
+thread_local! {
+    /// ID of the last crash report written by the panic hook on this thread, picked up by
+    /// [panic_response] which runs on the same thread right after the unwind is caught.
+    static LAST_CRASH_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Crash report written when a thread panics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    pub timestamp_ms: u64,
+    /// Request being handled when the panic happened, `None` outside request handling
+    pub request_id: Option<String>,
+    pub thread: Option<String>,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// Most recent log lines before the panic, oldest first
+    pub recent_logs: Vec<String>,
+    pub git_commit: String,
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Encrypted crash reports in a local directory, one file per report holding the nonce
+/// followed by the ciphertext of the JSON report. The report ID is the associated data.
+/// Parse a hex encoded 32 byte key, as given in `CRASH_REPORT_KEY`.
+pub fn parse_hex_key(hex_key: &str) -> Result<AesKey<typenum::U32>, EnclaveError> {
+    Hex::decode(hex_key)
+        .ok()
+        .and_then(|bytes| AesKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::ConfigError("Crash report key must be 32 hex encoded bytes".to_string()))
+}
+
+pub struct CrashReportStore {
+    dir: PathBuf,
+    key: AesKey<typenum::U32>,
+    key_source: KeySource,
+}
+
+/// Reports readable with the current key, plus the number of files that are not
+/// (written under another key or corrupted).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReportsResponse {
+    pub reports: Vec<CrashReport>,
+    pub unreadable: usize,
+}
+
+impl CrashReportStore {
+    pub fn new(dir: impl Into<PathBuf>, key: AesKey<typenum::U32>) -> Self {
+        Self {
+            dir: dir.into(),
+            key,
+            key_source: KeySource::Dedicated,
+        }
+    }
+
+    /// Store keyed by a hex encoded 32 byte key, or by a random key when none is given.
+    /// Reports written under a random key are unreadable after a restart.
+    pub fn with_hex_key(dir: impl Into<PathBuf>, hex_key: Option<&str>) -> Result<Self, EnclaveError> {
+        Self::with_keys(dir, hex_key, None)
+    }
+
+    /// Store keyed by a hex encoded 32 byte key, else by the key derived from the internal
+    /// key, else by a random key.
+    pub fn with_keys(
+        dir: impl Into<PathBuf>,
+        hex_key: Option<&str>,
+        internal_key: Option<&InternalKey>,
+    ) -> Result<Self, EnclaveError> {
+        let (key, key_source) = match (hex_key, internal_key) {
+            (Some(hex_key), _) => (parse_hex_key(hex_key)?, KeySource::Dedicated),
+            (None, Some(internal_key)) => (
+                AesKey::from_bytes(&internal_key.derive(KeyPurpose::CrashReports))
+                    .expect("derived keys are 32 bytes"),
+                KeySource::Internal,
+            ),
+            (None, None) => (AesKey::generate(&mut rand::thread_rng()), KeySource::Random),
+        };
+        Ok(Self {
+            dir: dir.into(),
+            key,
+            key_source,
+        })
+    }
+
+    /// Where the key of the store comes from.
+    pub fn key_source(&self) -> KeySource {
+        self.key_source
+    }
+
+    fn cipher(&self) -> Aes256Gcm<U12> {
+        Aes256Gcm::new(self.key.clone())
+    }
+
+    pub fn save(&self, report: &CrashReport) -> std::io::Result<PathBuf> {
+        let plaintext = serde_json::to_vec(report)?;
+        let iv = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+        let ciphertext = self.cipher().encrypt_authenticated(&iv, report.id.as_bytes(), &plaintext);
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.{}", report.id, REPORT_EXTENSION));
+        std::fs::write(&path, [iv.as_bytes(), &ciphertext].concat())?;
+        Ok(path)
+    }
+
+    fn load(&self, id: &str, bytes: &[u8]) -> Option<CrashReport> {
+        if bytes.len() < IV_LENGTH {
+            return None;
+        }
+        let (iv, ciphertext) = bytes.split_at(IV_LENGTH);
+        let iv = InitializationVector::<U12>::from_bytes(iv).ok()?;
+        let plaintext = self.cipher().decrypt_authenticated(&iv, id.as_bytes(), ciphertext).ok()?;
+        serde_json::from_slice(&plaintext).ok()
+    }
+
+    /// Decrypt every stored report, newest first. A missing directory means no reports.
+    pub fn list(&self) -> std::io::Result<CrashReportsResponse> {
+        let mut response = CrashReportsResponse {
+            reports: Vec::new(),
+            unreadable: 0,
+        };
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(response),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(REPORT_EXTENSION) {
+                continue;
+            }
+            let id = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            match self.load(&id, &std::fs::read(&path)?) {
+                Some(report) => response.reports.push(report),
+                None => response.unreadable += 1,
+            }
+        }
+        response.reports.sort_by(|a, b| b.timestamp_ms.cmp(&a.timestamp_ms));
+        Ok(response)
+    }
+}
+
+/// Install a panic hook that writes a crash report before running the previous hook.
+pub fn install_panic_hook(store: Arc<CrashReportStore>, logs: Arc<LogBuffer>, git_commit: String) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let report = CrashReport {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp_ms: now_ms(),
+            request_id: REQUEST_ID.try_with(|id| id.clone()).ok(),
+            thread: std::thread::current().name().map(str::to_string),
+            message: panic_message(info.payload()),
+            location: info.location().map(|l| l.to_string()),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            recent_logs: logs.snapshot(),
+            git_commit: git_commit.clone(),
+        };
+        match store.save(&report) {
+            Ok(path) => eprintln!("Crash report {} written to {}", report.id, path.display()),
+            Err(e) => eprintln!("Failed to write crash report {}: {}", report.id, e),
+        }
+        LAST_CRASH_ID.with(|id| *id.borrow_mut() = Some(report.id));
+        previous(info);
+    }));
+}
+
+/// Span of the work done for a request; log lines inside it end with its `request_id`.
+pub fn request_span(request_id: &str) -> tracing::Span {
+    tracing::info_span!("request", request_id)
+}
+
+/// Middleware giving every request an `x-request-id` (generated when missing), making it
+/// available to the panic hook and the tasks the request runs, and running the request in
+/// its [request_span]. The ID is echoed on every response.
+pub async fn scope_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let header = HeaderValue::from_str(&request_id).ok();
+    if let Some(value) = &header {
+        request.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    }
+    let span = request_span(&request_id);
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).instrument(span).await;
+    if let Some(value) = header {
+        response.headers_mut().entry(REQUEST_ID_HEADER).or_insert(value);
+    }
+    response
+}
+
+/// Request ID of the request being handled, set by [scope_request_id].
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// `future` running under the current request ID and in its span, for work a handler
+/// spawns.
+pub fn inherit_request_id<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let request_id = current_request_id();
+    async move {
+        match request_id {
+            Some(request_id) => {
+                let span = request_span(&request_id);
+                REQUEST_ID.scope(request_id, future).instrument(span).await
+            }
+            None => future.await,
+        }
+    }
+}
+
+/// Response for a panicking handler, used with `CatchPanicLayer::custom`.
+pub fn panic_response(_payload: Box<dyn Any + Send + 'static>) -> Response {
+    let request_id = REQUEST_ID.try_with(|id| id.clone()).ok();
+    let message = match LAST_CRASH_ID.with(|id| id.borrow_mut().take()) {
+        Some(crash_id) => format!("Internal server error, crash report {}", crash_id),
+        None => "Internal server error".to_string(),
+    };
+    RequestContext::new(request_id)
+        .error::<()>(EnclaveError::Internal(message))
+        .into_response()
+}
+
+/// Decrypted crash reports, newest first. Requires the admin token.
+pub async fn crash_reports(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<CrashReportsResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.respond(
+        state
+            .crash_reports
+            .list()
+            .map_err(|e| EnclaveError::Internal(format!("Failed to read crash reports: {}", e))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn report(id: &str, timestamp_ms: u64) -> CrashReport {
+        CrashReport {
+            id: id.to_string(),
+            timestamp_ms,
+            request_id: Some("req-1".to_string()),
+            thread: None,
+            message: "boom".to_string(),
+            location: Some("src/app.rs:1:1".to_string()),
+            backtrace: String::new(),
+            recent_logs: vec!["1 INFO app: started".to_string()],
+            git_commit: "abc".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_reports_are_encrypted_at_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CrashReportStore::with_hex_key(dir.path(), None).unwrap();
+        let path = store.save(&report("a", 1)).unwrap();
+        store.save(&report("b", 2)).unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        assert!(!String::from_utf8_lossy(&bytes).contains("boom"));
+
+        let listed = store.list().unwrap();
+        assert_eq!(listed.reports, vec![report("b", 2), report("a", 1)]);
+        assert_eq!(listed.unreadable, 0);
+
+        // Another key cannot read them
+        let other = CrashReportStore::with_hex_key(dir.path(), Some(&"11".repeat(32))).unwrap();
+        let listed = other.list().unwrap();
+        assert!(listed.reports.is_empty());
+        assert_eq!(listed.unreadable, 2);
+
+        assert!(CrashReportStore::with_hex_key(dir.path(), Some("1234")).is_err());
+        assert!(CrashReportStore::with_hex_key(dir.path().join("missing"), None)
+            .unwrap()
+            .list()
+            .unwrap()
+            .reports
+            .is_empty());
+    }
+
+    #[test]
+    fn test_internal_key_survives_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let internal = InternalKey::from_hex(&"22".repeat(32)).unwrap();
+        let store = CrashReportStore::with_keys(dir.path(), None, Some(&internal)).unwrap();
+        assert_eq!(store.key_source(), KeySource::Internal);
+        store.save(&report("a", 1)).unwrap();
+
+        let restarted = CrashReportStore::with_keys(dir.path(), None, Some(&internal)).unwrap();
+        assert_eq!(restarted.list().unwrap().reports, vec![report("a", 1)]);
+
+        // A dedicated key takes precedence
+        let dedicated = CrashReportStore::with_keys(dir.path(), Some(&"11".repeat(32)), Some(&internal)).unwrap();
+        assert_eq!(dedicated.key_source(), KeySource::Dedicated);
+        assert_eq!(dedicated.list().unwrap().unreadable, 1);
+        assert_eq!(CrashReportStore::with_hex_key(dir.path(), None).unwrap().key_source(), KeySource::Random);
+    }
+
+    #[tokio::test]
+    async fn test_panic_response_names_request_and_report() {
+        LAST_CRASH_ID.with(|id| *id.borrow_mut() = Some("crash-1".to_string()));
+        let response = REQUEST_ID
+            .scope("req-7".to_string(), async { panic_response(Box::new("boom")) })
+            .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-7");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["requestId"], "req-7");
+        assert!(value["error"]["message"].as_str().unwrap().contains("crash-1"));
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_becomes_500() {
+        async fn explode() -> &'static str {
+            panic!("boom")
+        }
+        let app = axum::Router::new()
+            .route("/explode", axum::routing::get(explode))
+            .layer(tower_http::catch_panic::CatchPanicLayer::custom(panic_response))
+            .layer(axum::middleware::from_fn(scope_request_id));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/explode", addr))
+            .header(REQUEST_ID_HEADER, "req-9")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 500);
+        let value: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(value["requestId"], "req-9");
+        assert!(value["error"]["message"].as_str().unwrap().starts_with("Internal server error"));
+    }
+
+    #[tokio::test]
+    async fn test_request_id_echoed_on_plain_responses() {
+        async fn plain() -> String {
+            current_request_id().unwrap_or_default()
+        }
+        let app = axum::Router::new()
+            .route("/plain", axum::routing::get(plain))
+            .layer(axum::middleware::from_fn(scope_request_id));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let response = reqwest::get(format!("http://{}/plain", addr)).await.unwrap();
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        assert!(!request_id.is_empty());
+        assert_eq!(response.text().await.unwrap(), request_id);
+    }
+}
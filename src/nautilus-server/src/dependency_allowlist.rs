@@ -0,0 +1,304 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Enforcement of a signed allowlist of Node.js task dependencies.
+//!
+//! The allowlist is a JSON file mapping package names to permitted versions, signed with an
+//! operator key: `<allowlist>.sig` holds the hex Ed25519 signature over the exact file bytes.
+//! The task's `package-lock.json` must only contain allowlisted packages and versions, so a
+//! dependency swapped inside the enclave image is caught before any task runs.
+
+use crate::EnclaveError;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Default allowlist file name inside the task directory.
+pub const DEFAULT_ALLOWLIST_FILE: &str = "dependency-allowlist.json";
+
+/// Permitted versions by package name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DependencyAllowlist {
+    pub packages: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// A package resolved in `package-lock.json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    /// Install path, e.g. `node_modules/a/node_modules/b`.
+    pub path: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// A locked package that the allowlist does not permit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DependencyViolation {
+    pub path: String,
+    pub name: String,
+    pub version: String,
+    /// Versions permitted for the package, empty if the package is not allowlisted at all.
+    pub allowed: Vec<String>,
+}
+
+/// Outcome of the dependency check, kept in [crate::AppState].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DependencyStatus {
+    /// No allowlist key is configured.
+    Disabled,
+    Verified { packages: usize },
+    Rejected { reason: String },
+}
+
+impl DependencyStatus {
+    /// Refuse to run tasks unless dependencies were verified or enforcement is disabled.
+    pub fn ensure_allowed(&self) -> Result<(), EnclaveError> {
+        match self {
+            DependencyStatus::Rejected { reason } => Err(EnclaveError::ConfigError(format!(
+                "Refusing to run task, dependency allowlist check failed: {}",
+                reason
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Combined status of two task bundles: rejected if either is, verified with the
+    /// packages of both if both are.
+    pub fn merge(self, other: DependencyStatus) -> DependencyStatus {
+        match (self, other) {
+            (DependencyStatus::Rejected { reason }, DependencyStatus::Rejected { reason: other }) => {
+                DependencyStatus::Rejected {
+                    reason: format!("{}; {}", reason, other),
+                }
+            }
+            (rejected @ DependencyStatus::Rejected { .. }, _) | (_, rejected @ DependencyStatus::Rejected { .. }) => rejected,
+            (DependencyStatus::Verified { packages }, DependencyStatus::Verified { packages: other }) => {
+                DependencyStatus::Verified {
+                    packages: packages + other,
+                }
+            }
+            (status, _) => status,
+        }
+    }
+}
+
+/// Parse the `packages` section of a lockfile (lockfileVersion 2 or 3).
+pub fn parse_lockfile(content: &str) -> Result<Vec<LockedPackage>, EnclaveError> {
+    let lock: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| EnclaveError::ConfigError(format!("Invalid package-lock.json: {}", e)))?;
+    let packages = lock["packages"].as_object().ok_or_else(|| {
+        EnclaveError::ConfigError(
+            "package-lock.json has no `packages` section (lockfileVersion >= 2 required)".to_string(),
+        )
+    })?;
+
+    Ok(packages
+        .iter()
+        .filter(|(path, _)| !path.is_empty())
+        .map(|(path, entry)| {
+            let name = entry["name"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| match path.rfind("node_modules/") {
+                    Some(i) => path[i + "node_modules/".len()..].to_string(),
+                    None => path.clone(),
+                });
+            LockedPackage {
+                path: path.clone(),
+                name,
+                version: entry["version"].as_str().unwrap_or_default().to_string(),
+            }
+        })
+        .collect())
+}
+
+/// Locked packages that are not permitted by the allowlist.
+pub fn find_violations(
+    locked: &[LockedPackage],
+    allowlist: &DependencyAllowlist,
+) -> Vec<DependencyViolation> {
+    locked
+        .iter()
+        .filter_map(|package| {
+            let allowed = allowlist.packages.get(&package.name);
+            if allowed.is_some_and(|versions| versions.contains(&package.version)) {
+                return None;
+            }
+            Some(DependencyViolation {
+                path: package.path.clone(),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                allowed: allowed.map(|v| v.iter().cloned().collect()).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Verify the allowlist signature and parse it.
+pub fn load_signed_allowlist(
+    content: &[u8],
+    signature_hex: &str,
+    public_key_hex: &str,
+) -> Result<DependencyAllowlist, EnclaveError> {
+    let public_key = Hex::decode(public_key_hex.trim())
+        .ok()
+        .and_then(|bytes| Ed25519PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::ConfigError("Invalid dependency allowlist public key".to_string()))?;
+    let signature = Hex::decode(signature_hex.trim())
+        .ok()
+        .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::ConfigError("Invalid dependency allowlist signature encoding".to_string()))?;
+    public_key.verify(content, &signature).map_err(|_| {
+        EnclaveError::ConfigError("Dependency allowlist signature does not verify".to_string())
+    })?;
+    serde_json::from_slice(content)
+        .map_err(|e| EnclaveError::ConfigError(format!("Invalid dependency allowlist: {}", e)))
+}
+
+/// Path of the detached signature for an allowlist file.
+pub fn signature_path(allowlist_path: &Path) -> PathBuf {
+    let mut path = allowlist_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// Check the task directory's lockfile against the signed allowlist.
+/// Returns the number of verified packages.
+pub fn verify_task_dependencies(
+    task_path: &Path,
+    allowlist_path: &Path,
+    public_key_hex: &str,
+) -> Result<usize, EnclaveError> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| {
+            EnclaveError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+        })
+    };
+    let content = read(allowlist_path)?;
+    let signature = String::from_utf8(read(&signature_path(allowlist_path))?)
+        .map_err(|_| EnclaveError::ConfigError("Allowlist signature is not valid UTF-8".to_string()))?;
+    let allowlist = load_signed_allowlist(&content, &signature, public_key_hex)?;
+
+    let lock = String::from_utf8(read(&task_path.join("package-lock.json"))?)
+        .map_err(|_| EnclaveError::ConfigError("package-lock.json is not valid UTF-8".to_string()))?;
+    let locked = parse_lockfile(&lock)?;
+    let violations = find_violations(&locked, &allowlist);
+    if !violations.is_empty() {
+        let listed: Vec<String> = violations
+            .iter()
+            .take(10)
+            .map(|v| format!("{}@{}", v.name, v.version))
+            .collect();
+        return Err(EnclaveError::ConfigError(format!(
+            "{} unexpected dependencies: {}{}",
+            violations.len(),
+            listed.join(", "),
+            if violations.len() > 10 { ", ..." } else { "" }
+        )));
+    }
+    Ok(locked.len())
+}
+
+/// Run the boot-time check. Enforcement is disabled when no public key is configured.
+pub fn check_dependencies(
+    task_path: &Path,
+    allowlist_path: &Path,
+    public_key_hex: Option<&str>,
+) -> DependencyStatus {
+    match public_key_hex {
+        None => DependencyStatus::Disabled,
+        Some(key) => match verify_task_dependencies(task_path, allowlist_path, key) {
+            Ok(packages) => DependencyStatus::Verified { packages },
+            Err(e) => DependencyStatus::Rejected {
+                reason: e.status_and_message().1,
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::{KeyPair, Signer};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const LOCKFILE: &str = r#"{
+        "lockfileVersion": 3,
+        "packages": {
+            "": { "name": "simple-nodejs-task", "version": "1.0.0" },
+            "node_modules/axios": { "version": "1.6.0" },
+            "node_modules/@mysten/sui": { "version": "1.37.6" },
+            "node_modules/axios/node_modules/form-data": { "version": "4.0.0" }
+        }
+    }"#;
+
+    fn allowlist(entries: &[(&str, &str)]) -> DependencyAllowlist {
+        let mut allowlist = DependencyAllowlist::default();
+        for (name, version) in entries {
+            allowlist.packages.entry(name.to_string()).or_default().insert(version.to_string());
+        }
+        allowlist
+    }
+
+    #[test]
+    fn test_parse_lockfile() {
+        let locked = parse_lockfile(LOCKFILE).unwrap();
+        assert_eq!(locked.len(), 3);
+        assert!(locked.iter().any(|p| p.name == "@mysten/sui" && p.version == "1.37.6"));
+        assert!(locked.iter().any(|p| p.name == "form-data" && p.path.starts_with("node_modules/axios/")));
+        assert!(parse_lockfile(r#"{ "lockfileVersion": 1, "dependencies": {} }"#).is_err());
+    }
+
+    #[test]
+    fn test_find_violations() {
+        let locked = parse_lockfile(LOCKFILE).unwrap();
+        let full = allowlist(&[("axios", "1.6.0"), ("@mysten/sui", "1.37.6"), ("form-data", "4.0.0")]);
+        assert!(find_violations(&locked, &full).is_empty());
+
+        let swapped = allowlist(&[("axios", "1.5.0"), ("@mysten/sui", "1.37.6")]);
+        let violations = find_violations(&locked, &swapped);
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().any(|v| v.name == "axios" && v.allowed == vec!["1.5.0"]));
+        assert!(violations.iter().any(|v| v.name == "form-data" && v.allowed.is_empty()));
+    }
+
+    #[test]
+    fn test_signed_allowlist() {
+        let kp = Ed25519KeyPair::generate(&mut StdRng::from_seed([1; 32]));
+        let content = serde_json::to_vec(&allowlist(&[("axios", "1.6.0")])).unwrap();
+        let signature = Hex::encode(kp.sign(&content));
+        let public_key = Hex::encode(kp.public().as_bytes());
+
+        let loaded = load_signed_allowlist(&content, &signature, &public_key).unwrap();
+        assert!(loaded.packages["axios"].contains("1.6.0"));
+
+        let mut tampered = content.clone();
+        tampered.extend_from_slice(b" ");
+        assert!(load_signed_allowlist(&tampered, &signature, &public_key).is_err());
+    }
+
+    #[test]
+    fn test_status_enforcement() {
+        assert!(DependencyStatus::Disabled.ensure_allowed().is_ok());
+        assert!(DependencyStatus::Verified { packages: 3 }.ensure_allowed().is_ok());
+        let rejected = DependencyStatus::Rejected {
+            reason: "1 unexpected dependencies".to_string(),
+        };
+        assert!(rejected.ensure_allowed().is_err());
+        assert_eq!(
+            DependencyStatus::Verified { packages: 3 }.merge(DependencyStatus::Verified { packages: 2 }),
+            DependencyStatus::Verified { packages: 5 }
+        );
+        assert_eq!(DependencyStatus::Verified { packages: 3 }.merge(rejected.clone()), rejected);
+        assert_eq!(
+            check_dependencies(Path::new("/nonexistent"), Path::new("/nonexistent/a.json"), None),
+            DependencyStatus::Disabled
+        );
+    }
+}
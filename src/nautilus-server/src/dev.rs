@@ -0,0 +1,206 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Development mode helpers for `--dev`: a router that remembers its routes so they can be
+//! listed on `/routes`, and a watcher reloading the task directory when it changes.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::task_runner::WorkerPool;
+use axum::handler::Handler;
+use axum::routing::{get, post, MethodRouter};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Interval between task directory scans.
+pub const DEFAULT_TASK_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One registered route.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteInfo {
+    pub method: String,
+    pub path: String,
+}
+
+/// Response of `/routes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutesResponse {
+    pub routes: Vec<RouteInfo>,
+}
+
+/// [Router] builder recording the method and path of every route it adds.
+pub struct RouteTable<S> {
+    router: Router<S>,
+    routes: Vec<RouteInfo>,
+}
+
+impl<S: Clone + Send + Sync + 'static> Default for RouteTable<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> RouteTable<S> {
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    fn route(mut self, method: &str, path: &str, method_router: MethodRouter<S>) -> Self {
+        self.routes.push(RouteInfo {
+            method: method.to_string(),
+            path: path.to_string(),
+        });
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    pub fn get<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.route("GET", path, get(handler))
+    }
+
+    pub fn post<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.route("POST", path, post(handler))
+    }
+
+    /// Add `GET /routes` listing every route, itself included.
+    pub fn with_route_listing(self) -> Self {
+        let mut routes = self.routes.clone();
+        routes.push(RouteInfo {
+            method: "GET".to_string(),
+            path: "/routes".to_string(),
+        });
+        let routes = Arc::new(routes);
+        self.route(
+            "GET",
+            "/routes",
+            get(move |ctx: RequestContext| async move { list_routes(ctx, &routes) }),
+        )
+    }
+
+    pub fn routes(&self) -> &[RouteInfo] {
+        &self.routes
+    }
+
+    pub fn into_router(self) -> Router<S> {
+        self.router
+    }
+}
+
+fn list_routes(ctx: RequestContext, routes: &[RouteInfo]) -> ApiResponse<RoutesResponse> {
+    ctx.ok(RoutesResponse {
+        routes: routes.to_vec(),
+    })
+}
+
+/// Modification times and sizes of the files in the task directory, `node_modules` excluded.
+/// Two fingerprints differ when a file was added, removed or changed.
+pub fn task_directory_fingerprint(task_path: &Path) -> Vec<(PathBuf, SystemTime, u64)> {
+    let mut files = Vec::new();
+    let mut dirs = vec![task_path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.is_dir() {
+                if entry.file_name() != "node_modules" && entry.file_name() != ".git" {
+                    dirs.push(path);
+                }
+            } else if let Ok(modified) = metadata.modified() {
+                files.push((path, modified, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Poll the task directory and reload the worker pool when it changes. Tasks spawned per
+/// request already read the files afresh, so without a pool changes are only logged.
+pub fn watch_task_directory(task_path: PathBuf, worker_pool: Option<Arc<WorkerPool>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut fingerprint = task_directory_fingerprint(&task_path);
+        loop {
+            tokio::time::sleep(interval).await;
+            let current = task_directory_fingerprint(&task_path);
+            if current == fingerprint {
+                continue;
+            }
+            fingerprint = current;
+            match &worker_pool {
+                Some(pool) => {
+                    let started = pool.reload().await;
+                    info!("Task directory changed, restarted {} Node.js workers", started);
+                    if started < pool.size() {
+                        warn!("Only {} of {} Node.js workers restarted", started, pool.size());
+                    }
+                }
+                None => info!("Task directory changed, the next task will use the new files"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_route_listing() {
+        let table = RouteTable::<()>::new()
+            .get("/", || async { "Pong!" })
+            .post("/echo", |body: String| async move { body })
+            .with_route_listing();
+        assert_eq!(table.routes().len(), 3);
+        let app = table.into_router();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let body: serde_json::Value = reqwest::get(format!("http://{}/routes", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let routes: Vec<RouteInfo> = serde_json::from_value(body["data"]["routes"].clone()).unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                RouteInfo { method: "GET".to_string(), path: "/".to_string() },
+                RouteInfo { method: "POST".to_string(), path: "/echo".to_string() },
+                RouteInfo { method: "GET".to_string(), path: "/routes".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.js"), "1").unwrap();
+        std::fs::create_dir(dir.path().join("node_modules")).unwrap();
+        let before = task_directory_fingerprint(dir.path());
+        assert_eq!(before.len(), 1);
+
+        std::fs::write(dir.path().join("node_modules").join("dep.js"), "1").unwrap();
+        assert_eq!(task_directory_fingerprint(dir.path()), before);
+
+        std::fs::write(dir.path().join("index.js"), "22").unwrap();
+        assert_ne!(task_directory_fingerprint(dir.path()), before);
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Embedding providers callable from Rust, so queries can be embedded without spawning a
+//! Node task. The backends send the same requests as the task's `ollama-embedding.js` and
+//! `azure-text-embedding.js`, so vectors computed here match the ingested ones.
+
+use crate::config::{url_str, ApiKey, Config};
+use crate::metrics::Metrics;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+/// Azure OpenAI deployment used by the Node task.
+pub const AZURE_DEPLOYMENT: &str = "text-embedding-3-small";
+pub const AZURE_API_VERSION: &str = "2024-04-01-preview";
+/// Dimensions requested from Azure, matching the ingested vectors.
+pub const AZURE_DIMENSIONS: u32 = 768;
+
+/// Computes embeddings for a batch of texts in a single request.
+pub trait Provider {
+    /// One vector per text, in the order of `texts`.
+    fn embed_batch(&self, texts: &[String]) -> impl Future<Output = Result<Vec<Vec<f32>>, EnclaveError>> + Send;
+
+    /// Provider and model, for logs and metrics.
+    fn describe(&self) -> String;
+}
+
+/// Value of `EMBEDDING_PROVIDER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Ollama,
+    #[default]
+    Azure,
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::Azure => "azure",
+        })
+    }
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ollama" => Ok(ProviderKind::Ollama),
+            "azure" => Ok(ProviderKind::Azure),
+            _ => Err(format!("unknown embedding provider {:?}, expected ollama or azure", s)),
+        }
+    }
+}
+
+fn http_client() -> Result<reqwest::Client, EnclaveError> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// Send `body` with `request` and return the JSON response of a successful request.
+async fn post_json(
+    request: reqwest::RequestBuilder,
+    body: &serde_json::Value,
+    provider: &str,
+) -> Result<serde_json::Value, EnclaveError> {
+    let response = request
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| EnclaveError::upstream("embedding", format!("{} embedding request failed: {}", provider, e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(EnclaveError::upstream("embedding", format!(
+            "{} embedding failed with HTTP {}: {}",
+            provider, status, body
+        )));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| EnclaveError::upstream("embedding", format!("Invalid {} embedding response: {}", provider, e)))
+}
+
+fn parse_vector(value: &serde_json::Value) -> Option<Vec<f32>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_f64().map(|f| f as f32))
+        .collect()
+}
+
+fn check_count(vectors: Vec<Vec<f32>>, expected: usize, provider: &str) -> Result<Vec<Vec<f32>>, EnclaveError> {
+    if vectors.len() != expected {
+        return Err(EnclaveError::upstream("embedding", format!(
+            "{} returned {} embeddings for {} texts",
+            provider,
+            vectors.len(),
+            expected
+        )));
+    }
+    Ok(vectors)
+}
+
+/// Parse Ollama's `POST /api/embed` response.
+pub fn parse_ollama_response(body: &serde_json::Value, expected: usize) -> Result<Vec<Vec<f32>>, EnclaveError> {
+    let vectors = body["embeddings"]
+        .as_array()
+        .and_then(|embeddings| embeddings.iter().map(parse_vector).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| EnclaveError::upstream("embedding", format!("Unexpected Ollama embedding response: {}", body)))?;
+    check_count(vectors, expected, "Ollama")
+}
+
+/// Parse the Azure OpenAI embeddings response, ordering the vectors by their `index`.
+pub fn parse_azure_response(body: &serde_json::Value, expected: usize) -> Result<Vec<Vec<f32>>, EnclaveError> {
+    let invalid = || EnclaveError::upstream("embedding", format!("Unexpected Azure embedding response: {}", body));
+    let mut items = body["data"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|item| Some((item["index"].as_u64()?, parse_vector(&item["embedding"])?)))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+    items.sort_by_key(|(index, _)| *index);
+    check_count(items.into_iter().map(|(_, vector)| vector).collect(), expected, "Azure")
+}
+
+/// Ollama backend.
+#[derive(Debug, Clone)]
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    url: String,
+    model: String,
+}
+
+impl OllamaProvider {
+    pub fn new(url: &str, model: &str) -> Result<Self, EnclaveError> {
+        Ok(Self {
+            http: http_client()?,
+            url: url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+        })
+    }
+}
+
+impl Provider for OllamaProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EnclaveError> {
+        let body = serde_json::json!({ "model": self.model, "input": texts });
+        let response = post_json(self.http.post(format!("{}/api/embed", self.url)), &body, "Ollama").await?;
+        parse_ollama_response(&response, texts.len())
+    }
+
+    fn describe(&self) -> String {
+        format!("ollama/{}", self.model)
+    }
+}
+
+/// Azure OpenAI backend.
+#[derive(Debug, Clone)]
+pub struct AzureProvider {
+    http: reqwest::Client,
+    endpoint: String,
+    api_key: ApiKey,
+}
+
+impl AzureProvider {
+    pub fn new(endpoint: &str, api_key: ApiKey) -> Result<Self, EnclaveError> {
+        Ok(Self {
+            http: http_client()?,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+}
+
+impl Provider for AzureProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EnclaveError> {
+        let url = format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            self.endpoint, AZURE_DEPLOYMENT, AZURE_API_VERSION
+        );
+        let body = serde_json::json!({
+            "input": texts,
+            "model": AZURE_DEPLOYMENT,
+            "dimensions": AZURE_DIMENSIONS,
+        });
+        let request = self.http.post(url).header("api-key", self.api_key.expose());
+        let response = post_json(request, &body, "Azure").await?;
+        parse_azure_response(&response, texts.len())
+    }
+
+    fn describe(&self) -> String {
+        format!("azure/{}", AZURE_DEPLOYMENT)
+    }
+}
+
+/// Backend selected by `EMBEDDING_PROVIDER`.
+#[derive(Debug, Clone)]
+pub enum EmbeddingProvider {
+    Ollama(OllamaProvider),
+    Azure(AzureProvider),
+}
+
+impl EmbeddingProvider {
+    pub fn from_config(config: &Config) -> Result<Self, EnclaveError> {
+        Ok(match config.embedding_provider {
+            ProviderKind::Ollama => {
+                EmbeddingProvider::Ollama(OllamaProvider::new(url_str(&config.ollama_api_url), &config.ollama_model)?)
+            }
+            ProviderKind::Azure => EmbeddingProvider::Azure(AzureProvider::new(
+                url_str(&config.azure_text_embedding_api_endpoint),
+                config.azure_text_embedding_api_key.clone(),
+            )?),
+        })
+    }
+
+    /// The same backend with Ollama model `model`, for a runtime `OLLAMA_MODEL` update.
+    /// Azure always uses [AZURE_DEPLOYMENT] and is returned as is.
+    pub fn with_ollama_model(&self, model: &str) -> Self {
+        match self {
+            EmbeddingProvider::Ollama(provider) => EmbeddingProvider::Ollama(OllamaProvider {
+                model: model.to_string(),
+                ..provider.clone()
+            }),
+            EmbeddingProvider::Azure(provider) => EmbeddingProvider::Azure(provider.clone()),
+        }
+    }
+
+    /// Embed `texts`, recording the call duration as an external `embedding` call.
+    pub async fn embed_observed(&self, texts: &[String], metrics: &Metrics) -> Result<Vec<Vec<f32>>, EnclaveError> {
+        let started = Instant::now();
+        let result = self.embed_batch(texts).await;
+        metrics.observe_external_call("embedding", "embed", started.elapsed());
+        crate::telemetry::record_upstream_call("embedding", "embed", started.elapsed(), result.is_ok());
+        result
+    }
+}
+
+impl Provider for EmbeddingProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EnclaveError> {
+        match self {
+            EmbeddingProvider::Ollama(provider) => provider.embed_batch(texts).await,
+            EmbeddingProvider::Azure(provider) => provider.embed_batch(texts).await,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            EmbeddingProvider::Ollama(provider) => provider.describe(),
+            EmbeddingProvider::Azure(provider) => provider.describe(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_responses() {
+        let ollama = json!({ "embeddings": [[0.5, 1.0], [0.25, -1.0]] });
+        assert_eq!(parse_ollama_response(&ollama, 2).unwrap(), vec![vec![0.5, 1.0], vec![0.25, -1.0]]);
+        assert!(parse_ollama_response(&ollama, 3).is_err());
+        assert!(parse_ollama_response(&json!({ "error": "model not found" }), 1).is_err());
+
+        let azure = json!({ "data": [
+            { "index": 1, "embedding": [2.0] },
+            { "index": 0, "embedding": [1.0] },
+        ]});
+        assert_eq!(parse_azure_response(&azure, 2).unwrap(), vec![vec![1.0], vec![2.0]]);
+        assert!(parse_azure_response(&json!({ "data": [{ "index": 0 }] }), 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_providers_against_stub() {
+        use axum::http::HeaderMap;
+        use axum::routing::post;
+        use axum::Json;
+
+        let app = axum::Router::new()
+            .route(
+                "/api/embed",
+                post(|Json(body): Json<serde_json::Value>| async move {
+                    assert_eq!(body["model"], "nomic-embed-text");
+                    let count = body["input"].as_array().unwrap().len();
+                    Json(json!({ "embeddings": vec![vec![0.5]; count] }))
+                }),
+            )
+            .route(
+                "/openai/deployments/text-embedding-3-small/embeddings",
+                post(|headers: HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                    assert_eq!(headers["api-key"], "key");
+                    assert_eq!(body["dimensions"], AZURE_DIMENSIONS);
+                    Json(json!({ "data": [{ "index": 0, "embedding": [0.25, 0.75] }] }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let texts = vec!["hello".to_string(), "world".to_string()];
+        let ollama = EmbeddingProvider::Ollama(OllamaProvider::new(&url, "nomic-embed-text").unwrap());
+        assert_eq!(ollama.embed_batch(&texts).await.unwrap(), vec![vec![0.5], vec![0.5]]);
+        assert_eq!(ollama.describe(), "ollama/nomic-embed-text");
+        assert_eq!(ollama.with_ollama_model("mxbai-embed-large").describe(), "ollama/mxbai-embed-large");
+
+        let azure = EmbeddingProvider::Azure(AzureProvider::new(&url, ApiKey::new("key")).unwrap());
+        assert_eq!(azure.embed_batch(&texts[..1]).await.unwrap(), vec![vec![0.25, 0.75]]);
+        // A response with fewer vectors than texts is an error
+        assert!(azure.embed_batch(&texts).await.is_err());
+        assert_eq!(azure.with_ollama_model("mxbai-embed-large").describe(), azure.describe());
+    }
+}
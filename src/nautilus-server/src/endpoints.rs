@@ -0,0 +1,628 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! `allowed_endpoints.yaml`: the external hosts the enclave may reach. The parent instance
+//! forwards traffic only to these hosts, and `/health_check` probes each of them. An entry
+//! is either a bare host, probed with `GET /` expecting a 2xx status, or a map giving the
+//! probe of an upstream that needs another request or check:
+//!
+//! ```yaml
+//! endpoints:
+//!   - fullnode.mainnet.sui.io
+//!   - host: secretsmanager.us-east-1.amazonaws.com
+//!     probe:
+//!       path: /ping
+//!       expect_body: healthy
+//!       timeout_secs: 10
+//!   - host: upload-relay.mainnet.walrus.space
+//!     critical: false
+//! ```
+//!
+//! Endpoints are critical unless marked otherwise: `/health_check` answers 503 while a
+//! critical endpoint is unreachable, and only reports the others.
+//!
+//! The file is parsed and validated once at boot. An invalid file is reported by
+//! `/health_check` rather than read as an empty list. `/admin/endpoints/reload` re-reads it
+//! at runtime, keeping the previous endpoints when the new file is invalid, and
+//! `/admin/endpoints/validate` checks a candidate file without applying it.
+//!
+//! Endpoints are probed concurrently, and `/health_check` reuses the results for
+//! `HEALTH_CHECK_CACHE_SECS`, so dead endpoints cost one probe timeout per cache period rather
+//! than one per endpoint and call.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use futures_util::future::join_all;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Endpoints file, relative to the working directory of the server.
+pub const DEFAULT_ENDPOINTS_FILE: &str = "allowed_endpoints.yaml";
+
+/// Timeout of a probe that does not set one.
+pub const DEFAULT_PROBE_TIMEOUT_SECS: u64 = 5;
+
+/// Upper bound for a configured probe timeout.
+pub const MAX_PROBE_TIMEOUT_SECS: u64 = 60;
+
+/// HTTP method of a probe.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProbeMethod {
+    #[default]
+    Get,
+    Head,
+    Post,
+    Options,
+}
+
+impl From<ProbeMethod> for reqwest::Method {
+    fn from(method: ProbeMethod) -> Self {
+        match method {
+            ProbeMethod::Get => reqwest::Method::GET,
+            ProbeMethod::Head => reqwest::Method::HEAD,
+            ProbeMethod::Post => reqwest::Method::POST,
+            ProbeMethod::Options => reqwest::Method::OPTIONS,
+        }
+    }
+}
+
+/// How `/health_check` decides that an endpoint is reachable: a request to
+/// `https://<host><path>` answered within the timeout with the expected status, any 2xx by
+/// default, and a body containing `expect_body`, ignoring case, when set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Probe {
+    pub method: ProbeMethod,
+    pub path: String,
+    pub expect_status: Option<u16>,
+    pub expect_body: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for Probe {
+    fn default() -> Self {
+        Self {
+            method: ProbeMethod::Get,
+            path: "/".to_string(),
+            expect_status: None,
+            expect_body: None,
+            timeout_secs: DEFAULT_PROBE_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl Probe {
+    fn validate(&self) -> Result<(), String> {
+        if !self.path.starts_with('/') {
+            return Err(format!("probe path {:?} must start with /", self.path));
+        }
+        if self.expect_status.is_some_and(|status| !(100..=599).contains(&status)) {
+            return Err("probe expect_status must be an HTTP status".to_string());
+        }
+        if !(1..=MAX_PROBE_TIMEOUT_SECS).contains(&self.timeout_secs) {
+            return Err(format!("probe timeout_secs must be 1 to {}", MAX_PROBE_TIMEOUT_SECS));
+        }
+        Ok(())
+    }
+}
+
+/// An allowed endpoint and how to probe it. In the file, an entry is either the bare host or
+/// a map with `host`, `probe` and `critical`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Endpoint {
+    /// Host, optionally with a port, e.g. `fullnode.mainnet.sui.io` or `localhost:6333`
+    pub host: String,
+    pub probe: Probe,
+    /// Whether the enclave is unhealthy while this endpoint is unreachable, true by default
+    pub critical: bool,
+}
+
+/// Map form of an entry.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EndpointEntry {
+    host: String,
+    #[serde(default)]
+    probe: Probe,
+    #[serde(default = "default_critical")]
+    critical: bool,
+}
+
+fn default_critical() -> bool {
+    true
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EndpointVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EndpointVisitor {
+            type Value = Endpoint;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a host or a map with host, probe and critical")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, host: &str) -> Result<Endpoint, E> {
+                Ok(Endpoint {
+                    host: host.to_string(),
+                    probe: Probe::default(),
+                    critical: true,
+                })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Endpoint, A::Error> {
+                let entry = EndpointEntry::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                Ok(Endpoint {
+                    host: entry.host,
+                    probe: entry.probe,
+                    critical: entry.critical,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(EndpointVisitor)
+    }
+}
+
+/// Validated content of the endpoints file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowedEndpoints {
+    pub endpoints: Vec<Endpoint>,
+}
+
+/// Layout of the file, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EndpointsFile {
+    /// `null` when every entry is commented out
+    #[serde(default)]
+    endpoints: Option<Vec<Endpoint>>,
+}
+
+impl AllowedEndpoints {
+    /// Parse and validate the YAML content of an endpoints file, reporting every invalid
+    /// entry at once.
+    pub fn parse(yaml: &str) -> Result<Self, EnclaveError> {
+        // A file holding only comments has no document at all
+        let blank = yaml.lines().map(str::trim).all(|line| line.is_empty() || line.starts_with('#'));
+        if blank {
+            return Ok(Self::default());
+        }
+        let file: EndpointsFile = serde_yaml::from_str(yaml)
+            .map_err(|e| EnclaveError::ConfigError(format!("Invalid endpoints file: {}", e)))?;
+        let endpoints = file.endpoints.unwrap_or_default();
+
+        let mut seen = BTreeSet::new();
+        let problems: Vec<String> = endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let valid = validate_host(&endpoint.host).and_then(|_| endpoint.probe.validate());
+                match valid {
+                    Err(e) => Some(format!("{}: {}", endpoint.host, e)),
+                    Ok(()) if !seen.insert(endpoint.host.to_ascii_lowercase()) => {
+                        Some(format!("{}: listed twice", endpoint.host))
+                    }
+                    Ok(()) => None,
+                }
+            })
+            .collect();
+        if !problems.is_empty() {
+            return Err(EnclaveError::ConfigError(format!(
+                "Invalid endpoints file: {}",
+                problems.join("; ")
+            )));
+        }
+        Ok(Self { endpoints })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, EnclaveError> {
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|e| EnclaveError::ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::parse(&yaml)
+    }
+
+    /// Hosts of the endpoints, in file order.
+    pub fn hosts(&self) -> Vec<&str> {
+        self.endpoints.iter().map(|endpoint| endpoint.host.as_str()).collect()
+    }
+}
+
+/// A host name or IP address with an optional port, without scheme or path.
+fn validate_host(endpoint: &str) -> Result<(), String> {
+    if endpoint.contains("://") {
+        return Err("expected a host without scheme".to_string());
+    }
+    let (host, port) = match endpoint.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (endpoint, None),
+    };
+    if host.is_empty() {
+        return Err("empty host".to_string());
+    }
+    if let Some(c) = host.chars().find(|c| !(c.is_ascii_alphanumeric() || *c == '.' || *c == '-')) {
+        return Err(format!("invalid character {:?} in host", c));
+    }
+    if host.split('.').any(|label| label.is_empty() || label.starts_with('-') || label.ends_with('-')) {
+        return Err("invalid host name".to_string());
+    }
+    match port.map(str::parse::<u16>) {
+        Some(Ok(0)) | Some(Err(_)) => Err("invalid port".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// State of the endpoints file, reported by `/health_check`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct EndpointsStatus {
+    pub path: String,
+    /// Whether the last load succeeded
+    pub valid: bool,
+    /// Endpoints in use, from the last valid file
+    pub endpoints: usize,
+    /// When the endpoints in use were loaded, `None` if no file was ever valid
+    pub loaded_at_ms: Option<u64>,
+    /// Why the last load failed
+    pub error: Option<String>,
+}
+
+struct Loaded {
+    endpoints: Arc<AllowedEndpoints>,
+    loaded_at_ms: Option<u64>,
+    error: Option<String>,
+}
+
+/// Default of `HEALTH_CHECK_CACHE_SECS`.
+pub const DEFAULT_HEALTH_CHECK_CACHE_SECS: u64 = 10;
+
+/// Reachability of each endpoint and when it was probed.
+#[derive(Debug, Clone)]
+pub struct ProbeResults {
+    pub status: HashMap<String, bool>,
+    pub probed_at_ms: u64,
+    probed_at: Instant,
+    /// Endpoints probed, so results of a file since reloaded are not reused
+    endpoints: Arc<AllowedEndpoints>,
+}
+
+/// Endpoints in use, the outcome of the last load of their file and the last probe results.
+pub struct EndpointRegistry {
+    path: PathBuf,
+    loaded: Mutex<Loaded>,
+    probe_cache_ttl: Duration,
+    probes: Mutex<Option<ProbeResults>>,
+    /// Held while probing, so concurrent health checks share one round of probes
+    probing: tokio::sync::Mutex<()>,
+}
+
+impl Default for EndpointRegistry {
+    fn default() -> Self {
+        Self::new(PathBuf::from(DEFAULT_ENDPOINTS_FILE), AllowedEndpoints::default())
+    }
+}
+
+impl EndpointRegistry {
+    /// Registry of endpoints already loaded from `path`.
+    pub fn new(path: PathBuf, endpoints: AllowedEndpoints) -> Self {
+        Self {
+            path,
+            loaded: Mutex::new(Loaded {
+                endpoints: Arc::new(endpoints),
+                loaded_at_ms: Some(current_timestamp_ms()),
+                error: None,
+            }),
+            probe_cache_ttl: Duration::from_secs(DEFAULT_HEALTH_CHECK_CACHE_SECS),
+            probes: Mutex::new(None),
+            probing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Load `path`. An invalid file leaves no endpoints and is reported by [Self::status].
+    pub fn load(path: PathBuf) -> Self {
+        let registry = Self {
+            loaded: Mutex::new(Loaded {
+                endpoints: Arc::default(),
+                loaded_at_ms: None,
+                error: None,
+            }),
+            ..Self::new(path, AllowedEndpoints::default())
+        };
+        let _ = registry.reload();
+        registry
+    }
+
+    /// Reuse probe results for `ttl`, probing on every call when zero.
+    pub fn with_probe_cache(mut self, ttl: Duration) -> Self {
+        self.probe_cache_ttl = ttl;
+        self
+    }
+
+    pub fn current(&self) -> Arc<AllowedEndpoints> {
+        self.loaded.lock().unwrap().endpoints.clone()
+    }
+
+    pub fn status(&self) -> EndpointsStatus {
+        let loaded = self.loaded.lock().unwrap();
+        EndpointsStatus {
+            path: self.path.display().to_string(),
+            valid: loaded.error.is_none(),
+            endpoints: loaded.endpoints.endpoints.len(),
+            loaded_at_ms: loaded.loaded_at_ms,
+            error: loaded.error.clone(),
+        }
+    }
+
+    /// Re-read the file. When it is invalid the previous endpoints stay in use and the error
+    /// is both returned and recorded.
+    pub fn reload(&self) -> Result<Arc<AllowedEndpoints>, EnclaveError> {
+        let result = AllowedEndpoints::load(&self.path);
+        let mut loaded = self.loaded.lock().unwrap();
+        match result {
+            Ok(endpoints) => {
+                loaded.endpoints = Arc::new(endpoints);
+                loaded.loaded_at_ms = Some(current_timestamp_ms());
+                loaded.error = None;
+                Ok(loaded.endpoints.clone())
+            }
+            Err(e) => {
+                let message = e.status_and_message().1;
+                loaded.error = Some(message.clone());
+                Err(EnclaveError::ConfigError(message))
+            }
+        }
+    }
+
+    /// Probe results of the current endpoints, reused when younger than the cache period.
+    pub async fn probe(&self, client: &Client) -> ProbeResults {
+        let endpoints = self.current();
+        if let Some(results) = self.cached(&endpoints) {
+            return results;
+        }
+        let _probing = self.probing.lock().await;
+        // Another health check may have probed while this one waited
+        if let Some(results) = self.cached(&endpoints) {
+            return results;
+        }
+        let results = ProbeResults {
+            status: check_endpoints(client, &endpoints).await,
+            probed_at_ms: current_timestamp_ms(),
+            probed_at: Instant::now(),
+            endpoints,
+        };
+        *self.probes.lock().unwrap() = Some(results.clone());
+        results
+    }
+
+    /// Last probe results of the current endpoints however old, without probing.
+    pub fn last_probe(&self) -> Option<ProbeResults> {
+        let endpoints = self.current();
+        self.probes
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|results| Arc::ptr_eq(&results.endpoints, &endpoints))
+    }
+
+    fn cached(&self, endpoints: &Arc<AllowedEndpoints>) -> Option<ProbeResults> {
+        self.last_probe()
+            .filter(|results| Arc::ptr_eq(&results.endpoints, endpoints))
+            .filter(|results| results.probed_at.elapsed() < self.probe_cache_ttl)
+    }
+}
+
+/// Client for connectivity probes; each probe sets its own timeout.
+pub fn probe_client() -> Result<Client, EnclaveError> {
+    Client::builder()
+        .build()
+        .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// Run the probe of `endpoint`, returning why it failed.
+async fn probe(client: &Client, endpoint: &Endpoint) -> Result<(), String> {
+    let probe = &endpoint.probe;
+    let url = format!("https://{}{}", endpoint.host, probe.path);
+    let response = client
+        .request(probe.method.into(), &url)
+        .timeout(Duration::from_secs(probe.timeout_secs))
+        .send()
+        .await
+        .map_err(|e| format!("failed to connect: {}", e))?;
+    let status = response.status();
+    let status_ok = match probe.expect_status {
+        Some(expected) => status.as_u16() == expected,
+        None => status.is_success(),
+    };
+    if !status_ok {
+        return Err(format!("unexpected status {}", status));
+    }
+    if let Some(expected) = &probe.expect_body {
+        let body = response.text().await.map_err(|e| format!("failed to read body: {}", e))?;
+        if !body.to_lowercase().contains(&expected.to_lowercase()) {
+            return Err(format!("body does not contain {:?}", expected));
+        }
+    }
+    Ok(())
+}
+
+/// Check connectivity to every endpoint with its probe, all probes at once so the check
+/// takes as long as the slowest one.
+pub async fn check_endpoints(client: &Client, endpoints: &AllowedEndpoints) -> HashMap<String, bool> {
+    let probes = endpoints.endpoints.iter().map(|endpoint| async move {
+        let result = probe(client, endpoint).await;
+        if let Err(e) = &result {
+            info!("Probe of {} failed: {}", endpoint.host, e);
+        }
+        info!("Checked endpoint {}: reachable = {}", endpoint.host, result.is_ok());
+        (endpoint.host.clone(), result.is_ok())
+    });
+    join_all(probes).await.into_iter().collect()
+}
+
+/// Response of `/admin/endpoints/reload`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EndpointsReloadResponse {
+    pub status: EndpointsStatus,
+    /// Reachability of the reloaded endpoints
+    pub endpoints_status: HashMap<String, bool>,
+}
+
+/// Re-read the endpoints file and probe the new endpoints. Requires the admin token.
+pub async fn reload_endpoints(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<EndpointsReloadResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let previous = state.endpoints.current();
+    let result = async {
+        let endpoints = state.endpoints.reload()?;
+        state.audit_log.record(
+            "endpoints_reloaded",
+            &state.endpoints.status().path,
+            serde_json::json!({ "previous": previous.hosts(), "endpoints": endpoints.hosts() }),
+        );
+        Ok(EndpointsReloadResponse {
+            status: state.endpoints.status(),
+            endpoints_status: check_endpoints(&probe_client()?, &endpoints).await,
+        })
+    }
+    .await;
+    ctx.respond(result)
+}
+
+/// Validate a candidate endpoints file, sent as the request body, without applying it.
+/// Requires the admin token.
+pub async fn validate_endpoints(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> ApiResponse<AllowedEndpoints> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.respond(AllowedEndpoints::parse(&body).map_err(|e| EnclaveError::BadRequest(e.status_and_message().1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shipped_file() {
+        let endpoints = AllowedEndpoints::parse(include_str!("../allowed_endpoints.yaml")).unwrap();
+        assert!(endpoints.hosts().contains(&"fullnode.mainnet.sui.io"));
+        assert!(endpoints.hosts().iter().all(|host| !host.starts_with('#')));
+    }
+
+    #[test]
+    fn test_parse_probes() {
+        let yaml = "endpoints:\n  - a.example.com\n  - host: kms.us-east-1.amazonaws.com\n    probe:\n      method: HEAD\n      path: /ping\n      expect_status: 204\n      expect_body: healthy\n      timeout_secs: 10\n  - host: b.example.com\n    critical: false\n";
+        let endpoints = AllowedEndpoints::parse(yaml).unwrap();
+        assert_eq!(endpoints.hosts(), ["a.example.com", "kms.us-east-1.amazonaws.com", "b.example.com"]);
+        let critical: Vec<bool> = endpoints.endpoints.iter().map(|endpoint| endpoint.critical).collect();
+        assert_eq!(critical, [true, true, false]);
+        assert_eq!(endpoints.endpoints[0].probe, Probe::default());
+        assert_eq!(endpoints.endpoints[2].probe, Probe::default());
+        assert_eq!(
+            endpoints.endpoints[1].probe,
+            Probe {
+                method: ProbeMethod::Head,
+                path: "/ping".to_string(),
+                expect_status: Some(204),
+                expect_body: Some("healthy".to_string()),
+                timeout_secs: 10,
+            }
+        );
+
+        // Unknown probe fields and methods are schema errors; bad values are listed per host
+        assert!(AllowedEndpoints::parse("endpoints:\n  - host: a.example.com\n    probe:\n      status: 200\n").is_err());
+        assert!(AllowedEndpoints::parse("endpoints:\n  - host: a.example.com\n    probe:\n      method: DELETE\n").is_err());
+        assert!(AllowedEndpoints::parse("endpoints:\n  - host: a.example.com\n    prob: {}\n").is_err());
+        let yaml = "endpoints:\n  - host: a.example.com\n    probe:\n      path: ping\n  - host: b.example.com\n    probe:\n      timeout_secs: 0\n  - host: c.example.com\n    probe:\n      expect_status: 42\n";
+        let message = AllowedEndpoints::parse(yaml).unwrap_err().status_and_message().1;
+        for reported in ["a.example.com: probe path", "b.example.com: probe timeout_secs", "c.example.com: probe expect_status"] {
+            assert!(message.contains(reported), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_parse_empty_files() {
+        assert_eq!(AllowedEndpoints::parse("# nothing yet\n\n").unwrap(), AllowedEndpoints::default());
+        assert_eq!(AllowedEndpoints::parse("endpoints:\n  # - a.example.com\n").unwrap(), AllowedEndpoints::default());
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_files() {
+        // Unknown sections and wrong types are schema errors, not an empty list
+        assert!(AllowedEndpoints::parse("endpoint:\n  - a.example.com\n").is_err());
+        assert!(AllowedEndpoints::parse("endpoints: a.example.com\n").is_err());
+        assert!(AllowedEndpoints::parse("endpoints:\n  - [a]\n").is_err());
+
+        let yaml = "endpoints:\n  - https://a.example.com\n  - b.example.com/path\n  - c.example.com:0\n  - ok.example.com\n  - OK.example.com\n  - localhost:6333\n";
+        let EnclaveError::ConfigError(message) = AllowedEndpoints::parse(yaml).unwrap_err() else {
+            panic!("expected a config error");
+        };
+        for reported in ["https://a.example.com", "b.example.com/path", "c.example.com:0", "OK.example.com: listed twice"] {
+            assert!(message.contains(reported), "{}", message);
+        }
+        assert!(!message.contains("localhost:6333"));
+    }
+
+    #[test]
+    fn test_reload_keeps_previous_endpoints_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("allowed_endpoints.yaml");
+        let registry = EndpointRegistry::load(path.clone());
+        assert!(!registry.status().valid);
+        assert_eq!(registry.status().loaded_at_ms, None);
+
+        std::fs::write(&path, "endpoints:\n  - a.example.com\n").unwrap();
+        assert_eq!(registry.reload().unwrap().hosts(), ["a.example.com"]);
+        let status = registry.status();
+        assert!(status.valid && status.error.is_none());
+        assert_eq!(status.endpoints, 1);
+
+        std::fs::write(&path, "endpoints: [\n").unwrap();
+        assert!(registry.reload().is_err());
+        let status = registry.status();
+        assert!(!status.valid);
+        assert!(status.error.unwrap().contains("Invalid endpoints file"));
+        assert_eq!(registry.current().hosts(), ["a.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_probe_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("allowed_endpoints.yaml");
+        std::fs::write(&path, "endpoints:\n").unwrap();
+        let registry = EndpointRegistry::load(path.clone()).with_probe_cache(Duration::from_secs(60));
+        let client = probe_client().unwrap();
+        assert!(registry.last_probe().is_none());
+
+        let first = registry.probe(&client).await;
+        assert!(first.status.is_empty());
+        assert_eq!(registry.probe(&client).await.probed_at, first.probed_at);
+        assert_eq!(registry.last_probe().unwrap().probed_at, first.probed_at);
+
+        // Results of the previous file are neither reused nor reported
+        registry.reload().unwrap();
+        assert!(registry.last_probe().is_none());
+        let reloaded = registry.probe(&client).await;
+        assert!(reloaded.probed_at >= first.probed_at);
+        assert!(Arc::ptr_eq(&reloaded.endpoints, &registry.current()));
+    }
+}
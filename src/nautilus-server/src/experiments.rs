@@ -0,0 +1,308 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A/B experiments over retrieval parameters. Named profiles each receive a percentage of
+//! retrieval traffic, assigned deterministically from the masked query ID so a query and the
+//! feedback given on it land in the same profile. Traffic outside every profile runs under
+//! [DEFAULT_PROFILE]. Latency and failures are tracked per profile here; result quality
+//! comes from relevance feedback (see [crate::feedback]).
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::feedback::PrecisionStats;
+use crate::metrics::Histogram;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Profile name reported for traffic not assigned to any configured profile.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Upper bounds of the retrieval latency histogram buckets in milliseconds.
+pub const LATENCY_BUCKETS_MS: [f64; 9] = [
+    100.0, 250.0, 500.0, 1_000.0, 2_500.0, 5_000.0, 10_000.0, 30_000.0, 60_000.0,
+];
+
+/// Retrieval parameters under evaluation. Unset parameters keep the task defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalProfile {
+    pub name: String,
+    /// Share of retrieval traffic assigned to this profile, 0-100
+    pub percent: u8,
+    /// Embedding model used for query vectors
+    pub model: Option<String>,
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub rerank: bool,
+    /// Weights of the fused result lists, e.g. `{"dense": 0.7, "sparse": 0.3}`
+    pub fusion_weights: Option<BTreeMap<String, f64>>,
+}
+
+#[derive(Debug, Clone)]
+struct ProfileStats {
+    requests: u64,
+    failures: u64,
+    latency_ms: Histogram,
+}
+
+impl Default for ProfileStats {
+    fn default() -> Self {
+        Self {
+            requests: 0,
+            failures: 0,
+            latency_ms: Histogram::new(&LATENCY_BUCKETS_MS),
+        }
+    }
+}
+
+/// Configured profiles and the traffic observed under each.
+#[derive(Debug, Clone, Default)]
+pub struct RetrievalExperiments {
+    profiles: Vec<RetrievalProfile>,
+    stats: Arc<Mutex<HashMap<String, ProfileStats>>>,
+}
+
+/// Per-profile entry of `/experiments`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub name: String,
+    pub percent: u8,
+    /// `None` for the default profile
+    pub profile: Option<RetrievalProfile>,
+    pub requests: u64,
+    pub failures: u64,
+    pub mean_latency_ms: Option<f64>,
+    /// Precision of the results judged through `/feedback`
+    pub feedback: PrecisionStats,
+}
+
+/// Bucket 0-99 of a masked query ID.
+fn bucket(query_hash: &str) -> u8 {
+    let prefix = query_hash.get(..8).unwrap_or(query_hash);
+    (u32::from_str_radix(prefix, 16).unwrap_or(0) % 100) as u8
+}
+
+impl RetrievalExperiments {
+    /// Validate profile names and that the percentages add up to at most 100.
+    pub fn new(profiles: Vec<RetrievalProfile>) -> anyhow::Result<Self> {
+        let total: u32 = profiles.iter().map(|p| p.percent as u32).sum();
+        if total > 100 {
+            anyhow::bail!("Retrieval profile percentages add up to {}, expected at most 100", total);
+        }
+        for (i, profile) in profiles.iter().enumerate() {
+            if profile.name.is_empty() || profile.name == DEFAULT_PROFILE {
+                anyhow::bail!("Invalid retrieval profile name {:?}", profile.name);
+            }
+            if profiles[..i].iter().any(|p| p.name == profile.name) {
+                anyhow::bail!("Duplicate retrieval profile {:?}", profile.name);
+            }
+        }
+        Ok(Self {
+            profiles,
+            stats: Arc::default(),
+        })
+    }
+
+    /// Parse profiles from a JSON array, as given in `RETRIEVAL_PROFILES`.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Self::new(serde_json::from_str(json)?)
+    }
+
+    pub fn profiles(&self) -> &[RetrievalProfile] {
+        &self.profiles
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RetrievalProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Profile a masked query ID falls into, `None` for default traffic.
+    pub fn assign(&self, query_hash: &str) -> Option<&RetrievalProfile> {
+        let mut bucket = bucket(query_hash);
+        for profile in &self.profiles {
+            if bucket < profile.percent {
+                return Some(profile);
+            }
+            bucket -= profile.percent;
+        }
+        None
+    }
+
+    /// Pick the profile for a request: an explicitly requested profile wins over the
+    /// assignment from the masked query ID.
+    pub fn select(
+        &self,
+        requested: Option<&str>,
+        query_hash: Option<&str>,
+    ) -> Result<Option<&RetrievalProfile>, EnclaveError> {
+        match requested {
+            Some(DEFAULT_PROFILE) => Ok(None),
+            Some(name) => self
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| EnclaveError::BadRequest(format!("Unknown retrieval profile: {}", name))),
+            None => Ok(query_hash.and_then(|hash| self.assign(hash))),
+        }
+    }
+
+    /// Record a finished retrieval under `profile`.
+    pub fn observe(&self, profile: &str, latency_ms: u64, success: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(profile.to_string()).or_default();
+        entry.requests += 1;
+        if !success {
+            entry.failures += 1;
+        }
+        entry.latency_ms.observe(latency_ms as f64);
+    }
+
+    /// Report every profile, the default one first, with feedback precision by profile.
+    pub fn report(&self, feedback: &HashMap<String, PrecisionStats>) -> Vec<ProfileReport> {
+        let stats = self.stats.lock().unwrap();
+        let default_percent = 100 - self.profiles.iter().map(|p| p.percent).sum::<u8>();
+        std::iter::once((DEFAULT_PROFILE, default_percent, None))
+            .chain(self.profiles.iter().map(|p| (p.name.as_str(), p.percent, Some(p.clone()))))
+            .map(|(name, percent, profile)| {
+                let stats = stats.get(name).cloned().unwrap_or_default();
+                let count = stats.latency_ms.count();
+                ProfileReport {
+                    name: name.to_string(),
+                    percent,
+                    profile,
+                    requests: stats.requests,
+                    failures: stats.failures,
+                    mean_latency_ms: (count > 0).then(|| stats.latency_ms.sum() / count as f64),
+                    feedback: feedback.get(name).cloned().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Render per-profile metrics in the Prometheus text format.
+    pub fn render(&self, feedback: &HashMap<String, PrecisionStats>) -> String {
+        let mut out = String::new();
+        let reports = self.report(feedback);
+        let _ = writeln!(out, "# HELP nautilus_retrieval_requests_total Retrievals by parameter profile.");
+        let _ = writeln!(out, "# TYPE nautilus_retrieval_requests_total counter");
+        for report in &reports {
+            let _ = writeln!(out, "nautilus_retrieval_requests_total{{profile=\"{}\"}} {}", report.name, report.requests);
+        }
+        let _ = writeln!(out, "# HELP nautilus_retrieval_failures_total Failed retrievals by parameter profile.");
+        let _ = writeln!(out, "# TYPE nautilus_retrieval_failures_total counter");
+        for report in &reports {
+            let _ = writeln!(out, "nautilus_retrieval_failures_total{{profile=\"{}\"}} {}", report.name, report.failures);
+        }
+        let name = "nautilus_retrieval_latency_ms";
+        let _ = writeln!(out, "# HELP {} Retrieval task latency by parameter profile.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (profile, stats) in self.stats.lock().unwrap().iter() {
+            stats.latency_ms.render(&mut out, name, &format!("profile=\"{}\"", profile));
+        }
+        let _ = writeln!(out, "# HELP nautilus_retrieval_feedback_precision Share of judged results marked relevant, by parameter profile.");
+        let _ = writeln!(out, "# TYPE nautilus_retrieval_feedback_precision gauge");
+        for report in &reports {
+            if let Some(precision) = report.feedback.precision {
+                let _ = writeln!(out, "nautilus_retrieval_feedback_precision{{profile=\"{}\"}} {}", report.name, precision);
+            }
+        }
+        out
+    }
+}
+
+/// Configured retrieval profiles with their traffic, latency and feedback precision.
+pub async fn experiments(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+) -> ApiResponse<Vec<ProfileReport>> {
+    ctx.ok(state.experiments.report(&state.feedback.precision_by_profile()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feedback::mask_id;
+
+    fn profile(name: &str, percent: u8) -> RetrievalProfile {
+        RetrievalProfile {
+            name: name.to_string(),
+            percent,
+            model: None,
+            top_k: None,
+            rerank: false,
+            fusion_weights: None,
+        }
+    }
+
+    #[test]
+    fn test_profile_validation() {
+        assert!(RetrievalExperiments::new(vec![profile("a", 60), profile("b", 50)]).is_err());
+        assert!(RetrievalExperiments::new(vec![profile("a", 10), profile("a", 10)]).is_err());
+        assert!(RetrievalExperiments::new(vec![profile(DEFAULT_PROFILE, 10)]).is_err());
+        let experiments = RetrievalExperiments::from_json(
+            r#"[{"name":"rerank","percent":20,"top_k":50,"rerank":true,"fusion_weights":{"dense":0.7,"sparse":0.3}}]"#,
+        )
+        .unwrap();
+        let rerank = experiments.get("rerank").unwrap();
+        assert!(rerank.rerank);
+        assert_eq!(rerank.top_k, Some(50));
+    }
+
+    #[test]
+    fn test_assignment_split() {
+        let experiments = RetrievalExperiments::new(vec![profile("a", 30), profile("b", 20)]).unwrap();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for i in 0..10_000 {
+            let hash = mask_id("salt", &format!("query-{}", i));
+            let name = experiments.assign(&hash).map_or(DEFAULT_PROFILE, |p| p.name.as_str());
+            *counts.entry(name).or_default() += 1;
+        }
+        assert!((2_700..3_300).contains(&counts["a"]));
+        assert!((1_700..2_300).contains(&counts["b"]));
+        assert!((4_700..5_300).contains(&counts[DEFAULT_PROFILE]));
+
+        // Assignment is stable for a query
+        let hash = mask_id("salt", "query-1");
+        assert_eq!(experiments.assign(&hash), experiments.assign(&hash));
+    }
+
+    #[test]
+    fn test_select() {
+        let experiments = RetrievalExperiments::new(vec![profile("all", 100)]).unwrap();
+        assert_eq!(experiments.select(None, Some("00")).unwrap().unwrap().name, "all");
+        assert_eq!(experiments.select(None, None).unwrap(), None);
+        assert_eq!(experiments.select(Some(DEFAULT_PROFILE), Some("00")).unwrap(), None);
+        assert!(experiments.select(Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn test_report_and_render() {
+        let experiments = RetrievalExperiments::new(vec![profile("a", 40)]).unwrap();
+        experiments.observe("a", 200, true);
+        experiments.observe("a", 400, false);
+        experiments.observe(DEFAULT_PROFILE, 300, true);
+        let feedback = HashMap::from([(
+            "a".to_string(),
+            PrecisionStats {
+                judged: 4,
+                relevant: 3,
+                precision: Some(0.75),
+            },
+        )]);
+
+        let reports = experiments.report(&feedback);
+        assert_eq!(reports[0].name, DEFAULT_PROFILE);
+        assert_eq!(reports[0].percent, 60);
+        assert_eq!(reports[1].requests, 2);
+        assert_eq!(reports[1].failures, 1);
+        assert_eq!(reports[1].mean_latency_ms, Some(300.0));
+        assert_eq!(reports[1].feedback.precision, Some(0.75));
+
+        let text = experiments.render(&feedback);
+        assert!(text.contains("nautilus_retrieval_requests_total{profile=\"a\"} 2"));
+        assert!(text.contains("nautilus_retrieval_latency_ms_bucket{profile=\"a\",le=\"250\"} 1"));
+        assert!(text.contains("nautilus_retrieval_feedback_precision{profile=\"a\"} 0.75"));
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Relevance feedback on retrieval results. Query and result IDs are masked with the
+//! server's ID mask salt before they are stored, so feedback cannot be traced back to
+//! users or messages. Qdrant point IDs are random UUIDs and are kept as-is so positive
+//! feedback can seed a recommendation query.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::ProcessDataRequest;
+use crate::experiments::DEFAULT_PROFILE;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::Json;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Upper bound on judgments in a single feedback request.
+pub const MAX_JUDGMENTS_PER_REQUEST: usize = 1000;
+
+/// Relevance judgment for one returned result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultJudgment {
+    /// Identifier of the result as returned to the client, e.g. `<blob_id>:<message_index>`
+    pub result_id: String,
+    pub relevant: bool,
+    /// Qdrant point ID of the result, used for recommendation re-queries
+    pub point_id: Option<String>,
+}
+
+/// Payload of `/feedback`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRequest {
+    /// Identifier of the query the results were returned for
+    pub query_id: String,
+    pub judgments: Vec<ResultJudgment>,
+    /// Retrieval profile the results came from, as returned with them. Defaults to the
+    /// profile `query_id` is assigned to.
+    pub profile: Option<String>,
+}
+
+/// Precision over the judged results.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrecisionStats {
+    pub judged: usize,
+    pub relevant: usize,
+    /// `relevant / judged`, `None` without judgments
+    pub precision: Option<f64>,
+}
+
+impl PrecisionStats {
+    fn new(judged: usize, relevant: usize) -> Self {
+        Self {
+            judged,
+            relevant,
+            precision: (judged > 0).then(|| relevant as f64 / judged as f64),
+        }
+    }
+}
+
+/// Response of `/feedback`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackResponse {
+    /// Masked query ID under which the feedback was stored
+    pub query_hash: String,
+    pub recorded: usize,
+    /// Precision of this query over all feedback received for it
+    pub query: PrecisionStats,
+    /// Point IDs judged relevant and irrelevant, for a Qdrant recommendation query
+    pub positive_point_ids: Vec<String>,
+    pub negative_point_ids: Vec<String>,
+}
+
+/// Response of `/feedback/metrics`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackMetrics {
+    pub queries: usize,
+    /// Micro-averaged precision over all judgments
+    pub overall: PrecisionStats,
+    /// Mean of the per-query precisions
+    pub mean_query_precision: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+struct Judgment {
+    relevant: bool,
+    point_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct QueryFeedback {
+    profile: String,
+    /// Latest judgment by masked result ID
+    judgments: HashMap<String, Judgment>,
+}
+
+/// Feedback by masked query ID.
+#[derive(Debug, Default)]
+pub struct FeedbackStore {
+    queries: Mutex<HashMap<String, QueryFeedback>>,
+}
+
+/// Mask an identifier with the server salt.
+pub fn mask_id(salt: &str, id: &str) -> String {
+    let mut hash = Sha3_256::default();
+    hash.update(salt.as_bytes());
+    hash.update([0u8]);
+    hash.update(id.as_bytes());
+    Hex::encode(hash.finalize().digest)
+}
+
+impl FeedbackStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store judgments for a query retrieved under `profile`, replacing earlier judgments
+    /// of the same results.
+    pub fn record(&self, salt: &str, profile: &str, request: &FeedbackRequest) -> FeedbackResponse {
+        let query_hash = mask_id(salt, &request.query_id);
+        let mut queries = self.queries.lock().unwrap();
+        let query = queries.entry(query_hash.clone()).or_default();
+        query.profile = profile.to_string();
+        let judgments = &mut query.judgments;
+        for judgment in &request.judgments {
+            judgments.insert(
+                mask_id(salt, &judgment.result_id),
+                Judgment {
+                    relevant: judgment.relevant,
+                    point_id: judgment.point_id.clone(),
+                },
+            );
+        }
+
+        let points = |relevant: bool| -> Vec<String> {
+            judgments
+                .values()
+                .filter(|j| j.relevant == relevant)
+                .filter_map(|j| j.point_id.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        FeedbackResponse {
+            recorded: request.judgments.len(),
+            query: precision_of(judgments),
+            positive_point_ids: points(true),
+            negative_point_ids: points(false),
+            query_hash,
+        }
+    }
+
+    pub fn metrics(&self) -> FeedbackMetrics {
+        let queries = self.queries.lock().unwrap();
+        let per_query: Vec<PrecisionStats> = queries.values().map(|q| precision_of(&q.judgments)).collect();
+        let judged = per_query.iter().map(|q| q.judged).sum();
+        let relevant = per_query.iter().map(|q| q.relevant).sum();
+        let precisions: Vec<f64> = per_query.iter().filter_map(|q| q.precision).collect();
+        FeedbackMetrics {
+            queries: queries.len(),
+            overall: PrecisionStats::new(judged, relevant),
+            mean_query_precision: (!precisions.is_empty())
+                .then(|| precisions.iter().sum::<f64>() / precisions.len() as f64),
+        }
+    }
+
+    /// Micro-averaged precision by retrieval profile.
+    pub fn precision_by_profile(&self) -> HashMap<String, PrecisionStats> {
+        let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+        for query in self.queries.lock().unwrap().values() {
+            let stats = precision_of(&query.judgments);
+            let entry = counts.entry(query.profile.clone()).or_default();
+            entry.0 += stats.judged;
+            entry.1 += stats.relevant;
+        }
+        counts
+            .into_iter()
+            .map(|(profile, (judged, relevant))| (profile, PrecisionStats::new(judged, relevant)))
+            .collect()
+    }
+
+    /// Render the aggregate metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let metrics = self.metrics();
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP nautilus_feedback_judgments_total Relevance judgments received.");
+        let _ = writeln!(out, "# TYPE nautilus_feedback_judgments_total gauge");
+        let _ = writeln!(out, "nautilus_feedback_judgments_total{{relevant=\"true\"}} {}", metrics.overall.relevant);
+        let _ = writeln!(
+            out,
+            "nautilus_feedback_judgments_total{{relevant=\"false\"}} {}",
+            metrics.overall.judged - metrics.overall.relevant
+        );
+        if let Some(precision) = metrics.overall.precision {
+            let _ = writeln!(out, "# HELP nautilus_feedback_precision Share of judged results marked relevant.");
+            let _ = writeln!(out, "# TYPE nautilus_feedback_precision gauge");
+            let _ = writeln!(out, "nautilus_feedback_precision {}", precision);
+        }
+        out
+    }
+}
+
+fn precision_of(judgments: &HashMap<String, Judgment>) -> PrecisionStats {
+    PrecisionStats::new(judgments.len(), judgments.values().filter(|j| j.relevant).count())
+}
+
+/// Record relevance feedback for a query's results.
+pub async fn submit_feedback(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ProcessDataRequest<FeedbackRequest>>,
+) -> ApiResponse<FeedbackResponse> {
+    let request = request.payload;
+    let result = if request.query_id.is_empty() {
+        Err(EnclaveError::BadRequest("query_id must not be empty".to_string()))
+    } else if request.judgments.is_empty() || request.judgments.len() > MAX_JUDGMENTS_PER_REQUEST {
+        Err(EnclaveError::BadRequest(format!(
+            "Expected between 1 and {} judgments",
+            MAX_JUDGMENTS_PER_REQUEST
+        )))
+    } else {
+        let query_hash = mask_id(state.id_mask_salt(), &request.query_id);
+        state
+            .experiments
+            .select(request.profile.as_deref(), Some(&query_hash))
+            .map(|profile| {
+                let profile = profile.map_or(DEFAULT_PROFILE, |p| p.name.as_str());
+                state.feedback.record(state.id_mask_salt(), profile, &request)
+            })
+    };
+    ctx.respond(result)
+}
+
+/// Aggregated precision over all feedback.
+pub async fn feedback_metrics(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+) -> ApiResponse<FeedbackMetrics> {
+    ctx.ok(state.feedback.metrics())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn judgment(result_id: &str, relevant: bool, point_id: Option<&str>) -> ResultJudgment {
+        ResultJudgment {
+            result_id: result_id.to_string(),
+            relevant,
+            point_id: point_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_mask_id() {
+        assert_eq!(mask_id("salt", "blob:1"), mask_id("salt", "blob:1"));
+        assert_ne!(mask_id("salt", "blob:1"), mask_id("other", "blob:1"));
+        assert!(!mask_id("salt", "blob:1").contains("blob"));
+    }
+
+    #[test]
+    fn test_record_feedback() {
+        let store = FeedbackStore::new();
+        let request = FeedbackRequest {
+            query_id: "q1".to_string(),
+            judgments: vec![
+                judgment("blob:1", true, Some("p1")),
+                judgment("blob:2", false, Some("p2")),
+                judgment("blob:3", true, None),
+            ],
+            profile: None,
+        };
+        let response = store.record("salt", DEFAULT_PROFILE, &request);
+        assert_eq!(response.query_hash, mask_id("salt", "q1"));
+        assert_eq!(response.query, PrecisionStats::new(3, 2));
+        assert_eq!(response.positive_point_ids, vec!["p1"]);
+        assert_eq!(response.negative_point_ids, vec!["p2"]);
+
+        // A later judgment of the same result replaces the earlier one
+        let update = FeedbackRequest {
+            query_id: "q1".to_string(),
+            judgments: vec![judgment("blob:2", true, Some("p2"))],
+            profile: None,
+        };
+        assert_eq!(store.record("salt", DEFAULT_PROFILE, &update).query, PrecisionStats::new(3, 3));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let store = FeedbackStore::new();
+        assert_eq!(store.metrics().overall.precision, None);
+        store.record(
+            "salt",
+            DEFAULT_PROFILE,
+            &FeedbackRequest {
+                query_id: "q1".to_string(),
+                judgments: vec![judgment("a", true, None), judgment("b", true, None)],
+                profile: None,
+            },
+        );
+        store.record(
+            "salt",
+            DEFAULT_PROFILE,
+            &FeedbackRequest {
+                query_id: "q2".to_string(),
+                judgments: vec![judgment("a", true, None), judgment("b", false, None)],
+                profile: None,
+            },
+        );
+        let metrics = store.metrics();
+        assert_eq!(metrics.queries, 2);
+        assert_eq!(metrics.overall, PrecisionStats::new(4, 3));
+        assert_eq!(metrics.mean_query_precision, Some(0.75));
+        assert!(store.render().contains("nautilus_feedback_precision 0.75"));
+        assert_eq!(store.precision_by_profile()[DEFAULT_PROFILE], PrecisionStats::new(4, 3));
+    }
+}
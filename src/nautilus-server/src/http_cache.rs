@@ -0,0 +1,178 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP caching of responses that stay valid for a while, like attestation documents. A
+//! [Freshness] sets `Cache-Control`, `Age` and `Last-Modified` from when the response was
+//! generated, and answers a request whose `If-Modified-Since` is no older with 304 Not
+//! Modified, so clients polling for the attestation only download it when it changed.
+
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Lifetime of a cacheable response, in milliseconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freshness {
+    pub generated_at_ms: u64,
+    /// Until when clients may reuse the response without asking again
+    pub expires_at_ms: u64,
+}
+
+impl Freshness {
+    /// Response generated at `generated_at_ms` and reusable for `max_age_secs` after that.
+    pub fn new(generated_at_ms: u64, max_age_secs: u64) -> Self {
+        Self {
+            generated_at_ms,
+            expires_at_ms: generated_at_ms.saturating_add(max_age_secs.saturating_mul(1000)),
+        }
+    }
+
+    /// Caching headers at `now_ms`. `max-age` counts from generation, `Age` tells how much
+    /// of it is used up.
+    pub fn headers(&self, now_ms: u64) -> HeaderMap {
+        let max_age = self.expires_at_ms.saturating_sub(self.generated_at_ms) / 1000;
+        let age = now_ms.saturating_sub(self.generated_at_ms) / 1000;
+        let mut headers = HeaderMap::new();
+        let values = [
+            (header::CACHE_CONTROL, format!("public, max-age={}", max_age)),
+            (header::AGE, age.to_string()),
+            (header::LAST_MODIFIED, http_date(self.generated_at_ms)),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+
+    /// Whether the request's `If-Modified-Since` is no older than the response. HTTP dates
+    /// have second precision, so the generation time is truncated to the second.
+    pub fn not_modified(&self, request: &HeaderMap) -> bool {
+        request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_http_date)
+            .is_some_and(|since_ms| since_ms >= self.generated_at_ms - self.generated_at_ms % 1000)
+    }
+
+    /// `response` with the caching headers, or an empty 304 with them when the request
+    /// already has it.
+    pub fn respond(&self, request: &HeaderMap, now_ms: u64, response: impl IntoResponse) -> Response {
+        let headers = self.headers(now_ms);
+        if self.not_modified(request) {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+        (headers, response).into_response()
+    }
+}
+
+/// IMF-fixdate of `timestamp_ms`, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn http_date(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let days = secs / DAY_SECS;
+    let (year, month, day) = civil_from_days(days);
+    let time = secs % DAY_SECS;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // 1970-01-01 was a Thursday
+        WEEKDAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Milliseconds since the epoch of an IMF-fixdate. The obsolete RFC 850 and asctime forms
+/// are not parsed; callers then ignore the header, as HTTP allows.
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [weekday, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    if !weekday.ends_with(',') {
+        return None;
+    }
+    let day: u64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| name == month)? as u64 + 1;
+    let year: u64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if year < 1970 || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 || time.next().is_some() {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * DAY_SECS + hours * 3600 + minutes * 60 + seconds;
+    Some(secs * 1000)
+}
+
+/// Year, month and day of a day count since 1970-01-01, after Howard Hinnant's algorithm.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of [civil_from_days], for years from 1970.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(http_date(784_111_777_123), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(http_date(1_709_164_800_000), "Thu, 29 Feb 2024 00:00:00 GMT");
+        for timestamp_ms in [0, 784_111_777_000, 951_782_400_000, 1_709_164_800_000, 4_102_444_799_000] {
+            assert_eq!(parse_http_date(&http_date(timestamp_ms)), Some(timestamp_ms));
+        }
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let freshness = Freshness::new(784_111_777_500, 300);
+        let headers = freshness.headers(784_111_837_600);
+        assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=300");
+        assert_eq!(headers[header::AGE], "60");
+        assert_eq!(headers[header::LAST_MODIFIED], "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        let since = |value: &'static str| HeaderMap::from_iter([(header::IF_MODIFIED_SINCE, HeaderValue::from_static(value))]);
+        assert!(!freshness.not_modified(&HeaderMap::new()));
+        assert!(freshness.not_modified(&since("Sun, 06 Nov 1994 08:49:37 GMT")));
+        assert!(freshness.not_modified(&since("Sun, 06 Nov 1994 09:00:00 GMT")));
+        assert!(!freshness.not_modified(&since("Sun, 06 Nov 1994 08:49:36 GMT")));
+        assert!(!freshness.not_modified(&since("yesterday")));
+
+        let response = freshness.respond(&since("Sun, 06 Nov 1994 08:49:37 GMT"), 784_111_837_600, "document");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::AGE], "60");
+        let response = freshness.respond(&HeaderMap::new(), 784_111_837_600, "document");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "public, max-age=300");
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Idempotency keys for `/embedding_ingest`. A retried ingest re-embeds and re-upserts the
+//! whole blob, so a request carrying an `Idempotency-Key` header, or a `requestId` in its
+//! payload, is answered with the job of the first request with that key for
+//! `IDEMPOTENCY_TTL_SECS`: still queued or running, or succeeded with its signed response.
+//! A key is bound to the canonical hash of its payload and refused for any other payload. A
+//! failed job, or one dropped by the job retention, releases its key so a retry runs again.
+//! With `STORAGE_PATH` set, keys are persisted and outlive a server restart, and a
+//! replication primary sends them to its standby.
+
+use crate::canonical::canonical_hash_of;
+use crate::common::current_timestamp_ms;
+use crate::jobs::{JobRecord, JobStatus, JobStore};
+use crate::storage::{Storage, StoredKey};
+use crate::EnclaveError;
+use axum::http::HeaderMap;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Header carrying the idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Default time a key is remembered.
+pub const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Longest key accepted.
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Keys remembered at most; the oldest are forgotten first.
+const MAX_IDEMPOTENCY_KEYS: usize = 10_000;
+
+struct Entry {
+    fingerprint: [u8; 32],
+    job_id: String,
+    /// Succeeded job, kept here so it outlives the job retention
+    record: Option<JobRecord>,
+    created_at: Instant,
+    /// Wall clock time of `created_at`, for storage
+    created_at_ms: u64,
+}
+
+impl Entry {
+    /// Entry of a key stored or replicated at `now_ms`, None once older than `ttl`.
+    fn restore(stored: StoredKey, ttl: Duration, now: Instant, now_ms: u64) -> Option<(String, Entry)> {
+        let age = Duration::from_millis(now_ms.saturating_sub(stored.created_at_ms));
+        let created_at = now.checked_sub(age).filter(|_| age < ttl)?;
+        let entry = Entry {
+            fingerprint: stored.fingerprint,
+            job_id: stored.job_id,
+            record: stored.record,
+            created_at,
+            created_at_ms: stored.created_at_ms,
+        };
+        Some((stored.key, entry))
+    }
+
+    fn stored(&self, key: &str) -> StoredKey {
+        StoredKey {
+            key: key.to_string(),
+            fingerprint: self.fingerprint,
+            job_id: self.job_id.clone(),
+            record: self.record.clone(),
+            created_at_ms: self.created_at_ms,
+        }
+    }
+}
+
+/// Job started for a request, or the one it repeats.
+#[derive(Debug)]
+pub enum Claim {
+    Started(JobRecord),
+    Replayed(JobRecord),
+}
+
+/// Idempotency keys and the job started for each.
+pub struct IdempotencyStore {
+    /// Zero disables idempotency keys
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+    storage: Option<Arc<Storage>>,
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_IDEMPOTENCY_TTL_SECS))
+    }
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            storage: None,
+        }
+    }
+
+    /// Load the keys persisted in `storage` that have not expired, and persist changes
+    /// there from now on.
+    pub fn with_storage(mut self, storage: Arc<Storage>) -> Result<Self, EnclaveError> {
+        let now = Instant::now();
+        let now_ms = current_timestamp_ms();
+        let mut expired = Vec::new();
+        let entries = self.entries.get_mut().unwrap();
+        for stored in storage.idempotency_keys()? {
+            let key = stored.key.clone();
+            match Entry::restore(stored, self.ttl, now, now_ms) {
+                Some((key, entry)) => {
+                    entries.insert(key, entry);
+                }
+                None => expired.push(key),
+            }
+        }
+        storage.delete_idempotency_keys(&expired);
+        self.storage = Some(storage);
+        Ok(self)
+    }
+
+    fn persist(&self, key: &str, entry: &Entry) {
+        if let Some(storage) = &self.storage {
+            storage.put_idempotency_key(&entry.stored(key));
+        }
+    }
+
+    fn forget(&self, keys: &[String]) {
+        if let Some(storage) = &self.storage {
+            storage.delete_idempotency_keys(keys);
+        }
+    }
+
+    /// Idempotency key of a request, from the header or else the payload, `None` when the
+    /// request has none or keys are disabled.
+    pub fn key(&self, headers: &HeaderMap, request_id: Option<&str>) -> Result<Option<String>, EnclaveError> {
+        if self.ttl.is_zero() {
+            return Ok(None);
+        }
+        let key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .map_err(|_| EnclaveError::BadRequest("Idempotency-Key must be ASCII".to_string()))?,
+            ),
+            None => request_id,
+        };
+        match key.map(str::trim) {
+            None => Ok(None),
+            Some(key) if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH => Err(EnclaveError::BadRequest(
+                format!("Idempotency key must be 1 to {} characters", MAX_IDEMPOTENCY_KEY_LENGTH),
+            )),
+            Some(key) => Ok(Some(key.to_string())),
+        }
+    }
+
+    /// Return the live job of `key`, or run `start` and remember the job it returns.
+    /// `start` runs under the store's lock, so concurrent duplicates start a single job.
+    pub fn claim(
+        &self,
+        jobs: &JobStore,
+        key: &str,
+        payload: &impl Serialize,
+        start: impl FnOnce() -> Result<JobRecord, EnclaveError>,
+    ) -> Result<Claim, EnclaveError> {
+        let fingerprint = canonical_hash_of(payload)?;
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let mut expired = Vec::new();
+        entries.retain(|key, entry| {
+            let live = now.duration_since(entry.created_at) < self.ttl;
+            if !live {
+                expired.push(key.clone());
+            }
+            live
+        });
+        self.forget(&expired);
+        if let Some(entry) = entries.get_mut(key) {
+            if entry.fingerprint != fingerprint {
+                return Err(EnclaveError::BadRequest(
+                    "Idempotency key was already used with a different payload".to_string(),
+                ));
+            }
+            match entry.record.clone().or_else(|| jobs.get(&entry.job_id)) {
+                Some(record) if record.status == JobStatus::Succeeded => {
+                    if entry.record.is_none() {
+                        entry.record = Some(record.clone());
+                        self.persist(key, entry);
+                    }
+                    return Ok(Claim::Replayed(record));
+                }
+                Some(record) if record.status != JobStatus::Failed => return Ok(Claim::Replayed(record)),
+                _ => {}
+            }
+        }
+
+        let record = start()?;
+        if entries.len() >= MAX_IDEMPOTENCY_KEYS && !entries.contains_key(key) {
+            let oldest = entries.iter().min_by_key(|(_, entry)| entry.created_at).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+                self.forget(&[oldest]);
+            }
+        }
+        let entry = Entry {
+            fingerprint,
+            job_id: record.id.clone(),
+            record: None,
+            created_at: now,
+            created_at_ms: current_timestamp_ms(),
+        };
+        self.persist(key, &entry);
+        entries.insert(key.to_string(), entry);
+        Ok(Claim::Started(record))
+    }
+
+    /// Keep the finished job of `key` if it succeeded, or release the key if it failed.
+    pub fn finish(&self, key: &str, record: Option<JobRecord>) {
+        let mut entries = self.entries.lock().unwrap();
+        match record {
+            Some(record) if record.status == JobStatus::Succeeded => {
+                if let Some(entry) = entries.get_mut(key).filter(|entry| entry.job_id == record.id) {
+                    entry.record = Some(record);
+                    self.persist(key, entry);
+                }
+            }
+            _ => {
+                if entries.remove(key).is_some() {
+                    self.forget(&[key.to_string()]);
+                }
+            }
+        }
+    }
+
+    /// Succeeded job `id` kept for its key, once the job store may have dropped it.
+    pub fn job(&self, id: &str) -> Option<JobRecord> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .filter(|entry| entry.created_at.elapsed() < self.ttl)
+            .find_map(|entry| entry.record.clone().filter(|record| record.id == id))
+    }
+
+    /// Live keys, as replicated to a standby.
+    pub fn snapshot(&self) -> Vec<StoredKey> {
+        let entries = self.entries.lock().unwrap();
+        let keys = entries
+            .iter()
+            .filter(|(_, entry)| entry.created_at.elapsed() < self.ttl)
+            .map(|(key, entry)| entry.stored(key))
+            .collect();
+        keys
+    }
+
+    /// Insert or replace keys replicated from a primary, skipping those already expired.
+    pub fn replicate(&self, keys: Vec<StoredKey>) {
+        let (now, now_ms) = (Instant::now(), current_timestamp_ms());
+        let mut entries = self.entries.lock().unwrap();
+        for (key, entry) in keys
+            .into_iter()
+            .filter_map(|stored| Entry::restore(stored, self.ttl, now, now_ms))
+        {
+            self.persist(&key, &entry);
+            entries.insert(key, entry);
+        }
+    }
+
+    /// Number of keys remembered.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_sources() {
+        let store = IdempotencyStore::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(store.key(&headers, None).unwrap(), None);
+        assert_eq!(store.key(&headers, Some("payload")).unwrap().as_deref(), Some("payload"));
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "header".parse().unwrap());
+        assert_eq!(store.key(&headers, Some("payload")).unwrap().as_deref(), Some("header"));
+        assert!(store.key(&HeaderMap::new(), Some(&"k".repeat(256))).is_err());
+        assert_eq!(IdempotencyStore::new(Duration::ZERO).key(&headers, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_claim_replays_live_jobs() {
+        let store = IdempotencyStore::default();
+        let jobs = JobStore::new();
+        let payload = json!({ "walrusBlobId": "blob" });
+        let start = || Ok(jobs.create("embedding_ingest"));
+
+        let Claim::Started(first) = store.claim(&jobs, "key", &payload, start).unwrap() else {
+            panic!("expected a new job");
+        };
+        // A duplicate gets the running job, a different payload is refused
+        let Claim::Replayed(replayed) = store.claim(&jobs, "key", &payload, start).unwrap() else {
+            panic!("expected a replay");
+        };
+        assert_eq!(replayed.id, first.id);
+        assert!(store.claim(&jobs, "key", &json!({ "walrusBlobId": "other" }), start).is_err());
+
+        // A failed job releases its key
+        jobs.complete(&first.id, Err(EnclaveError::Internal("boom".to_string())));
+        store.finish("key", jobs.get(&first.id));
+        let Claim::Started(second) = store.claim(&jobs, "key", &payload, start).unwrap() else {
+            panic!("expected a new job");
+        };
+
+        // A succeeded job is replayed even once the job store dropped it
+        let mut succeeded = jobs.get(&second.id).unwrap();
+        succeeded.status = JobStatus::Succeeded;
+        store.finish("key", Some(succeeded));
+        let dropped = JobStore::new();
+        let Claim::Replayed(replayed) = store.claim(&dropped, "key", &payload, || panic!("rerun")).unwrap() else {
+            panic!("expected a replay");
+        };
+        assert_eq!((replayed.id.clone(), replayed.status), (second.id.clone(), JobStatus::Succeeded));
+        assert!(store.job(&second.id).is_some());
+        assert!(store.job(&first.id).is_none());
+        assert_eq!(store.len(), 1);
+    }
+}
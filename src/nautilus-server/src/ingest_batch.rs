@@ -0,0 +1,306 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! `/embedding_ingest_batch`: ingest several blobs in one call. Each item runs as its own
+//! embedding task, like a call to `/embedding_ingest` with the shared options of the batch,
+//! at most `parallelism` at a time and still through the task scheduler. The call returns
+//! once every item finished, with a signed response or an error per item in request order.
+
+use crate::address_limits::AddressOperation;
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::app::{execute_embedding_ingest, with_attestation_ref, EmbeddingIngestRequest, TaskResponse};
+use crate::common::{current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest, ProcessedDataResponse};
+use crate::receipts::ReceiptContext;
+use crate::scheduler::Priority;
+use crate::validation::{check_address, check_blob_id, check_threshold, FieldError, FieldErrors, ValidJson, Validate};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Items accepted in one batch.
+pub const MAX_BATCH_ITEMS: usize = 100;
+
+/// Items ingested at once when the request does not say.
+pub const DEFAULT_BATCH_PARALLELISM: usize = 4;
+
+/// Upper bound for the requested parallelism.
+pub const MAX_BATCH_PARALLELISM: usize = 16;
+
+/// One blob to ingest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchIngestItem {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    #[serde(rename = "onChainFileObjId")]
+    pub on_chain_file_obj_id: String,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: String,
+    /// Walrus end epoch of the blob; its vectors are reaped once it has expired
+    pub blob_expiry_epoch: Option<u64>,
+}
+
+/// Blobs to ingest and the options shared by all of them, as in [EmbeddingIngestRequest].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingIngestBatchRequest {
+    pub items: Vec<BatchIngestItem>,
+    pub threshold: String,
+    pub timeout_secs: Option<u64>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: Option<u32>,
+    /// Scheduling priority, defaults to normal
+    pub priority: Option<Priority>,
+    /// Anchor a signed execution receipt per item to Walrus, defaults to ANCHOR_RECEIPTS
+    pub anchor_receipt: Option<bool>,
+    /// Qdrant collection to ingest into, one of QDRANT_COLLECTIONS
+    pub collection: Option<String>,
+    /// Hex encoded X25519 public key; message text is stored encrypted to it
+    pub encryption_public_key: Option<String>,
+    /// Also return the task output undecoded, base64 encoded in `raw_output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<bool>,
+    /// Items ingested at once, defaults to [DEFAULT_BATCH_PARALLELISM] and capped at
+    /// [MAX_BATCH_PARALLELISM]
+    pub parallelism: Option<usize>,
+}
+
+impl EmbeddingIngestBatchRequest {
+    fn validate(&self) -> Result<(), EnclaveError> {
+        if self.items.is_empty() {
+            return Err(EnclaveError::BadRequest("No items to ingest".to_string()));
+        }
+        if self.items.len() > MAX_BATCH_ITEMS {
+            return Err(EnclaveError::BadRequest(format!(
+                "Too many items: {}, at most {} per batch",
+                self.items.len(),
+                MAX_BATCH_ITEMS
+            )));
+        }
+        Ok(())
+    }
+
+    fn parallelism(&self) -> usize {
+        self.parallelism
+            .unwrap_or(DEFAULT_BATCH_PARALLELISM)
+            .clamp(1, MAX_BATCH_PARALLELISM)
+    }
+
+    /// The single ingest request of `item`.
+    fn item_request(&self, item: &BatchIngestItem) -> EmbeddingIngestRequest {
+        EmbeddingIngestRequest {
+            walrus_blob_id: item.walrus_blob_id.clone(),
+            on_chain_file_obj_id: item.on_chain_file_obj_id.clone(),
+            policy_object_id: item.policy_object_id.clone(),
+            threshold: self.threshold.clone(),
+            timeout_secs: self.timeout_secs,
+            batch_size: self.batch_size,
+            priority: self.priority,
+            anchor_receipt: self.anchor_receipt,
+            collection: self.collection.clone(),
+            blob_expiry_epoch: item.blob_expiry_epoch,
+            encryption_public_key: self.encryption_public_key.clone(),
+            raw: self.raw,
+            request_id: None,
+        }
+    }
+}
+
+impl Validate for EmbeddingIngestBatchRequest {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        for (i, item) in self.items.iter().enumerate() {
+            errors.check(format!("items[{}].walrusBlobId", i), check_blob_id(&item.walrus_blob_id));
+            errors.check(format!("items[{}].onChainFileObjId", i), check_address(&item.on_chain_file_obj_id));
+            errors.check(format!("items[{}].policyObjectId", i), check_address(&item.policy_object_id));
+        }
+        errors.check("threshold", check_threshold(&self.threshold));
+        errors.into_vec()
+    }
+}
+
+/// Outcome of one item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchIngestItemResult {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    /// Whether the task ran and reported success
+    pub success: bool,
+    /// Task response signed under [IntentScope::EmbeddingIngest], also for a task that
+    /// reported a failure
+    pub response: Option<ProcessedDataResponse<IntentMessage<TaskResponse>>>,
+    /// Why the task could not be run or did not succeed
+    pub error: Option<String>,
+}
+
+/// Response of `/embedding_ingest_batch`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingIngestBatchResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// In request order
+    pub items: Vec<BatchIngestItemResult>,
+}
+
+/// Run one item and sign its response.
+async fn ingest_item(state: &AppState, request: EmbeddingIngestRequest) -> BatchIngestItemResult {
+    let walrus_blob_id = request.walrus_blob_id.clone();
+    let receipt = ReceiptContext::start(state, "embedding_ingest", &request, request.anchor_receipt);
+    let result = execute_embedding_ingest(state, request, None, None).await;
+    let key = state.keys.current();
+    let result = receipt.attach(state, result).await.and_then(|response| {
+        state.key_usage.acquire(IntentScope::EmbeddingIngest)?;
+        let response = with_attestation_ref(state, &key, response);
+        let error = task_error(&response);
+        let signed = to_signed_response(&*key, response, current_timestamp_ms(), IntentScope::EmbeddingIngest)?;
+        Ok((signed, error))
+    });
+    match result {
+        Ok((signed, error)) => BatchIngestItemResult {
+            walrus_blob_id,
+            success: error.is_none(),
+            response: Some(signed),
+            error,
+        },
+        Err(e) => {
+            tracing::warn!("Batch ingest of blob {} failed: {:?}", walrus_blob_id, e);
+            BatchIngestItemResult {
+                walrus_blob_id,
+                success: false,
+                response: None,
+                error: Some(e.status_and_message().1),
+            }
+        }
+    }
+}
+
+/// Failure reported by a task that ran, if any.
+fn task_error(response: &TaskResponse) -> Option<String> {
+    if response.exit_code != 0 {
+        return Some(format!("Task exited with code {}", response.exit_code));
+    }
+    match &response.data["status"] {
+        serde_json::Value::String(status) if status == "success" => None,
+        _ => Some(
+            response.data["error"]
+                .as_str()
+                .unwrap_or("Task did not report success")
+                .to_string(),
+        ),
+    }
+}
+
+/// Ingest every item of the batch and return their results once all finished.
+pub async fn embedding_ingest_batch(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    ValidJson(request): ValidJson<ProcessDataRequest<EmbeddingIngestBatchRequest>>,
+) -> ApiResponse<EmbeddingIngestBatchResponse> {
+    let batch = request.payload;
+    let checked = batch
+        .validate()
+        .and_then(|_| state.qdrant_collection(batch.collection.as_deref()).map(|_| ()))
+        .and_then(|_| state.scheduler.check_capacity())
+        .and_then(|_| {
+            let addresses = batch.items.iter().map(|item| item.policy_object_id.as_str());
+            state.address_limits.acquire(AddressOperation::Ingest, addresses)
+        });
+    if let Err(e) = checked {
+        return ctx.error(e);
+    }
+
+    // Polled within this request, so every task is recorded under its request ID
+    let items: Vec<BatchIngestItemResult> = futures_util::stream::iter(&batch.items)
+        .map(|item| ingest_item(&state, batch.item_request(item)))
+        .buffered(batch.parallelism())
+        .collect()
+        .await;
+    let succeeded = items.iter().filter(|item| item.success).count();
+    ctx.ok(EmbeddingIngestBatchResponse {
+        succeeded,
+        failed: items.len() - succeeded,
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    fn batch(items: usize) -> EmbeddingIngestBatchRequest {
+        serde_json::from_value(serde_json::json!({
+            "items": (0..items).map(|i| serde_json::json!({
+                "walrusBlobId": format!("blob-{}", i),
+                "onChainFileObjId": "0x1",
+                "policyObjectId": "0x2",
+            })).collect::<Vec<_>>(),
+            "threshold": "2",
+            "batchSize": 10,
+            "collection": "messages",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_item_request() {
+        let mut request = batch(2);
+        request.items[1].blob_expiry_epoch = Some(42);
+        let item = request.item_request(&request.items[1]);
+        assert_eq!(item.walrus_blob_id, "blob-1");
+        assert_eq!(item.policy_object_id, "0x2");
+        assert_eq!(item.threshold, "2");
+        assert_eq!(item.batch_size, Some(10));
+        assert_eq!(item.collection.as_deref(), Some("messages"));
+        assert_eq!(item.blob_expiry_epoch, Some(42));
+    }
+
+    #[test]
+    fn test_validate_and_parallelism() {
+        assert!(batch(0).validate().is_err());
+        assert!(batch(MAX_BATCH_ITEMS + 1).validate().is_err());
+        let mut request = batch(MAX_BATCH_ITEMS);
+        assert!(request.validate().is_ok());
+        assert_eq!(request.parallelism(), DEFAULT_BATCH_PARALLELISM);
+        request.parallelism = Some(0);
+        assert_eq!(request.parallelism(), 1);
+        request.parallelism = Some(1000);
+        assert_eq!(request.parallelism(), MAX_BATCH_PARALLELISM);
+    }
+
+    #[test]
+    fn test_task_error() {
+        let response = |exit_code, data| TaskResponse {
+            status: "success".to_string(),
+            data,
+            stderr: String::new(),
+            exit_code,
+            execution_time_ms: 1,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        };
+        assert_eq!(task_error(&response(0, serde_json::json!({ "status": "success" }))), None);
+        assert_eq!(
+            task_error(&response(0, serde_json::json!({ "status": "failed", "error": "Blob not found" }))),
+            Some("Blob not found".to_string())
+        );
+        assert!(task_error(&response(1, serde_json::json!({ "status": "success" }))).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_item_errors_are_reported_per_item() {
+        let state = test_app_state();
+        let mut request = batch(1);
+        request.encryption_public_key = Some("not hex".to_string());
+        let result = ingest_item(&state, request.item_request(&request.items[0])).await;
+        assert_eq!(result.walrus_blob_id, "blob-0");
+        assert!(!result.success);
+        assert!(result.response.is_none());
+        assert!(result.error.is_some());
+    }
+}
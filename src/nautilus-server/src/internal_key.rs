@@ -0,0 +1,116 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Internal encryption secret key. `INTERNAL_ENCRYPTION_SECRET_KEY` (hex encoded 32 bytes) is
+//! a master key from which the enclave derives one key per [KeyPurpose] with HKDF-SHA3-256,
+//! so a single provisioned secret keeps every encrypted-at-rest store readable across
+//! restarts. A store configured with its own key (`CRASH_REPORT_KEY`,
+//! `PAYLOAD_ENCRYPTION_KEY`) keeps using it; the internal key replaces the fallback used when
+//! that is unset.
+
+use crate::EnclaveError;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hmac::{hkdf_sha3_256, HkdfIkm};
+use fastcrypto::traits::ToFromBytes;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+const HKDF_SALT: &[u8] = b"nautilus-internal-encryption";
+
+/// What a derived key encrypts. Each purpose gets an unrelated key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPurpose {
+    /// AES-256-GCM key of the crash report store
+    CrashReports,
+    /// Master key of the payload keyring, see [crate::payload_crypto]
+    PayloadEncryption,
+}
+
+impl KeyPurpose {
+    fn info(&self) -> &'static [u8] {
+        match self {
+            KeyPurpose::CrashReports => b"crash_reports",
+            KeyPurpose::PayloadEncryption => b"payload_encryption",
+        }
+    }
+}
+
+/// The internal master key, redacted in `Debug` output.
+#[derive(Clone)]
+pub struct InternalKey {
+    master: Vec<u8>,
+}
+
+impl InternalKey {
+    pub fn from_hex(hex: &str) -> Result<Self, EnclaveError> {
+        let invalid = || EnclaveError::ConfigError("Internal encryption key must be 32 hex encoded bytes".to_string());
+        let master = Hex::decode(hex).ok().filter(|bytes| bytes.len() == 32).ok_or_else(invalid)?;
+        HkdfIkm::from_bytes(&master).map_err(|_| invalid())?;
+        Ok(Self { master })
+    }
+
+    /// The 32 byte key of `purpose`.
+    pub fn derive(&self, purpose: KeyPurpose) -> Vec<u8> {
+        let ikm = HkdfIkm::from_bytes(&self.master).expect("master key was checked at load");
+        hkdf_sha3_256(&ikm, HKDF_SALT, purpose.info(), 32).expect("HKDF yields a 32 byte key")
+    }
+
+    /// The key of `purpose`, hex encoded like the dedicated key it stands in for.
+    pub fn derive_hex(&self, purpose: KeyPurpose) -> String {
+        Hex::encode(self.derive(purpose))
+    }
+}
+
+impl fmt::Debug for InternalKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InternalKey(******)")
+    }
+}
+
+/// Where the key of an encrypted store comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// The store's own variable
+    Dedicated,
+    /// Derived from `INTERNAL_ENCRYPTION_SECRET_KEY`
+    Internal,
+    /// Random per boot, data is unreadable after a restart
+    Random,
+    /// No key, the store is disabled
+    Unset,
+}
+
+/// Key sources of the encrypted stores, reported by `/config` and `/health_check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct EncryptionKeySources {
+    pub crash_reports: KeySource,
+    /// Stored message text, off when unset
+    pub payload_encryption: KeySource,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_derives_a_key_per_purpose() {
+        let key = InternalKey::from_hex(KEY).unwrap();
+        let crash = key.derive(KeyPurpose::CrashReports);
+        let payload = key.derive(KeyPurpose::PayloadEncryption);
+        assert_eq!(crash.len(), 32);
+        assert_ne!(crash, payload);
+        assert_ne!(Hex::encode(&crash), KEY);
+        assert_eq!(InternalKey::from_hex(KEY).unwrap().derive_hex(KeyPurpose::CrashReports), Hex::encode(crash));
+        assert_eq!(format!("{:?}", key), "InternalKey(******)");
+    }
+
+    #[test]
+    fn test_rejects_invalid_keys() {
+        assert!(InternalKey::from_hex("not hex").is_err());
+        assert!(InternalKey::from_hex(&KEY[..62]).is_err());
+    }
+}
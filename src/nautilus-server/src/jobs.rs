@@ -0,0 +1,594 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::app::{respond_task, TaskResponse};
+use crate::common::{current_timestamp_ms, IntentScope};
+use crate::retention::{CleanupReport, RetentionPolicy, RetentionStats};
+use crate::storage::Storage;
+use crate::warmup::WarmupReport;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+/// Default long-poll timeout for `/jobs/:id/wait`.
+pub const DEFAULT_WAIT_SECS: u64 = 30;
+/// Upper bound for the long-poll timeout, so connections are not held indefinitely.
+pub const MAX_WAIT_SECS: u64 = 120;
+/// Error of a job that was queued or running when the server stopped.
+pub const INTERRUPTED_JOB_ERROR: &str = "Interrupted by a server restart";
+
+/// Lifecycle of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    /// Whether the job will not change status anymore.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed)
+    }
+}
+
+/// Snapshot of a job.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobRecord {
+    pub id: String,
+    pub operation: String,
+    pub status: JobStatus,
+    pub created_at_ms: u64,
+    pub updated_at_ms: u64,
+    pub result: Option<TaskResponse>,
+    pub error: Option<String>,
+    /// Error of a failed job, served by `/jobs/:id/result` with its own status and details.
+    /// Missing for jobs interrupted by a restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub failure: Option<EnclaveError>,
+    /// Optimizer wait and warm-up searches run after a large ingest, see `INGEST_WARMUP_MIN_POINTS`
+    #[serde(default)]
+    pub warmup: Option<WarmupReport>,
+}
+
+struct JobEntry {
+    record: JobRecord,
+    /// Size of the record serialized as JSON, counted against `JOB_RETENTION_MAX_BYTES`
+    size_bytes: u64,
+    status_tx: watch::Sender<JobStatus>,
+}
+
+impl JobEntry {
+    fn new(record: JobRecord) -> Self {
+        let (status_tx, _) = watch::channel(record.status);
+        Self {
+            size_bytes: record_size(&record),
+            record,
+            status_tx,
+        }
+    }
+}
+
+fn record_size(record: &JobRecord) -> u64 {
+    serde_json::to_vec(record).map_or(0, |bytes| bytes.len() as u64)
+}
+
+/// In-memory job registry. Status changes are broadcast so callers can wait
+/// for a job to finish instead of polling. Finished jobs are kept until dropped by
+/// [JobStore::cleanup]. With [JobStore::with_storage], every change is also persisted.
+#[derive(Default)]
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, JobEntry>>,
+    retention: Mutex<RetentionStats>,
+    storage: Option<Arc<Storage>>,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the jobs persisted in `storage` and persist changes there from now on. Jobs a
+    /// previous run left queued or running were lost with it and are marked failed.
+    pub fn with_storage(mut self, storage: Arc<Storage>) -> Result<Self, EnclaveError> {
+        let jobs = self.jobs.get_mut().unwrap();
+        for mut record in storage.jobs()? {
+            if !record.status.is_terminal() {
+                record.status = JobStatus::Failed;
+                record.error = Some(INTERRUPTED_JOB_ERROR.to_string());
+                record.updated_at_ms = current_timestamp_ms();
+                storage.put_job(&record);
+            }
+            jobs.insert(record.id.clone(), JobEntry::new(record));
+        }
+        self.storage = Some(storage);
+        Ok(self)
+    }
+
+    fn persist(&self, record: &JobRecord) {
+        if let Some(storage) = &self.storage {
+            storage.put_job(record);
+        }
+    }
+
+    /// Register a new queued job for the given operation.
+    pub fn create(&self, operation: &str) -> JobRecord {
+        let now = current_timestamp_ms();
+        let record = JobRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            operation: operation.to_string(),
+            status: JobStatus::Queued,
+            created_at_ms: now,
+            updated_at_ms: now,
+            result: None,
+            error: None,
+            failure: None,
+            warmup: None,
+        };
+        self.persist(&record);
+        self.jobs.lock().unwrap().insert(record.id.clone(), JobEntry::new(record.clone()));
+        record
+    }
+
+    /// Get a snapshot of a job.
+    pub fn get(&self, id: &str) -> Option<JobRecord> {
+        self.jobs.lock().unwrap().get(id).map(|entry| entry.record.clone())
+    }
+
+    /// Snapshot of every job, for replication to a standby.
+    pub fn snapshot(&self) -> Vec<JobRecord> {
+        self.jobs.lock().unwrap().values().map(|entry| entry.record.clone()).collect()
+    }
+
+    /// Insert or replace jobs replicated from a primary, waking waiters of changed jobs.
+    pub fn replicate(&self, records: Vec<JobRecord>) {
+        // Written before taking the lock, so readers do not wait on SQLite
+        for record in &records {
+            self.persist(record);
+        }
+        let mut jobs = self.jobs.lock().unwrap();
+        for record in records {
+            match jobs.get_mut(&record.id) {
+                Some(entry) => {
+                    entry.status_tx.send_replace(record.status);
+                    entry.size_bytes = record_size(&record);
+                    entry.record = record;
+                }
+                None => {
+                    jobs.insert(record.id.clone(), JobEntry::new(record));
+                }
+            }
+        }
+    }
+
+    /// Mark a queued job as running.
+    pub fn mark_running(&self, id: &str) {
+        self.update(id, |record| record.status = JobStatus::Running);
+    }
+
+    /// Record the warm-up run after a job's ingest, before the job completes.
+    pub fn set_warmup(&self, id: &str, report: WarmupReport) {
+        self.update(id, |record| record.warmup = Some(report));
+    }
+
+    /// Record the outcome of a job.
+    pub fn complete(&self, id: &str, result: Result<TaskResponse, EnclaveError>) {
+        self.update(id, |record| match result {
+            Ok(response) => {
+                record.status = JobStatus::Succeeded;
+                record.result = Some(response);
+            }
+            Err(e) => {
+                record.status = JobStatus::Failed;
+                record.error = Some(e.clone().status_and_message().1);
+                record.failure = Some(e);
+            }
+        });
+    }
+
+    /// Drop finished jobs by `policy`: first the ones last updated over `max_age_secs` ago,
+    /// then the least recently updated ones until at most `max_count` jobs and `max_bytes`
+    /// of records are kept. Queued and running jobs are never dropped but count towards the
+    /// limits.
+    pub fn cleanup(&self, policy: &RetentionPolicy, now_ms: u64) -> CleanupReport {
+        let mut jobs = self.jobs.lock().unwrap();
+        let cutoff_ms = now_ms.saturating_sub(policy.max_age_secs.saturating_mul(1000));
+        let mut removed = Vec::new();
+        jobs.retain(|id, entry| {
+            let keep = !entry.record.status.is_terminal() || entry.record.updated_at_ms >= cutoff_ms;
+            if !keep {
+                removed.push(id.clone());
+            }
+            keep
+        });
+        let mut report = CleanupReport {
+            at_ms: now_ms,
+            expired: removed.len(),
+            retained: jobs.len(),
+            retained_bytes: jobs.values().map(|entry| entry.size_bytes).sum(),
+            ..Default::default()
+        };
+
+        let mut finished: Vec<(u64, String)> = jobs
+            .iter()
+            .filter(|(_, entry)| entry.record.status.is_terminal())
+            .map(|(id, entry)| (entry.record.updated_at_ms, id.clone()))
+            .collect();
+        finished.sort();
+        for (_, id) in finished {
+            let over_count = policy.max_count.is_some_and(|max| report.retained > max);
+            let over_bytes = policy.max_bytes.is_some_and(|max| report.retained_bytes > max);
+            if !over_count && !over_bytes {
+                break;
+            }
+            let entry = jobs.remove(&id).expect("finished job is in the store");
+            removed.push(id);
+            report.retained -= 1;
+            report.retained_bytes -= entry.size_bytes;
+            if over_count {
+                report.over_count += 1;
+            } else {
+                report.over_bytes += 1;
+            }
+        }
+        drop(jobs);
+        if let Some(storage) = &self.storage {
+            storage.delete_jobs(&removed);
+        }
+        self.retention.lock().unwrap().record(&report);
+        report
+    }
+
+    /// Jobs held and the size of their records.
+    pub fn usage(&self) -> (usize, u64) {
+        let jobs = self.jobs.lock().unwrap();
+        (jobs.len(), jobs.values().map(|entry| entry.size_bytes).sum())
+    }
+
+    /// Cleanup runs and the jobs they dropped.
+    pub fn retention_stats(&self) -> RetentionStats {
+        self.retention.lock().unwrap().clone()
+    }
+
+    /// Render the job and cleanup counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let (jobs, bytes) = self.usage();
+        let stats = self.retention_stats();
+        let mut out = String::new();
+        let gauges = [
+            ("nautilus_jobs_retained", "Jobs held in memory.", jobs as u64),
+            ("nautilus_jobs_retained_bytes", "Size of the job records held, serialized as JSON.", bytes),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        let name = "nautilus_job_cleanup_runs_total";
+        let _ = writeln!(out, "# HELP {} Job retention cleanups run.", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, stats.runs);
+        let name = "nautilus_jobs_removed_total";
+        let _ = writeln!(out, "# HELP {} Finished jobs dropped by the retention policy.", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (reason, value) in [
+            ("age", stats.expired),
+            ("count", stats.over_count),
+            ("bytes", stats.over_bytes),
+        ] {
+            let _ = writeln!(out, "{}{{reason=\"{}\"}} {}", name, reason, value);
+        }
+        out
+    }
+
+    /// Apply `f` to a job and persist the new record once the lock is released. A job is
+    /// only updated by the task running it, one step after the other, so the writes stay in
+    /// order.
+    fn update(&self, id: &str, f: impl FnOnce(&mut JobRecord)) {
+        let updated = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(entry) = jobs.get_mut(id) else { return };
+            f(&mut entry.record);
+            entry.record.updated_at_ms = current_timestamp_ms();
+            entry.size_bytes = record_size(&entry.record);
+            entry.status_tx.send_replace(entry.record.status);
+            entry.record.clone()
+        };
+        self.persist(&updated);
+    }
+
+    /// Wait until the job reaches a terminal state or the timeout elapses and return
+    /// its latest snapshot. Returns `None` if the job does not exist.
+    pub async fn wait(&self, id: &str, timeout: Duration) -> Option<JobRecord> {
+        let mut status_rx = self.jobs.lock().unwrap().get(id)?.status_tx.subscribe();
+        // A timeout or a dropped sender both just mean we report the current snapshot.
+        let _ = tokio::time::timeout(timeout, status_rx.wait_for(|status| status.is_terminal())).await;
+        self.get(id)
+    }
+}
+
+fn job_not_found(id: &str) -> EnclaveError {
+    EnclaveError::NotFound(format!("Job not found: {}", id))
+}
+
+/// Current snapshot of a job, including its result once it succeeded.
+pub async fn get_job(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResponse<JobRecord> {
+    let job = state.jobs.get(&id).or_else(|| state.idempotency.job(&id));
+    ctx.respond(job.ok_or_else(|| job_not_found(&id)))
+}
+
+/// Result of a finished job, served like the synchronous task endpoints (JSON envelope,
+/// or BCS when requested via `Accept`) and signed with the scope of the job's operation.
+/// Fails while the job is queued or running, and with the job's own error once it failed.
+pub async fn get_job_result(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    // Succeeded jobs with an idempotency key outlive the job retention
+    let job = state.jobs.get(&id).or_else(|| state.idempotency.job(&id));
+    let scope = job
+        .as_ref()
+        .map_or(IntentScope::Generic, |job| IntentScope::for_operation(&job.operation));
+    let result = match job {
+        None => Err(job_not_found(&id)),
+        Some(JobRecord {
+            result: Some(result), ..
+        }) => Ok(result),
+        Some(JobRecord {
+            status: JobStatus::Failed,
+            failure: Some(failure),
+            ..
+        }) => Err(failure),
+        Some(JobRecord {
+            status: JobStatus::Failed,
+            error,
+            ..
+        }) => Err(EnclaveError::Internal(format!(
+            "Job {} failed: {}",
+            id,
+            error.unwrap_or_default()
+        ))),
+        Some(job) => Err(EnclaveError::BadRequest(format!(
+            "Job {} has not finished, status: {:?}",
+            id, job.status
+        ))),
+    };
+    respond_task(&ctx, &state, &headers, scope, result)
+}
+
+/// Query parameters for the long-poll endpoint.
+#[derive(Debug, Deserialize)]
+pub struct WaitQuery {
+    /// Seconds to wait for completion, capped at [MAX_WAIT_SECS].
+    pub timeout: Option<u64>,
+}
+
+/// Response for the long-poll endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobWaitResponse {
+    pub job: JobRecord,
+    /// True if the timeout elapsed before the job reached a terminal state.
+    pub timed_out: bool,
+}
+
+/// Endpoint that holds the connection until the job finishes or the timeout elapses.
+pub async fn wait_for_job(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<WaitQuery>,
+) -> ApiResponse<JobWaitResponse> {
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(DEFAULT_WAIT_SECS).min(MAX_WAIT_SECS));
+    let result = match state.jobs.wait(&id, timeout).await {
+        Some(job) => Ok(JobWaitResponse {
+            timed_out: !job.status.is_terminal(),
+            job,
+        }),
+        None => Err(job_not_found(&id)),
+    };
+    ctx.respond(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task_response() -> TaskResponse {
+        TaskResponse {
+            status: "success".to_string(),
+            data: serde_json::json!({}),
+            stderr: "".to_string(),
+            exit_code: 0,
+            execution_time_ms: 10,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let store = JobStore::new();
+        let job = store.create("embedding");
+        assert_eq!(job.status, JobStatus::Queued);
+
+        store.mark_running(&job.id);
+        assert_eq!(store.get(&job.id).unwrap().status, JobStatus::Running);
+
+        store.complete(&job.id, Ok(task_response()));
+        let job = store.get(&job.id).unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert!(job.result.is_some());
+        assert!(store.get("missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wait_returns_on_completion() {
+        let store = Arc::new(JobStore::new());
+        let job = store.create("embedding");
+
+        let waiter = {
+            let store = store.clone();
+            let id = job.id.clone();
+            tokio::spawn(async move { store.wait(&id, Duration::from_secs(5)).await })
+        };
+        store.complete(&job.id, Err(EnclaveError::Internal("boom".to_string())));
+
+        let job = waiter.await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn test_prune_keeps_unfinished_jobs() {
+        let store = JobStore::new();
+        let finished = store.create("embedding_ingest");
+        store.complete(&finished.id, Ok(task_response()));
+        let running = store.create("embedding_ingest");
+        store.mark_running(&running.id);
+
+        let policy = RetentionPolicy {
+            max_age_secs: 0,
+            max_count: Some(0),
+            max_bytes: Some(0),
+        };
+        let report = store.cleanup(&policy, u64::MAX);
+        assert_eq!(report.expired, 1);
+        assert_eq!(report.retained, 1);
+        assert!(store.get(&finished.id).is_none());
+        assert!(store.get(&running.id).is_some());
+    }
+
+    #[test]
+    fn test_cleanup_drops_oldest_finished_jobs_over_limits() {
+        let store = JobStore::new();
+        let record = |i: u64, status| JobRecord {
+            id: format!("job-{}", i),
+            operation: "embedding_ingest".to_string(),
+            status,
+            created_at_ms: i,
+            updated_at_ms: i,
+            result: status.is_terminal().then(task_response),
+            error: None,
+            failure: None,
+            warmup: None,
+        };
+        let mut records: Vec<JobRecord> = (0..4).map(|i| record(i, JobStatus::Succeeded)).collect();
+        records.push(record(4, JobStatus::Queued));
+        store.replicate(records);
+
+        let mut policy = RetentionPolicy {
+            max_age_secs: 3600,
+            max_count: Some(3),
+            max_bytes: None,
+        };
+        let report = store.cleanup(&policy, 10_000);
+        assert_eq!((report.expired, report.over_count, report.over_bytes), (0, 2, 0));
+        assert_eq!(report.retained, 3);
+        assert!(store.get("job-0").is_none() && store.get("job-1").is_none());
+        assert!(store.get("job-3").is_some() && store.get("job-4").is_some());
+
+        // One finished job fits next to the queued one
+        let (_, bytes) = store.usage();
+        policy.max_count = None;
+        policy.max_bytes = Some(bytes - 1);
+        let report = store.cleanup(&policy, 10_000);
+        assert_eq!(report.over_bytes, 1);
+        assert!(store.get("job-2").is_none() && store.get("job-3").is_some());
+
+        let stats = store.retention_stats();
+        assert_eq!((stats.runs, stats.over_count, stats.over_bytes), (2, 2, 1));
+        let text = store.render();
+        assert!(text.contains("nautilus_jobs_retained 2"));
+        assert!(text.contains("nautilus_jobs_removed_total{reason=\"count\"} 2"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_result_keeps_its_error() {
+        let state = Arc::new(crate::test_app_state());
+        let job = state.jobs.create("embedding_ingest");
+        state.jobs.complete(
+            &job.id,
+            Err(EnclaveError::TaskFailed {
+                exit_code: 3,
+                stderr: "boom".to_string(),
+            }),
+        );
+        // Stored tagged with its error code, so it survives a restart and replication
+        let stored = serde_json::to_value(state.jobs.get(&job.id).unwrap()).unwrap();
+        assert_eq!(stored["failure"]["code"], "task_failed");
+        assert_eq!(stored["failure"]["detail"]["exit_code"], 3);
+
+        let response =
+            get_job_result(RequestContext::new(None), State(state.clone()), HeaderMap::new(), Path(job.id)).await;
+        assert_eq!(response.status(), axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "task_failed");
+        assert_eq!(body["error"]["details"]["exitCode"], 3);
+
+        // Jobs without a stored error, e.g. interrupted by a restart, still fail as internal errors
+        let mut interrupted = state.jobs.create("embedding_ingest");
+        interrupted.status = JobStatus::Failed;
+        interrupted.error = Some(INTERRUPTED_JOB_ERROR.to_string());
+        state.jobs.replicate(vec![interrupted.clone()]);
+        let response =
+            get_job_result(RequestContext::new(None), State(state), HeaderMap::new(), Path(interrupted.id)).await;
+        assert_eq!(response.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_wait_times_out() {
+        let store = JobStore::new();
+        let job = store.create("embedding");
+        let job = store.wait(&job.id, Duration::from_millis(20)).await.unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+        assert!(store.wait("missing", Duration::from_millis(20)).await.is_none());
+    }
+
+    #[test]
+    fn test_storage_restores_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(Storage::open(&dir.path().join("nautilus.db")).unwrap());
+        let store = JobStore::new().with_storage(storage.clone()).unwrap();
+        let finished = store.create("embedding_ingest");
+        store.complete(&finished.id, Err(EnclaveError::Internal("boom".to_string())));
+        let running = store.create("embedding_ingest");
+        store.mark_running(&running.id);
+        let mut replicated = store.get(&finished.id).unwrap();
+        replicated.id = "replicated".to_string();
+        store.replicate(vec![replicated]);
+        drop(store);
+
+        // A restarted server still knows the finished job, the running one was lost with it
+        let store = JobStore::new().with_storage(storage).unwrap();
+        assert_eq!(store.get(&finished.id).unwrap().error.as_deref(), Some("boom"));
+        assert!(matches!(store.get(&finished.id).unwrap().failure, Some(EnclaveError::Internal(_))));
+        assert_eq!(store.get("replicated").unwrap().error.as_deref(), Some("boom"));
+        let interrupted = store.get(&running.id).unwrap();
+        assert_eq!(interrupted.status, JobStatus::Failed);
+        assert_eq!(interrupted.error.as_deref(), Some(INTERRUPTED_JOB_ERROR));
+    }
+}
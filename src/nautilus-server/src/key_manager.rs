@@ -0,0 +1,422 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rotation of the ephemeral signing key. The key generated at boot is replaced every
+//! `KEY_ROTATION_INTERVAL_SECS`, or on `POST /admin/keys/rotate`, by a new one with its own
+//! attestation. Every signed response carries the `key_id` of its key, and the previous key
+//! stays listed on `GET /keys` for `KEY_ROTATION_OVERLAP_SECS` so responses signed just
+//! before a rotation can still be verified. The TLS certificate and the leader election
+//! identity keep the boot key.
+//!
+//! Each signing key also holds a secp256k1 key pair, for Move contracts that verify secp256k1
+//! signatures. `SIGNATURE_SCHEME` picks which of the two signs responses; the `key_id` is
+//! always derived from the Ed25519 public key, and the attestation of a key carries its
+//! secp256k1 public key in its `user_data`.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::{current_timestamp_ms, BootAttestation, GetAttestationResponse};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use fastcrypto::secp256k1::Secp256k1KeyPair;
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Default time a rotated out key stays valid for verification.
+pub const DEFAULT_KEY_ROTATION_OVERLAP_SECS: u64 = 60 * 60;
+
+/// Bytes of the public key hash used as key ID.
+const KEY_ID_BYTES: usize = 8;
+
+/// ID of a signing key: the hex SHA3-256 of its public key, truncated to 8 bytes.
+pub fn key_id(public_key: &Ed25519PublicKey) -> String {
+    Hex::encode(&Sha3_256::digest(public_key.as_bytes()).digest[..KEY_ID_BYTES])
+}
+
+/// Signature scheme of signed responses, from `SIGNATURE_SCHEME`. Serialized as the Sui
+/// signature scheme flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum SignatureScheme {
+    #[default]
+    Ed25519 = 0,
+    /// ECDSA over the SHA-256 hash of the message, with a 64 byte `r || s` signature and a
+    /// 33 byte compressed public key
+    Secp256k1 = 1,
+}
+
+impl FromStr for SignatureScheme {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            "secp256k1" => Ok(SignatureScheme::Secp256k1),
+            other => Err(format!("unknown signature scheme {}, expected ed25519 or secp256k1", other)),
+        }
+    }
+}
+
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureScheme::Ed25519 => write!(f, "ed25519"),
+            SignatureScheme::Secp256k1 => write!(f, "secp256k1"),
+        }
+    }
+}
+
+/// Key that signs responses with its scheme.
+pub trait ResponseSigner {
+    fn scheme(&self) -> SignatureScheme;
+    /// ID listed on `GET /keys`
+    fn key_id(&self) -> String;
+    fn sign_bytes(&self, message: &[u8]) -> Vec<u8>;
+}
+
+impl ResponseSigner for Ed25519KeyPair {
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::Ed25519
+    }
+
+    fn key_id(&self) -> String {
+        key_id(self.public())
+    }
+
+    fn sign_bytes(&self, message: &[u8]) -> Vec<u8> {
+        self.sign(message).as_bytes().to_vec()
+    }
+}
+
+/// Ephemeral key that signs responses.
+pub struct SigningKey {
+    pub key_id: String,
+    pub keypair: Ed25519KeyPair,
+    /// Signs instead of `keypair` with [SignatureScheme::Secp256k1]
+    pub secp256k1: Secp256k1KeyPair,
+    pub scheme: SignatureScheme,
+    pub created_at_ms: u64,
+    /// Attestation over this key, requested once
+    attestation: OnceLock<BootAttestation>,
+    /// Latest attestation over this key without a challenge, see [SigningKey::recent_attestation]
+    recent_attestation: Mutex<Option<GetAttestationResponse>>,
+}
+
+impl SigningKey {
+    /// Key signing with `scheme`, with a newly generated secp256k1 key pair.
+    pub fn new(keypair: Ed25519KeyPair, scheme: SignatureScheme) -> Self {
+        Self {
+            key_id: key_id(keypair.public()),
+            keypair,
+            secp256k1: Secp256k1KeyPair::generate(&mut rand::thread_rng()),
+            scheme,
+            created_at_ms: current_timestamp_ms(),
+            attestation: OnceLock::new(),
+            recent_attestation: Mutex::new(None),
+        }
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        Hex::encode(self.keypair.public().as_bytes())
+    }
+
+    /// Hex encoded compressed secp256k1 public key.
+    pub fn secp256k1_public_key_hex(&self) -> String {
+        Hex::encode(self.secp256k1.public().as_bytes())
+    }
+
+    /// Hex encoded public key of the scheme responses are signed with.
+    pub fn signing_public_key_hex(&self) -> String {
+        match self.scheme {
+            SignatureScheme::Ed25519 => self.public_key_hex(),
+            SignatureScheme::Secp256k1 => self.secp256k1_public_key_hex(),
+        }
+    }
+
+    /// Attestation of this key, requested with `request` the first time.
+    pub fn attestation(
+        &self,
+        request: impl FnOnce() -> Result<BootAttestation, EnclaveError>,
+    ) -> Result<BootAttestation, EnclaveError> {
+        if let Some(attestation) = self.attestation.get() {
+            return Ok(attestation.clone());
+        }
+        let attestation = request()?;
+        Ok(self.attestation.get_or_init(|| attestation).clone())
+    }
+
+    /// Attestation of this key without a challenge, requested again with `request` once it
+    /// is `max_age` old.
+    pub fn recent_attestation(
+        &self,
+        max_age: Duration,
+        request: impl FnOnce() -> Result<GetAttestationResponse, EnclaveError>,
+    ) -> Result<GetAttestationResponse, EnclaveError> {
+        let mut recent = self.recent_attestation.lock().unwrap();
+        let fresh_since = current_timestamp_ms().saturating_sub(max_age.as_millis() as u64);
+        if let Some(attestation) = recent.as_ref().filter(|a| a.generated_at_ms > fresh_since) {
+            return Ok(attestation.clone());
+        }
+        let attestation = request()?;
+        *recent = Some(attestation.clone());
+        Ok(attestation)
+    }
+}
+
+impl ResponseSigner for SigningKey {
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+
+    fn key_id(&self) -> String {
+        self.key_id.clone()
+    }
+
+    fn sign_bytes(&self, message: &[u8]) -> Vec<u8> {
+        match self.scheme {
+            SignatureScheme::Ed25519 => self.keypair.sign_bytes(message),
+            SignatureScheme::Secp256k1 => self.secp256k1.sign(message).as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Public description of a signing key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub key_id: String,
+    /// Hex encoded Ed25519 public key
+    pub public_key: String,
+    /// Hex encoded compressed secp256k1 public key
+    pub secp256k1_public_key: String,
+    pub created_at_ms: u64,
+    /// Whether new responses are signed with this key
+    pub current: bool,
+    /// When the key was rotated out, for previous keys
+    pub retired_at_ms: Option<u64>,
+    /// Until when responses signed with the key should still be accepted
+    pub valid_until_ms: Option<u64>,
+}
+
+struct KeyRing {
+    current: Arc<SigningKey>,
+    /// Rotated out key and when
+    previous: Option<(Arc<SigningKey>, u64)>,
+}
+
+/// Current and previous signing keys.
+pub struct KeyManager {
+    overlap: Duration,
+    scheme: SignatureScheme,
+    ring: RwLock<KeyRing>,
+}
+
+impl Default for KeyManager {
+    fn default() -> Self {
+        Self::new(
+            Ed25519KeyPair::generate(&mut rand::thread_rng()),
+            Duration::from_secs(DEFAULT_KEY_ROTATION_OVERLAP_SECS),
+            SignatureScheme::default(),
+        )
+    }
+}
+
+impl KeyManager {
+    /// Keys starting with `keypair` and signing with `scheme`, keeping rotated out keys for
+    /// `overlap`.
+    pub fn new(keypair: Ed25519KeyPair, overlap: Duration, scheme: SignatureScheme) -> Self {
+        Self {
+            overlap,
+            scheme,
+            ring: RwLock::new(KeyRing {
+                current: Arc::new(SigningKey::new(keypair, scheme)),
+                previous: None,
+            }),
+        }
+    }
+
+    /// Scheme responses are signed with.
+    pub fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+
+    /// Key new responses are signed with.
+    pub fn current(&self) -> Arc<SigningKey> {
+        self.ring.read().unwrap().current.clone()
+    }
+
+    /// Rotated out key still within the overlap, with when it was rotated out.
+    pub fn previous(&self) -> Option<(Arc<SigningKey>, u64)> {
+        let now = current_timestamp_ms();
+        let ring = self.ring.read().unwrap();
+        ring.previous
+            .clone()
+            .filter(|(_, retired_at_ms)| now < retired_at_ms.saturating_add(self.overlap.as_millis() as u64))
+    }
+
+    /// Replace the current key with `keypair`, keeping the current one as previous key.
+    pub fn rotate_to(&self, keypair: Ed25519KeyPair) -> Arc<SigningKey> {
+        let key = Arc::new(SigningKey::new(keypair, self.scheme));
+        let mut ring = self.ring.write().unwrap();
+        let retired = std::mem::replace(&mut ring.current, key.clone());
+        ring.previous = Some((retired, current_timestamp_ms()));
+        key
+    }
+
+    /// Replace the current key with a newly generated one.
+    pub fn rotate(&self) -> Arc<SigningKey> {
+        self.rotate_to(Ed25519KeyPair::generate(&mut rand::thread_rng()))
+    }
+
+    /// Keys responses may be signed with: the current one, then the previous one.
+    pub fn infos(&self) -> Vec<KeyInfo> {
+        let current = self.current();
+        let mut keys = vec![KeyInfo {
+            key_id: current.key_id.clone(),
+            public_key: current.public_key_hex(),
+            secp256k1_public_key: current.secp256k1_public_key_hex(),
+            created_at_ms: current.created_at_ms,
+            current: true,
+            retired_at_ms: None,
+            valid_until_ms: None,
+        }];
+        if let Some((previous, retired_at_ms)) = self.previous() {
+            keys.push(KeyInfo {
+                key_id: previous.key_id.clone(),
+                public_key: previous.public_key_hex(),
+            secp256k1_public_key: previous.secp256k1_public_key_hex(),
+                created_at_ms: previous.created_at_ms,
+                current: false,
+                retired_at_ms: Some(retired_at_ms),
+                valid_until_ms: Some(retired_at_ms.saturating_add(self.overlap.as_millis() as u64)),
+            });
+        }
+        keys
+    }
+}
+
+/// Rotate the signing key of `state` and request the attestation of the new key, so the
+/// first response signed with it need not wait for the NSM.
+pub fn rotate_signing_key(state: &AppState) -> Arc<SigningKey> {
+    let key = state.keys.rotate();
+    info!("Rotated the signing key to {}", key.key_id);
+    if let Err(e) = state.key_attestation(&key) {
+        warn!("Attestation of signing key {} failed, retrying on the first signed response: {:?}", key.key_id, e);
+    }
+    key
+}
+
+/// Rotate the signing key every `interval`.
+pub fn spawn_key_rotation(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            rotate_signing_key(&state);
+        }
+    });
+}
+
+/// Response of `GET /keys` and `POST /admin/keys/rotate`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeysResponse {
+    pub keys: Vec<KeyInfo>,
+    /// Scheme flag of the signatures, 0 for Ed25519 and 1 for secp256k1
+    pub scheme: SignatureScheme,
+    /// Seconds a rotated out key stays listed
+    pub overlap_secs: u64,
+}
+
+fn signing_keys(state: &AppState) -> SigningKeysResponse {
+    SigningKeysResponse {
+        keys: state.keys.infos(),
+        scheme: state.keys.scheme(),
+        overlap_secs: state.keys.overlap.as_secs(),
+    }
+}
+
+/// Keys signed responses can be verified with, by `key_id`.
+pub async fn get_signing_keys(ctx: RequestContext, State(state): State<Arc<AppState>>) -> ApiResponse<SigningKeysResponse> {
+    ctx.ok(signing_keys(&state))
+}
+
+/// Rotate the signing key now. Requires the admin token.
+pub async fn rotate_key(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<SigningKeysResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let previous = state.keys.current();
+    let key = rotate_signing_key(&state);
+    state.audit_log.record(
+        "signing_key_rotated",
+        &key.key_id,
+        serde_json::json!({ "previousKeyId": previous.key_id, "publicKey": key.public_key_hex() }),
+    );
+    ctx.ok(signing_keys(&state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    #[test]
+    fn test_rotation_keeps_previous_key_for_overlap() {
+        let manager = KeyManager::default();
+        let first = manager.current();
+        assert_eq!(first.key_id.len(), KEY_ID_BYTES * 2);
+        assert!(manager.previous().is_none());
+
+        let second = manager.rotate();
+        assert_ne!(second.key_id, first.key_id);
+        assert_eq!(manager.current().key_id, second.key_id);
+        let infos = manager.infos();
+        assert_eq!(infos.len(), 2);
+        assert!(infos[0].current && infos[0].key_id == second.key_id);
+        assert_eq!(infos[1].key_id, first.key_id);
+        assert!(infos[1].valid_until_ms.is_some());
+
+        // Without overlap the rotated out key is dropped at once
+        let manager = KeyManager::new(
+            Ed25519KeyPair::generate(&mut rand::thread_rng()),
+            Duration::ZERO,
+            SignatureScheme::Ed25519,
+        );
+        manager.rotate();
+        assert_eq!(manager.infos().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_requires_admin_and_reattests() {
+        let mut state = test_app_state();
+        state.admin_token = Some("secret".to_string());
+        let state = Arc::new(state);
+        let before = state.keys.current();
+        let attested = state.boot_attestation().unwrap();
+
+        let response = rotate_key(RequestContext::new(None), State(state.clone()), HeaderMap::new()).await;
+        assert!(response.error.is_some());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let keys = rotate_key(RequestContext::new(None), State(state.clone()), headers).await.data.unwrap();
+        assert_eq!(keys.keys[1].key_id, before.key_id);
+        assert_ne!(state.keys.current().key_id, before.key_id);
+        // The new key has its own attestation
+        assert!(state.keys.current().attestation.get().is_some());
+        assert_eq!(before.attestation.get().map(|boot| &boot.reference), Some(&attested.reference));
+        assert_eq!(state.audit_log.recent(10)[0].action, "signing_key_rotated");
+    }
+}
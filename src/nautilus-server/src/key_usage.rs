@@ -0,0 +1,166 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Usage of the ephemeral signing key. Every signature is counted per intent scope and
+//! exported on `/metrics`; scopes listed in `SIGNING_RATE_LIMITS` are limited to that many
+//! signatures per minute, so a client driving the enclave as a signing oracle gets 429s
+//! and shows up in the rejection counter instead of collecting signatures.
+
+use crate::common::IntentScope;
+use crate::EnclaveError;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Window over which signing rate limits are counted.
+pub const SIGNING_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct ScopeUsage {
+    signed: u64,
+    rejected: u64,
+    /// Whether the last signature was rejected, to warn once per limited period
+    limited: bool,
+    /// Signing times within the window, only kept for limited scopes
+    recent: VecDeque<Instant>,
+}
+
+/// Signature counters and optional per-scope rate limits.
+#[derive(Debug, Clone, Default)]
+pub struct KeyUsage {
+    /// Signatures allowed per [SIGNING_WINDOW] by scope
+    limits: BTreeMap<IntentScope, u32>,
+    usage: Arc<Mutex<BTreeMap<IntentScope, ScopeUsage>>>,
+}
+
+impl KeyUsage {
+    pub fn new(limits: BTreeMap<IntentScope, u32>) -> Self {
+        Self {
+            limits,
+            usage: Default::default(),
+        }
+    }
+
+    /// Parse `SIGNING_RATE_LIMITS`: comma separated `scope=signatures_per_minute`, e.g.
+    /// `process_data=120,stream_summary=600`.
+    pub fn parse_limits(value: &str) -> Result<BTreeMap<IntentScope, u32>, EnclaveError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (scope, limit) = entry.split_once('=').ok_or_else(|| {
+                    EnclaveError::BadRequest(format!("Expected scope=limit, got {}", entry))
+                })?;
+                let limit = limit.trim().parse::<u32>().map_err(|e| {
+                    EnclaveError::BadRequest(format!("Invalid limit for {}: {}", scope, e))
+                })?;
+                Ok((scope.trim().parse()?, limit))
+            })
+            .collect()
+    }
+
+    /// Record a signature under `scope` about to be made, or reject it with
+    /// [EnclaveError::Overloaded] when the scope's limit is reached.
+    pub fn acquire(&self, scope: IntentScope) -> Result<(), EnclaveError> {
+        self.acquire_at(scope, Instant::now())
+    }
+
+    fn acquire_at(&self, scope: IntentScope, now: Instant) -> Result<(), EnclaveError> {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(scope).or_default();
+        if let Some(&limit) = self.limits.get(&scope) {
+            while entry.recent.front().is_some_and(|at| now.duration_since(*at) >= SIGNING_WINDOW) {
+                entry.recent.pop_front();
+            }
+            if entry.recent.len() >= limit as usize {
+                if !entry.limited {
+                    entry.limited = true;
+                    warn!("Signing rate limit of {} per minute reached for {}", limit, scope.name());
+                }
+                entry.rejected += 1;
+                let oldest = entry.recent.front().copied().unwrap_or(now);
+                let retry_after = SIGNING_WINDOW.saturating_sub(now.duration_since(oldest));
+                return Err(EnclaveError::Overloaded {
+                    message: format!("Signing rate limit reached for {}", scope.name()),
+                    retry_after_secs: retry_after.as_secs().max(1),
+                });
+            }
+            entry.recent.push_back(now);
+            entry.limited = false;
+        }
+        entry.signed += 1;
+        Ok(())
+    }
+
+    /// Signatures made under `scope`.
+    pub fn signed(&self, scope: IntentScope) -> u64 {
+        self.usage.lock().unwrap().get(&scope).map_or(0, |u| u.signed)
+    }
+
+    /// Render the counters and limits in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let usage = self.usage.lock().unwrap();
+        let mut out = String::new();
+        let series = [
+            ("nautilus_signatures_total", "Signatures made with the enclave key.", false),
+            ("nautilus_signatures_rejected_total", "Signatures refused by the signing rate limit.", true),
+        ];
+        for (name, help, rejected) in series {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (scope, u) in usage.iter() {
+                let value = if rejected { u.rejected } else { u.signed };
+                let _ = writeln!(out, "{}{{scope=\"{}\"}} {}", name, scope.name(), value);
+            }
+        }
+        let name = "nautilus_signing_rate_limit";
+        let _ = writeln!(out, "# HELP {} Signatures allowed per minute.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (scope, limit) in &self.limits {
+            let _ = writeln!(out, "{}{{scope=\"{}\"}} {}", name, scope.name(), limit);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = KeyUsage::parse_limits("process_data=2, stream_summary=10").unwrap();
+        assert_eq!(limits[&IntentScope::ProcessData], 2);
+        assert_eq!(limits[&IntentScope::StreamSummary], 10);
+        assert!(KeyUsage::parse_limits("").unwrap().is_empty());
+        assert!(KeyUsage::parse_limits("process_data").is_err());
+        assert!(KeyUsage::parse_limits("unknown=1").is_err());
+        assert!(KeyUsage::parse_limits("process_data=-1").is_err());
+    }
+
+    #[test]
+    fn test_limit_per_window() {
+        let usage = KeyUsage::new(KeyUsage::parse_limits("process_data=2").unwrap());
+        let start = Instant::now();
+        assert!(usage.acquire_at(IntentScope::ProcessData, start).is_ok());
+        assert!(usage.acquire_at(IntentScope::ProcessData, start + Duration::from_secs(20)).is_ok());
+        let err = usage
+            .acquire_at(IntentScope::ProcessData, start + Duration::from_secs(30))
+            .unwrap_err();
+        assert_eq!(err.retry_after_secs(), Some(30));
+        // Other scopes are only counted
+        assert!(usage.acquire_at(IntentScope::BlobRetrieval, start).is_ok());
+        // The first signature leaves the window
+        assert!(usage.acquire_at(IntentScope::ProcessData, start + Duration::from_secs(60)).is_ok());
+
+        assert_eq!(usage.signed(IntentScope::ProcessData), 3);
+        let text = usage.render();
+        assert!(text.contains("nautilus_signatures_total{scope=\"process_data\"} 3"));
+        assert!(text.contains("nautilus_signatures_rejected_total{scope=\"process_data\"} 1"));
+        assert!(text.contains("nautilus_signatures_total{scope=\"blob_retrieval\"} 1"));
+        assert!(text.contains("nautilus_signing_rate_limit{scope=\"process_data\"} 2"));
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Leader election for periodic jobs. When several enclaves share a Qdrant and Walrus
+//! deployment, jobs such as the vector reaper must run on one of them only. With
+//! `LEADER_LEASE_SECS` set, instances compete for a lease stored as a point in the Qdrant
+//! collection `LEADER_LEASE_COLLECTION`; the holder renews it every third of the lease and
+//! runs the periodic jobs, the others take over once it expires.
+//!
+//! The lease is taken with a conditional payload update that only matches the lease point
+//! while it is expired or already held by the caller. Qdrant applies updates to a point in
+//! order, so of two instances racing for an expired lease only the first one's update
+//! matches. Expiry uses wall clock time, so the lease must be much longer than the clock
+//! skew between hosts.
+
+use crate::collections::CollectionSettings;
+use crate::common::current_timestamp_ms;
+use crate::qdrant::QdrantClient;
+use crate::AppState;
+use crate::EnclaveError;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Point holding the lease in the lease collection.
+pub const LEASE_POINT_ID: u64 = 1;
+
+/// Default collection holding the lease.
+pub const DEFAULT_LEASE_COLLECTION: &str = "nautilus_leases";
+
+/// Lease settings, from `LEADER_LEASE_SECS` and `LEADER_LEASE_COLLECTION`.
+#[derive(Debug, Clone)]
+pub struct LeaseConfig {
+    pub collection: String,
+    pub ttl: Duration,
+}
+
+/// Qdrant filter matching the lease point when `holder` may take it at `now_ms`.
+pub fn acquire_filter(holder: &str, now_ms: u64) -> serde_json::Value {
+    serde_json::json!({
+        "must": [{ "has_id": [LEASE_POINT_ID] }],
+        "should": [
+            { "key": "holder", "match": { "value": holder } },
+            { "key": "expires_at_ms", "range": { "lt": now_ms } }
+        ]
+    })
+}
+
+/// Whether the lease payload read back from Qdrant is held by `holder`.
+pub fn holds_lease(payload: &serde_json::Value, holder: &str) -> bool {
+    payload["holder"].as_str() == Some(holder)
+}
+
+/// Leadership of this instance. Without a lease configuration every instance leads, as
+/// when a single enclave is deployed.
+#[derive(Debug, Default)]
+pub struct LeaderElection {
+    config: Option<LeaseConfig>,
+    /// Identifies this instance in the lease, the hex public key of the ephemeral keypair
+    holder: String,
+    /// End of the lease this instance last took, in Unix milliseconds
+    leader_until_ms: AtomicU64,
+    /// Leadership at the last attempt, to log transitions
+    was_leader: AtomicBool,
+}
+
+impl LeaderElection {
+    pub fn new(config: Option<LeaseConfig>, holder: String) -> Self {
+        Self {
+            config,
+            holder,
+            ..Default::default()
+        }
+    }
+
+    /// Whether periodic jobs should run on this instance now.
+    pub fn is_leader(&self) -> bool {
+        self.config.is_none() || current_timestamp_ms() < self.leader_until_ms.load(Ordering::SeqCst)
+    }
+
+    /// Take or renew the lease, returning whether this instance holds it.
+    async fn try_acquire(&self, client: &QdrantClient, config: &LeaseConfig) -> Result<bool, EnclaveError> {
+        if client.collection_info(&config.collection).await?.is_none() {
+            client
+                .create_collection(&config.collection, 1, &CollectionSettings::default())
+                .await?;
+        }
+        if client.get_point_payload(&config.collection, LEASE_POINT_ID).await?.is_none() {
+            let lease = serde_json::json!({
+                "id": LEASE_POINT_ID,
+                "vector": [1.0],
+                "payload": { "holder": "", "expires_at_ms": 0 }
+            });
+            client.upsert_points(&config.collection, vec![lease]).await?;
+        }
+
+        let now_ms = current_timestamp_ms();
+        let expires_at_ms = now_ms + config.ttl.as_millis() as u64;
+        let payload = serde_json::json!({ "holder": self.holder, "expires_at_ms": expires_at_ms });
+        client
+            .set_payload(&config.collection, payload, &acquire_filter(&self.holder, now_ms))
+            .await?;
+        let lease = client
+            .get_point_payload(&config.collection, LEASE_POINT_ID)
+            .await?
+            .unwrap_or_default();
+        if !holds_lease(&lease, &self.holder) {
+            return Ok(false);
+        }
+        // Counted from before the update, so this instance stops first
+        self.leader_until_ms.store(expires_at_ms, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    /// Render the leadership gauge in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let name = "nautilus_leader";
+        let _ = writeln!(out, "# HELP {} Whether this instance runs the periodic jobs.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, u8::from(self.is_leader()));
+        out
+    }
+}
+
+/// Compete for the lease every third of its duration. Does nothing without a lease
+/// configuration.
+pub fn spawn_leader_election(state: Arc<AppState>) {
+    let Some(config) = state.leader.config.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let client = match QdrantClient::from_state(&state) {
+            Ok(client) => client,
+            Err(e) => return warn!("Leader election disabled: {:?}", e),
+        };
+        loop {
+            let election = &state.leader;
+            if let Err(e) = election.try_acquire(&client, &config).await {
+                warn!("Leader lease update failed: {:?}", e);
+            }
+            let leader = election.is_leader();
+            if election.was_leader.swap(leader, Ordering::SeqCst) != leader {
+                if leader {
+                    info!("Acquired the leader lease, running periodic jobs");
+                } else {
+                    info!("Not holding the leader lease, periodic jobs run elsewhere");
+                }
+            }
+            tokio::time::sleep(config.ttl / 3).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_filter() {
+        let filter = acquire_filter("abc", 1000);
+        assert_eq!(filter["must"][0]["has_id"][0], LEASE_POINT_ID);
+        assert_eq!(filter["should"][0]["match"]["value"], "abc");
+        assert_eq!(filter["should"][1]["range"]["lt"], 1000);
+
+        assert!(holds_lease(&serde_json::json!({ "holder": "abc", "expires_at_ms": 5 }), "abc"));
+        assert!(!holds_lease(&serde_json::json!({ "holder": "def" }), "abc"));
+        assert!(!holds_lease(&serde_json::Value::Null, "abc"));
+    }
+
+    #[test]
+    fn test_leads_without_lease() {
+        assert!(LeaderElection::default().is_leader());
+        let election = LeaderElection::new(
+            Some(LeaseConfig {
+                collection: DEFAULT_LEASE_COLLECTION.to_string(),
+                ttl: Duration::from_secs(30),
+            }),
+            "abc".to_string(),
+        );
+        assert!(!election.is_leader());
+        election.leader_until_ms.store(current_timestamp_ms() + 30_000, Ordering::SeqCst);
+        assert!(election.is_leader());
+        assert!(election.render().contains("nautilus_leader 1"));
+    }
+}
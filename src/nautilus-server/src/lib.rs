@@ -1,273 +1,669 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use arc_swap::ArcSwap;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
-use axum::Json;
-use fastcrypto::ed25519::Ed25519KeyPair;
-use serde_json::json;
+use std::collections::HashMap;
 
+pub mod address_limits;
+pub mod api_response;
 pub mod app;
+pub mod audit;
+pub mod authorization;
+pub mod breakers;
+pub mod build_info;
+pub mod caller_limits;
+pub mod canonical;
+pub mod collections;
 pub mod common;
+pub mod config;
+pub mod config_check;
+pub mod crash_reports;
+pub mod dev;
+pub mod dependency_allowlist;
+pub mod embeddings;
+pub mod endpoints;
+pub mod experiments;
+pub mod feedback;
+pub mod http_cache;
+pub mod idempotency;
+pub mod ingest_batch;
+pub mod internal_key;
+pub mod jobs;
+pub mod key_manager;
+pub mod key_usage;
+pub mod leader;
+pub mod listener;
+pub mod logging;
+pub mod metrics;
+pub mod nitro_attestation;
+pub mod openapi;
+pub mod payload_crypto;
+pub mod qdrant;
+pub mod reaper;
+pub mod receipts;
+pub mod replication;
+pub mod request_log;
+pub mod retention;
+pub mod retrieval_stream;
+pub mod runtime_config;
+pub mod runtime_health;
+pub mod scheduler;
+pub mod schemas;
+pub mod seal_policy;
+pub mod soft_delete;
+pub mod storage;
+pub mod stream_signing;
+pub mod sui;
+pub mod task_audit;
+pub mod task_bundles;
+pub mod task_env;
+pub mod task_result;
 pub mod task_runner;
+pub mod task_stream;
+pub mod telemetry;
+pub mod timeline;
+pub mod tx_sequencer;
+pub mod validation;
+pub mod walrus;
+pub mod warmup;
 
 /// App state, at minimum needs to maintain the ephemeral keypair and environment configuration.  
 pub struct AppState {
-    /// Ephemeral keypair on boot
-    pub eph_kp: Ed25519KeyPair,
-    
-    /// Sui blockchain configuration
-    pub move_package_id: String,
-    pub sui_secret_key: String,
-    
-    /// Ruby nodes configuration
-    pub ruby_nodes_api_key: String,
-    
-    /// Walrus distributed storage configuration
-    pub walrus_aggregator_url: String,
-    pub walrus_publisher_url: String,
-    pub walrus_epochs: String,
-    
-    /// Ollama embedding service configuration
-    pub ollama_api_url: String,
-    pub ollama_model: String,
-    
-    // Azure open ai embedding configuration
-    pub azure_text_embedding_api_endpoint: String,
-    pub azure_text_embedding_api_key: String,
-    
-    /// Qdrant vector database configuration
-    pub qdrant_url: String,
-    pub qdrant_api_key: Option<String>,
-    pub qdrant_collection_name: String,
-    
-    /// Task processing configuration
-    pub embedding_batch_size: String,
-    pub vector_batch_size: String,
-    
-    // Social truth telegram bot configuration
-    pub telegram_social_truth_bot_id: String,
-    
-    // ID mask salt configuration
-    pub id_mask_salt: String,
+    /// Ephemeral signing keys, the first generated on boot and rotated since
+    pub keys: key_manager::KeyManager,
+
+    /// Build metadata served on `/version` and committed to in attestations
+    pub build_info: build_info::BuildInfo,
+
+    /// Where attestation documents come from, mocked in `--dev` mode
+    pub attestation: common::AttestationProvider,
+
+    /// Typed service configuration loaded from the environment
+    pub config: config::Config,
+
+    /// Settings replaced on `POST /admin/config`, read through the accessors below
+    pub runtime_config: ArcSwap<runtime_config::RuntimeConfig>,
+    /// Held by `POST /admin/config` while it swaps `runtime_config` and `embeddings` together
+    pub config_updates: std::sync::Mutex<()>,
+
+    /// Retry and certification behaviour for blobs stored by the server
+    pub walrus_store: walrus::StoreOptions,
+    /// Epoch and cost limits for Walrus stores
+    pub walrus_budget: walrus::StorageBudget,
+
+    /// Registry of asynchronous jobs
+    pub jobs: jobs::JobStore,
+
+    /// Ingest jobs by idempotency key, kept for `IDEMPOTENCY_TTL_SECS`
+    pub idempotency: idempotency::IdempotencyStore,
+
+    /// Masked relevance feedback on retrieval results
+    pub feedback: feedback::FeedbackStore,
+
+    /// Retrieval parameter profiles under A/B evaluation
+    pub experiments: experiments::RetrievalExperiments,
+
+    /// Priority scheduler gating Node task execution
+    pub scheduler: std::sync::Arc<scheduler::TaskScheduler>,
+
+    /// Anchor signed execution receipts to Walrus unless a request opts out
+    pub anchor_receipts: bool,
+
+    /// CPU affinity and nice value applied to Node.js task processes
+    pub task_scheduling: task_runner::SchedulingHints,
+
+    /// Node.js heap and stack flags for each operation
+    pub task_node_flags: task_runner::NodeFlagsByOperation,
+
+    /// When failed Node.js tasks are run again
+    pub task_retry: task_runner::TaskRetryPolicy,
+
+    /// Task directory, env policy and measurement of each operation
+    pub task_bundles: task_bundles::TaskBundles,
+
+    /// Warm Node.js workers running tasks, a process is spawned per task when unset
+    pub worker_pool: Option<std::sync::Arc<task_runner::WorkerPool>>,
+
+    /// Result of checking the Node.js task dependencies against the signed allowlist
+    pub dependency_status: dependency_allowlist::DependencyStatus,
+
+    /// Prometheus metrics
+    pub metrics: metrics::Metrics,
+
+    /// Signatures made per intent scope and `SIGNING_RATE_LIMITS`
+    pub key_usage: key_usage::KeyUsage,
+
+    /// Ingest and retrieval requests made per address and `ADDRESS_RATE_LIMITS`
+    pub address_limits: address_limits::AddressLimits,
+
+    /// Requests made per caller and route class and `CALLER_RATE_LIMITS`
+    pub caller_limits: caller_limits::CallerLimits,
+
+    /// Circuit breakers of the upstream services, served on `/admin/breakers`
+    pub breakers: breakers::CircuitBreakers,
+
+    /// Cached Sui object reads and the fullnode currently answering
+    pub sui_cache: sui::SuiCache,
+
+    /// Gas lanes serializing the Sui transactions the enclave submits
+    pub tx_sequencer: tx_sequencer::TransactionSequencer,
+
+    /// Crash and crash loop tracking for Node.js task processes
+    pub runtime_health: runtime_health::RuntimeHealth,
+
+    /// Encrypted crash reports written by the panic hook
+    pub crash_reports: std::sync::Arc<crash_reports::CrashReportStore>,
+
+    /// Bearer token for `/admin` endpoints, which are disabled when unset
+    pub admin_token: Option<String>,
+
+    /// Latest requests, served on `/admin/requests`
+    pub request_log: request_log::RequestLog,
+
+    /// Runtime changes such as collection creation and tuning, served on `/admin/audit`
+    pub audit_log: audit::AuditLog,
+
+    /// Signed record of every task invocation, served on `/audit/tasks`
+    pub task_audit: task_audit::TaskAuditLog,
+
+    /// Search parameters per Qdrant collection, tuned on `/admin/collections/:name/tune`
+    pub collection_tuning: collections::CollectionTuning,
+
+    /// Endpoints of `allowed_endpoints.yaml`, reloaded on `/admin/endpoints/reload`
+    pub endpoints: endpoints::EndpointRegistry,
+
+    /// Warm standby replication status, see `REPLICATION_ROLE`
+    pub replication: replication::Replication,
+
+    /// Whether this instance holds the lease to run periodic jobs
+    pub leader: leader::LeaderElection,
+
+    /// Embedding backend selected by `EMBEDDING_PROVIDER`, swapped when `OLLAMA_MODEL` is updated
+    pub embeddings: ArcSwap<embeddings::EmbeddingProvider>,
+
+    /// Asked before every task operation, see [authorization]
+    pub authorization: std::sync::Arc<dyn authorization::AuthorizationHook>,
 }
 
 impl AppState {
+    /// Attestation of the current signing key, requested on first use
+    pub fn boot_attestation(&self) -> Result<common::BootAttestation, EnclaveError> {
+        self.key_attestation(&self.keys.current())
+    }
+
+    /// Attestation of signing key `key`, requested on first use
+    pub fn key_attestation(&self, key: &key_manager::SigningKey) -> Result<common::BootAttestation, EnclaveError> {
+        key.attestation(|| common::request_boot_attestation(self, key))
+    }
+
     /// Get Sui Move package ID
     pub fn move_package_id(&self) -> &str {
-        &self.move_package_id
+        &self.config.move_package_id
     }
 
     /// Get Sui secret key
     pub fn sui_secret_key(&self) -> &str {
-        &self.sui_secret_key
+        self.config.sui_secret_key.expose()
     }
     
     /// Get ruby nodes api key
     pub fn ruby_nodes_api_key(&self) -> &str {
-        &self.ruby_nodes_api_key
+        self.config.ruby_nodes_api_key.expose()
     }
 
     /// Get Walrus aggregator URL
     pub fn walrus_aggregator_url(&self) -> &str {
-        &self.walrus_aggregator_url
+        config::url_str(&self.config.walrus_aggregator_url)
+    }
+
+    /// `WALRUS_AGGREGATOR_FALLBACK_URLS`, tried in order when the aggregator is unavailable
+    pub fn walrus_aggregator_fallback_urls(&self) -> Vec<&str> {
+        self.config.walrus_aggregator_fallback_urls.iter().map(config::url_str).collect()
     }
 
     /// Get Walrus publisher URL
     pub fn walrus_publisher_url(&self) -> &str {
-        &self.walrus_publisher_url
+        config::url_str(&self.config.walrus_publisher_url)
     }
 
-    /// Get Walrus epochs as string
-    pub fn walrus_epochs_str(&self) -> &str {
-        &self.walrus_epochs
+    /// Get Walrus epochs
+    pub fn walrus_epochs(&self) -> u32 {
+        self.config.walrus_epochs
     }
 
-    /// Get Walrus epochs as number
-    pub fn walrus_epochs(&self) -> Result<u32, std::num::ParseIntError> {
-        self.walrus_epochs.parse()
+    /// Get Sui fullnode RPC URL
+    pub fn sui_rpc_url(&self) -> &str {
+        config::url_str(&self.config.sui_rpc_url)
+    }
+
+    /// `SUI_RPC_URL` followed by `SUI_RPC_FALLBACK_URLS`
+    pub fn sui_rpc_urls(&self) -> Vec<&str> {
+        std::iter::once(&self.config.sui_rpc_url)
+            .chain(&self.config.sui_rpc_fallback_urls)
+            .map(config::url_str)
+            .collect()
     }
 
     /// Get Ollama API URL
     pub fn ollama_api_url(&self) -> &str {
-        &self.ollama_api_url
+        config::url_str(&self.config.ollama_api_url)
+    }
+
+    /// Current values of the settings that can be updated on `POST /admin/config`
+    pub fn runtime_config(&self) -> runtime_config::RuntimeConfig {
+        runtime_config::RuntimeConfig::clone(&self.runtime_config.load())
     }
 
     /// Get Ollama model
-    pub fn ollama_model(&self) -> &str {
-        &self.ollama_model
+    pub fn ollama_model(&self) -> String {
+        self.runtime_config.load().ollama_model.clone()
     }
     
     pub fn azure_text_embedding_api_endpoint(&self) -> &str {
-        &self.azure_text_embedding_api_endpoint
+        config::url_str(&self.config.azure_text_embedding_api_endpoint)
     }
     
     pub fn azure_text_embedding_api_key(&self) -> &str {
-        &self.azure_text_embedding_api_key
+        self.config.azure_text_embedding_api_key.expose()
     }
 
     /// Get Qdrant URL
     pub fn qdrant_url(&self) -> &str {
-        &self.qdrant_url
+        config::url_str(&self.config.qdrant_url)
     }
 
     /// Get Qdrant API key
     pub fn qdrant_api_key(&self) -> Option<&str> {
-        self.qdrant_api_key.as_deref()
+        self.config.qdrant_api_key.as_ref().map(config::ApiKey::expose)
     }
 
     /// Get Qdrant collection name
     pub fn qdrant_collection_name(&self) -> &str {
-        &self.qdrant_collection_name
-    }
-
-    /// Get embedding batch size as string
-    pub fn embedding_batch_size_str(&self) -> &str {
-        &self.embedding_batch_size
+        &self.config.qdrant_collection_name
     }
 
-    /// Get embedding batch size as number
-    pub fn embedding_batch_size(&self) -> Result<u32, std::num::ParseIntError> {
-        self.embedding_batch_size.parse()
+    /// Collection a request targets: the requested one if allowlisted in
+    /// `QDRANT_COLLECTIONS`, otherwise the default collection.
+    pub fn qdrant_collection(&self, requested: Option<&str>) -> Result<&str, EnclaveError> {
+        match requested {
+            None => Ok(&self.config.qdrant_collection_name),
+            Some(collection) => self
+                .config
+                .qdrant_collections
+                .iter()
+                .find(|c| *c == collection)
+                .map(String::as_str)
+                .ok_or_else(|| {
+                    EnclaveError::BadRequest(format!(
+                        "Collection '{}' is not allowed, expected one of: {}",
+                        collection,
+                        self.config.qdrant_collections.join(", ")
+                    ))
+                }),
+        }
     }
 
-    /// Get vector batch size as string
-    pub fn vector_batch_size_str(&self) -> &str {
-        &self.vector_batch_size
+    /// Get embedding batch size
+    pub fn embedding_batch_size(&self) -> u32 {
+        self.runtime_config.load().embedding_batch_size
     }
 
-    /// Get vector batch size as number
-    pub fn vector_batch_size(&self) -> Result<u32, std::num::ParseIntError> {
-        self.vector_batch_size.parse()
+    /// Get vector batch size
+    pub fn vector_batch_size(&self) -> u32 {
+        self.runtime_config.load().vector_batch_size
     }
 
     pub fn telegram_social_truth_bot_id(&self) -> &str {
-        &self.telegram_social_truth_bot_id
+        &self.config.telegram_social_truth_bot_id
     }
 
     pub fn id_mask_salt(&self) -> &str {
-        &self.id_mask_salt
+        self.config.id_mask_salt.expose()
     }
 
-    /// Check if all required environment variables are properly configured
-    pub fn validate_config(&self) -> Result<(), String> {
-        if self.move_package_id.is_empty() {
-            return Err("MOVE_PACKAGE_ID is empty".to_string());
-        }
-        if self.sui_secret_key.is_empty() {
-            return Err("SUI_SECRET_KEY is empty".to_string());
-        }
-        if self.ruby_nodes_api_key.is_empty() {
-            return Err("RUBY_NODES_API_KEY is empty".to_string());
-        }
-        if self.walrus_aggregator_url.is_empty() {
-            return Err("WALRUS_AGGREGATOR_URL is empty".to_string());
-        }
-        if self.walrus_publisher_url.is_empty() {
-            return Err("WALRUS_PUBLISHER_URL is empty".to_string());
-        }
-        if self.walrus_epochs.is_empty() {
-            return Err("WALRUS_EPOCHS is empty".to_string());
-        }
-        if self.ollama_api_url.is_empty() {
-            return Err("OLLAMA_API_URL is empty".to_string());
-        }
-        if self.ollama_model.is_empty() {
-            return Err("OLLAMA_MODEL is empty".to_string());
+    /// Environment of the Node.js task of `operation`, run against the Qdrant `collection`.
+    /// Sets at least [task_env::Operation::required_env_vars].
+    pub fn task_env_vars(&self, operation: task_env::Operation, collection: &str) -> HashMap<String, String> {
+        let mut env_vars = HashMap::new();
+        // One snapshot, so an update in between cannot mix old and new settings
+        let runtime = self.runtime_config();
+
+        // Core blockchain configuration
+        env_vars.insert("MOVE_PACKAGE_ID".to_string(), self.move_package_id().to_string());
+        env_vars.insert("SUI_SECRET_KEY".to_string(), self.sui_secret_key().to_string());
+        env_vars.insert("RUBY_NODES_API_KEY".to_string(), self.ruby_nodes_api_key().to_string());
+        env_vars.insert("WALRUS_AGGREGATOR_URL".to_string(), self.walrus_aggregator_url().to_string());
+        if !self.config.walrus_aggregator_fallback_urls.is_empty() {
+            env_vars.insert(
+                "WALRUS_AGGREGATOR_FALLBACK_URLS".to_string(),
+                self.walrus_aggregator_fallback_urls().join(","),
+            );
         }
-        if self.azure_text_embedding_api_endpoint.is_empty() {
-            return Err("AZURE_TEXT_EMBEDDING_API_ENDPOINT is empty".to_string());
+        env_vars.insert("WALRUS_PUBLISHER_URL".to_string(), self.walrus_publisher_url().to_string());
+        env_vars.insert("WALRUS_EPOCHS".to_string(), self.walrus_epochs().to_string());
+
+        // Embedding backend, shared with queries embedded by the server
+        env_vars.insert("EMBEDDING_PROVIDER".to_string(), self.config.embedding_provider.to_string());
+        env_vars.insert("OLLAMA_API_URL".to_string(), self.ollama_api_url().to_string());
+        env_vars.insert("OLLAMA_MODEL".to_string(), runtime.ollama_model);
+        env_vars.insert("AZURE_TEXT_EMBEDDING_API_ENDPOINT".to_string(), self.azure_text_embedding_api_endpoint().to_string());
+        env_vars.insert("AZURE_TEXT_EMBEDDING_API_KEY".to_string(), self.azure_text_embedding_api_key().to_string());
+
+        // Qdrant vector database configuration
+        env_vars.insert("QDRANT_URL".to_string(), self.qdrant_url().to_string());
+        env_vars.insert("QDRANT_COLLECTION_NAME".to_string(), collection.to_string());
+        if let Some(api_key) = self.qdrant_api_key() {
+            env_vars.insert("QDRANT_API_KEY".to_string(), api_key.to_string());
         }
-        if self.azure_text_embedding_api_key.is_empty() {
-            return Err("AZURE_TEXT_EMBEDDING_API_KEY is empty".to_string());
+
+        // Task processing configuration
+        env_vars.insert("EMBEDDING_BATCH_SIZE".to_string(), runtime.embedding_batch_size.to_string());
+        env_vars.insert("VECTOR_BATCH_SIZE".to_string(), runtime.vector_batch_size.to_string());
+        env_vars.insert("TELEGRAM_SOCIAL_TRUTH_BOT_ID".to_string(), self.telegram_social_truth_bot_id().to_string());
+        env_vars.insert("ID_MASK_SALT".to_string(), self.id_mask_salt().to_string());
+
+        match operation {
+            task_env::Operation::ProcessData => {}
+            task_env::Operation::EmbeddingIngest => {
+                env_vars.extend(self.config.qdrant_collection_settings.task_env());
+            }
+            task_env::Operation::RetrieveMessagesByBlobIds | task_env::Operation::RetrieveMessagesFiltered => {
+                let search_params = serde_json::to_string(&self.collection_tuning.get(collection))
+                    .expect("search parameters serialize to JSON");
+                env_vars.insert("QDRANT_SEARCH_PARAMS".to_string(), search_params);
+            }
         }
-        if self.qdrant_url.is_empty() {
-            return Err("QDRANT_URL is empty".to_string());
+        if operation.uses_vectors() {
+            // Searches truncate query vectors like ingests truncate stored ones
+            if let Some(dimensions) = self.config.qdrant_collection_settings.embedding_dimensions {
+                env_vars.insert("EMBEDDING_DIMENSIONS".to_string(), dimensions.to_string());
+            }
+            env_vars.extend(self.config.vector_privacy.task_env());
         }
-        if self.qdrant_collection_name.is_empty() {
-            return Err("QDRANT_COLLECTION_NAME is empty".to_string());
+        env_vars
+    }
+
+    /// Whether the request carries `Authorization: Bearer <ADMIN_TOKEN>`. Admin endpoints
+    /// are disabled when no token is configured. The SHA-256 digests are compared without
+    /// stopping at the first difference, so timing reveals neither the token nor its length.
+    pub fn is_admin(&self, headers: &axum::http::HeaderMap) -> bool {
+        use fastcrypto::hash::{HashFunction, Sha256};
+        let Some(token) = self.admin_token.as_deref() else {
+            return false;
+        };
+        let Some(given) = headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        let (given, token) = (Sha256::digest(given.as_bytes()).digest, Sha256::digest(token.as_bytes()).digest);
+        given.iter().zip(token.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
+    /// Check the configuration against limits configured separately from [config::Config].
+    /// Presence and types are already guaranteed by [config::Config::from_env].
+    pub fn validate_config(&self) -> Result<(), String> {
+        self.walrus_budget
+            .check_epochs(self.config.walrus_epochs)
+            .map_err(|e| e.status_and_message().1)
+    }
+}
+
+impl EnclaveError {
+    /// Error for a failed call to an external service such as `walrus` or `qdrant`.
+    pub fn upstream(service: &str, message: impl Into<String>) -> Self {
+        EnclaveError::UpstreamUnavailable {
+            service: service.to_string(),
+            message: message.into(),
         }
-        if self.embedding_batch_size.is_empty() {
-            return Err("EMBEDDING_BATCH_SIZE is empty".to_string());
+    }
+
+    /// HTTP status code and message for the error.
+    pub fn status_and_message(self) -> (StatusCode, String) {
+        let status = self.status();
+        let message = match self {
+            EnclaveError::BadRequest(message)
+            | EnclaveError::Unauthorized(message)
+            | EnclaveError::Forbidden(message)
+            | EnclaveError::NotFound(message)
+            | EnclaveError::PayloadTooLarge(message)
+            | EnclaveError::Timeout(message)
+            | EnclaveError::ConfigError(message)
+            | EnclaveError::AttestationError(message)
+            | EnclaveError::Internal(message)
+            | EnclaveError::Overloaded { message, .. }
+            | EnclaveError::UpstreamUnavailable { message, .. } => message,
+            EnclaveError::TaskFailed { exit_code, stderr } => {
+                let hint = task_runner::diagnose_failure(&stderr)
+                    .map(|hint| format!(" ({})", hint))
+                    .unwrap_or_default();
+                format!("Task failed with exit code {}{}: {}", exit_code, hint, stderr)
+            }
+            EnclaveError::InvalidPayload(fields) => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("{}: {}", f.field, f.message)).collect();
+                format!("Invalid payload: {}", fields.join("; "))
+            }
+            EnclaveError::InvalidTaskResult(fields) => {
+                let fields: Vec<String> = fields.iter().map(|f| format!("{}: {}", f.field, f.message)).collect();
+                format!("Invalid task result: {}", fields.join("; "))
+            }
+            EnclaveError::TaskProtocolMismatch { protocol, task_version } => format!(
+                "Task{} speaks protocol version {}, the server supports versions {} to {}",
+                task_version.map(|v| format!(" version {}", v)).unwrap_or_default(),
+                protocol,
+                task_runner::SUPPORTED_TASK_PROTOCOL_VERSIONS.start(),
+                task_runner::SUPPORTED_TASK_PROTOCOL_VERSIONS.end()
+            ),
+        };
+        (status, message)
+    }
+
+    /// HTTP status code of the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            EnclaveError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            EnclaveError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            EnclaveError::Forbidden(_) => StatusCode::FORBIDDEN,
+            EnclaveError::NotFound(_) => StatusCode::NOT_FOUND,
+            EnclaveError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            EnclaveError::InvalidPayload(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            EnclaveError::Overloaded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            EnclaveError::TaskFailed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            EnclaveError::UpstreamUnavailable { .. }
+            | EnclaveError::InvalidTaskResult(_)
+            | EnclaveError::TaskProtocolMismatch { .. } => StatusCode::BAD_GATEWAY,
+            EnclaveError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            EnclaveError::ConfigError(_) | EnclaveError::AttestationError(_) | EnclaveError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         }
-        if self.vector_batch_size.is_empty() {
-            return Err("VECTOR_BATCH_SIZE is empty".to_string());
+    }
+
+    /// Machine readable error code, sent as `error.code` in the response envelope.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EnclaveError::BadRequest(_) => "bad_request",
+            EnclaveError::Unauthorized(_) => "unauthorized",
+            EnclaveError::Forbidden(_) => "forbidden",
+            EnclaveError::NotFound(_) => "not_found",
+            EnclaveError::PayloadTooLarge(_) => "payload_too_large",
+            EnclaveError::InvalidPayload(_) => "invalid_payload",
+            EnclaveError::Overloaded { .. } => "overloaded",
+            EnclaveError::TaskFailed { .. } => "task_failed",
+            EnclaveError::UpstreamUnavailable { .. } => "upstream_unavailable",
+            EnclaveError::InvalidTaskResult(_) => "invalid_task_result",
+            EnclaveError::TaskProtocolMismatch { .. } => "task_protocol_mismatch",
+            EnclaveError::Timeout(_) => "timeout",
+            EnclaveError::ConfigError(_) => "config_error",
+            EnclaveError::AttestationError(_) => "attestation_error",
+            EnclaveError::Internal(_) => "internal_error",
         }
-        if self.telegram_social_truth_bot_id.is_empty() {
-            return Err("TELEGRAM_SOCIAL_TRUTH_BOT_ID is empty".to_string());
+    }
+
+    /// Structured fields of the error, sent as `error.details`.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            EnclaveError::TaskFailed { exit_code, .. } => Some(serde_json::json!({ "exitCode": exit_code })),
+            EnclaveError::InvalidPayload(fields) | EnclaveError::InvalidTaskResult(fields) => {
+                Some(serde_json::json!({ "fields": fields }))
+            }
+            EnclaveError::UpstreamUnavailable { service, .. } => Some(serde_json::json!({ "service": service })),
+            EnclaveError::TaskProtocolMismatch { protocol, task_version } => Some(serde_json::json!({
+                "taskProtocol": protocol,
+                "taskVersion": task_version,
+                "supportedProtocols": [
+                    task_runner::SUPPORTED_TASK_PROTOCOL_VERSIONS.start(),
+                    task_runner::SUPPORTED_TASK_PROTOCOL_VERSIONS.end()
+                ],
+            })),
+            EnclaveError::Overloaded { retry_after_secs, .. } => {
+                Some(serde_json::json!({ "retryAfterSecs": retry_after_secs }))
+            }
+            _ => None,
         }
-        if self.id_mask_salt.is_empty() {
-            return Err("ID_MASK_SALT is empty".to_string());
+    }
+
+    /// Seconds the client should wait before retrying, sent as `Retry-After`.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            EnclaveError::Overloaded { retry_after_secs, .. } => Some(*retry_after_secs),
+            _ => None,
         }
-        
-        // Validate that numeric values are valid
-        self.walrus_epochs().map_err(|_| "WALRUS_EPOCHS must be a valid number".to_string())?;
-        self.embedding_batch_size().map_err(|_| "EMBEDDING_BATCH_SIZE must be a valid number".to_string())?;
-        self.vector_batch_size().map_err(|_| "VECTOR_BATCH_SIZE must be a valid number".to_string())?;
-        
-        Ok(())
     }
 }
 
-/// Implement IntoResponse for EnclaveError.
+/// Implement IntoResponse for EnclaveError, using the standard response envelope.
 impl IntoResponse for EnclaveError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            EnclaveError::GenericError(e) => (StatusCode::BAD_REQUEST, e),
-        };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
+        api_response::ApiResponse::from_error(self).into_response()
     }
 }
 
-/// Enclave errors enum.
-#[derive(Debug)]
+/// Enclave errors enum. Each variant maps to an HTTP status and an `error.code`. Serialized
+/// tagged with that code, so a failed job keeps the error it is served with.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "code", content = "detail", rename_all = "snake_case")]
 pub enum EnclaveError {
-    GenericError(String),
+    /// Invalid request; 400.
+    BadRequest(String),
+    /// Missing or wrong admin token; 401.
+    Unauthorized(String),
+    /// The enclave may not serve the request, e.g. a Seal policy it cannot decrypt under; 403.
+    Forbidden(String),
+    /// Job, blob, collection or other resource does not exist; 404.
+    NotFound(String),
+    /// Request body over `MAX_REQUEST_BODY_BYTES`, or blob over `WALRUS_MAX_INGEST_BLOB_BYTES`; 413.
+    PayloadTooLarge(String),
+    /// Payload fields that failed validation; 422 listing each field.
+    InvalidPayload(Vec<validation::FieldError>),
+    /// No capacity to run the request now; served as 429 with `Retry-After`.
+    Overloaded { message: String, retry_after_secs: u64 },
+    /// The Node.js task exited with a non-zero code; 422.
+    TaskFailed { exit_code: i32, stderr: String },
+    /// An external service (`walrus`, `sui`, `qdrant`, `embedding`) failed or returned an
+    /// unexpected response; 502.
+    UpstreamUnavailable { service: String, message: String },
+    /// The task reported a result that is no valid JSON or does not match the schema of
+    /// its operation; 502 listing each problem.
+    InvalidTaskResult(Vec<validation::FieldError>),
+    /// The task speaks a protocol version the server does not support, e.g. after the
+    /// server and task images were updated apart; 502.
+    TaskProtocolMismatch { protocol: u32, task_version: Option<String> },
+    /// A task or external call ran out of time; 504.
+    Timeout(String),
+    /// Invalid or missing server configuration; 500.
+    ConfigError(String),
+    /// The attestation document could not be produced; 500.
+    AttestationError(String),
+    /// Any other failure inside the server; 500.
+    #[serde(rename = "internal_error")]
+    Internal(String),
+}
+
+/// AppState with placeholder configuration for unit tests.
+#[cfg(test)]
+pub(crate) fn test_app_state() -> AppState {
+    AppState {
+        keys: Default::default(),
+        build_info: build_info::BuildInfo::compiled(),
+        attestation: common::AttestationProvider::Mock,
+        config: config::test_config(),
+        runtime_config: ArcSwap::from_pointee(runtime_config::RuntimeConfig::from_config(&config::test_config())),
+        config_updates: Default::default(),
+        walrus_store: walrus::StoreOptions::default(),
+        walrus_budget: walrus::StorageBudget::default(),
+        jobs: jobs::JobStore::new(),
+        idempotency: idempotency::IdempotencyStore::default(),
+        feedback: feedback::FeedbackStore::new(),
+        experiments: experiments::RetrievalExperiments::default(),
+        scheduler: std::sync::Arc::new(scheduler::TaskScheduler::new(
+            1,
+            std::time::Duration::from_secs(30),
+        )),
+        anchor_receipts: false,
+        task_scheduling: task_runner::SchedulingHints::default(),
+        task_node_flags: task_runner::NodeFlagsByOperation::default(),
+        task_retry: task_runner::TaskRetryPolicy::default(),
+        task_bundles: task_bundles::TaskBundles::single(std::path::Path::new(".")),
+        worker_pool: None,
+        dependency_status: dependency_allowlist::DependencyStatus::Disabled,
+        metrics: metrics::Metrics::new(),
+        key_usage: key_usage::KeyUsage::default(),
+        address_limits: address_limits::AddressLimits::default(),
+        caller_limits: caller_limits::CallerLimits::default(),
+        breakers: breakers::CircuitBreakers::default(),
+        sui_cache: sui::SuiCache::default(),
+        tx_sequencer: tx_sequencer::TransactionSequencer::default(),
+        runtime_health: runtime_health::RuntimeHealth::default(),
+        crash_reports: std::sync::Arc::new(
+            crash_reports::CrashReportStore::with_hex_key(std::env::temp_dir().join("nautilus-crash-reports"), None)
+                .unwrap(),
+        ),
+        admin_token: None,
+        request_log: request_log::RequestLog::default(),
+        audit_log: audit::AuditLog::default(),
+        task_audit: task_audit::TaskAuditLog::default(),
+        collection_tuning: collections::CollectionTuning::default(),
+        endpoints: endpoints::EndpointRegistry::default(),
+        replication: replication::Replication::default(),
+        leader: leader::LeaderElection::default(),
+        embeddings: ArcSwap::from_pointee(embeddings::EmbeddingProvider::from_config(&config::test_config()).unwrap()),
+        authorization: std::sync::Arc::new(authorization::AllowAll),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fastcrypto::{ed25519::Ed25519KeyPair, traits::KeyPair};
-    use std::collections::HashMap;
-    use crate::task_runner::{NodeTaskRunner, TaskConfig};
+
+    #[test]
+    fn test_is_admin() {
+        let bearer = |value: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(axum::http::header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+        let mut state = test_app_state();
+        assert!(!state.is_admin(&bearer("Bearer secret")));
+
+        state.admin_token = Some("secret".to_string());
+        assert!(state.is_admin(&bearer("Bearer secret")));
+        assert!(!state.is_admin(&bearer("Bearer secre")));
+        assert!(!state.is_admin(&bearer("Bearer secret2")));
+        assert!(!state.is_admin(&bearer("secret")));
+        assert!(!state.is_admin(&axum::http::HeaderMap::new()));
+    }
 
     #[tokio::test]
     async fn test_env_vars_passing() {
-        // Create AppState with test values
-        let state = AppState {
-            eph_kp: Ed25519KeyPair::generate(&mut rand::thread_rng()),
-            move_package_id: "0x1234567890abcdef".to_string(),
-            sui_secret_key: "suiprivkey1qtest".to_string(),
-            ruby_nodes_api_key: "ABC123".to_string(),
-            walrus_aggregator_url: "https://aggregator.walrus-testnet.walrus.space".to_string(),
-            walrus_publisher_url: "https://publisher.walrus-testnet.walrus.space".to_string(),
-            walrus_epochs: "5".to_string(),
-            ollama_api_url: "http://localhost:11434".to_string(),
-            ollama_model: "nomic-embed-text".to_string(),
-            azure_text_embedding_api_endpoint: "https://example.com".to_string(),
-            azure_text_embedding_api_key: "test-key".to_string(),
-            qdrant_url: "http://localhost:6333".to_string(),
-            qdrant_api_key: None,
-            qdrant_collection_name: "messages".to_string(),
-            embedding_batch_size: "10".to_string(),
-            vector_batch_size: "100".to_string(),
-            telegram_social_truth_bot_id: "123456789".to_string(),
-            id_mask_salt: "test-salt".to_string(),
-        };
+        let state = test_app_state();
 
-        // Create environment variables map
-        let mut env_vars = HashMap::new();
-        env_vars.insert("MOVE_PACKAGE_ID".to_string(), state.move_package_id().to_string());
-        env_vars.insert("SUI_SECRET_KEY".to_string(), state.sui_secret_key().to_string());
-        env_vars.insert("RUBY_NODES_API_KEY".to_string(), state.ruby_nodes_api_key().to_string());
-        env_vars.insert("WALRUS_AGGREGATOR_URL".to_string(), state.walrus_aggregator_url().to_string());
-        env_vars.insert("WALRUS_PUBLISHER_URL".to_string(), state.walrus_publisher_url().to_string());
-        env_vars.insert("WALRUS_EPOCHS".to_string(), state.walrus_epochs_str().to_string());
+        let env_vars = state.task_env_vars(task_env::Operation::ProcessData, state.qdrant_collection_name());
 
         // Verify that env vars from AppState are correctly mapped
         assert_eq!(env_vars.get("MOVE_PACKAGE_ID").unwrap(), "0x1234567890abcdef");
@@ -282,4 +678,17 @@ mod tests {
             println!("  {}: {}", key, if key.contains("SECRET") { "***hidden***" } else { value });
         }
     }
+
+    #[test]
+    fn test_qdrant_collection_allowlist() {
+        let mut state = test_app_state();
+        state.config.qdrant_collections = vec!["messages".to_string(), "documents".to_string()];
+
+        assert_eq!(state.qdrant_collection(None).unwrap(), "messages");
+        assert_eq!(state.qdrant_collection(Some("documents")).unwrap(), "documents");
+        match state.qdrant_collection(Some("secrets")) {
+            Err(EnclaveError::BadRequest(message)) => assert!(message.contains("messages, documents")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,272 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP listener. The server binds `BIND_ADDR:PORT` and serves plain HTTP by default, as
+//! when a vsock proxy on the parent instance terminates the client connection. With
+//! `TLS_MODE` set it terminates TLS itself, for deployments where clients connect to the
+//! enclave over direct TCP:
+//!
+//! - `files` loads a PEM certificate chain and private key from `TLS_CERT_PATH` and
+//!   `TLS_KEY_PATH`.
+//! - `self_signed` issues a certificate for the ephemeral key at boot. Its public key is the
+//!   one in the attestation document, so a client that verified the attestation can pin the
+//!   certificate instead of trusting a CA.
+
+use anyhow::{Context, Result};
+use axum::extract::ConnectInfo;
+use axum::{Extension, Router};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tracing::debug;
+
+/// Default address the server binds.
+pub const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// Default port the server listens on.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Name the self-signed certificate is issued for.
+const SELF_SIGNED_NAME: &str = "nautilus-enclave";
+
+/// PKCS#8 v1 prefix of an Ed25519 private key, followed by the 32 byte seed (RFC 8410).
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Source of the TLS certificate, from `TLS_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsMode {
+    /// Plain HTTP
+    Off,
+    /// Certificate and key from `TLS_CERT_PATH` and `TLS_KEY_PATH`
+    Files,
+    /// Certificate issued at boot for the ephemeral key
+    SelfSigned,
+}
+
+impl FromStr for TlsMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Ok(TlsMode::Off),
+            "files" => Ok(TlsMode::Files),
+            "self_signed" => Ok(TlsMode::SelfSigned),
+            other => Err(format!("unknown TLS mode {}, expected off, files or self_signed", other)),
+        }
+    }
+}
+
+impl fmt::Display for TlsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsMode::Off => write!(f, "off"),
+            TlsMode::Files => write!(f, "files"),
+            TlsMode::SelfSigned => write!(f, "self_signed"),
+        }
+    }
+}
+
+/// Where the server's certificate comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsConfig {
+    Files { cert_path: PathBuf, key_path: PathBuf },
+    SelfSigned,
+}
+
+/// Listener settings, from `BIND_ADDR`, `PORT` and the `TLS_*` variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenConfig {
+    pub bind_addr: IpAddr,
+    pub port: u16,
+    /// Plain HTTP when unset
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: DEFAULT_BIND_ADDR,
+            port: DEFAULT_PORT,
+            tls: None,
+        }
+    }
+}
+
+impl ListenConfig {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_addr, self.port)
+    }
+
+    /// Build the TLS acceptor, if TLS is on. `eph_kp` is the key of a self-signed certificate.
+    pub fn tls_acceptor(&self, eph_kp: &Ed25519KeyPair) -> Result<Option<TlsAcceptor>> {
+        let (certs, key) = match &self.tls {
+            None => return Ok(None),
+            Some(TlsConfig::Files { cert_path, key_path }) => load_pem(cert_path, key_path)?,
+            Some(TlsConfig::SelfSigned) => self_signed_cert(eph_kp)?,
+        };
+        let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("Failed to select TLS versions")?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("Invalid TLS certificate or key")?;
+        Ok(Some(TlsAcceptor::from(Arc::new(config))))
+    }
+}
+
+/// Read a PEM certificate chain and the first private key of a PEM key file.
+fn load_pem(cert_path: &Path, key_path: &Path) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let cert_pem = std::fs::read(cert_path).with_context(|| format!("Failed to read {}", cert_path.display()))?;
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate in {}", cert_path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate in {}", cert_path.display());
+    }
+    let key_pem = std::fs::read(key_path).with_context(|| format!("Failed to read {}", key_path.display()))?;
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .with_context(|| format!("Invalid private key in {}", key_path.display()))?
+        .with_context(|| format!("No private key in {}", key_path.display()))?;
+    Ok((certs, key))
+}
+
+/// Issue a self-signed certificate whose key is `eph_kp`.
+pub fn self_signed_cert(eph_kp: &Ed25519KeyPair) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let mut pkcs8 = ED25519_PKCS8_PREFIX.to_vec();
+    pkcs8.extend_from_slice(eph_kp.copy().private().as_bytes());
+    let pkcs8 = PrivatePkcs8KeyDer::from(pkcs8);
+    let key_pair = rcgen::KeyPair::from_pkcs8_der_and_sign_algo(&pkcs8, &rcgen::PKCS_ED25519)
+        .context("Failed to load the ephemeral key")?;
+    let cert = rcgen::CertificateParams::new(vec![SELF_SIGNED_NAME.to_string()])
+        .and_then(|params| params.self_signed(&key_pair))
+        .context("Failed to issue a self-signed certificate")?;
+    Ok((vec![cert.der().clone()], PrivateKeyDer::Pkcs8(pkcs8)))
+}
+
+/// Serve `app` on `listener`, over TLS when an acceptor is given.
+pub async fn serve(listener: TcpListener, app: Router, tls: Option<TlsAcceptor>) -> Result<()> {
+    let Some(acceptor) = tls else {
+        return axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .map_err(|e| anyhow::anyhow!("Server error: {}", e));
+    };
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Such as running out of file descriptors; keep accepting once it clears
+                debug!("Failed to accept connection: {}", e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone().layer(Extension(ConnectInfo(peer))));
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+    #[test]
+    fn test_tls_mode() {
+        assert_eq!("Self_Signed".parse::<TlsMode>(), Ok(TlsMode::SelfSigned));
+        assert_eq!("off".parse::<TlsMode>(), Ok(TlsMode::Off));
+        assert!("on".parse::<TlsMode>().is_err());
+        assert_eq!(TlsMode::Files.to_string(), "files");
+    }
+
+    #[test]
+    fn test_self_signed_cert_carries_ephemeral_key() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let (certs, _) = self_signed_cert(&kp).unwrap();
+        // The SubjectPublicKeyInfo ends with the raw Ed25519 public key
+        let der = certs[0].as_ref();
+        let public_key = kp.public().as_bytes();
+        assert!(der.windows(public_key.len()).any(|window| window == public_key));
+    }
+
+    #[test]
+    fn test_files_must_exist() {
+        let config = ListenConfig {
+            tls: Some(TlsConfig::Files {
+                cert_path: "/nonexistent/cert.pem".into(),
+                key_path: "/nonexistent/key.pem".into(),
+            }),
+            ..Default::default()
+        };
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        assert!(config.tls_acceptor(&kp).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_serves_over_self_signed_tls() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let config = ListenConfig {
+            bind_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            tls: Some(TlsConfig::SelfSigned),
+        };
+        let acceptor = config.tls_acceptor(&kp).unwrap();
+        let listener = TcpListener::bind(config.socket_addr()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", axum::routing::get(|| async { "Pong!" }));
+        tokio::spawn(serve(listener, app, acceptor));
+
+        // Pin the self-signed certificate, as a client that verified the attestation would
+        let mut roots = RootCertStore::empty();
+        roots.add(self_signed_cert(&kp).unwrap().0.remove(0)).unwrap();
+        let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = tokio_rustls::TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from(SELF_SIGNED_NAME).unwrap(), stream)
+            .await
+            .unwrap();
+
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: enclave\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("Pong!"));
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Log output. A minimal `tracing` subscriber prints events to stderr and keeps the most
+//! recent lines in memory so crash reports can include what happened before a panic.
+//! Fields of the spans an event happens in, such as the `request_id` of
+//! [crate::crash_reports::request_span], are appended to its line.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Log lines kept for crash reports by default.
+pub const DEFAULT_LOG_BUFFER_LINES: usize = 200;
+
+/// Fixed-size buffer of the most recent log lines.
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Mutex<VecDeque<String>>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn push(&self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Buffered lines, oldest first.
+    pub fn snapshot(&self) -> Vec<String> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        lines.iter().cloned().collect()
+    }
+}
+
+struct LineVisitor<'a> {
+    line: &'a mut String,
+}
+
+impl Visit for LineVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.line, " {}", value);
+        } else {
+            let _ = write!(self.line, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.line, " {:?}", value);
+        } else {
+            let _ = write!(self.line, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Formatted fields of a span and the number of handles to it.
+struct SpanFields {
+    fields: String,
+    refs: usize,
+}
+
+thread_local! {
+    /// Spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Subscriber writing `<unix ms> <LEVEL> <target>: <message> <fields> <span fields>` lines
+/// to stderr and to a [LogBuffer].
+pub struct RingBufferSubscriber {
+    buffer: Arc<LogBuffer>,
+    max_level: Level,
+    pretty: bool,
+    next_span_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanFields>>,
+}
+
+impl RingBufferSubscriber {
+    pub fn new(buffer: Arc<LogBuffer>, max_level: Level) -> Self {
+        Self {
+            buffer,
+            max_level,
+            pretty: false,
+            next_span_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Print `HH:MM:SS.mmm` UTC times and colored levels to stderr, for `--dev`. Buffered
+    /// lines keep the plain format.
+    pub fn pretty(mut self) -> Self {
+        self.pretty = true;
+        self
+    }
+
+    fn format(&self, event: &Event<'_>) -> (u128, String) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let metadata = event.metadata();
+        let mut line = format!("{} {}:", metadata.level(), metadata.target());
+        event.record(&mut LineVisitor { line: &mut line });
+        let spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        ENTERED.with(|entered| {
+            for id in entered.borrow().iter() {
+                if let Some(span) = spans.get(id) {
+                    line.push_str(&span.fields);
+                }
+            }
+        });
+        (timestamp_ms, line)
+    }
+
+    fn pretty_line(timestamp_ms: u128, level: &Level, line: &str) -> String {
+        let color = match *level {
+            Level::ERROR => "31",
+            Level::WARN => "33",
+            Level::INFO => "32",
+            Level::DEBUG => "34",
+            Level::TRACE => "90",
+        };
+        let ms_of_day = timestamp_ms % 86_400_000;
+        // The level is the first word of the line
+        let rest = line.split_once(' ').map_or("", |(_, rest)| rest);
+        format!(
+            "{:02}:{:02}:{:02}.{:03} \x1b[{}m{:>5}\x1b[0m {}",
+            ms_of_day / 3_600_000,
+            ms_of_day / 60_000 % 60,
+            ms_of_day / 1000 % 60,
+            ms_of_day % 1000,
+            color,
+            level,
+            rest
+        )
+    }
+}
+
+impl Subscriber for RingBufferSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.max_level
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_span_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = String::new();
+        span.record(&mut LineVisitor { line: &mut fields });
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.insert(id, SpanFields { fields, refs: 1 });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(span) = spans.get_mut(&span.into_u64()) {
+            values.record(&mut LineVisitor { line: &mut span.fields });
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let (timestamp_ms, line) = self.format(event);
+        if self.pretty {
+            eprintln!("{}", Self::pretty_line(timestamp_ms, event.metadata().level(), &line));
+        } else {
+            eprintln!("{} {}", timestamp_ms, line);
+        }
+        self.buffer.push(format!("{} {}", timestamp_ms, line));
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(position) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(fields) = spans.get_mut(&span.into_u64()) {
+            fields.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        let id = span.into_u64();
+        let closed = match spans.get_mut(&id) {
+            Some(fields) => {
+                fields.refs -= 1;
+                fields.refs == 0
+            }
+            None => false,
+        };
+        if closed {
+            spans.remove(&id);
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_keeps_latest_lines() {
+        let buffer = Arc::new(LogBuffer::new(2));
+        let subscriber = RingBufferSubscriber::new(buffer.clone(), Level::INFO);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::debug!("filtered");
+            tracing::warn!(blob_id = "abc", "second");
+            tracing::error!("third");
+        });
+
+        let lines = buffer.snapshot();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("WARN") && lines[0].ends_with("second blob_id=\"abc\""));
+        assert!(lines[1].ends_with("third"));
+    }
+
+    #[test]
+    fn test_span_fields() {
+        let buffer = Arc::new(LogBuffer::new(10));
+        let subscriber = RingBufferSubscriber::new(buffer.clone(), Level::INFO);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "req-1");
+            span.in_scope(|| tracing::info!("inside"));
+            tracing::info!("outside");
+        });
+
+        let lines = buffer.snapshot();
+        assert!(lines[0].ends_with("inside request_id=\"req-1\""));
+        assert!(lines[1].ends_with("outside"));
+    }
+
+    #[test]
+    fn test_pretty_line() {
+        let line = RingBufferSubscriber::pretty_line(3_723_004, &Level::WARN, "WARN app: slow blob_id=\"abc\"");
+        assert_eq!(line, "01:02:03.004 \x1b[33m WARN\x1b[0m app: slow blob_id=\"abc\"");
+    }
+}
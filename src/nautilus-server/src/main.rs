@@ -1,118 +1,608 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
-use axum::{routing::get, routing::post, Router};
-use fastcrypto::{ed25519::Ed25519KeyPair, traits::KeyPair};
-use nautilus_server::app::{process_data, embedding_ingest, retrieve_messages_by_blob_ids};
-use nautilus_server::common::{get_attestation, health_check, get_config};
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use axum::extract::DefaultBodyLimit;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::{ed25519::Ed25519KeyPair, traits::{KeyPair, ToFromBytes}};
+use nautilus_server::address_limits::AddressLimits;
+use nautilus_server::breakers::{list_breakers, reset_breaker, CircuitBreakers};
+use nautilus_server::caller_limits::{limit_caller_rate, CallerLimits};
+use nautilus_server::app::{process_data, embedding_ingest, retrieve_messages_by_blob_ids, retrieve_messages_filtered};
+use nautilus_server::sui::{register_attestation, SuiCache};
+use nautilus_server::tx_sequencer::{spawn_gas_lane_setup, TransactionSequencer};
+use nautilus_server::task_stream::{embedding_ingest_stream, process_data_stream};
+use nautilus_server::audit::{audit_events, AuditLog};
+use nautilus_server::build_info::{version, BuildInfo};
+use nautilus_server::canonical::{canonical_test_vectors, verify_canonical};
+use nautilus_server::collections::{tune_collection, CollectionTuning};
+use nautilus_server::embeddings::{EmbeddingProvider, Provider};
+use nautilus_server::endpoints::{
+    check_endpoints, reload_endpoints, validate_endpoints, AllowedEndpoints, EndpointRegistry, DEFAULT_ENDPOINTS_FILE,
+};
+use nautilus_server::experiments::{experiments, RetrievalExperiments};
+use nautilus_server::feedback::{feedback_metrics, submit_feedback, FeedbackStore};
+use nautilus_server::ingest_batch::embedding_ingest_batch;
+use nautilus_server::internal_key::KeySource;
+use nautilus_server::dependency_allowlist::{DependencyStatus, DEFAULT_ALLOWLIST_FILE};
+use nautilus_server::common::{
+    get_attestation, get_boot_attestation, get_config, health_check, post_attestation, AttestationProvider,
+};
+use nautilus_server::config::{url_str, ApiKey, Config};
+use nautilus_server::config_check::{check_config, CONFIG_VARS};
+use nautilus_server::dev::{watch_task_directory, RouteTable, DEFAULT_TASK_WATCH_INTERVAL};
+use nautilus_server::crash_reports::{
+    crash_reports, install_panic_hook, panic_response, scope_request_id, CrashReportStore,
+};
+use nautilus_server::logging::{LogBuffer, RingBufferSubscriber, DEFAULT_LOG_BUFFER_LINES};
+use nautilus_server::idempotency::IdempotencyStore;
+use nautilus_server::jobs::{get_job, get_job_result, wait_for_job, JobStore};
+use nautilus_server::key_manager::{get_signing_keys, rotate_key, spawn_key_rotation, KeyManager};
+use nautilus_server::key_usage::KeyUsage;
+use nautilus_server::leader::{spawn_leader_election, LeaderElection};
+use nautilus_server::listener::{serve, TlsConfig};
+use nautilus_server::metrics::{metrics, track_http_metrics, Metrics};
+use nautilus_server::openapi::{openapi_json, swagger_ui};
+use nautilus_server::payload_crypto::{decrypt_messages, rotate_payload_keys};
+use nautilus_server::schemas::{operation_schemas, schema_index};
+use nautilus_server::qdrant::{collection_info, create_collection, delete_collection};
+use nautilus_server::reaper::spawn_vector_reaper;
+use nautilus_server::retention::{retention_status, run_retention_cleanup, spawn_job_cleanup};
+use nautilus_server::replication::{replication_status, replication_sync, spawn_primary, Replication, ReplicationRole};
+use nautilus_server::soft_delete::{delete_messages, delete_vectors, restore_messages};
+use nautilus_server::request_log::{record_request, recent_requests, RequestLog};
+use nautilus_server::runtime_config::{update_config, RuntimeConfig};
+use nautilus_server::runtime_health::{readyz, RuntimeHealth};
+use nautilus_server::storage::Storage;
+use nautilus_server::task_audit::{task_audit, TaskAuditLog};
+use nautilus_server::telemetry;
+use nautilus_server::scheduler::TaskScheduler;
+use nautilus_server::task_runner::{WorkerPool, WorkerPoolConfig};
+use nautilus_server::validation::limit_request_body;
 use nautilus_server::AppState;
 use std::sync::Arc;
+use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::cors::{Any, CorsLayer, AllowHeaders};
-use tracing::info;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Development mode: mock attestation, placeholder config, route listing, verbose pretty
+    // logs and task directory reloading
+    let dev_mode = args.iter().any(|a| a == "--dev");
+
+    // Load all environment variables required by the application, reporting every missing
+    // or invalid one at once. These values are stored in AWS Secrets Manager and injected
+    // via configure_enclave.sh
+    let loaded = if dev_mode {
+        Config::from_lookup_relaxed(&|name| std::env::var(name).ok())
+    } else {
+        Config::from_env().map(|config| (config, Vec::new()))
+    };
+
+    // Log to stderr and keep the latest lines for crash reports. The validation modes
+    // below log at the defaults when the configuration is invalid.
+    let (log_level, log_lines) = match &loaded {
+        Ok((config, _)) => (config.log_level, config.crash_report_log_lines),
+        Err(_) if dev_mode => (tracing::Level::DEBUG, DEFAULT_LOG_BUFFER_LINES),
+        Err(_) => (tracing::Level::INFO, DEFAULT_LOG_BUFFER_LINES),
+    };
+    let log_buffer = Arc::new(LogBuffer::new(log_lines));
+    let subscriber = RingBufferSubscriber::new(log_buffer.clone(), log_level);
+    let subscriber = if dev_mode { subscriber.pretty() } else { subscriber };
+    tracing::subscriber::set_global_default(subscriber).context("Failed to install log subscriber")?;
+
+    // Validation modes for deployment pipelines: print JSON to stdout and exit
+    if args.iter().any(|a| a == "--config-schema") {
+        println!("{}", serde_json::to_string_pretty(CONFIG_VARS)?);
+        return Ok(());
+    }
+    if args.iter().any(|a| a == "--check-config") {
+        let task_path = std::env::current_dir()?.join("nodejs-task");
+        let mut report = check_config(&|name| std::env::var(name).ok(), &task_path);
+        if args.iter().any(|a| a == "--check-connectivity") {
+            match AllowedEndpoints::load(std::path::Path::new(DEFAULT_ENDPOINTS_FILE)) {
+                Ok(endpoints) => report.add_connectivity(check_endpoints(&reqwest::Client::new(), &endpoints).await),
+                Err(e) => report.add_endpoints_error(DEFAULT_ENDPOINTS_FILE, e.status_and_message().1),
+            }
+        }
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        std::process::exit(if report.valid { 0 } else { 1 });
+    }
+
     let eph_kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
 
-    // Load all environment variables required by the application
-    // These values are stored in AWS Secrets Manager and injected via configure_enclave.sh
-    let move_package_id = std::env::var("MOVE_PACKAGE_ID").expect("MOVE_PACKAGE_ID must be set");
-    let sui_secret_key = std::env::var("SUI_SECRET_KEY").expect("SUI_SECRET_KEY must be set");
-    let ruby_nodes_api_key = std::env::var("RUBY_NODES_API_KEY").expect("RUBY_NODES_API_KEY must be set");
-    let walrus_aggregator_url = std::env::var("WALRUS_AGGREGATOR_URL").expect("WALRUS_AGGREGATOR_URL must be set");
-    let walrus_publisher_url = std::env::var("WALRUS_PUBLISHER_URL").expect("WALRUS_PUBLISHER_URL must be set");
-    let walrus_epochs = std::env::var("WALRUS_EPOCHS").expect("WALRUS_EPOCHS must be set");
-    
-    // Load Ollama embedding service configuration
-    let ollama_api_url = std::env::var("OLLAMA_API_URL").unwrap_or_else(|_| "http://localhost:11434".to_string());
-    let ollama_model = std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| "nomic-embed-text".to_string());
-    
-    // Load Azure open ai embedding service configuration
-    let azure_text_embedding_api_endpoint = std::env::var("AZURE_TEXT_EMBEDDING_API_ENDPOINT").expect("AZURE_TEXT_EMBEDDING_API_ENDPOINT must be set");
-    let azure_text_embedding_api_key = std::env::var("AZURE_TEXT_EMBEDDING_API_KEY").expect("AZURE_TEXT_EMBEDDING_API_KEY must be set");
-    
-    // Load Qdrant vector database configuration
-    let qdrant_url = std::env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6333".to_string());
-    let qdrant_api_key = std::env::var("QDRANT_API_KEY").ok(); // Optional
-    let qdrant_collection_name = std::env::var("QDRANT_COLLECTION_NAME").unwrap_or_else(|_| "messages".to_string());
-    
-    // Load task processing configuration
-    let embedding_batch_size = std::env::var("EMBEDDING_BATCH_SIZE").unwrap_or_else(|_| "10".to_string());
-    let vector_batch_size = std::env::var("VECTOR_BATCH_SIZE").unwrap_or_else(|_| "100".to_string());
-
-    // Load Telegram Social Truth Bot configuration
-    let telegram_social_truth_bot_id = std::env::var("TELEGRAM_SOCIAL_TRUTH_BOT_ID").expect("TELEGRAM_SOCIAL_TRUTH_BOT_ID must be set");
-
-    // Load ID mask salt configuration
-    let id_mask_salt = std::env::var("ID_MASK_SALT").expect("ID_MASK_SALT must be set");
+    let (config, warnings) = loaded?;
+    if dev_mode {
+        warn!("🛠️  Development mode: mock attestation, relaxed validation, task directory reloading");
+        for warning in warnings {
+            warn!("  {}", warning);
+        }
+    }
+
+    let task_scheduling = config.task_scheduling.clone();
+    let task_node_flags = config.task_node_flags.clone();
+    let task_retry = config.task_retry.clone();
+    let task_bundles = config.task_bundles.clone();
+    let retrieval_experiments = RetrievalExperiments::new(config.retrieval_profiles.clone())?;
+    let key_usage = KeyUsage::new(config.signing_rate_limits.clone());
+    let address_limits = AddressLimits::new(config.address_rate_limits.clone());
+    let caller_limits = CallerLimits::new(config.caller_rate_limit_key, config.caller_rate_limits.clone());
+    let crash_store = Arc::new(
+        CrashReportStore::with_keys(
+            &config.crash_report_dir,
+            config.crash_report_key.as_ref().map(ApiKey::expose),
+            config.internal_encryption_key.as_ref(),
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid CRASH_REPORT_KEY: {:?}", e))?,
+    );
+    let admin_token = config.admin_token.as_ref().map(|token| token.expose().to_string());
 
     // Log loaded configuration (without sensitive values)
     info!("Loading Nautilus server configuration:");
-    info!("  MOVE_PACKAGE_ID: {}", move_package_id);
-    info!("  WALRUS_AGGREGATOR_URL: {}", walrus_aggregator_url);
-    info!("  WALRUS_PUBLISHER_URL: {}", walrus_publisher_url);
-    info!("  WALRUS_EPOCHS: {}", walrus_epochs);
-    info!("  OLLAMA_API_URL: {}", ollama_api_url);
-    info!("  OLLAMA_MODEL: {}", ollama_model);
-    info!("  AZURE_TEXT_EMBEDDING_API_ENDPOINT: {}", azure_text_embedding_api_endpoint);
-    info!("  AZURE_TEXT_EMBEDDING_API_KEY: {}", azure_text_embedding_api_key);
-    info!("  QDRANT_URL: {}", qdrant_url);
-    info!("  QDRANT_COLLECTION_NAME: {}", qdrant_collection_name);
-    info!("  EMBEDDING_BATCH_SIZE: {}", embedding_batch_size);
-    info!("  VECTOR_BATCH_SIZE: {}", vector_batch_size);
+    info!("  MOVE_PACKAGE_ID: {}", config.move_package_id);
+    info!("  WALRUS_AGGREGATOR_URL: {}", config.walrus_aggregator_url);
+    for url in &config.walrus_aggregator_fallback_urls {
+        info!("  WALRUS_AGGREGATOR_FALLBACK_URL: {}", url);
+    }
+    info!("  WALRUS_PUBLISHER_URL: {}", config.walrus_publisher_url);
+    info!("  WALRUS_EPOCHS: {}", config.walrus_epochs);
+    info!("  EMBEDDING_PROVIDER: {}", config.embedding_provider);
+    info!("  OLLAMA_API_URL: {}", config.ollama_api_url);
+    info!("  OLLAMA_MODEL: {}", config.ollama_model);
+    info!("  AZURE_TEXT_EMBEDDING_API_ENDPOINT: {}", config.azure_text_embedding_api_endpoint);
+    info!("  AZURE_TEXT_EMBEDDING_API_KEY: ****** (hidden)");
+    info!("  QDRANT_URL: {}", config.qdrant_url);
+    info!("  QDRANT_COLLECTION_NAME: {}", config.qdrant_collection_name);
+    info!("  QDRANT_COLLECTIONS: {}", config.qdrant_collections.join(", "));
+    if config.vector_reaper_interval_secs > 0 {
+        info!(
+            "  VECTOR_REAPER: every {}s, {} grace epochs",
+            config.vector_reaper_interval_secs, config.vector_ttl_grace_epochs
+        );
+    } else {
+        info!("  VECTOR_REAPER: disabled");
+    }
+    info!(
+        "  JOB_RETENTION: {}s, at most {} jobs and {} bytes, cleaned up every {}s",
+        config.job_retention.max_age_secs,
+        config.job_retention.max_count.map_or("unlimited".to_string(), |v| v.to_string()),
+        config.job_retention.max_bytes.map_or("unlimited".to_string(), |v| v.to_string()),
+        config.job_cleanup_interval_secs
+    );
+    info!("  QDRANT_DISTANCE: {}", config.qdrant_collection_settings.distance);
+    info!(
+        "  QDRANT_HNSW: m={:?} ef_construct={:?}, search ef={:?}",
+        config.qdrant_collection_settings.hnsw_m,
+        config.qdrant_collection_settings.hnsw_ef_construct,
+        config.qdrant_search_params.hnsw_ef
+    );
+    info!(
+        "  EMBEDDING_DIMENSIONS: {}",
+        config
+            .qdrant_collection_settings
+            .embedding_dimensions
+            .map_or("all".to_string(), |d| d.to_string())
+    );
+    match config.ingest_warmup.min_points {
+        Some(min_points) => info!(
+            "  INGEST_WARMUP: after {} points, waiting up to {}s, {} queries",
+            min_points, config.ingest_warmup.timeout_secs, config.ingest_warmup.queries
+        ),
+        None => info!("  INGEST_WARMUP: disabled"),
+    }
+    info!("  EMBEDDING_BATCH_SIZE: {}", config.embedding_batch_size);
+    info!("  VECTOR_BATCH_SIZE: {}", config.vector_batch_size);
+    info!("  MAX_CONCURRENT_TASKS: {}", config.max_concurrent_tasks);
+    info!("  PRIORITY_AGING_SECS: {}", config.priority_aging_secs);
+    info!("  MAX_QUEUED_TASKS: {}", config.max_queued_tasks);
+    info!("  TASK_QUEUE_TIMEOUT_SECS: {}", config.task_queue_timeout_secs);
+    info!("  TASK_CPU_AFFINITY: {:?}", task_scheduling.cpu_affinity);
+    info!("  TASK_NICE: {:?}", task_scheduling.nice);
+    for bundle in task_bundles.configured() {
+        info!(
+            "  TASK_BUNDLES: {} at {} for {} (env: {})",
+            bundle.name,
+            bundle.path.display(),
+            bundle.operations.join(","),
+            bundle.env.as_ref().map_or("all".to_string(), |env| env.iter().cloned().collect::<Vec<_>>().join(","))
+        );
+    }
+    info!("  TASK_NODE_OPTIONS: {:?}", task_node_flags.default.to_args());
+    for (operation, flags) in &task_node_flags.operations {
+        info!("  TASK_NODE_OPTIONS_{}: {:?}", operation.to_uppercase(), flags.to_args());
+    }
+    info!(
+        "  TASK_RETRY: {} attempts, backoff {}ms, exit codes {:?}, error classes {:?}",
+        task_retry.max_attempts, task_retry.initial_backoff_ms, task_retry.retry_exit_codes, task_retry.retry_error_classes
+    );
+    if config.task_worker_pool_size > 0 {
+        info!(
+            "  TASK_WORKER_POOL_SIZE: {} (health check every {}s, recycled after {} tasks)",
+            config.task_worker_pool_size, config.task_worker_health_check_secs, config.task_worker_max_tasks
+        );
+    } else {
+        info!("  TASK_WORKER_POOL_SIZE: 0 (a Node.js process is spawned per task)");
+    }
+    info!(
+        "  TASK_CRASH_LOOP: {} crashes in {}s, backoff up to {}s",
+        config.crash_loop_policy.threshold,
+        config.crash_loop_policy.window.as_secs(),
+        config.crash_loop_policy.max_backoff.as_secs()
+    );
+    for profile in retrieval_experiments.profiles() {
+        info!("  RETRIEVAL_PROFILES: {} at {}%", profile.name, profile.percent);
+    }
+    info!("  WALRUS_WAIT_FOR_CERTIFICATION: {}", config.walrus_store.wait_for_certification);
+    info!("  WALRUS_CERTIFICATION_TIMEOUT_SECS: {}", config.walrus_store.certification_timeout.as_secs());
+    info!("  WALRUS_MAX_EPOCHS: {}", config.walrus_budget.max_epochs);
+    info!(
+        "  WALRUS_MAX_STORE_BYTE_EPOCHS: {}",
+        config.walrus_budget.max_byte_epochs.map_or("unlimited".to_string(), |v| v.to_string())
+    );
+    info!("  SUI_RPC_URL: {}", config.sui_rpc_url);
+    for url in &config.sui_rpc_fallback_urls {
+        info!("  SUI_RPC_FALLBACK_URL: {}", url);
+    }
+    info!("  SUI_OBJECT_CACHE_SECS: {}", config.sui_object_cache_secs);
+    info!("  SUI_GAS_LANES: {} of {} MIST", config.sui_gas_lanes, config.sui_gas_lane_balance);
+    info!("  MAX_REQUEST_BODY_BYTES: {}", config.max_request_body_bytes);
+    info!("  CALLER_RATE_LIMIT_KEY: {}", caller_limits.key());
+    let idempotency_ttl_secs = config.idempotency_ttl_secs;
+    info!("  IDEMPOTENCY_TTL_SECS: {}", idempotency_ttl_secs);
+    let mut jobs = JobStore::new();
+    let mut idempotency = IdempotencyStore::new(std::time::Duration::from_secs(idempotency_ttl_secs));
+    let mut audit_log = AuditLog::default();
+    let mut task_audit_log = TaskAuditLog::new(config.task_audit_log_size);
+    match &config.storage_path {
+        Some(path) => {
+            let storage = Arc::new(
+                Storage::open(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to open STORAGE_PATH {}: {:?}", path.display(), e))?,
+            );
+            let restore_failed = |e| anyhow::anyhow!("Failed to restore state from STORAGE_PATH: {:?}", e);
+            jobs = jobs.with_storage(storage.clone()).map_err(restore_failed)?;
+            idempotency = idempotency.with_storage(storage.clone()).map_err(restore_failed)?;
+            audit_log = audit_log.with_storage(storage.clone()).map_err(restore_failed)?;
+            task_audit_log = task_audit_log.with_storage(storage).map_err(restore_failed)?;
+            info!(
+                "  STORAGE_PATH: {}, restored {} jobs and {} idempotency keys, task audit log at {} entries",
+                path.display(),
+                jobs.usage().0,
+                idempotency.len(),
+                task_audit_log.recorded()
+            );
+        }
+        None => info!("  STORAGE_PATH: unset, state is kept in memory only"),
+    }
+    info!(
+        "  KEY_ROTATION: every {}s, previous key kept {}s",
+        config.key_rotation_interval_secs, config.key_rotation_overlap_secs
+    );
+    info!("  SIGNATURE_SCHEME: {}", config.signature_scheme);
+    info!("  ATTESTATION_CACHE_SECS: {}", config.attestation_cache_secs);
+    info!("  HEALTH_CHECK_CACHE_SECS: {}", config.health_check_cache_secs);
+    info!(
+        "  AUTHORIZATION_ALLOWLIST_OBJECT_ID: {}",
+        config.authorization_allowlist_object_id.as_deref().unwrap_or("unset")
+    );
+    info!(
+        "  CIRCUIT_BREAKER: open after {} failures for {}s",
+        config.breaker_policy.failure_threshold, config.breaker_policy.open_secs
+    );
+    let exporting_spans = telemetry::init(config.otel_exporter_otlp_endpoint.as_deref())
+        .context("Failed to set up the OTLP exporter")?;
+    info!(
+        "  OTEL_EXPORTER_OTLP_ENDPOINT: {}{}",
+        config.otel_exporter_otlp_endpoint.as_deref().unwrap_or("unset"),
+        if exporting_spans { ", exporting spans" } else { "" }
+    );
+    info!("  ANCHOR_RECEIPTS: {}", config.anchor_receipts);
+    info!("  CRASH_REPORT_DIR: {}", config.crash_report_dir.display());
+    info!(
+        "  INTERNAL_ENCRYPTION_SECRET_KEY: {}",
+        if config.internal_encryption_key.is_some() { "****** (hidden)" } else { "not set" }
+    );
+    info!(
+        "  CRASH_REPORT_KEY: {}",
+        match crash_store.key_source() {
+            KeySource::Dedicated => "****** (hidden)",
+            KeySource::Internal => "not set, derived from INTERNAL_ENCRYPTION_SECRET_KEY",
+            _ => "not set, reports are readable until restart",
+        }
+    );
+    info!(
+        "  PAYLOAD_ENCRYPTION_KEY: {}",
+        match config.payload_key_source {
+            KeySource::Dedicated => "****** (hidden)",
+            KeySource::Internal => "not set, derived from INTERNAL_ENCRYPTION_SECRET_KEY",
+            _ => "not set, no message text is stored",
+        }
+    );
+    info!("  REQUEST_LOG_SIZE: {}", config.request_log_size);
+    info!("  TASK_AUDIT_LOG_SIZE: {}", config.task_audit_log_size);
+    info!("  ADMIN_TOKEN: {}", if admin_token.is_some() { "****** (hidden)" } else { "not set, admin endpoints disabled" });
     info!("  SUI_SECRET_KEY: ****** (hidden)");
     info!("  RUBY_NODES_API_KEY: ****** (hidden)");
-    info!("  QDRANT_API_KEY: {}", if qdrant_api_key.is_some() { "****** (hidden)" } else { "not set" });
-    info!("  TELEGRAM_SOCIAL_TRUTH_BOT_ID: {}", telegram_social_truth_bot_id);
+    info!("  QDRANT_API_KEY: {}", if config.qdrant_api_key.is_some() { "****** (hidden)" } else { "not set" });
+    info!("  TELEGRAM_SOCIAL_TRUTH_BOT_ID: {}", config.telegram_social_truth_bot_id);
     info!("  ID_MASK_SALT: ****** (hidden)");
+    info!("  BIND_ADDR/PORT: {}", config.listen.socket_addr());
+    match &config.listen.tls {
+        Some(TlsConfig::Files { cert_path, key_path }) => {
+            info!("  TLS_MODE: files ({}, {})", cert_path.display(), key_path.display())
+        }
+        Some(TlsConfig::SelfSigned) => info!("  TLS_MODE: self_signed (ephemeral key)"),
+        None => info!("  TLS_MODE: off"),
+    }
+
+    // Check the Node.js task dependencies of every bundle against the signed allowlist
+    let task_path = task_bundles.default_bundle().path.clone();
+    let allowlist_path = std::env::var("DEPENDENCY_ALLOWLIST_PATH")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| task_path.join(DEFAULT_ALLOWLIST_FILE));
+    let allowlist_pubkey = std::env::var("DEPENDENCY_ALLOWLIST_PUBKEY").ok();
+    let dependency_status = if dev_mode {
+        DependencyStatus::Disabled
+    } else {
+        task_bundles.check_dependencies(&allowlist_path, allowlist_pubkey.as_deref())
+    };
+    match &dependency_status {
+        DependencyStatus::Disabled if dev_mode => warn!("Development mode, dependency allowlist is not enforced"),
+        DependencyStatus::Disabled => warn!("DEPENDENCY_ALLOWLIST_PUBKEY not set, dependency allowlist is not enforced"),
+        DependencyStatus::Verified { packages } => info!("✅ {} task dependencies match the signed allowlist", packages),
+        DependencyStatus::Rejected { reason } => error!("❌ Dependency allowlist check failed, tasks will be refused: {}", reason),
+    }
 
+    // Identify the running build: compile-time metadata plus the task bundle and Node.js runtime
+    let build_info = BuildInfo::collect(&task_bundles).await;
+    info!(
+        "🚀 nautilus-server {} (commit {}, built {}, features [{}])",
+        build_info.version,
+        build_info.git_commit,
+        build_info.build_timestamp,
+        build_info.features.join(",")
+    );
+    info!(
+        "  task bundle: {}, node: {}",
+        build_info.task_bundle_hash.as_deref().unwrap_or("unavailable"),
+        build_info.node_version.as_deref().unwrap_or("unavailable")
+    );
+    for (name, hash) in &build_info.bundle_hashes {
+        info!("  task bundle {}: {}", name, hash.as_deref().unwrap_or("unavailable"));
+    }
+
+    // Start the warm worker pool once the task bundle has been checked; workers load the
+    // task dependencies, so a rejected bundle gets none
+    let worker_pool = if config.task_worker_pool_size > 0 && matches!(dependency_status, DependencyStatus::Rejected { .. }) {
+        warn!("Not starting the Node.js worker pool, the task dependencies were rejected");
+        None
+    } else if config.task_worker_pool_size > 0 {
+        let pool = WorkerPool::start(WorkerPoolConfig {
+            size: config.task_worker_pool_size,
+            task_path: task_path.clone(),
+            node_flags: task_node_flags.default.clone(),
+            scheduling: task_scheduling.clone(),
+            health_check_interval: std::time::Duration::from_secs(config.task_worker_health_check_secs),
+            max_tasks_per_worker: config.task_worker_max_tasks,
+            ..Default::default()
+        })
+        .await
+        .context("Failed to start Node.js worker pool")?;
+        info!("✅ {} warm Node.js workers started", pool.size());
+        Some(pool)
+    } else {
+        None
+    };
+
+    install_panic_hook(crash_store.clone(), log_buffer, build_info.git_commit.clone());
+
+    // Endpoints probed by /health_check; an invalid file is reported there instead of failing boot
+    let endpoints = EndpointRegistry::load(DEFAULT_ENDPOINTS_FILE.into())
+        .with_probe_cache(std::time::Duration::from_secs(config.health_check_cache_secs));
+    match endpoints.status() {
+        status if status.valid => info!("  ALLOWED_ENDPOINTS: {} from {}", status.endpoints, status.path),
+        status => error!("❌ {}", status.error.unwrap_or_default()),
+    }
+
+    // The TLS certificate and the leader ID keep the boot key, only response signing rotates
+    let tls_acceptor = config.listen.tls_acceptor(&eph_kp).context("Failed to set up TLS")?;
+    let collection_tuning = CollectionTuning::new(config.qdrant_search_params.clone());
+    let replication = Replication::new(config.replication.as_ref());
+    let leader = LeaderElection::new(config.leader_lease.clone(), Hex::encode(eph_kp.public().as_bytes()));
+    let embeddings = EmbeddingProvider::from_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to create embedding provider: {:?}", e))?;
+    info!("Embedding queries with {}", embeddings.describe());
+    let authorization = nautilus_server::authorization::from_config(&config);
+    info!("Authorizing operations with {}", authorization.name());
+    let runtime_config = RuntimeConfig::from_config(&config);
+    let keys = KeyManager::new(
+        eph_kp,
+        std::time::Duration::from_secs(config.key_rotation_overlap_secs),
+        config.signature_scheme,
+    );
     let state = Arc::new(AppState { 
-        eph_kp, 
-        move_package_id,
-        sui_secret_key,
-        ruby_nodes_api_key,
-        walrus_aggregator_url,
-        walrus_publisher_url,
-        walrus_epochs,
-        ollama_api_url,
-        ollama_model,
-        azure_text_embedding_api_endpoint,
-        azure_text_embedding_api_key,
-        qdrant_url,
-        qdrant_api_key,
-        qdrant_collection_name,
-        embedding_batch_size,
-        vector_batch_size,
-        telegram_social_truth_bot_id,
-        id_mask_salt,
+        keys, 
+        build_info,
+        attestation: if dev_mode { AttestationProvider::Mock } else { AttestationProvider::Nsm },
+        runtime_config: ArcSwap::from_pointee(runtime_config),
+        config_updates: Default::default(),
+        walrus_store: config.walrus_store.clone(),
+        walrus_budget: config.walrus_budget.clone(),
+        jobs,
+        idempotency,
+        feedback: FeedbackStore::new(),
+        experiments: retrieval_experiments,
+        scheduler: Arc::new(
+            TaskScheduler::new(config.max_concurrent_tasks, std::time::Duration::from_secs(config.priority_aging_secs))
+                .with_queue_limits(
+                    config.max_queued_tasks,
+                    std::time::Duration::from_secs(config.task_queue_timeout_secs),
+                ),
+        ),
+        anchor_receipts: config.anchor_receipts,
+        task_scheduling,
+        task_node_flags,
+        task_retry,
+        task_bundles,
+        worker_pool,
+        dependency_status,
+        metrics: Metrics::new(),
+        key_usage,
+        address_limits,
+        caller_limits,
+        breakers: CircuitBreakers::new(config.breaker_policy.clone()),
+        sui_cache: SuiCache::new(std::time::Duration::from_secs(config.sui_object_cache_secs)),
+        tx_sequencer: TransactionSequencer::new(config.sui_gas_lanes, config.sui_gas_lane_balance),
+        runtime_health: RuntimeHealth::new(config.crash_loop_policy.clone()),
+        crash_reports: crash_store,
+        admin_token,
+        request_log: RequestLog::new(config.request_log_size),
+        audit_log,
+        task_audit: task_audit_log,
+        collection_tuning,
+        endpoints,
+        replication,
+        leader,
+        embeddings: ArcSwap::from_pointee(embeddings),
+        authorization,
+        config,
     });
 
     // Validate configuration before starting server
-    if let Err(e) = state.validate_config() {
-        return Err(anyhow::anyhow!("Configuration validation failed: {}", e));
+    match state.validate_config() {
+        Ok(()) => info!("✅ Configuration validation passed"),
+        Err(e) if dev_mode => warn!("Configuration validation failed, continuing in development mode: {}", e),
+        Err(e) => return Err(anyhow::anyhow!("Configuration validation failed: {}", e)),
     }
-    info!("✅ Configuration validation passed");
+
+    match state.boot_attestation() {
+        Ok(boot) => info!("Boot attestation {} (PCR0 {})", boot.reference.document_hash, boot.reference.pcr0_hash),
+        Err(e) => warn!("Boot attestation failed, retrying on the first signed response: {:?}", e),
+    }
+
+    spawn_leader_election(state.clone());
+    if state.tx_sequencer.lane_count() > 1 {
+        spawn_gas_lane_setup(state.clone());
+    }
+    if state.config.key_rotation_interval_secs > 0 {
+        spawn_key_rotation(
+            state.clone(),
+            std::time::Duration::from_secs(state.config.key_rotation_interval_secs),
+        );
+    }
+    if state.config.job_cleanup_interval_secs > 0 {
+        spawn_job_cleanup(
+            state.clone(),
+            std::time::Duration::from_secs(state.config.job_cleanup_interval_secs),
+        );
+    }
+    if state.config.vector_reaper_interval_secs > 0 {
+        spawn_vector_reaper(
+            state.clone(),
+            std::time::Duration::from_secs(state.config.vector_reaper_interval_secs),
+        );
+    }
+
+    match state.config.replication.clone() {
+        Some(config) if config.role == ReplicationRole::Primary => {
+            info!("Replicating to standby {} every {:?}", url_str(&config.peer_url), config.interval);
+            spawn_primary(state.clone(), config);
+        }
+        Some(config) => info!("Standby of primary {}", url_str(&config.peer_url)),
+        None => {}
+    }
+
+    if dev_mode {
+        watch_task_directory(task_path, state.worker_pool.clone(), DEFAULT_TASK_WATCH_INTERVAL);
+    }
+
+    let listen = state.config.listen.clone();
 
     // Define your own restricted CORS policy here if needed.
     let cors = CorsLayer::new().allow_methods(Any).allow_headers(AllowHeaders::any()).allow_origin(Any);
 
-    let app = Router::new()
-        .route("/", get(ping))
-        .route("/get_attestation", get(get_attestation))
-        .route("/process_data", post(process_data))
-        .route("/embedding_ingest", post(embedding_ingest))
-        .route("/retrieve_messages_by_blob_ids", post(retrieve_messages_by_blob_ids))
-        .route("/health_check", get(health_check))
-        .route("/config", get(get_config))
-        .with_state(state)
+    let routes = RouteTable::new()
+        .get("/", ping)
+        .get("/get_attestation", get_attestation)
+        .post("/get_attestation", post_attestation)
+        .get("/boot_attestation", get_boot_attestation)
+        .get("/keys", get_signing_keys)
+        .post("/process_data", process_data)
+        .post("/process_data/stream", process_data_stream)
+        .post("/embedding_ingest", embedding_ingest)
+        .post("/embedding_ingest/stream", embedding_ingest_stream)
+        .post("/embedding_ingest_batch", embedding_ingest_batch)
+        .post("/retrieve_messages_by_blob_ids", retrieve_messages_by_blob_ids)
+        .post("/retrieve_messages_filtered", retrieve_messages_filtered)
+        .get("/health_check", health_check)
+        .get("/version", version)
+        .get("/config", get_config)
+        .get("/openapi.json", openapi_json)
+        .get("/docs", swagger_ui)
+        .get("/schemas", schema_index)
+        .get("/schemas/:operation", operation_schemas)
+        .get("/canonical/test_vectors", canonical_test_vectors)
+        .post("/canonical/verify", verify_canonical)
+        .get("/jobs/:id", get_job)
+        .get("/jobs/:id/result", get_job_result)
+        .get("/jobs/:id/wait", wait_for_job)
+        .get("/metrics", metrics)
+        .get("/readyz", readyz)
+        .post("/feedback", submit_feedback)
+        .get("/feedback/metrics", feedback_metrics)
+        .get("/experiments", experiments)
+        .post("/collections/create", create_collection)
+        .get("/collections/info", collection_info)
+        .post("/collections/delete", delete_collection)
+        .post("/delete_messages", delete_messages)
+        .post("/restore_messages", restore_messages)
+        .post("/delete_vectors", delete_vectors)
+        .post("/decrypt_messages", decrypt_messages)
+        .get("/admin/crash_reports", crash_reports)
+        .get("/admin/requests", recent_requests)
+        .get("/admin/audit", audit_events)
+        .get("/admin/retention", retention_status)
+        .post("/admin/retention", run_retention_cleanup)
+        .get("/audit/tasks", task_audit)
+        .post("/admin/payload_keys/rotate", rotate_payload_keys)
+        .post("/admin/keys/rotate", rotate_key)
+        .get("/admin/breakers", list_breakers)
+        .post("/admin/breakers/:service/reset", reset_breaker)
+        .post("/admin/register_attestation", register_attestation)
+        .post("/admin/collections/:name/tune", tune_collection)
+        .post("/admin/config", update_config)
+        .post("/admin/endpoints/reload", reload_endpoints)
+        .post("/admin/endpoints/validate", validate_endpoints)
+        .post("/replication/sync", replication_sync)
+        .get("/admin/replication", replication_status);
+    let routes = if dev_mode { routes.with_route_listing() } else { routes };
+    let max_request_body_bytes = state.config.max_request_body_bytes;
+    let app = routes
+        .into_router()
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), limit_caller_rate))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), track_http_metrics))
+        .with_state(state.clone())
+        .layer(DefaultBodyLimit::max(max_request_body_bytes))
+        .layer(CatchPanicLayer::custom(panic_response))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), record_request))
+        .layer(axum::middleware::from_fn_with_state(state, limit_request_body))
+        .layer(axum::middleware::from_fn(scope_request_id))
         .layer(cors);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app.into_make_service())
+    let listener = tokio::net::TcpListener::bind(listen.socket_addr())
         .await
-        .map_err(|e| anyhow::anyhow!("Server error: {}", e))
+        .with_context(|| format!("Failed to bind {}", listen.socket_addr()))?;
+    info!(
+        "listening on {} ({})",
+        listener.local_addr().unwrap(),
+        if tls_acceptor.is_some() { "https" } else { "http" }
+    );
+    let served = serve(listener, app, tls_acceptor).await;
+    telemetry::shutdown();
+    served
 }
 
 async fn ping() -> &'static str {
@@ -0,0 +1,354 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-process metrics exposed in the Prometheus text format on `/metrics`.
+
+use crate::task_runner::TaskOutput;
+use crate::timeline::parse_task_samples;
+use crate::AppState;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Upper bounds of the task memory histogram buckets, 32 MiB to 4 GiB.
+pub const MEMORY_BUCKETS_BYTES: [f64; 8] = [
+    33_554_432.0,
+    67_108_864.0,
+    134_217_728.0,
+    268_435_456.0,
+    536_870_912.0,
+    1_073_741_824.0,
+    2_147_483_648.0,
+    4_294_967_296.0,
+];
+
+/// Upper bounds of the duration histogram buckets, 5 ms to 5 minutes.
+pub const DURATION_BUCKETS_SECS: [f64; 15] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Upper bounds of the batch size histogram buckets.
+pub const BATCH_SIZE_BUCKETS: [f64; 9] = [1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+/// External service called in each task phase, for the phases that make network calls.
+const PHASE_SERVICES: [(&str, &str); 3] = [("blob_fetch", "walrus"), ("embed", "embedding"), ("upsert", "qdrant")];
+
+/// Cumulative histogram with fixed bucket bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// Observations less than or equal to each bound, cumulative.
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Append the histogram series for one label set to `out`.
+    pub(crate) fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Series of one metric keyed by their rendered label set, e.g. `operation="process_data"`.
+type Family<T> = Arc<Mutex<BTreeMap<String, T>>>;
+
+fn increment(family: &Family<u64>, labels: String) {
+    *family.lock().unwrap().entry(labels).or_default() += 1;
+}
+
+fn observe(family: &Family<Histogram>, labels: String, bounds: &[f64], value: f64) {
+    family
+        .lock()
+        .unwrap()
+        .entry(labels)
+        .or_insert_with(|| Histogram::new(bounds))
+        .observe(value);
+}
+
+fn render_counter(out: &mut String, name: &str, help: &str, family: &Family<u64>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (labels, value) in family.lock().unwrap().iter() {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn render_histogram(out: &mut String, name: &str, help: &str, family: &Family<Histogram>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (labels, histogram) in family.lock().unwrap().iter() {
+        histogram.render(out, name, labels);
+    }
+}
+
+/// Metrics shared across handlers.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Peak task process RSS by operation.
+    task_peak_rss_bytes: Family<Histogram>,
+    /// HTTP requests by method, route and status.
+    http_requests: Family<u64>,
+    /// HTTP request latency by method and route.
+    http_request_duration: Family<Histogram>,
+    /// Finished tasks by operation and exit code.
+    task_runs: Family<u64>,
+    /// Task wall time by operation.
+    task_duration: Family<Histogram>,
+    /// Messages per embedding batch, by operation.
+    embedding_batch_size: Family<Histogram>,
+    /// Walrus, embedding and Qdrant call latency by service and call.
+    external_call_duration: Family<Histogram>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a finished task: exit code, duration, peak memory if sampled, and the call
+    /// durations and batch sizes the task reported.
+    pub fn observe_task_output(&self, operation: &str, output: &TaskOutput) {
+        let labels = format!("operation=\"{}\"", operation);
+        // Runs retried before the reported one count as well
+        for exit_code in output.retried_attempts.iter().map(|attempt| attempt.exit_code).chain([output.exit_code]) {
+            increment(&self.task_runs, format!("{},exit_code=\"{}\"", labels, exit_code));
+        }
+        observe(
+            &self.task_duration,
+            labels.clone(),
+            &DURATION_BUCKETS_SECS,
+            output.execution_time_ms as f64 / 1000.0,
+        );
+        if let Some(peak) = output.resource_usage.as_ref().and_then(|u| u.peak_rss_bytes) {
+            self.observe_task_peak_rss(operation, peak);
+        }
+        let Some(samples) = parse_task_samples(&output.stdout_text()) else { return };
+        for size in samples.batch_sizes.get("embed").into_iter().flatten() {
+            observe(&self.embedding_batch_size, labels.clone(), &BATCH_SIZE_BUCKETS, *size as f64);
+        }
+        for (phase, service) in PHASE_SERVICES {
+            for ms in samples.calls_ms.get(phase).into_iter().flatten() {
+                self.observe_external_call(service, phase, Duration::from_millis(*ms));
+            }
+        }
+    }
+
+    /// Record one call to an external service made by the server or a task.
+    pub fn observe_external_call(&self, service: &str, call: &str, duration: Duration) {
+        observe(
+            &self.external_call_duration,
+            format!("service=\"{}\",call=\"{}\"", service, call),
+            &DURATION_BUCKETS_SECS,
+            duration.as_secs_f64(),
+        );
+    }
+
+    /// Record a handled HTTP request. `route` is the matched route pattern, so IDs in
+    /// paths do not create new series.
+    pub fn observe_http_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        let labels = format!("method=\"{}\",route=\"{}\"", method, route);
+        increment(&self.http_requests, format!("{},status=\"{}\"", labels, status));
+        observe(&self.http_request_duration, labels, &DURATION_BUCKETS_SECS, duration.as_secs_f64());
+    }
+
+    pub fn observe_task_peak_rss(&self, operation: &str, bytes: u64) {
+        self.task_peak_rss_bytes
+            .lock()
+            .unwrap()
+            .entry(operation.to_string())
+            .or_insert_with(|| Histogram::new(&MEMORY_BUCKETS_BYTES))
+            .observe(bytes as f64);
+    }
+
+    /// Snapshot of the memory histogram for an operation.
+    pub fn task_peak_rss(&self, operation: &str) -> Option<Histogram> {
+        self.task_peak_rss_bytes.lock().unwrap().get(operation).cloned()
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let name = "nautilus_task_peak_rss_bytes";
+        let _ = writeln!(out, "# HELP {} Peak resident set size of Node.js task processes.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (operation, histogram) in self.task_peak_rss_bytes.lock().unwrap().iter() {
+            histogram.render(&mut out, name, &format!("operation=\"{}\"", operation));
+        }
+        render_counter(&mut out, "nautilus_http_requests_total", "HTTP requests handled.", &self.http_requests);
+        render_histogram(
+            &mut out,
+            "nautilus_http_request_duration_seconds",
+            "HTTP request latency.",
+            &self.http_request_duration,
+        );
+        render_counter(&mut out, "nautilus_task_runs_total", "Node.js tasks finished, by exit code.", &self.task_runs);
+        render_histogram(
+            &mut out,
+            "nautilus_task_duration_seconds",
+            "Wall time of Node.js tasks.",
+            &self.task_duration,
+        );
+        render_histogram(
+            &mut out,
+            "nautilus_embedding_batch_size",
+            "Messages per embedding batch.",
+            &self.embedding_batch_size,
+        );
+        render_histogram(
+            &mut out,
+            "nautilus_external_call_duration_seconds",
+            "Latency of Walrus, embedding and Qdrant calls.",
+            &self.external_call_duration,
+        );
+        out
+    }
+}
+
+/// Route layer counting requests and their latency per matched route.
+pub async fn track_http_metrics(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let started = std::time::Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path().to_string(), |path| path.as_str().to_string());
+    let response = next.run(request).await;
+    let status = response.status().as_u16();
+    state.metrics.observe_http_request(&method, &route, status, started.elapsed());
+    crate::telemetry::record_http_request(&method, &route, status, started.elapsed());
+    response
+}
+
+/// Prometheus scrape endpoint.
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render()
+            + &state.key_usage.render()
+            + &state.address_limits.render()
+            + &state.caller_limits.render()
+            + &state.breakers.render()
+            + &state.jobs.render()
+            + &state.leader.render()
+            + &state.feedback.render()
+            + &state.experiments.render(&state.feedback.precision_by_profile()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::new(&[10.0, 100.0]);
+        histogram.observe(5.0);
+        histogram.observe(50.0);
+        histogram.observe(500.0);
+        assert_eq!(histogram.counts, vec![1, 2]);
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum(), 555.0);
+    }
+
+    #[test]
+    fn test_render_task_memory() {
+        let metrics = Metrics::new();
+        metrics.observe_task_peak_rss("process_data", 100 * 1024 * 1024);
+        let text = metrics.render();
+        assert!(text.contains("# TYPE nautilus_task_peak_rss_bytes histogram"));
+        assert!(text.contains(
+            "nautilus_task_peak_rss_bytes_bucket{operation=\"process_data\",le=\"67108864\"} 0"
+        ));
+        assert!(text.contains(
+            "nautilus_task_peak_rss_bytes_bucket{operation=\"process_data\",le=\"134217728\"} 1"
+        ));
+        assert!(text.contains("nautilus_task_peak_rss_bytes_count{operation=\"process_data\"} 1"));
+        assert!(metrics.task_peak_rss("embedding_ingest").is_none());
+    }
+
+    #[test]
+    fn test_render_task_runs_and_calls() {
+        let metrics = Metrics::new();
+        let output = TaskOutput {
+            stdout: b"===TASK_TIMELINE_START===\n{\"calls_ms\":{\"blob_fetch\":[40],\"upsert\":[2000],\"decrypt\":[5]},\"batch_sizes\":{\"embed\":[10]}}\n===TASK_TIMELINE_END===\n".to_vec(),
+            stderr: Vec::new(),
+            exit_code: 1,
+            execution_time_ms: 1500,
+            resource_usage: None,
+            stdout_stats: Default::default(),
+            stderr_stats: Default::default(),
+            retried_attempts: vec![crate::task_runner::TaskAttempt {
+                exit_code: 75,
+                error_class: None,
+                execution_time_ms: 10,
+            }],
+        };
+        metrics.observe_task_output("embedding_ingest", &output);
+        metrics.observe_external_call("walrus", "store", Duration::from_millis(300));
+        let text = metrics.render();
+        assert!(text.contains("nautilus_task_runs_total{operation=\"embedding_ingest\",exit_code=\"1\"} 1"));
+        assert!(text.contains("nautilus_task_runs_total{operation=\"embedding_ingest\",exit_code=\"75\"} 1"));
+        assert!(text.contains("nautilus_task_duration_seconds_sum{operation=\"embedding_ingest\"} 1.5"));
+        assert!(text.contains("nautilus_embedding_batch_size_count{operation=\"embedding_ingest\"} 1"));
+        assert!(text.contains("nautilus_external_call_duration_seconds_count{service=\"walrus\",call=\"blob_fetch\"} 1"));
+        assert!(text.contains("nautilus_external_call_duration_seconds_sum{service=\"qdrant\",call=\"upsert\"} 2"));
+        assert!(text.contains("nautilus_external_call_duration_seconds_count{service=\"walrus\",call=\"store\"} 1"));
+        assert!(!text.contains("call=\"decrypt\""));
+    }
+
+    #[tokio::test]
+    async fn test_http_metrics_use_route_patterns() {
+        let state = Arc::new(crate::test_app_state());
+        let app = axum::Router::new()
+            .route("/jobs/:id", axum::routing::get(|| async { "job" }))
+            .route_layer(axum::middleware::from_fn_with_state(state.clone(), track_http_metrics))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        for id in ["a", "b"] {
+            reqwest::get(format!("http://{}/jobs/{}", addr, id)).await.unwrap();
+        }
+        let text = state.metrics.render();
+        assert!(text.contains("nautilus_http_requests_total{method=\"GET\",route=\"/jobs/:id\",status=\"200\"} 2"));
+    }
+}
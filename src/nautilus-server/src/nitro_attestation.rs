@@ -0,0 +1,273 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of AWS Nitro attestation documents received from other enclaves. A document
+//! is a COSE_Sign1 structure signed with ES384 by the leaf certificate it carries, and the
+//! `cabundle` chain of that certificate has to lead to the AWS Nitro Enclaves root, pinned
+//! here by its SHA-256 fingerprint. The PCRs and public key of a document are only taken
+//! from a document that passes these checks: anyone can write a CBOR payload claiming a
+//! public PCR0.
+
+use crate::EnclaveError;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256};
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::spki::ObjectIdentifier;
+use x509_cert::Certificate;
+
+/// SHA-256 fingerprint of the DER encoded AWS Nitro Enclaves root certificate (G1), as
+/// published on https://docs.aws.amazon.com/enclaves/latest/user/verify-root.html.
+pub const NITRO_ROOT_CERT_SHA256: &str = "641a0321a3e244efe456463195d606317ed7cdcc3c1756e09893f3c68f79bb5b";
+
+/// Oldest document accepted, by its timestamp.
+pub const MAX_DOCUMENT_AGE: Duration = Duration::from_secs(5 * 60);
+/// How far a document timestamp may be ahead of this enclave's clock.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// COSE algorithm identifier of ES384, the only one the Nitro Secure Module signs with.
+const COSE_ES384: i128 = -35;
+/// COSE header label of the algorithm.
+const COSE_ALGORITHM_LABEL: i128 = 1;
+/// ecdsa-with-SHA384, the signature algorithm of the Nitro certificate chain.
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+
+/// Payload of a verified attestation document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttestationDocument {
+    /// Milliseconds since the epoch when the document was signed
+    pub timestamp: u64,
+    pub pcrs: BTreeMap<u32, ByteBuf>,
+    /// DER leaf certificate that signed the document
+    pub certificate: ByteBuf,
+    /// DER certificates from the root to the issuer of `certificate`
+    pub cabundle: Vec<ByteBuf>,
+    pub public_key: Option<ByteBuf>,
+    pub user_data: Option<ByteBuf>,
+    pub nonce: Option<ByteBuf>,
+}
+
+fn invalid(reason: impl fmt::Display) -> EnclaveError {
+    EnclaveError::AttestationError(format!("Invalid peer attestation: {}", reason))
+}
+
+/// Verify a COSE_Sign1 attestation document at `now_ms`: its certificate chain leads to the
+/// root certificate with SHA-256 fingerprint `root_sha256` (hex) and every certificate was
+/// valid when the document was signed, the leaf certificate signed the document, and the
+/// document is recent. Returns the payload.
+pub fn verify_document(bytes: &[u8], root_sha256: &str, now_ms: u64) -> Result<AttestationDocument, EnclaveError> {
+    let cose = match serde_cbor::from_slice::<Value>(bytes).map_err(invalid)? {
+        Value::Tag(_, inner) => *inner,
+        other => other,
+    };
+    let Value::Array(items) = cose else {
+        return Err(invalid("not a COSE_Sign1 structure"));
+    };
+    let [Value::Bytes(protected), _, Value::Bytes(payload), Value::Bytes(signature)] = items.as_slice() else {
+        return Err(invalid("not a COSE_Sign1 structure"));
+    };
+    let algorithm = match serde_cbor::from_slice::<Value>(protected).map_err(invalid)? {
+        Value::Map(header) => header.get(&Value::Integer(COSE_ALGORITHM_LABEL)).cloned(),
+        _ => None,
+    };
+    if algorithm != Some(Value::Integer(COSE_ES384)) {
+        return Err(invalid("document is not signed with ES384"));
+    }
+    let document: AttestationDocument = serde_cbor::from_slice(payload).map_err(invalid)?;
+
+    let max_age = MAX_DOCUMENT_AGE.as_millis() as u64;
+    if now_ms.saturating_sub(document.timestamp) > max_age
+        || document.timestamp > now_ms + MAX_CLOCK_SKEW.as_millis() as u64
+    {
+        return Err(invalid(format!("document timestamp {} is not current", document.timestamp)));
+    }
+
+    let (root, intermediates) = document
+        .cabundle
+        .split_first()
+        .ok_or_else(|| invalid("empty CA bundle"))?;
+    if Hex::encode(Sha256::digest(root.as_slice()).digest) != root_sha256.to_ascii_lowercase() {
+        return Err(invalid("certificate chain does not lead to the AWS Nitro root"));
+    }
+    let signed_at = Duration::from_millis(document.timestamp);
+    let mut issuer = parse_certificate(root, signed_at)?;
+    for der in intermediates.iter().chain([&document.certificate]) {
+        let certificate = parse_certificate(der, signed_at)?;
+        check_issued_by(&certificate, &issuer)?;
+        issuer = certificate;
+    }
+
+    let signature = Signature::from_slice(signature).map_err(|_| invalid("malformed COSE signature"))?;
+    let signed = serde_cbor::to_vec(&Value::Array(vec![
+        Value::Text("Signature1".to_string()),
+        Value::Bytes(protected.clone()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.clone()),
+    ]))
+    .map_err(invalid)?;
+    public_key(&issuer)?
+        .verify(&signed, &signature)
+        .map_err(|_| invalid("COSE signature does not verify with the leaf certificate"))?;
+    Ok(document)
+}
+
+/// A DER certificate, checked to be valid at `at` since the epoch.
+fn parse_certificate(der: &[u8], at: Duration) -> Result<Certificate, EnclaveError> {
+    let certificate = Certificate::from_der(der).map_err(invalid)?;
+    let validity = &certificate.tbs_certificate.validity;
+    if at < validity.not_before.to_unix_duration() || at > validity.not_after.to_unix_duration() {
+        return Err(invalid(format!(
+            "certificate {} was not valid when the document was signed",
+            certificate.tbs_certificate.subject
+        )));
+    }
+    Ok(certificate)
+}
+
+/// Check that `issuer` signed `certificate`.
+fn check_issued_by(certificate: &Certificate, issuer: &Certificate) -> Result<(), EnclaveError> {
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(invalid("certificate chain is out of order"));
+    }
+    if certificate.signature_algorithm.oid != ECDSA_WITH_SHA384 {
+        return Err(invalid("certificate is not signed with ECDSA over P-384"));
+    }
+    let tbs = certificate.tbs_certificate.to_der().map_err(invalid)?;
+    let signature = certificate
+        .signature
+        .as_bytes()
+        .and_then(|bytes| Signature::from_der(bytes).ok())
+        .ok_or_else(|| invalid("malformed certificate signature"))?;
+    public_key(issuer)?
+        .verify(&tbs, &signature)
+        .map_err(|_| invalid("certificate signature does not verify"))
+}
+
+fn public_key(certificate: &Certificate) -> Result<VerifyingKey, EnclaveError> {
+    certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .as_bytes()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(bytes).ok())
+        .ok_or_else(|| invalid("certificate key is not a P-384 key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p384::ecdsa::signature::Signer;
+    use p384::ecdsa::SigningKey;
+    use p384::pkcs8::DecodePrivateKey;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, PKCS_ECDSA_P384_SHA384};
+
+    const TIMESTAMP: u64 = 1744038900000;
+
+    /// Root and leaf certificates in DER, and the leaf's signing key.
+    fn chain() -> (Vec<u8>, Vec<u8>, SigningKey) {
+        let root_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let mut root_params = CertificateParams::new(Vec::new()).unwrap();
+        root_params.distinguished_name.push(DnType::CommonName, "aws.nitro-enclaves");
+        root_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let root = root_params.self_signed(&root_key).unwrap();
+
+        let leaf_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let mut leaf_params = CertificateParams::new(Vec::new()).unwrap();
+        leaf_params.distinguished_name.push(DnType::CommonName, "i-123-enc456");
+        let leaf = leaf_params.signed_by(&leaf_key, &root, &root_key).unwrap();
+        let signing_key = SigningKey::from_pkcs8_der(&leaf_key.serialize_der()).unwrap();
+        (root.der().to_vec(), leaf.der().to_vec(), signing_key)
+    }
+
+    fn document(root: &[u8], leaf: &[u8], key: &SigningKey) -> Vec<u8> {
+        let mut payload = BTreeMap::new();
+        payload.insert(Value::Text("module_id".into()), Value::Text("i-123-enc456".into()));
+        payload.insert(Value::Text("digest".into()), Value::Text("SHA384".into()));
+        payload.insert(Value::Text("timestamp".into()), Value::Integer(TIMESTAMP.into()));
+        payload.insert(
+            Value::Text("pcrs".into()),
+            Value::Map(BTreeMap::from([(Value::Integer(0), Value::Bytes(vec![7; 48]))])),
+        );
+        payload.insert(Value::Text("certificate".into()), Value::Bytes(leaf.to_vec()));
+        payload.insert(Value::Text("cabundle".into()), Value::Array(vec![Value::Bytes(root.to_vec())]));
+        payload.insert(Value::Text("public_key".into()), Value::Bytes(vec![9; 32]));
+        payload.insert(Value::Text("user_data".into()), Value::Null);
+        payload.insert(Value::Text("nonce".into()), Value::Bytes(vec![1; 32]));
+        let payload = serde_cbor::to_vec(&Value::Map(payload)).unwrap();
+        let protected = serde_cbor::to_vec(&Value::Map(BTreeMap::from([(
+            Value::Integer(COSE_ALGORITHM_LABEL),
+            Value::Integer(COSE_ES384),
+        )])))
+        .unwrap();
+        let signed = serde_cbor::to_vec(&Value::Array(vec![
+            Value::Text("Signature1".into()),
+            Value::Bytes(protected.clone()),
+            Value::Bytes(Vec::new()),
+            Value::Bytes(payload.clone()),
+        ]))
+        .unwrap();
+        let signature: Signature = key.sign(&signed);
+        serde_cbor::to_vec(&Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(BTreeMap::new()),
+            Value::Bytes(payload),
+            Value::Bytes(signature.to_bytes().to_vec()),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_document() {
+        let (root, leaf, key) = chain();
+        let root_sha256 = Hex::encode(Sha256::digest(&root).digest);
+        let bytes = document(&root, &leaf, &key);
+
+        let verified = verify_document(&bytes, &root_sha256, TIMESTAMP + 1000).unwrap();
+        assert_eq!(verified.pcrs[&0].as_slice(), &[7; 48]);
+        assert_eq!(verified.public_key.unwrap().as_slice(), &[9; 32]);
+
+        // Another root, a stale or future document
+        assert!(verify_document(&bytes, NITRO_ROOT_CERT_SHA256, TIMESTAMP).is_err());
+        assert!(verify_document(&bytes, &root_sha256, TIMESTAMP + MAX_DOCUMENT_AGE.as_millis() as u64 + 1).is_err());
+        assert!(verify_document(&bytes, &root_sha256, TIMESTAMP - 2 * MAX_CLOCK_SKEW.as_millis() as u64).is_err());
+    }
+
+    #[test]
+    fn test_rejects_tampered_document() {
+        let (root, leaf, key) = chain();
+        let root_sha256 = Hex::encode(Sha256::digest(&root).digest);
+        let Value::Array(mut items) = serde_cbor::from_slice::<Value>(&document(&root, &leaf, &key)).unwrap() else {
+            panic!("not a COSE_Sign1 structure");
+        };
+
+        // A flipped bit in the signature
+        let mut tampered = items.clone();
+        if let Value::Bytes(signature) = &mut tampered[3] {
+            signature[10] ^= 1;
+        }
+        let tampered = serde_cbor::to_vec(&Value::Array(tampered)).unwrap();
+        assert!(verify_document(&tampered, &root_sha256, TIMESTAMP).is_err());
+
+        // A payload attesting another key under the original signature
+        let Value::Bytes(payload) = &items[2] else { panic!("payload is not a byte string") };
+        let Value::Map(mut payload) = serde_cbor::from_slice::<Value>(payload).unwrap() else {
+            panic!("payload is not a map");
+        };
+        payload.insert(Value::Text("public_key".into()), Value::Bytes(vec![6; 32]));
+        items[2] = Value::Bytes(serde_cbor::to_vec(&Value::Map(payload)).unwrap());
+        let forged = serde_cbor::to_vec(&Value::Array(items)).unwrap();
+        assert!(verify_document(&forged, &root_sha256, TIMESTAMP).is_err());
+
+        // A chain signed by a key other than the pinned root
+        let (other_root, _, _) = chain();
+        let other = document(&other_root, &leaf, &key);
+        assert!(verify_document(&other, &root_sha256, TIMESTAMP).is_err());
+    }
+}
@@ -23,6 +23,10 @@ const IdUnmasker = require("./utils/id-unmasker");
 const logger = require("./utils/logger");
 const SummaryReporter = require("./utils/summary-reporter");
 const RateLimiter = require("./utils/rate-limiter");
+const PhaseTimer = require("./utils/phase-timer");
+const taskProtocol = require("./utils/task-protocol");
+const { classifyError, classifyErrors } = require("./utils/error-class");
+const { encryptForRecipient, encryptWithKey } = require("./utils/payload-encryption");
 
 // Enable quiet mode - only write summaries to console, detailed logs go to file
 logger.setQuietMode(true);
@@ -38,6 +42,9 @@ const SELECT_PER_GROUP = 30; // Select this many patches from each group
 
 // Create summary reporter
 const summaryReporter = new SummaryReporter();
+const phaseTimer = new PhaseTimer();
+// Report phase timings on every exit path, including process.exit() calls
+process.on("exit", () => phaseTimer.report(logger));
 summaryReporter.start();
 
 // Required environment variables that should be passed from Rust app
@@ -100,6 +107,15 @@ logger.log("✅ All required environment variables are available from Rust app")
 // Parse CLI arguments for different operations
 const args = process.argv.slice(2);
 
+// Inputs the server sends in the protocol request rather than argv
+let input;
+try {
+  input = taskProtocol.readRequest() || {};
+} catch (error) {
+  logger.error("❌ Failed to read task request:", error.message);
+  process.exit(1);
+}
+
 // Check for operation type
 const operationIndex = args.indexOf('--operation');
 const operation = operationIndex !== -1 ? args[operationIndex + 1] : 'default';
@@ -109,7 +125,7 @@ logger.log(`🎯 Operation: ${operation}`);
 let parsedArgs = {};
 
 if (operation === 'embedding') {
-  // Embedding operation: --operation embedding --quilt-id <quiltId> --on-chain-file-obj-id <objId> --policy-object-id <policyId> --threshold <threshold> [--batch-size N] <enclaveId>
+  // Embedding operation: --operation embedding --quilt-id <quiltId> --on-chain-file-obj-id <objId> --policy-object-id <policyId> --threshold <threshold> [--batch-size N] [--blob-expiry-epoch N] [--encryption-public-key <hex>] <enclaveId>
   const quiltIdIndex = args.indexOf('--quilt-id');
   const onChainFileObjIdIndex = args.indexOf('--on-chain-file-obj-id');
   const policyObjectIdIndex = args.indexOf('--policy-object-id');
@@ -117,7 +133,7 @@ if (operation === 'embedding') {
   
   if (quiltIdIndex === -1 || onChainFileObjIdIndex === -1 || 
       policyObjectIdIndex === -1 || thresholdIndex === -1 || args.length < 11) {
-    logger.error("Usage for embedding: node index.js --operation embedding --quilt-id <quiltId> --on-chain-file-obj-id <objId> --policy-object-id <policyId> --threshold <threshold> [--batch-size N] <enclaveId>");
+    logger.error("Usage for embedding: node index.js --operation embedding --quilt-id <quiltId> --on-chain-file-obj-id <objId> --policy-object-id <policyId> --threshold <threshold> [--batch-size N] [--blob-expiry-epoch N] [--encryption-public-key <hex>] <enclaveId>");
     process.exit(1);
   }
 
@@ -129,6 +145,14 @@ if (operation === 'embedding') {
     processingConfig.batchSize = args[batchSizeIndex + 1];
   }
   
+  // Walrus end epoch of the quilt, stored with each vector so it can be reaped on expiry
+  const blobExpiryEpochIndex = args.indexOf('--blob-expiry-epoch');
+  const blobExpiryEpoch = blobExpiryEpochIndex !== -1 ? parseInt(args[blobExpiryEpochIndex + 1]) : null;
+
+  // Client X25519 key; message text is only stored when it can be encrypted to it
+  const encryptionPublicKeyIndex = args.indexOf('--encryption-public-key');
+  const encryptionPublicKey = encryptionPublicKeyIndex !== -1 ? args[encryptionPublicKeyIndex + 1] : null;
+
   // Embedding operation always stores vectors and includes embeddings
   processingConfig.storeVectors = 'true';
   processingConfig.includeEmbeddings = 'false'; // We don't need to include raw embeddings in response
@@ -139,6 +163,8 @@ if (operation === 'embedding') {
     onChainFileObjId: args[onChainFileObjIdIndex + 1],
     policyObjectId: args[policyObjectIdIndex + 1],
     threshold: args[thresholdIndex + 1],
+    blobExpiryEpoch,
+    encryptionPublicKey,
     enclaveId: args[args.length - 1], // Last argument is enclaveId
     processingConfig,
   };
@@ -154,24 +180,26 @@ logger.log("📋 Embedding Operation Arguments:");
     }
   
 } else if (operation === 'retrieve-by-blob-ids') {
-  // Retrieve by blob IDs operation: --operation retrieve-by-blob-ids --blob-file-pairs <jsonString> --threshold <threshold> <enclaveId>
+  // Retrieve by blob IDs operation: --operation retrieve-by-blob-ids [--blob-file-pairs <jsonString>] --threshold <threshold> [--explain] [--retrieval-profile <jsonString>] <enclaveId>
+  // The server sends blobFilePairs and retrievalProfile in the protocol request instead
   const blobFilePairsIndex = args.indexOf('--blob-file-pairs');
   const thresholdIndex = args.indexOf('--threshold');
   
-  if (blobFilePairsIndex === -1 || 
-      thresholdIndex === -1 || args.length < 7) {
-    logger.error("Usage for retrieve-by-blob-ids: node index.js --operation retrieve-by-blob-ids --blob-file-pairs <jsonString> --threshold <threshold> <enclaveId>");
+  if ((blobFilePairsIndex === -1 && !input.blobFilePairs) ||
+      thresholdIndex === -1 || args.length < 5) {
+    logger.error("Usage for retrieve-by-blob-ids: node index.js --operation retrieve-by-blob-ids --blob-file-pairs <jsonString> --threshold <threshold> [--explain] [--retrieval-profile <jsonString>] <enclaveId>");
     process.exit(1);
   }
 
-  const blobFilePairsStr = args[blobFilePairsIndex + 1];
-  let blobFilePairs;
+  let blobFilePairs = input.blobFilePairs;
   
-  try {
-    blobFilePairs = JSON.parse(blobFilePairsStr);
-  } catch (error) {
-    logger.error("❌ Failed to parse blob file pairs JSON:", error.message);
-    process.exit(1);
+  if (!blobFilePairs) {
+    try {
+      blobFilePairs = JSON.parse(args[blobFilePairsIndex + 1]);
+    } catch (error) {
+      logger.error("❌ Failed to parse blob file pairs JSON:", error.message);
+      process.exit(1);
+    }
   }
   
   if (!Array.isArray(blobFilePairs) || blobFilePairs.length === 0) {
@@ -193,10 +221,24 @@ logger.log("📋 Embedding Operation Arguments:");
     }
   }
   
+  // Retrieval parameters of the A/B profile this request was assigned to, if any
+  const retrievalProfileIndex = args.indexOf('--retrieval-profile');
+  let retrievalProfile = input.retrievalProfile || null;
+  if (!retrievalProfile && retrievalProfileIndex !== -1) {
+    try {
+      retrievalProfile = JSON.parse(args[retrievalProfileIndex + 1]);
+    } catch (error) {
+      logger.error("❌ Failed to parse retrieval profile JSON:", error.message);
+      process.exit(1);
+    }
+  }
+
   parsedArgs = {
     operation: 'retrieve-by-blob-ids',
     blobFilePairs: blobFilePairs,
     threshold: args[thresholdIndex + 1],
+    explain: args.includes('--explain'),
+    retrievalProfile: retrievalProfile,
     enclaveId: args[args.length - 1], // Last argument is enclaveId
     processingConfig: {},
   };
@@ -210,6 +252,57 @@ logger.log("📋 Embedding Operation Arguments:");
   logger.log(`  Threshold: ${parsedArgs.threshold}`);
   logger.log(`  Enclave ID: ${parsedArgs.enclaveId}`);
   
+} else if (operation === 'retrieve-filtered') {
+  // Filtered similarity retrieval: --operation retrieve-filtered [--query <text>] [--filter <jsonString>] --limit <N> [--explain] [--retrieval-profile <jsonString>] <enclaveId>
+  // The server sends query, filter and retrievalProfile in the protocol request instead
+  const queryIndex = args.indexOf('--query');
+  const filterIndex = args.indexOf('--filter');
+  const limitIndex = args.indexOf('--limit');
+  const query = 'query' in input ? input.query : queryIndex !== -1 ? args[queryIndex + 1] : undefined;
+
+  if (query === undefined || (filterIndex === -1 && !('filter' in input)) || limitIndex === -1 || args.length < 5) {
+    logger.error("Usage for retrieve-filtered: node index.js --operation retrieve-filtered --query <text> --filter <jsonString> --limit <N> [--explain] [--retrieval-profile <jsonString>] <enclaveId>");
+    process.exit(1);
+  }
+
+  // Qdrant payload filter built by the server from sender, chat and date filters, or null
+  let filter = input.filter;
+  if (!('filter' in input)) {
+    try {
+      filter = JSON.parse(args[filterIndex + 1]);
+    } catch (error) {
+      logger.error("❌ Failed to parse filter JSON:", error.message);
+      process.exit(1);
+    }
+  }
+
+  const retrievalProfileIndex = args.indexOf('--retrieval-profile');
+  let retrievalProfile = input.retrievalProfile || null;
+  if (!retrievalProfile && retrievalProfileIndex !== -1) {
+    try {
+      retrievalProfile = JSON.parse(args[retrievalProfileIndex + 1]);
+    } catch (error) {
+      logger.error("❌ Failed to parse retrieval profile JSON:", error.message);
+      process.exit(1);
+    }
+  }
+
+  parsedArgs = {
+    operation: 'retrieve-filtered',
+    query,
+    filter,
+    limit: parseInt(args[limitIndex + 1]),
+    explain: args.includes('--explain'),
+    retrievalProfile: retrievalProfile,
+    enclaveId: args[args.length - 1], // Last argument is enclaveId
+    processingConfig: {},
+  };
+
+  logger.log("📋 Filtered Retrieval Operation Arguments:");
+  logger.log(`  Filter: ${JSON.stringify(filter)}`);
+  logger.log(`  Limit: ${parsedArgs.limit}`);
+  logger.log(`  Enclave ID: ${parsedArgs.enclaveId}`);
+
   } else {
     // Default operation (refinement): <blobId> <onChainFileObjId> <policyObjectId> <threshold> <enclaveId>
     if (args.length < 5) {
@@ -251,10 +344,8 @@ async function initializeServices() {
     // Initialize all services using factory
     services = {
       refinement: ServiceFactory.createRefinementService('chat'),
-      // embedding: ServiceFactory.createEmbeddingService('ollama', {
-      //   batchSize: parseInt(process.env.EMBEDDING_BATCH_SIZE || '50')
-      // }),
-      embedding: ServiceFactory.createEmbeddingService('azure', {
+      // EMBEDDING_PROVIDER is 'azure' or 'ollama'
+      embedding: ServiceFactory.createEmbeddingService(process.env.EMBEDDING_PROVIDER || 'azure', {
         batchSize: parseInt(process.env.EMBEDDING_BATCH_SIZE || '50')
       }),
       vectorDb: ServiceFactory.createVectorDbService('qdrant', {
@@ -286,6 +377,8 @@ async function runTasks() {
       await runEmbeddingOperation();
     } else if (parsedArgs.operation === 'retrieve-by-blob-ids') {
       await runRetrieveByBlobIdsOperation();
+    } else if (parsedArgs.operation === 'retrieve-filtered') {
+      await runRetrieveFilteredOperation();
     } else {
       await runDefaultOperation();
     }
@@ -304,7 +397,7 @@ async function runEmbeddingOperation() {
   
   // Step 1: Fetch all patches from the quilt
   logger.log("📥 Step 1: Fetching all patches from quilt...");
-  const patches = await services.blockchain.walrus.fetchQuiltPatches(parsedArgs.quiltId);
+  const patches = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchQuiltPatches(parsedArgs.quiltId));
   
   if (!patches || patches.length === 0) {
     logger.error("❌ No patches found in quilt");
@@ -366,18 +459,18 @@ async function runEmbeddingOperation() {
     
     try {
       // Fetch encrypted patch blob from Walrus (rate limited!)
-      const encryptedPatch = await services.blockchain.walrus.fetchEncryptedFile(patchId);
+      const encryptedPatch = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchEncryptedFile(patchId));
       
       // Parse encrypted object
-      const encryptedObject = await services.blockchain.seal.parseEncryptedObject(encryptedPatch);
+      const encryptedObject = await phaseTimer.time("parse", () => services.blockchain.seal.parseEncryptedObject(encryptedPatch));
       
       // Decrypt patch
-      const decryptedPatch = await services.blockchain.seal.decryptFile(
+      const decryptedPatch = await phaseTimer.time("decrypt", () => services.blockchain.seal.decryptFile(
         encryptedObject.id,
         encryptedPatch,
         parsedArgs.policyObjectId,
         services.blockchain.sui
-      );
+      ));
       
       return {
         patchIndex: i,
@@ -598,11 +691,10 @@ async function runEmbeddingOperation() {
   finalResult.summary = summary;
   
   if (finalResult.status === "failed") {
+    finalResult.errorClass = classifyErrors(failedResults.map(r => r.result.error || r.result.failureReason));
     logger.error("❌ Embedding operation failed for all patches!");
     summaryReporter.printSummary(logger);
-    logger.log("===TASK_RESULT_START===");
-    logger.log(JSON.stringify(finalResult));
-    logger.log("===TASK_RESULT_END===");
+    taskProtocol.writeResult(finalResult);
     process.exit(1);
   } else {
     logger.log(`\n✅ Embedding operation completed!`);
@@ -611,9 +703,7 @@ async function runEmbeddingOperation() {
     // Print summary report to console (for database capture)
     summaryReporter.printSummary(logger);
     
-    logger.log("===TASK_RESULT_START===");
-    logger.log(JSON.stringify(finalResult));
-    logger.log("===TASK_RESULT_END===");
+    taskProtocol.writeResult(finalResult);
     process.exit(0);
   }
 }
@@ -750,7 +840,8 @@ async function processMessagesByMessage(rawData, services, args) {
         logger.log(`📦 Processing batch ${batchNum + 1}/${totalBatches} (${batch.length} messages)`);
 
         // Generate embeddings for this batch
-        const embeddingResults = await services.embedding.embedBatch(
+        phaseTimer.batch("embed", batch.length);
+        const embeddingResults = await phaseTimer.time("embed", () => services.embedding.embedBatch(
           batch.map(msg => {
             const datetime = msg.date ? new Date(msg.date * 1000).toISOString() : "";
             const fromUserId = msg.fromId?.userId || "";
@@ -759,7 +850,7 @@ async function processMessagesByMessage(rawData, services, args) {
             const ownerUserId = msg.user_id || "";
             return `Date: ${datetime}, From User Id: ${fromUserId}, Message: ${message}, Conversation Id: ${conversationId}, Owner User Id: ${ownerUserId}`;
           })
-        );
+        ));
 
         // Check for embedding failures
         for (let j = 0; j < embeddingResults.length; j++) {
@@ -786,16 +877,30 @@ async function processMessagesByMessage(rawData, services, args) {
               user_id: message.user_id,
               chat_id: message.chat_id,
               from_id: message.fromId?.userId || null,
+              message_date: message.date ?? null,
               original_blob_id: args.originalBlobId,
               on_chain_file_obj_id: args.onChainFileObjId,
               policy_object_id: args.policyObjectId,
-              embedding_dimensions: embeddingResult.embedding.length
+              embedding_dimensions: embeddingResult.embedding.length,
+              ...(args.blobExpiryEpoch != null ? { walrus_expiry_epoch: args.blobExpiryEpoch } : {}),
+              ...(args.encryptionPublicKey
+                ? { message_ciphertext: encryptForRecipient(args.encryptionPublicKey, message.message) }
+                : process.env.MESSAGE_TEXT_KEY
+                  ? {
+                      message_text_encrypted: encryptWithKey(
+                        process.env.MESSAGE_TEXT_KEY,
+                        process.env.MESSAGE_TEXT_KEY_ID,
+                        message.message,
+                        message.id
+                      )
+                    }
+                  : {})
             }
           };
         });
 
         // Store vectors
-        const storeResults = await services.vectorDb.storeBatch(vectorBatch);
+        const storeResults = await phaseTimer.time("upsert", () => services.vectorDb.storeBatch(vectorBatch));
         for (let r = 0; r < storeResults.length; r++) {
           if (!storeResults[r] || !storeResults[r].success) {
             const failedMessage = batch[r].id;
@@ -823,7 +928,8 @@ async function processMessagesByMessage(rawData, services, args) {
       failureReason: "parallel_processing_failed",
       error: error.message,
       totalMessages: selectedMessages.length,
-      processedSoFar: stats.successfulEmbeddings
+      processedSoFar: stats.successfulEmbeddings,
+      collectionCreated: services.vectorDb.createdCollection
     };
   }
 
@@ -835,6 +941,7 @@ async function processMessagesByMessage(rawData, services, args) {
     successfulEmbeddings: stats.successfulEmbeddings,
     successfulWalrusUploads: stats.successfulWalrusUploads,
     successfulVectorStorages: stats.successfulVectorStorages,
+    collectionCreated: services.vectorDb.createdCollection,
     message: "All messages processed successfully"
   };
 
@@ -844,9 +951,7 @@ async function processMessagesByMessage(rawData, services, args) {
   // Note: Don't record here - let the caller (patch processing loop) record it
   // This prevents double counting when processMessagesByMessage is called from patch processing
   
-  logger.log("===TASK_RESULT_START===");
-  logger.log(JSON.stringify(result));
-  logger.log("===TASK_RESULT_END===");
+  taskProtocol.writeResult(result);
 
   return result;
 }
@@ -916,22 +1021,26 @@ async function runRetrieveByBlobIdsOperation() {
     
     logger.log(`📦 Optimized to ${Object.keys(fileGroups).length} unique file downloads`);
     
+    // Per-file details reported when explain mode is on
+    const explainFiles = [];
+    
     // Process each unique file group
     for (const [key, group] of Object.entries(fileGroups)) {
       const { walrusBlobId, onChainFileObjId, policyObjectId, messageIndices } = group;
       
       logger.log(`📥 Processing file: ${walrusBlobId} (${onChainFileObjId})`);
+      const firstResult = retrievedMessages.length;
       const indicesInfo = messageIndices ? `indices: ${Array.from(messageIndices).join(',')}` : 'all messages';
       logger.log(`   Retrieving: ${indicesInfo}`);
       
       try {
         // Step 1: Fetch encrypted file from Walrus (once per unique file)
         logger.log(`📥 Fetching encrypted file from Walrus...`);
-        const encryptedFile = await services.blockchain.walrus.fetchEncryptedFile(walrusBlobId);
+        const encryptedFile = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchEncryptedFile(walrusBlobId));
         
         // Step 2: Parse encrypted object
         logger.log(`📦 Parsing encrypted object...`);
-        const encryptedObject = await services.blockchain.seal.parseEncryptedObject(encryptedFile);
+        const encryptedObject = await phaseTimer.time("parse", () => services.blockchain.seal.parseEncryptedObject(encryptedFile));
         
         // Step 3: Register attestation for decryption
         // logger.log(`🔗 Registering attestation...`);
@@ -942,7 +1051,7 @@ async function runRetrieveByBlobIdsOperation() {
         
         // Step 4: Decrypt file once
         logger.log(`🔓 Decrypting refined file...`);
-        const decryptedFile = await services.blockchain.seal.decryptFile(
+        const decryptedFile = await phaseTimer.time("decrypt", () => services.blockchain.seal.decryptFile(
           encryptedObject.id,
           // attestationObjId,
           encryptedFile,
@@ -950,7 +1059,7 @@ async function runRetrieveByBlobIdsOperation() {
           policyObjectId,
           // parsedArgs.threshold,
           services.blockchain.sui
-        );
+        ));
         
         // Step 5: Extract specific messages by indices from patch format
         // Each patch is a single chat object with chat_id and contents array
@@ -973,6 +1082,15 @@ async function runRetrieveByBlobIdsOperation() {
           });
         }
         
+        explainFiles.push({
+          walrus_blob_id: walrusBlobId,
+          on_chain_file_obj_id: onChainFileObjId,
+          requested_indices: messageIndices ? Array.from(messageIndices) : null,
+          messages_in_file: flatMessages.length,
+          missing_indices: messageIndices ? Array.from(messageIndices).filter(i => !flatMessages[i]) : [],
+          status: 'success'
+        });
+        
         if (messageIndices === null) {
           // Return all messages
           if (flatMessages.length > 0) {
@@ -1030,6 +1148,13 @@ async function runRetrieveByBlobIdsOperation() {
         
       } catch (error) {
         logger.error(`❌ Failed to process file ${walrusBlobId}: ${error.message}`);
+        explainFiles.push({
+          walrus_blob_id: walrusBlobId,
+          on_chain_file_obj_id: onChainFileObjId,
+          requested_indices: messageIndices ? Array.from(messageIndices) : null,
+          status: 'failed',
+          error: error.message
+        });
         
         // Add failed result for this entire file group
         const affectedIndices = messageIndices ? Array.from(messageIndices) : ['all'];
@@ -1044,6 +1169,16 @@ async function runRetrieveByBlobIdsOperation() {
           });
         }
       }
+
+      // Report the file's results as soon as they are known, for NDJSON responses
+      const fileResults = retrievedMessages.slice(firstResult);
+      logger.log(`===BLOB_RESULT===${JSON.stringify({
+        walrus_blob_id: walrusBlobId,
+        on_chain_file_obj_id: onChainFileObjId,
+        policy_object_id: policyObjectId,
+        status: fileResults.some(msg => msg.status === 'success') ? 'success' : 'failed',
+        results: fileResults
+      })}`);
     }
     
     // Return optimized results
@@ -1063,6 +1198,18 @@ async function runRetrieveByBlobIdsOperation() {
       failed_retrievals: retrievedMessages.filter(msg => msg.status === 'failed').length
     };
     
+    if (parsedArgs.explain) {
+      // Retrieval by blob ID is a direct lookup: there is no query vector, search or scoring
+      result.explain = {
+        mode: "blob_ids",
+        requested_pairs: parsedArgs.blobFilePairs.length,
+        file_groups: explainFiles,
+        vector_search: null,
+        retrieval_profile: parsedArgs.retrievalProfile,
+        timings_ms: phaseTimer.toJSON()
+      };
+    }
+    
     summaryReporter.end();
     const summary = summaryReporter.generateSummary();
     
@@ -1073,9 +1220,7 @@ async function runRetrieveByBlobIdsOperation() {
     
     logger.log("✅ Optimized blob ID retrieval completed!");
     logger.log(`📊 Processed ${result.total_files_processed} unique files, retrieved ${result.total_messages_retrieved} messages (${result.successful_retrievals} successful, ${result.failed_retrievals} failed)`);
-    logger.log("===TASK_RESULT_START===");
-    logger.log(JSON.stringify(result));
-    logger.log("===TASK_RESULT_END===");
+    taskProtocol.writeResult(result);
     process.exit(0);
     
   } catch (error) {
@@ -1094,13 +1239,85 @@ async function runRetrieveByBlobIdsOperation() {
         message_indices: pair.messageIndices || null
       })),
       error: error.message,
+      errorClass: classifyError(error),
       summary: summary
     };
     
     summaryReporter.printSummary(logger);
-    logger.log("===TASK_RESULT_START===");
-    logger.log(JSON.stringify(result));
-    logger.log("===TASK_RESULT_END===");
+    taskProtocol.writeResult(result);
+    process.exit(1);
+  }
+}
+
+async function runRetrieveFilteredOperation() {
+  logger.log("🔎 Running Filtered Message Retrieval...");
+
+  try {
+    const [queryEmbedding] = await phaseTimer.time("embed", () =>
+      services.embedding.embedBatch([parsedArgs.query])
+    );
+    const queryVector = queryEmbedding.embedding;
+
+    // Payload filters are applied by Qdrant while searching, so the limit counts matching messages only
+    const searched = await phaseTimer.time("search", () =>
+      parsedArgs.explain
+        ? services.vectorDb.searchWithExplain(queryVector, parsedArgs.limit, parsedArgs.filter)
+        : services.vectorDb.search(queryVector, parsedArgs.limit, parsedArgs.filter).then(results => ({ results }))
+    );
+
+    const result = {
+      status: "success",
+      operation: "retrieve-filtered",
+      filter: parsedArgs.filter,
+      limit: parsedArgs.limit,
+      results: searched.results.map(match => ({
+        id: match.id,
+        score: match.score,
+        message_id: match.metadata?.message_id ?? null,
+        chat_id: match.metadata?.chat_id ?? null,
+        from_id: match.metadata?.from_id ?? null,
+        message_date: match.metadata?.message_date ?? null,
+        original_blob_id: match.metadata?.original_blob_id ?? null,
+        on_chain_file_obj_id: match.metadata?.on_chain_file_obj_id ?? null,
+        message_index: match.metadata?.message_index ?? null,
+        message_ciphertext: match.metadata?.message_ciphertext,
+        message_text_encrypted: match.metadata?.message_text_encrypted
+      })),
+      total_results: searched.results.length
+    };
+
+    if (parsedArgs.explain) {
+      result.explain = {
+        mode: "filtered_search",
+        vector_search: searched.explain,
+        retrieval_profile: parsedArgs.retrievalProfile,
+        timings_ms: phaseTimer.toJSON()
+      };
+    }
+
+    summaryReporter.end();
+    result.summary = summaryReporter.generateSummary();
+    summaryReporter.printSummary(logger);
+
+    logger.log(`✅ Filtered retrieval returned ${result.total_results} messages`);
+    taskProtocol.writeResult(result);
+    process.exit(0);
+
+  } catch (error) {
+    logger.error("💥 Filtered retrieval operation failed:", error.message);
+
+    summaryReporter.end();
+    const result = {
+      status: "failed",
+      operation: "retrieve-filtered",
+      filter: parsedArgs.filter,
+      error: error.message,
+      errorClass: classifyError(error),
+      summary: summaryReporter.generateSummary()
+    };
+
+    summaryReporter.printSummary(logger);
+    taskProtocol.writeResult(result);
     process.exit(1);
   }
 }
@@ -1115,7 +1332,7 @@ async function runDefaultOperation() {
   
   let patches = null;
   try {
-    patches = await services.blockchain.walrus.fetchQuiltPatches(parsedArgs.blobId);
+    patches = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchQuiltPatches(parsedArgs.blobId));
     if (patches && patches.length > 0) {
       logger.log(`✅ Detected quilt ID. Found ${patches.length} patches. Processing as quilt...`);
       // It's a quilt ID - process all patches
@@ -1128,9 +1345,7 @@ async function runDefaultOperation() {
       };
       
       logger.log("✅ Task completed successfully!");
-      logger.log("===TASK_RESULT_START===");
-      logger.log(JSON.stringify(result));
-      logger.log("===TASK_RESULT_END===");
+      taskProtocol.writeResult(result);
       console.timeEnd('⌚ runDefaultOperation <<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<');
       process.exit(0);
       return;
@@ -1142,11 +1357,11 @@ async function runDefaultOperation() {
   
   // Step 2: Treat as patch ID and fetch encrypted file from Walrus
   logger.log("📥 Step 2: Fetching encrypted file as patch ID...");
-  const encryptedFile = await services.blockchain.walrus.fetchEncryptedFile(parsedArgs.blobId);
+  const encryptedFile = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchEncryptedFile(parsedArgs.blobId));
   
   // Step 3: Parse encrypted object
   logger.log("📦 Step 3: Parsing encrypted object...");
-  const encryptedObject = await services.blockchain.seal.parseEncryptedObject(encryptedFile);
+  const encryptedObject = await phaseTimer.time("parse", () => services.blockchain.seal.parseEncryptedObject(encryptedFile));
   
   // Step 4: Register attestation
   // logger.log("🔗 Step 4: Registering attestation...");
@@ -1157,7 +1372,7 @@ async function runDefaultOperation() {
   
   // Step 5: Decrypt file
   logger.log("🔓 Step 5: Decrypting file...");
-  const decryptedFile = await services.blockchain.seal.decryptFile(
+  const decryptedFile = await phaseTimer.time("decrypt", () => services.blockchain.seal.decryptFile(
     encryptedObject.id, // seal id
     // attestationObjId,
     encryptedFile,
@@ -1165,7 +1380,7 @@ async function runDefaultOperation() {
     parsedArgs.policyObjectId,
     // parsedArgs.threshold,
     services.blockchain.sui
-  );
+  ));
   
   // Step 6: Process embeddings directly from decrypted data
   logger.log("🔤 Step 6: Processing embeddings directly from decrypted data...");
@@ -1197,9 +1412,7 @@ async function runDefaultOperation() {
   summaryReporter.printSummary(logger);
   
   logger.log("✅ Task completed successfully!");
-  logger.log("===TASK_RESULT_START===");
-  logger.log(JSON.stringify(result));
-  logger.log("===TASK_RESULT_END===");
+  taskProtocol.writeResult(result);
   console.timeEnd('⌚ runDefaultOperation <<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<');
   process.exit(0);
 }
@@ -1263,18 +1476,18 @@ async function processQuiltPatches(patches, parsedArgs) {
     
     try {
       // Fetch encrypted patch blob from Walrus (rate limited!)
-      const encryptedPatch = await services.blockchain.walrus.fetchEncryptedFile(patchId);
+      const encryptedPatch = await phaseTimer.time("blob_fetch", () => services.blockchain.walrus.fetchEncryptedFile(patchId));
       
       // Parse encrypted object
-      const encryptedObject = await services.blockchain.seal.parseEncryptedObject(encryptedPatch);
+      const encryptedObject = await phaseTimer.time("parse", () => services.blockchain.seal.parseEncryptedObject(encryptedPatch));
       
       // Decrypt patch
-      const decryptedPatch = await services.blockchain.seal.decryptFile(
+      const decryptedPatch = await phaseTimer.time("decrypt", () => services.blockchain.seal.decryptFile(
         encryptedObject.id,
         encryptedPatch,
         parsedArgs.policyObjectId,
         services.blockchain.sui
-      );
+      ));
       
       return {
         patchIndex: i,
@@ -2,6 +2,11 @@ class WalrusOperations {
   constructor(options = {}) {
     this.options = options;
     this.aggregatorUrl = process.env.WALRUS_AGGREGATOR_URL;
+    // Aggregators reads move on to, in order, while the previous one is unavailable
+    this.fallbackAggregatorUrls = (process.env.WALRUS_AGGREGATOR_FALLBACK_URLS || '')
+      .split(',')
+      .map(url => url.trim().replace(/\/+$/, ''))
+      .filter(Boolean);
     this.publisherUrl = process.env.WALRUS_PUBLISHER_URL;
     this.epochs = process.env.WALRUS_EPOCHS;
     
@@ -10,6 +15,27 @@ class WalrusOperations {
     }
   }
 
+  // GET `path` from the aggregator, then from each fallback aggregator while the previous
+  // one fails to connect or answers with a 5xx. Any other answer, including 404, is returned.
+  async fetchFromAggregators(path, options) {
+    const aggregators = [this.aggregatorUrl, ...this.fallbackAggregatorUrls];
+    for (let i = 0; i < aggregators.length; i++) {
+      const isLast = i === aggregators.length - 1;
+      try {
+        const res = await fetch(`${aggregators[i]}${path}`, options);
+        if (res.status < 500 || isLast) {
+          return res;
+        }
+        console.error(`⚠️ Aggregator ${aggregators[i]} answered HTTP ${res.status}, trying ${aggregators[i + 1]}`);
+      } catch (err) {
+        if (isLast) {
+          throw err;
+        }
+        console.error(`⚠️ Aggregator ${aggregators[i]} failed: ${err.message}, trying ${aggregators[i + 1]}`);
+      }
+    }
+  }
+
   // {
   //   "id": "", -----------------> id for by-quilt-patch-id
   //   "blobId": "",
@@ -26,12 +52,12 @@ class WalrusOperations {
   // }
 
   async fetchQuiltPatches(quiltId) {
-    const walrusUrl = `${this.aggregatorUrl}/v1/quilts/${quiltId}/patches`;
+    const walrusPath = `/v1/quilts/${quiltId}/patches`;
     
     try {
-      console.log(`📥 Fetching quilt patches from ${walrusUrl}`);
+      console.log(`📥 Fetching quilt patches from ${this.aggregatorUrl}${walrusPath}`);
       
-      const res = await fetch(walrusUrl, {
+      const res = await this.fetchFromAggregators(walrusPath, {
         headers: { "Content-Type": "application/json" },
         method: "GET",
       });
@@ -57,13 +83,13 @@ class WalrusOperations {
     // This endpoint fetches the blob of a patch using the quilt patch ID
     // The quilt patch ID comes from the "patch_id" field in patches returned by /v1/quilts/{quilt_id}/patches
     // https://github.com/MystenLabs/walrus-sdk-example-app/blob/6db2b791a102dc7f7ffc202ec89f2a14537177e9/src/components/ImageCard.tsx#L31
-    const walrusUrl = `${this.aggregatorUrl}/v1/blobs/by-quilt-patch-id/${quiltPatchId}`;
+    const walrusPath = `/v1/blobs/by-quilt-patch-id/${quiltPatchId}`;
     
     try {
       // Reduced verbosity: only log on success for batch operations
       // Individual fetch attempts are logged at aggregate level in index.js
       
-      const res = await fetch(walrusUrl, {
+      const res = await this.fetchFromAggregators(walrusPath, {
         headers: { "Content-Type": "application/octet-stream" },
         method: "GET",
       });
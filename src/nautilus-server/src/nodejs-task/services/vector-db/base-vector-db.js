@@ -47,10 +47,18 @@ class BaseVectorDb {
     throw new Error('_storeBatch method must be implemented by subclass');
   }
 
-  async search(queryVector, limit = 10, filter = null) {
+  async search(queryVector, limit = 10, filter = null, params = null) {
     throw new Error('search method must be implemented by subclass');
   }
 
+  async searchWithExplain(queryVector, limit = 10, filter = null, params = null) {
+    throw new Error('searchWithExplain method must be implemented by subclass');
+  }
+
+  async recommend(positiveIds, negativeIds = [], limit = 10, filter = null) {
+    throw new Error('recommend method must be implemented by subclass');
+  }
+
   async deleteById(id) {
     throw new Error('deleteById method must be implemented by subclass');
   }
@@ -1,6 +1,8 @@
 const BaseVectorDb = require('./base-vector-db');
 const { QdrantClient } = require('@qdrant/js-client-rest');
-const { randomUUID } = require('crypto');
+const { randomUUID, createHash } = require('crypto');
+const { VectorPrivacy } = require('../../utils/vector-privacy');
+const { EMBEDDING_DIMENSIONS_METADATA, parseDimensions, truncateEmbedding } = require('../../utils/matryoshka');
 
 class QdrantService extends BaseVectorDb {
   constructor(options = {}) {
@@ -11,6 +13,24 @@ class QdrantService extends BaseVectorDb {
     this.apiKey = process.env.QDRANT_API_KEY || null;
     this.collectionName = process.env.QDRANT_COLLECTION_NAME || 'messages';
 
+    // Parameters of a collection created on first ingest, and default search parameters
+    this.distance = process.env.QDRANT_DISTANCE || 'Cosine';
+    this.hnswConfig = {};
+    if (process.env.QDRANT_HNSW_M) {
+      this.hnswConfig.m = parseInt(process.env.QDRANT_HNSW_M);
+    }
+    if (process.env.QDRANT_HNSW_EF_CONSTRUCT) {
+      this.hnswConfig.ef_construct = parseInt(process.env.QDRANT_HNSW_EF_CONSTRUCT);
+    }
+    this.searchParams = process.env.QDRANT_SEARCH_PARAMS ? JSON.parse(process.env.QDRANT_SEARCH_PARAMS) : null;
+    // Set when this service created the collection, reported in the task result
+    this.createdCollection = null;
+    // Projection and noise applied to stored vectors, see utils/vector-privacy.js
+    this.privacy = VectorPrivacy.fromEnv();
+    // Matryoshka truncation, see utils/matryoshka.js. Replaced by the dimensions recorded in
+    // the metadata of an existing collection.
+    this.embeddingDimensions = parseDimensions(process.env.EMBEDDING_DIMENSIONS);
+
     this.client = new QdrantClient({
       url: this.url,
       port: this.port,
@@ -61,6 +81,7 @@ class QdrantService extends BaseVectorDb {
     if (!Array.isArray(vector)) {
       throw new Error('Vector must be an array');
     }
+    vector = this.privacy.protect(truncateEmbedding(vector, this.embeddingDimensions));
 
     if (this.vectorSize === null) {
       this.vectorSize = vector.length;
@@ -98,6 +119,11 @@ class QdrantService extends BaseVectorDb {
     if (!this.connected) {
       await this.connect();
     }
+    batch = batch.map(item =>
+      Array.isArray(item.vector)
+        ? { ...item, vector: this.privacy.protect(truncateEmbedding(item.vector, this.embeddingDimensions)) }
+        : item
+    );
 
     // Set vector size from first item and ensure collection exists
     if (batch.length > 0 && this.vectorSize === null) {
@@ -157,7 +183,18 @@ class QdrantService extends BaseVectorDb {
     return this._retryOperation(operation);
   }
 
-  async search(queryVector, limit = 10, filter = null) {
+  // Exclude points soft deleted through /delete_messages (tombstoned with deleted_at_ms)
+  _withoutDeleted(filter) {
+    const notDeleted = { is_empty: { key: 'deleted_at_ms' } };
+    if (!filter) {
+      return { must: [notDeleted] };
+    }
+    return { ...filter, must: [...(filter.must || []), notDeleted] };
+  }
+
+  // params: optional Qdrant search params, e.g. { hnsw_ef: 128, exact: false }; defaults to
+  // the ones tuned for the collection. Soft deleted points are excluded unless includeDeleted.
+  async search(queryVector, limit = 10, filter = null, params = this.searchParams, includeDeleted = false) {
     if (!this.connected) {
       await this.connect();
     }
@@ -165,6 +202,7 @@ class QdrantService extends BaseVectorDb {
     if (!Array.isArray(queryVector)) {
       throw new Error('Query vector must be an array');
     }
+    queryVector = this.privacy.query(truncateEmbedding(queryVector, this.embeddingDimensions));
 
     const operation = async () => {
       const searchParams = {
@@ -174,8 +212,13 @@ class QdrantService extends BaseVectorDb {
         with_vector: false
       };
 
-      if (filter) {
-        searchParams.filter = filter;
+      const effectiveFilter = includeDeleted ? filter : this._withoutDeleted(filter);
+      if (effectiveFilter) {
+        searchParams.filter = effectiveFilter;
+      }
+
+      if (params) {
+        searchParams.params = params;
       }
 
       const results = await this.client.search(this.collectionName, searchParams);
@@ -191,6 +234,67 @@ class QdrantService extends BaseVectorDb {
     return this._retryOperation(operation);
   }
 
+  // Search and describe how the results were produced, for debugging relevance. The query
+  // vector is reported as a SHA-256 of its float64 bytes rather than the vector itself.
+  async searchWithExplain(queryVector, limit = 10, filter = null, params = this.searchParams, includeDeleted = false) {
+    const start = Date.now();
+    const results = await this.search(queryVector, limit, filter, params, includeDeleted);
+    const searchMs = Date.now() - start;
+
+    return {
+      results,
+      explain: {
+        collection: this.collectionName,
+        query_vector_sha256: createHash('sha256')
+          .update(Buffer.from(new Float64Array(queryVector).buffer))
+          .digest('hex'),
+        query_vector_dimensions: queryVector.length,
+        filter,
+        search_params: { limit, ...(params || {}) },
+        timings_ms: { search: searchMs },
+        raw_scores: results.map(result => ({ id: result.id, score: result.score }))
+      }
+    };
+  }
+
+  // Re-query using relevance feedback: points similar to the positive examples and
+  // dissimilar to the negative ones (the point IDs returned by POST /feedback)
+  async recommend(positiveIds, negativeIds = [], limit = 10, filter = null, includeDeleted = false) {
+    if (!this.connected) {
+      await this.connect();
+    }
+
+    if (!Array.isArray(positiveIds) || positiveIds.length === 0) {
+      throw new Error('At least one positive point ID is required');
+    }
+
+    const operation = async () => {
+      const recommendParams = {
+        positive: positiveIds,
+        negative: negativeIds,
+        limit,
+        with_payload: true,
+        with_vector: false
+      };
+
+      const effectiveFilter = includeDeleted ? filter : this._withoutDeleted(filter);
+      if (effectiveFilter) {
+        recommendParams.filter = effectiveFilter;
+      }
+
+      const results = await this.client.recommend(this.collectionName, recommendParams);
+
+      console.log(`🔍 Recommended ${results.length} vectors from ${positiveIds.length} positive examples`);
+      return results.map(result => ({
+        id: result.id,
+        score: result.score,
+        metadata: result.payload
+      }));
+    };
+
+    return this._retryOperation(operation);
+  }
+
   async deleteById(id) {
     if (!this.connected) {
       await this.connect();
@@ -243,31 +347,48 @@ class QdrantService extends BaseVectorDb {
           return;
         }
         
-        console.log(`📦 Creating Qdrant collection: ${this.collectionName} with vector size ${this.vectorSize}`);
+        console.log(`📦 Creating Qdrant collection: ${this.collectionName} with vector size ${this.vectorSize}, ${this.distance} distance`);
         
-        await this.client.createCollection(this.collectionName, {
+        const collectionConfig = {
           vectors: {
             size: this.vectorSize,
-            distance: 'Cosine'
+            distance: this.distance
           }
-        });
+        };
+        if (Object.keys(this.hnswConfig).length > 0) {
+          collectionConfig.hnsw_config = this.hnswConfig;
+        }
+        if (this.embeddingDimensions) {
+          collectionConfig.metadata = { [EMBEDDING_DIMENSIONS_METADATA]: this.embeddingDimensions };
+        }
+        await this.client.createCollection(this.collectionName, collectionConfig);
+        this.createdCollection = {
+          vectorSize: this.vectorSize,
+          distance: this.distance,
+          hnswConfig: this.hnswConfig,
+          embeddingDimensions: this.embeddingDimensions
+        };
         
         console.log(`✅ Created Qdrant collection: ${this.collectionName}`);
       } else {
         console.log(`✅ Qdrant collection already exists: ${this.collectionName}`);
         
-        // Get collection info to verify vector size matches if we have one set
-        if (this.vectorSize !== null) {
-          try {
-            const collectionInfo = await this.client.getCollection(this.collectionName);
-            const existingVectorSize = collectionInfo.config.params.vectors.size;
-            
-            if (this.vectorSize !== existingVectorSize) {
-              throw new Error(`Vector size mismatch: expected ${this.vectorSize}, collection has ${existingVectorSize}`);
-            }
-          } catch (error) {
-            console.warn(`⚠️  Could not verify collection vector size: ${error.message}`);
-          }
+        const collectionInfo = await this.client.getCollection(this.collectionName).catch(error => {
+          console.warn(`⚠️  Could not read collection info: ${error.message}`);
+          return null;
+        });
+
+        // Truncate like the vectors already in the collection
+        const recordedDimensions = parseDimensions(collectionInfo?.config?.metadata?.[EMBEDDING_DIMENSIONS_METADATA]);
+        if (recordedDimensions && recordedDimensions !== this.embeddingDimensions) {
+          console.warn(`⚠️  Collection ${this.collectionName} keeps ${recordedDimensions} embedding dimensions, not EMBEDDING_DIMENSIONS (${this.embeddingDimensions || 'all'})`);
+          this.embeddingDimensions = recordedDimensions;
+        }
+
+        // Verify the vector size matches if we have one set
+        const existingVectorSize = collectionInfo?.config?.params?.vectors?.size;
+        if (this.vectorSize !== null && existingVectorSize !== undefined && this.vectorSize !== existingVectorSize) {
+          console.warn(`⚠️  Could not verify collection vector size: Vector size mismatch: expected ${this.vectorSize}, collection has ${existingVectorSize}`);
         }
       }
     } catch (error) {
@@ -326,7 +447,8 @@ class QdrantService extends BaseVectorDb {
       ...super.getStats(),
       url: this.url,
       collectionName: this.collectionName,
-      vectorSize: this.vectorSize
+      vectorSize: this.vectorSize,
+      embeddingDimensions: this.embeddingDimensions
     };
   }
 }
@@ -0,0 +1,37 @@
+const crypto = require('crypto');
+
+/**
+ * Canonical JSON serialization (RFC 8785 / JCS) matching the Rust `canonical` module.
+ * Keys are sorted by UTF-16 code units, no whitespace is emitted and numbers use
+ * the ECMAScript formatting that JSON.stringify already applies.
+ * Test vectors shared with Rust live in `canonical_test_vectors.json`.
+ */
+function canonicalize(value) {
+  if (value === null || typeof value !== 'object') {
+    if (typeof value === 'number' && !Number.isFinite(value)) {
+      throw new Error('Non-finite numbers cannot be canonicalized');
+    }
+    return JSON.stringify(value);
+  }
+
+  if (Array.isArray(value)) {
+    return `[${value.map((item) => canonicalize(item)).join(',')}]`;
+  }
+
+  const keys = Object.keys(value)
+    .filter((key) => value[key] !== undefined)
+    .sort();
+  return `{${keys.map((key) => `${JSON.stringify(key)}:${canonicalize(value[key])}`).join(',')}}`;
+}
+
+/**
+ * Hex encoded SHA3-256 of the canonical serialization.
+ *
+ * @param {*} value - Any JSON compatible value
+ * @returns {string} Hex digest
+ */
+function canonicalHash(value) {
+  return crypto.createHash('sha3-256').update(canonicalize(value), 'utf8').digest('hex');
+}
+
+module.exports = { canonicalize, canonicalHash };
@@ -0,0 +1,30 @@
+// Error classes reported as `errorClass` in the result of a failed task, so the server's
+// retry policy (TaskRetryPolicy in task_runner.rs) can tell failures another run may get
+// past from those it would repeat.
+const TRANSIENT = "transient";
+
+const TRANSIENT_CODES = ["ECONNRESET", "ECONNREFUSED", "ETIMEDOUT", "EAI_AGAIN", "EPIPE", "UND_ERR_SOCKET"];
+// Services answering with these statuses may answer the next request
+const TRANSIENT_STATUS = /\bHTTP (429|5\d\d)\b|\bstatus code (429|5\d\d)\b/;
+const TRANSIENT_MESSAGE = /fetch failed|socket hang up|network|timed? ?out|rate limit/i;
+
+// Class of an error or error message: TRANSIENT for network failures, timeouts, rate limits
+// and 5xx answers, otherwise null
+function classifyError(error) {
+  if (!error) {
+    return null;
+  }
+  const message = typeof error === "string" ? error : error.message || "";
+  const code = error.code || error.cause?.code;
+  if (TRANSIENT_CODES.includes(code) || TRANSIENT_STATUS.test(message) || TRANSIENT_MESSAGE.test(message)) {
+    return TRANSIENT;
+  }
+  return null;
+}
+
+// TRANSIENT when every error is, so a run is only retried when nothing else went wrong
+function classifyErrors(errors) {
+  return errors.length > 0 && errors.every(error => classifyError(error) === TRANSIENT) ? TRANSIENT : null;
+}
+
+module.exports = { TRANSIENT, classifyError, classifyErrors };
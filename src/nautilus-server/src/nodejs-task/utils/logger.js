@@ -1,6 +1,13 @@
 const fs = require('fs');
 const path = require('path');
 
+// Prefix stderr lines with the ID of the server request running the task, so they can be
+// matched with the server's log lines for that request
+function withRequestId(message) {
+  const requestId = process.env.REQUEST_ID;
+  return requestId ? `[${requestId}] ${message}` : message;
+}
+
 class Logger {
   constructor() {
     this.logFile = null;
@@ -34,6 +41,8 @@ class Logger {
                                 message.includes('===TASK_RESULT_END===') ||
                                 message.includes('===SUMMARY_JSON_START===') ||
                                 message.includes('===SUMMARY_JSON_END===') ||
+                                message.includes('===TASK_TIMELINE_START===') ||
+                                message.startsWith('===BLOB_RESULT===') ||
                                 (message.startsWith('{') && message.endsWith('}') && message.includes('"status"'));
     
     // Write to console only if explicitly requested, not in quiet mode, or is structured output
@@ -51,7 +60,7 @@ class Logger {
     
     // Write to console only if explicitly requested or not in quiet mode
     if (!this.quietMode || toConsole) {
-      console.error(message);
+      console.error(withRequestId(message));
     }
     
     // Always write to file
@@ -64,7 +73,7 @@ class Logger {
     
     // Write to console only if explicitly requested or not in quiet mode
     if (!this.quietMode || toConsole) {
-      console.warn(message);
+      console.warn(withRequestId(message));
     }
     
     // Always write to file
@@ -0,0 +1,29 @@
+// Matryoshka truncation of embeddings (EMBEDDING_DIMENSIONS). Models trained with Matryoshka
+// representation learning front-load information, so their leading dimensions make a usable
+// embedding on their own. Stored and query vectors are cut to the same length and scaled back
+// to unit norm, so cosine and dot product scores stay comparable.
+//
+// A collection records the dimensions it was created with in its metadata
+// (`embeddingDimensions`); that value wins over EMBEDDING_DIMENSIONS, so vectors stored and
+// searched later keep matching the collection even if the setting changes.
+const EMBEDDING_DIMENSIONS_METADATA = "embeddingDimensions";
+
+function parseDimensions(value) {
+  const dimensions = parseInt(value || "0");
+  return dimensions > 0 ? dimensions : null;
+}
+
+// Leading `dimensions` of a vector, renormalized; unchanged when dimensions is null
+function truncateEmbedding(vector, dimensions) {
+  if (!dimensions) {
+    return vector;
+  }
+  if (dimensions > vector.length) {
+    throw new Error(`EMBEDDING_DIMENSIONS (${dimensions}) exceeds the embedding dimensions (${vector.length})`);
+  }
+  const truncated = vector.slice(0, dimensions);
+  const norm = Math.sqrt(truncated.reduce((sum, v) => sum + v * v, 0));
+  return norm > 0 ? truncated.map(v => v / norm) : truncated;
+}
+
+module.exports = { EMBEDDING_DIMENSIONS_METADATA, parseDimensions, truncateEmbedding };
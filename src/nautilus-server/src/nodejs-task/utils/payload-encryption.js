@@ -0,0 +1,74 @@
+const crypto = require("crypto");
+
+// Bring-your-own-key encryption of stored payload fields. Values are sealed to the client's
+// X25519 public key: an ephemeral X25519 key agreement, HKDF-SHA256 over the shared secret
+// (salt: ephemeral public key || recipient public key) and AES-256-GCM. Only the holder of
+// the client's private key can decrypt them; the enclave keeps no key material.
+const ALGORITHM = "x25519-hkdf-sha256-aes256gcm";
+const HKDF_INFO = Buffer.from("nautilus-byok-v1");
+// DER prefix of an X25519 SubjectPublicKeyInfo, followed by the 32 raw key bytes
+const X25519_SPKI_PREFIX = Buffer.from("302a300506032b656e032100", "hex");
+
+function parsePublicKey(publicKeyHex) {
+  const raw = Buffer.from(publicKeyHex.replace(/^0x/, ""), "hex");
+  if (raw.length !== 32) {
+    throw new Error("Encryption public key must be 32 hex encoded bytes");
+  }
+  return raw;
+}
+
+function rawPublicKey(keyObject) {
+  return keyObject.export({ format: "der", type: "spki" }).subarray(X25519_SPKI_PREFIX.length);
+}
+
+// Encrypt a UTF-8 string to a hex encoded X25519 public key
+function encryptForRecipient(publicKeyHex, plaintext) {
+  const recipient = parsePublicKey(publicKeyHex);
+  const recipientKey = crypto.createPublicKey({
+    key: Buffer.concat([X25519_SPKI_PREFIX, recipient]),
+    format: "der",
+    type: "spki",
+  });
+  const ephemeral = crypto.generateKeyPairSync("x25519");
+  const ephemeralPublic = rawPublicKey(ephemeral.publicKey);
+  const shared = crypto.diffieHellman({ privateKey: ephemeral.privateKey, publicKey: recipientKey });
+  const key = Buffer.from(
+    crypto.hkdfSync("sha256", shared, Buffer.concat([ephemeralPublic, recipient]), HKDF_INFO, 32)
+  );
+
+  const nonce = crypto.randomBytes(12);
+  const cipher = crypto.createCipheriv("aes-256-gcm", key, nonce);
+  const ciphertext = Buffer.concat([cipher.update(plaintext, "utf8"), cipher.final(), cipher.getAuthTag()]);
+  return {
+    alg: ALGORITHM,
+    epk: ephemeralPublic.toString("hex"),
+    nonce: nonce.toString("hex"),
+    // AES-GCM output with the 16 byte tag appended
+    ciphertext: ciphertext.toString("hex"),
+  };
+}
+
+// Encryption of stored message text under the enclave's payload key, passed to the task as
+// MESSAGE_TEXT_KEY (hex, already derived by the server) with its key ID MESSAGE_TEXT_KEY_ID.
+// The message ID is the associated data, so a ciphertext cannot be moved to another point.
+const ENCLAVE_ALGORITHM = "aes256gcm";
+
+function encryptWithKey(keyHex, kid, plaintext, aad) {
+  const key = Buffer.from(keyHex, "hex");
+  if (key.length !== 32) {
+    throw new Error("Message text key must be 32 hex encoded bytes");
+  }
+  const nonce = crypto.randomBytes(12);
+  const cipher = crypto.createCipheriv("aes-256-gcm", key, nonce);
+  cipher.setAAD(Buffer.from(String(aad), "utf8"));
+  const ciphertext = Buffer.concat([cipher.update(plaintext, "utf8"), cipher.final(), cipher.getAuthTag()]);
+  return {
+    alg: ENCLAVE_ALGORITHM,
+    kid,
+    nonce: nonce.toString("hex"),
+    // AES-GCM output with the 16 byte tag appended
+    ciphertext: ciphertext.toString("hex"),
+  };
+}
+
+module.exports = { ALGORITHM, ENCLAVE_ALGORITHM, encryptForRecipient, encryptWithKey, parsePublicKey };
@@ -0,0 +1,48 @@
+/**
+ * Accumulates the time spent in each pipeline phase (blob_fetch, decrypt, parse, embed,
+ * upsert) so the server can report where a task spent its time. Phases that run
+ * concurrently are summed, so totals can exceed the task's wall-clock time.
+ * Individual call durations and batch sizes are kept too, for the server's metrics.
+ */
+class PhaseTimer {
+  constructor() {
+    this.phases = {};
+    this.calls = {};
+    this.batchSizes = {};
+  }
+
+  add(phase, ms) {
+    this.phases[phase] = (this.phases[phase] || 0) + ms;
+    (this.calls[phase] = this.calls[phase] || []).push(ms);
+  }
+
+  // Record the size of a batch handed to a service, e.g. messages per embedding call
+  batch(name, size) {
+    (this.batchSizes[name] = this.batchSizes[name] || []).push(size);
+  }
+
+  async time(phase, fn) {
+    const start = Date.now();
+    try {
+      return await fn();
+    } finally {
+      this.add(phase, Date.now() - start);
+    }
+  }
+
+  toJSON() {
+    const timeline = {};
+    for (const [phase, ms] of Object.entries(this.phases)) {
+      timeline[`${phase}_ms`] = ms;
+    }
+    return timeline;
+  }
+
+  // Print the timeline between markers the Rust task runner looks for
+  report(logger) {
+    const report = { ...this.toJSON(), calls_ms: this.calls, batch_sizes: this.batchSizes };
+    logger.log(`===TASK_TIMELINE_START===\n${JSON.stringify(report)}\n===TASK_TIMELINE_END===`);
+  }
+}
+
+module.exports = PhaseTimer;
@@ -0,0 +1,50 @@
+/**
+ * Structured request/response protocol with the Rust task runner (TaskProtocolRequest and
+ * TaskProtocolResponse in task_runner.rs). The server writes one JSON request to stdin
+ * and closes it, with inputs too large or too private for argv; a pool worker hands it
+ * over in its `run` call instead. The result goes back as a single stdout line starting
+ * with RESPONSE_FRAME. Before reading the request the task prints a HELLO_FRAME line with the
+ * protocol version it speaks and its package version (TaskHello), so a server from another
+ * image version reports the mismatch instead of failing to parse the output.
+ *
+ * Without a request, e.g. when index.js is run by hand, inputs come from argv and the
+ * result is printed between the TASK_RESULT markers.
+ */
+const fs = require("fs");
+
+const PROTOCOL_VERSION = 1;
+const PROTOCOL_ENV = "NAUTILUS_TASK_PROTOCOL";
+const RESPONSE_FRAME = "===TASK_PROTOCOL_RESPONSE===";
+const HELLO_FRAME = "===TASK_PROTOCOL_HELLO===";
+const TASK_VERSION = require("../package.json").version;
+
+let request = null;
+
+// Read the request of this run. Returns its input, or null when there is no request.
+function readRequest() {
+  if (global.nautilusTaskRequest || process.env[PROTOCOL_ENV]) {
+    const hello = { protocol: PROTOCOL_VERSION, taskVersion: TASK_VERSION };
+    process.stdout.write(`${HELLO_FRAME}${JSON.stringify(hello)}\n`);
+  }
+  if (global.nautilusTaskRequest) {
+    request = global.nautilusTaskRequest;
+  } else if (process.env[PROTOCOL_ENV]) {
+    const raw = fs.readFileSync(0, "utf8");
+    request = raw.trim() ? JSON.parse(raw) : null;
+  }
+  if (request && request.version !== PROTOCOL_VERSION) {
+    throw new Error(`Unsupported task protocol version ${request.version}, expected ${PROTOCOL_VERSION}`);
+  }
+  return request ? request.input : null;
+}
+
+// Report the task result in the form the caller expects
+function writeResult(result) {
+  if (request) {
+    process.stdout.write(`${RESPONSE_FRAME}${JSON.stringify({ version: PROTOCOL_VERSION, result })}\n`);
+  } else {
+    process.stdout.write(`===TASK_RESULT_START===\n${JSON.stringify(result)}\n===TASK_RESULT_END===\n`);
+  }
+}
+
+module.exports = { PROTOCOL_VERSION, RESPONSE_FRAME, HELLO_FRAME, readRequest, writeResult };
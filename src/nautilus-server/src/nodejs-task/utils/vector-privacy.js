@@ -0,0 +1,109 @@
+const crypto = require("crypto");
+
+// Embedding inversion mitigation, applied to vectors before they are stored in Qdrant.
+//
+// - VECTOR_PROJECTION_DIMENSIONS: random projection to fewer dimensions. The sparse ±1 matrix
+//   (scaled by 1/sqrt(k)) is derived from the secret VECTOR_PROJECTION_SEED with HMAC-SHA256,
+//   so it is the same for every task and approximately preserves distances. Query vectors
+//   get the same projection so searches keep working.
+// - VECTOR_NOISE_SCALE: Gaussian noise whose expected norm is this fraction of the vector's
+//   norm. Only stored vectors are noised, queries are not.
+//
+// Both trade some recall for vectors that no longer map back to the original embedding.
+class VectorPrivacy {
+  constructor({ projectionDimensions = 0, projectionSeed = null, noiseScale = 0 } = {}) {
+    if (projectionDimensions > 0 && !projectionSeed) {
+      throw new Error("VECTOR_PROJECTION_SEED is required with VECTOR_PROJECTION_DIMENSIONS");
+    }
+    if (!(noiseScale >= 0)) {
+      throw new Error(`Invalid vector noise scale ${noiseScale}`);
+    }
+    this.projectionDimensions = projectionDimensions;
+    this.projectionSeed = projectionSeed ? Buffer.from(projectionSeed, "hex") : null;
+    this.noiseScale = noiseScale;
+    // Sign bits of the projection matrix, derived once the input dimension is known
+    this.signs = null;
+    this.inputDimensions = null;
+  }
+
+  static fromEnv(env = process.env) {
+    return new VectorPrivacy({
+      projectionDimensions: parseInt(env.VECTOR_PROJECTION_DIMENSIONS || "0"),
+      projectionSeed: env.VECTOR_PROJECTION_SEED || null,
+      noiseScale: parseFloat(env.VECTOR_NOISE_SCALE || "0"),
+    });
+  }
+
+  get enabled() {
+    return this.projectionDimensions > 0 || this.noiseScale > 0;
+  }
+
+  _projectionSigns(inputDimensions) {
+    if (this.inputDimensions === inputDimensions) {
+      return this.signs;
+    }
+    if (this.projectionDimensions >= inputDimensions) {
+      throw new Error(
+        `VECTOR_PROJECTION_DIMENSIONS (${this.projectionDimensions}) must be below the embedding dimensions (${inputDimensions})`
+      );
+    }
+    const bits = this.projectionDimensions * inputDimensions;
+    const blocks = [];
+    for (let counter = 0; counter * 256 < bits; counter++) {
+      const label = Buffer.alloc(8);
+      label.writeUInt32BE(inputDimensions, 0);
+      label.writeUInt32BE(counter, 4);
+      blocks.push(crypto.createHmac("sha256", this.projectionSeed).update(label).digest());
+    }
+    this.signs = Buffer.concat(blocks);
+    this.inputDimensions = inputDimensions;
+    return this.signs;
+  }
+
+  // Project a vector; unchanged when projection is disabled
+  project(vector) {
+    if (this.projectionDimensions === 0) {
+      return vector;
+    }
+    const signs = this._projectionSigns(vector.length);
+    const scale = 1 / Math.sqrt(this.projectionDimensions);
+    const projected = new Array(this.projectionDimensions).fill(0);
+    for (let row = 0; row < this.projectionDimensions; row++) {
+      let sum = 0;
+      for (let col = 0; col < vector.length; col++) {
+        const bit = row * vector.length + col;
+        sum += (signs[bit >> 3] >> (bit & 7)) & 1 ? vector[col] : -vector[col];
+      }
+      projected[row] = sum * scale;
+    }
+    return projected;
+  }
+
+  _gaussian() {
+    // Box-Muller over cryptographically random uniforms in (0, 1]
+    const [a, b] = [crypto.randomInt(1, 2 ** 48), crypto.randomInt(1, 2 ** 48)].map(n => n / 2 ** 48);
+    return Math.sqrt(-2 * Math.log(a)) * Math.cos(2 * Math.PI * b);
+  }
+
+  // Add noise to a vector; unchanged when noise is disabled
+  addNoise(vector) {
+    if (this.noiseScale === 0) {
+      return vector;
+    }
+    const norm = Math.sqrt(vector.reduce((sum, v) => sum + v * v, 0));
+    const sigma = (this.noiseScale * norm) / Math.sqrt(vector.length);
+    return vector.map(v => v + sigma * this._gaussian());
+  }
+
+  // Vector as stored: projected, then noised
+  protect(vector) {
+    return this.addNoise(this.project(vector));
+  }
+
+  // Query vector as searched: projected into the stored space, without noise
+  query(vector) {
+    return this.project(vector);
+  }
+}
+
+module.exports = { VectorPrivacy };
@@ -0,0 +1,181 @@
+#!/usr/bin/env node
+// Warm pool worker. Keeps npm dependencies loaded and runs index.js once per request,
+// so requests skip the Node.js and module cold start.
+//
+// Protocol: one JSON-RPC 2.0 message per line, requests on stdin, responses on stdout.
+//   {"jsonrpc":"2.0","id":1,"method":"ping"}
+//     -> {"jsonrpc":"2.0","id":1,"result":{"pid":123,"runs":4}}
+//   {"jsonrpc":"2.0","id":2,"method":"run","params":{"args":[...],"env":{...},"request":{...}}}
+//     -> {"jsonrpc":"2.0","id":2,"result":{"stdout":"...","stderr":"...","exit_code":0}}
+//
+// A run behaves like `node index.js <args>` with `env` added to the environment and
+// `request` as its protocol request (utils/task-protocol.js): its stdout and stderr are
+// captured, and process.exit() (or an uncaught error) ends the run instead of the worker.
+// Task files are re-evaluated on every run, node_modules are not. Requests are handled
+// one at a time.
+
+const path = require("path");
+const readline = require("readline");
+
+const TASK_ENTRY = path.join(__dirname, "index.js");
+const NODE_MODULES = `${path.sep}node_modules${path.sep}`;
+// Listeners a run registers on these events are removed when it ends
+const TASK_EVENTS = ["exit", "SIGINT", "SIGTERM"];
+
+const writeProtocol = process.stdout.write.bind(process.stdout);
+const writeStderr = process.stderr.write.bind(process.stderr);
+const exitWorker = process.exit.bind(process);
+
+let runs = 0;
+// Run in progress: captured output, exit listeners present before it, completion callback
+let current = null;
+
+class TaskExit extends Error {
+  constructor(code) {
+    super(`process.exit(${code})`);
+    this.code = code;
+  }
+}
+
+function send(message) {
+  writeProtocol(`${JSON.stringify({ jsonrpc: "2.0", ...message })}\n`);
+}
+
+// Output outside a run (late async work of a finished run) goes to the worker's stderr
+function capture(stream, chunk, encoding, callback) {
+  if (current) {
+    current[stream].push(
+      typeof chunk === "string" ? chunk : Buffer.from(chunk).toString(typeof encoding === "string" ? encoding : "utf8")
+    );
+  } else {
+    writeStderr(chunk, typeof encoding === "string" ? encoding : undefined);
+  }
+  const done = typeof encoding === "function" ? encoding : callback;
+  if (done) done();
+  return true;
+}
+
+process.stdout.write = (chunk, encoding, callback) => capture("stdout", chunk, encoding, callback);
+process.stderr.write = (chunk, encoding, callback) => capture("stderr", chunk, encoding, callback);
+
+function finish(code) {
+  if (!current) return;
+  const run = current;
+  // Run the exit listeners the task registered, as a real exit would (e.g. phase timings)
+  for (const listener of process.listeners("exit")) {
+    if (!run.listeners.exit.includes(listener)) {
+      try {
+        listener(code);
+      } catch (error) {
+        run.stderr.push(`${error.stack || error}\n`);
+      }
+    }
+  }
+  current = null;
+  run.resolve({ stdout: run.stdout.join(""), stderr: run.stderr.join(""), exit_code: code });
+}
+
+function fail(error) {
+  if (current) current.stderr.push(`${(error && error.stack) || error}\n`);
+  finish(1);
+}
+
+// Only task code calls process.exit; late calls from a finished run are ignored
+process.exit = (code) => {
+  const exitCode = Number(code ?? process.exitCode ?? 0);
+  finish(exitCode);
+  // Unwind the task code that called exit
+  throw new TaskExit(exitCode);
+};
+
+function onError(error) {
+  if (error instanceof TaskExit) return;
+  if (current) {
+    fail(error);
+  } else {
+    writeStderr(`Unhandled error outside a task run: ${(error && error.stack) || error}\n`);
+  }
+}
+process.on("uncaughtException", onError);
+process.on("unhandledRejection", onError);
+
+function clearTaskModules() {
+  for (const file of Object.keys(require.cache)) {
+    if (file.startsWith(__dirname + path.sep) && !file.includes(NODE_MODULES) && file !== __filename) {
+      delete require.cache[file];
+    }
+  }
+}
+
+async function run({ args = [], env = {}, request = null } = {}) {
+  const savedArgv = process.argv;
+  const savedEnv = Object.fromEntries(Object.keys(env).map((key) => [key, process.env[key]]));
+  const listeners = Object.fromEntries(TASK_EVENTS.map((event) => [event, process.listeners(event)]));
+
+  Object.assign(process.env, env);
+  process.argv = [process.argv[0], TASK_ENTRY, ...args];
+  process.exitCode = undefined;
+  global.nautilusTaskRequest = request;
+  clearTaskModules();
+
+  const result = await new Promise((resolve) => {
+    current = { stdout: [], stderr: [], listeners, resolve };
+    try {
+      require(TASK_ENTRY);
+    } catch (error) {
+      onError(error);
+    }
+  });
+
+  for (const event of TASK_EVENTS) {
+    for (const listener of process.listeners(event)) {
+      if (!listeners[event].includes(listener)) process.removeListener(event, listener);
+    }
+  }
+  for (const [key, value] of Object.entries(savedEnv)) {
+    if (value === undefined) delete process.env[key];
+    else process.env[key] = value;
+  }
+  process.argv = savedArgv;
+  delete global.nautilusTaskRequest;
+  runs += 1;
+  return result;
+}
+
+async function handle(line) {
+  let request;
+  try {
+    request = JSON.parse(line);
+  } catch (error) {
+    send({ id: null, error: { code: -32700, message: `Parse error: ${error.message}` } });
+    return;
+  }
+  switch (request.method) {
+    case "ping":
+      send({ id: request.id, result: { pid: process.pid, runs } });
+      break;
+    case "run":
+      send({ id: request.id, result: await run(request.params) });
+      break;
+    default:
+      send({ id: request.id, error: { code: -32601, message: `Unknown method: ${request.method}` } });
+  }
+}
+
+// Load the task dependencies up front so the first run is warm too
+const { dependencies = {} } = require("./package.json");
+for (const dependency of Object.keys(dependencies)) {
+  try {
+    require(dependency);
+  } catch (error) {
+    writeStderr(`Failed to preload ${dependency}: ${error.message}\n`);
+  }
+}
+
+let queue = Promise.resolve();
+readline
+  .createInterface({ input: process.stdin })
+  .on("line", (line) => {
+    queue = queue.then(() => handle(line));
+  })
+  .on("close", () => queue.then(() => exitWorker(0)));
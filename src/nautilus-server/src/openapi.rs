@@ -0,0 +1,154 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenAPI description of the task, attestation and health endpoints, served as
+//! `/openapi.json` with a Swagger UI at `/docs`. Schemas are derived with utoipa from the
+//! request and response types, so they follow the serde renames of the wire format.
+
+use crate::api_response::{ApiError, AttestationEnvelope, ConfigEnvelope, HealthCheckEnvelope, JobEnvelope, TaskEnvelope, Timing};
+use crate::app::{
+    BlobFileIdPair, EmbeddingIngestRequest, FilteredRetrievalRequest, MessageBlobRetrievalRequest, MessageFilters,
+    TaskRequest, TaskResponse,
+};
+use crate::collections::SearchQuality;
+use crate::common::{
+    AttestationInfo, AttestationMode, AttestationRef, BcsSignedEnvelope, ConfigInfo, ConfigResponse, ConfigStatus,
+    EmbeddingIngestProcessDataRequest, FilteredRetrievalProcessDataRequest, GetAttestationResponse,
+    HealthCheckResponse, MessageBlobRetrievalProcessDataRequest, OverallHealth, SignedTaskResponse,
+    TaskIntentMessage, TaskProcessDataRequest,
+};
+use crate::endpoints::EndpointsStatus;
+use crate::internal_key::{EncryptionKeySources, KeySource};
+use crate::jobs::{JobRecord, JobStatus};
+use crate::runtime_config::RuntimeConfig;
+use crate::scheduler::Priority;
+use crate::task_runner::{RawOutput, ResourceUsage};
+use crate::timeline::Timeline;
+use crate::warmup::WarmupReport;
+use axum::response::Html;
+use axum::Json;
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Nautilus server",
+        description = "Every JSON response is wrapped in an envelope with `data` or `error`, `requestId` and `timing`. Task responses are signed by the enclave key."
+    ),
+    paths(
+        crate::app::process_data,
+        crate::app::embedding_ingest,
+        crate::app::retrieve_messages_by_blob_ids,
+        crate::app::retrieve_messages_filtered,
+        crate::common::get_attestation,
+        crate::common::health_check,
+        crate::common::get_config,
+    ),
+    components(schemas(
+        TaskProcessDataRequest,
+        EmbeddingIngestProcessDataRequest,
+        MessageBlobRetrievalProcessDataRequest,
+        FilteredRetrievalProcessDataRequest,
+        TaskRequest,
+        EmbeddingIngestRequest,
+        MessageBlobRetrievalRequest,
+        BlobFileIdPair,
+        FilteredRetrievalRequest,
+        MessageFilters,
+        Priority,
+        SearchQuality,
+        AttestationMode,
+        TaskEnvelope,
+        SignedTaskResponse,
+        TaskIntentMessage,
+        TaskResponse,
+        ResourceUsage,
+        Timeline,
+        RawOutput,
+        AttestationRef,
+        BcsSignedEnvelope,
+        JobEnvelope,
+        JobRecord,
+        JobStatus,
+        WarmupReport,
+        AttestationEnvelope,
+        GetAttestationResponse,
+        AttestationInfo,
+        HealthCheckEnvelope,
+        HealthCheckResponse,
+        OverallHealth,
+        EndpointsStatus,
+        ConfigStatus,
+        ConfigEnvelope,
+        ConfigResponse,
+        ConfigInfo,
+        RuntimeConfig,
+        EncryptionKeySources,
+        KeySource,
+        ApiError,
+        Timing,
+    ))
+)]
+pub struct ApiDoc;
+
+/// Swagger UI page. Its assets are loaded by the browser from a CDN rather than bundled,
+/// so they are not part of the measured enclave image.
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Nautilus server API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Endpoint that returns the OpenAPI document.
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Endpoint that serves the Swagger UI over `/openapi.json`.
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI_HTML)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_document() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for path in [
+            "/process_data",
+            "/embedding_ingest",
+            "/retrieve_messages_by_blob_ids",
+            "/retrieve_messages_filtered",
+            "/get_attestation",
+            "/health_check",
+            "/config",
+        ] {
+            assert!(doc["paths"][path].is_object(), "{} is not documented", path);
+        }
+
+        let schemas = &doc["components"]["schemas"];
+        // Field names follow the serde renames
+        let ingest = &schemas["EmbeddingIngestRequest"]["properties"];
+        assert!(ingest["walrusBlobId"].is_object());
+        assert!(ingest["walrus_blob_id"].is_null());
+        assert!(schemas["ApiError"]["properties"]["code"].is_object());
+        assert!(schemas["TaskEnvelope"]["properties"]["requestId"].is_object());
+        assert_eq!(
+            doc["paths"]["/embedding_ingest"]["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/EmbeddingIngestProcessDataRequest"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Field-level encryption of stored message text under an enclave-held key. When
+//! `PAYLOAD_ENCRYPTION_KEY` is set, ingest stores each message's text AES-256-GCM encrypted
+//! in the `message_text_encrypted` payload field (unless the request brings its own key,
+//! see `encryption_public_key`), so Qdrant alone only ever sees vectors, IDs and
+//! ciphertext. Text is decrypted on `/decrypt_messages` for admin callers.
+//!
+//! The task gets a data key derived from the master key with HKDF-SHA3-256, never the
+//! master key itself. Each data key has a key ID stored next to the ciphertext; keys in
+//! `PAYLOAD_ENCRYPTION_PREVIOUS_KEYS` still decrypt, and `/admin/payload_keys/rotate`
+//! re-encrypts everything under the active key.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::qdrant::QdrantClient;
+use crate::soft_delete::{authorize, MessageSelector, TOMBSTONE_FIELD};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use fastcrypto::hmac::{hkdf_sha3_256, HkdfIkm};
+use fastcrypto::traits::{Generate, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use typenum::U12;
+
+/// Payload field holding the encrypted message text.
+pub const ENCRYPTED_TEXT_FIELD: &str = "message_text_encrypted";
+/// Algorithm identifier of [EncryptedText].
+pub const ENCRYPTED_TEXT_ALGORITHM: &str = "aes256gcm";
+const HKDF_SALT: &[u8] = b"nautilus-payload-encryption";
+const HKDF_INFO: &[u8] = b"message_text";
+/// Default and largest number of messages returned by one `/decrypt_messages` call.
+const DEFAULT_DECRYPT_LIMIT: u64 = 100;
+const MAX_DECRYPT_LIMIT: u64 = 1000;
+/// Points re-encrypted per scroll page during rotation.
+const ROTATION_PAGE_SIZE: u64 = 256;
+
+/// Message text encrypted under a payload key. Byte fields are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedText {
+    pub alg: String,
+    /// ID of the data key, see [PayloadKey::kid]
+    pub kid: String,
+    pub nonce: String,
+    /// AES-GCM output with the 16 byte tag appended
+    pub ciphertext: String,
+}
+
+/// Associated data of a message's ciphertext: its `message_id` as the task formats it
+/// (`String(message.id)`), which binds the ciphertext to its point.
+pub fn message_aad(message_id: &serde_json::Value) -> String {
+    match message_id {
+        serde_json::Value::String(id) => id.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Data key derived from a master key.
+#[derive(Clone)]
+pub struct PayloadKey {
+    kid: String,
+    key: AesKey<typenum::U32>,
+}
+
+impl PayloadKey {
+    /// Derive the data key of a hex encoded 32 byte master key.
+    pub fn derive(master_hex: &str) -> Result<Self, EnclaveError> {
+        let invalid = || EnclaveError::ConfigError("Payload encryption key must be 32 hex encoded bytes".to_string());
+        let master = Hex::decode(master_hex).ok().filter(|bytes| bytes.len() == 32).ok_or_else(invalid)?;
+        let ikm = HkdfIkm::from_bytes(&master).map_err(|_| invalid())?;
+        let data_key = hkdf_sha3_256(&ikm, HKDF_SALT, HKDF_INFO, 32)
+            .map_err(|e| EnclaveError::Internal(format!("Payload key derivation failed: {}", e)))?;
+        let kid = Hex::encode(&Sha3_256::digest(&data_key).digest[..8]);
+        let key = AesKey::from_bytes(&data_key).map_err(|_| invalid())?;
+        Ok(Self { kid, key })
+    }
+
+    /// Public identifier of the key, a truncated hash of the data key.
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    fn cipher(&self) -> Aes256Gcm<U12> {
+        Aes256Gcm::new(self.key.clone())
+    }
+
+    pub fn encrypt(&self, plaintext: &str, aad: &str) -> EncryptedText {
+        let iv = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+        let ciphertext = self.cipher().encrypt_authenticated(&iv, aad.as_bytes(), plaintext.as_bytes());
+        EncryptedText {
+            alg: ENCRYPTED_TEXT_ALGORITHM.to_string(),
+            kid: self.kid.clone(),
+            nonce: Hex::encode(iv.as_bytes()),
+            ciphertext: Hex::encode(ciphertext),
+        }
+    }
+
+    fn decrypt(&self, encrypted: &EncryptedText, aad: &str) -> Result<String, EnclaveError> {
+        let invalid = |field: &str| EnclaveError::Internal(format!("Invalid encrypted text {}", field));
+        let nonce = Hex::decode(&encrypted.nonce).map_err(|_| invalid("nonce"))?;
+        let iv = InitializationVector::<U12>::from_bytes(&nonce).map_err(|_| invalid("nonce"))?;
+        let ciphertext = Hex::decode(&encrypted.ciphertext).map_err(|_| invalid("ciphertext"))?;
+        let plaintext = self
+            .cipher()
+            .decrypt_authenticated(&iv, aad.as_bytes(), &ciphertext)
+            .map_err(|_| EnclaveError::Internal("Encrypted text does not decrypt with its key".to_string()))?;
+        String::from_utf8(plaintext).map_err(|e| EnclaveError::Internal(e.to_string()))
+    }
+}
+
+impl fmt::Debug for PayloadKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PayloadKey({})", self.kid)
+    }
+}
+
+/// The active payload key, which encrypts, and previous keys that still decrypt.
+#[derive(Debug, Clone)]
+pub struct PayloadKeyring {
+    active: PayloadKey,
+    previous: Vec<PayloadKey>,
+}
+
+impl PayloadKeyring {
+    /// Keyring of a hex encoded master key and a comma separated list of previous ones.
+    pub fn from_hex(active: &str, previous: &str) -> Result<Self, EnclaveError> {
+        Ok(Self {
+            active: PayloadKey::derive(active)?,
+            previous: previous
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(PayloadKey::derive)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn active(&self) -> &PayloadKey {
+        &self.active
+    }
+
+    /// Decrypt with the key named by the ciphertext's key ID.
+    pub fn decrypt(&self, encrypted: &EncryptedText, aad: &str) -> Result<String, EnclaveError> {
+        if encrypted.alg != ENCRYPTED_TEXT_ALGORITHM {
+            return Err(EnclaveError::Internal(format!(
+                "Unsupported encrypted text algorithm {}",
+                encrypted.alg
+            )));
+        }
+        std::iter::once(&self.active)
+            .chain(&self.previous)
+            .find(|key| key.kid == encrypted.kid)
+            .ok_or_else(|| EnclaveError::Internal(format!("Unknown payload key {}", encrypted.kid)))?
+            .decrypt(encrypted, aad)
+    }
+
+    /// Environment of ingest tasks: the active data key and its ID.
+    pub fn task_env(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("MESSAGE_TEXT_KEY".to_string(), Hex::encode(self.active.key.as_bytes())),
+            ("MESSAGE_TEXT_KEY_ID".to_string(), self.active.kid.clone()),
+        ])
+    }
+}
+
+/// Decrypt the text field of a point, `None` if the point has none.
+fn decrypt_point(keyring: &PayloadKeyring, point: &serde_json::Value) -> Option<Result<String, EnclaveError>> {
+    let payload = &point["payload"];
+    let field = payload.get(ENCRYPTED_TEXT_FIELD)?;
+    let result = serde_json::from_value::<EncryptedText>(field.clone())
+        .map_err(|e| EnclaveError::Internal(format!("Invalid encrypted text: {}", e)))
+        .and_then(|encrypted| keyring.decrypt(&encrypted, &message_aad(&payload["message_id"])));
+    Some(result)
+}
+
+fn keyring(state: &AppState) -> Result<&PayloadKeyring, EnclaveError> {
+    state
+        .config
+        .payload_keys
+        .as_ref()
+        .ok_or_else(|| EnclaveError::NotFound("Payload encryption is not configured".to_string()))
+}
+
+/// Request of `/decrypt_messages`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecryptMessagesRequest {
+    #[serde(flatten)]
+    pub selector: MessageSelector,
+    /// Messages per call, default 100, at most 1000
+    pub limit: Option<u64>,
+    /// `next_offset` of the previous call
+    pub offset: Option<serde_json::Value>,
+}
+
+/// Decrypted text of one message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecryptedMessage {
+    pub message_id: serde_json::Value,
+    pub chat_id: serde_json::Value,
+    pub text: String,
+}
+
+/// Response of `/decrypt_messages`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptMessagesResponse {
+    pub collection: String,
+    pub messages: Vec<DecryptedMessage>,
+    /// Selected messages stored without encrypted text
+    pub without_text: u64,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<serde_json::Value>,
+}
+
+async fn decrypt_messages_page(
+    state: &AppState,
+    keyring: &PayloadKeyring,
+    collection: &str,
+    request: DecryptMessagesRequest,
+) -> Result<DecryptMessagesResponse, EnclaveError> {
+    let mut must = request.selector.conditions()?;
+    must.push(serde_json::json!({ "is_empty": { "key": TOMBSTONE_FIELD } }));
+    let limit = request.limit.unwrap_or(DEFAULT_DECRYPT_LIMIT).clamp(1, MAX_DECRYPT_LIMIT);
+    let (points, next_offset) = QdrantClient::from_state(state)?
+        .scroll_points(collection, &serde_json::json!({ "must": must }), limit, request.offset)
+        .await?;
+
+    let mut response = DecryptMessagesResponse {
+        collection: collection.to_string(),
+        messages: Vec::new(),
+        without_text: 0,
+        next_offset,
+    };
+    for point in &points {
+        match decrypt_point(keyring, point) {
+            Some(text) => response.messages.push(DecryptedMessage {
+                message_id: point["payload"]["message_id"].clone(),
+                chat_id: point["payload"]["chat_id"].clone(),
+                text: text?,
+            }),
+            None => response.without_text += 1,
+        }
+    }
+    state.audit_log.record(
+        "messages_decrypted",
+        collection,
+        serde_json::json!({ "count": response.messages.len(), "selector": request.selector }),
+    );
+    Ok(response)
+}
+
+/// Decrypt the stored text of selected messages. Deleted messages are skipped. Requires
+/// the admin token.
+pub async fn decrypt_messages(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<DecryptMessagesRequest>,
+) -> ApiResponse<DecryptMessagesResponse> {
+    let authorized = authorize(&state, &headers, &request.selector)
+        .and_then(|collection| keyring(&state).map(|keyring| (collection, keyring)));
+    let (collection, keyring) = match authorized {
+        Ok(authorized) => authorized,
+        Err(e) => return ctx.error(e),
+    };
+    ctx.respond(decrypt_messages_page(&state, keyring, &collection, request).await)
+}
+
+/// Request of `/admin/payload_keys/rotate`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RotatePayloadKeysRequest {
+    /// Defaults to `QDRANT_COLLECTION_NAME`
+    pub collection: Option<String>,
+}
+
+/// Response of `/admin/payload_keys/rotate`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotatePayloadKeysResponse {
+    pub collection: String,
+    pub active_key_id: String,
+    /// Points re-encrypted under the active key
+    pub rotated: u64,
+    /// Points whose text no configured key decrypts, left unchanged
+    pub unreadable: u64,
+}
+
+/// Filter matching points with encrypted text under a key other than `active_kid`.
+pub fn rotation_filter(active_kid: &str) -> serde_json::Value {
+    serde_json::json!({
+        "must_not": [
+            { "is_empty": { "key": ENCRYPTED_TEXT_FIELD } },
+            { "key": format!("{}.kid", ENCRYPTED_TEXT_FIELD), "match": { "value": active_kid } },
+        ]
+    })
+}
+
+async fn rotate(state: &AppState, keyring: &PayloadKeyring, collection: &str) -> Result<RotatePayloadKeysResponse, EnclaveError> {
+    let client = QdrantClient::from_state(state)?;
+    let filter = rotation_filter(keyring.active().kid());
+    let mut response = RotatePayloadKeysResponse {
+        collection: collection.to_string(),
+        active_key_id: keyring.active().kid().to_string(),
+        rotated: 0,
+        unreadable: 0,
+    };
+    let mut offset = None;
+    loop {
+        let (points, next) = client.scroll_points(collection, &filter, ROTATION_PAGE_SIZE, offset).await?;
+        for point in &points {
+            let Some(Ok(text)) = decrypt_point(keyring, point) else {
+                response.unreadable += 1;
+                continue;
+            };
+            let encrypted = keyring.active().encrypt(&text, &message_aad(&point["payload"]["message_id"]));
+            let payload = serde_json::json!({ ENCRYPTED_TEXT_FIELD: encrypted });
+            client.set_point_payload(collection, payload, &[point["id"].clone()]).await?;
+            response.rotated += 1;
+        }
+        match next {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    if response.rotated > 0 {
+        state.audit_log.record(
+            "payload_keys_rotated",
+            collection,
+            serde_json::json!({ "count": response.rotated, "activeKeyId": response.active_key_id }),
+        );
+    }
+    Ok(response)
+}
+
+/// Re-encrypt message text stored under previous keys with the active key. Requires the
+/// admin token.
+pub async fn rotate_payload_keys(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<RotatePayloadKeysRequest>,
+) -> ApiResponse<RotatePayloadKeysResponse> {
+    let selector = MessageSelector {
+        collection: request.collection,
+        ..Default::default()
+    };
+    let authorized = authorize(&state, &headers, &selector)
+        .and_then(|collection| keyring(&state).map(|keyring| (collection, keyring)));
+    let (collection, keyring) = match authorized {
+        Ok(authorized) => authorized,
+        Err(e) => return ctx.error(e),
+    };
+    ctx.respond(rotate(&state, keyring, &collection).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MASTER: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const PREVIOUS: &str = "0202020202020202020202020202020202020202020202020202020202020202";
+
+    #[test]
+    fn test_keyring_round_trip_and_rotation() {
+        let old = PayloadKeyring::from_hex(PREVIOUS, "").unwrap();
+        let rotated = PayloadKeyring::from_hex(MASTER, &format!(" {} ,", PREVIOUS)).unwrap();
+        assert_ne!(old.active().kid(), rotated.active().kid());
+
+        let encrypted = old.active().encrypt("gm, see you at the standup", "42");
+        assert_eq!(encrypted.kid, old.active().kid());
+        assert_eq!(rotated.decrypt(&encrypted, "42").unwrap(), "gm, see you at the standup");
+        // Bound to the message ID
+        assert!(rotated.decrypt(&encrypted, "43").is_err());
+        // Unknown once the previous key is dropped
+        assert!(PayloadKeyring::from_hex(MASTER, "").unwrap().decrypt(&encrypted, "42").is_err());
+
+        assert!(PayloadKeyring::from_hex(MASTER, "abcd").is_err());
+        assert_eq!(message_aad(&json!(42)), "42");
+        assert_eq!(message_aad(&json!("m-1")), "m-1");
+        assert_eq!(rotation_filter("k")["must_not"][1]["key"], "message_text_encrypted.kid");
+    }
+
+    #[test]
+    fn test_decrypts_task_vector() {
+        let keyring = PayloadKeyring::from_hex(MASTER, "").unwrap();
+        let env = keyring.task_env();
+        assert_eq!(env["MESSAGE_TEXT_KEY_ID"], keyring.active().kid());
+        assert_eq!(env["MESSAGE_TEXT_KEY"], "6566934c423db31def3013b3e6a0c1af5272e9c601f5c1770bd431cb66297ff6");
+        // Encrypted by the task's utils/payload-encryption.js with MESSAGE_TEXT_KEY
+        let point = json!({ "id": 1, "payload": {
+            "message_id": 42,
+            ENCRYPTED_TEXT_FIELD: {
+                "alg": ENCRYPTED_TEXT_ALGORITHM,
+                "kid": keyring.active().kid(),
+                "nonce": "6384e0000de8243e787b0c13",
+                "ciphertext": "49244a362039685091ef1fa6b0f59f07df0f6562e59f0144e2a65f1cdacd7a6bc7096d43ee83d49b2956",
+            }
+        }});
+        assert_eq!(decrypt_point(&keyring, &point).unwrap().unwrap(), "gm, see you at the standup");
+        assert!(decrypt_point(&keyring, &json!({ "payload": { "message_id": 42 } })).is_none());
+    }
+}
@@ -0,0 +1,558 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Native Qdrant client for collection management, so operators can bootstrap the vector
+//! store through the enclave instead of reaching Qdrant directly. It talks to the REST API
+//! at `QDRANT_URL` with `QDRANT_API_KEY`, the same endpoint the Node task uses; points are
+//! still written and searched by the task.
+//!
+//! The `qdrant-client` crate is deliberately not used. It speaks gRPC, on port 6334 by
+//! default, while enclaves reach Qdrant through the vsock proxy and `allowed_endpoints.yaml`
+//! entry of the REST port in `QDRANT_URL`; a second port would have to be opened for every
+//! deployment. Staying on reqwest also keeps these calls under the shared circuit breaker,
+//! metrics and timeout of the other upstream clients, and avoids the tonic and prost
+//! dependency tree in the enclave image. Only the few collection endpoints below are modeled.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::collections::{CollectionSettings, Distance, SearchParams, EMBEDDING_DIMENSIONS_METADATA};
+use crate::config::{url_str, ApiKey};
+use crate::breakers::CircuitBreakers;
+use crate::metrics::Metrics;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// State and configuration of a collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionInfo {
+    pub name: String,
+    /// `green`, `yellow`, `grey` or `red`
+    pub status: String,
+    pub points_count: Option<u64>,
+    pub indexed_vectors_count: Option<u64>,
+    pub vector_size: Option<u64>,
+    pub distance: Option<String>,
+    /// Dimensions embeddings are truncated to, as recorded when the collection was created
+    pub embedding_dimensions: Option<u32>,
+    /// Full collection configuration as reported by Qdrant
+    pub config: serde_json::Value,
+}
+
+/// Parse the `GET /collections/{name}` response.
+pub fn parse_collection_info(name: &str, body: &serde_json::Value) -> Result<CollectionInfo, EnclaveError> {
+    let result = body.get("result").filter(|r| r.is_object()).ok_or_else(|| {
+        EnclaveError::upstream("qdrant", format!("Unexpected Qdrant collection response: {}", body))
+    })?;
+    let vectors = &result["config"]["params"]["vectors"];
+    Ok(CollectionInfo {
+        name: name.to_string(),
+        status: result["status"].as_str().unwrap_or("unknown").to_string(),
+        points_count: result["points_count"].as_u64(),
+        indexed_vectors_count: result["indexed_vectors_count"].as_u64(),
+        vector_size: vectors["size"].as_u64(),
+        distance: vectors["distance"].as_str().map(str::to_string),
+        embedding_dimensions: result["config"]["metadata"][EMBEDDING_DIMENSIONS_METADATA]
+            .as_u64()
+            .and_then(|dimensions| u32::try_from(dimensions).ok()),
+        config: result["config"].clone(),
+    })
+}
+
+/// Body of `PUT /collections/{name}`.
+pub fn create_collection_body(vector_size: u64, settings: &CollectionSettings) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "vectors": { "size": vector_size, "distance": settings.distance },
+    });
+    let mut hnsw = serde_json::Map::new();
+    if let Some(m) = settings.hnsw_m {
+        hnsw.insert("m".to_string(), m.into());
+    }
+    if let Some(ef_construct) = settings.hnsw_ef_construct {
+        hnsw.insert("ef_construct".to_string(), ef_construct.into());
+    }
+    if !hnsw.is_empty() {
+        body["hnsw_config"] = serde_json::Value::Object(hnsw);
+    }
+    if let Some(dimensions) = settings.embedding_dimensions {
+        body["metadata"] = serde_json::json!({ EMBEDDING_DIMENSIONS_METADATA: dimensions });
+    }
+    body
+}
+
+/// Async client for the Qdrant REST API.
+#[derive(Debug, Clone)]
+pub struct QdrantClient {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<ApiKey>,
+    metrics: Option<Metrics>,
+    breakers: Option<CircuitBreakers>,
+}
+
+impl QdrantClient {
+    pub fn new(url: &str, api_key: Option<ApiKey>) -> Result<Self, EnclaveError> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            metrics: None,
+            breakers: None,
+        })
+    }
+
+    /// Client for the configured Qdrant, recording call durations in the server metrics and
+    /// going through the Qdrant circuit breaker.
+    pub fn from_state(state: &AppState) -> Result<Self, EnclaveError> {
+        let mut client = Self::new(url_str(&state.config.qdrant_url), state.config.qdrant_api_key.clone())?;
+        client.metrics = Some(state.metrics.clone());
+        client.breakers = Some(state.breakers.clone());
+        Ok(client)
+    }
+
+    /// Send a request to `/collections/{path}` and return the JSON body of a successful
+    /// response, or `None` for 404. Fails at once while the circuit breaker is open.
+    async fn send(
+        &self,
+        call: &str,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Option<serde_json::Value>, EnclaveError> {
+        match &self.breakers {
+            Some(breakers) => breakers.call("qdrant", call, self.send_once(call, method, path, body)).await,
+            None => self.send_once(call, method, path, body).await,
+        }
+    }
+
+    async fn send_once(
+        &self,
+        call: &str,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Option<serde_json::Value>, EnclaveError> {
+        let mut request = self.http.request(method, format!("{}/collections/{}", self.url, path));
+        if let Some(api_key) = &self.api_key {
+            request = request.header("api-key", api_key.expose());
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let started = Instant::now();
+        let response = request.send().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_external_call("qdrant", call, started.elapsed());
+        }
+        crate::telemetry::record_upstream_call("qdrant", call, started.elapsed(), response.is_ok());
+        let response =
+            response.map_err(|e| EnclaveError::upstream("qdrant", format!("Qdrant {} request failed: {}", call, e)))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(EnclaveError::upstream("qdrant", format!(
+                "Qdrant {} failed with HTTP {}: {}",
+                call, status, body
+            )));
+        }
+        let body = response
+            .json()
+            .await
+            .map_err(|e| EnclaveError::upstream("qdrant", format!("Invalid Qdrant {} response: {}", call, e)))?;
+        Ok(Some(body))
+    }
+
+    /// Create `collection` for vectors of `vector_size` dimensions.
+    pub async fn create_collection(
+        &self,
+        collection: &str,
+        vector_size: u64,
+        settings: &CollectionSettings,
+    ) -> Result<(), EnclaveError> {
+        let body = create_collection_body(vector_size, settings);
+        self.send("create_collection", reqwest::Method::PUT, collection, Some(body))
+            .await?
+            .ok_or_else(|| EnclaveError::upstream("qdrant", format!("Qdrant could not create collection {}", collection)))?;
+        Ok(())
+    }
+
+    /// Information on `collection`, `None` if it does not exist.
+    pub async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>, EnclaveError> {
+        match self.send("collection_info", reqwest::Method::GET, collection, None).await? {
+            Some(body) => parse_collection_info(collection, &body).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete `collection` and its points. Returns whether it existed.
+    pub async fn delete_collection(&self, collection: &str) -> Result<bool, EnclaveError> {
+        let body = self.send("delete_collection", reqwest::Method::DELETE, collection, None).await?;
+        Ok(body.is_some_and(|b| b["result"].as_bool() == Some(true)))
+    }
+
+    /// Exact number of points matching a Qdrant `filter`, 0 if the collection does not exist.
+    pub async fn count_points(&self, collection: &str, filter: &serde_json::Value) -> Result<u64, EnclaveError> {
+        let body = serde_json::json!({ "filter": filter, "exact": true });
+        let path = format!("{}/points/count", collection);
+        match self.send("count_points", reqwest::Method::POST, &path, Some(body)).await? {
+            Some(response) => response["result"]["count"].as_u64().ok_or_else(|| {
+                EnclaveError::upstream("qdrant", format!("Unexpected Qdrant count response: {}", response))
+            }),
+            None => Ok(0),
+        }
+    }
+
+    /// Set `payload` fields on the points matching a Qdrant `filter`.
+    pub async fn set_payload(
+        &self,
+        collection: &str,
+        payload: serde_json::Value,
+        filter: &serde_json::Value,
+    ) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "payload": payload, "filter": filter });
+        let path = format!("{}/points/payload?wait=true", collection);
+        self.send("set_payload", reqwest::Method::POST, &path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Set `payload` fields on the points with the given IDs.
+    pub async fn set_point_payload(
+        &self,
+        collection: &str,
+        payload: serde_json::Value,
+        ids: &[serde_json::Value],
+    ) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "payload": payload, "points": ids });
+        let path = format!("{}/points/payload?wait=true", collection);
+        self.send("set_payload", reqwest::Method::POST, &path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// One page of the points matching a Qdrant `filter`, with payloads but without vectors,
+    /// and the offset of the next page. No points if the collection does not exist.
+    pub async fn scroll_points(
+        &self,
+        collection: &str,
+        filter: &serde_json::Value,
+        limit: u64,
+        offset: Option<serde_json::Value>,
+    ) -> Result<(Vec<serde_json::Value>, Option<serde_json::Value>), EnclaveError> {
+        let mut body = serde_json::json!({ "filter": filter, "limit": limit, "with_payload": true, "with_vector": false });
+        if let Some(offset) = offset {
+            body["offset"] = offset;
+        }
+        let path = format!("{}/points/scroll", collection);
+        let Some(response) = self.send("scroll_points", reqwest::Method::POST, &path, Some(body)).await? else {
+            return Ok((Vec::new(), None));
+        };
+        let points = response["result"]["points"].as_array().cloned().ok_or_else(|| {
+            EnclaveError::upstream("qdrant", format!("Unexpected Qdrant scroll response: {}", response))
+        })?;
+        let next = Some(response["result"]["next_page_offset"].clone()).filter(|offset| !offset.is_null());
+        Ok((points, next))
+    }
+
+    /// Remove payload `keys` from the points matching a Qdrant `filter`.
+    pub async fn delete_payload_keys(
+        &self,
+        collection: &str,
+        keys: &[&str],
+        filter: &serde_json::Value,
+    ) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "keys": keys, "filter": filter });
+        let path = format!("{}/points/payload/delete?wait=true", collection);
+        self.send("delete_payload", reqwest::Method::POST, &path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Insert or replace `points`, each with an `id`, `vector` and `payload`.
+    pub async fn upsert_points(&self, collection: &str, points: Vec<serde_json::Value>) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "points": points });
+        let path = format!("{}/points?wait=true", collection);
+        self.send("upsert_points", reqwest::Method::PUT, &path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Payload of the point with `id`, `None` if it or the collection does not exist.
+    pub async fn get_point_payload(&self, collection: &str, id: u64) -> Result<Option<serde_json::Value>, EnclaveError> {
+        let path = format!("{}/points/{}", collection, id);
+        let body = self.send("get_point", reqwest::Method::GET, &path, None).await?;
+        Ok(body.map(|b| b["result"]["payload"].clone()).filter(|payload| !payload.is_null()))
+    }
+
+    /// Delete the points matching a Qdrant `filter`, waiting until the deletion is applied.
+    pub async fn delete_points(&self, collection: &str, filter: &serde_json::Value) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "filter": filter });
+        let path = format!("{}/points/delete?wait=true", collection);
+        self.send("delete_points", reqwest::Method::POST, &path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Ask Qdrant to run its optimizers on `collection` now, with an empty optimizer config
+    /// update, rather than when its next write arrives.
+    pub async fn trigger_optimizers(&self, collection: &str) -> Result<(), EnclaveError> {
+        let body = serde_json::json!({ "optimizers_config": {} });
+        self.send("update_collection", reqwest::Method::PATCH, collection, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Vectors of up to `limit` points, to search with. Empty if the collection does not exist.
+    pub async fn sample_vectors(&self, collection: &str, limit: u64) -> Result<Vec<Vec<f32>>, EnclaveError> {
+        let body = serde_json::json!({ "limit": limit, "with_payload": false, "with_vector": true });
+        let path = format!("{}/points/scroll", collection);
+        let Some(response) = self.send("scroll_points", reqwest::Method::POST, &path, Some(body)).await? else {
+            return Ok(Vec::new());
+        };
+        let points = response["result"]["points"].as_array().ok_or_else(|| {
+            EnclaveError::upstream("qdrant", format!("Unexpected Qdrant scroll response: {}", response))
+        })?;
+        Ok(points
+            .iter()
+            .filter_map(|point| serde_json::from_value(point["vector"].clone()).ok())
+            .collect())
+    }
+
+    /// Number of points found searching `collection` for the `limit` nearest neighbours of
+    /// `vector` with search `params`.
+    pub async fn search(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        limit: u64,
+        params: &SearchParams,
+    ) -> Result<usize, EnclaveError> {
+        let body = serde_json::json!({ "vector": vector, "limit": limit, "params": params, "with_payload": false });
+        let path = format!("{}/points/search", collection);
+        let response = self.send("search", reqwest::Method::POST, &path, Some(body)).await?;
+        Ok(response.and_then(|r| r["result"].as_array().map(Vec::len)).unwrap_or(0))
+    }
+}
+
+/// Payload of `/collections/create`. Unset parameters fall back to `QDRANT_DISTANCE`,
+/// `QDRANT_HNSW_M`, `QDRANT_HNSW_EF_CONSTRUCT` and `EMBEDDING_DIMENSIONS`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCollectionRequest {
+    /// Collection to create, defaults to `QDRANT_COLLECTION_NAME`
+    pub collection: Option<String>,
+    /// Dimension of the embedding model's vectors
+    pub vector_size: u64,
+    pub distance: Option<Distance>,
+    pub hnsw_m: Option<u32>,
+    pub hnsw_ef_construct: Option<u32>,
+    /// Leading embedding dimensions kept, recorded in the collection metadata
+    pub embedding_dimensions: Option<u32>,
+}
+
+/// Query parameters of `/collections/info`, and payload of `/collections/delete`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionSelector {
+    /// Defaults to `QDRANT_COLLECTION_NAME`
+    pub collection: Option<String>,
+}
+
+/// Response of `/collections/create` and `/collections/delete`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionChangeResponse {
+    pub collection: String,
+    /// False when deleting a collection that did not exist
+    pub changed: bool,
+}
+
+/// Allowlisted collection of a request, or the error response to return.
+fn admin_collection<T>(
+    ctx: &RequestContext,
+    state: &AppState,
+    headers: &HeaderMap,
+    requested: Option<&str>,
+) -> Result<String, ApiResponse<T>> {
+    if !state.is_admin(headers) {
+        return Err(ctx.error(EnclaveError::Unauthorized("Admin token required".to_string())));
+    }
+    state
+        .qdrant_collection(requested)
+        .map(str::to_string)
+        .map_err(|e| ctx.error(e))
+}
+
+/// Create a collection. Requires the admin token.
+pub async fn create_collection(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateCollectionRequest>,
+) -> ApiResponse<CollectionChangeResponse> {
+    let collection = match admin_collection(&ctx, &state, &headers, request.collection.as_deref()) {
+        Ok(collection) => collection,
+        Err(response) => return response,
+    };
+    if request.vector_size == 0 {
+        return ctx.error(EnclaveError::BadRequest("vector_size must be positive".to_string()));
+    }
+    if request.embedding_dimensions == Some(0) {
+        return ctx.error(EnclaveError::BadRequest("embedding_dimensions must be positive".to_string()));
+    }
+    let defaults = &state.config.qdrant_collection_settings;
+    let settings = CollectionSettings {
+        distance: request.distance.unwrap_or(defaults.distance),
+        hnsw_m: request.hnsw_m.or(defaults.hnsw_m),
+        hnsw_ef_construct: request.hnsw_ef_construct.or(defaults.hnsw_ef_construct),
+        embedding_dimensions: request.embedding_dimensions.or(defaults.embedding_dimensions),
+    };
+
+    let result = async {
+        QdrantClient::from_state(&state)?
+            .create_collection(&collection, request.vector_size, &settings)
+            .await
+    }
+    .await;
+    if let Err(e) = result {
+        return ctx.error(e);
+    }
+    state.audit_log.record(
+        "collection_created",
+        &collection,
+        serde_json::json!({
+            "vectorSize": request.vector_size,
+            "distance": settings.distance,
+            "hnswConfig": { "m": settings.hnsw_m, "ef_construct": settings.hnsw_ef_construct },
+            "embeddingDimensions": settings.embedding_dimensions,
+        }),
+    );
+    ctx.ok(CollectionChangeResponse {
+        collection,
+        changed: true,
+    })
+}
+
+/// Information on a collection, 404 if it does not exist. Requires the admin token.
+pub async fn collection_info(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<CollectionSelector>,
+) -> ApiResponse<CollectionInfo> {
+    let collection = match admin_collection(&ctx, &state, &headers, query.collection.as_deref()) {
+        Ok(collection) => collection,
+        Err(response) => return response,
+    };
+    let result = async { QdrantClient::from_state(&state)?.collection_info(&collection).await }.await;
+    match result {
+        Ok(Some(info)) => ctx.ok(info),
+        Ok(None) => ctx
+            .error(EnclaveError::NotFound(format!("Collection {} does not exist", collection))),
+        Err(e) => ctx.error(e),
+    }
+}
+
+/// Delete a collection and all its points. Requires the admin token.
+pub async fn delete_collection(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CollectionSelector>,
+) -> ApiResponse<CollectionChangeResponse> {
+    let collection = match admin_collection(&ctx, &state, &headers, request.collection.as_deref()) {
+        Ok(collection) => collection,
+        Err(response) => return response,
+    };
+    let result = async { QdrantClient::from_state(&state)?.delete_collection(&collection).await }.await;
+    match result {
+        Ok(changed) => {
+            if changed {
+                state.audit_log.record("collection_deleted", &collection, serde_json::json!({}));
+            }
+            ctx.ok(CollectionChangeResponse { collection, changed })
+        }
+        Err(e) => ctx.error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn test_create_collection_body() {
+        let settings = CollectionSettings {
+            distance: Distance::Dot,
+            hnsw_m: Some(32),
+            hnsw_ef_construct: None,
+            embedding_dimensions: Some(256),
+        };
+        assert_eq!(
+            create_collection_body(256, &settings),
+            json!({
+                "vectors": { "size": 256, "distance": "Dot" },
+                "hnsw_config": { "m": 32 },
+                "metadata": { "embeddingDimensions": 256 },
+            })
+        );
+        let body = create_collection_body(768, &CollectionSettings::default());
+        assert!(body.get("hnsw_config").is_none());
+        assert!(body.get("metadata").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_against_stub() {
+        use axum::extract::Path;
+        use axum::routing::put;
+
+        let app = axum::Router::new().route(
+            "/collections/:name",
+            put(|headers: HeaderMap, Path(_name): Path<String>, Json(body): Json<serde_json::Value>| async move {
+                assert_eq!(headers["api-key"], "secret");
+                assert_eq!(body["vectors"]["size"], 3);
+                Json(json!({ "result": true, "status": "ok" }))
+            })
+            .get(|Path(name): Path<String>| async move {
+                if name != "messages" {
+                    return Err(StatusCode::NOT_FOUND);
+                }
+                Ok(Json(json!({ "result": {
+                    "status": "green",
+                    "points_count": 10,
+                    "indexed_vectors_count": 0,
+                    "config": {
+                        "params": { "vectors": { "size": 3, "distance": "Cosine" } },
+                        "metadata": { "embeddingDimensions": 3 }
+                    }
+                }})))
+            })
+            .delete(|| async { Json(json!({ "result": true, "status": "ok" })) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = QdrantClient::new(&url, Some(ApiKey::new("secret"))).unwrap();
+        client
+            .create_collection("messages", 3, &CollectionSettings::default())
+            .await
+            .unwrap();
+        let info = client.collection_info("messages").await.unwrap().unwrap();
+        assert_eq!(info.status, "green");
+        assert_eq!(info.points_count, Some(10));
+        assert_eq!(info.vector_size, Some(3));
+        assert_eq!(info.distance.as_deref(), Some("Cosine"));
+        assert_eq!(info.embedding_dimensions, Some(3));
+        assert!(client.collection_info("missing").await.unwrap().is_none());
+        assert!(client.delete_collection("messages").await.unwrap());
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reaper keeping the vector index consistent with what Walrus can still serve. Ingested
+//! points carry the expiry epoch of their source blob in `walrus_expiry_epoch`; once the
+//! current Walrus epoch passes it by `VECTOR_TTL_GRACE_EPOCHS`, the points are deleted from
+//! every allowlisted collection. Points ingested without an expiry epoch are never reaped.
+//! Each run also purges soft deleted points past their restore window
+//! (see [crate::soft_delete]).
+
+use crate::qdrant::QdrantClient;
+use crate::soft_delete::purge_deleted_vectors;
+use crate::sui::SuiClient;
+use crate::AppState;
+use crate::EnclaveError;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Payload field holding the expiry epoch of a point's source blob.
+pub const EXPIRY_EPOCH_FIELD: &str = "walrus_expiry_epoch";
+
+/// Qdrant filter matching points whose source blob expired at least `grace_epochs` ago.
+/// A blob is readable until its end epoch, so it is expired from that epoch on.
+pub fn expired_filter(current_epoch: u64, grace_epochs: u64) -> Option<serde_json::Value> {
+    let last_reaped_epoch = current_epoch.checked_sub(grace_epochs)?;
+    Some(serde_json::json!({
+        "must": [{ "key": EXPIRY_EPOCH_FIELD, "range": { "lte": last_reaped_epoch } }]
+    }))
+}
+
+/// Delete expired points from every allowlisted collection, returning the number deleted
+/// per collection where any were.
+pub async fn reap_expired_vectors(state: &AppState) -> Result<Vec<(String, u64)>, EnclaveError> {
+    let sui = SuiClient::from_state(state)?;
+    let current_epoch = crate::walrus::current_epoch(&sui, &state.config.walrus_system_object_id).await?;
+    let Some(filter) = expired_filter(current_epoch, state.config.vector_ttl_grace_epochs) else {
+        return Ok(Vec::new());
+    };
+    let client = QdrantClient::from_state(state)?;
+
+    let mut reaped = Vec::new();
+    for collection in &state.config.qdrant_collections {
+        let count = client.count_points(collection, &filter).await?;
+        if count == 0 {
+            continue;
+        }
+        client.delete_points(collection, &filter).await?;
+        state.audit_log.record(
+            "vectors_expired",
+            collection,
+            serde_json::json!({
+                "count": count,
+                "currentEpoch": current_epoch,
+                "graceEpochs": state.config.vector_ttl_grace_epochs,
+            }),
+        );
+        reaped.push((collection.clone(), count));
+    }
+    Ok(reaped)
+}
+
+/// Run [reap_expired_vectors] and [purge_deleted_vectors] every `interval` while this
+/// instance holds the leader lease (see [crate::leader]). Failures, including an unknown
+/// current epoch, skip the run so nothing is deleted on uncertain information.
+pub fn spawn_vector_reaper(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if !state.leader.is_leader() {
+                debug!("Skipping vector reap, another instance holds the leader lease");
+                continue;
+            }
+            match reap_expired_vectors(&state).await {
+                Ok(reaped) => {
+                    for (collection, count) in reaped {
+                        info!("Reaped {} expired vectors from {}", count, collection);
+                    }
+                }
+                Err(e) => warn!("Skipping expired vector reap: {:?}", e),
+            }
+            match purge_deleted_vectors(&state).await {
+                Ok(purged) => {
+                    for (collection, count) in purged {
+                        info!("Purged {} deleted vectors from {}", count, collection);
+                    }
+                }
+                Err(e) => warn!("Skipping deleted vector purge: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_filter() {
+        let filter = expired_filter(10, 2).unwrap();
+        assert_eq!(filter["must"][0]["key"], EXPIRY_EPOCH_FIELD);
+        assert_eq!(filter["must"][0]["range"]["lte"], 8);
+        assert_eq!(expired_filter(10, 0).unwrap()["must"][0]["range"]["lte"], 10);
+        // Nothing can be expired with a grace period longer than the chain's history
+        assert!(expired_filter(1, 2).is_none());
+    }
+}
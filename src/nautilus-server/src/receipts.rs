@@ -0,0 +1,186 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signed execution receipts anchored to Walrus. A receipt binds the request and result
+//! hashes to the enclave key and attestation, and is stored as a small blob so it can be
+//! fetched and verified independently of the server.
+
+use crate::app::TaskResponse;
+use crate::canonical::canonical_hash_of;
+use crate::common::{current_timestamp_ms, fetch_attestation, to_signed_response, IntentScope};
+use crate::timeline::timed;
+use crate::walrus::{store_blob, StoredBlob};
+use crate::AppState;
+use crate::EnclaveError;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Receipt of a single task execution. Hashes are hex encoded canonical JSON hashes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExecutionReceipt {
+    pub operation: String,
+    pub request_hash: String,
+    pub result_hash: String,
+    pub started_at_ms: u64,
+    pub finished_at_ms: u64,
+    pub duration_ms: u64,
+    /// Hex encoded enclave public key that signed the receipt.
+    pub enclave_public_key: String,
+    /// Enclave ID and SHA3-256 of the attestation document, as `<enclave_id>:<hash>`.
+    pub attestation_ref: String,
+}
+
+/// Captures what is known about a request before it runs.
+pub struct ReceiptContext {
+    operation: &'static str,
+    request_hash: Option<String>,
+    started_at_ms: u64,
+    anchor: bool,
+}
+
+impl ReceiptContext {
+    /// Start tracking a request. `anchor` overrides the server default for this request.
+    pub fn start<T: Serialize>(
+        state: &AppState,
+        operation: &'static str,
+        request: &T,
+        anchor: Option<bool>,
+    ) -> Self {
+        Self {
+            operation,
+            request_hash: canonical_hash_of(request).ok().map(Hex::encode),
+            started_at_ms: current_timestamp_ms(),
+            anchor: anchor.unwrap_or(state.anchor_receipts),
+        }
+    }
+
+    /// Build the receipt for a successful response.
+    pub async fn build(
+        &self,
+        state: &AppState,
+        response: &TaskResponse,
+    ) -> Result<ExecutionReceipt, EnclaveError> {
+        let request_hash = self.request_hash.clone().ok_or_else(|| {
+            EnclaveError::Internal("Request could not be hashed".to_string())
+        })?;
+        let attestation = fetch_attestation(state).await?.attestation;
+        let document_hash = Sha3_256::digest(attestation.attestationDocument.as_bytes()).digest;
+        let finished_at_ms = current_timestamp_ms();
+        Ok(ExecutionReceipt {
+            operation: self.operation.to_string(),
+            request_hash,
+            result_hash: Hex::encode(canonical_hash_of(response)?),
+            started_at_ms: self.started_at_ms,
+            finished_at_ms,
+            duration_ms: finished_at_ms.saturating_sub(self.started_at_ms),
+            enclave_public_key: state.keys.current().signing_public_key_hex(),
+            attestation_ref: format!("{}:{}", attestation.enclaveId, Hex::encode(document_hash)),
+        })
+    }
+
+    /// Bind a successful response to its request through `request_hash`, then sign and
+    /// store its receipt when anchoring is enabled, recording the blob ID on the response.
+    /// Anchoring failures are logged and do not fail the request.
+    pub async fn attach(
+        self,
+        state: &AppState,
+        result: Result<TaskResponse, EnclaveError>,
+    ) -> Result<TaskResponse, EnclaveError> {
+        let mut response = result?;
+        response.request_hash = self.request_hash.clone();
+        if !self.anchor {
+            return Ok(response);
+        }
+        let (anchored, sign_ms) = timed(self.anchor_receipt(state, &response)).await;
+        if let Some(timeline) = response.timeline.as_mut() {
+            timeline.sign_ms = Some(sign_ms);
+        }
+        match anchored {
+            Ok(blob) => {
+                info!("Anchored {} receipt as Walrus blob {}", self.operation, blob.blob_id);
+                response.receipt_blob_id = Some(blob.blob_id);
+            }
+            Err(e) => warn!("Failed to anchor {} receipt: {:?}", self.operation, e),
+        }
+        Ok(response)
+    }
+
+    async fn anchor_receipt(
+        &self,
+        state: &AppState,
+        response: &TaskResponse,
+    ) -> Result<StoredBlob, EnclaveError> {
+        let receipt = self.build(state, response).await?;
+        state.key_usage.acquire(IntentScope::ExecutionReceipt)?;
+        let signed = to_signed_response(
+            &*state.keys.current(),
+            receipt,
+            current_timestamp_ms(),
+            IntentScope::ExecutionReceipt,
+        )?;
+        let bytes = serde_json::to_vec(&signed).map_err(|e| {
+            EnclaveError::Internal(format!("Failed to serialize receipt: {}", e))
+        })?;
+        store_blob(state, bytes, &state.walrus_store).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::TaskRequest;
+    use crate::test_app_state;
+
+    fn task_response() -> TaskResponse {
+        TaskResponse {
+            status: "success".to_string(),
+            data: serde_json::json!({ "ok": true }),
+            stderr: String::new(),
+            exit_code: 0,
+            execution_time_ms: 5,
+            receipt_blob_id: None,
+            resource_usage: None,
+            timeline: None,
+            attestation_ref: None,
+            raw_output: None,
+            request_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_receipt() {
+        let state = test_app_state();
+        let request = TaskRequest {
+            timeout_secs: Some(10),
+            args: Some(vec!["list".to_string()]),
+            input: None,
+            priority: None,
+            anchor_receipt: None,
+            attestation: None,
+            attestation_nonce: None,
+            raw: None,
+        };
+        let ctx = ReceiptContext::start(&state, "process_data", &request, None);
+        let receipt = ctx.build(&state, &task_response()).await.unwrap();
+
+        assert_eq!(receipt.operation, "process_data");
+        assert_eq!(receipt.request_hash, Hex::encode(canonical_hash_of(&request).unwrap()));
+        assert_eq!(receipt.result_hash, Hex::encode(canonical_hash_of(&task_response()).unwrap()));
+        assert_eq!(receipt.enclave_public_key, state.keys.current().public_key_hex());
+        assert!(receipt.finished_at_ms >= receipt.started_at_ms);
+    }
+
+    #[tokio::test]
+    async fn test_attach_without_anchoring_keeps_response() {
+        let state = test_app_state();
+        let ctx = ReceiptContext::start(&state, "process_data", &serde_json::json!({}), Some(false));
+        let response = ctx.attach(&state, Ok(task_response())).await.unwrap();
+        assert_eq!(response.receipt_blob_id, None);
+        assert_eq!(
+            response.request_hash,
+            Some(Hex::encode(canonical_hash_of(&serde_json::json!({})).unwrap()))
+        );
+    }
+}
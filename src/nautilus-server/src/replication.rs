@@ -0,0 +1,461 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Warm standby replication. A primary pushes a signed snapshot of its job store, its
+//! idempotency key cache and the runtime collection tuning to a standby every
+//! `REPLICATION_INTERVAL_SECS`, so jobs accepted by the primary stay visible on `/jobs/:id`,
+//! and retried ingests are still answered with their job, after failing over to the standby.
+//! The cached Sui object reads are not replicated: they expire within seconds and the
+//! standby reads them from the chain again.
+//!
+//! Both sides verify the other's attestation before trusting it: the peer's document must
+//! be signed through the AWS Nitro certificate chain ([crate::nitro_attestation]), be
+//! recent, carry a fresh nonce and the same PCR0 as this enclave, so state only moves
+//! between enclaves running the same image. The standby additionally checks that the
+//! snapshot is signed by the key attested in the primary's document. With the mock
+//! attestation of `--dev` there is no document to check and the peer is trusted as is.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::collections::SearchParams;
+use crate::common::{
+    current_timestamp_ms, to_signed_response, AttestationProvider, GetAttestationResponse, IntentMessage, IntentScope,
+    ProcessedDataResponse,
+};
+use crate::config::url_str;
+use crate::jobs::JobRecord;
+use crate::nitro_attestation::{verify_document, AttestationDocument, NITRO_ROOT_CERT_SHA256};
+use crate::storage::StoredKey;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes, VerifyingKey};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Timeout of each request to the peer.
+const PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Part an instance plays in replication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicationRole {
+    /// Serves traffic and pushes its state to the standby
+    Primary,
+    /// Receives the primary's state, ready to take over
+    Standby,
+}
+
+impl FromStr for ReplicationRole {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "primary" => Ok(ReplicationRole::Primary),
+            "standby" => Ok(ReplicationRole::Standby),
+            other => Err(format!("unknown replication role {}, expected primary or standby", other)),
+        }
+    }
+}
+
+impl fmt::Display for ReplicationRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationRole::Primary => write!(f, "primary"),
+            ReplicationRole::Standby => write!(f, "standby"),
+        }
+    }
+}
+
+/// Replication settings, from `REPLICATION_ROLE`, `REPLICATION_PEER_URL` and
+/// `REPLICATION_INTERVAL_SECS`.
+#[derive(Debug, Clone)]
+pub struct ReplicationConfig {
+    pub role: ReplicationRole,
+    /// The standby, for a primary; the primary, for a standby
+    pub peer_url: Url,
+    /// Interval between snapshots pushed by a primary
+    pub interval: Duration,
+}
+
+/// State a primary replicates, signed under [IntentScope::ReplicationSnapshot].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationSnapshot {
+    /// Increases with every snapshot of the same primary, so old snapshots are not replayed
+    pub sequence: u64,
+    pub jobs: Vec<JobRecord>,
+    /// Search parameters tuned on `/admin/collections/:name/tune`
+    pub collection_tuning: BTreeMap<String, SearchParams>,
+    /// Live idempotency keys and the job of each
+    pub idempotency_keys: Vec<StoredKey>,
+}
+
+/// Body of `/replication/sync`.
+#[derive(Serialize, Deserialize)]
+pub struct ReplicationSync {
+    /// Hex Ed25519 public key of the primary
+    pub public_key: String,
+    pub snapshot: ProcessedDataResponse<IntentMessage<ReplicationSnapshot>>,
+}
+
+/// Response of `/replication/sync`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplicationSyncResponse {
+    pub sequence: u64,
+    pub jobs: usize,
+}
+
+/// Replication progress, served on `/admin/replication`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplicationStatus {
+    /// Unset when replication is disabled
+    pub role: Option<ReplicationRole>,
+    pub peer_url: Option<String>,
+    /// Whether the peer's attestation was checked, and when
+    pub peer_verified: bool,
+    pub peer_verified_at_ms: Option<u64>,
+    /// Hex public key of the primary whose snapshots are applied (standby only)
+    pub primary_public_key: Option<String>,
+    /// Sequence of the last snapshot sent (primary) or applied (standby)
+    pub last_sequence: u64,
+    pub last_sync_ms: Option<u64>,
+    pub jobs_synced: usize,
+    pub last_error: Option<String>,
+}
+
+/// Replication status shared by the sync loop and the handlers.
+#[derive(Debug, Default)]
+pub struct Replication {
+    status: Mutex<ReplicationStatus>,
+}
+
+impl Replication {
+    pub fn new(config: Option<&ReplicationConfig>) -> Self {
+        Self {
+            status: Mutex::new(ReplicationStatus {
+                role: config.map(|c| c.role),
+                peer_url: config.map(|c| url_str(&c.peer_url).to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn status(&self) -> ReplicationStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ReplicationStatus)) {
+        f(&mut self.status.lock().unwrap());
+    }
+}
+
+/// Check a verified peer document against the nonce it was requested with and this
+/// enclave's PCR0, returning the public key it attests.
+fn check_peer_document(document: &AttestationDocument, nonce: &[u8], pcr0: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    if document.nonce.as_deref().map(|n| n.as_slice()) != Some(nonce) {
+        return Err(EnclaveError::AttestationError("Peer attestation nonce does not match".to_string()));
+    }
+    if document.pcrs.get(&0).map(|p| p.as_slice()) != Some(pcr0) {
+        return Err(EnclaveError::AttestationError(
+            "Peer PCR0 differs from this enclave, it runs another image".to_string(),
+        ));
+    }
+    document
+        .public_key
+        .as_ref()
+        .map(|key| key.to_vec())
+        .ok_or_else(|| EnclaveError::AttestationError("Peer attestation has no public key".to_string()))
+}
+
+fn http_client() -> Result<reqwest::Client, EnclaveError> {
+    reqwest::Client::builder()
+        .timeout(PEER_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// `data` of a peer's response envelope.
+async fn peer_data<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, EnclaveError> {
+    let status = response.status();
+    let envelope: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| EnclaveError::upstream("replication", format!("Invalid peer response: {}", e)))?;
+    if !status.is_success() {
+        return Err(EnclaveError::upstream(
+            "replication",
+            format!("Peer answered HTTP {}: {}", status, envelope["error"]["message"]),
+        ));
+    }
+    serde_json::from_value(envelope["data"].clone())
+        .map_err(|e| EnclaveError::upstream("replication", format!("Invalid peer response: {}", e)))
+}
+
+/// Request the peer's attestation over a fresh nonce and check it. Returns the attested
+/// public key, or `None` with the mock attestation of `--dev`.
+async fn verify_peer(state: &AppState, client: &reqwest::Client, peer_url: &str) -> Result<Option<Vec<u8>>, EnclaveError> {
+    let nonce: [u8; 32] = rand::random();
+    let response = client
+        .get(format!("{}/get_attestation?nonce={}", peer_url, Hex::encode(nonce)))
+        .send()
+        .await
+        .map_err(|e| EnclaveError::upstream("replication", format!("Peer attestation request failed: {}", e)))?;
+    let attestation: GetAttestationResponse = peer_data(response).await?;
+    let public_key = match state.attestation {
+        AttestationProvider::Mock => {
+            warn!("Development mode, trusting replication peer {} without attestation", peer_url);
+            None
+        }
+        AttestationProvider::Nsm => {
+            let bytes = Hex::decode(&attestation.attestation.attestationDocument)
+                .map_err(|_| EnclaveError::AttestationError("Peer attestation is not hex encoded".to_string()))?;
+            let pcr0 = Hex::decode(&state.boot_attestation()?.pcr0).unwrap_or_default();
+            let document = verify_document(&bytes, NITRO_ROOT_CERT_SHA256, current_timestamp_ms())?;
+            Some(check_peer_document(&document, &nonce, &pcr0)?)
+        }
+    };
+    state.replication.update(|status| {
+        status.peer_verified = true;
+        status.peer_verified_at_ms = Some(current_timestamp_ms());
+    });
+    Ok(public_key)
+}
+
+/// Sign the current snapshot and send it to the standby.
+async fn push_snapshot(state: &AppState, client: &reqwest::Client, peer_url: &str) -> Result<(), EnclaveError> {
+    if !state.replication.status().peer_verified {
+        verify_peer(state, client, peer_url).await?;
+        info!("Verified replication standby {}", peer_url);
+    }
+    let snapshot = ReplicationSnapshot {
+        sequence: state.replication.status().last_sequence + 1,
+        jobs: state.jobs.snapshot(),
+        collection_tuning: state.collection_tuning.tuned(),
+        idempotency_keys: state.idempotency.snapshot(),
+    };
+    let (sequence, jobs) = (snapshot.sequence, snapshot.jobs.len());
+    state.key_usage.acquire(IntentScope::ReplicationSnapshot)?;
+    let key = state.keys.current();
+    let sync = ReplicationSync {
+        public_key: Hex::encode(key.keypair.public().as_bytes()),
+        snapshot: to_signed_response(&key.keypair, snapshot, current_timestamp_ms(), IntentScope::ReplicationSnapshot)?,
+    };
+    let response = client
+        .post(format!("{}/replication/sync", peer_url))
+        .json(&sync)
+        .send()
+        .await
+        .map_err(|e| EnclaveError::upstream("replication", format!("Snapshot push failed: {}", e)))?;
+    let _: ReplicationSyncResponse = peer_data(response).await?;
+    state.replication.update(|status| {
+        status.last_sequence = sequence;
+        status.last_sync_ms = Some(current_timestamp_ms());
+        status.jobs_synced = jobs;
+        status.last_error = None;
+    });
+    Ok(())
+}
+
+/// Push snapshots to the standby every `config.interval`. The standby is verified again
+/// after a failed push, since it may have restarted with a new key.
+pub fn spawn_primary(state: Arc<AppState>, config: ReplicationConfig) {
+    tokio::spawn(async move {
+        let client = match http_client() {
+            Ok(client) => client,
+            Err(e) => return warn!("Replication disabled: {:?}", e),
+        };
+        let peer_url = url_str(&config.peer_url).to_string();
+        loop {
+            tokio::time::sleep(config.interval).await;
+            if let Err(e) = push_snapshot(&state, &client, &peer_url).await {
+                let message = e.status_and_message().1;
+                warn!("Replication to {} failed: {}", peer_url, message);
+                state.replication.update(|status| {
+                    status.peer_verified = false;
+                    status.last_error = Some(message);
+                });
+            }
+        }
+    });
+}
+
+/// Check the signature and sequence of a snapshot from the primary with public key
+/// `public_key`, then apply it.
+pub fn apply_sync(state: &AppState, sync: ReplicationSync, public_key: &[u8]) -> Result<ReplicationSyncResponse, EnclaveError> {
+    let key = Ed25519PublicKey::from_bytes(public_key)
+        .map_err(|_| EnclaveError::BadRequest("Invalid primary public key".to_string()))?;
+    let signature = Hex::decode(&sync.snapshot.signature)
+        .ok()
+        .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::BadRequest("Invalid snapshot signature encoding".to_string()))?;
+    let message = sync.snapshot.response;
+    if message.intent != IntentScope::ReplicationSnapshot {
+        return Err(EnclaveError::BadRequest("Snapshot is not signed as a replication snapshot".to_string()));
+    }
+    let signed_bytes = bcs::to_bytes(&message).expect("should not fail");
+    key.verify(&signed_bytes, &signature)
+        .map_err(|_| EnclaveError::BadRequest("Snapshot signature does not verify".to_string()))?;
+
+    let primary = Hex::encode(public_key);
+    let status = state.replication.status();
+    let snapshot = message.data;
+    if status.primary_public_key.as_deref() == Some(primary.as_str()) && snapshot.sequence <= status.last_sequence {
+        return Err(EnclaveError::BadRequest(format!(
+            "Snapshot {} is not newer than the applied snapshot {}",
+            snapshot.sequence, status.last_sequence
+        )));
+    }
+    let (sequence, jobs) = (snapshot.sequence, snapshot.jobs.len());
+    state.jobs.replicate(snapshot.jobs);
+    for (collection, params) in snapshot.collection_tuning {
+        state.collection_tuning.set(&collection, params);
+    }
+    state.idempotency.replicate(snapshot.idempotency_keys);
+    state.replication.update(|status| {
+        status.primary_public_key = Some(primary);
+        status.last_sequence = sequence;
+        status.last_sync_ms = Some(current_timestamp_ms());
+        status.jobs_synced = jobs;
+        status.last_error = None;
+    });
+    Ok(ReplicationSyncResponse { sequence, jobs })
+}
+
+/// Accept a snapshot on a standby. A primary key not seen before is only trusted once the
+/// primary's attestation, fetched from `REPLICATION_PEER_URL`, attests that key.
+async fn receive_sync(state: &AppState, sync: ReplicationSync) -> Result<ReplicationSyncResponse, EnclaveError> {
+    let config = match &state.config.replication {
+        Some(config) if config.role == ReplicationRole::Standby => config,
+        _ => return Err(EnclaveError::NotFound("This instance is not a replication standby".to_string())),
+    };
+    let public_key = Hex::decode(&sync.public_key)
+        .map_err(|_| EnclaveError::BadRequest("public_key must be hex encoded".to_string()))?;
+    let status = state.replication.status();
+    if status.primary_public_key.as_deref() != Some(sync.public_key.as_str()) || !status.peer_verified {
+        let attested = verify_peer(state, &http_client()?, url_str(&config.peer_url)).await?;
+        if attested.is_some_and(|attested| attested != public_key) {
+            state.replication.update(|status| status.peer_verified = false);
+            return Err(EnclaveError::AttestationError(
+                "Snapshot is not signed by the attested primary".to_string(),
+            ));
+        }
+        info!("Verified replication primary {}", sync.public_key);
+    }
+    apply_sync(state, sync, &public_key)
+}
+
+/// `/replication/sync`: snapshots pushed by the primary.
+pub async fn replication_sync(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Json(sync): Json<ReplicationSync>,
+) -> ApiResponse<ReplicationSyncResponse> {
+    let result = receive_sync(&state, sync).await;
+    if let Err(e) = &result {
+        let message = format!("{:?}", e);
+        state.replication.update(|status| status.last_error = Some(message));
+    }
+    ctx.respond(result)
+}
+
+/// `/admin/replication`: role, peer verification and last sync. Requires the admin token.
+pub async fn replication_status(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<ReplicationStatus> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.ok(state.replication.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idempotency::Claim;
+    use crate::jobs::JobStatus;
+    use crate::test_app_state;
+    use serde_bytes::ByteBuf;
+
+    fn snapshot_sync(state: &AppState, sequence: u64) -> ReplicationSync {
+        let snapshot = ReplicationSnapshot {
+            sequence,
+            jobs: state.jobs.snapshot(),
+            collection_tuning: BTreeMap::from([(
+                "messages".to_string(),
+                SearchParams {
+                    hnsw_ef: Some(256),
+                    exact: None,
+                },
+            )]),
+            idempotency_keys: state.idempotency.snapshot(),
+        };
+        ReplicationSync {
+            public_key: Hex::encode(state.keys.current().keypair.public().as_bytes()),
+            snapshot: to_signed_response(&state.keys.current().keypair, snapshot, 1744038900000, IntentScope::ReplicationSnapshot)
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_apply_snapshot() {
+        let primary = test_app_state();
+        let job = primary.jobs.create("embedding_ingest");
+        primary.jobs.mark_running(&job.id);
+        let payload = serde_json::json!({ "blob_id": "blob" });
+        primary.idempotency.claim(&primary.jobs, "retry-1", &payload, || Ok(job.clone())).unwrap();
+        let standby = test_app_state();
+        let key = primary.keys.current().keypair.public().as_bytes().to_vec();
+
+        // Round trip through JSON, as sent over HTTP
+        let sync: ReplicationSync = serde_json::from_value(serde_json::to_value(snapshot_sync(&primary, 1)).unwrap()).unwrap();
+        let applied = apply_sync(&standby, sync, &key).unwrap();
+        assert_eq!(applied.jobs, 1);
+        assert_eq!(standby.jobs.get(&job.id).unwrap().status, JobStatus::Running);
+        assert_eq!(standby.collection_tuning.get("messages").hnsw_ef, Some(256));
+        assert_eq!(standby.replication.status().last_sequence, 1);
+        let retried = standby.idempotency.claim(&standby.jobs, "retry-1", &payload, || panic!("job started again"));
+        assert!(matches!(retried, Ok(Claim::Replayed(record)) if record.id == job.id));
+
+        // Replays and other keys are rejected
+        assert!(apply_sync(&standby, snapshot_sync(&primary, 1), &key).is_err());
+        let other = test_app_state();
+        assert!(apply_sync(&standby, snapshot_sync(&primary, 2), other.keys.current().keypair.public().as_bytes()).is_err());
+        assert!(apply_sync(&standby, snapshot_sync(&primary, 2), &key).is_ok());
+    }
+
+    #[test]
+    fn test_check_peer_document() {
+        let document = AttestationDocument {
+            timestamp: 1744038900000,
+            pcrs: BTreeMap::from([(0, ByteBuf::from(vec![7; 48]))]),
+            certificate: ByteBuf::new(),
+            cabundle: Vec::new(),
+            public_key: Some(ByteBuf::from(vec![9; 32])),
+            user_data: None,
+            nonce: Some(ByteBuf::from(vec![1; 32])),
+        };
+        assert_eq!(check_peer_document(&document, &[1; 32], &[7; 48]).unwrap(), vec![9; 32]);
+        assert!(check_peer_document(&document, &[2; 32], &[7; 48]).is_err());
+        assert!(check_peer_document(&document, &[1; 32], &[8; 48]).is_err());
+        let not_cose = serde_cbor::to_vec(&serde_cbor::Value::Integer(1)).unwrap();
+        assert!(verify_document(&not_cose, NITRO_ROOT_CERT_SHA256, 1744038900000).is_err());
+    }
+
+    #[test]
+    fn test_role() {
+        assert_eq!("Standby".parse::<ReplicationRole>(), Ok(ReplicationRole::Standby));
+        assert!("leader".parse::<ReplicationRole>().is_err());
+    }
+}
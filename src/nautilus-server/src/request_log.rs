@@ -0,0 +1,237 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory ring buffer of the most recent requests, for reconstructing what happened
+//! right before a failure. Payloads are never stored, only a hash salted with the ID
+//! mask salt, so equal payloads can be matched without revealing them.
+
+use crate::api_response::{ApiResponse, RequestContext, REQUEST_ID_HEADER};
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::body::Body;
+use axum::extract::{Query, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Requests kept by default.
+pub const DEFAULT_REQUEST_LOG_SIZE: usize = 500;
+
+/// One handled request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestLogEntry {
+    pub request_id: Option<String>,
+    pub timestamp_ms: u64,
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Hex SHA3-256 of the ID mask salt and the body, `None` for empty bodies
+    pub payload_hash: Option<String>,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+/// Fixed-size log of the latest requests.
+pub struct RequestLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<RequestLogEntry>>,
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUEST_LOG_SIZE)
+    }
+}
+
+impl RequestLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn record(&self, entry: RequestLogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Up to `limit` entries, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<RequestLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// Salted hash of a request body, `None` when it is empty.
+pub fn masked_payload_hash(salt: &str, body: &[u8]) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+    let mut hash = Sha3_256::default();
+    hash.update(salt.as_bytes());
+    hash.update([0u8]);
+    hash.update(body);
+    Some(Hex::encode(hash.finalize().digest))
+}
+
+/// Middleware recording every request in [AppState::request_log]. Bodies are buffered to
+/// be hashed, so ones over `MAX_REQUEST_BODY_BYTES` are refused.
+pub async fn record_request(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let started = std::time::Instant::now();
+    let timestamp_ms = current_timestamp_ms();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+
+    let (parts, body) = request.into_parts();
+    let max_body_bytes = state.config.max_request_body_bytes;
+    let (payload_hash, response) = match axum::body::to_bytes(body, max_body_bytes).await {
+        Ok(bytes) => {
+            let payload_hash = masked_payload_hash(state.id_mask_salt(), &bytes);
+            let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+            (payload_hash, response)
+        }
+        Err(_) => {
+            let response = RequestContext::new(request_id.clone())
+                .error::<()>(EnclaveError::PayloadTooLarge(format!(
+                    "Request body exceeds {} bytes",
+                    max_body_bytes
+                )))
+                .into_response();
+            (None, response)
+        }
+    };
+
+    state.request_log.record(RequestLogEntry {
+        request_id,
+        timestamp_ms,
+        method,
+        path,
+        payload_hash,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_millis() as u64,
+    });
+    response
+}
+
+/// Query parameters of `/admin/requests`.
+#[derive(Debug, Deserialize)]
+pub struct RecentRequestsQuery {
+    pub limit: Option<usize>,
+}
+
+/// Response of `/admin/requests`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentRequestsResponse {
+    pub capacity: usize,
+    /// Newest first
+    pub requests: Vec<RequestLogEntry>,
+}
+
+/// Latest requests, newest first. Requires the admin token.
+pub async fn recent_requests(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<RecentRequestsQuery>,
+) -> ApiResponse<RecentRequestsResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    ctx.ok(RecentRequestsResponse {
+        capacity: state.request_log.capacity(),
+        requests: state.request_log.recent(query.limit.unwrap_or(usize::MAX)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> RequestLogEntry {
+        RequestLogEntry {
+            request_id: None,
+            timestamp_ms: 0,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            payload_hash: None,
+            status: 200,
+            latency_ms: 1,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_latest() {
+        let log = RequestLog::new(2);
+        log.record(entry("/a"));
+        log.record(entry("/b"));
+        log.record(entry("/c"));
+        let paths: Vec<String> = log.recent(10).into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, vec!["/c", "/b"]);
+        assert_eq!(log.recent(1).len(), 1);
+    }
+
+    #[test]
+    fn test_masked_payload_hash() {
+        assert_eq!(masked_payload_hash("salt", b""), None);
+        let hash = masked_payload_hash("salt", b"{\"payload\":1}").unwrap();
+        assert_eq!(hash, masked_payload_hash("salt", b"{\"payload\":1}").unwrap());
+        assert_ne!(Some(hash), masked_payload_hash("other", b"{\"payload\":1}"));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_records_requests() {
+        let mut state = crate::test_app_state();
+        state.admin_token = Some("secret".to_string());
+        let state = Arc::new(state);
+        let app = axum::Router::new()
+            .route("/echo", axum::routing::post(|body: String| async move { body }))
+            .route("/admin/requests", axum::routing::get(recent_requests))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), record_request))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = reqwest::Client::new();
+        let echoed = client
+            .post(format!("http://{}/echo?x=1", addr))
+            .header(REQUEST_ID_HEADER, "req-1")
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(echoed.text().await.unwrap(), "hello");
+
+        let unauthorized = client.get(format!("http://{}/admin/requests", addr)).send().await.unwrap();
+        assert_eq!(unauthorized.status().as_u16(), 401);
+
+        let entries = state.request_log.recent(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].status, 401);
+        assert_eq!(entries[1].path, "/echo");
+        assert_eq!(entries[1].request_id.as_deref(), Some("req-1"));
+        assert_eq!(entries[1].payload_hash, masked_payload_hash(state.id_mask_salt(), b"hello"));
+    }
+}
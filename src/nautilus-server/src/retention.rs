@@ -0,0 +1,204 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retention of finished jobs. A job record keeps its task result, with the receipt blob ID
+//! and any raw output, in memory until the cleanup drops it: every
+//! `JOB_CLEANUP_INTERVAL_SECS`, and on demand with `POST /admin/retention`, finished jobs
+//! older than `JOB_RETENTION_SECS` are dropped, then the oldest ones until the store is within
+//! `JOB_RETENTION_MAX_COUNT` jobs and `JOB_RETENTION_MAX_BYTES`. Queued and running jobs are
+//! always kept. `GET /admin/retention` reports the policy and the last cleanup.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+/// Default age after which finished jobs are dropped.
+pub const DEFAULT_JOB_RETENTION_SECS: u64 = 60 * 60;
+/// Default number of jobs kept.
+pub const DEFAULT_JOB_RETENTION_MAX_COUNT: usize = 10_000;
+/// Default size of the job records kept.
+pub const DEFAULT_JOB_RETENTION_MAX_BYTES: u64 = 256 * 1024 * 1024;
+/// Default interval between cleanups.
+pub const DEFAULT_JOB_CLEANUP_INTERVAL_SECS: u64 = 60;
+
+/// Limits on the finished jobs kept. `None` leaves a dimension unlimited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age_secs: u64,
+    pub max_count: Option<usize>,
+    /// Size of the job records, serialized as JSON
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_secs: DEFAULT_JOB_RETENTION_SECS,
+            max_count: Some(DEFAULT_JOB_RETENTION_MAX_COUNT),
+            max_bytes: Some(DEFAULT_JOB_RETENTION_MAX_BYTES),
+        }
+    }
+}
+
+/// Outcome of one cleanup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub at_ms: u64,
+    /// Jobs dropped for being older than `max_age_secs`
+    pub expired: usize,
+    /// Jobs dropped to get within `max_count`
+    pub over_count: usize,
+    /// Jobs dropped to get within `max_bytes`
+    pub over_bytes: usize,
+    /// Jobs kept, finished or not
+    pub retained: usize,
+    pub retained_bytes: u64,
+}
+
+impl CleanupReport {
+    pub fn removed(&self) -> usize {
+        self.expired + self.over_count + self.over_bytes
+    }
+}
+
+/// Cleanup totals since boot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionStats {
+    pub runs: u64,
+    pub expired: u64,
+    pub over_count: u64,
+    pub over_bytes: u64,
+    pub last_cleanup: Option<CleanupReport>,
+}
+
+impl RetentionStats {
+    pub fn record(&mut self, report: &CleanupReport) {
+        self.runs += 1;
+        self.expired += report.expired as u64;
+        self.over_count += report.over_count as u64;
+        self.over_bytes += report.over_bytes as u64;
+        self.last_cleanup = Some(report.clone());
+    }
+}
+
+/// Apply the configured retention policy to the job store.
+pub fn cleanup_jobs(state: &AppState) -> CleanupReport {
+    let report = state.jobs.cleanup(&state.config.job_retention, current_timestamp_ms());
+    if report.removed() > 0 {
+        info!(
+            "Dropped {} finished jobs ({} expired, {} over count, {} over bytes), {} kept",
+            report.removed(),
+            report.expired,
+            report.over_count,
+            report.over_bytes,
+            report.retained
+        );
+    }
+    report
+}
+
+/// Run [cleanup_jobs] every `interval`. Jobs are held per instance, so every instance
+/// cleans up its own.
+pub fn spawn_job_cleanup(state: Arc<AppState>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            cleanup_jobs(&state);
+        }
+    });
+}
+
+/// Response of `GET /admin/retention`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionStatusResponse {
+    pub policy: RetentionPolicy,
+    /// Seconds between cleanups, 0 when only run on demand
+    pub cleanup_interval_secs: u64,
+    /// Jobs held, finished or not
+    pub jobs: usize,
+    pub jobs_bytes: u64,
+    pub stats: RetentionStats,
+}
+
+/// Retention policy, job store usage and cleanup totals. Requires the admin token.
+pub async fn retention_status(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<RetentionStatusResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let (jobs, jobs_bytes) = state.jobs.usage();
+    ctx.ok(RetentionStatusResponse {
+        policy: state.config.job_retention.clone(),
+        cleanup_interval_secs: state.config.job_cleanup_interval_secs,
+        jobs,
+        jobs_bytes,
+        stats: state.jobs.retention_stats(),
+    })
+}
+
+/// Run a cleanup now. Requires the admin token.
+pub async fn run_retention_cleanup(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResponse<CleanupReport> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let report = cleanup_jobs(&state);
+    state.audit_log.record(
+        "jobs_cleaned_up",
+        "jobs",
+        serde_json::json!({
+            "expired": report.expired,
+            "overCount": report.over_count,
+            "overBytes": report.over_bytes,
+            "retained": report.retained,
+        }),
+    );
+    ctx.ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    #[tokio::test]
+    async fn test_admin_retention() {
+        let mut state = test_app_state();
+        state.admin_token = Some("secret".to_string());
+        state.config.job_retention.max_count = Some(0);
+        let state = Arc::new(state);
+        let job = state.jobs.create("embedding_ingest");
+        state.jobs.complete(&job.id, Err(EnclaveError::Internal("boom".to_string())));
+
+        let response = run_retention_cleanup(RequestContext::new(None), State(state.clone()), HeaderMap::new()).await;
+        assert!(response.error.is_some());
+        assert!(state.jobs.get(&job.id).is_some());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let report = run_retention_cleanup(RequestContext::new(None), State(state.clone()), headers.clone())
+            .await
+            .data
+            .unwrap();
+        assert_eq!(report.over_count, 1);
+        assert!(state.jobs.get(&job.id).is_none());
+
+        let status = retention_status(RequestContext::new(None), State(state.clone()), headers).await.data.unwrap();
+        assert_eq!(status.jobs, 0);
+        assert_eq!(status.stats.runs, 1);
+        assert_eq!(status.stats.last_cleanup, Some(report));
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! NDJSON variant of `/retrieve_messages_by_blob_ids`, for requests with
+//! `Accept: application/x-ndjson`. The task reports the results of each blob as soon as it
+//! is decrypted, and each report is sent as a `blob` record, so clients can start on the
+//! first blobs before the slowest one finishes. A `done` record with the task outcome (or an
+//! `error` record) follows, and the signed stream summary closes the stream as a `summary`
+//! record: the Merkle root over every record line before it, see [crate::stream_signing].
+
+use crate::api_response::RequestContext;
+use crate::app::{
+    execute_retrieve_messages_by_blob_ids, with_attestation_ref, Admitted, MessageBlobRetrievalRequest, TaskResponse,
+};
+use crate::common::{accepts, current_timestamp_ms, IntentScope};
+use crate::crash_reports::inherit_request_id;
+use crate::key_manager::SigningKey;
+use crate::receipts::ReceiptContext;
+use crate::stream_signing::ChunkAccumulator;
+use crate::task_runner::{OutputLine, OutputStream, BLOB_RESULT_PREFIX};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::body::{Body, Bytes};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+/// Media type clients send in `Accept` to receive NDJSON records.
+pub const NDJSON_MEDIA_TYPE: &str = "application/x-ndjson";
+
+/// Returns true if the request asks for an NDJSON response body.
+pub fn wants_ndjson(headers: &HeaderMap) -> bool {
+    accepts(headers, NDJSON_MEDIA_TYPE)
+}
+
+/// One NDJSON line: `fields` with their record `type`, followed by a newline.
+fn record(kind: &str, fields: Value) -> String {
+    let mut fields = match fields {
+        Value::Object(fields) => fields,
+        other => serde_json::Map::from_iter([("value".to_string(), other)]),
+    };
+    fields.insert("type".to_string(), Value::String(kind.to_string()));
+    let line = serde_json::to_string(&fields).unwrap_or_else(|e| format!("{{\"type\":\"error\",\"message\":\"{}\"}}", e));
+    line + "\n"
+}
+
+fn error_record(message: &str) -> String {
+    record("error", json!({ "message": message }))
+}
+
+/// Task outcome without the per blob results, which were already streamed.
+fn done_record(response: TaskResponse) -> String {
+    let mut data = response.data;
+    if let Some(data) = data.as_object_mut() {
+        data.remove("results");
+    }
+    record(
+        "done",
+        json!({
+            "data": data,
+            "exit_code": response.exit_code,
+            "execution_time_ms": response.execution_time_ms,
+            "receipt_blob_id": response.receipt_blob_id,
+            "attestation_ref": response.attestation_ref,
+        }),
+    )
+}
+
+/// Streams a retrieval task's blob reports, then its outcome and the signed summary.
+struct RecordStream {
+    state: Arc<AppState>,
+    /// Key signing the summary, the `attestation_ref` of the `done` record is its own
+    key: Arc<SigningKey>,
+    output: UnboundedReceiver<OutputLine>,
+    task: Option<JoinHandle<Result<TaskResponse, EnclaveError>>>,
+    chunks: ChunkAccumulator,
+    /// Start of a blob report the task runner forwarded in pieces, being too long
+    partial: Option<String>,
+    finished: bool,
+}
+
+impl RecordStream {
+    fn new(
+        state: Arc<AppState>,
+        output: UnboundedReceiver<OutputLine>,
+        task: JoinHandle<Result<TaskResponse, EnclaveError>>,
+    ) -> Self {
+        Self {
+            key: state.keys.current(),
+            state,
+            output,
+            task: Some(task),
+            chunks: ChunkAccumulator::new(),
+            partial: None,
+            finished: false,
+        }
+    }
+
+    /// `blob` record of a task output line, `None` for other lines. Reports are single
+    /// line JSON, so the pieces of a split one are joined until they parse.
+    fn blob_record(&mut self, line: &str) -> Option<String> {
+        let text = match (line.strip_prefix(BLOB_RESULT_PREFIX), self.partial.take()) {
+            (Some(report), _) => report.to_string(),
+            (None, Some(partial)) => partial + line,
+            (None, None) => return None,
+        };
+        match serde_json::from_str::<Value>(&text) {
+            Ok(report @ Value::Object(_)) => Some(record("blob", report)),
+            _ => {
+                self.partial = Some(text);
+                None
+            }
+        }
+    }
+
+    /// Next record, or `None` once the summary has been sent.
+    async fn next_record(&mut self) -> Option<String> {
+        if self.finished {
+            return None;
+        }
+        // The output channel closes once the task is done with its sink
+        let line = loop {
+            match self.output.recv().await {
+                Some(line) if line.stream == OutputStream::Stdout => {
+                    if let Some(record) = self.blob_record(&line.line) {
+                        break record;
+                    }
+                }
+                Some(_) => {}
+                None => break self.outcome().await?,
+            }
+        };
+        self.chunks.push(line.as_bytes());
+        Some(line)
+    }
+
+    /// `done` or `error` record once the task finished, then the summary.
+    async fn outcome(&mut self) -> Option<String> {
+        if let Some(task) = self.task.take() {
+            return Some(match task.await {
+                Ok(Ok(response)) => done_record(with_attestation_ref(&self.state, &self.key, response)),
+                Ok(Err(e)) => error_record(&e.status_and_message().1),
+                Err(e) => error_record(&format!("Task panicked: {}", e)),
+            });
+        }
+        self.finished = true;
+        // A stream whose summary cannot be signed ends with an unsigned error record
+        let summary = self
+            .state
+            .key_usage
+            .acquire(IntentScope::StreamSummary)
+            .and_then(|()| self.chunks.finish(&*self.key, current_timestamp_ms()));
+        Some(match summary {
+            Ok(summary) => record("summary", serde_json::to_value(&summary).unwrap_or_default()),
+            Err(e) => error_record(&e.status_and_message().1),
+        })
+    }
+}
+
+/// `/retrieve_messages_by_blob_ids` streaming one record per blob. The task keeps running
+/// if the client disconnects. A full task queue is reported with 429 before the stream
+/// starts; later failures end the stream with an `error` record.
+pub fn retrieve_messages_ndjson(
+    ctx: &RequestContext,
+    state: Arc<AppState>,
+    payload: MessageBlobRetrievalRequest,
+    admitted: Admitted,
+) -> Response {
+    if let Err(e) = state.scheduler.check_capacity() {
+        return ctx.error::<()>(e).into_response();
+    }
+    let (sink, output) = unbounded_channel();
+    let task_state = state.clone();
+    let task = tokio::spawn(inherit_request_id(async move {
+        let receipt = ReceiptContext::start(&task_state, "retrieve_messages_by_blob_ids", &payload, payload.anchor_receipt);
+        let result = execute_retrieve_messages_by_blob_ids(&task_state, payload, Some(admitted), Some(sink)).await;
+        receipt.attach(&task_state, result).await
+    }));
+    let records = futures_util::stream::unfold(RecordStream::new(state, output, task), |mut stream| async move {
+        let record = stream.next_record().await?;
+        Some((Ok::<_, Infallible>(Bytes::from(record)), stream))
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, NDJSON_MEDIA_TYPE)
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(records))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    fn stdout(line: &str) -> OutputLine {
+        OutputLine {
+            stream: OutputStream::Stdout,
+            line: line.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_sends_blob_records_then_outcome_and_summary() {
+        let state = Arc::new(test_app_state());
+        let (sink, output) = unbounded_channel();
+        let task = tokio::spawn(async move {
+            sink.send(stdout("📥 Processing file: blob-1")).unwrap();
+            sink.send(stdout(r#"===BLOB_RESULT==={"walrus_blob_id":"blob-1","results":[]}"#)).unwrap();
+            // A report over the line limit arrives in pieces
+            sink.send(stdout(r#"===BLOB_RESULT==={"walrus_blob_id":"#)).unwrap();
+            sink.send(stdout(r#""blob-2","results":[]}"#)).unwrap();
+            sink.send(OutputLine {
+                stream: OutputStream::Stderr,
+                line: "===BLOB_RESULT==={}".to_string(),
+            })
+            .unwrap();
+            Ok(TaskResponse {
+                status: "success".to_string(),
+                data: json!({ "status": "success", "results": [1, 2], "successful_retrievals": 2 }),
+                stderr: String::new(),
+                exit_code: 0,
+                execution_time_ms: 10,
+                receipt_blob_id: None,
+                resource_usage: None,
+                timeline: None,
+                attestation_ref: None,
+                raw_output: None,
+                request_hash: None,
+            })
+        });
+        let mut stream = RecordStream::new(state.clone(), output, task);
+
+        let mut records = Vec::new();
+        while let Some(record) = stream.next_record().await {
+            assert!(record.ends_with('\n') && !record.trim_end().contains('\n'));
+            records.push(serde_json::from_str::<Value>(&record).unwrap());
+        }
+        let kinds: Vec<_> = records.iter().map(|r| r["type"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["blob", "blob", "done", "summary"]);
+        assert_eq!(records[1]["walrus_blob_id"], "blob-2");
+        assert_eq!(records[2]["data"]["successful_retrievals"], 2);
+        assert!(records[2]["data"].get("results").is_none());
+        assert!(records[2]["attestation_ref"].is_object());
+        // The summary covers every record before it and names its key
+        assert_eq!(records[3]["response"]["data"]["chunk_count"], 3);
+        assert_eq!(records[3]["key_id"], state.keys.current().key_id.as_str());
+    }
+
+    #[test]
+    fn test_wants_ndjson() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_ndjson(&headers));
+        headers.insert(axum::http::header::ACCEPT, "application/x-ndjson".parse().unwrap());
+        assert!(wants_ndjson(&headers));
+        assert!(!crate::common::wants_bcs(&headers));
+    }
+}
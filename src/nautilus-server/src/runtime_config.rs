@@ -0,0 +1,186 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Settings that can change without restarting the enclave. They start from the environment
+//! and are replaced on `POST /admin/config`, which validates the update and swaps the whole
+//! [RuntimeConfig] at once, so a request sees either the old or the new settings and never a
+//! mix. Tasks started after the swap get the new values. Secrets and everything else in
+//! [Config] stay as booted: the update only accepts the fields below and refuses any other.
+//! Updates are kept in memory and the environment applies again after a restart.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::config::Config;
+use crate::validation::{FieldError, FieldErrors, ValidJson, Validate};
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Largest batch size accepted, as many texts or vectors per upstream request.
+pub const MAX_BATCH_SIZE: u32 = 10_000;
+
+/// Current values of the settings that can be reloaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeConfig {
+    /// `EMBEDDING_BATCH_SIZE`
+    pub embedding_batch_size: u32,
+    /// `VECTOR_BATCH_SIZE`
+    pub vector_batch_size: u32,
+    /// `OLLAMA_MODEL`, also used to embed queries
+    pub ollama_model: String,
+}
+
+impl RuntimeConfig {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            embedding_batch_size: config.embedding_batch_size,
+            vector_batch_size: config.vector_batch_size,
+            ollama_model: config.ollama_model.clone(),
+        }
+    }
+}
+
+/// Payload of `POST /admin/config`. Unset fields keep their value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfigUpdate {
+    pub embedding_batch_size: Option<u32>,
+    pub vector_batch_size: Option<u32>,
+    pub ollama_model: Option<String>,
+}
+
+fn check_batch_size(size: &Option<u32>) -> Result<(), String> {
+    match size {
+        Some(size) if *size == 0 || *size > MAX_BATCH_SIZE => {
+            Err(format!("must be from 1 to {}, got {}", MAX_BATCH_SIZE, size))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Validate for RuntimeConfigUpdate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.check("embedding_batch_size", check_batch_size(&self.embedding_batch_size));
+        errors.check("vector_batch_size", check_batch_size(&self.vector_batch_size));
+        if let Some(model) = &self.ollama_model {
+            let valid = !model.is_empty() && !model.chars().any(|c| c.is_whitespace() || c.is_control());
+            if !valid {
+                errors.check("ollama_model", Err(format!("must be a model name without spaces, got {:?}", model)));
+            }
+        }
+        errors.into_vec()
+    }
+}
+
+impl RuntimeConfigUpdate {
+    /// `current` with the fields of the update applied.
+    pub fn apply(self, current: &RuntimeConfig) -> RuntimeConfig {
+        RuntimeConfig {
+            embedding_batch_size: self.embedding_batch_size.unwrap_or(current.embedding_batch_size),
+            vector_batch_size: self.vector_batch_size.unwrap_or(current.vector_batch_size),
+            ollama_model: self.ollama_model.unwrap_or_else(|| current.ollama_model.clone()),
+        }
+    }
+}
+
+/// Response of `POST /admin/config`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeConfigResponse {
+    pub previous: RuntimeConfig,
+    pub current: RuntimeConfig,
+}
+
+/// Validate and apply a runtime configuration update. Requires the admin token.
+pub async fn update_config(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ValidJson(update): ValidJson<RuntimeConfigUpdate>,
+) -> ApiResponse<RuntimeConfigResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    // Both swaps happen under the lock, so the embedding model always matches `ollama_model`
+    let (previous, current) = {
+        let _guard = state.config_updates.lock().unwrap();
+        let previous = RuntimeConfig::clone(&state.runtime_config.load());
+        let current = update.apply(&previous);
+        if current.ollama_model != previous.ollama_model {
+            state
+                .embeddings
+                .store(Arc::new(state.embeddings.load().with_ollama_model(&current.ollama_model)));
+        }
+        state.runtime_config.store(Arc::new(current.clone()));
+        (previous, current)
+    };
+    info!("Runtime configuration updated: {:?}", current);
+    state.audit_log.record(
+        "config_updated",
+        "runtime",
+        serde_json::json!({ "previous": previous, "current": current }),
+    );
+    ctx.ok(RuntimeConfigResponse { previous, current })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_validation_and_apply() {
+        let current = RuntimeConfig::from_config(&crate::config::test_config());
+        let update: RuntimeConfigUpdate = serde_json::from_str(r#"{ "embedding_batch_size": 32 }"#).unwrap();
+        assert!(update.field_errors().is_empty());
+        let updated = update.apply(&current);
+        assert_eq!(updated.embedding_batch_size, 32);
+        assert_eq!(updated.vector_batch_size, current.vector_batch_size);
+        assert_eq!(updated.ollama_model, current.ollama_model);
+
+        // Secrets and boot settings cannot be updated
+        assert!(serde_json::from_str::<RuntimeConfigUpdate>(r#"{ "sui_secret_key": "x" }"#).is_err());
+
+        let invalid = RuntimeConfigUpdate {
+            embedding_batch_size: Some(0),
+            vector_batch_size: Some(MAX_BATCH_SIZE + 1),
+            ollama_model: Some("nomic embed".to_string()),
+        };
+        let fields: Vec<String> = invalid.field_errors().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["embedding_batch_size", "vector_batch_size", "ollama_model"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_updates_keep_embeddings_in_sync() {
+        use crate::embeddings::{EmbeddingProvider, OllamaProvider, Provider};
+
+        let mut state = crate::test_app_state();
+        state.admin_token = Some("secret".to_string());
+        state.embeddings = arc_swap::ArcSwap::from_pointee(EmbeddingProvider::Ollama(
+            OllamaProvider::new("http://127.0.0.1:11434", "nomic-embed-text").unwrap(),
+        ));
+        let state = Arc::new(state);
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+
+        let updates = (0..32).map(|i| {
+            let (state, headers) = (state.clone(), headers.clone());
+            tokio::spawn(async move {
+                let update = RuntimeConfigUpdate {
+                    embedding_batch_size: None,
+                    vector_batch_size: None,
+                    ollama_model: Some(format!("model-{}", i % 4)),
+                };
+                update_config(RequestContext::new(None), State(state), headers, ValidJson(update)).await
+            })
+        });
+        for update in updates.collect::<Vec<_>>() {
+            assert!(update.await.unwrap().error.is_none());
+        }
+        let model = state.runtime_config.load().ollama_model.clone();
+        assert_eq!(state.embeddings.load().describe(), format!("ollama/{}", model));
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Crash tracking for Node.js task processes. Consecutive crashes delay the next spawn
+//! with exponential backoff, and too many crashes within a window mark the task runtime
+//! unhealthy, which `/readyz` reports together with the last crash's stderr.
+
+use crate::common::current_timestamp_ms;
+use crate::dependency_allowlist::DependencyStatus;
+use crate::task_runner::TaskOutput;
+use crate::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CRASH_LOOP_THRESHOLD: usize = 5;
+pub const DEFAULT_CRASH_LOOP_WINDOW_SECS: u64 = 60;
+pub const DEFAULT_CRASH_BACKOFF_MAX_SECS: u64 = 30;
+
+/// Bytes of stderr kept from the last crash.
+const STDERR_EXCERPT_BYTES: usize = 2048;
+
+/// When crashes count as a crash loop and how long to back off between spawns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashLoopPolicy {
+    /// Crashes within `window` that mark the runtime unhealthy
+    pub threshold: usize,
+    pub window: Duration,
+    /// Delay after the first crash, doubled for each further consecutive crash
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for CrashLoopPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_CRASH_LOOP_THRESHOLD,
+            window: Duration::from_secs(DEFAULT_CRASH_LOOP_WINDOW_SECS),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(DEFAULT_CRASH_BACKOFF_MAX_SECS),
+        }
+    }
+}
+
+impl CrashLoopPolicy {
+    /// Backoff after `consecutive` crashes in a row.
+    pub fn backoff(&self, consecutive: u32) -> Duration {
+        if consecutive == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 2u32.saturating_pow(consecutive - 1);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// A task process that exited abnormally.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaskCrash {
+    pub operation: String,
+    pub exit_code: i32,
+    pub crashed_at_ms: u64,
+    /// Tail of the process stderr
+    pub stderr_excerpt: String,
+}
+
+/// Health of the task runtime as reported by `/readyz`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RuntimeStatus {
+    pub healthy: bool,
+    /// Crashes within the crash loop window
+    pub recent_crashes: usize,
+    pub consecutive_crashes: u32,
+    pub last_crash: Option<TaskCrash>,
+}
+
+#[derive(Debug, Default)]
+struct CrashLog {
+    recent: VecDeque<Instant>,
+    consecutive: u32,
+    last_crash_at: Option<Instant>,
+    last_crash: Option<TaskCrash>,
+}
+
+impl CrashLog {
+    fn prune(&mut self, window: Duration, now: Instant) {
+        while self.recent.front().is_some_and(|at| now.duration_since(*at) > window) {
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// Shared crash tracker for task processes.
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeHealth {
+    policy: CrashLoopPolicy,
+    log: Arc<Mutex<CrashLog>>,
+}
+
+/// Whether a finished task process crashed rather than completing, with or without a
+/// reported failure. Processes killed by a signal report exit code -1.
+pub fn is_crash(output: &TaskOutput) -> bool {
+    use crate::task_runner::{TASK_PROTOCOL_FRAME, TASK_RESULT_START};
+    let stdout = output.stdout_text();
+    output.exit_code != 0 && !stdout.contains(TASK_PROTOCOL_FRAME) && !stdout.contains(TASK_RESULT_START)
+}
+
+/// Last `max_bytes` of `stderr`, cut at a character boundary.
+fn stderr_excerpt(stderr: &str, max_bytes: usize) -> String {
+    let mut start = stderr.len().saturating_sub(max_bytes);
+    while !stderr.is_char_boundary(start) {
+        start += 1;
+    }
+    stderr[start..].trim().to_string()
+}
+
+impl RuntimeHealth {
+    pub fn new(policy: CrashLoopPolicy) -> Self {
+        Self {
+            policy,
+            log: Arc::default(),
+        }
+    }
+
+    /// Record the outcome of a task process.
+    pub fn record(&self, operation: &str, output: &TaskOutput) {
+        if is_crash(output) {
+            self.record_crash(operation, output.exit_code, &output.stderr_text());
+        } else {
+            self.log.lock().unwrap().consecutive = 0;
+        }
+    }
+
+    pub fn record_crash(&self, operation: &str, exit_code: i32, stderr: &str) {
+        let now = Instant::now();
+        let mut log = self.log.lock().unwrap();
+        log.prune(self.policy.window, now);
+        log.recent.push_back(now);
+        log.consecutive = log.consecutive.saturating_add(1);
+        log.last_crash_at = Some(now);
+        log.last_crash = Some(TaskCrash {
+            operation: operation.to_string(),
+            exit_code,
+            crashed_at_ms: current_timestamp_ms(),
+            stderr_excerpt: stderr_excerpt(stderr, STDERR_EXCERPT_BYTES),
+        });
+    }
+
+    /// Time to wait before spawning the next task process, zero unless the last
+    /// processes crashed.
+    pub fn spawn_delay(&self) -> Duration {
+        let log = self.log.lock().unwrap();
+        match log.last_crash_at {
+            Some(at) => self.policy.backoff(log.consecutive).saturating_sub(at.elapsed()),
+            None => Duration::ZERO,
+        }
+    }
+
+    pub fn status(&self) -> RuntimeStatus {
+        let mut log = self.log.lock().unwrap();
+        log.prune(self.policy.window, Instant::now());
+        RuntimeStatus {
+            healthy: log.recent.len() < self.policy.threshold,
+            recent_crashes: log.recent.len(),
+            consecutive_crashes: log.consecutive,
+            last_crash: log.last_crash.clone(),
+        }
+    }
+}
+
+/// Response of `/readyz`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub task_runtime: RuntimeStatus,
+    pub dependencies: DependencyStatus,
+}
+
+/// Readiness probe: 503 while the task runtime is crash looping or task dependencies
+/// failed verification.
+pub async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessResponse>) {
+    let task_runtime = state.runtime_health.status();
+    let dependencies = state.dependency_status.clone();
+    let ready = task_runtime.healthy && dependencies.ensure_allowed().is_ok();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadinessResponse {
+            ready,
+            task_runtime,
+            dependencies,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(exit_code: i32, stdout: &str, stderr: &str) -> TaskOutput {
+        TaskOutput {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+            exit_code,
+            execution_time_ms: 1,
+            resource_usage: None,
+            stdout_stats: Default::default(),
+            stderr_stats: Default::default(),
+            retried_attempts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_is_crash() {
+        assert!(!is_crash(&output(0, "", "")));
+        assert!(is_crash(&output(-1, "", "")));
+        assert!(is_crash(&output(134, "starting", "FATAL ERROR")));
+        let reported = "===TASK_RESULT_START===\n{\"status\":\"failed\"}\n===TASK_RESULT_END===";
+        assert!(!is_crash(&output(1, reported, "")));
+        let framed = "===TASK_PROTOCOL_RESPONSE==={\"version\":1,\"result\":{\"status\":\"failed\"}}";
+        assert!(!is_crash(&output(1, framed, "")));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = CrashLoopPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::ZERO);
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(40), policy.max_backoff);
+    }
+
+    #[test]
+    fn test_crash_loop_detection() {
+        let health = RuntimeHealth::new(CrashLoopPolicy {
+            threshold: 3,
+            ..Default::default()
+        });
+        assert!(health.status().healthy);
+        assert_eq!(health.spawn_delay(), Duration::ZERO);
+
+        health.record("process_data", &output(134, "", "JavaScript heap out of memory"));
+        health.record("process_data", &output(-1, "", "killed"));
+        assert!(health.status().healthy);
+        assert!(health.spawn_delay() > Duration::ZERO);
+
+        health.record("embedding_ingest", &output(1, "", "Error: Cannot find module 'axios'"));
+        let status = health.status();
+        assert!(!status.healthy);
+        assert_eq!(status.recent_crashes, 3);
+        let last = status.last_crash.unwrap();
+        assert_eq!(last.operation, "embedding_ingest");
+        assert_eq!(last.stderr_excerpt, "Error: Cannot find module 'axios'");
+
+        // A clean run resets the backoff but not the crash loop window
+        health.record("process_data", &output(0, "", ""));
+        assert_eq!(health.status().consecutive_crashes, 0);
+        assert_eq!(health.spawn_delay(), Duration::ZERO);
+        assert!(!health.status().healthy);
+    }
+
+    #[test]
+    fn test_stderr_excerpt() {
+        assert_eq!(stderr_excerpt("abcdef", 3), "def");
+        assert_eq!(stderr_excerpt("aé", 1), "");
+        assert_eq!(stderr_excerpt(" short \n", 100), "short");
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
+
+/// Default number of Node tasks allowed to run at the same time.
+pub const DEFAULT_MAX_CONCURRENT_TASKS: usize = 4;
+/// Default time a waiting request needs to be promoted by one priority level.
+pub const DEFAULT_PRIORITY_AGING_SECS: u64 = 30;
+/// Default number of requests allowed to wait for a slot.
+pub const DEFAULT_MAX_QUEUED_TASKS: usize = 32;
+/// Default time a request may wait for a slot before it is rejected.
+pub const DEFAULT_TASK_QUEUE_TIMEOUT_SECS: u64 = 120;
+
+/// Priority of a task request. Higher priorities are dispatched first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low = 0,
+    #[default]
+    Normal = 1,
+    High = 2,
+}
+
+struct Waiter {
+    seq: u64,
+    priority: Priority,
+    enqueued_at: Instant,
+    tx: oneshot::Sender<SchedulerPermit>,
+}
+
+impl Waiter {
+    /// Base priority plus one level per `aging` period spent waiting, so low
+    /// priority requests cannot be starved by a steady stream of high priority ones.
+    fn effective_priority(&self, now: Instant, aging: Duration) -> u64 {
+        let waited = now.duration_since(self.enqueued_at).as_millis();
+        let promotions = match aging.as_millis() {
+            0 => 0,
+            aging_ms => (waited / aging_ms) as u64,
+        };
+        self.priority as u64 + promotions
+    }
+}
+
+/// Queue entry of a pending [TaskScheduler::acquire], removed when the request stops
+/// waiting, including when its future is dropped before a slot frees up.
+struct QueuedWaiter<'a> {
+    scheduler: &'a TaskScheduler,
+    seq: u64,
+}
+
+impl QueuedWaiter<'_> {
+    /// Remove the entry, returning whether it was still queued.
+    fn remove(&self) -> bool {
+        let mut state = self.scheduler.state.lock().unwrap();
+        match state.waiters.iter().position(|waiter| waiter.seq == self.seq) {
+            Some(index) => {
+                state.waiters.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for QueuedWaiter<'_> {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+struct SchedulerState {
+    available: usize,
+    waiters: Vec<Waiter>,
+    next_seq: u64,
+}
+
+/// Dispatches task executions to a fixed number of slots, in priority order. At most
+/// `max_queued` requests wait for a slot, each for at most `max_wait`.
+pub struct TaskScheduler {
+    state: Mutex<SchedulerState>,
+    max_concurrent: usize,
+    aging: Duration,
+    max_queued: usize,
+    max_wait: Duration,
+}
+
+/// A running slot. The slot is handed to the next waiter when dropped.
+pub struct SchedulerPermit {
+    scheduler: Option<Arc<TaskScheduler>>,
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+impl TaskScheduler {
+    pub fn new(max_concurrent: usize, aging: Duration) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            state: Mutex::new(SchedulerState {
+                available: max_concurrent,
+                waiters: Vec::new(),
+                next_seq: 0,
+            }),
+            max_concurrent,
+            aging,
+            max_queued: DEFAULT_MAX_QUEUED_TASKS,
+            max_wait: Duration::from_secs(DEFAULT_TASK_QUEUE_TIMEOUT_SECS),
+        }
+    }
+
+    /// Bound the number of waiting requests and how long each may wait.
+    pub fn with_queue_limits(mut self, max_queued: usize, max_wait: Duration) -> Self {
+        self.max_queued = max_queued;
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Maximum number of concurrently running tasks.
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Number of requests waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiters.len()
+    }
+
+    /// Seconds a rejected request is told to wait before retrying.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.max_wait.as_secs().max(1)
+    }
+
+    fn overloaded(&self, message: String) -> EnclaveError {
+        EnclaveError::Overloaded {
+            message,
+            retry_after_secs: self.retry_after_secs(),
+        }
+    }
+
+    /// Fail fast when a request arriving now would be rejected because the queue is full.
+    pub fn check_capacity(&self) -> Result<(), EnclaveError> {
+        let state = self.state.lock().unwrap();
+        if state.available == 0 && state.waiters.len() >= self.max_queued {
+            return Err(self.overloaded(format!("Task queue is full ({} waiting)", state.waiters.len())));
+        }
+        Ok(())
+    }
+
+    /// Wait for a free slot. Requests are served by effective priority, then in arrival order.
+    /// Fails with [EnclaveError::Overloaded] when the queue is full or no slot frees up
+    /// within the queue timeout.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Result<SchedulerPermit, EnclaveError> {
+        let (queued, mut rx) = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return Ok(SchedulerPermit {
+                    scheduler: Some(self.clone()),
+                });
+            }
+            if state.waiters.len() >= self.max_queued {
+                return Err(self.overloaded(format!("Task queue is full ({} waiting)", state.waiters.len())));
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter {
+                seq,
+                priority,
+                enqueued_at: Instant::now(),
+                tx,
+            });
+            (QueuedWaiter { scheduler: self, seq }, rx)
+        };
+        // The sender is only dropped together with the scheduler, which outlives us.
+        if let Ok(permit) = tokio::time::timeout(self.max_wait, &mut rx).await {
+            return Ok(permit.expect("scheduler dropped while waiting"));
+        }
+        if queued.remove() {
+            return Err(self.overloaded(format!(
+                "No task slot became free within {} seconds",
+                self.max_wait.as_secs()
+            )));
+        }
+        // A slot was handed over as the wait timed out
+        Ok(rx.try_recv().expect("waiter was dispatched"))
+    }
+
+    fn release(self: Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while !state.waiters.is_empty() {
+            let next = state
+                .waiters
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| {
+                    a.effective_priority(now, self.aging)
+                        .cmp(&b.effective_priority(now, self.aging))
+                        .then(b.seq.cmp(&a.seq))
+                })
+                .map(|(i, _)| i)
+                .expect("waiters is not empty");
+            let waiter = state.waiters.swap_remove(next);
+            let permit = SchedulerPermit {
+                scheduler: Some(self.clone()),
+            };
+            match waiter.tx.send(permit) {
+                Ok(()) => return,
+                // The waiter went away (e.g. client disconnected), disarm and try the next one.
+                Err(mut permit) => {
+                    permit.scheduler = None;
+                }
+            }
+        }
+        state.available += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spawn a task that records its priority once it gets a slot, and wait until it is queued.
+    async fn spawn_waiter(
+        scheduler: &Arc<TaskScheduler>,
+        priority: Priority,
+        order: &Arc<Mutex<Vec<Priority>>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let before = scheduler.queued();
+        let handle = tokio::spawn({
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            async move {
+                let _permit = scheduler.acquire(priority).await.unwrap();
+                order.lock().unwrap().push(priority);
+            }
+        });
+        while scheduler.queued() == before {
+            tokio::task::yield_now().await;
+        }
+        handle
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_dispatched_first() {
+        let scheduler = Arc::new(TaskScheduler::new(1, Duration::from_secs(60)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let permit = scheduler.acquire(Priority::Normal).await.unwrap();
+        let low = spawn_waiter(&scheduler, Priority::Low, &order).await;
+        let high = spawn_waiter(&scheduler, Priority::High, &order).await;
+
+        drop(permit);
+        low.await.unwrap();
+        high.await.unwrap();
+        assert_eq!(*order.lock().unwrap(), vec![Priority::High, Priority::Low]);
+    }
+
+    #[tokio::test]
+    async fn test_aging_prevents_starvation() {
+        let scheduler = Arc::new(TaskScheduler::new(1, Duration::from_millis(10)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let permit = scheduler.acquire(Priority::Normal).await.unwrap();
+        let low = spawn_waiter(&scheduler, Priority::Low, &order).await;
+        // Waiting long enough promotes the low priority request past high.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let high = spawn_waiter(&scheduler, Priority::High, &order).await;
+
+        drop(permit);
+        low.await.unwrap();
+        high.await.unwrap();
+        assert_eq!(*order.lock().unwrap(), vec![Priority::Low, Priority::High]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_does_not_leak_slot() {
+        let scheduler = Arc::new(TaskScheduler::new(1, Duration::from_secs(60)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let permit = scheduler.acquire(Priority::Normal).await.unwrap();
+
+        let waiter = spawn_waiter(&scheduler, Priority::High, &order).await;
+        waiter.abort();
+        let _ = waiter.await;
+
+        drop(permit);
+        // The slot must be available again.
+        let _permit = tokio::time::timeout(Duration::from_secs(1), scheduler.acquire(Priority::Low))
+            .await
+            .expect("slot should be released")
+            .unwrap();
+        assert!(order.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_queue() {
+        let scheduler = Arc::new(
+            TaskScheduler::new(1, Duration::from_secs(60)).with_queue_limits(1, Duration::from_secs(60)),
+        );
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let _permit = scheduler.acquire(Priority::Normal).await.unwrap();
+
+        let waiter = spawn_waiter(&scheduler, Priority::Normal, &order).await;
+        assert!(scheduler.check_capacity().is_err());
+        waiter.abort();
+        let _ = waiter.await;
+
+        // The aborted request gives its place in the queue back right away
+        assert_eq!(scheduler.queued(), 0);
+        assert!(scheduler.check_capacity().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queue_limits() {
+        let scheduler = Arc::new(
+            TaskScheduler::new(1, Duration::from_secs(60)).with_queue_limits(1, Duration::from_millis(50)),
+        );
+        let permit = scheduler.acquire(Priority::Normal).await.unwrap();
+        assert!(scheduler.check_capacity().is_ok());
+
+        let waiter = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire(Priority::Normal).await.map(drop) }
+        });
+        while scheduler.queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+        // The queue is full, so further requests are rejected right away
+        assert!(scheduler.check_capacity().is_err());
+        let rejected = scheduler.acquire(Priority::High).await;
+        assert!(matches!(rejected, Err(EnclaveError::Overloaded { retry_after_secs: 1, .. })));
+
+        // The queued request gives up once the queue timeout passes
+        assert!(matches!(waiter.await.unwrap(), Err(EnclaveError::Overloaded { .. })));
+        assert_eq!(scheduler.queued(), 0);
+        drop(permit);
+        assert!(scheduler.acquire(Priority::Low).await.is_ok());
+    }
+}
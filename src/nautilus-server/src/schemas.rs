@@ -0,0 +1,196 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON Schemas of the request and result payloads of each operation, served at
+//! `/schemas/:operation` so task authors and API consumers share one definition of the
+//! payload shapes. Like `/openapi.json` they are derived with utoipa, from the types the
+//! server parses requests into and checks task results against ([crate::task_result]).
+//! [SCHEMA_VERSION] is bumped whenever a payload changes incompatibly.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::app::{
+    BlobFileIdPair, EmbeddingIngestRequest, FilteredRetrievalRequest, MessageBlobRetrievalRequest, MessageFilters,
+    TaskRequest,
+};
+use crate::collections::SearchQuality;
+use crate::common::{
+    AttestationMode, EmbeddingIngestProcessDataRequest, FilteredRetrievalProcessDataRequest,
+    MessageBlobRetrievalProcessDataRequest, TaskProcessDataRequest,
+};
+use crate::scheduler::Priority;
+use crate::task_env::Operation;
+use crate::task_result::{
+    EmbeddingResult, FilteredRetrievalResult, PointId, RequestedPair, ResultStatus, RetrievalResult, RetrievedMessage,
+    ScoredMessage,
+};
+use crate::EnclaveError;
+use axum::extract::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use utoipa::OpenApi;
+
+/// Version of the published payload schemas.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_REF_PREFIX: &str = "#/components/schemas/";
+
+#[derive(OpenApi)]
+#[openapi(components(schemas(
+    TaskProcessDataRequest,
+    EmbeddingIngestProcessDataRequest,
+    MessageBlobRetrievalProcessDataRequest,
+    FilteredRetrievalProcessDataRequest,
+    TaskRequest,
+    EmbeddingIngestRequest,
+    MessageBlobRetrievalRequest,
+    BlobFileIdPair,
+    FilteredRetrievalRequest,
+    MessageFilters,
+    Priority,
+    SearchQuality,
+    AttestationMode,
+    ResultStatus,
+    EmbeddingResult,
+    RetrievalResult,
+    RequestedPair,
+    RetrievedMessage,
+    FilteredRetrievalResult,
+    ScoredMessage,
+    PointId,
+)))]
+struct PayloadSchemas;
+
+/// Schema names of the request body and task result of `operation`. Results of
+/// `process_data` are up to the task and have none.
+fn payload_schemas(operation: Operation) -> (&'static str, Option<&'static str>) {
+    match operation {
+        Operation::ProcessData => ("TaskProcessDataRequest", None),
+        Operation::EmbeddingIngest => ("EmbeddingIngestProcessDataRequest", Some("EmbeddingResult")),
+        Operation::RetrieveMessagesByBlobIds => ("MessageBlobRetrievalProcessDataRequest", Some("RetrievalResult")),
+        Operation::RetrieveMessagesFiltered => ("FilteredRetrievalProcessDataRequest", Some("FilteredRetrievalResult")),
+    }
+}
+
+/// Response of `/schemas/:operation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationSchemas {
+    pub version: u32,
+    pub operation: String,
+    /// Schema of the request body
+    pub request: Value,
+    /// Schema of the task result, the `data` of the signed response; any JSON for
+    /// `process_data`
+    pub result: Value,
+    /// Schemas referenced from `request` and `result` as `#/components/schemas/<name>`
+    pub components: Value,
+}
+
+impl OperationSchemas {
+    pub fn of(operation: Operation) -> Self {
+        let doc = serde_json::to_value(PayloadSchemas::openapi()).expect("schemas serialize to JSON");
+        let schemas = doc["components"]["schemas"].as_object().cloned().unwrap_or_default();
+        let reference = |name: &str| json!({ "$ref": format!("{}{}", SCHEMA_REF_PREFIX, name) });
+
+        let (request, result) = payload_schemas(operation);
+        let request = reference(request);
+        let result = result.map_or_else(|| json!({}), reference);
+        let mut referenced = Map::new();
+        collect_references(&schemas, &request, &mut referenced);
+        collect_references(&schemas, &result, &mut referenced);
+        Self {
+            version: SCHEMA_VERSION,
+            operation: operation.name().to_string(),
+            request,
+            result,
+            components: json!({ "schemas": referenced }),
+        }
+    }
+}
+
+/// Add the schemas `value` references to `referenced`, and those they reference in turn.
+fn collect_references(schemas: &Map<String, Value>, value: &Value, referenced: &mut Map<String, Value>) {
+    match value {
+        Value::Object(object) => {
+            let name = object
+                .get("$ref")
+                .and_then(Value::as_str)
+                .and_then(|reference| reference.strip_prefix(SCHEMA_REF_PREFIX));
+            if let Some((name, schema)) = name.and_then(|name| schemas.get_key_value(name)) {
+                if !referenced.contains_key(name) {
+                    referenced.insert(name.clone(), schema.clone());
+                    collect_references(schemas, schema, referenced);
+                }
+            }
+            for child in object.values() {
+                collect_references(schemas, child, referenced);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_references(schemas, item, referenced);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Response of `/schemas`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaIndex {
+    pub version: u32,
+    /// Operations with schemas at `/schemas/:operation`
+    pub operations: Vec<String>,
+}
+
+/// Operations whose payload schemas are published.
+pub async fn schema_index(ctx: RequestContext) -> ApiResponse<SchemaIndex> {
+    ctx.ok(SchemaIndex {
+        version: SCHEMA_VERSION,
+        operations: Operation::ALL.iter().map(|operation| operation.name().to_string()).collect(),
+    })
+}
+
+/// Request and result schemas of one operation.
+pub async fn operation_schemas(ctx: RequestContext, Path(operation): Path<String>) -> ApiResponse<OperationSchemas> {
+    match Operation::from_name(&operation) {
+        Some(operation) => ctx.ok(OperationSchemas::of(operation)),
+        None => ctx.error(EnclaveError::NotFound(format!("Unknown operation: {}", operation))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_schemas() {
+        for operation in Operation::ALL {
+            let schemas = OperationSchemas::of(operation);
+            assert_eq!(schemas.version, SCHEMA_VERSION);
+            let components = schemas.components["schemas"].as_object().unwrap();
+            // Every reference resolves within the document
+            let text = serde_json::to_string(&schemas).unwrap();
+            for reference in text.split(SCHEMA_REF_PREFIX).skip(1) {
+                let name = &reference[..reference.find('"').unwrap()];
+                assert!(components.contains_key(name), "{} misses {}", operation.name(), name);
+            }
+        }
+
+        let ingest = OperationSchemas::of(Operation::EmbeddingIngest);
+        let components = &ingest.components["schemas"];
+        assert!(components["EmbeddingIngestRequest"]["properties"]["walrusBlobId"].is_object());
+        assert!(components["EmbeddingResult"]["properties"]["processedCount"].is_object());
+        assert!(components["ResultStatus"].is_object());
+        assert!(components["RetrievalResult"].is_null());
+
+        let process = OperationSchemas::of(Operation::ProcessData);
+        assert_eq!(process.result, json!({}));
+        assert!(process.components["schemas"]["TaskRequest"]["properties"]["input"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_operation() {
+        let response = operation_schemas(RequestContext::new(None), Path("scoring".to_string())).await;
+        assert_eq!(axum::response::IntoResponse::into_response(response).status(), axum::http::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Seal policy pre-check. Decryption is authorized by `seal_approve` of `MOVE_PACKAGE_ID`,
+//! which the key servers dry run for the Node task once it has fetched the encrypted blob,
+//! so a request for a policy the enclave cannot decrypt under used to cost a task process
+//! and a Walrus read before failing. [precheck_policies] reads the policy objects from Sui
+//! first, through the cached [SuiClient], and refuses with 403:
+//!
+//! - a policy object that does not exist, or is no Move object;
+//! - a policy listing the addresses allowed to decrypt, like Seal's allowlist pattern,
+//!   without the enclave's `SUI_SECRET_KEY` address.
+//!
+//! Anything else is left to `seal_approve`: the check only refuses what would certainly
+//! fail. A Sui read that fails does not refuse the request either, the task still runs
+//! the authoritative check. `SEAL_POLICY_PRECHECK=false` turns the pre-check off.
+
+use crate::sui::{parse_address, SuiAccount, SuiAddress, SuiClient, SuiObject};
+use crate::AppState;
+use crate::EnclaveError;
+use std::collections::BTreeSet;
+use tracing::warn;
+
+/// Fields of a policy object holding the addresses allowed to decrypt under it.
+const ALLOWLIST_FIELDS: [&str; 2] = ["list", "allowlist"];
+
+/// Addresses listed by an allowlist object, or None when it has no list. Entries that are
+/// no valid address are skipped.
+pub fn allowlist(object: &SuiObject) -> Option<Vec<SuiAddress>> {
+    let list = ALLOWLIST_FIELDS.iter().find_map(|field| object.fields[*field].as_array())?;
+    Some(list.iter().filter_map(|address| parse_address(address.as_str()?).ok()).collect())
+}
+
+/// Whether `enclave` may decrypt under `policy`, as far as its object shows.
+pub fn check_policy(policy_id: &str, policy: Option<&SuiObject>, enclave: Option<&SuiAddress>) -> Result<(), EnclaveError> {
+    let Some(policy) = policy else {
+        return Err(EnclaveError::Forbidden(format!("Seal policy {} does not exist", policy_id)));
+    };
+    if !policy.fields.is_object() {
+        return Err(EnclaveError::Forbidden(format!("{} is not a Seal policy object", policy_id)));
+    }
+    if let (Some(allowlist), Some(enclave)) = (allowlist(policy), enclave) {
+        if !allowlist.contains(enclave) {
+            return Err(EnclaveError::Forbidden(format!(
+                "The enclave is not on the allowlist of Seal policy {}",
+                policy_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse the request if the enclave certainly cannot decrypt under one of `policies`.
+pub async fn precheck_policies<'a>(
+    state: &AppState,
+    policies: impl IntoIterator<Item = &'a str>,
+) -> Result<(), EnclaveError> {
+    if !state.config.seal_policy_precheck {
+        return Ok(());
+    }
+    // Without a valid key the task fails anyway, the objects can still be checked
+    let enclave = SuiAccount::from_secret_key(state.sui_secret_key()).ok().map(|account| account.address);
+    let client = SuiClient::from_state(state)?;
+    for policy_id in policies.into_iter().collect::<BTreeSet<_>>() {
+        match client.get_object(policy_id).await {
+            Ok(policy) => check_policy(policy_id, policy.as_ref(), enclave.as_ref())?,
+            Err(EnclaveError::BadRequest(message)) => return Err(EnclaveError::BadRequest(message)),
+            Err(e) => warn!("Seal policy {} not pre-checked, leaving it to the task: {:?}", policy_id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(fields: serde_json::Value) -> SuiObject {
+        let result = json!({ "data": { "objectId": "0xa11", "version": "2", "digest": "d", "content": { "fields": fields } } });
+        SuiObject::from_result(&result).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_check_policy() {
+        let enclave = parse_address("0xe1").unwrap();
+        let forbidden = |result: Result<(), EnclaveError>| matches!(result, Err(EnclaveError::Forbidden(_)));
+
+        assert!(forbidden(check_policy("0xa11", None, Some(&enclave))));
+        assert!(forbidden(check_policy("0xa11", Some(&policy(json!(null))), Some(&enclave))));
+
+        // Allowlists are compared by address, whatever the padding
+        let listed = policy(json!({ "name": "team", "list": [format!("0x{:0>64}", "e1"), "0xb0b"] }));
+        assert!(check_policy("0xa11", Some(&listed), Some(&enclave)).is_ok());
+        let other = policy(json!({ "allowlist": ["0xb0b"] }));
+        assert!(forbidden(check_policy("0xa11", Some(&other), Some(&enclave))));
+        assert!(check_policy("0xa11", Some(&other), None).is_ok());
+
+        // Policies of other shapes are left to seal_approve
+        assert!(check_policy("0xa11", Some(&policy(json!({ "owner": "0xb0b" }))), Some(&enclave)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_precheck_can_be_disabled() {
+        let mut state = crate::test_app_state();
+        state.config.seal_policy_precheck = false;
+        assert!(precheck_policies(&state, ["0xa11"]).await.is_ok());
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Soft deletion of message vectors. `/delete_messages` only marks points with a
+//! `deleted_at_ms` tombstone, which search excludes by default; `/restore_messages` clears
+//! tombstones younger than `VECTOR_RESTORE_WINDOW_SECS`. Older tombstoned points are purged
+//! by the reaper.
+//!
+//! `/delete_vectors` is the permanent counterpart for user data removal requests: it
+//! deletes every point of an address or of source blobs at once, tombstoned or not, and
+//! returns the number deleted signed by the enclave as evidence of the removal.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::common::{current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessedDataResponse};
+use crate::qdrant::QdrantClient;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Payload field holding the soft deletion time of a point.
+pub const TOMBSTONE_FIELD: &str = "deleted_at_ms";
+/// Default for `VECTOR_RESTORE_WINDOW_SECS`, one week.
+pub const DEFAULT_RESTORE_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Messages to delete or restore. Either the source blob or message IDs must be given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageSelector {
+    /// Defaults to `QDRANT_COLLECTION_NAME`
+    pub collection: Option<String>,
+    /// Every message ingested from this blob
+    pub original_blob_id: Option<String>,
+    /// Restrict to one chat, as stored in the `chat_id` payload field
+    pub chat_id: Option<serde_json::Value>,
+    /// Messages with these IDs, as stored in the `message_id` payload field
+    pub message_ids: Option<Vec<serde_json::Value>>,
+}
+
+impl MessageSelector {
+    /// Qdrant conditions matching the selected messages.
+    pub fn conditions(&self) -> Result<Vec<serde_json::Value>, EnclaveError> {
+        let mut conditions = Vec::new();
+        if let Some(blob_id) = &self.original_blob_id {
+            conditions.push(serde_json::json!({ "key": "original_blob_id", "match": { "value": blob_id } }));
+        }
+        if let Some(message_ids) = self.message_ids.as_ref().filter(|ids| !ids.is_empty()) {
+            conditions.push(serde_json::json!({ "key": "message_id", "match": { "any": message_ids } }));
+        }
+        if conditions.is_empty() {
+            return Err(EnclaveError::BadRequest(
+                "original_blob_id or message_ids is required".to_string(),
+            ));
+        }
+        if let Some(chat_id) = &self.chat_id {
+            conditions.push(serde_json::json!({ "key": "chat_id", "match": { "value": chat_id } }));
+        }
+        Ok(conditions)
+    }
+}
+
+/// Payload marking a point as deleted at `now_ms`.
+pub fn tombstone(now_ms: u64) -> serde_json::Value {
+    serde_json::json!({ TOMBSTONE_FIELD: now_ms })
+}
+
+/// Condition matching points with a tombstone set at or after `since_ms`.
+fn deleted_since(since_ms: u64) -> serde_json::Value {
+    serde_json::json!({ "key": TOMBSTONE_FIELD, "range": { "gte": since_ms } })
+}
+
+/// Filter matching the selected messages that are not deleted yet.
+pub fn delete_filter(selector: &MessageSelector) -> Result<serde_json::Value, EnclaveError> {
+    let mut must = selector.conditions()?;
+    must.push(serde_json::json!({ "is_empty": { "key": TOMBSTONE_FIELD } }));
+    Ok(serde_json::json!({ "must": must }))
+}
+
+/// Filter matching the selected messages deleted within the restore window.
+pub fn restore_filter(selector: &MessageSelector, now_ms: u64, window_secs: u64) -> Result<serde_json::Value, EnclaveError> {
+    let mut must = selector.conditions()?;
+    must.push(deleted_since(now_ms.saturating_sub(window_secs * 1000)));
+    Ok(serde_json::json!({ "must": must }))
+}
+
+/// Filter matching points deleted before the restore window, which can no longer be restored.
+pub fn purge_filter(now_ms: u64, window_secs: u64) -> serde_json::Value {
+    serde_json::json!({
+        "must": [{ "key": TOMBSTONE_FIELD, "range": { "lt": now_ms.saturating_sub(window_secs * 1000) } }]
+    })
+}
+
+/// Response of `/delete_messages` and `/restore_messages`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftDeleteResponse {
+    pub collection: String,
+    /// Points deleted or restored
+    pub count: u64,
+    /// Until when deleted points can be restored, only set on deletion
+    pub restorable_until_ms: Option<u64>,
+}
+
+/// Points to permanently delete with `/delete_vectors`. At least one of `address` and
+/// `original_blob_ids` is required; when both are given, points must match both.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorDeletionRequest {
+    /// Defaults to `QDRANT_COLLECTION_NAME`
+    pub collection: Option<String>,
+    /// Owner of the messages, as stored in the `user_id` payload field
+    pub address: Option<String>,
+    /// Messages ingested from any of these blobs
+    pub original_blob_ids: Option<Vec<String>>,
+}
+
+impl VectorDeletionRequest {
+    /// Qdrant filter matching the points to delete.
+    pub fn filter(&self) -> Result<serde_json::Value, EnclaveError> {
+        let mut must = Vec::new();
+        if let Some(address) = self.address.as_ref().filter(|address| !address.is_empty()) {
+            must.push(serde_json::json!({ "key": "user_id", "match": { "value": address } }));
+        }
+        if let Some(blob_ids) = self.original_blob_ids.as_ref().filter(|ids| !ids.is_empty()) {
+            must.push(serde_json::json!({ "key": "original_blob_id", "match": { "any": blob_ids } }));
+        }
+        if must.is_empty() {
+            return Err(EnclaveError::BadRequest(
+                "address or original_blob_ids is required".to_string(),
+            ));
+        }
+        Ok(serde_json::json!({ "must": must }))
+    }
+}
+
+/// Signed result of `/delete_vectors`, under [IntentScope::VectorDeletion].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorDeletion {
+    pub collection: String,
+    pub address: Option<String>,
+    pub original_blob_ids: Vec<String>,
+    /// Points deleted
+    pub deleted: u64,
+    pub deleted_at_ms: u64,
+}
+
+/// Permanently delete points whose restore window has passed from every allowlisted
+/// collection, returning the number purged per collection where any were.
+pub async fn purge_deleted_vectors(state: &AppState) -> Result<Vec<(String, u64)>, EnclaveError> {
+    let filter = purge_filter(current_timestamp_ms(), state.config.vector_restore_window_secs);
+    let client = QdrantClient::from_state(state)?;
+    let mut purged = Vec::new();
+    for collection in &state.config.qdrant_collections {
+        let count = client.count_points(collection, &filter).await?;
+        if count == 0 {
+            continue;
+        }
+        client.delete_points(collection, &filter).await?;
+        state.audit_log.record("vectors_purged", collection, serde_json::json!({ "count": count }));
+        purged.push((collection.clone(), count));
+    }
+    Ok(purged)
+}
+
+/// Check the admin token and resolve the collection of a selector.
+pub(crate) fn authorize(state: &AppState, headers: &HeaderMap, selector: &MessageSelector) -> Result<String, EnclaveError> {
+    if !state.is_admin(headers) {
+        return Err(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    state.qdrant_collection(selector.collection.as_deref()).map(str::to_string)
+}
+
+async fn soft_delete(state: &AppState, collection: &str, selector: &MessageSelector) -> Result<SoftDeleteResponse, EnclaveError> {
+    let filter = delete_filter(selector)?;
+    let client = QdrantClient::from_state(state)?;
+    let count = client.count_points(collection, &filter).await?;
+    let now_ms = current_timestamp_ms();
+    if count > 0 {
+        client.set_payload(collection, tombstone(now_ms), &filter).await?;
+        state.audit_log.record(
+            "messages_deleted",
+            collection,
+            serde_json::json!({ "count": count, "filter": filter }),
+        );
+    }
+    Ok(SoftDeleteResponse {
+        collection: collection.to_string(),
+        count,
+        restorable_until_ms: Some(now_ms + state.config.vector_restore_window_secs * 1000),
+    })
+}
+
+async fn restore(state: &AppState, collection: &str, selector: &MessageSelector) -> Result<SoftDeleteResponse, EnclaveError> {
+    let filter = restore_filter(selector, current_timestamp_ms(), state.config.vector_restore_window_secs)?;
+    let client = QdrantClient::from_state(state)?;
+    let count = client.count_points(collection, &filter).await?;
+    if count > 0 {
+        client.delete_payload_keys(collection, &[TOMBSTONE_FIELD], &filter).await?;
+        state.audit_log.record(
+            "messages_restored",
+            collection,
+            serde_json::json!({ "count": count, "filter": filter }),
+        );
+    }
+    Ok(SoftDeleteResponse {
+        collection: collection.to_string(),
+        count,
+        restorable_until_ms: None,
+    })
+}
+
+async fn delete_vectors_in(
+    state: &AppState,
+    collection: &str,
+    request: &VectorDeletionRequest,
+) -> Result<VectorDeletion, EnclaveError> {
+    let filter = request.filter()?;
+    let client = QdrantClient::from_state(state)?;
+    let deleted = client.count_points(collection, &filter).await?;
+    if deleted > 0 {
+        client.delete_points(collection, &filter).await?;
+        state.audit_log.record(
+            "vectors_deleted",
+            collection,
+            serde_json::json!({ "count": deleted, "filter": filter }),
+        );
+    }
+    Ok(VectorDeletion {
+        collection: collection.to_string(),
+        address: request.address.clone(),
+        original_blob_ids: request.original_blob_ids.clone().unwrap_or_default(),
+        deleted,
+        deleted_at_ms: current_timestamp_ms(),
+    })
+}
+
+/// Permanently delete the points of an address or of source blobs, returning the count
+/// signed by the enclave. Requires the admin token.
+pub async fn delete_vectors(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<VectorDeletionRequest>,
+) -> ApiResponse<ProcessedDataResponse<IntentMessage<VectorDeletion>>> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    let result = async {
+        let collection = state.qdrant_collection(request.collection.as_deref())?;
+        let deletion = delete_vectors_in(&state, collection, &request).await?;
+        state.key_usage.acquire(IntentScope::VectorDeletion)?;
+        to_signed_response(&*state.keys.current(), deletion, current_timestamp_ms(), IntentScope::VectorDeletion)
+    }
+    .await;
+    match result {
+        Ok(signed) => {
+            let signature = signed.signature.clone();
+            ctx.ok(signed).with_signature(signature)
+        }
+        Err(e) => ctx.error(e),
+    }
+}
+
+/// Soft delete messages. Requires the admin token.
+pub async fn delete_messages(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(selector): Json<MessageSelector>,
+) -> ApiResponse<SoftDeleteResponse> {
+    let collection = match authorize(&state, &headers, &selector) {
+        Ok(collection) => collection,
+        Err(e) => return ctx.error(e),
+    };
+    ctx.respond(soft_delete(&state, &collection, &selector).await)
+}
+
+/// Restore messages deleted within the restore window. Requires the admin token.
+pub async fn restore_messages(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(selector): Json<MessageSelector>,
+) -> ApiResponse<SoftDeleteResponse> {
+    let collection = match authorize(&state, &headers, &selector) {
+        Ok(collection) => collection,
+        Err(e) => return ctx.error(e),
+    };
+    ctx.respond(restore(&state, &collection, &selector).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filters() {
+        assert!(MessageSelector::default().conditions().is_err());
+        assert_eq!(tombstone(5), json!({ "deleted_at_ms": 5 }));
+        let selector = MessageSelector {
+            chat_id: Some(json!(42)),
+            message_ids: Some(vec![json!(1), json!(2)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            delete_filter(&selector).unwrap(),
+            json!({ "must": [
+                { "key": "message_id", "match": { "any": [1, 2] } },
+                { "key": "chat_id", "match": { "value": 42 } },
+                { "is_empty": { "key": TOMBSTONE_FIELD } },
+            ]})
+        );
+
+        let restore = restore_filter(&selector, 10_000, 3).unwrap();
+        assert_eq!(restore["must"][2]["range"]["gte"], 7_000);
+        assert_eq!(purge_filter(10_000, 3)["must"][0]["range"]["lt"], 7_000);
+    }
+
+    #[test]
+    fn test_vector_deletion_filter() {
+        assert!(VectorDeletionRequest::default().filter().is_err());
+        let request = VectorDeletionRequest {
+            address: Some("0xabc".to_string()),
+            original_blob_ids: Some(vec!["blob-1".to_string(), "blob-2".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            request.filter().unwrap(),
+            json!({ "must": [
+                { "key": "user_id", "match": { "value": "0xabc" } },
+                { "key": "original_blob_id", "match": { "any": ["blob-1", "blob-2"] } },
+            ]})
+        );
+        let by_blob = VectorDeletionRequest {
+            address: Some(String::new()),
+            original_blob_ids: Some(vec!["blob-1".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(by_blob.filter().unwrap()["must"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_vectors_requires_admin() {
+        let response = delete_vectors(
+            RequestContext::new(None),
+            State(Arc::new(crate::test_app_state())),
+            HeaderMap::new(),
+            Json(VectorDeletionRequest::default()),
+        )
+        .await;
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+}
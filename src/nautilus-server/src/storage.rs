@@ -0,0 +1,348 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent state on the enclave's disk. With `STORAGE_PATH` set, job records, idempotency
+//! keys, audit events and task audit entries are written to a SQLite database there as they
+//! change and loaded back on boot, so a restarted server still answers `/jobs/:id` and
+//! replays idempotent ingests. The schema is migrated on open. The in-memory stores stay
+//! authoritative: a failed write is logged and the request goes on. The enclave's disk does
+//! not survive the enclave itself, so this covers server restarts, not a new enclave.
+
+use crate::audit::AuditEvent;
+use crate::jobs::JobRecord;
+use crate::EnclaveError;
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Schema migrations, applied in order. `PRAGMA user_version` holds how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        record TEXT NOT NULL
+    );
+    CREATE TABLE idempotency_keys (
+        key TEXT PRIMARY KEY,
+        fingerprint BLOB NOT NULL,
+        job_id TEXT NOT NULL,
+        record TEXT,
+        created_at_ms INTEGER NOT NULL
+    );
+    CREATE TABLE audit_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        event TEXT NOT NULL
+    );
+    CREATE TABLE task_audit (
+        sequence INTEGER PRIMARY KEY,
+        entry TEXT NOT NULL
+    );",
+];
+
+/// Idempotency key as stored, and as replicated to a standby.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub key: String,
+    pub fingerprint: [u8; 32],
+    pub job_id: String,
+    /// Succeeded job kept for the key
+    pub record: Option<JobRecord>,
+    pub created_at_ms: u64,
+}
+
+/// SQLite database under `STORAGE_PATH`.
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+fn storage_error(e: impl std::fmt::Display) -> EnclaveError {
+    EnclaveError::Internal(format!("Storage error: {}", e))
+}
+
+impl Storage {
+    /// Open or create the database at `path` and migrate it to the current schema.
+    pub fn open(path: &Path) -> Result<Self, EnclaveError> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(storage_error)?;
+        }
+        let conn = Connection::open(path).map_err(storage_error)?;
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(storage_error)?;
+        conn.pragma_update(None, "synchronous", "NORMAL").map_err(storage_error)?;
+        let storage = Self { conn: Mutex::new(conn) };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    fn migrate(&self) -> Result<(), EnclaveError> {
+        let mut conn = self.conn.lock().unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(storage_error)?;
+        if version > MIGRATIONS.len() {
+            return Err(EnclaveError::ConfigError(format!(
+                "Storage schema version {} is newer than this server supports ({})",
+                version,
+                MIGRATIONS.len()
+            )));
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction().map_err(storage_error)?;
+            tx.execute_batch(migration).map_err(storage_error)?;
+            tx.pragma_update(None, "user_version", i + 1).map_err(storage_error)?;
+            tx.commit().map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    /// Schema version of the database.
+    pub fn schema_version(&self) -> Result<usize, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(storage_error)
+    }
+
+    /// Run a write, logging rather than returning its failure.
+    fn write(&self, what: &str, f: impl FnOnce(&Connection) -> rusqlite::Result<()>) {
+        let conn = self.conn.lock().unwrap();
+        if let Err(e) = f(&conn) {
+            warn!("Failed to persist {}: {}", what, e);
+        }
+    }
+
+    /// Every stored job.
+    pub fn jobs(&self) -> Result<Vec<JobRecord>, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT record FROM jobs").map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(storage_error)?;
+        let decoded = rows.map(|row| decode(&row.map_err(storage_error)?)).collect();
+        decoded
+    }
+
+    pub fn put_job(&self, record: &JobRecord) {
+        let Some(json) = encode(record) else { return };
+        self.write("job", |conn| {
+            conn.execute(
+                "INSERT INTO jobs (id, record) VALUES (?1, ?2)
+                 ON CONFLICT (id) DO UPDATE SET record = excluded.record",
+                params![record.id, json],
+            )
+            .map(drop)
+        });
+    }
+
+    pub fn delete_jobs(&self, ids: &[String]) {
+        if ids.is_empty() {
+            return;
+        }
+        self.write("job removal", |conn| {
+            let mut statement = conn.prepare_cached("DELETE FROM jobs WHERE id = ?1")?;
+            for id in ids {
+                statement.execute(params![id])?;
+            }
+            Ok(())
+        });
+    }
+
+    /// Every stored idempotency key.
+    pub fn idempotency_keys(&self) -> Result<Vec<StoredKey>, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT key, fingerprint, job_id, record, created_at_ms FROM idempotency_keys")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })
+            .map_err(storage_error)?;
+        let keys = rows
+            .map(|row| {
+                let (key, fingerprint, job_id, record, created_at_ms) = row.map_err(storage_error)?;
+                Ok(StoredKey {
+                    key,
+                    fingerprint: fingerprint
+                        .try_into()
+                        .map_err(|_| storage_error("idempotency key fingerprint is not 32 bytes"))?,
+                    job_id,
+                    record: record.as_deref().map(decode).transpose()?,
+                    created_at_ms: created_at_ms as u64,
+                })
+            })
+            .collect();
+        keys
+    }
+
+    pub fn put_idempotency_key(&self, stored: &StoredKey) {
+        let record = match &stored.record {
+            Some(record) => match encode(record) {
+                Some(json) => Some(json),
+                None => return,
+            },
+            None => None,
+        };
+        self.write("idempotency key", |conn| {
+            conn.execute(
+                "INSERT INTO idempotency_keys (key, fingerprint, job_id, record, created_at_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (key) DO UPDATE SET fingerprint = excluded.fingerprint,
+                     job_id = excluded.job_id, record = excluded.record, created_at_ms = excluded.created_at_ms",
+                params![
+                    stored.key,
+                    stored.fingerprint.as_slice(),
+                    stored.job_id,
+                    record,
+                    stored.created_at_ms as i64
+                ],
+            )
+            .map(drop)
+        });
+    }
+
+    pub fn delete_idempotency_keys(&self, keys: &[String]) {
+        if keys.is_empty() {
+            return;
+        }
+        self.write("idempotency key removal", |conn| {
+            let mut statement = conn.prepare_cached("DELETE FROM idempotency_keys WHERE key = ?1")?;
+            for key in keys {
+                statement.execute(params![key])?;
+            }
+            Ok(())
+        });
+    }
+
+    /// Up to `limit` latest audit events, oldest first.
+    pub fn audit_events(&self, limit: usize) -> Result<Vec<AuditEvent>, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT event FROM (SELECT id, event FROM audit_events ORDER BY id DESC LIMIT ?1) ORDER BY id")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map(params![limit as i64], |row| row.get::<_, String>(0))
+            .map_err(storage_error)?;
+        let decoded = rows.map(|row| decode(&row.map_err(storage_error)?)).collect();
+        decoded
+    }
+
+    /// Append an audit event, keeping only the latest `keep`.
+    pub fn append_audit_event(&self, event: &AuditEvent, keep: usize) {
+        let Some(json) = encode(event) else { return };
+        self.write("audit event", |conn| {
+            conn.execute("INSERT INTO audit_events (event) VALUES (?1)", params![json])?;
+            let id = conn.last_insert_rowid();
+            conn.execute("DELETE FROM audit_events WHERE id <= ?1", params![id - keep as i64])
+                .map(drop)
+        });
+    }
+
+    /// Up to `limit` latest task audit entries, oldest first.
+    pub fn task_audit_entries<T: DeserializeOwned>(&self, limit: usize) -> Result<Vec<T>, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare("SELECT entry FROM (SELECT sequence, entry FROM task_audit ORDER BY sequence DESC LIMIT ?1) ORDER BY sequence")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map(params![limit as i64], |row| row.get::<_, String>(0))
+            .map_err(storage_error)?;
+        let decoded = rows.map(|row| decode(&row.map_err(storage_error)?)).collect();
+        decoded
+    }
+
+    /// Sequence the next task audit entry takes: one past the latest stored.
+    pub fn next_task_audit_sequence(&self) -> Result<u64, EnclaveError> {
+        let conn = self.conn.lock().unwrap();
+        let latest: Option<i64> = conn
+            .query_row("SELECT MAX(sequence) FROM task_audit", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        Ok(latest.map_or(0, |sequence| sequence as u64 + 1))
+    }
+
+    /// Append task audit entry `sequence`, keeping only the latest `keep`.
+    pub fn append_task_audit(&self, sequence: u64, entry: &impl Serialize, keep: usize) {
+        let Some(json) = encode(entry) else { return };
+        self.write("task audit entry", |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO task_audit (sequence, entry) VALUES (?1, ?2)",
+                params![sequence as i64, json],
+            )?;
+            conn.execute(
+                "DELETE FROM task_audit WHERE sequence <= ?1",
+                params![sequence as i64 - keep as i64],
+            )
+            .map(drop)
+        });
+    }
+}
+
+fn encode(value: &impl Serialize) -> Option<String> {
+    serde_json::to_string(value)
+        .map_err(|e| warn!("Failed to encode a record for storage: {}", e))
+        .ok()
+}
+
+fn decode<T: DeserializeOwned>(json: &str) -> Result<T, EnclaveError> {
+    serde_json::from_str(json).map_err(storage_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobStatus, JobStore};
+
+    #[test]
+    fn test_state_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("nautilus.db");
+        let storage = Storage::open(&path).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+
+        let jobs = JobStore::new();
+        let job = jobs.create("embedding_ingest");
+        storage.put_job(&job);
+        storage.put_idempotency_key(&StoredKey {
+            key: "key".to_string(),
+            fingerprint: [7; 32],
+            job_id: job.id.clone(),
+            record: None,
+            created_at_ms: 1000,
+        });
+        for i in 0..3 {
+            let event = AuditEvent {
+                timestamp_ms: i,
+                action: "collection_created".to_string(),
+                subject: format!("c{}", i),
+                details: serde_json::json!({}),
+            };
+            storage.append_audit_event(&event, 2);
+            storage.append_task_audit(i, &serde_json::json!({ "sequence": i }), 2);
+        }
+        drop(storage);
+
+        // Reopening runs no migration twice
+        let storage = Storage::open(&path).unwrap();
+        let stored = storage.jobs().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!((stored[0].id.clone(), stored[0].status), (job.id.clone(), JobStatus::Queued));
+        let keys = storage.idempotency_keys().unwrap();
+        assert_eq!((keys[0].fingerprint, keys[0].job_id.clone()), ([7; 32], job.id.clone()));
+        let subjects: Vec<String> = storage.audit_events(10).unwrap().into_iter().map(|e| e.subject).collect();
+        assert_eq!(subjects, vec!["c1", "c2"]);
+        let entries: Vec<serde_json::Value> = storage.task_audit_entries(10).unwrap();
+        assert_eq!(entries, vec![serde_json::json!({ "sequence": 1 }), serde_json::json!({ "sequence": 2 })]);
+        assert_eq!(storage.next_task_audit_sequence().unwrap(), 3);
+
+        storage.delete_jobs(&[job.id]);
+        storage.delete_idempotency_keys(&["key".to_string()]);
+        assert!(storage.jobs().unwrap().is_empty());
+        assert!(storage.idempotency_keys().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Integrity for streamed responses. Every chunk sent to the client is fed into a
+//! [ChunkAccumulator]; once the stream ends, the Merkle root over all chunks is signed
+//! by the enclave key and sent as the final frame.
+//!
+//! Tree layout: leaves are `sha3_256(0x00 || chunk)`, inner nodes are
+//! `sha3_256(0x01 || left || right)`, and an unpaired node is promoted to the next level
+//! unchanged. An empty stream has the root `sha3_256("")`.
+
+use crate::common::{to_signed_response, IntentMessage, IntentScope, ProcessedDataResponse};
+use crate::key_manager::ResponseSigner;
+use crate::EnclaveError;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha3_256};
+use serde::{Deserialize, Serialize};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Name of the SSE event carrying the signed [StreamSummary].
+pub const STREAM_SIGNATURE_EVENT: &str = "signature";
+
+/// Hash of a single streamed chunk.
+pub fn leaf_hash(chunk: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3_256::default();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(chunk);
+    hasher.finalize().digest
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha3_256::default();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().digest
+}
+
+/// Merkle root over already hashed leaves.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return Sha3_256::digest(b"").digest;
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Signed summary of a completed stream, sent as its final frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StreamSummary {
+    pub chunk_count: u64,
+    pub total_bytes: u64,
+    /// Hex encoded Merkle root over all chunks, in the order they were sent.
+    pub merkle_root: String,
+}
+
+/// Accumulates the hashes of streamed chunks.
+#[derive(Debug, Default)]
+pub struct ChunkAccumulator {
+    leaves: Vec<[u8; 32]>,
+    total_bytes: u64,
+}
+
+impl ChunkAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a chunk exactly as it is written to the response body.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.leaves.push(leaf_hash(chunk));
+        self.total_bytes += chunk.len() as u64;
+    }
+
+    pub fn chunk_count(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        merkle_root(&self.leaves)
+    }
+
+    pub fn summary(&self) -> StreamSummary {
+        StreamSummary {
+            chunk_count: self.chunk_count(),
+            total_bytes: self.total_bytes,
+            merkle_root: Hex::encode(self.root()),
+        }
+    }
+
+    /// Sign the stream summary with the enclave key.
+    pub fn finish<S: ResponseSigner + ?Sized>(
+        &self,
+        kp: &S,
+        timestamp_ms: u64,
+    ) -> Result<ProcessedDataResponse<IntentMessage<StreamSummary>>, EnclaveError> {
+        to_signed_response(kp, self.summary(), timestamp_ms, IntentScope::StreamSummary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey, Ed25519Signature};
+    use fastcrypto::traits::{KeyPair, ToFromBytes, VerifyingKey};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_merkle_root_shape() {
+        let (a, b, c) = (leaf_hash(b"a"), leaf_hash(b"b"), leaf_hash(b"c"));
+        assert_eq!(merkle_root(&[a]), a);
+        assert_eq!(merkle_root(&[a, b]), node_hash(&a, &b));
+        assert_eq!(merkle_root(&[a, b, c]), node_hash(&node_hash(&a, &b), &c));
+        assert_ne!(merkle_root(&[a, b]), merkle_root(&[b, a]));
+        assert_eq!(merkle_root(&[]), Sha3_256::digest(b"").digest);
+    }
+
+    #[test]
+    fn test_signed_summary_verifies() {
+        let kp = Ed25519KeyPair::generate(&mut StdRng::from_seed([0; 32]));
+        let mut acc = ChunkAccumulator::new();
+        for chunk in [b"data: one\n\n".as_slice(), b"data: two\n\n", b"data: three\n\n"] {
+            acc.push(chunk);
+        }
+        let signed = acc.finish(&kp, 1744038900000).unwrap();
+        assert_eq!(signed.response.data.chunk_count, 3);
+        assert_eq!(signed.response.data.total_bytes, 35);
+
+        let bytes = bcs::to_bytes(&signed.response).unwrap();
+        let pk = Ed25519PublicKey::from_bytes(kp.public().as_bytes()).unwrap();
+        let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+        assert!(pk.verify(&bytes, &sig).is_ok());
+    }
+}
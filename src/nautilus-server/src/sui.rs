@@ -0,0 +1,1062 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Native Sui client that registers the enclave on-chain, replacing `register_enclave.sh`.
+//! `POST /admin/register_attestation` loads a fresh attestation document with
+//! `0x2::nitro_attestation::load_nitro_attestation` and passes it to
+//! `enclave::register_enclave<T>` of `MOVE_PACKAGE_ID`, in one programmable transaction
+//! signed with `SUI_SECRET_KEY` and paid from its SUI coins through the
+//! [crate::tx_sequencer].
+//!
+//! [SuiClient] is also the server's typed read client: objects, dynamic fields and dry runs,
+//! used by [crate::walrus] to check blob certification and read the Walrus epoch. It talks to
+//! the JSON-RPC API of `SUI_RPC_URL`, failing over to `SUI_RPC_FALLBACK_URLS` in order, and
+//! caches object reads in [SuiCache] for `SUI_OBJECT_CACHE_SECS`.
+//!
+//! The client builds the BCS transaction itself, rather than pulling the Sui SDK and its
+//! workspace into the enclave image. Only the transaction types the registration and the gas
+//! coin split need are modelled, with their variants in the order that gives their BCS tags.
+//! A mistake there produces transactions the fullnode refuses, or worse signs a different
+//! one, so the tests pin the full bytes of both transactions. They were encoded field by field
+//! from `TransactionData` in `sui-types`; after a Sui protocol change, compare them with
+//! `sui client ptb --serialize-unsigned-transaction` for the same inputs.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::breakers::CircuitBreakers;
+use crate::common::fetch_attestation;
+use crate::metrics::Metrics;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PrivateKey};
+use fastcrypto::encoding::{Base58, Base64, Bech32, Encoding, Hex};
+use fastcrypto::hash::{Blake2b256, HashFunction};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+/// Default gas budget of the registration, in MIST.
+pub const DEFAULT_REGISTRATION_GAS_BUDGET: u64 = 100_000_000;
+
+/// Human readable part of Bech32 encoded Sui private keys.
+const SUI_PRIVATE_KEY_HRP: &str = "suiprivkey";
+/// Sui signature scheme flag of Ed25519, the only scheme `SUI_SECRET_KEY` may use here.
+const ED25519_FLAG: u8 = 0;
+
+/// Shared `0x6` clock object, created at genesis.
+const CLOCK_OBJECT_ID: &str = "0x6";
+const CLOCK_INITIAL_SHARED_VERSION: u64 = 1;
+
+pub(crate) type SuiAddress = [u8; 32];
+
+/// Parse a hex object ID or address, with or without `0x` and leading zeros.
+pub fn parse_address(value: &str) -> Result<SuiAddress, EnclaveError> {
+    let hex = value.strip_prefix("0x").unwrap_or(value);
+    if hex.is_empty() || hex.len() > 64 {
+        return Err(EnclaveError::BadRequest(format!("Invalid Sui address {}", value)));
+    }
+    let bytes = Hex::decode(&format!("{:0>64}", hex))
+        .map_err(|_| EnclaveError::BadRequest(format!("Invalid Sui address {}", value)))?;
+    Ok(bytes.try_into().expect("64 hex characters"))
+}
+
+pub(crate) fn address_hex(address: &SuiAddress) -> String {
+    format!("0x{}", Hex::encode(address))
+}
+
+/// Ed25519 account of `SUI_SECRET_KEY`.
+pub struct SuiAccount {
+    keypair: Ed25519KeyPair,
+    pub address: SuiAddress,
+}
+
+impl SuiAccount {
+    /// Account of a `suiprivkey1...` key, as exported by `sui keytool export`.
+    pub fn from_secret_key(secret: &str) -> Result<Self, EnclaveError> {
+        let bytes = Bech32::decode(secret.trim(), SUI_PRIVATE_KEY_HRP)
+            .map_err(|_| EnclaveError::ConfigError("SUI_SECRET_KEY must be a suiprivkey1... key".to_string()))?;
+        match bytes.split_first() {
+            Some((&ED25519_FLAG, secret)) => {
+                let private = Ed25519PrivateKey::from_bytes(secret)
+                    .map_err(|e| EnclaveError::ConfigError(format!("Invalid SUI_SECRET_KEY: {}", e)))?;
+                Ok(Self::new(Ed25519KeyPair::from(private)))
+            }
+            _ => Err(EnclaveError::ConfigError("SUI_SECRET_KEY must be an Ed25519 key".to_string())),
+        }
+    }
+
+    pub fn new(keypair: Ed25519KeyPair) -> Self {
+        let mut hasher = Blake2b256::default();
+        hasher.update([ED25519_FLAG]);
+        hasher.update(keypair.public().as_bytes());
+        Self {
+            address: hasher.finalize().digest,
+            keypair,
+        }
+    }
+
+    /// Serialized Sui signature over the transaction intent: flag, signature, public key.
+    pub(crate) fn sign_transaction(&self, transaction: &TransactionData) -> String {
+        let mut hasher = Blake2b256::default();
+        // Intent scope TransactionData, version 0, app ID Sui
+        hasher.update([0u8, 0, 0]);
+        hasher.update(bcs::to_bytes(transaction).expect("should not fail"));
+        let signature = self.keypair.sign(&hasher.finalize().digest);
+        let serialized = [&[ED25519_FLAG][..], signature.as_bytes(), self.keypair.public().as_bytes()].concat();
+        Base64::encode(serialized)
+    }
+}
+
+/// Object ID, version and BCS digest: the digest is length prefixed like Sui's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct ObjectRef(pub(crate) SuiAddress, pub(crate) u64, pub(crate) Vec<u8>);
+
+#[derive(Debug, Serialize)]
+pub(crate) enum TransactionData {
+    V1(TransactionDataV1),
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TransactionDataV1 {
+    kind: TransactionKind,
+    sender: SuiAddress,
+    gas_data: GasData,
+    expiration: TransactionExpiration,
+}
+
+#[derive(Debug, Serialize)]
+enum TransactionKind {
+    ProgrammableTransaction(ProgrammableTransaction),
+}
+
+#[derive(Debug, Serialize)]
+struct ProgrammableTransaction {
+    inputs: Vec<CallArg>,
+    commands: Vec<Command>,
+}
+
+#[derive(Debug, Serialize)]
+enum CallArg {
+    /// BCS bytes of the value
+    Pure(Vec<u8>),
+    Object(ObjectArg),
+}
+
+#[derive(Debug, Serialize)]
+enum ObjectArg {
+    #[allow(dead_code)]
+    ImmOrOwnedObject(ObjectRef),
+    SharedObject {
+        id: SuiAddress,
+        initial_shared_version: u64,
+        mutable: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+enum Command {
+    MoveCall(Box<ProgrammableMoveCall>),
+    TransferObjects(Vec<Argument>, Argument),
+    SplitCoins(Argument, Vec<Argument>),
+}
+
+#[derive(Debug, Serialize)]
+struct ProgrammableMoveCall {
+    package: SuiAddress,
+    module: String,
+    function: String,
+    type_arguments: Vec<TypeTag>,
+    arguments: Vec<Argument>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+enum Argument {
+    GasCoin,
+    Input(u16),
+    Result(u16),
+    NestedResult(u16, u16),
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+enum TypeTag {
+    Bool,
+    U8,
+    U64,
+    U128,
+    Address,
+    Signer,
+    Vector(Box<TypeTag>),
+    Struct(Box<StructTag>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct StructTag {
+    address: SuiAddress,
+    module: String,
+    name: String,
+    type_params: Vec<TypeTag>,
+}
+
+/// Parse a struct type without type parameters, such as `0x2::sui::SUI`.
+fn parse_struct_type(value: &str) -> Result<TypeTag, EnclaveError> {
+    let invalid = || EnclaveError::BadRequest(format!("Expected a type like 0x1::module::NAME, got {}", value));
+    let mut parts = value.split("::");
+    let (Some(address), Some(module), Some(name), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let identifier = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !identifier(module) || !identifier(name) {
+        return Err(invalid());
+    }
+    Ok(TypeTag::Struct(Box::new(StructTag {
+        address: parse_address(address).map_err(|_| invalid())?,
+        module: module.to_string(),
+        name: name.to_string(),
+        type_params: Vec::new(),
+    })))
+}
+
+#[derive(Debug, Serialize)]
+struct GasData {
+    payment: Vec<ObjectRef>,
+    owner: SuiAddress,
+    price: u64,
+    budget: u64,
+}
+
+#[derive(Debug, Serialize)]
+enum TransactionExpiration {
+    None,
+}
+
+/// Registration of `document` with the enclave config object, as a transaction of `sender`.
+fn registration_transaction(
+    package: SuiAddress,
+    enclave_type: TypeTag,
+    config: (SuiAddress, u64),
+    document: &[u8],
+    sender: SuiAddress,
+    gas: (ObjectRef, u64, u64),
+) -> TransactionData {
+    let (payment, price, budget) = gas;
+    let inputs = vec![
+        CallArg::Pure(bcs::to_bytes(document).expect("should not fail")),
+        CallArg::Object(ObjectArg::SharedObject {
+            id: parse_address(CLOCK_OBJECT_ID).expect("valid clock ID"),
+            initial_shared_version: CLOCK_INITIAL_SHARED_VERSION,
+            mutable: false,
+        }),
+        CallArg::Object(ObjectArg::SharedObject {
+            id: config.0,
+            initial_shared_version: config.1,
+            mutable: false,
+        }),
+    ];
+    let commands = vec![
+        Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package: parse_address("0x2").expect("valid framework ID"),
+            module: "nitro_attestation".to_string(),
+            function: "load_nitro_attestation".to_string(),
+            type_arguments: Vec::new(),
+            arguments: vec![Argument::Input(0), Argument::Input(1)],
+        })),
+        Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: "enclave".to_string(),
+            function: "register_enclave".to_string(),
+            type_arguments: vec![enclave_type],
+            arguments: vec![Argument::Input(2), Argument::Result(0)],
+        })),
+    ];
+    TransactionData::V1(TransactionDataV1 {
+        kind: TransactionKind::ProgrammableTransaction(ProgrammableTransaction { inputs, commands }),
+        sender,
+        gas_data: GasData {
+            payment: vec![payment],
+            owner: sender,
+            price,
+            budget,
+        },
+        expiration: TransactionExpiration::None,
+    })
+}
+
+/// Transaction of `sender` splitting `count` coins of `amount` MIST off its gas coin and
+/// keeping them, so concurrent transactions can each pay with a coin of their own.
+pub(crate) fn split_transaction(
+    sender: SuiAddress,
+    count: u16,
+    amount: u64,
+    gas: (ObjectRef, u64, u64),
+) -> TransactionData {
+    let (payment, price, budget) = gas;
+    let inputs = vec![
+        CallArg::Pure(bcs::to_bytes(&amount).expect("should not fail")),
+        CallArg::Pure(sender.to_vec()),
+    ];
+    let commands = vec![
+        Command::SplitCoins(Argument::GasCoin, vec![Argument::Input(0); count as usize]),
+        Command::TransferObjects((0..count).map(|i| Argument::NestedResult(0, i)).collect(), Argument::Input(1)),
+    ];
+    TransactionData::V1(TransactionDataV1 {
+        kind: TransactionKind::ProgrammableTransaction(ProgrammableTransaction { inputs, commands }),
+        sender,
+        gas_data: GasData {
+            payment: vec![payment],
+            owner: sender,
+            price,
+            budget,
+        },
+        expiration: TransactionExpiration::None,
+    })
+}
+
+/// Default for `SUI_OBJECT_CACHE_SECS`.
+pub const DEFAULT_SUI_OBJECT_CACHE_SECS: u64 = 5;
+/// Objects cached at most, expired ones are dropped first.
+const MAX_CACHED_OBJECTS: usize = 1024;
+
+/// Object read through `sui_getObject` or `suix_getDynamicFieldObject`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuiObject {
+    pub object_id: String,
+    pub version: u64,
+    /// Base58 object digest
+    pub digest: String,
+    /// Move type, such as `0x2::coin::Coin<0x2::sui::SUI>`
+    pub object_type: Option<String>,
+    /// Owner as the RPC reports it, such as `{"Shared": {"initial_shared_version": 1}}`
+    pub owner: serde_json::Value,
+    /// Fields of the Move object, `null` for packages
+    pub fields: serde_json::Value,
+}
+
+impl SuiObject {
+    /// Object of a `sui_getObject` result, `None` if it does not exist or was deleted.
+    pub fn from_result(result: &serde_json::Value) -> Result<Option<Self>, EnclaveError> {
+        let data = &result["data"];
+        if data.is_null() {
+            return Ok(None);
+        }
+        let object_id = data["objectId"].as_str().unwrap_or_default().to_string();
+        let version = json_u64(&data["version"])
+            .ok_or_else(|| EnclaveError::upstream("sui", format!("Invalid version of object {}", object_id)))?;
+        Ok(Some(Self {
+            version,
+            digest: data["digest"].as_str().unwrap_or_default().to_string(),
+            object_type: data["type"].as_str().map(str::to_string),
+            owner: data["owner"].clone(),
+            fields: data["content"]["fields"].clone(),
+            object_id,
+        }))
+    }
+
+    /// Reference to this version of the object, for paying gas or passing it owned.
+    pub(crate) fn object_ref(&self) -> Result<ObjectRef, EnclaveError> {
+        object_ref(&self.object_id, &serde_json::json!(self.version), &self.digest)
+    }
+
+    /// Version the object was shared at, `None` unless it is shared.
+    pub fn initial_shared_version(&self) -> Option<u64> {
+        json_u64(&self.owner["Shared"]["initial_shared_version"])
+    }
+}
+
+/// Entry of `suix_getDynamicFields`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicFieldInfo {
+    /// Field name with its Move type, `{"type": "u64", "value": "2"}`
+    pub name: serde_json::Value,
+    pub object_id: String,
+    /// Type of the field value
+    pub object_type: String,
+    pub version: u64,
+}
+
+/// Page of dynamic fields, `next_cursor` continues it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DynamicFieldPage {
+    pub fields: Vec<DynamicFieldInfo>,
+    pub next_cursor: Option<String>,
+}
+
+impl DynamicFieldPage {
+    pub fn from_result(result: &serde_json::Value) -> Self {
+        let fields = result["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|field| {
+                Some(DynamicFieldInfo {
+                    name: field["name"].clone(),
+                    object_id: field["objectId"].as_str()?.to_string(),
+                    object_type: field["objectType"].as_str().unwrap_or_default().to_string(),
+                    version: json_u64(&field["version"])?,
+                })
+            })
+            .collect();
+        let next_cursor = match result["hasNextPage"].as_bool() {
+            Some(true) => result["nextCursor"].as_str().map(str::to_string),
+            _ => None,
+        };
+        Self { fields, next_cursor }
+    }
+}
+
+/// Effects of `sui_dryRunTransactionBlock`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunOutcome {
+    pub success: bool,
+    /// Abort or execution error when it failed
+    pub error: Option<String>,
+    /// Computation and storage cost net of the storage rebate, in MIST
+    pub gas_used: u64,
+    pub events: Vec<serde_json::Value>,
+}
+
+impl DryRunOutcome {
+    pub fn from_result(result: &serde_json::Value) -> Self {
+        let status = &result["effects"]["status"];
+        let gas = &result["effects"]["gasUsed"];
+        let cost = |name: &str| json_u64(&gas[name]).unwrap_or(0);
+        Self {
+            success: status["status"] == "success",
+            error: status["error"].as_str().map(str::to_string),
+            gas_used: (cost("computationCost") + cost("storageCost")).saturating_sub(cost("storageRebate")),
+            events: result["events"].as_array().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Sui object reads shared by every client, and the fullnode that answered last so
+/// clients keep using a fallback until it fails too.
+#[derive(Debug, Clone)]
+pub struct SuiCache {
+    ttl: Duration,
+    objects: Arc<Mutex<HashMap<String, (Instant, SuiObject)>>>,
+    preferred: Arc<AtomicUsize>,
+}
+
+impl Default for SuiCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_SUI_OBJECT_CACHE_SECS))
+    }
+}
+
+impl SuiCache {
+    /// Cache keeping objects for `ttl`, a zero `ttl` disables it.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            objects: Arc::new(Mutex::new(HashMap::new())),
+            preferred: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<SuiObject> {
+        let objects = self.objects.lock().unwrap();
+        objects
+            .get(id)
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, object)| object.clone())
+    }
+
+    fn insert(&self, id: String, object: SuiObject) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut objects = self.objects.lock().unwrap();
+        if objects.len() >= MAX_CACHED_OBJECTS {
+            objects.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+        }
+        if objects.len() < MAX_CACHED_OBJECTS || objects.contains_key(&id) {
+            objects.insert(id, (Instant::now(), object));
+        }
+    }
+
+    /// Drop the cached copy of object `id`, e.g. after a transaction changed it.
+    pub fn invalidate(&self, id: &str) {
+        if let Ok(address) = parse_address(id) {
+            self.objects.lock().unwrap().remove(&address_hex(&address));
+        }
+    }
+}
+
+/// Sui JSON-RPC client. Calls go to the fullnode that answered last and fail over to the
+/// next configured one on connection errors, 429 and 5xx responses and unreadable bodies;
+/// an RPC error is the fullnode's answer and returned as is.
+#[derive(Debug, Clone)]
+pub struct SuiClient {
+    http: reqwest::Client,
+    rpc_urls: Vec<String>,
+    cache: SuiCache,
+    metrics: Option<Metrics>,
+    breakers: Option<CircuitBreakers>,
+}
+
+impl SuiClient {
+    /// Client for `rpc_urls` in order of preference, with a cache of its own.
+    pub fn new(rpc_urls: &[&str]) -> Result<Self, EnclaveError> {
+        if rpc_urls.is_empty() {
+            return Err(EnclaveError::ConfigError("No Sui fullnode configured".to_string()));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self {
+            http,
+            rpc_urls: rpc_urls.iter().map(|url| url.to_string()).collect(),
+            cache: SuiCache::default(),
+            metrics: None,
+            breakers: None,
+        })
+    }
+
+    /// Client for `SUI_RPC_URL` and `SUI_RPC_FALLBACK_URLS`, sharing the server's object
+    /// cache, recording call durations in the server metrics and going through the Sui
+    /// circuit breaker.
+    pub fn from_state(state: &AppState) -> Result<Self, EnclaveError> {
+        let mut client = Self::new(&state.sui_rpc_urls())?;
+        client.cache = state.sui_cache.clone();
+        client.metrics = Some(state.metrics.clone());
+        client.breakers = Some(state.breakers.clone());
+        Ok(client)
+    }
+
+    /// `result` of a JSON-RPC call, failing on an RPC error.
+    async fn call(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, EnclaveError> {
+        match &self.breakers {
+            Some(breakers) => breakers.call("sui", method, self.call_once(method, params)).await,
+            None => self.call_once(method, params).await,
+        }
+    }
+
+    /// One call, trying each fullnode once starting with the preferred one.
+    async fn call_once(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, EnclaveError> {
+        let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let first = self.cache.preferred.load(Ordering::Relaxed) % self.rpc_urls.len();
+        let mut last_error = None;
+        for offset in 0..self.rpc_urls.len() {
+            let index = (first + offset) % self.rpc_urls.len();
+            match self.post(&self.rpc_urls[index], method, &request).await {
+                Ok(mut body) => {
+                    if index != first {
+                        warn!("Sui fullnode {} is unavailable, using {}", self.rpc_urls[first], self.rpc_urls[index]);
+                        self.cache.preferred.store(index, Ordering::Relaxed);
+                    }
+                    if let Some(error) = body.get("error") {
+                        return Err(EnclaveError::upstream("sui", format!("Sui {} failed: {}", method, error)));
+                    }
+                    return Ok(body["result"].take());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("at least one fullnode"))
+    }
+
+    async fn post(&self, url: &str, method: &str, request: &serde_json::Value) -> Result<serde_json::Value, EnclaveError> {
+        let started = Instant::now();
+        let response = self.http.post(url).json(request).send().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_external_call("sui", method, started.elapsed());
+        }
+        crate::telemetry::record_upstream_call("sui", method, started.elapsed(), response.is_ok());
+        let response = response.map_err(|e| EnclaveError::upstream("sui", format!("Sui {} request failed: {}", method, e)))?;
+        let status = response.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(EnclaveError::upstream("sui", format!("Sui {} returned {}", method, status)));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| EnclaveError::upstream("sui", format!("Invalid Sui {} response: {}", method, e)))
+    }
+
+    /// Object `id` with its type, owner and fields, served from the cache while fresh.
+    /// `None` if it does not exist.
+    pub async fn get_object(&self, id: &str) -> Result<Option<SuiObject>, EnclaveError> {
+        let key = address_hex(&parse_address(id)?);
+        if let Some(object) = self.cache.get(&key) {
+            return Ok(Some(object));
+        }
+        self.fetch_object(id).await
+    }
+
+    /// Object `id` read from the fullnode, refreshing the cache. For objects expected to
+    /// change, such as a blob being certified.
+    pub async fn fetch_object(&self, id: &str) -> Result<Option<SuiObject>, EnclaveError> {
+        let key = address_hex(&parse_address(id)?);
+        let options = serde_json::json!({ "showType": true, "showOwner": true, "showContent": true });
+        let result = self.call("sui_getObject", serde_json::json!([key, options])).await?;
+        let object = SuiObject::from_result(&result)?;
+        if let Some(object) = &object {
+            self.cache.insert(key, object.clone());
+        }
+        Ok(object)
+    }
+
+    /// Page of the dynamic fields of `parent`, after `cursor`.
+    pub async fn get_dynamic_fields(
+        &self,
+        parent: &str,
+        cursor: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<DynamicFieldPage, EnclaveError> {
+        let parent = address_hex(&parse_address(parent)?);
+        let result = self
+            .call("suix_getDynamicFields", serde_json::json!([parent, cursor, limit]))
+            .await?;
+        Ok(DynamicFieldPage::from_result(&result))
+    }
+
+    /// Dynamic field of `parent` named `value` of Move type `name_type`, e.g. `u64` and
+    /// `"2"`. `None` if there is none.
+    pub async fn get_dynamic_field_object(
+        &self,
+        parent: &str,
+        name_type: &str,
+        value: serde_json::Value,
+    ) -> Result<Option<SuiObject>, EnclaveError> {
+        let parent = address_hex(&parse_address(parent)?);
+        let name = serde_json::json!({ "type": name_type, "value": value });
+        let result = self
+            .call("suix_getDynamicFieldObject", serde_json::json!([parent, name]))
+            .await?;
+        SuiObject::from_result(&result)
+    }
+
+    /// Execute base64 BCS `tx_bytes` without committing it, for its status, gas and events.
+    pub async fn dry_run(&self, tx_bytes: &str) -> Result<DryRunOutcome, EnclaveError> {
+        let result = self.call("sui_dryRunTransactionBlock", serde_json::json!([tx_bytes])).await?;
+        Ok(DryRunOutcome::from_result(&result))
+    }
+
+    pub(crate) async fn reference_gas_price(&self) -> Result<u64, EnclaveError> {
+        let price = self.call("suix_getReferenceGasPrice", serde_json::json!([])).await?;
+        json_u64(&price).ok_or_else(|| EnclaveError::upstream("sui", format!("Unexpected gas price {}", price)))
+    }
+
+    /// SUI coins of `owner` with their balance, richest first.
+    pub(crate) async fn coins(&self, owner: &SuiAddress) -> Result<Vec<(ObjectRef, u64)>, EnclaveError> {
+        let coins = self
+            .call("suix_getCoins", serde_json::json!([address_hex(owner), "0x2::sui::SUI", null, 50]))
+            .await?;
+        let mut coins = coins["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|coin| {
+                let balance = json_u64(&coin["balance"])?;
+                let coin = object_ref(coin["coinObjectId"].as_str()?, &coin["version"], coin["digest"].as_str()?).ok()?;
+                Some((coin, balance))
+            })
+            .collect::<Vec<_>>();
+        coins.sort_by_key(|(_, balance)| std::cmp::Reverse(*balance));
+        Ok(coins)
+    }
+
+    /// Initial shared version of shared object `id`.
+    async fn initial_shared_version(&self, id: &SuiAddress) -> Result<u64, EnclaveError> {
+        let object = self
+            .get_object(&address_hex(id))
+            .await?
+            .ok_or_else(|| EnclaveError::NotFound(format!("Object {} not found", address_hex(id))))?;
+        object
+            .initial_shared_version()
+            .ok_or_else(|| EnclaveError::BadRequest(format!("Object {} is not shared", address_hex(id))))
+    }
+
+    /// Sign and execute `transaction`, returning its digest and the new reference of its
+    /// gas coin once it succeeded.
+    pub(crate) async fn execute(
+        &self,
+        account: &SuiAccount,
+        transaction: &TransactionData,
+    ) -> Result<(String, Option<ObjectRef>), EnclaveError> {
+        let signature = account.sign_transaction(transaction);
+        let result = self
+            .call(
+                "sui_executeTransactionBlock",
+                serde_json::json!([transaction_bytes(transaction), [signature], { "showEffects": true }, "WaitForLocalExecution"]),
+            )
+            .await?;
+        let digest = result["digest"].as_str().unwrap_or_default().to_string();
+        let status = &result["effects"]["status"];
+        if status["status"] != "success" {
+            return Err(EnclaveError::BadRequest(format!(
+                "Transaction {} failed: {}",
+                digest,
+                status["error"].as_str().unwrap_or("unknown error")
+            )));
+        }
+        let gas = &result["effects"]["gasObject"]["reference"];
+        let gas = object_ref(gas["objectId"].as_str().unwrap_or_default(), &gas["version"], gas["digest"].as_str().unwrap_or_default());
+        Ok((digest, gas.ok()))
+    }
+}
+
+/// Base64 BCS of `transaction`, as the JSON-RPC API takes it.
+pub(crate) fn transaction_bytes(transaction: &TransactionData) -> String {
+    Base64::encode(bcs::to_bytes(transaction).expect("should not fail"))
+}
+
+/// Number the JSON-RPC API sends as a number or a decimal string.
+fn json_u64(value: &serde_json::Value) -> Option<u64> {
+    value.as_u64().or_else(|| value.as_str()?.parse().ok())
+}
+
+fn object_ref(id: &str, version: &serde_json::Value, digest: &str) -> Result<ObjectRef, EnclaveError> {
+    let digest = Base58::decode(digest)
+        .ok()
+        .filter(|digest| digest.len() == 32)
+        .ok_or_else(|| EnclaveError::upstream("sui", format!("Invalid digest of object {}", id)))?;
+    let version = json_u64(version).ok_or_else(|| EnclaveError::upstream("sui", format!("Invalid version of object {}", id)))?;
+    Ok(ObjectRef(parse_address(id)?, version, digest))
+}
+
+/// Body of `POST /admin/register_attestation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterAttestationRequest {
+    /// Shared `EnclaveConfig<T>` object holding the expected PCRs
+    pub enclave_config_id: String,
+    /// `T` of the config, the app's one time witness such as `0x...::weather::WEATHER`
+    pub enclave_type: String,
+    /// In MIST, default 0.1 SUI
+    pub gas_budget: Option<u64>,
+}
+
+/// Response of `POST /admin/register_attestation`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterAttestationResponse {
+    /// Digest of the executed transaction
+    pub digest: String,
+    /// Address that signed and paid for it
+    pub sender: String,
+    /// Hex public key registered, the current signing key
+    pub public_key: String,
+    pub key_id: String,
+}
+
+/// Register a fresh attestation of the current signing key on Sui.
+pub async fn register_attestation_on_chain(
+    state: &AppState,
+    request: &RegisterAttestationRequest,
+) -> Result<RegisterAttestationResponse, EnclaveError> {
+    let package = parse_address(state.move_package_id())
+        .map_err(|_| EnclaveError::ConfigError("MOVE_PACKAGE_ID is not a Sui object ID".to_string()))?;
+    let config_id = parse_address(&request.enclave_config_id)?;
+    let enclave_type = parse_struct_type(&request.enclave_type)?;
+    let budget = request.gas_budget.unwrap_or(DEFAULT_REGISTRATION_GAS_BUDGET);
+    let account = SuiAccount::from_secret_key(state.sui_secret_key())?;
+
+    let key = state.keys.current();
+    let attestation = fetch_attestation(state).await?.attestation;
+    let document = Hex::decode(&attestation.attestationDocument).map_err(|_| {
+        EnclaveError::AttestationError("Registration needs an NSM attestation document".to_string())
+    })?;
+
+    let client = SuiClient::from_state(state)?;
+    let initial_shared_version = client.initial_shared_version(&config_id).await?;
+    let price = client.reference_gas_price().await?;
+    let digest = state
+        .tx_sequencer
+        .submit(&client, &account, |payment| {
+            registration_transaction(
+                package,
+                enclave_type,
+                (config_id, initial_shared_version),
+                &document,
+                account.address,
+                (payment, price, budget),
+            )
+        })
+        .await?;
+    info!("Registered the attestation of signing key {} in transaction {}", key.key_id, digest);
+    Ok(RegisterAttestationResponse {
+        digest,
+        sender: address_hex(&account.address),
+        public_key: key.public_key_hex(),
+        key_id: key.key_id.clone(),
+    })
+}
+
+/// Register the enclave's attestation on Sui and return the transaction digest. Requires
+/// the admin token.
+pub async fn register_attestation(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<RegisterAttestationRequest>,
+) -> ApiResponse<RegisterAttestationResponse> {
+    if !state.is_admin(&headers) {
+        return ctx.error(EnclaveError::Unauthorized("Admin token required".to_string()));
+    }
+    match register_attestation_on_chain(&state, &request).await {
+        Ok(registered) => {
+            state.audit_log.record(
+                "attestation_registered",
+                &registered.digest,
+                serde_json::json!({
+                    "enclaveConfigId": request.enclave_config_id,
+                    "enclaveType": request.enclave_type,
+                    "keyId": registered.key_id,
+                    "sender": registered.sender,
+                }),
+            );
+            ctx.ok(registered)
+        }
+        Err(e) => ctx.error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+    use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+    use fastcrypto::traits::VerifyingKey;
+
+    #[test]
+    fn test_account_from_secret_key() {
+        let secret = Bech32::encode([[ED25519_FLAG].as_slice(), &[7u8; 32]].concat(), SUI_PRIVATE_KEY_HRP).unwrap();
+        let account = SuiAccount::from_secret_key(&secret).unwrap();
+        let expected = Blake2b256::digest([[ED25519_FLAG].as_slice(), account.keypair.public().as_bytes()].concat());
+        assert_eq!(account.address, expected.digest);
+
+        // Other schemes and encodings are refused
+        let secp = Bech32::encode([[1u8].as_slice(), &[7u8; 32]].concat(), SUI_PRIVATE_KEY_HRP).unwrap();
+        assert!(SuiAccount::from_secret_key(&secp).is_err());
+        assert!(SuiAccount::from_secret_key("suiprivkey1qtest").is_err());
+    }
+
+    #[test]
+    fn test_registration_transaction_layout() {
+        let account = SuiAccount::new(Ed25519KeyPair::generate(&mut rand::thread_rng()));
+        let payment = ObjectRef(parse_address("0xc0").unwrap(), 3, vec![9; 32]);
+        let transaction = registration_transaction(
+            parse_address("0xabc").unwrap(),
+            parse_struct_type("0x2::sui::SUI").unwrap(),
+            (parse_address("0xc0f").unwrap(), 42),
+            &[1, 2, 3],
+            account.address,
+            (payment, 1000, DEFAULT_REGISTRATION_GAS_BUDGET),
+        );
+        let bytes = bcs::to_bytes(&transaction).unwrap();
+        // V1, programmable, three inputs, the first a pure vector<u8> of three bytes
+        assert_eq!(bytes[..7], [0, 0, 3, 0, 4, 3, 1]);
+        // Ends with the gas budget and no expiration
+        assert_eq!(bytes[bytes.len() - 9..bytes.len() - 1], DEFAULT_REGISTRATION_GAS_BUDGET.to_le_bytes());
+        assert_eq!(bytes.last(), Some(&0));
+
+        // The signature covers the Blake2b-256 of the transaction intent
+        let signature = Base64::decode(&account.sign_transaction(&transaction)).unwrap();
+        assert_eq!((signature.len(), signature[0]), (97, ED25519_FLAG));
+        let digest = Blake2b256::digest([vec![0, 0, 0], bytes].concat()).digest;
+        let public_key = Ed25519PublicKey::from_bytes(&signature[65..]).unwrap();
+        assert!(public_key.verify(&digest, &Ed25519Signature::from_bytes(&signature[1..65]).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_split_transaction_layout() {
+        let sender = parse_address("0x5e").unwrap();
+        let payment = ObjectRef(parse_address("0xc0").unwrap(), 3, vec![9; 32]);
+        let bytes = bcs::to_bytes(&split_transaction(sender, 2, 700, (payment, 1000, 5000))).unwrap();
+        // Two pure inputs: the amount and the recipient
+        assert_eq!(bytes[..5], [0, 0, 2, 0, 8]);
+        assert_eq!(bytes[5..13], 700u64.to_le_bytes());
+        // SplitCoins(GasCoin, [Input(0), Input(0)]) then TransferObjects of both results
+        let commands = 13 + 2 + 32;
+        assert_eq!(bytes[commands..commands + 8], [2, 2, 0, 2, 1, 0, 0, 1]);
+        assert_eq!(bytes[commands + 8..commands + 16], [0, 0, 1, 2, 3, 0, 0, 0]);
+    }
+
+    /// Zero padded hex of a short address.
+    fn padded(address: &str) -> String {
+        format!("{:0>64}", address)
+    }
+
+    #[test]
+    fn test_split_transaction_bytes() {
+        let sender = parse_address("0x5e").unwrap();
+        let payment = ObjectRef(parse_address("0xc0").unwrap(), 3, vec![9; 32]);
+        let transaction = split_transaction(sender, 2, 700, (payment, 1000, 5000));
+        let expected = [
+            // V1, ProgrammableTransaction, inputs: Pure(700u64), Pure(sender)
+            "0000".to_string(),
+            format!("02 0008bc02000000000000 0020{}", padded("5e")),
+            // SplitCoins(GasCoin, [Input(0), Input(0)]),
+            // TransferObjects([NestedResult(0, 0), NestedResult(0, 1)], Input(1))
+            "02 02 00 02 010000 010000 01 02 0300000000 0300000100 010100".to_string(),
+            // Sender, then gas: payment (ID, version, digest), owner, price, budget
+            padded("5e"),
+            format!("01 {} 0300000000000000 20{}", padded("c0"), "09".repeat(32)),
+            format!("{} e803000000000000 8813000000000000", padded("5e")),
+            // No expiration
+            "00".to_string(),
+        ];
+        let expected = Hex::decode(&expected.concat().replace(' ', "")).unwrap();
+        assert_eq!(bcs::to_bytes(&transaction).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_registration_transaction_bytes() {
+        let sender = parse_address("0x5e").unwrap();
+        let payment = ObjectRef(parse_address("0xc0").unwrap(), 3, vec![9; 32]);
+        let transaction = registration_transaction(
+            parse_address("0xabc").unwrap(),
+            parse_struct_type("0x2::sui::SUI").unwrap(),
+            (parse_address("0xc0f").unwrap(), 42),
+            &[1, 2, 3],
+            sender,
+            (payment, 1000, DEFAULT_REGISTRATION_GAS_BUDGET),
+        );
+        let expected = [
+            // V1, ProgrammableTransaction, inputs: Pure(vector<u8> document),
+            // SharedObject(clock, 1, immutable), SharedObject(config, 42, immutable)
+            "0000".to_string(),
+            "03 00 04 03010203".to_string(),
+            format!("01 01 {} 0100000000000000 00", padded("6")),
+            format!("01 01 {} 2a00000000000000 00", padded("c0f")),
+            // MoveCall 0x2::nitro_attestation::load_nitro_attestation(Input(0), Input(1))
+            format!("02 00 {}", padded("2")),
+            format!("11{} 16{}", Hex::encode("nitro_attestation"), Hex::encode("load_nitro_attestation")),
+            "00 02 010000 010100".to_string(),
+            // MoveCall <package>::enclave::register_enclave<0x2::sui::SUI>(Input(2), Result(0))
+            format!("00 {} 07{} 10{}", padded("abc"), Hex::encode("enclave"), Hex::encode("register_enclave")),
+            format!("01 07 {} 03{} 03{} 00", padded("2"), Hex::encode("sui"), Hex::encode("SUI")),
+            "02 010200 020000".to_string(),
+            // Sender, then gas: payment (ID, version, digest), owner, price, budget
+            padded("5e"),
+            format!("01 {} 0300000000000000 20{}", padded("c0"), "09".repeat(32)),
+            format!("{} e803000000000000 00e1f50500000000", padded("5e")),
+            // No expiration
+            "00".to_string(),
+        ];
+        let expected = Hex::decode(&expected.concat().replace(' ', "")).unwrap();
+        assert_eq!(bcs::to_bytes(&transaction).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_addresses_and_types() {
+        assert_eq!(parse_address("0x6").unwrap()[31], 6);
+        assert!(parse_address(&format!("0x{}", "1".repeat(65))).is_err());
+        assert!(parse_struct_type("0x2::sui").is_err());
+        assert!(parse_struct_type("0x2::sui::SUI<u8>").is_err());
+        assert_eq!(json_u64(&serde_json::json!("750")), Some(750));
+    }
+
+    #[tokio::test]
+    async fn test_client_fails_over_and_caches_objects() {
+        use axum::http::StatusCode;
+        use axum::routing::post;
+        use std::sync::atomic::AtomicU32;
+
+        async fn serve(app: axum::Router) -> String {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            url
+        }
+        let down = serve(axum::Router::new().route("/", post(|| async { StatusCode::SERVICE_UNAVAILABLE }))).await;
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let up = serve(axum::Router::new().route(
+            "/",
+            post(move |Json(request): Json<serde_json::Value>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let result = match request["method"].as_str().unwrap() {
+                        "sui_getObject" => serde_json::json!({ "data": {
+                            "objectId": request["params"][0], "version": "7", "digest": "d", "type": "0x2::clock::Clock",
+                            "owner": { "Shared": { "initial_shared_version": 1 } }, "content": { "fields": { "timestamp_ms": "5" } }
+                        }}),
+                        "suix_getDynamicFields" => serde_json::json!({
+                            "data": [{ "name": { "type": "u64", "value": "2" }, "objectId": "0xf1", "objectType": "0x3::S", "version": 4 }],
+                            "nextCursor": "0xf1", "hasNextPage": false
+                        }),
+                        "sui_dryRunTransactionBlock" => serde_json::json!({
+                            "effects": { "status": { "status": "failure", "error": "MoveAbort(1)" },
+                                "gasUsed": { "computationCost": "1000", "storageCost": "500", "storageRebate": "300" } },
+                            "events": []
+                        }),
+                        _ => return Json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32601 } })),
+                    };
+                    Json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+                }
+            }),
+        ))
+        .await;
+
+        let client = SuiClient::new(&[&down, &up]).unwrap();
+        let clock = client.get_object("0x6").await.unwrap().unwrap();
+        assert_eq!(clock.object_id, address_hex(&parse_address("0x6").unwrap()));
+        assert_eq!((clock.version, clock.initial_shared_version()), (7, Some(1)));
+        assert_eq!(clock.fields["timestamp_ms"], "5");
+        // Later calls go to the fullnode that answered, and fresh objects come from the cache
+        assert_eq!(client.cache.preferred.load(Ordering::Relaxed), 1);
+        assert_eq!(client.get_object("0x06").await.unwrap(), Some(clock));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        client.fetch_object("0x6").await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let page = client.get_dynamic_fields("0x6", None, Some(10)).await.unwrap();
+        assert_eq!((page.fields[0].version, page.next_cursor), (4, None));
+        let dry_run = client.dry_run("AAAA").await.unwrap();
+        assert!(!dry_run.success);
+        assert_eq!((dry_run.error.as_deref(), dry_run.gas_used), (Some("MoveAbort(1)"), 1200));
+
+        // An RPC error is the fullnode's answer, not a reason to fail over
+        assert!(client.reference_gas_price().await.is_err());
+        assert_eq!(client.cache.preferred.load(Ordering::Relaxed), 1);
+        assert!(SuiClient::new(&[]).is_err());
+    }
+
+    #[test]
+    fn test_cache_expiry_and_invalidation() {
+        let object = SuiObject::from_result(&serde_json::json!({ "data": { "objectId": "0x6", "version": 1 } }))
+            .unwrap()
+            .unwrap();
+        let key = address_hex(&parse_address("0x6").unwrap());
+        let cache = SuiCache::new(Duration::from_secs(60));
+        cache.insert(key.clone(), object.clone());
+        assert_eq!(cache.get(&key), Some(object.clone()));
+        cache.invalidate("0x6");
+        assert_eq!(cache.get(&key), None);
+
+        let disabled = SuiCache::new(Duration::ZERO);
+        disabled.insert(key.clone(), object);
+        assert_eq!(disabled.get(&key), None);
+    }
+
+    #[tokio::test]
+    async fn test_register_attestation_requires_admin() {
+        let mut state = test_app_state();
+        state.admin_token = Some("secret".to_string());
+        let state = Arc::new(state);
+        let request = RegisterAttestationRequest {
+            enclave_config_id: "0xc0f".to_string(),
+            enclave_type: "0xabc::weather::WEATHER".to_string(),
+            gas_budget: None,
+        };
+
+        let response =
+            register_attestation(RequestContext::new(None), State(state.clone()), HeaderMap::new(), Json(request.clone())).await;
+        assert!(response.error.is_some());
+
+        // The test SUI_SECRET_KEY is no valid key, which fails before anything is sent
+        let result = register_attestation_on_chain(&state, &request).await;
+        assert!(matches!(result, Err(EnclaveError::ConfigError(_))));
+        assert!(state.audit_log.recent(10).is_empty());
+    }
+}
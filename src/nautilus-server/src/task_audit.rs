@@ -0,0 +1,259 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Audit log of the tasks run inside the enclave. Every invocation is recorded with its
+//! operation, caller, duration, exit code and hashes of its arguments and result, and
+//! signed with the enclave key under [IntentScope::TaskAudit] when it is recorded, so
+//! operators can verify what ran against the attested key. Kept in memory, the oldest
+//! entries are dropped past `TASK_AUDIT_LOG_SIZE`. With `STORAGE_PATH` set, the kept entries
+//! are also persisted and sequences continue across restarts. Served on `/audit/tasks`.
+
+use crate::api_response::{ApiResponse, RequestContext};
+use crate::canonical::canonical_hash_of;
+use crate::common::{current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessedDataResponse};
+use crate::key_manager::{ResponseSigner, SignatureScheme};
+use crate::request_log::masked_payload_hash;
+use crate::storage::Storage;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Query, State};
+use fastcrypto::encoding::{Encoding, Hex};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Entries kept by default.
+pub const DEFAULT_TASK_AUDIT_LOG_SIZE: usize = 10_000;
+
+/// Entries returned by `/audit/tasks` without a `limit`.
+pub const DEFAULT_TASK_AUDIT_PAGE_SIZE: usize = 100;
+
+/// One task invocation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskAuditEntry {
+    /// Position in the log since boot, or since the storage was created, starting at 0
+    pub sequence: u64,
+    pub timestamp_ms: u64,
+    pub operation: String,
+    /// Hex SHA3-256 of the ID mask salt and the JSON task arguments
+    pub args_hash: String,
+    /// Request ID of the request that ran the task
+    pub caller: Option<String>,
+    pub duration_ms: u64,
+    /// `None` when the task could not be run or timed out
+    pub exit_code: Option<i32>,
+    /// Hex canonical JSON hash of the task result, `None` when it printed none
+    pub result_hash: Option<String>,
+}
+
+/// What is known of a task invocation once it finished.
+pub struct TaskInvocation<'a> {
+    pub operation: &'a str,
+    pub args: &'a [String],
+    pub caller: Option<String>,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+    pub result: Option<&'a serde_json::Value>,
+}
+
+/// Entry with its signature, signing key ID and scheme.
+type SignedEntry = (TaskAuditEntry, String, String, SignatureScheme);
+
+#[derive(Debug, Default)]
+struct Entries {
+    recorded: u64,
+    /// Oldest first
+    signed: VecDeque<SignedEntry>,
+}
+
+/// Fixed-size log of the latest task invocations.
+pub struct TaskAuditLog {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    storage: Option<Arc<Storage>>,
+}
+
+impl Default for TaskAuditLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_TASK_AUDIT_LOG_SIZE)
+    }
+}
+
+impl TaskAuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+            storage: None,
+        }
+    }
+
+    /// Load the latest entries persisted in `storage`, continue their sequence and persist
+    /// new entries there.
+    pub fn with_storage(mut self, storage: Arc<Storage>) -> Result<Self, EnclaveError> {
+        let entries = self.entries.get_mut().unwrap();
+        entries.recorded = storage.next_task_audit_sequence()?;
+        entries.signed = storage.task_audit_entries::<SignedEntry>(self.capacity)?.into();
+        self.storage = Some(storage);
+        Ok(self)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sign and record an invocation. Arguments are hashed with `salt`, since they can hold
+    /// user data. An entry that cannot be signed is not recorded.
+    pub fn record<S: ResponseSigner + ?Sized>(
+        &self,
+        kp: &S,
+        salt: &str,
+        invocation: TaskInvocation,
+    ) -> Result<TaskAuditEntry, EnclaveError> {
+        let args = serde_json::to_vec(invocation.args).unwrap_or_default();
+        let mut entries = self.entries.lock().unwrap();
+        let entry = TaskAuditEntry {
+            sequence: entries.recorded,
+            timestamp_ms: current_timestamp_ms(),
+            operation: invocation.operation.to_string(),
+            args_hash: masked_payload_hash(salt, &args).unwrap_or_default(),
+            caller: invocation.caller,
+            duration_ms: invocation.duration_ms,
+            exit_code: invocation.exit_code,
+            result_hash: invocation
+                .result
+                .and_then(|result| canonical_hash_of(result).ok())
+                .map(Hex::encode),
+        };
+        if self.capacity > 0 {
+            let signed = to_signed_response(kp, entry.clone(), entry.timestamp_ms, IntentScope::TaskAudit)?;
+            if entries.signed.len() == self.capacity {
+                entries.signed.pop_front();
+            }
+            let signed = (entry.clone(), signed.signature, signed.key_id, signed.scheme);
+            if let Some(storage) = &self.storage {
+                storage.append_task_audit(entry.sequence, &signed, self.capacity);
+            }
+            entries.signed.push_back(signed);
+        }
+        entries.recorded += 1;
+        Ok(entry)
+    }
+
+    /// Invocations recorded since boot or the storage was created, including those no
+    /// longer kept.
+    pub fn recorded(&self) -> u64 {
+        self.entries.lock().unwrap().recorded
+    }
+
+    /// Up to `limit` signed entries, newest first, after skipping the `offset` newest.
+    pub fn page(&self, offset: usize, limit: usize) -> Vec<ProcessedDataResponse<IntentMessage<TaskAuditEntry>>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .signed
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .map(|(entry, signature, key_id, scheme)| ProcessedDataResponse {
+                response: IntentMessage::new(entry.clone(), entry.timestamp_ms, IntentScope::TaskAudit),
+                signature: signature.clone(),
+                key_id: key_id.clone(),
+                scheme: *scheme,
+                attestation: None,
+            })
+            .collect()
+    }
+}
+
+/// Query parameters of `/audit/tasks`.
+#[derive(Debug, Deserialize)]
+pub struct TaskAuditQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Response of `/audit/tasks`.
+#[derive(Serialize, Deserialize)]
+pub struct TaskAuditResponse {
+    /// Invocations recorded since boot or the storage was created; the oldest are dropped
+    /// past `capacity`
+    pub recorded: u64,
+    pub capacity: usize,
+    /// Newest first, each signed under [IntentScope::TaskAudit]
+    pub entries: Vec<ProcessedDataResponse<IntentMessage<TaskAuditEntry>>>,
+}
+
+/// Recorded task invocations, newest first. Entries hold only hashes of arguments and
+/// results, so the log is public like the attestation it is verified against.
+pub async fn task_audit(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TaskAuditQuery>,
+) -> ApiResponse<TaskAuditResponse> {
+    let log = &state.task_audit;
+    ctx.ok(TaskAuditResponse {
+        recorded: log.recorded(),
+        capacity: log.capacity(),
+        entries: log.page(
+            query.offset.unwrap_or(0),
+            query.limit.unwrap_or(DEFAULT_TASK_AUDIT_PAGE_SIZE),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519Signature};
+    use fastcrypto::traits::{KeyPair, ToFromBytes, VerifyingKey};
+
+    fn invocation<'a>(operation: &'a str, args: &'a [String], result: Option<&'a serde_json::Value>) -> TaskInvocation<'a> {
+        TaskInvocation {
+            operation,
+            args,
+            caller: Some("req-1".to_string()),
+            duration_ms: 20,
+            exit_code: Some(0),
+            result,
+        }
+    }
+
+    #[test]
+    fn test_records_signed_entries() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let log = TaskAuditLog::new(2);
+        let args = vec!["--blob-id".to_string(), "abc".to_string()];
+        let result = serde_json::json!({ "status": "success" });
+        let first = log.record(&kp, "salt", invocation("process_data", &args, Some(&result))).unwrap();
+        assert_eq!(first.sequence, 0);
+        assert_eq!(Some(first.args_hash.clone()), masked_payload_hash("salt", &serde_json::to_vec(&args).unwrap()));
+        assert_eq!(first.result_hash, Some(Hex::encode(canonical_hash_of(&result).unwrap())));
+
+        log.record(&kp, "salt", invocation("embedding_ingest", &args, None)).unwrap();
+        log.record(&kp, "salt", invocation("retrieve_messages_filtered", &[], None)).unwrap();
+        assert_eq!(log.recorded(), 3);
+
+        let page = log.page(0, 10);
+        let sequences: Vec<u64> = page.iter().map(|e| e.response.data.sequence).collect();
+        assert_eq!(sequences, vec![2, 1]);
+        assert_eq!(log.page(1, 10).len(), 1);
+        assert!(log.page(2, 10).is_empty());
+
+        let signed = &page[1];
+        assert_eq!(signed.response.intent, IntentScope::TaskAudit);
+        assert_eq!(signed.response.data.result_hash, None);
+        let signature = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+        let message = bcs::to_bytes(&signed.response).unwrap();
+        assert!(kp.public().verify(&message, &signature).is_ok());
+    }
+
+    #[test]
+    fn test_zero_capacity_counts_only() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let log = TaskAuditLog::new(0);
+        log.record(&kp, "salt", invocation("process_data", &[], None)).unwrap();
+        assert_eq!(log.recorded(), 1);
+        assert!(log.page(0, 10).is_empty());
+    }
+}
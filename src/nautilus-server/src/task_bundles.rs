@@ -0,0 +1,214 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Task bundles: directories of Node.js task code, each with its own `index.js`, lockfile
+//! and dependencies. Every operation runs the default bundle, `nodejs-task`, unless
+//! `TASK_BUNDLES` maps it to another one, so unrelated workloads do not have to share one
+//! monolithic task:
+//!
+//! ```json
+//! [{"name": "search", "path": "search-task", "operations": ["retrieve_messages_filtered"],
+//!   "env": ["QDRANT_URL", "QDRANT_API_KEY", "QDRANT_COLLECTION_NAME", "QDRANT_SEARCH_PARAMS"]}]
+//! ```
+//!
+//! Each bundle is measured on its own: the hashes of configured bundles are part of
+//! [crate::build_info::BuildInfo] and so of the attestation `user_data`. `env` is the
+//! bundle's env policy; when given, its tasks only get the listed variables. The
+//! dependencies of each bundle are checked against the allowlist in its own directory, and
+//! the warm worker pool only runs the default bundle.
+
+use crate::dependency_allowlist::{check_dependencies, DependencyStatus, DEFAULT_ALLOWLIST_FILE};
+use crate::task_env::Operation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Name of the bundle running the operations `TASK_BUNDLES` does not map.
+pub const DEFAULT_BUNDLE: &str = "default";
+
+/// Directory of the default bundle, in the working directory.
+pub const DEFAULT_TASK_DIR: &str = "nodejs-task";
+
+/// A task directory and the operations it runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskBundle {
+    pub name: String,
+    /// Task directory, relative paths are taken from the working directory
+    pub path: PathBuf,
+    /// Operations run by the bundle, see [Operation::name]
+    #[serde(default)]
+    pub operations: Vec<String>,
+    /// Variables passed to the bundle's tasks, all of them when unset
+    #[serde(default)]
+    pub env: Option<BTreeSet<String>>,
+}
+
+impl TaskBundle {
+    /// `env_vars` narrowed to the variables the bundle's env policy allows.
+    pub fn filter_env(&self, mut env_vars: HashMap<String, String>) -> HashMap<String, String> {
+        if let Some(allowed) = &self.env {
+            env_vars.retain(|name, _| allowed.contains(name));
+        }
+        env_vars
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_BUNDLE
+    }
+}
+
+/// The default bundle and the bundles configured in `TASK_BUNDLES`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskBundles {
+    default: TaskBundle,
+    configured: Vec<TaskBundle>,
+}
+
+impl TaskBundles {
+    /// Only the default bundle in `root`.
+    pub fn single(root: &Path) -> Self {
+        Self {
+            default: TaskBundle {
+                name: DEFAULT_BUNDLE.to_string(),
+                path: root.join(DEFAULT_TASK_DIR),
+                operations: Vec::new(),
+                env: None,
+            },
+            configured: Vec::new(),
+        }
+    }
+
+    /// The default bundle in `root` plus `configured`, whose relative paths are resolved
+    /// against `root`. Validates bundle names and that each operation is mapped once.
+    pub fn new(root: &Path, configured: Vec<TaskBundle>) -> anyhow::Result<Self> {
+        let mut bundles = Self::single(root);
+        for (i, bundle) in configured.iter().enumerate() {
+            if bundle.name.is_empty() || bundle.name == DEFAULT_BUNDLE {
+                anyhow::bail!("Invalid task bundle name {:?}", bundle.name);
+            }
+            if configured[..i].iter().any(|b| b.name == bundle.name) {
+                anyhow::bail!("Duplicate task bundle {:?}", bundle.name);
+            }
+            if bundle.operations.is_empty() {
+                anyhow::bail!("Task bundle {:?} runs no operation", bundle.name);
+            }
+            for operation in &bundle.operations {
+                if Operation::from_name(operation).is_none() {
+                    anyhow::bail!("Unknown operation {:?} in task bundle {:?}", operation, bundle.name);
+                }
+                if configured[..i].iter().any(|b| b.operations.contains(operation)) {
+                    anyhow::bail!("Operation {:?} is mapped to more than one task bundle", operation);
+                }
+            }
+        }
+        bundles.configured = configured
+            .into_iter()
+            .map(|bundle| TaskBundle {
+                path: root.join(&bundle.path),
+                ..bundle
+            })
+            .collect();
+        Ok(bundles)
+    }
+
+    /// Parse bundles from a JSON array, as given in `TASK_BUNDLES`.
+    pub fn from_json(root: &Path, json: &str) -> anyhow::Result<Self> {
+        Self::new(root, serde_json::from_str(json)?)
+    }
+
+    pub fn default_bundle(&self) -> &TaskBundle {
+        &self.default
+    }
+
+    /// Bundles from `TASK_BUNDLES`, without the default one.
+    pub fn configured(&self) -> &[TaskBundle] {
+        &self.configured
+    }
+
+    /// Every bundle, the default one first.
+    pub fn all(&self) -> impl Iterator<Item = &TaskBundle> {
+        std::iter::once(&self.default).chain(&self.configured)
+    }
+
+    /// Bundle running `operation`.
+    pub fn for_operation(&self, operation: Operation) -> &TaskBundle {
+        self.configured
+            .iter()
+            .find(|bundle| bundle.operations.iter().any(|o| o == operation.name()))
+            .unwrap_or(&self.default)
+    }
+
+    /// Check the dependencies of every bundle. The default bundle is checked against
+    /// `default_allowlist`, the others against the allowlist in their directory. A rejected
+    /// bundle rejects them all, as tasks are refused on the combined status.
+    pub fn check_dependencies(&self, default_allowlist: &Path, public_key: Option<&str>) -> DependencyStatus {
+        let mut status = check_dependencies(&self.default.path, default_allowlist, public_key);
+        for bundle in &self.configured {
+            let bundle_status = match check_dependencies(&bundle.path, &bundle.path.join(DEFAULT_ALLOWLIST_FILE), public_key) {
+                DependencyStatus::Rejected { reason } => DependencyStatus::Rejected {
+                    reason: format!("task bundle {}: {}", bundle.name, reason),
+                },
+                other => other,
+            };
+            status = status.merge(bundle_status);
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_env::SHARED_ENV_VARS;
+
+    #[test]
+    fn test_operation_mapping() {
+        let root = Path::new("/app");
+        let bundles = TaskBundles::from_json(
+            root,
+            r#"[{"name": "search", "path": "search-task", "operations": ["retrieve_messages_filtered"], "env": ["QDRANT_URL"]}]"#,
+        )
+        .unwrap();
+
+        let search = bundles.for_operation(Operation::RetrieveMessagesFiltered);
+        assert_eq!(search.name, "search");
+        assert_eq!(search.path, Path::new("/app/search-task"));
+        let ingest = bundles.for_operation(Operation::EmbeddingIngest);
+        assert!(ingest.is_default());
+        assert_eq!(ingest.path, Path::new("/app/nodejs-task"));
+        assert_eq!(bundles.all().count(), 2);
+
+        let single = TaskBundles::single(root);
+        assert!(Operation::ALL.iter().all(|o| single.for_operation(*o).is_default()));
+    }
+
+    #[test]
+    fn test_invalid_bundles() {
+        let root = Path::new("/app");
+        let bundle = |name: &str, operations: &[&str]| TaskBundle {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            operations: operations.iter().map(|o| o.to_string()).collect(),
+            env: None,
+        };
+        assert!(TaskBundles::new(root, vec![bundle("default", &["process_data"])]).is_err());
+        assert!(TaskBundles::new(root, vec![bundle("a", &[])]).is_err());
+        assert!(TaskBundles::new(root, vec![bundle("a", &["scoring"])]).is_err());
+        assert!(TaskBundles::new(root, vec![bundle("a", &["process_data"]), bundle("a", &["embedding_ingest"])]).is_err());
+        assert!(TaskBundles::new(root, vec![bundle("a", &["process_data"]), bundle("b", &["process_data"])]).is_err());
+        assert!(TaskBundles::from_json(root, r#"[{"name": "a", "path": "a", "operations": ["process_data"], "nice": 1}]"#).is_err());
+        assert!(TaskBundles::new(root, vec![bundle("a", &["process_data"]), bundle("b", &["embedding_ingest"])]).is_ok());
+    }
+
+    #[test]
+    fn test_env_policy() {
+        let env: HashMap<String, String> = SHARED_ENV_VARS.iter().map(|n| (n.to_string(), "v".to_string())).collect();
+        let mut bundle = TaskBundles::single(Path::new("/app")).default_bundle().clone();
+        assert_eq!(bundle.filter_env(env.clone()), env);
+
+        bundle.env = Some(["QDRANT_URL".to_string(), "NOT_SET".to_string()].into());
+        let filtered = bundle.filter_env(env);
+        assert_eq!(filtered.keys().collect::<Vec<_>>(), ["QDRANT_URL"]);
+    }
+}
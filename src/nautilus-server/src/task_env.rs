@@ -0,0 +1,143 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Environment passed to the Node.js task of each operation, built by
+//! [crate::AppState::task_env_vars] so the handlers cannot drift apart. The variables every
+//! operation needs are listed in [SHARED_ENV_VARS]; [Operation::required_env_vars] adds the
+//! ones of a single operation, and tests check that the built environment sets all of them.
+
+/// Variable carrying the `x-request-id` of the request that runs the task, set whatever the
+/// bundle's env policy so task log lines can be matched with the server's.
+pub const REQUEST_ID_ENV: &str = "REQUEST_ID";
+
+/// Operations run as Node.js tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    ProcessData,
+    EmbeddingIngest,
+    RetrieveMessagesByBlobIds,
+    RetrieveMessagesFiltered,
+}
+
+/// Variables set for every operation. `QDRANT_API_KEY` is also passed when configured.
+pub const SHARED_ENV_VARS: &[&str] = &[
+    "MOVE_PACKAGE_ID",
+    "SUI_SECRET_KEY",
+    "RUBY_NODES_API_KEY",
+    "WALRUS_AGGREGATOR_URL",
+    "WALRUS_PUBLISHER_URL",
+    "WALRUS_EPOCHS",
+    "EMBEDDING_PROVIDER",
+    "OLLAMA_API_URL",
+    "OLLAMA_MODEL",
+    "AZURE_TEXT_EMBEDDING_API_ENDPOINT",
+    "AZURE_TEXT_EMBEDDING_API_KEY",
+    "QDRANT_URL",
+    "QDRANT_COLLECTION_NAME",
+    "EMBEDDING_BATCH_SIZE",
+    "VECTOR_BATCH_SIZE",
+    "TELEGRAM_SOCIAL_TRUTH_BOT_ID",
+    "ID_MASK_SALT",
+];
+
+impl Operation {
+    pub const ALL: [Operation; 4] = [
+        Operation::ProcessData,
+        Operation::EmbeddingIngest,
+        Operation::RetrieveMessagesByBlobIds,
+        Operation::RetrieveMessagesFiltered,
+    ];
+
+    /// Name used for the operation in metrics, logs and per-operation settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::ProcessData => "process_data",
+            Operation::EmbeddingIngest => "embedding_ingest",
+            Operation::RetrieveMessagesByBlobIds => "retrieve_messages_by_blob_ids",
+            Operation::RetrieveMessagesFiltered => "retrieve_messages_filtered",
+        }
+    }
+
+    /// Operation with the given [Operation::name].
+    pub fn from_name(name: &str) -> Option<Operation> {
+        Operation::ALL.into_iter().find(|operation| operation.name() == name)
+    }
+
+    /// Whether the task stores or searches vectors, and so needs the vector privacy settings.
+    pub fn uses_vectors(&self) -> bool {
+        matches!(self, Operation::EmbeddingIngest | Operation::RetrieveMessagesFiltered)
+    }
+
+    /// Variables always set for this operation, on top of [SHARED_ENV_VARS]. Optional
+    /// settings, such as HNSW parameters or vector privacy, are only passed when configured.
+    pub fn required_env_vars(&self) -> Vec<&'static str> {
+        let own: &[&str] = match self {
+            Operation::ProcessData => &[],
+            Operation::EmbeddingIngest => &["QDRANT_DISTANCE"],
+            Operation::RetrieveMessagesByBlobIds | Operation::RetrieveMessagesFiltered => &["QDRANT_SEARCH_PARAMS"],
+        };
+        SHARED_ENV_VARS.iter().chain(own).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::{SearchParams, VectorPrivacy};
+    use crate::common::IntentScope;
+    use crate::config::ApiKey;
+    use crate::test_app_state;
+
+    #[test]
+    fn test_required_env_vars_are_set() {
+        let state = test_app_state();
+        for operation in Operation::ALL {
+            let env = state.task_env_vars(operation, "messages");
+            for name in operation.required_env_vars() {
+                assert!(env.contains_key(name), "{} is missing for {}", name, operation.name());
+            }
+            assert_eq!(env["QDRANT_COLLECTION_NAME"], "messages");
+            assert_eq!(env["MOVE_PACKAGE_ID"], state.move_package_id());
+            assert_eq!(env["ID_MASK_SALT"], state.id_mask_salt());
+        }
+    }
+
+    #[test]
+    fn test_operation_specific_env_vars() {
+        let mut state = test_app_state();
+        state.config.qdrant_api_key = Some(ApiKey::new("qdrant-key".to_string()));
+        state.config.vector_privacy = VectorPrivacy {
+            noise_scale: 0.1,
+            ..Default::default()
+        };
+        state.collection_tuning.set("docs", SearchParams { hnsw_ef: Some(256), exact: None });
+        state.config.qdrant_collection_settings.embedding_dimensions = Some(512);
+
+        let process = state.task_env_vars(Operation::ProcessData, "docs");
+        assert_eq!(process["QDRANT_API_KEY"], "qdrant-key");
+        assert!(!process.contains_key("QDRANT_SEARCH_PARAMS"));
+        assert!(!process.contains_key("VECTOR_NOISE_SCALE"));
+        assert!(!process.contains_key("EMBEDDING_DIMENSIONS"));
+
+        let ingest = state.task_env_vars(Operation::EmbeddingIngest, "docs");
+        assert!(ingest.contains_key("QDRANT_DISTANCE"));
+        assert_eq!(ingest["VECTOR_NOISE_SCALE"], "0.1");
+        assert_eq!(ingest["EMBEDDING_DIMENSIONS"], "512");
+
+        let by_blob = state.task_env_vars(Operation::RetrieveMessagesByBlobIds, "docs");
+        assert_eq!(by_blob["QDRANT_SEARCH_PARAMS"], r#"{"hnsw_ef":256}"#);
+        assert!(!by_blob.contains_key("VECTOR_NOISE_SCALE"));
+
+        let filtered = state.task_env_vars(Operation::RetrieveMessagesFiltered, "docs");
+        assert_eq!(filtered["QDRANT_SEARCH_PARAMS"], r#"{"hnsw_ef":256}"#);
+        assert_eq!(filtered["VECTOR_NOISE_SCALE"], "0.1");
+        assert_eq!(filtered["EMBEDDING_DIMENSIONS"], "512");
+    }
+
+    #[test]
+    fn test_operation_names_have_intent_scopes() {
+        for operation in Operation::ALL {
+            assert_ne!(IntentScope::for_operation(operation.name()), IntentScope::Generic);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Schemas of the results Node.js tasks report. A result that was no valid JSON, or no
+//! result at all, used to be passed on as a `"failed"` blob with the raw output, which a
+//! client could not tell from a task reporting its own failure. [parse_result] checks the
+//! result against the schema of its operation instead, and refuses garbage with 502
+//! `invalid_task_result` listing each problem. A task that exits non-zero without a result
+//! fails with `task_failed`, and one whose [TaskHello] names an unsupported protocol
+//! version with `task_protocol_mismatch`.
+//!
+//! The schemas cover the fields the server and clients rely on: status, counts, blob IDs
+//! and point IDs. The result is returned as the task reported it, other fields included.
+//! They are published as JSON Schemas at `/schemas`, see [crate::schemas].
+
+use crate::task_runner::{
+    TaskHello, TaskOutput, TaskProtocolResponse, TASK_PROTOCOL_FRAME, TASK_RESULT_END, TASK_RESULT_START,
+};
+use crate::validation::{FieldError, FieldErrors, Validate};
+use crate::EnclaveError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Outcome a task reports for its operation, or for one message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultStatus {
+    Success,
+    /// Some quilt patches failed
+    Partial,
+    Failed,
+}
+
+/// Result of an `embedding` task.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingResult {
+    pub status: ResultStatus,
+    pub operation: String,
+    /// Messages embedded and stored
+    pub processed_count: Option<u64>,
+    pub total_messages: Option<u64>,
+    pub successful_embeddings: Option<u64>,
+    pub successful_vector_storages: Option<u64>,
+    /// Parameters of the collection the task created, when it did
+    #[schema(value_type = Option<Object>)]
+    pub collection_created: Option<serde_json::Value>,
+    pub error: Option<String>,
+    /// Class of the error, `transient` for failures a retry may get past
+    pub error_class: Option<String>,
+}
+
+impl Validate for EmbeddingResult {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.check("operation", check_operation(&self.operation, "embedding"));
+        for (field, count) in [
+            ("processedCount", self.processed_count),
+            ("successfulEmbeddings", self.successful_embeddings),
+            ("successfulVectorStorages", self.successful_vector_storages),
+        ] {
+            errors.check(field, check_at_most(count, self.total_messages, "totalMessages"));
+        }
+        errors.into_vec()
+    }
+}
+
+/// Blob file pair a retrieval was asked for.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RequestedPair {
+    pub walrus_blob_id: String,
+    pub on_chain_file_obj_id: String,
+    pub policy_object_id: String,
+    pub message_indices: Option<Vec<u32>>,
+}
+
+/// One message of a retrieval by blob IDs. Its content is left out of the schema.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RetrievedMessage {
+    pub walrus_blob_id: String,
+    pub on_chain_file_obj_id: String,
+    pub policy_object_id: String,
+    /// None when the whole file failed
+    pub message_index: Option<u64>,
+    pub status: ResultStatus,
+    pub error: Option<String>,
+}
+
+/// Result of a `retrieve-by-blob-ids` task.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RetrievalResult {
+    pub status: ResultStatus,
+    pub operation: String,
+    #[serde(default)]
+    pub requested_pairs: Vec<RequestedPair>,
+    #[serde(default)]
+    pub results: Vec<RetrievedMessage>,
+    pub total_files_processed: Option<u64>,
+    pub total_messages_retrieved: Option<u64>,
+    pub successful_retrievals: Option<u64>,
+    pub failed_retrievals: Option<u64>,
+    pub error: Option<String>,
+    /// Class of the error, `transient` for failures a retry may get past
+    #[serde(rename = "errorClass")]
+    pub error_class: Option<String>,
+}
+
+impl Validate for RetrievalResult {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.check("operation", check_operation(&self.operation, "retrieve-by-blob-ids"));
+        let total = self.results.len() as u64;
+        errors.check("total_messages_retrieved", check_equal(self.total_messages_retrieved, total));
+        let successful = self.results.iter().filter(|message| message.status == ResultStatus::Success).count() as u64;
+        errors.check("successful_retrievals", check_equal(self.successful_retrievals, successful));
+        errors.check("failed_retrievals", check_equal(self.failed_retrievals, total - successful));
+        for (i, message) in self.results.iter().enumerate() {
+            if message.status == ResultStatus::Partial {
+                errors.check(format!("results[{}].status", i), Err("must be success or failed".to_string()));
+            }
+            let requested = self.requested_pairs.is_empty()
+                || self.requested_pairs.iter().any(|pair| pair.walrus_blob_id == message.walrus_blob_id);
+            if !requested {
+                errors.check(
+                    format!("results[{}].walrus_blob_id", i),
+                    Err(format!("blob {} was not requested", message.walrus_blob_id)),
+                );
+            }
+        }
+        errors.into_vec()
+    }
+}
+
+/// Qdrant point ID, an unsigned integer or a UUID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum PointId {
+    Num(u64),
+    Uuid(String),
+}
+
+/// One match of a filtered retrieval.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScoredMessage {
+    pub id: PointId,
+    pub score: f64,
+    pub original_blob_id: Option<String>,
+    pub message_index: Option<u64>,
+}
+
+/// Result of a `retrieve-filtered` task.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FilteredRetrievalResult {
+    pub status: ResultStatus,
+    pub operation: String,
+    #[serde(default)]
+    pub results: Vec<ScoredMessage>,
+    pub total_results: Option<u64>,
+    pub limit: Option<u64>,
+    pub error: Option<String>,
+    /// Class of the error, `transient` for failures a retry may get past
+    #[serde(rename = "errorClass")]
+    pub error_class: Option<String>,
+}
+
+impl Validate for FilteredRetrievalResult {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.check("operation", check_operation(&self.operation, "retrieve-filtered"));
+        let total = self.results.len() as u64;
+        errors.check("total_results", check_equal(self.total_results, total));
+        errors.check("results", check_at_most(Some(total), self.limit, "limit"));
+        for (i, message) in self.results.iter().enumerate() {
+            if !message.score.is_finite() {
+                errors.check(format!("results[{}].score", i), Err("must be a finite number".to_string()));
+            }
+        }
+        errors.into_vec()
+    }
+}
+
+/// Results of `process_data`, whose arguments and so results are up to the caller, are
+/// only checked to be JSON.
+impl Validate for serde_json::Value {}
+
+fn check_operation(operation: &str, expected: &str) -> Result<(), String> {
+    match operation == expected {
+        true => Ok(()),
+        false => Err(format!("must be {:?}, got {:?}", expected, operation)),
+    }
+}
+
+fn check_equal(count: Option<u64>, actual: u64) -> Result<(), String> {
+    match count {
+        Some(count) if count != actual => Err(format!("is {} but the results hold {}", count, actual)),
+        _ => Ok(()),
+    }
+}
+
+fn check_at_most(count: Option<u64>, max: Option<u64>, max_field: &str) -> Result<(), String> {
+    match (count, max) {
+        (Some(count), Some(max)) if count > max => Err(format!("is {}, more than {} {}", count, max_field, max)),
+        _ => Ok(()),
+    }
+}
+
+/// The result the task reported on stdout, from its protocol frame or between the result
+/// delimiters. None when it reported none, an error when what it reported is no JSON.
+pub fn extract(stdout: &str) -> Option<Result<serde_json::Value, String>> {
+    if let Some(response) = TaskProtocolResponse::from_stdout(stdout) {
+        return Some(Ok(response.result));
+    }
+    if stdout.contains(TASK_PROTOCOL_FRAME) {
+        return Some(Err("no response frame holds a valid response of this protocol version".to_string()));
+    }
+    let start = stdout.find(TASK_RESULT_START)? + TASK_RESULT_START.len();
+    let Some(end) = stdout[start..].find(TASK_RESULT_END) else {
+        return Some(Err(format!("{} without {}", TASK_RESULT_START, TASK_RESULT_END)));
+    };
+    Some(serde_json::from_str(stdout[start..start + end].trim()).map_err(|e| format!("not valid JSON: {}", e)))
+}
+
+/// The result of `output` checked against `T`, with the JSON the task reported.
+pub fn parse_result<T: DeserializeOwned + Validate>(output: &TaskOutput) -> Result<(T, serde_json::Value), EnclaveError> {
+    let invalid = |field: &str, message: String| {
+        EnclaveError::InvalidTaskResult(vec![FieldError {
+            field: field.to_string(),
+            message,
+        }])
+    };
+    let stdout = output.stdout_text();
+    // A task of another protocol version prints nothing this server can parse
+    if let Some(hello) = TaskHello::from_stdout(&stdout).filter(|hello| !hello.is_supported()) {
+        return Err(EnclaveError::TaskProtocolMismatch {
+            protocol: hello.protocol,
+            task_version: hello.task_version,
+        });
+    }
+    let json = match extract(&stdout) {
+        Some(Ok(json)) => json,
+        Some(Err(message)) => return Err(invalid("result", message)),
+        None if output.exit_code != 0 => {
+            return Err(EnclaveError::TaskFailed {
+                exit_code: output.exit_code,
+                stderr: output.stderr_text().into_owned(),
+            })
+        }
+        None => return Err(invalid("result", "the task exited without reporting a result".to_string())),
+    };
+    let result: T = serde_json::from_value(json.clone()).map_err(|e| invalid("result", e.to_string()))?;
+    let errors = result.field_errors();
+    if !errors.is_empty() {
+        let errors = errors
+            .into_iter()
+            .map(|error| FieldError {
+                field: format!("result.{}", error.field),
+                message: error.message,
+            })
+            .collect();
+        return Err(EnclaveError::InvalidTaskResult(errors));
+    }
+    Ok((result, json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_runner::{MIN_TASK_PROTOCOL_VERSION, TASK_PROTOCOL_VERSION};
+    use serde_json::json;
+
+    fn output(exit_code: i32, result: &serde_json::Value) -> TaskOutput {
+        let stdout = format!("Loading...\n{}{}\n", TASK_PROTOCOL_FRAME, json!({ "version": 1, "result": result }));
+        TaskOutput {
+            stdout: stdout.into_bytes(),
+            stderr: Vec::new(),
+            exit_code,
+            execution_time_ms: 0,
+            resource_usage: None,
+            stdout_stats: Default::default(),
+            stderr_stats: Default::default(),
+            retried_attempts: Vec::new(),
+        }
+    }
+
+    fn invalid_fields(error: EnclaveError) -> Vec<String> {
+        match error {
+            EnclaveError::InvalidTaskResult(fields) => fields.into_iter().map(|f| f.field).collect(),
+            other => panic!("expected an invalid task result, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(extract("===TASK_RESULT_START===\n{\"a\":1}\n===TASK_RESULT_END==="), Some(Ok(json!({ "a": 1 }))));
+        assert!(matches!(extract("===TASK_RESULT_START===\n{oops\n===TASK_RESULT_END==="), Some(Err(_))));
+        assert!(matches!(extract("===TASK_RESULT_START===\n{}"), Some(Err(_))));
+        assert!(matches!(extract("===TASK_PROTOCOL_RESPONSE===garbage"), Some(Err(_))));
+        assert_eq!(extract("no result"), None);
+    }
+
+    #[test]
+    fn test_retrieval_result() {
+        let pair = json!({ "walrus_blob_id": "b1", "on_chain_file_obj_id": "0xf", "policy_object_id": "0xa", "message_indices": null });
+        let message = |blob: &str, status: &str| {
+            json!({ "walrus_blob_id": blob, "on_chain_file_obj_id": "0xf", "policy_object_id": "0xa", "message_index": 0, "status": status, "message": { "text": "hi" } })
+        };
+        let result = json!({
+            "status": "success",
+            "operation": "retrieve-by-blob-ids",
+            "requested_pairs": [pair],
+            "results": [message("b1", "success"), message("b1", "failed")],
+            "total_messages_retrieved": 2,
+            "successful_retrievals": 1,
+            "failed_retrievals": 1,
+        });
+        let (parsed, json) = parse_result::<RetrievalResult>(&output(0, &result)).unwrap();
+        assert_eq!(parsed.status, ResultStatus::Success);
+        assert_eq!(json["results"][0]["message"]["text"], "hi");
+
+        let mut wrong = result.clone();
+        wrong["results"][1] = message("b2", "failed");
+        wrong["successful_retrievals"] = json!(2);
+        assert_eq!(
+            invalid_fields(parse_result::<RetrievalResult>(&output(0, &wrong)).unwrap_err()),
+            vec!["result.successful_retrievals", "result.results[1].walrus_blob_id"]
+        );
+        let garbage = parse_result::<RetrievalResult>(&output(0, &json!({ "status": 3 }))).unwrap_err();
+        assert_eq!(invalid_fields(garbage), vec!["result"]);
+        assert!(parse_result::<EmbeddingResult>(&output(0, &result)).is_err());
+    }
+
+    #[test]
+    fn test_missing_result() {
+        let mut crashed = output(134, &json!(null));
+        crashed.stdout = b"Loading...".to_vec();
+        assert!(matches!(parse_result::<serde_json::Value>(&crashed), Err(EnclaveError::TaskFailed { exit_code: 134, .. })));
+        crashed.exit_code = 0;
+        assert_eq!(invalid_fields(parse_result::<serde_json::Value>(&crashed).unwrap_err()), vec!["result"]);
+    }
+
+    #[test]
+    fn test_protocol_mismatch() {
+        let mut newer = output(1, &json!(null));
+        newer.stdout = b"===TASK_PROTOCOL_HELLO==={\"protocol\":2,\"taskVersion\":\"2.0.0\"}\n===TASK_PROTOCOL_RESPONSE==={\"version\":2}".to_vec();
+        let error = parse_result::<serde_json::Value>(&newer).unwrap_err();
+        assert!(matches!(&error, EnclaveError::TaskProtocolMismatch { protocol: 2, task_version: Some(v) } if v == "2.0.0"));
+        assert_eq!(error.code(), "task_protocol_mismatch");
+        assert_eq!(
+            error.details().unwrap(),
+            json!({
+                "taskProtocol": 2,
+                "taskVersion": "2.0.0",
+                "supportedProtocols": [MIN_TASK_PROTOCOL_VERSION, TASK_PROTOCOL_VERSION],
+            })
+        );
+
+        let mut current = output(0, &json!({ "a": 1 }));
+        current.stdout.splice(0..0, b"===TASK_PROTOCOL_HELLO==={\"protocol\":1}\n".iter().copied());
+        assert!(parse_result::<serde_json::Value>(&current).is_ok());
+    }
+
+    #[test]
+    fn test_filtered_result() {
+        let filtered = json!({
+            "status": "success",
+            "operation": "retrieve-filtered",
+            "limit": 1,
+            "results": [{ "id": 7, "score": 0.5 }, { "id": "4f0e7a5c-2d5e-4c1b-9d8e-1f2a3b4c5d6e", "score": 0.4 }],
+            "total_results": 2,
+        });
+        assert_eq!(
+            invalid_fields(parse_result::<FilteredRetrievalResult>(&output(0, &filtered)).unwrap_err()),
+            vec!["result.results"]
+        );
+        let mut within_limit = filtered.clone();
+        within_limit["limit"] = json!(10);
+        let (parsed, _) = parse_result::<FilteredRetrievalResult>(&output(0, &within_limit)).unwrap();
+        assert_eq!(parsed.results[0].id, PointId::Num(7));
+    }
+}
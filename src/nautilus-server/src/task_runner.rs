@@ -1,27 +1,576 @@
 use anyhow::{Context, Result};
+use fastcrypto::encoding::{Base64, Encoding};
 use std::path::PathBuf;
 use std::process::Stdio;
-use tokio::process::Command as TokioCommand;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command as TokioCommand};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
+/// Delimiters around the JSON result a task prints to stdout.
+pub const TASK_RESULT_START: &str = "===TASK_RESULT_START===";
+pub const TASK_RESULT_END: &str = "===TASK_RESULT_END===";
+/// Delimiters around the phase timings a task prints when it exits.
+pub const TASK_TIMELINE_START: &str = "===TASK_TIMELINE_START===";
+pub const TASK_TIMELINE_END: &str = "===TASK_TIMELINE_END===";
+/// Prefix of the line a blob retrieval task prints with the results of each blob.
+pub const BLOB_RESULT_PREFIX: &str = "===BLOB_RESULT===";
+/// Static Node.js binary shipped in the enclave image.
+pub const NODE_BINARY: &str = "/nodejs/bin/node";
+/// Version of [TaskProtocolRequest] and [TaskProtocolResponse]. Bump it when either
+/// changes incompatibly; responses of versions outside [SUPPORTED_TASK_PROTOCOL_VERSIONS]
+/// are ignored.
+pub const TASK_PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version of tasks the server still understands.
+pub const MIN_TASK_PROTOCOL_VERSION: u32 = 1;
+/// Protocol versions a task may report in its [TaskHello].
+pub const SUPPORTED_TASK_PROTOCOL_VERSIONS: std::ops::RangeInclusive<u32> =
+    MIN_TASK_PROTOCOL_VERSION..=TASK_PROTOCOL_VERSION;
+/// Prefix of the stdout line carrying the [TaskHello] JSON.
+pub const TASK_HELLO_FRAME: &str = "===TASK_PROTOCOL_HELLO===";
+/// Environment variable telling a task process to read its [TaskProtocolRequest] from stdin.
+pub const TASK_PROTOCOL_ENV: &str = "NAUTILUS_TASK_PROTOCOL";
+/// Prefix of the stdout line carrying the [TaskProtocolResponse] JSON.
+pub const TASK_PROTOCOL_FRAME: &str = "===TASK_PROTOCOL_RESPONSE===";
+/// Largest total size of client supplied task arguments, half of the 2 MiB ARG_MAX of a
+/// default Linux stack, which arguments share with the task environment. Longer argument
+/// lists would fail to spawn; such payloads go in the [TaskProtocolRequest] input.
+pub const MAX_TASK_ARGS_BYTES: usize = 1024 * 1024;
+
+/// Request written as one JSON document to the task's stdin, which is then closed. It
+/// carries inputs too large or too private for argv, like blob file pairs and queries,
+/// which would otherwise show up in the process list. Pool workers get it in the `run`
+/// call instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskProtocolRequest {
+    pub version: u32,
+    /// Named inputs of the operation, `null` when it has none
+    pub input: serde_json::Value,
+}
+
+impl TaskProtocolRequest {
+    pub fn new(input: serde_json::Value) -> Self {
+        Self { version: TASK_PROTOCOL_VERSION, input }
+    }
+}
+
+/// Handshake a task prints as a [TASK_HELLO_FRAME] line when it starts, before reading
+/// its request, so a task from another image version is reported as such instead of
+/// failing to parse.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskHello {
+    /// Protocol version the task speaks
+    pub protocol: u32,
+    /// Version of the task package
+    #[serde(default)]
+    pub task_version: Option<String>,
+}
+
+impl TaskHello {
+    /// The first valid hello in `stdout`, None for tasks that print none.
+    pub fn from_stdout(stdout: &str) -> Option<Self> {
+        stdout
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix(TASK_HELLO_FRAME))
+            .find_map(|frame| serde_json::from_str(frame).ok())
+    }
+
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_TASK_PROTOCOL_VERSIONS.contains(&self.protocol)
+    }
+}
+
+/// Result of a task, printed as a single [TASK_PROTOCOL_FRAME] line on stdout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskProtocolResponse {
+    pub version: u32,
+    pub result: serde_json::Value,
+}
+
+impl TaskProtocolResponse {
+    /// The first response frame in `stdout`. Frames of unsupported protocol versions, or
+    /// that are no valid JSON, are skipped.
+    pub fn from_stdout(stdout: &str) -> Option<Self> {
+        stdout
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix(TASK_PROTOCOL_FRAME))
+            .filter_map(|frame| serde_json::from_str::<Self>(frame).ok())
+            .find(|response| SUPPORTED_TASK_PROTOCOL_VERSIONS.contains(&response.version))
+    }
+}
+
+/// What a task wrote and how it ended. Output is kept as the raw bytes the task wrote;
+/// [TaskOutput::stdout_text] and [TaskOutput::stderr_text] decode it for responses.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskOutput {
+    #[serde(with = "serde_bytes")]
+    pub stdout: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+    pub execution_time_ms: u64,
+    /// Resources used by the Node.js process, when they could be read from /proc
+    pub resource_usage: Option<ResourceUsage>,
+    #[serde(default)]
+    pub stdout_stats: StreamStats,
+    #[serde(default)]
+    pub stderr_stats: StreamStats,
+    /// Earlier runs of the task that failed and were retried under its [TaskRetryPolicy],
+    /// oldest first; empty when the first run is the one reported
+    #[serde(default)]
+    pub retried_attempts: Vec<TaskAttempt>,
+}
+
+impl TaskOutput {
+    /// Stdout as UTF-8, with invalid sequences replaced.
+    pub fn stdout_text(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.stdout)
+    }
+
+    /// Stderr as UTF-8, with invalid sequences replaced.
+    pub fn stderr_text(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.stderr)
+    }
+
+    /// Both streams as the task wrote them, base64 encoded.
+    pub fn raw(&self) -> RawOutput {
+        RawOutput {
+            stdout: Base64::encode(&self.stdout),
+            stderr: Base64::encode(&self.stderr),
+        }
+    }
+}
+
+/// Task output returned undecoded when a request sets `raw`, for tasks that write binary
+/// data or text in another encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RawOutput {
+    /// Base64 of the captured stdout
     pub stdout: String,
+    /// Base64 of the captured stderr
     pub stderr: String,
+}
+
+/// How the captured output of one stream differs from what the task wrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamStats {
+    /// Bytes read after the stream reached `max_stream_bytes`, forwarded but not kept
+    pub dropped_bytes: u64,
+    /// Lines longer than `max_line_bytes`, forwarded in pieces
+    pub split_lines: u64,
+    /// Invalid UTF-8 was read and replaced
+    pub binary: bool,
+}
+
+/// Bounds on the memory spent reading one output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Longest line forwarded whole; longer lines are split
+    pub max_line_bytes: usize,
+    /// Output kept per stream
+    pub max_stream_bytes: usize,
+}
+
+/// Default [OutputLimits::max_line_bytes].
+pub const DEFAULT_MAX_LINE_BYTES: usize = 1024 * 1024;
+/// Default [OutputLimits::max_stream_bytes].
+pub const DEFAULT_MAX_STREAM_BYTES: usize = 128 * 1024 * 1024;
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            max_stream_bytes: DEFAULT_MAX_STREAM_BYTES,
+        }
+    }
+}
+
+/// CPU time and memory consumed by a task process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ResourceUsage {
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+    /// Highest resident set size observed while the process ran
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Output stream a task line was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// One line of task output, without its line ending.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+/// Receives task output lines as soon as they are read.
+pub type OutputSink = tokio::sync::mpsc::UnboundedSender<OutputLine>;
+
+/// Interval at which task process memory is sampled.
+const MEMORY_SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// CPUs a `cpu_set_t` holds; higher CPU numbers cannot be set.
+#[cfg(target_os = "linux")]
+pub const MAX_CPUS: usize = libc::CPU_SETSIZE as usize;
+#[cfg(not(target_os = "linux"))]
+pub const MAX_CPUS: usize = 1024;
+
+/// Placement hints applied to the Node.js process before it starts, so task CPU spikes
+/// cannot starve the HTTP server.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulingHints {
+    /// vCPUs the process may run on, all CPUs when unset
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Nice value (-20 to 19), inherited when unset
+    pub nice: Option<i32>,
+}
+
+impl SchedulingHints {
+    /// Parse a CPU list such as `2,3` or `1-3,6`. CPUs from [MAX_CPUS] up are refused
+    /// before any range is expanded.
+    pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+        let mut cpus = Vec::new();
+        for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (start, end): (usize, usize) = match part.split_once('-') {
+                Some((start, end)) => (
+                    start.trim().parse().context("Invalid CPU range start")?,
+                    end.trim().parse().context("Invalid CPU range end")?,
+                ),
+                None => {
+                    let cpu = part.parse().context("Invalid CPU number")?;
+                    (cpu, cpu)
+                }
+            };
+            if start > end {
+                anyhow::bail!("Invalid CPU range {}", part);
+            }
+            if end >= MAX_CPUS {
+                anyhow::bail!("CPU {} is out of range, CPUs are numbered below {}", end, MAX_CPUS);
+            }
+            cpus.extend(start..=end);
+        }
+        if cpus.is_empty() {
+            anyhow::bail!("CPU list is empty");
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(cpus)
+    }
+
+    /// Apply the hints to the calling process. Only async-signal-safe calls are used,
+    /// so this may run between fork and exec.
+    #[cfg(target_os = "linux")]
+    fn apply_to_current_process(&self) -> std::io::Result<()> {
+        if let Some(nice) = self.nice {
+            // SAFETY: setpriority only reads its arguments.
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(cpus) = &self.cpu_affinity {
+            // SAFETY: cpu_set_t is plain data, zero is a valid empty set, and the set is
+            // only passed by reference with its exact size.
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for cpu in cpus {
+                    // CPU_SET indexes the set, so a CPU it cannot hold would panic after fork
+                    if *cpu >= MAX_CPUS {
+                        return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+                    }
+                    libc::CPU_SET(*cpu, &mut set);
+                }
+                if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read user and system CPU time of a process from `/proc/<pid>/stat`. The values stay
+/// readable after the process exits until it is reaped.
+pub fn read_process_cpu(pid: u32) -> Option<ResourceUsage> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_proc_stat_cpu(&stat, clock_ticks_per_sec())
+}
+
+fn clock_ticks_per_sec() -> u64 {
+    // SAFETY: sysconf has no memory safety requirements.
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+fn parse_proc_stat_cpu(stat: &str, ticks_per_sec: u64) -> Option<ResourceUsage> {
+    // The command name may contain spaces, so fields are counted after its closing paren.
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // utime and stime are fields 14 and 15 of the full line, 12 and 13 after the name.
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(ResourceUsage {
+        user_cpu_ms: utime * 1000 / ticks_per_sec,
+        system_cpu_ms: stime * 1000 / ticks_per_sec,
+        peak_rss_bytes: None,
+    })
+}
+
+/// Peak resident set size of a running process in bytes, from the `VmHWM` (high water
+/// mark) and `VmRSS` lines of `/proc/<pid>/status`.
+pub fn read_process_peak_rss(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    parse_proc_status_peak_rss(&status)
+}
+
+fn parse_proc_status_peak_rss(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .filter(|line| line.starts_with("VmHWM:") || line.starts_with("VmRSS:"))
+        .filter_map(|line| line.split_whitespace().nth(1)?.parse::<u64>().ok())
+        .max()
+        .map(|kb| kb * 1024)
+}
+
+/// Sample the peak RSS of `pid` until `stop` fires. Returns the highest value seen.
+async fn sample_peak_rss(pid: u32, mut stop: tokio::sync::oneshot::Receiver<()>) -> Option<u64> {
+    let mut peak = None;
+    let mut interval = tokio::time::interval(MEMORY_SAMPLE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Some(rss) = read_process_peak_rss(pid) {
+                    peak = peak.max(Some(rss));
+                }
+            }
+            _ = &mut stop => return peak,
+        }
+    }
+}
+
+/// V8 flags passed to the Node.js binary ahead of the script.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeFlags {
+    /// Heap limit in MiB (`--max-old-space-size`)
+    pub max_old_space_size_mb: Option<u64>,
+    /// Stack size in KiB (`--stack-size`)
+    pub stack_size_kb: Option<u64>,
+    /// Any other flags, e.g. `--expose-gc`
+    pub extra: Vec<String>,
+}
+
+impl NodeFlags {
+    /// Parse a whitespace separated `NODE_OPTIONS`-style flag string. The heap and stack
+    /// flags are recognised; everything else is kept in `extra`.
+    pub fn parse(options: &str) -> Result<Self> {
+        let mut flags = Self::default();
+        for flag in options.split_whitespace() {
+            if let Some(mb) = flag.strip_prefix("--max-old-space-size=") {
+                flags.max_old_space_size_mb = Some(mb.parse().context("Invalid --max-old-space-size")?);
+            } else if let Some(kb) = flag.strip_prefix("--stack-size=") {
+                flags.stack_size_kb = Some(kb.parse().context("Invalid --stack-size")?);
+            } else if flag.starts_with("--") {
+                flags.extra.push(flag.to_string());
+            } else {
+                anyhow::bail!("Invalid Node.js flag {}", flag);
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Fill unset fields from `defaults`; extra flags are appended after the defaults.
+    pub fn or(&self, defaults: &NodeFlags) -> NodeFlags {
+        NodeFlags {
+            max_old_space_size_mb: self.max_old_space_size_mb.or(defaults.max_old_space_size_mb),
+            stack_size_kb: self.stack_size_kb.or(defaults.stack_size_kb),
+            extra: defaults.extra.iter().chain(&self.extra).cloned().collect(),
+        }
+    }
+
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(mb) = self.max_old_space_size_mb {
+            args.push(format!("--max-old-space-size={}", mb));
+        }
+        if let Some(kb) = self.stack_size_kb {
+            args.push(format!("--stack-size={}", kb));
+        }
+        args.extend(self.extra.iter().cloned());
+        args
+    }
+}
+
+/// Node.js flags by operation, falling back to a server-wide default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeFlagsByOperation {
+    pub default: NodeFlags,
+    pub operations: HashMap<String, NodeFlags>,
+}
+
+impl NodeFlagsByOperation {
+    pub fn for_operation(&self, operation: &str) -> NodeFlags {
+        match self.operations.get(operation) {
+            Some(flags) => flags.or(&self.default),
+            None => self.default.clone(),
+        }
+    }
+}
+
+/// Error of a task that did not finish within its timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskTimedOut {
+    pub secs: u64,
+}
+
+impl std::fmt::Display for TaskTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Task execution timed out after {} seconds", self.secs)
+    }
+}
+
+impl std::error::Error for TaskTimedOut {}
+
+/// Explain common Node.js resource failures that otherwise show up as a bare exit code.
+pub fn diagnose_failure(stderr: &str) -> Option<&'static str> {
+    if stderr.contains("JavaScript heap out of memory") || stderr.contains("Reached heap limit") {
+        Some("the task ran out of heap memory; raise --max-old-space-size for this operation")
+    } else if stderr.contains("Maximum call stack size exceeded") {
+        Some("the task exhausted its stack; raise --stack-size for this operation")
+    } else {
+        None
+    }
+}
+
+/// Field of a task result naming the class of the error a failed task ran into.
+pub const ERROR_CLASS_FIELD: &str = "errorClass";
+/// Error class of failures a later run may not run into, such as an unreachable service.
+pub const TRANSIENT_ERROR_CLASS: &str = "transient";
+/// Default `TASK_RETRY_BACKOFF_MS`.
+pub const DEFAULT_TASK_RETRY_BACKOFF_MS: u64 = 500;
+/// Longest delay between two runs of a task.
+const MAX_TASK_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// A failed run of a task that was retried.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TaskAttempt {
     pub exit_code: i32,
+    /// `errorClass` of the result the run reported
+    pub error_class: Option<String>,
     pub execution_time_ms: u64,
 }
 
+/// When a task that exited with an error is run again. Only failures the task reports as
+/// retryable are: an exit code from `retry_exit_codes`, or a result whose `errorClass` is in
+/// `retry_error_classes`. Runs that time out or cannot start are not retried.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskRetryPolicy {
+    /// Runs of a task at most, 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the second run, doubled for every further run
+    pub initial_backoff_ms: u64,
+    pub retry_exit_codes: Vec<i32>,
+    pub retry_error_classes: Vec<String>,
+}
+
+impl Default for TaskRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff_ms: DEFAULT_TASK_RETRY_BACKOFF_MS,
+            retry_exit_codes: Vec::new(),
+            retry_error_classes: vec![TRANSIENT_ERROR_CLASS.to_string()],
+        }
+    }
+}
+
+impl TaskRetryPolicy {
+    /// Parse comma separated exit codes such as `75,137`.
+    pub fn parse_exit_codes(list: &str) -> Result<Vec<i32>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| code.parse().with_context(|| format!("Invalid exit code: {}", code)))
+            .collect()
+    }
+
+    /// `errorClass` of the result `output` reports.
+    pub fn error_class(output: &TaskOutput) -> Option<String> {
+        let result = crate::task_result::extract(&output.stdout_text())?.ok()?;
+        result.get(ERROR_CLASS_FIELD)?.as_str().map(str::to_string)
+    }
+
+    /// Whether the run that produced `output` failed in a way worth another run.
+    pub fn is_retryable(&self, output: &TaskOutput) -> bool {
+        output.exit_code != 0
+            && (self.retry_exit_codes.contains(&output.exit_code)
+                || Self::error_class(output).is_some_and(|class| self.retry_error_classes.contains(&class)))
+    }
+
+    /// Delay before retry number `retry`, counting from 1.
+    pub fn backoff(&self, retry: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        std::time::Duration::from_millis(self.initial_backoff_ms.saturating_mul(factor)).min(MAX_TASK_RETRY_BACKOFF)
+    }
+
+    /// Run `attempt` until a run succeeds, fails in a way not retried or was the last one
+    /// allowed. The output of that run is returned with the earlier ones recorded in
+    /// [TaskOutput::retried_attempts].
+    pub async fn run<F, Fut>(&self, mut attempt: F) -> Result<TaskOutput>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<TaskOutput>>,
+    {
+        let mut retried_attempts = Vec::new();
+        loop {
+            let mut output = attempt().await?;
+            let runs = retried_attempts.len() as u32 + 1;
+            if runs >= self.max_attempts || !self.is_retryable(&output) {
+                output.retried_attempts = retried_attempts;
+                return Ok(output);
+            }
+            let failed = TaskAttempt {
+                exit_code: output.exit_code,
+                error_class: Self::error_class(&output),
+                execution_time_ms: output.execution_time_ms,
+            };
+            let delay = self.backoff(runs);
+            tracing::warn!(
+                "Task run {} of {} failed with exit code {} ({}), retrying in {:?}",
+                runs,
+                self.max_attempts,
+                failed.exit_code,
+                failed.error_class.as_deref().unwrap_or("no error class"),
+                delay
+            );
+            retried_attempts.push(failed);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskConfig {
     pub task_path: String,
     pub timeout_secs: u64,
     pub args: Vec<String>,
     pub env_vars: HashMap<String, String>,
+    pub scheduling: SchedulingHints,
+    pub node_flags: NodeFlags,
+    /// Sent as the [TaskProtocolRequest] input
+    #[serde(default)]
+    pub input: serde_json::Value,
+    #[serde(default)]
+    pub retry: TaskRetryPolicy,
 }
 
 impl Default for TaskConfig {
@@ -31,6 +580,10 @@ impl Default for TaskConfig {
             timeout_secs: 30,
             args: vec![],
             env_vars: HashMap::new(),
+            scheduling: SchedulingHints::default(),
+            node_flags: NodeFlags::default(),
+            input: serde_json::Value::Null,
+            retry: TaskRetryPolicy::default(),
         }
     }
 }
@@ -40,6 +593,11 @@ pub struct NodeTaskRunner {
     timeout_secs: u64,
     args: Vec<String>,
     env_vars: HashMap<String, String>,
+    scheduling: SchedulingHints,
+    node_flags: NodeFlags,
+    request: TaskProtocolRequest,
+    retry: TaskRetryPolicy,
+    output: Option<OutputSink>,
 }
 
 impl NodeTaskRunner {
@@ -49,10 +607,28 @@ impl NodeTaskRunner {
             timeout_secs: config.timeout_secs,
             args: config.args,
             env_vars: config.env_vars,
+            scheduling: config.scheduling,
+            node_flags: config.node_flags,
+            request: TaskProtocolRequest::new(config.input),
+            retry: config.retry,
+            output: None,
         }
     }
 
+    /// Also send every output line to `sink` while the task runs. The collected
+    /// [TaskOutput] is unaffected.
+    pub fn with_output(mut self, sink: OutputSink) -> Self {
+        self.output = Some(sink);
+        self
+    }
+
+    /// Run the task, again after failures its [TaskRetryPolicy] retries. Output lines of
+    /// every run go to the sink set with [NodeTaskRunner::with_output].
     pub async fn run(&self) -> Result<TaskOutput> {
+        self.retry.run(|| self.run_once()).await
+    }
+
+    async fn run_once(&self) -> Result<TaskOutput> {
         let start_time = std::time::Instant::now();
         
         self.validate_task_directory()?;
@@ -60,6 +636,7 @@ impl NodeTaskRunner {
         
         let timeout_duration = std::time::Duration::from_secs(self.timeout_secs);
         
+        // On timeout the task future is dropped, which kills the process
         match tokio::time::timeout(timeout_duration, self.execute_task()).await {
             Ok(result) => {
                 match result {
@@ -70,7 +647,7 @@ impl NodeTaskRunner {
                     Err(e) => Err(e),
                 }
             },
-            Err(_) => anyhow::bail!("Task execution timed out after {} seconds", self.timeout_secs),
+            Err(_) => Err(TaskTimedOut { secs: self.timeout_secs }.into()),
         }
     }
 
@@ -93,36 +670,21 @@ impl NodeTaskRunner {
     }
 
     async fn validate_node_installation(&self) -> Result<()> {
-        let node_path = "/nodejs/bin/node";
-        
-        // Check if the static Node.js binary exists
-        if !std::path::Path::new(node_path).exists() {
-            anyhow::bail!("Static Node.js binary not found at {}", node_path);
-        }
-
-        // Test Node.js binary by running --version
-        let output = TokioCommand::new(node_path)
-            .arg("--version")
-            .output()
-            .await
-            .context("Failed to check Node.js version")?;
-
-        if output.status.success() {
-            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            tracing::debug!("Static Node.js version: {}", version);
-            Ok(())
-        } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("Node.js binary failed to run: {}", error)
-        }
+        let version = node_version().await?;
+        tracing::debug!("Static Node.js version: {}", version);
+        Ok(())
     }
 
     async fn execute_task(&self) -> Result<TaskOutput> {
         // Use the static Node.js binary from the new path in container
-        let node_path = "/nodejs/bin/node";
+        let node_path = NODE_BINARY;
         let mut cmd = TokioCommand::new(node_path);
-        cmd.arg("index.js")
+        // V8 flags must come before the script
+        cmd.args(self.node_flags.to_args())
+           .arg("index.js")
            .current_dir(&self.task_path)
+           .env(TASK_PROTOCOL_ENV, TASK_PROTOCOL_VERSION.to_string())
+           .stdin(Stdio::piped())
            .stdout(Stdio::piped())
            .stderr(Stdio::piped());
 
@@ -136,72 +698,616 @@ impl NodeTaskRunner {
             cmd.arg(arg);
         }
 
+        // Pin the process and lower its priority before Node.js starts
+        #[cfg(target_os = "linux")]
+        if self.scheduling != SchedulingHints::default() {
+            let scheduling = self.scheduling.clone();
+            // SAFETY: the closure only makes async-signal-safe syscalls.
+            unsafe {
+                cmd.pre_exec(move || scheduling.apply_to_current_process());
+            }
+        }
+
+        // Killed if the task is abandoned, e.g. when `run` times out
+        cmd.kill_on_drop(true);
         let mut child = cmd.spawn()
             .context("Failed to spawn Node.js process")?;
 
-        let stdout = child.stdout.take().context("Failed to get stdout")?;
-        let stderr = child.stderr.take().context("Failed to get stderr")?;
+        // Write the request while the output is read, so neither side blocks on a full pipe
+        let mut stdin = child.stdin.take().context("Failed to get stdin")?;
+        let request = serde_json::to_vec(&self.request).context("Failed to serialize task request")?;
+        let write_request = async move {
+            use tokio::io::AsyncWriteExt;
+            // A task that exits without reading its request fails on its own
+            if let Err(e) = stdin.write_all(&request).await {
+                tracing::warn!("Failed to write the task request: {}", e);
+            }
+            // Dropping stdin closes it, ending the request
+        };
+        let (_, output) = tokio::join!(write_request, collect_output(child, &self.output));
+        output
+    }
+}
 
-        let stdout_reader = BufReader::new(stdout);
-        let stderr_reader = BufReader::new(stderr);
+/// Read the output of `child` until both streams close, then reap it. The readers and the
+/// memory sampler run inside this future rather than as spawned tasks, so dropping it
+/// drops them with it; spawn the child with `kill_on_drop` so the process goes too. A
+/// read error kills and reaps the child before it is returned.
+async fn collect_output(mut child: Child, sink: &Option<OutputSink>) -> Result<TaskOutput> {
+    let stdout = child.stdout.take().context("Failed to get stdout")?;
+    let stderr = child.stderr.take().context("Failed to get stderr")?;
+    let pid = child.id();
+    let limits = OutputLimits::default();
 
-        let stdout_lines = Arc::new(Mutex::new(Vec::new()));
-        let stderr_lines = Arc::new(Mutex::new(Vec::new()));
+    // Sample memory while the process runs; the kernel drops it once the process exits
+    let (stop_sampling, stop_rx) = tokio::sync::oneshot::channel();
+    let sampler = async move {
+        match pid {
+            Some(pid) => sample_peak_rss(pid, stop_rx).await,
+            None => None,
+        }
+    };
+    let readers = async {
+        let output = tokio::try_join!(
+            read_stream(stdout, OutputStream::Stdout, sink, limits),
+            read_stream(stderr, OutputStream::Stderr, sink, limits),
+        );
+        // Output is closed, so the process has exited or is about to; read its CPU time
+        // before reaping it.
+        let resource_usage = pid.and_then(read_process_cpu);
+        let _ = stop_sampling.send(());
+        output.map(|output| (output, resource_usage))
+    };
+    let (output, peak_rss_bytes) = tokio::join!(readers, sampler);
 
-        // Clone for tasks
-        let stdout_lines_clone = Arc::clone(&stdout_lines);
-        let stderr_lines_clone = Arc::clone(&stderr_lines);
+    let (((stdout, stdout_stats), (stderr, stderr_stats)), mut resource_usage) = match output {
+        Ok(output) => output,
+        Err(e) => {
+            // `kill` also waits for the process
+            let _ = child.kill().await;
+            return Err(e);
+        }
+    };
+    if let Some(usage) = resource_usage.as_mut() {
+        usage.peak_rss_bytes = peak_rss_bytes;
+    }
+    let status = child.wait().await.context("Failed to wait for child process")?;
 
-        // Read stdout and stderr concurrently
-        let stdout_task = async move {
-            let mut stdout_reader = stdout_reader;
-            let mut line = String::new();
-            loop {
-                line.clear();
-                match stdout_reader.read_line(&mut line).await {
-                    Ok(0) => break, // EOF
-                    Ok(_) => {
-                        stdout_lines_clone.lock().await.push(line.clone());
-                    }
-                    Err(_) => break,
-                }
+    for (kind, stats) in [(OutputStream::Stdout, &stdout_stats), (OutputStream::Stderr, &stderr_stats)] {
+        if stats.binary {
+            tracing::warn!("Task {:?} contained invalid UTF-8, which was replaced", kind);
+        }
+        if stats.dropped_bytes > 0 {
+            tracing::warn!(
+                "Task {:?} exceeded {} bytes, {} more bytes were dropped",
+                kind,
+                limits.max_stream_bytes,
+                stats.dropped_bytes
+            );
+        }
+    }
+
+    Ok(TaskOutput {
+        stdout,
+        stderr,
+        exit_code: status.code().unwrap_or(-1),
+        execution_time_ms: 0, // Will be set by the caller
+        resource_usage,
+        stdout_stats,
+        stderr_stats,
+        retried_attempts: Vec::new(),
+    })
+}
+
+/// Output of one stream kept within [OutputLimits::max_stream_bytes].
+struct StreamCapture {
+    output: Vec<u8>,
+    stats: StreamStats,
+    max_bytes: usize,
+}
+
+impl StreamCapture {
+    /// Forward a line, or a piece of a split line, decoded for the sink, and keep the raw
+    /// bytes that fit.
+    fn push(&mut self, bytes: &[u8], kind: OutputStream, sink: &Option<OutputSink>) {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => std::borrow::Cow::Borrowed(text),
+            Err(_) => {
+                self.stats.binary = true;
+                String::from_utf8_lossy(bytes)
             }
         };
+        send_line(sink, kind, &text);
+        let keep = bytes.len().min(self.max_bytes.saturating_sub(self.output.len()));
+        self.output.extend_from_slice(&bytes[..keep]);
+        self.stats.dropped_bytes += (bytes.len() - keep) as u64;
+    }
+}
 
-        let stderr_task = async move {
-            let mut stderr_reader = stderr_reader;
-            let mut line = String::new();
-            loop {
+/// Read one output stream to the end, forwarding each line to the sink. Lines are buffered
+/// up to `max_line_bytes` and longer ones forwarded in pieces, so a task writing one huge
+/// line or binary data cannot grow the buffer without bound. Invalid UTF-8 is kept as is
+/// and only replaced in the lines sent to the sink, rather than treated as a read error.
+async fn read_stream(
+    stream: impl AsyncRead + Unpin,
+    kind: OutputStream,
+    sink: &Option<OutputSink>,
+    limits: OutputLimits,
+) -> Result<(Vec<u8>, StreamStats)> {
+    let mut reader = BufReader::new(stream);
+    let mut capture = StreamCapture {
+        output: Vec::new(),
+        stats: StreamStats::default(),
+        max_bytes: limits.max_stream_bytes,
+    };
+    let mut line = Vec::new();
+    // Whether `line` continues a line already forwarded in part
+    let mut continued = false;
+    loop {
+        let end = fill_line(&mut reader, &mut line, limits.max_line_bytes)
+            .await
+            .with_context(|| format!("Failed to read task {:?}", kind))?;
+        match end {
+            LineEnd::Eof => {
+                if !line.is_empty() {
+                    capture.push(&line, kind, sink);
+                }
+                return Ok((capture.output, capture.stats));
+            }
+            LineEnd::Newline => {
+                capture.push(&line, kind, sink);
                 line.clear();
-                match stderr_reader.read_line(&mut line).await {
-                    Ok(0) => break, // EOF
-                    Ok(_) => {
-                        stderr_lines_clone.lock().await.push(line.clone());
-                    }
-                    Err(_) => break,
+                continued = false;
+            }
+            LineEnd::Full => {
+                let split = utf8_split_point(&line);
+                capture.push(&line[..split], kind, sink);
+                line.drain(..split);
+                if !continued {
+                    capture.stats.split_lines += 1;
+                    continued = true;
                 }
             }
+        }
+    }
+}
+
+/// Why [fill_line] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEnd {
+    /// The line is complete, with its newline
+    Newline,
+    /// The line reached `max_bytes` without a newline
+    Full,
+    /// The stream ended, possibly after a last line without a newline
+    Eof,
+}
+
+/// Append the rest of the current line of `reader` to `line`, stopping once `line` holds
+/// `max_bytes`, so a line never takes more memory than that whatever the stream carries.
+async fn fill_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    line: &mut Vec<u8>,
+    max_bytes: usize,
+) -> std::io::Result<LineEnd> {
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(LineEnd::Eof);
+        }
+        let room = max_bytes.saturating_sub(line.len()).max(1);
+        let (take, complete) = match buf[..buf.len().min(room)].iter().position(|b| *b == b'\n') {
+            Some(end) => (end + 1, true),
+            None => (buf.len().min(room), false),
+        };
+        line.extend_from_slice(&buf[..take]);
+        reader.consume(take);
+        if complete {
+            return Ok(LineEnd::Newline);
+        }
+        if line.len() >= max_bytes {
+            return Ok(LineEnd::Full);
+        }
+    }
+}
+
+/// Length of `bytes` without a UTF-8 sequence cut off at its end, so splitting a long line
+/// does not tear a character in two.
+fn utf8_split_point(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let start = bytes.len() - back;
+        let width = match bytes[start] {
+            0x80..=0xBF => continue,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if width > back && start > 0 { start } else { bytes.len() };
+    }
+    bytes.len()
+}
+
+/// Forward a line to the output sink, if any. A closed sink only stops forwarding.
+fn send_line(sink: &Option<OutputSink>, stream: OutputStream, line: &str) {
+    if let Some(sink) = sink {
+        let line = line.trim_end_matches(['\n', '\r']).to_string();
+        let _ = sink.send(OutputLine { stream, line });
+    }
+}
+
+/// Run the static Node.js binary with `--version`.
+pub async fn node_version() -> Result<String> {
+    // Check if the static Node.js binary exists
+    if !std::path::Path::new(NODE_BINARY).exists() {
+        anyhow::bail!("Static Node.js binary not found at {}", NODE_BINARY);
+    }
+
+    let output = TokioCommand::new(NODE_BINARY)
+        .arg("--version")
+        .output()
+        .await
+        .context("Failed to check Node.js version")?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Node.js binary failed to run: {}", error)
+    }
+}
+
+// ==== Warm worker pool ====
+
+/// Entry point of pool workers in the task directory, see its header for the protocol.
+pub const WORKER_SCRIPT: &str = "worker.js";
+/// Default interval between health checks of idle workers.
+pub const DEFAULT_WORKER_HEALTH_CHECK_SECS: u64 = 30;
+/// Default number of tasks a worker runs before it is replaced, bounding leaks in task code.
+pub const DEFAULT_WORKER_MAX_TASKS: u64 = 100;
+/// Time a worker has to answer a ping, including loading its dependencies after spawn.
+const WORKER_PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    id: Option<u64>,
+    result: Option<serde_json::Value>,
+    error: Option<RpcError>,
+}
+
+/// Output of one `run` call, the in-process equivalent of a task process exiting.
+#[derive(Debug, Deserialize)]
+struct WorkerRunResult {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerPoolConfig {
+    /// Number of workers, and so of tasks running at once
+    pub size: usize,
+    pub task_path: PathBuf,
+    pub node_binary: PathBuf,
+    /// Applied when a worker starts; per-operation flags cannot change a running worker
+    pub node_flags: NodeFlags,
+    pub scheduling: SchedulingHints,
+    pub health_check_interval: std::time::Duration,
+    pub max_tasks_per_worker: u64,
+    /// A worker response carries the whole output of a run on one line, read up to
+    /// `max_stream_bytes`; a run writing more fails and its worker is replaced
+    pub output_limits: OutputLimits,
+}
+
+impl Default for WorkerPoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            task_path: PathBuf::from("nodejs-task"),
+            node_binary: PathBuf::from(NODE_BINARY),
+            node_flags: NodeFlags::default(),
+            scheduling: SchedulingHints::default(),
+            health_check_interval: std::time::Duration::from_secs(DEFAULT_WORKER_HEALTH_CHECK_SECS),
+            max_tasks_per_worker: DEFAULT_WORKER_MAX_TASKS,
+            output_limits: OutputLimits::default(),
+        }
+    }
+}
+
+/// Long-lived Node.js process running tasks over line-delimited JSON-RPC on stdin/stdout.
+/// The process is killed when the worker is dropped.
+struct PoolWorker {
+    child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    stdout: BufReader<tokio::process::ChildStdout>,
+    /// Longest response line read
+    max_line_bytes: usize,
+    next_id: u64,
+    tasks_run: u64,
+    /// Pool generation the worker was started in, see [WorkerPool::reload]
+    generation: u64,
+}
+
+impl PoolWorker {
+    async fn spawn(config: &WorkerPoolConfig) -> Result<Self> {
+        let mut cmd = TokioCommand::new(&config.node_binary);
+        cmd.args(config.node_flags.to_args())
+            .arg(WORKER_SCRIPT)
+            .current_dir(&config.task_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+
+        #[cfg(target_os = "linux")]
+        if config.scheduling != SchedulingHints::default() {
+            let scheduling = config.scheduling.clone();
+            // SAFETY: the closure only makes async-signal-safe syscalls.
+            unsafe {
+                cmd.pre_exec(move || scheduling.apply_to_current_process());
+            }
+        }
+
+        let mut child = cmd.spawn().context("Failed to spawn Node.js worker")?;
+        let stdin = child.stdin.take().context("Failed to get worker stdin")?;
+        let stdout = child.stdout.take().context("Failed to get worker stdout")?;
+        let mut worker = Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            max_line_bytes: config.output_limits.max_stream_bytes,
+            next_id: 0,
+            tasks_run: 0,
+            generation: 0,
         };
+        worker.ping().await.context("Node.js worker did not become ready")?;
+        Ok(worker)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    async fn call(&mut self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        use tokio::io::AsyncWriteExt;
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut request = serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).to_string();
+        request.push('\n');
+        self.stdin.write_all(request.as_bytes()).await.context("Failed to write to worker")?;
+        self.stdin.flush().await.context("Failed to write to worker")?;
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match fill_line(&mut self.stdout, &mut line, self.max_line_bytes)
+                .await
+                .context("Failed to read from worker")?
+            {
+                LineEnd::Newline => {}
+                // The rest of the line is still unread, so the worker cannot answer again
+                LineEnd::Full => anyhow::bail!("Worker output line exceeds {} bytes", self.max_line_bytes),
+                LineEnd::Eof => anyhow::bail!("Worker closed its output"),
+            }
+            let Ok(response) = serde_json::from_slice::<RpcResponse>(&line) else {
+                tracing::debug!("Ignoring non JSON-RPC worker output: {}", String::from_utf8_lossy(&line));
+                continue;
+            };
+            if response.id != Some(id) {
+                continue;
+            }
+            return match (response.result, response.error) {
+                (Some(result), _) => Ok(result),
+                (None, Some(error)) => anyhow::bail!("Worker error {}: {}", error.code, error.message),
+                (None, None) => anyhow::bail!("Worker response has neither result nor error"),
+            };
+        }
+    }
+
+    async fn ping(&mut self) -> Result<()> {
+        tokio::time::timeout(WORKER_PING_TIMEOUT, self.call("ping", serde_json::Value::Null))
+            .await
+            .context("Worker ping timed out")??;
+        Ok(())
+    }
+
+    /// Exit code of a worker that stopped answering, -1 if it is still running or was
+    /// killed by a signal.
+    async fn exit_code(&mut self) -> i32 {
+        match tokio::time::timeout(std::time::Duration::from_secs(1), self.child.wait()).await {
+            Ok(Ok(status)) => status.code().unwrap_or(-1),
+            _ => -1,
+        }
+    }
+}
+
+/// Pool of warm Node.js workers. Workers are replaced when they crash, time out, fail a
+/// health check or reach [WorkerPoolConfig::max_tasks_per_worker].
+pub struct WorkerPool {
+    config: WorkerPoolConfig,
+    idle: Mutex<std::collections::VecDeque<PoolWorker>>,
+    slots: tokio::sync::Semaphore,
+    respawns: std::sync::atomic::AtomicU64,
+    generation: std::sync::atomic::AtomicU64,
+}
+
+impl WorkerPool {
+    /// Start `config.size` workers and the periodic health checks.
+    pub async fn start(config: WorkerPoolConfig) -> Result<Arc<Self>> {
+        if config.size == 0 {
+            anyhow::bail!("Worker pool size must be at least 1");
+        }
+        if !config.task_path.join(WORKER_SCRIPT).exists() {
+            anyhow::bail!("{} not found in task directory", WORKER_SCRIPT);
+        }
+        let mut idle = std::collections::VecDeque::with_capacity(config.size);
+        for _ in 0..config.size {
+            idle.push_back(PoolWorker::spawn(&config).await?);
+        }
+        let pool = Arc::new(Self {
+            slots: tokio::sync::Semaphore::new(config.size),
+            idle: Mutex::new(idle),
+            config,
+            respawns: std::sync::atomic::AtomicU64::new(0),
+            generation: std::sync::atomic::AtomicU64::new(0),
+        });
+
+        let health = Arc::downgrade(&pool);
+        let interval = pool.config.health_check_interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(pool) = health.upgrade() else { break };
+                pool.check_health().await;
+            }
+        });
+        Ok(pool)
+    }
+
+    pub fn size(&self) -> usize {
+        self.config.size
+    }
+
+    /// Workers replaced since the pool started.
+    pub fn respawns(&self) -> u64 {
+        self.respawns.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn current_generation(&self) -> u64 {
+        self.generation.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    async fn spawn_worker(&self) -> Result<PoolWorker> {
+        let generation = self.current_generation();
+        let mut worker = PoolWorker::spawn(&self.config).await?;
+        worker.generation = generation;
+        self.respawns.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(worker)
+    }
+
+    async fn take_worker(&self) -> Result<PoolWorker> {
+        if let Some(worker) = self.idle.lock().await.pop_front() {
+            return Ok(worker);
+        }
+        self.spawn_worker().await
+    }
+
+    /// Keep a worker for reuse unless the pool is full or was reloaded since it started.
+    async fn return_worker(&self, worker: PoolWorker) {
+        let mut idle = self.idle.lock().await;
+        if idle.len() < self.config.size && worker.generation == self.current_generation() {
+            idle.push_back(worker);
+        }
+    }
+
+    /// Replace every worker so changed task dependencies are loaded. Idle workers are
+    /// stopped now, busy ones once their task finishes. Returns the number of workers started.
+    pub async fn reload(&self) -> usize {
+        self.generation.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.idle.lock().await.clear();
+        self.check_health().await
+    }
+
+    /// Run a task on a warm worker. Behaves like [NodeTaskRunner::run] except that
+    /// `node_flags` and `scheduling` of the config are those the pool was started with.
+    /// CPU time is measured for the task; peak memory is the worker's peak since it started.
+    pub async fn run(&self, config: &TaskConfig) -> Result<TaskOutput> {
+        config.retry.run(|| self.run_once(config)).await
+    }
+
+    async fn run_once(&self, config: &TaskConfig) -> Result<TaskOutput> {
+        let start_time = std::time::Instant::now();
+        let _slot = self.slots.acquire().await.context("Worker pool is closed")?;
+        let mut worker = self.take_worker().await?;
 
-        // Wait for both stdout/stderr reading and process completion
-        tokio::try_join!(
-            tokio::spawn(stdout_task),
-            tokio::spawn(stderr_task)
-        )?;
+        let pid = worker.pid();
+        let cpu_before = pid.and_then(read_process_cpu);
+        let request = TaskProtocolRequest::new(config.input.clone());
+        let params = serde_json::json!({"args": config.args, "env": config.env_vars, "request": request});
+        let timeout = std::time::Duration::from_secs(config.timeout_secs);
+        let (stdout, stderr, exit_code, healthy) =
+            match tokio::time::timeout(timeout, worker.call("run", params)).await {
+                // The task may still be running inside the worker; dropping it kills the process
+                Err(_) => return Err(TaskTimedOut { secs: config.timeout_secs }.into()),
+                Ok(Ok(result)) => {
+                    let run: WorkerRunResult =
+                        serde_json::from_value(result).context("Invalid worker run result")?;
+                    (run.stdout.into_bytes(), run.stderr.into_bytes(), run.exit_code, true)
+                }
+                Ok(Err(e)) => {
+                    let exit_code = worker.exit_code().await;
+                    tracing::warn!("Node.js worker {:?} failed during a task, replacing it: {}", pid, e);
+                    let stderr = format!("Node.js worker failed during the task: {}", e);
+                    (Vec::new(), stderr.into_bytes(), exit_code, false)
+                }
+            };
 
-        let status = child.wait().await.context("Failed to wait for child process")?;
-        let exit_code = status.code().unwrap_or(-1);
+        let resource_usage = pid.and_then(read_process_cpu).map(|after| {
+            let before = cpu_before.unwrap_or_default();
+            ResourceUsage {
+                user_cpu_ms: after.user_cpu_ms.saturating_sub(before.user_cpu_ms),
+                system_cpu_ms: after.system_cpu_ms.saturating_sub(before.system_cpu_ms),
+                peak_rss_bytes: pid.and_then(read_process_peak_rss),
+            }
+        });
 
-        let stdout_data = stdout_lines.lock().await.join("");
-        let stderr_data = stderr_lines.lock().await.join("");
+        worker.tasks_run += 1;
+        if healthy && worker.tasks_run < self.config.max_tasks_per_worker {
+            self.return_worker(worker).await;
+        }
 
         Ok(TaskOutput {
-            stdout: stdout_data,
-            stderr: stderr_data,
+            stdout,
+            stderr,
             exit_code,
-            execution_time_ms: 0, // Will be set by the caller
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            resource_usage,
+            stdout_stats: StreamStats::default(),
+            stderr_stats: StreamStats::default(),
+            retried_attempts: Vec::new(),
         })
     }
+
+    /// Ping idle workers, replace those that do not answer and refill the pool to its
+    /// size. Busy workers are left alone. Returns the number of workers started.
+    pub async fn check_health(&self) -> usize {
+        let mut started = 0;
+        let idle_count = self.idle.lock().await.len();
+        for _ in 0..idle_count {
+            let Ok(_slot) = self.slots.try_acquire() else { break };
+            let Some(mut worker) = self.idle.lock().await.pop_front() else { break };
+            match worker.ping().await {
+                Ok(()) => self.return_worker(worker).await,
+                Err(e) => tracing::warn!("Node.js worker {:?} failed its health check, replacing it: {}", worker.pid(), e),
+            }
+        }
+
+        // Replace workers lost to crashes, timeouts, failed checks and recycling
+        loop {
+            let busy = self.config.size - self.slots.available_permits();
+            if self.idle.lock().await.len() + busy >= self.config.size {
+                break;
+            }
+            match self.spawn_worker().await {
+                Ok(worker) => {
+                    self.return_worker(worker).await;
+                    started += 1;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to start Node.js worker: {}", e);
+                    break;
+                }
+            }
+        }
+        started
+    }
 }
 
 #[cfg(test)]
@@ -231,4 +1337,419 @@ mod tests {
         // Should pass now
         assert!(runner.validate_task_directory().is_ok());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_task_protocol() {
+        let request = TaskProtocolRequest::new(serde_json::json!({ "blobFilePairs": [] }));
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"version":1,"input":{"blobFilePairs":[]}}"#
+        );
+
+        let stdout = "Loading...\n\
+            ===TASK_PROTOCOL_RESPONSE==={\"version\":2,\"result\":\"newer\"}\n\
+            ===TASK_PROTOCOL_RESPONSE===not json\n\
+            ===TASK_PROTOCOL_RESPONSE==={\"version\":1,\"result\":{\"status\":\"success\"}}\n\
+            ===TASK_PROTOCOL_RESPONSE==={\"version\":1,\"result\":\"second\"}\n";
+        let response = TaskProtocolResponse::from_stdout(stdout).unwrap();
+        assert_eq!(response.result, serde_json::json!({ "status": "success" }));
+        assert!(TaskProtocolResponse::from_stdout("===TASK_RESULT_START===\n{}\n===TASK_RESULT_END===").is_none());
+    }
+
+    #[test]
+    fn test_task_hello() {
+        let stdout = "===TASK_PROTOCOL_HELLO==={\"protocol\":1,\"taskVersion\":\"1.0.0\"}\nLoading...\n";
+        let hello = TaskHello::from_stdout(stdout).unwrap();
+        assert_eq!(hello.task_version.as_deref(), Some("1.0.0"));
+        assert!(hello.is_supported());
+
+        let newer = TaskHello::from_stdout("===TASK_PROTOCOL_HELLO==={\"protocol\":7}").unwrap();
+        assert!(!newer.is_supported());
+        assert!(TaskHello::from_stdout("Loading...\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        fn output(exit_code: i32, error_class: Option<&str>) -> TaskOutput {
+            let result = serde_json::json!({ "status": "failed", "errorClass": error_class });
+            TaskOutput {
+                stdout: format!("{}\n{}\n{}\n", TASK_RESULT_START, result, TASK_RESULT_END).into_bytes(),
+                stderr: Vec::new(),
+                exit_code,
+                execution_time_ms: 5,
+                resource_usage: None,
+                stdout_stats: StreamStats::default(),
+                stderr_stats: StreamStats::default(),
+                retried_attempts: Vec::new(),
+            }
+        }
+        let policy = TaskRetryPolicy {
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            retry_exit_codes: vec![75],
+            ..Default::default()
+        };
+        assert!(policy.is_retryable(&output(1, Some("transient"))));
+        assert!(policy.is_retryable(&output(75, None)));
+        assert!(!policy.is_retryable(&output(1, Some("invalid_input"))));
+        assert!(!policy.is_retryable(&output(0, Some("transient"))));
+        assert_eq!(TaskRetryPolicy::parse_exit_codes(" 75, ,137").unwrap(), vec![75, 137]);
+        assert!(TaskRetryPolicy::parse_exit_codes("75,x").is_err());
+        assert_eq!(policy.backoff(3), std::time::Duration::from_millis(4));
+        assert_eq!(policy.backoff(40), MAX_TASK_RETRY_BACKOFF);
+
+        // Retried until a run succeeds
+        let mut runs = vec![output(0, None), output(1, Some("transient"))];
+        let result = policy.run(|| std::future::ready(Ok(runs.pop().unwrap()))).await.unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.retried_attempts.len(), 1);
+        assert_eq!(result.retried_attempts[0].error_class.as_deref(), Some("transient"));
+
+        // At most max_attempts runs, and failures not retried end at once
+        let mut count = 0;
+        let result = policy
+            .run(|| {
+                count += 1;
+                std::future::ready(Ok(output(75, None)))
+            })
+            .await
+            .unwrap();
+        assert_eq!((count, result.retried_attempts.len()), (3, 2));
+        let result = policy.run(|| std::future::ready(Ok(output(2, None)))).await.unwrap();
+        assert!(result.retried_attempts.is_empty());
+        let disabled = TaskRetryPolicy::default();
+        let result = disabled.run(|| std::future::ready(Ok(output(75, Some("transient"))))).await.unwrap();
+        assert!(result.retried_attempts.is_empty());
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(SchedulingHints::parse_cpu_list("2,3").unwrap(), vec![2, 3]);
+        assert_eq!(SchedulingHints::parse_cpu_list("1-3, 6,2").unwrap(), vec![1, 2, 3, 6]);
+        assert!(SchedulingHints::parse_cpu_list("3-1").is_err());
+        assert!(SchedulingHints::parse_cpu_list("a").is_err());
+        assert!(SchedulingHints::parse_cpu_list("").is_err());
+
+        // CPU numbers a cpu_set_t cannot hold are refused, without expanding the range
+        assert_eq!(SchedulingHints::parse_cpu_list(&(MAX_CPUS - 1).to_string()).unwrap(), vec![MAX_CPUS - 1]);
+        assert!(SchedulingHints::parse_cpu_list("2048").is_err());
+        assert!(SchedulingHints::parse_cpu_list("0-18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn test_parse_proc_stat_cpu() {
+        let stat = "1234 (node worker) S 1 1234 1234 0 -1 4194560 100 0 0 0 250 50 0 0 20 0 11 0 100 0 0";
+        let usage = parse_proc_stat_cpu(stat, 100).unwrap();
+        assert_eq!(usage.user_cpu_ms, 2500);
+        assert_eq!(usage.system_cpu_ms, 500);
+        assert!(parse_proc_stat_cpu("garbage", 100).is_none());
+        assert!(read_process_cpu(std::process::id()).is_some());
+    }
+
+    #[test]
+    fn test_parse_proc_status_peak_rss() {
+        let status = "Name:\tnode\nVmPeak:\t 900000 kB\nVmHWM:\t  204800 kB\nVmRSS:\t  102400 kB\n";
+        assert_eq!(parse_proc_status_peak_rss(status), Some(204800 * 1024));
+        assert_eq!(parse_proc_status_peak_rss("Name:\tzombie\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sample_peak_rss() {
+        let (stop, stop_rx) = tokio::sync::oneshot::channel();
+        let sampler = tokio::spawn(sample_peak_rss(std::process::id(), stop_rx));
+        tokio::time::sleep(MEMORY_SAMPLE_INTERVAL * 2).await;
+        stop.send(()).unwrap();
+        assert!(sampler.await.unwrap().unwrap() > 0);
+    }
+
+    fn spawn_shell(script: &str) -> Child {
+        TokioCommand::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap()
+    }
+
+    /// Write `bytes` of line oriented output to both streams at once.
+    async fn assert_collects_output(bytes: u64) {
+        let script = format!(
+            "(yes stdout-line | head -c {bytes}) & (yes stderr-line | head -c {bytes} >&2); wait; exit 3"
+        );
+        let (sink, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        let forwarded = tokio::spawn(async move {
+            let mut count = 0u64;
+            while lines.recv().await.is_some() {
+                count += 1;
+            }
+            count
+        });
+        let output = collect_output(spawn_shell(&script), &Some(sink)).await.unwrap();
+        let kept = bytes.min(DEFAULT_MAX_STREAM_BYTES as u64);
+        assert_eq!(output.exit_code, 3);
+        assert_eq!(output.stdout.len() as u64, kept);
+        assert_eq!(output.stderr.len() as u64, kept);
+        assert_eq!(output.stdout_stats.dropped_bytes, bytes - kept);
+        assert_eq!(output.stderr_stats.dropped_bytes, bytes - kept);
+        assert!(output.stdout.starts_with(b"stdout-line\n"));
+        assert!(output.stderr.starts_with(b"stderr-line\n"));
+        let expected_lines = (bytes / 12 + u64::from(bytes % 12 != 0)) * 2;
+        assert_eq!(forwarded.await.unwrap(), expected_lines);
+    }
+
+    #[tokio::test]
+    async fn test_collect_output() {
+        assert_collects_output(16 * 1024 * 1024).await;
+
+        let output = collect_output(spawn_shell("printf 'a\\377b\\n'"), &None).await.unwrap();
+        assert_eq!(output.stdout, b"a\xffb\n");
+        assert_eq!(output.stdout_text(), "a\u{FFFD}b\n");
+        assert_eq!(Base64::decode(&output.raw().stdout).unwrap(), b"a\xffb\n");
+        assert!(output.stdout_stats.binary);
+        assert!(!output.stderr_stats.binary);
+    }
+
+    #[tokio::test]
+    async fn test_read_stream_limits() {
+        let limits = OutputLimits {
+            max_line_bytes: 4,
+            max_stream_bytes: 12,
+        };
+        let (sink, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        let input = "ab\nabcdefghij\nxyz\n12345".as_bytes();
+        let (output, stats) = read_stream(input, OutputStream::Stdout, &Some(sink), limits).await.unwrap();
+        assert_eq!(output, b"ab\nabcdefghi");
+        assert_eq!(stats, StreamStats { dropped_bytes: 11, split_lines: 2, binary: false });
+        let mut forwarded = Vec::new();
+        while let Ok(line) = lines.try_recv() {
+            forwarded.push(line.line);
+        }
+        assert_eq!(forwarded, ["ab", "abcd", "efgh", "ij", "xyz", "1234", "5"]);
+
+        // A split never tears a character, and one that cannot be decoded marks the stream
+        // binary; it is only replaced in the forwarded lines
+        let (sink, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        let (output, stats) = read_stream("aé€\n".as_bytes(), OutputStream::Stdout, &Some(sink), limits).await.unwrap();
+        assert_eq!(output, "aé€\n".as_bytes());
+        assert!(!stats.binary);
+        assert_eq!(lines.try_recv().unwrap().line, "aé");
+        assert_eq!(lines.try_recv().unwrap().line, "€");
+        let (sink, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        let (output, stats) = read_stream(&b"\xff\xfe\n"[..], OutputStream::Stdout, &Some(sink), limits).await.unwrap();
+        assert_eq!(output, b"\xff\xfe\n");
+        assert!(stats.binary);
+        assert_eq!(lines.try_recv().unwrap().line, "\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn test_utf8_split_point() {
+        assert_eq!(utf8_split_point(b"abcd"), 4);
+        assert_eq!(utf8_split_point("abc€".as_bytes()), 6);
+        assert_eq!(utf8_split_point(&"abc€".as_bytes()[..5]), 3);
+        assert_eq!(utf8_split_point(&"€".as_bytes()[..2]), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "writes 1 GiB to each stream, past the kept output"]
+    async fn test_collect_output_multi_gb() {
+        assert_collects_output(1024 * 1024 * 1024).await;
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_task_is_killed() {
+        let child = spawn_shell("exec sleep 30");
+        let pid = child.id().unwrap();
+        let collect = collect_output(child, &None);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(200), collect).await.is_err());
+        // The process is gone, or a zombie until the orphan reaper collects it
+        let stopped = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid))
+                .map_or(true, |stat| stat.rsplit(')').next().unwrap().trim_start().starts_with('Z'))
+        };
+        for _ in 0..50 {
+            if stopped() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("task process {} outlived its readers", pid);
+    }
+
+    #[test]
+    fn test_node_flags() {
+        let flags = NodeFlags::parse("--max-old-space-size=4096 --expose-gc --stack-size=2048").unwrap();
+        assert_eq!(flags.max_old_space_size_mb, Some(4096));
+        assert_eq!(flags.stack_size_kb, Some(2048));
+        assert_eq!(
+            flags.to_args(),
+            vec!["--max-old-space-size=4096", "--stack-size=2048", "--expose-gc"]
+        );
+        assert!(NodeFlags::parse("--max-old-space-size=lots").is_err());
+        assert!(NodeFlags::parse("index.js").is_err());
+    }
+
+    #[test]
+    fn test_node_flags_by_operation() {
+        let mut by_operation = NodeFlagsByOperation {
+            default: NodeFlags::parse("--max-old-space-size=1024 --stack-size=984").unwrap(),
+            operations: HashMap::new(),
+        };
+        by_operation.operations.insert(
+            "embedding_ingest".to_string(),
+            NodeFlags::parse("--max-old-space-size=6144").unwrap(),
+        );
+
+        let ingest = by_operation.for_operation("embedding_ingest");
+        assert_eq!(ingest.max_old_space_size_mb, Some(6144));
+        assert_eq!(ingest.stack_size_kb, Some(984));
+        assert_eq!(by_operation.for_operation("process_data"), by_operation.default);
+    }
+
+    #[test]
+    fn test_diagnose_failure() {
+        let oom = "FATAL ERROR: Reached heap limit Allocation failed - JavaScript heap out of memory";
+        assert!(diagnose_failure(oom).unwrap().contains("--max-old-space-size"));
+        assert!(diagnose_failure("RangeError: Maximum call stack size exceeded")
+            .unwrap()
+            .contains("--stack-size"));
+        assert_eq!(diagnose_failure("Error: ECONNREFUSED"), None);
+    }
+
+    /// Task directory with the real worker script and a small index.js, or `None` when
+    /// Node.js is not installed.
+    fn worker_task_dir() -> Option<TempDir> {
+        if std::process::Command::new("node").arg("--version").output().is_err() {
+            return None;
+        }
+        let dir = TempDir::new().unwrap();
+        let worker = concat!(env!("CARGO_MANIFEST_DIR"), "/src/nodejs-task/worker.js");
+        fs::copy(worker, dir.path().join(WORKER_SCRIPT)).unwrap();
+        fs::write(dir.path().join("package.json"), "{}").unwrap();
+        fs::write(
+            dir.path().join("index.js"),
+            r#"
+            const mode = process.argv[2];
+            console.log(`pid=${process.pid} value=${process.env.TASK_VALUE}`);
+            console.log(`input=${JSON.stringify(global.nautilusTaskRequest.input)}`);
+            if (mode === "flood") console.log("x".repeat(1024 * 1024));
+            if (mode === "crash") process.kill(process.pid, "SIGKILL");
+            if (mode === "hang") setInterval(() => {}, 1000);
+            else process.exit(mode === "fail" ? 2 : 0);
+            "#,
+        )
+        .unwrap();
+        Some(dir)
+    }
+
+    fn pool_task(mode: &str) -> TaskConfig {
+        TaskConfig {
+            args: vec![mode.to_string()],
+            env_vars: HashMap::from([("TASK_VALUE".to_string(), mode.to_string())]),
+            input: serde_json::json!({ "mode": mode }),
+            timeout_secs: 5,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool_reuses_and_replaces_workers() {
+        let Some(dir) = worker_task_dir() else { return };
+        let pool = WorkerPool::start(WorkerPoolConfig {
+            size: 1,
+            task_path: dir.path().to_path_buf(),
+            node_binary: PathBuf::from("node"),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let first = pool.run(&pool_task("ok")).await.unwrap();
+        let second = pool.run(&pool_task("fail")).await.unwrap();
+        assert_eq!(first.exit_code, 0);
+        assert!(first.stdout_text().contains("value=ok"));
+        assert!(first.stdout_text().contains(r#"input={"mode":"ok"}"#));
+        assert_eq!(second.exit_code, 2);
+        assert!(second.stdout_text().contains("value=fail"));
+        // Same warm process for both tasks
+        let pid = |output: &TaskOutput| output.stdout_text().split_whitespace().next().unwrap().to_string();
+        assert_eq!(pid(&first), pid(&second));
+
+        // A crashed worker fails its task and is replaced
+        let crashed = pool.run(&pool_task("crash")).await.unwrap();
+        assert_ne!(crashed.exit_code, 0);
+        assert_eq!(pool.check_health().await, 1);
+        assert_eq!(pool.respawns(), 1);
+        let after_crash = pool.run(&pool_task("ok")).await.unwrap();
+        assert_eq!(after_crash.exit_code, 0);
+        assert_ne!(pid(&after_crash), pid(&first));
+
+        // A hanging task times out and its worker is killed
+        assert!(pool.run(&pool_task("hang")).await.is_err());
+        assert_eq!(pool.run(&pool_task("ok")).await.unwrap().exit_code, 0);
+        assert_eq!(pool.respawns(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool_recycles_workers() {
+        let Some(dir) = worker_task_dir() else { return };
+        let pool = WorkerPool::start(WorkerPoolConfig {
+            size: 1,
+            task_path: dir.path().to_path_buf(),
+            node_binary: PathBuf::from("node"),
+            max_tasks_per_worker: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        pool.run(&pool_task("ok")).await.unwrap();
+        assert_eq!(pool.check_health().await, 1);
+        assert!(WorkerPool::start(WorkerPoolConfig::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool_bounds_response_lines() {
+        let Some(dir) = worker_task_dir() else { return };
+        let pool = WorkerPool::start(WorkerPoolConfig {
+            size: 1,
+            task_path: dir.path().to_path_buf(),
+            node_binary: PathBuf::from("node"),
+            output_limits: OutputLimits {
+                max_line_bytes: 1024,
+                max_stream_bytes: 64 * 1024,
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // A run printing more than the cap fails without buffering all of it
+        let flood = pool.run(&pool_task("flood")).await.unwrap();
+        assert_ne!(flood.exit_code, 0);
+        assert!(flood.stderr_text().contains("exceeds 65536 bytes"));
+        assert!(flood.stdout.is_empty());
+
+        // Its worker is replaced
+        assert_eq!(pool.check_health().await, 1);
+        assert_eq!(pool.run(&pool_task("ok")).await.unwrap().exit_code, 0);
+    }
+
+    #[tokio::test]
+    async fn test_worker_pool_reload_replaces_workers() {
+        let Some(dir) = worker_task_dir() else { return };
+        let pool = WorkerPool::start(WorkerPoolConfig {
+            size: 2,
+            task_path: dir.path().to_path_buf(),
+            node_binary: PathBuf::from("node"),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let before: Vec<Option<u32>> = pool.idle.lock().await.iter().map(PoolWorker::pid).collect();
+        assert_eq!(pool.reload().await, 2);
+        let after: Vec<Option<u32>> = pool.idle.lock().await.iter().map(PoolWorker::pid).collect();
+        assert_eq!(after.len(), 2);
+        assert!(after.iter().all(|pid| !before.contains(pid)));
+        assert_eq!(pool.run(&pool_task("ok")).await.unwrap().exit_code, 0);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server-Sent Events variants of the task endpoints, for live progress on long jobs.
+//! Every stdout and stderr line of the task is sent as a `stdout` or `stderr` event as it
+//! is read, followed by a `result` event with the signed task response (or an `error`
+//! event), and finally the signed stream summary (see [crate::stream_signing]).
+
+use crate::address_limits::AddressOperation;
+use crate::api_response::RequestContext;
+use crate::app::{execute_embedding_ingest, execute_process_data, with_attestation_ref, EmbeddingIngestRequest, TaskRequest, TaskResponse};
+use crate::common::{current_timestamp_ms, to_signed_response, IntentScope, ProcessDataRequest};
+use crate::crash_reports::inherit_request_id;
+use crate::receipts::ReceiptContext;
+use crate::stream_signing::{ChunkAccumulator, STREAM_SIGNATURE_EVENT};
+use crate::task_runner::{OutputLine, OutputStream};
+use crate::validation::ValidJson;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+/// Event carrying the signed task response.
+pub const RESULT_EVENT: &str = "result";
+/// Event carrying the error message of a failed task.
+pub const ERROR_EVENT: &str = "error";
+
+/// Encode one SSE frame. `data` is serialized as single-line JSON, so a frame never
+/// needs more than one `data` field.
+pub fn sse_frame<T: Serialize>(event: &str, data: &T) -> String {
+    let data = serde_json::to_string(data).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e));
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+/// Frame of a task output line.
+pub fn output_frame(line: &OutputLine) -> String {
+    let event = match line.stream {
+        OutputStream::Stdout => "stdout",
+        OutputStream::Stderr => "stderr",
+    };
+    sse_frame(event, &serde_json::json!({ "line": line.line }))
+}
+
+/// Streams the output of a running task, then its outcome and the signed summary.
+struct TaskStream {
+    state: Arc<AppState>,
+    scope: IntentScope,
+    output: UnboundedReceiver<OutputLine>,
+    task: Option<JoinHandle<Result<TaskResponse, EnclaveError>>>,
+    chunks: ChunkAccumulator,
+    finished: bool,
+}
+
+impl TaskStream {
+    fn new(
+        state: Arc<AppState>,
+        scope: IntentScope,
+        output: UnboundedReceiver<OutputLine>,
+        task: JoinHandle<Result<TaskResponse, EnclaveError>>,
+    ) -> Self {
+        Self {
+            state,
+            scope,
+            output,
+            task: Some(task),
+            chunks: ChunkAccumulator::new(),
+            finished: false,
+        }
+    }
+
+    /// Next frame, or `None` once the summary has been sent.
+    async fn next_frame(&mut self) -> Option<String> {
+        if self.finished {
+            return None;
+        }
+        // The output channel closes once the task is done with its sink
+        let frame = if let Some(line) = self.output.recv().await {
+            output_frame(&line)
+        } else if let Some(task) = self.task.take() {
+            match task.await {
+                Ok(Ok(response)) => {
+                    let signed = self.state.key_usage.acquire(self.scope).and_then(|()| {
+                        let key = self.state.keys.current();
+                        let response = with_attestation_ref(&self.state, &key, response);
+                        to_signed_response(&*key, response, current_timestamp_ms(), self.scope)
+                    });
+                    match signed {
+                        Ok(signed) => sse_frame(RESULT_EVENT, &signed),
+                        Err(e) => sse_frame(ERROR_EVENT, &serde_json::json!({ "message": e.status_and_message().1 })),
+                    }
+                }
+                Ok(Err(e)) => sse_frame(ERROR_EVENT, &serde_json::json!({ "message": e.status_and_message().1 })),
+                Err(e) => sse_frame(ERROR_EVENT, &serde_json::json!({ "message": format!("Task panicked: {}", e) })),
+            }
+        } else {
+            self.finished = true;
+            // A stream whose summary cannot be signed ends with an unsigned error frame
+            let summary = self
+                .state
+                .key_usage
+                .acquire(IntentScope::StreamSummary)
+                .and_then(|()| self.chunks.finish(&*self.state.keys.current(), current_timestamp_ms()));
+            return Some(match summary {
+                Ok(summary) => sse_frame(STREAM_SIGNATURE_EVENT, &summary),
+                Err(e) => sse_frame(ERROR_EVENT, &serde_json::json!({ "message": e.status_and_message().1 })),
+            });
+        };
+        self.chunks.push(frame.as_bytes());
+        Some(frame)
+    }
+}
+
+fn sse_response(stream: TaskStream) -> Response {
+    let frames = futures_util::stream::unfold(stream, |mut stream| async move {
+        let frame = stream.next_frame().await?;
+        Some((Ok::<_, Infallible>(Bytes::from(frame)), stream))
+    });
+    Response::builder()
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(frames))
+        .unwrap()
+}
+
+/// `/process_data` streaming the task output. The task keeps running if the client
+/// disconnects. A full task queue is reported with 429 before the stream starts.
+pub async fn process_data_stream(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    ValidJson(request): ValidJson<ProcessDataRequest<TaskRequest>>,
+) -> Response {
+    if let Err(e) = state.scheduler.check_capacity() {
+        return ctx.error::<()>(e).into_response();
+    }
+    let (sink, output) = unbounded_channel();
+    let payload = request.payload;
+    let task_state = state.clone();
+    let task = tokio::spawn(inherit_request_id(async move {
+        let receipt = ReceiptContext::start(&task_state, "process_data", &payload, payload.anchor_receipt);
+        let result = execute_process_data(&task_state, payload, Some(sink)).await;
+        receipt.attach(&task_state, result).await
+    }));
+    sse_response(TaskStream::new(state, IntentScope::ProcessData, output, task))
+}
+
+/// `/embedding_ingest` streaming the task output. Unlike `/embedding_ingest` the task runs
+/// for the duration of the request rather than as a job.
+pub async fn embedding_ingest_stream(
+    ctx: RequestContext,
+    State(state): State<Arc<AppState>>,
+    ValidJson(request): ValidJson<ProcessDataRequest<EmbeddingIngestRequest>>,
+) -> Response {
+    let payload = request.payload;
+    let admitted = state
+        .scheduler
+        .check_capacity()
+        .and_then(|_| state.address_limits.acquire(AddressOperation::Ingest, [payload.policy_object_id.as_str()]));
+    if let Err(e) = admitted {
+        return ctx.error::<()>(e).into_response();
+    }
+    let (sink, output) = unbounded_channel();
+    let task_state = state.clone();
+    let task = tokio::spawn(inherit_request_id(async move {
+        let receipt = ReceiptContext::start(&task_state, "embedding_ingest", &payload, payload.anchor_receipt);
+        let result = execute_embedding_ingest(&task_state, payload, None, Some(sink)).await;
+        receipt.attach(&task_state, result).await
+    }));
+    sse_response(TaskStream::new(state, IntentScope::EmbeddingIngest, output, task))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_app_state;
+
+    #[test]
+    fn test_frames() {
+        let line = OutputLine {
+            stream: OutputStream::Stderr,
+            line: "batch 1/3 \"done\"".to_string(),
+        };
+        assert_eq!(output_frame(&line), "event: stderr\ndata: {\"line\":\"batch 1/3 \\\"done\\\"\"}\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_stream_sends_output_then_outcome_and_summary() {
+        let state = Arc::new(test_app_state());
+        let (sink, output) = unbounded_channel();
+        let task = tokio::spawn(async move {
+            for line in ["loading", "embedding"] {
+                sink.send(OutputLine { stream: OutputStream::Stdout, line: line.to_string() }).unwrap();
+            }
+            Err(EnclaveError::TaskFailed { exit_code: 1, stderr: String::new() })
+        });
+        let mut stream = TaskStream::new(state, IntentScope::ProcessData, output, task);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = stream.next_frame().await {
+            frames.push(frame);
+        }
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[1], "event: stdout\ndata: {\"line\":\"embedding\"}\n\n");
+        assert!(frames[2].starts_with("event: error\n") && frames[2].contains("exit code 1"));
+        assert!(frames[3].starts_with("event: signature\n"));
+        // The summary covers every frame before it
+        assert!(frames[3].contains("\"chunk_count\":3"));
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenTelemetry export. With the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` set,
+//! spans of HTTP requests, task runs and upstream calls are exported to an OTLP collector
+//! outside the enclave. Spans are recorded when the work ends, from the same measurements
+//! as the metrics. The spans of a request share a trace derived from its `x-request-id`
+//! and hang under the span of the HTTP request, so nothing has to be threaded through the
+//! handlers. Without the feature every function here does nothing.
+
+use std::time::Duration;
+
+/// Install the OTLP exporter when `endpoint` is set. Returns whether spans are exported.
+pub fn init(endpoint: Option<&str>) -> anyhow::Result<bool> {
+    let Some(endpoint) = endpoint else { return Ok(false) };
+    #[cfg(feature = "otel")]
+    {
+        otel::install(endpoint)?;
+        Ok(true)
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        tracing::warn!(
+            "OTEL_EXPORTER_OTLP_ENDPOINT is set to {} but the server was built without the otel feature",
+            endpoint
+        );
+        Ok(false)
+    }
+}
+
+/// Flush pending spans, before the server exits.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Span of a handled HTTP request, the parent of the other spans of the request.
+pub fn record_http_request(method: &str, route: &str, status: u16, duration: Duration) {
+    #[cfg(feature = "otel")]
+    otel::record(
+        otel::Kind::Request,
+        format!("{} {}", method, route),
+        duration,
+        vec![
+            ("http.request.method", method.to_string().into()),
+            ("http.route", route.to_string().into()),
+            ("http.response.status_code", i64::from(status).into()),
+        ],
+        status >= 500,
+    );
+    #[cfg(not(feature = "otel"))]
+    let _ = (method, route, status, duration);
+}
+
+/// Span of a Node.js task run. `exit_code` is None when the task could not be run.
+pub fn record_task(operation: &str, exit_code: Option<i32>, duration: Duration) {
+    #[cfg(feature = "otel")]
+    {
+        let mut attributes = vec![("nautilus.operation", operation.to_string().into())];
+        if let Some(exit_code) = exit_code {
+            attributes.push(("process.exit.code", i64::from(exit_code).into()));
+        }
+        otel::record(
+            otel::Kind::Internal,
+            format!("task {}", operation),
+            duration,
+            attributes,
+            exit_code != Some(0),
+        );
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (operation, exit_code, duration);
+}
+
+/// Span of a call to an external service: `qdrant`, `walrus`, `sui` or `embedding`.
+pub fn record_upstream_call(service: &str, call: &str, duration: Duration, succeeded: bool) {
+    #[cfg(feature = "otel")]
+    otel::record(
+        otel::Kind::Client,
+        format!("{} {}", service, call),
+        duration,
+        vec![
+            ("peer.service", service.to_string().into()),
+            ("nautilus.call", call.to_string().into()),
+        ],
+        !succeeded,
+    );
+    #[cfg(not(feature = "otel"))]
+    let _ = (service, call, duration, succeeded);
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use crate::crash_reports::current_request_id;
+    use fastcrypto::hash::{HashFunction, Sha3_256};
+    use opentelemetry::trace::{
+        Span, SpanContext, SpanId, SpanKind, Status, TraceContextExt, TraceFlags, TraceId, TraceState, Tracer,
+    };
+    use opentelemetry::{global, Context, KeyValue, Value};
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use std::time::{Duration, SystemTime};
+
+    const TRACER: &str = "nautilus-server";
+
+    pub enum Kind {
+        /// The HTTP request, root of the request's trace
+        Request,
+        Internal,
+        Client,
+    }
+
+    pub fn install(endpoint: &str) -> anyhow::Result<()> {
+        let provider = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_trace_config(
+                trace::Config::default().with_resource(Resource::new([KeyValue::new("service.name", TRACER)])),
+            )
+            .install_batch(runtime::Tokio)?;
+        global::set_tracer_provider(provider);
+        Ok(())
+    }
+
+    /// Trace and request span IDs of a request, so that spans recorded anywhere while it
+    /// is handled end up in one trace.
+    fn request_trace(request_id: &str) -> (TraceId, SpanId) {
+        let digest = Sha3_256::digest(request_id.as_bytes()).digest;
+        let trace_id = TraceId::from_bytes(digest[..16].try_into().unwrap());
+        let span_id = SpanId::from_bytes(digest[16..24].try_into().unwrap());
+        (trace_id, span_id)
+    }
+
+    pub fn record(kind: Kind, name: String, duration: Duration, attributes: Vec<(&'static str, Value)>, error: bool) {
+        let tracer = global::tracer(TRACER);
+        let end = SystemTime::now();
+        let request_id = current_request_id();
+        let mut attributes: Vec<KeyValue> = attributes.into_iter().map(|(key, value)| KeyValue::new(key, value)).collect();
+        if let Some(request_id) = &request_id {
+            attributes.push(KeyValue::new("nautilus.request_id", request_id.clone()));
+        }
+        let mut builder = tracer
+            .span_builder(name)
+            .with_start_time(end.checked_sub(duration).unwrap_or(end))
+            .with_attributes(attributes);
+        let mut parent = Context::new();
+        match (kind, request_id.as_deref().map(request_trace)) {
+            (Kind::Request, Some((trace_id, span_id))) => {
+                builder = builder.with_kind(SpanKind::Server).with_trace_id(trace_id).with_span_id(span_id);
+            }
+            (Kind::Request, None) => builder = builder.with_kind(SpanKind::Server),
+            (kind, request) => {
+                builder = builder.with_kind(match kind {
+                    Kind::Client => SpanKind::Client,
+                    _ => SpanKind::Internal,
+                });
+                if let Some((trace_id, span_id)) = request {
+                    let context = SpanContext::new(trace_id, span_id, TraceFlags::SAMPLED, true, TraceState::default());
+                    parent = parent.with_remote_span_context(context);
+                }
+            }
+        }
+        let mut span = tracer.build_with_context(builder, &parent);
+        if error {
+            span.set_status(Status::error(""));
+        }
+        span.end_with_timestamp(end);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_request_trace() {
+            let (trace_id, span_id) = request_trace("req-1");
+            assert_eq!(request_trace("req-1"), (trace_id, span_id));
+            assert_ne!(request_trace("req-2").0, trace_id);
+            assert_ne!(trace_id, TraceId::INVALID);
+            assert_ne!(span_id, SpanId::INVALID);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per request phase breakdown. The server times queueing, attestation, the task process
+//! and receipt signing; the task reports its own phases on stdout between the
+//! [TASK_TIMELINE_START] and [TASK_TIMELINE_END] markers.
+
+use crate::task_runner::{TaskOutput, TASK_TIMELINE_END, TASK_TIMELINE_START};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Milliseconds spent in each phase of a request. Task phases run concurrently inside the
+/// task and are summed, so together they can exceed `task_ms`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Timeline {
+    /// Waiting for a free task slot
+    pub queue_wait_ms: u64,
+    pub attestation_ms: u64,
+    pub blob_fetch_ms: Option<u64>,
+    pub decrypt_ms: Option<u64>,
+    pub parse_ms: Option<u64>,
+    pub embed_ms: Option<u64>,
+    pub upsert_ms: Option<u64>,
+    /// Wall time of the Node.js process
+    pub task_ms: u64,
+    /// Signing and anchoring the execution receipt, when enabled
+    pub sign_ms: Option<u64>,
+}
+
+impl Timeline {
+    /// Start from the phases reported by the task, if any.
+    pub fn from_task_output(output: &TaskOutput) -> Self {
+        let mut timeline = parse_task_timeline(&output.stdout_text()).unwrap_or_default();
+        timeline.task_ms = output.execution_time_ms;
+        timeline
+    }
+}
+
+/// Individual call durations and batch sizes the task reports in its timeline block,
+/// for metrics. Not part of responses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TaskSamples {
+    /// Milliseconds of each call, by phase
+    pub calls_ms: BTreeMap<String, Vec<u64>>,
+    /// Size of each batch, by phase
+    pub batch_sizes: BTreeMap<String, Vec<u64>>,
+}
+
+fn timeline_block(stdout: &str) -> Option<&str> {
+    let start = stdout.rfind(TASK_TIMELINE_START)? + TASK_TIMELINE_START.len();
+    let end = stdout[start..].find(TASK_TIMELINE_END)?;
+    Some(stdout[start..start + end].trim())
+}
+
+/// Parse the last timeline block printed by the task.
+pub fn parse_task_timeline(stdout: &str) -> Option<Timeline> {
+    serde_json::from_str(timeline_block(stdout)?).ok()
+}
+
+/// Parse the call samples of the last timeline block printed by the task.
+pub fn parse_task_samples(stdout: &str) -> Option<TaskSamples> {
+    serde_json::from_str(timeline_block(stdout)?).ok()
+}
+
+/// Run `future`, returning its output and how long it took in milliseconds.
+pub async fn timed<F: Future>(future: F) -> (F::Output, u64) {
+    let start = Instant::now();
+    let output = future.await;
+    (output, start.elapsed().as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_task_output() {
+        let stdout = "===TASK_RESULT_START===\n{\"status\":\"success\"}\n===TASK_RESULT_END===\n\
+            ===TASK_TIMELINE_START===\n{\"blob_fetch_ms\":1200,\"decrypt_ms\":300,\"embed_ms\":5000,\"upsert_ms\":800}\n===TASK_TIMELINE_END===\n";
+        let output = TaskOutput {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+            exit_code: 0,
+            execution_time_ms: 7000,
+            resource_usage: None,
+            stdout_stats: Default::default(),
+            stderr_stats: Default::default(),
+            retried_attempts: Vec::new(),
+        };
+        let timeline = Timeline::from_task_output(&output);
+        assert_eq!(timeline.blob_fetch_ms, Some(1200));
+        assert_eq!(timeline.decrypt_ms, Some(300));
+        assert_eq!(timeline.parse_ms, None);
+        assert_eq!(timeline.embed_ms, Some(5000));
+        assert_eq!(timeline.task_ms, 7000);
+    }
+
+    #[test]
+    fn test_task_samples() {
+        let stdout = "===TASK_TIMELINE_START===\n{\"embed_ms\":30,\"calls_ms\":{\"embed\":[10,20]},\"batch_sizes\":{\"embed\":[50,7]}}\n===TASK_TIMELINE_END===\n";
+        let samples = parse_task_samples(stdout).unwrap();
+        assert_eq!(samples.calls_ms["embed"], vec![10, 20]);
+        assert_eq!(samples.batch_sizes["embed"], vec![50, 7]);
+        assert_eq!(parse_task_timeline(stdout).unwrap().embed_ms, Some(30));
+    }
+
+    #[test]
+    fn test_missing_or_invalid_timeline() {
+        assert_eq!(parse_task_timeline("no markers"), None);
+        assert_eq!(
+            parse_task_timeline("===TASK_TIMELINE_START===\nnot json\n===TASK_TIMELINE_END==="),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timed() {
+        let (value, ms) = timed(async {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            7
+        })
+        .await;
+        assert_eq!(value, 7);
+        assert!(ms >= 20);
+    }
+}
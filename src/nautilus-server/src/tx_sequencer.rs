@@ -0,0 +1,242 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sequencer of the Sui transactions the enclave signs. Two transactions paying with the
+//! same version of a gas coin equivocate it: validators lock the coin to the first one they
+//! see, the other fails, and the coin may stay locked until the end of the epoch. Every
+//! transaction therefore goes through [TransactionSequencer::submit], which runs it in a gas
+//! lane: a queue per gas coin holding the coin's latest reference. Transactions of a lane
+//! run one at a time, in arrival order; `SUI_GAS_LANES` lanes run side by side.
+//!
+//! Lanes take the richest SUI coins of the `SUI_SECRET_KEY` account. With more than one lane,
+//! [spawn_gas_lane_setup] splits the coins of `SUI_GAS_LANE_BALANCE` the account lacks off
+//! its richest coin at boot.
+
+use crate::sui::{
+    address_hex, split_transaction, transaction_bytes, ObjectRef, SuiAccount, SuiAddress, SuiClient, TransactionData,
+};
+use crate::AppState;
+use crate::EnclaveError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tracing::{info, warn};
+
+/// Default for `SUI_GAS_LANES`.
+pub const DEFAULT_SUI_GAS_LANES: usize = 1;
+/// Most gas lanes, the boot split pays for one coin per lane.
+pub const MAX_SUI_GAS_LANES: usize = 32;
+/// Default for `SUI_GAS_LANE_BALANCE`, in MIST.
+pub const DEFAULT_SUI_GAS_LANE_BALANCE: u64 = 200_000_000;
+/// Gas budget of the boot split, in MIST.
+const SPLIT_GAS_BUDGET: u64 = 50_000_000;
+
+/// Latest reference of a lane's gas coin, locked by the transaction using it.
+type Lane = Arc<Mutex<ObjectRef>>;
+
+/// Gas lanes shared by every handler submitting transactions.
+#[derive(Debug, Clone)]
+pub struct TransactionSequencer {
+    lane_count: usize,
+    lane_balance: u64,
+    /// Assigned by the boot split or the first submission. Submissions hold a read lock, so
+    /// lanes are only reassigned once none is in use.
+    lanes: Arc<RwLock<Vec<Lane>>>,
+    /// Set when a lane's coin disappeared, e.g. merged by another wallet of the account
+    stale: Arc<AtomicBool>,
+    next: Arc<AtomicUsize>,
+}
+
+impl Default for TransactionSequencer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUI_GAS_LANES, DEFAULT_SUI_GAS_LANE_BALANCE)
+    }
+}
+
+impl TransactionSequencer {
+    pub fn new(lane_count: usize, lane_balance: u64) -> Self {
+        Self {
+            lane_count: lane_count.clamp(1, MAX_SUI_GAS_LANES),
+            lane_balance,
+            lanes: Arc::new(RwLock::new(Vec::new())),
+            stale: Arc::new(AtomicBool::new(false)),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn lane_count(&self) -> usize {
+        self.lane_count
+    }
+
+    /// Build a transaction paying with a lane's gas coin, dry run it and execute it,
+    /// returning its digest. Waits for a free lane; a transaction that would fail is
+    /// refused before it touches the coin.
+    pub async fn submit<F>(&self, client: &SuiClient, account: &SuiAccount, build: F) -> Result<String, EnclaveError>
+    where
+        F: FnOnce(ObjectRef) -> TransactionData,
+    {
+        if self.stale.swap(false, Ordering::SeqCst) || self.lanes.read().await.is_empty() {
+            let coins = client.coins(&account.address).await?;
+            *self.lanes.write().await = self.lanes_from(&account.address, coins)?;
+        }
+        let lanes = self.lanes.read().await;
+        let mut gas = self.acquire(&lanes).await;
+        let transaction = build(gas.clone());
+        let dry_run = client.dry_run(&transaction_bytes(&transaction)).await?;
+        if !dry_run.success {
+            return Err(EnclaveError::BadRequest(format!(
+                "Transaction would fail: {}",
+                dry_run.error.as_deref().unwrap_or("unknown error")
+            )));
+        }
+        let result = client.execute(account, &transaction).await;
+        match &result {
+            Ok((_, Some(next))) => *gas = next.clone(),
+            // A failed or unconfirmed transaction may still have charged the coin
+            _ => self.refresh(client, &mut *gas).await,
+        }
+        result.map(|(digest, _)| digest)
+    }
+
+    /// Assign the lanes, first splitting the coins of `SUI_GAS_LANE_BALANCE` the account
+    /// lacks off its richest coin. Returns the number of lanes.
+    pub async fn prepare(&self, client: &SuiClient, account: &SuiAccount) -> Result<usize, EnclaveError> {
+        let mut lanes = self.lanes.write().await;
+        let mut coins = client.coins(&account.address).await?;
+        let funded = coins.iter().filter(|(_, balance)| *balance >= self.lane_balance).count();
+        if funded < self.lane_count {
+            let missing = self.lane_count - funded;
+            let needed = missing as u64 * self.lane_balance + SPLIT_GAS_BUDGET;
+            let (richest, balance) = coins.first().cloned().ok_or_else(|| no_coins(&account.address))?;
+            if balance < needed {
+                return Err(EnclaveError::BadRequest(format!(
+                    "{} needs a coin of {} MIST to split {} gas coins, its richest holds {}",
+                    address_hex(&account.address),
+                    needed,
+                    missing,
+                    balance
+                )));
+            }
+            let price = client.reference_gas_price().await?;
+            let transaction = split_transaction(
+                account.address,
+                missing as u16,
+                self.lane_balance,
+                (richest, price, SPLIT_GAS_BUDGET),
+            );
+            let (digest, _) = client.execute(account, &transaction).await?;
+            info!("Split {} gas coins of {} MIST in transaction {}", missing, self.lane_balance, digest);
+            coins = client.coins(&account.address).await?;
+        }
+        *lanes = self.lanes_from(&account.address, coins)?;
+        Ok(lanes.len())
+    }
+
+    /// Lanes of the richest `coins`.
+    fn lanes_from(&self, owner: &SuiAddress, coins: Vec<(ObjectRef, u64)>) -> Result<Vec<Lane>, EnclaveError> {
+        if coins.is_empty() {
+            return Err(no_coins(owner));
+        }
+        if coins.len() < self.lane_count {
+            warn!("{} holds {} SUI coins for {} gas lanes", address_hex(owner), coins.len(), self.lane_count);
+        }
+        Ok(coins
+            .into_iter()
+            .take(self.lane_count)
+            .map(|(coin, _)| Arc::new(Mutex::new(coin)))
+            .collect())
+    }
+
+    /// A free lane if there is one, else the next lane in turn once it is free.
+    async fn acquire(&self, lanes: &[Lane]) -> OwnedMutexGuard<ObjectRef> {
+        let first = self.next.fetch_add(1, Ordering::Relaxed) % lanes.len();
+        for offset in 0..lanes.len() {
+            if let Ok(gas) = lanes[(first + offset) % lanes.len()].clone().try_lock_owned() {
+                return gas;
+            }
+        }
+        lanes[first].clone().lock_owned().await
+    }
+
+    /// Reread the lane's coin after a transaction whose effects did not report it.
+    async fn refresh(&self, client: &SuiClient, gas: &mut ObjectRef) {
+        match client.fetch_object(&address_hex(&gas.0)).await {
+            Ok(Some(object)) => match object.object_ref() {
+                Ok(next) => *gas = next,
+                Err(e) => warn!("Unexpected gas coin {}: {:?}", object.object_id, e),
+            },
+            Ok(None) => {
+                warn!("Gas coin {} no longer exists, reassigning the gas lanes", address_hex(&gas.0));
+                self.stale.store(true, Ordering::SeqCst);
+            }
+            Err(e) => warn!("Failed to refresh gas coin {}: {:?}", address_hex(&gas.0), e),
+        }
+    }
+}
+
+fn no_coins(owner: &SuiAddress) -> EnclaveError {
+    EnclaveError::BadRequest(format!("{} has no SUI coin to pay gas", address_hex(owner)))
+}
+
+/// Split the gas lane coins in the background, when there is more than one lane. Without
+/// them the lanes are assigned to the account's coins on the first submission.
+pub fn spawn_gas_lane_setup(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let account = match SuiAccount::from_secret_key(state.sui_secret_key()) {
+            Ok(account) => account,
+            Err(e) => {
+                warn!("Gas lanes not prepared: {:?}", e);
+                return;
+            }
+        };
+        let prepared = match SuiClient::from_state(&state) {
+            Ok(client) => state.tx_sequencer.prepare(&client, &account).await,
+            Err(e) => Err(e),
+        };
+        match prepared {
+            Ok(lanes) => info!("{} gas lanes ready for {}", lanes, address_hex(&account.address)),
+            Err(e) => warn!("Gas lanes not prepared, using the account's coins as they are: {:?}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn coin(id: u8, balance: u64) -> (ObjectRef, u64) {
+        let mut address = [0u8; 32];
+        address[31] = id;
+        (ObjectRef(address, 1, vec![id; 32]), balance)
+    }
+
+    #[tokio::test]
+    async fn test_lanes_queue_per_gas_coin() {
+        let sequencer = TransactionSequencer::new(2, 100);
+        let lanes = sequencer.lanes_from(&[0; 32], vec![coin(1, 500), coin(2, 300), coin(3, 100)]).unwrap();
+        assert_eq!(lanes.len(), 2);
+
+        // Concurrent submissions get a coin each
+        let first = sequencer.acquire(&lanes).await;
+        let second = sequencer.acquire(&lanes).await;
+        assert_ne!(first.0, second.0);
+
+        // A third waits for a lane to be released
+        let waiting = tokio::time::timeout(Duration::from_millis(20), sequencer.acquire(&lanes)).await;
+        assert!(waiting.is_err());
+        let released = first.0;
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_millis(20), sequencer.acquire(&lanes)).await.unwrap();
+        assert_eq!(third.0, released);
+    }
+
+    #[test]
+    fn test_lane_count_is_bounded() {
+        assert_eq!(TransactionSequencer::new(0, 1).lane_count(), 1);
+        assert_eq!(TransactionSequencer::new(1000, 1).lane_count(), MAX_SUI_GAS_LANES);
+        let sequencer = TransactionSequencer::new(4, 1);
+        assert!(sequencer.lanes_from(&[0; 32], Vec::new()).is_err());
+        assert_eq!(sequencer.lanes_from(&[0; 32], vec![coin(1, 5)]).unwrap().len(), 1);
+    }
+}
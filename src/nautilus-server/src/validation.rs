@@ -0,0 +1,306 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Request size limit and payload validation. [limit_request_body] refuses bodies over
+//! `MAX_REQUEST_BODY_BYTES` with 413 before they are read, and [ValidJson] extracts a JSON
+//! payload and checks its fields, so a malformed request gets an envelope naming what is
+//! wrong: 400 for invalid JSON, 422 with one [FieldError] per invalid field.
+
+use crate::api_response::{ApiResponse, RequestContext, REQUEST_ID_HEADER};
+use crate::common::ProcessDataRequest;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request, State};
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Largest request body accepted by default, axum's own default.
+pub const DEFAULT_MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Length of a Walrus blob ID: 32 bytes, URL-safe base64 without padding.
+const BLOB_ID_LENGTH: usize = 43;
+
+/// Longest Sui address or object ID, in hex digits.
+const MAX_ADDRESS_HEX_DIGITS: usize = 64;
+
+/// Why one field of a payload is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Path of the field, e.g. `payload.blobFilePairs[0].walrusBlobId`
+    pub field: String,
+    pub message: String,
+}
+
+/// Field problems collected while validating a payload.
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    /// Record the problem of `field`, if any.
+    pub fn check(&mut self, field: impl Into<String>, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.0.push(FieldError {
+                field: field.into(),
+                message,
+            });
+        }
+    }
+
+    /// Record the problems of a nested value under `prefix`.
+    pub fn nested(&mut self, prefix: &str, value: &impl Validate) {
+        self.0.extend(value.field_errors().into_iter().map(|error| FieldError {
+            field: format!("{}.{}", prefix, error.field),
+            message: error.message,
+        }));
+    }
+
+    pub fn into_vec(self) -> Vec<FieldError> {
+        self.0
+    }
+}
+
+/// Payloads whose fields are checked on extraction by [ValidJson].
+pub trait Validate {
+    /// Problems with the fields of the payload, empty when it is valid.
+    fn field_errors(&self) -> Vec<FieldError> {
+        Vec::new()
+    }
+}
+
+impl<T: Validate> Validate for ProcessDataRequest<T> {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = FieldErrors::default();
+        errors.nested("payload", &self.payload);
+        errors.into_vec()
+    }
+}
+
+/// Seal threshold: a number of key servers from 1 to 255.
+pub fn check_threshold(value: &str) -> Result<(), String> {
+    match value.parse::<u8>() {
+        Ok(threshold) if threshold > 0 => Ok(()),
+        _ => Err(format!("must be an integer from 1 to 255, got {:?}", value)),
+    }
+}
+
+/// Sui address or object ID: `0x` followed by 1 to 64 hex digits.
+pub fn check_address(value: &str) -> Result<(), String> {
+    let digits = value
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("must be 0x-prefixed hex, got {:?}", value))?;
+    if digits.is_empty() || digits.len() > MAX_ADDRESS_HEX_DIGITS || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("must be 0x followed by 1 to {} hex digits", MAX_ADDRESS_HEX_DIGITS));
+    }
+    Ok(())
+}
+
+/// Walrus blob ID: 43 characters of URL-safe base64.
+pub fn check_blob_id(value: &str) -> Result<(), String> {
+    let valid = value.len() == BLOB_ID_LENGTH
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!(
+            "must be a Walrus blob ID of {} URL-safe base64 characters",
+            BLOB_ID_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// JSON extractor that also runs [Validate], rejecting with an envelope instead of axum's
+/// plain text rejections.
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Validate,
+{
+    type Rejection = ApiResponse<()>;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let ctx = RequestContext::new(request_id);
+        let Json(value) = Json::<T>::from_request(request, state)
+            .await
+            .map_err(|rejection| ctx.error(rejection_error(rejection)))?;
+        let errors = value.field_errors();
+        if !errors.is_empty() {
+            return Err(ctx.error(EnclaveError::InvalidPayload(errors)));
+        }
+        Ok(ValidJson(value))
+    }
+}
+
+fn rejection_error(rejection: JsonRejection) -> EnclaveError {
+    match rejection {
+        JsonRejection::JsonDataError(e) => EnclaveError::InvalidPayload(vec![data_error_field(&e.body_text())]),
+        other if other.status() == StatusCode::PAYLOAD_TOO_LARGE => EnclaveError::PayloadTooLarge(other.body_text()),
+        other => EnclaveError::BadRequest(other.body_text()),
+    }
+}
+
+/// Field error of a deserialization failure, whose text reads
+/// `Failed to deserialize the JSON body into the target type: <path>: <error>`.
+fn data_error_field(text: &str) -> FieldError {
+    let detail = text.split_once("target type: ").map_or(text, |(_, detail)| detail);
+    match detail.split_once(": ") {
+        Some((path, message)) if !path.is_empty() && !path.contains(' ') => FieldError {
+            field: path.to_string(),
+            message: message.to_string(),
+        },
+        _ => FieldError {
+            field: "body".to_string(),
+            message: detail.to_string(),
+        },
+    }
+}
+
+/// Middleware refusing requests whose `Content-Length` exceeds `MAX_REQUEST_BODY_BYTES`.
+/// Bodies without a length are cut off at the same limit when read.
+pub async fn limit_request_body(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let max = state.config.max_request_body_bytes;
+    let length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if length.is_some_and(|length| length > max as u64) {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        return RequestContext::new(request_id)
+            .error::<()>(EnclaveError::PayloadTooLarge(format!("Request body exceeds {} bytes", max)))
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::EmbeddingIngestRequest;
+    use crate::test_app_state;
+    use axum::routing::post;
+    use axum::Router;
+
+    const BLOB_ID: &str = "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk";
+
+    #[test]
+    fn test_field_checks() {
+        assert!(check_threshold("2").is_ok());
+        assert!(check_threshold("255").is_ok());
+        for invalid in ["0", "256", "-1", "two", ""] {
+            assert!(check_threshold(invalid).is_err(), "{}", invalid);
+        }
+
+        assert!(check_address("0x2").is_ok());
+        assert!(check_address(&format!("0x{}", "aB".repeat(32))).is_ok());
+        for invalid in ["0x", "2", "0xg1", &format!("0x{}", "a".repeat(65))] {
+            assert!(check_address(invalid).is_err(), "{}", invalid);
+        }
+
+        assert!(check_blob_id(BLOB_ID).is_ok());
+        assert!(check_blob_id("blob123").is_err());
+        assert!(check_blob_id(&BLOB_ID.replace('-', "+")).is_err());
+    }
+
+    #[test]
+    fn test_data_error_field() {
+        let error = data_error_field(
+            "Failed to deserialize the JSON body into the target type: payload.threshold: invalid type: integer `2`, expected a string at line 1 column 30",
+        );
+        assert_eq!(error.field, "payload.threshold");
+        assert!(error.message.starts_with("invalid type"));
+
+        let error = data_error_field("Failed to deserialize the JSON body into the target type: missing field `payload`");
+        assert_eq!(error.field, "body");
+        assert_eq!(error.message, "missing field `payload`");
+    }
+
+    /// Serve `app` on a local port, returning its base URL.
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_reports_fields() {
+        async fn ingest(ValidJson(_): ValidJson<ProcessDataRequest<EmbeddingIngestRequest>>) -> &'static str {
+            "ok"
+        }
+        let url = format!("{}/ingest", serve(Router::new().route("/ingest", post(ingest))).await);
+        let client = reqwest::Client::new();
+        let post_ingest = |blob_id: &str, threshold: serde_json::Value| {
+            client.post(&url).json(&serde_json::json!({ "payload": {
+                "walrusBlobId": blob_id,
+                "onChainFileObjId": "0x1",
+                "policyObjectId": "policy",
+                "threshold": threshold,
+            }}))
+        };
+
+        let response = post_ingest("blob123", "300".into()).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 422);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "invalid_payload");
+        let fields: Vec<&str> = body["error"]["details"]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["payload.walrusBlobId", "payload.policyObjectId", "payload.threshold"]);
+
+        // A wrong type is reported on its field too
+        let response = post_ingest(BLOB_ID, 2.into()).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 422);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["details"]["fields"][0]["field"], "payload.threshold");
+
+        let response = client
+            .post(&url)
+            .header("content-type", "application/json")
+            .body("{")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_limit_request_body() {
+        let mut state = test_app_state();
+        state.config.max_request_body_bytes = 16;
+        let app = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(state), limit_request_body));
+        let url = format!("{}/echo", serve(app).await);
+        let client = reqwest::Client::new();
+
+        let response = client.post(&url).body("small").send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let response = client.post(&url).body("a body over sixteen bytes").send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 413);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "payload_too_large");
+    }
+}
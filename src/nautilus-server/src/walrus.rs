@@ -0,0 +1,978 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Native Walrus client, so the server can fetch and store blobs without delegating to the
+//! Node task. [WalrusClient] talks to the aggregator and publisher from [AppState] and retries
+//! connection errors, timeouts and 5xx responses with exponential backoff.
+//!
+//! A publisher may answer before the blob is certified. With
+//! [StoreOptions::wait_for_certification] set, [store_blob] polls the blob object on Sui
+//! until its `certified_epoch` is set, so callers can safely reference the blob on-chain.
+//!
+//! Many small files are cheaper to store as one quilt: [store_quilt] writes them as a single
+//! blob whose files, the patches, are read back one by one with
+//! [WalrusClient::get_quilt_patch] or [WalrusClient::get_quilt_file].
+
+use crate::breakers::CircuitBreakers;
+use crate::metrics::Metrics;
+use crate::sui::{SuiClient, SuiObject};
+use crate::AppState;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(8);
+
+/// Default for `WALRUS_CERTIFICATION_TIMEOUT_SECS`.
+pub const DEFAULT_CERTIFICATION_TIMEOUT_SECS: u64 = 60;
+/// Default for `WALRUS_MAX_EPOCHS`, the longest storage period Walrus accepts.
+pub const DEFAULT_MAX_EPOCHS: u32 = 53;
+/// Default for `SUI_RPC_URL`.
+pub const DEFAULT_SUI_RPC_URL: &str = "https://fullnode.mainnet.sui.io:443";
+/// Default for `WALRUS_SYSTEM_OBJECT_ID`, the Walrus mainnet system object.
+pub const DEFAULT_WALRUS_SYSTEM_OBJECT_ID: &str =
+    "0x2134d52768ea07e8c43570ef975eb3e4c27a39fa6396bef985b5abc58d03ddd2";
+
+/// How a blob is stored.
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Storage epochs for this blob, defaults to `WALRUS_EPOCHS`.
+    pub epochs: Option<u32>,
+    /// Attempts for the store request; connection errors and 5xx responses are retried.
+    pub max_attempts: u32,
+    /// Delay before the first retry and the first certification poll, doubled each time.
+    pub initial_backoff: Duration,
+    /// Poll until the blob is certified before returning.
+    pub wait_for_certification: bool,
+    /// Deadline for certification, measured from the end of the store request.
+    pub certification_timeout: Duration,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            epochs: None,
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            wait_for_certification: false,
+            certification_timeout: Duration::from_secs(DEFAULT_CERTIFICATION_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// Limits on what the server may store, guarding against accidentally expensive stores.
+#[derive(Debug, Clone)]
+pub struct StorageBudget {
+    pub max_epochs: u32,
+    /// Ceiling on `size_bytes * epochs` for a single store, unlimited when unset.
+    pub max_byte_epochs: Option<u64>,
+}
+
+impl Default for StorageBudget {
+    fn default() -> Self {
+        Self {
+            max_epochs: DEFAULT_MAX_EPOCHS,
+            max_byte_epochs: None,
+        }
+    }
+}
+
+/// Estimated storage cost of a blob, in byte-epochs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StorageEstimate {
+    pub size_bytes: u64,
+    pub epochs: u32,
+    pub byte_epochs: u64,
+}
+
+impl StorageBudget {
+    pub fn check_epochs(&self, epochs: u32) -> Result<(), EnclaveError> {
+        if epochs == 0 {
+            return Err(EnclaveError::BadRequest("Walrus epochs must be at least 1".to_string()));
+        }
+        if epochs > self.max_epochs {
+            return Err(EnclaveError::BadRequest(format!(
+                "Requested {} Walrus epochs exceeds the maximum of {} (WALRUS_MAX_EPOCHS)",
+                epochs, self.max_epochs
+            )));
+        }
+        Ok(())
+    }
+
+    /// Estimate the cost of storing `size_bytes` for `epochs`, rejecting stores over budget.
+    pub fn estimate(&self, size_bytes: u64, epochs: u32) -> Result<StorageEstimate, EnclaveError> {
+        self.check_epochs(epochs)?;
+        let byte_epochs = size_bytes.saturating_mul(epochs as u64);
+        if let Some(max) = self.max_byte_epochs {
+            if byte_epochs > max {
+                return Err(EnclaveError::BadRequest(format!(
+                    "Estimated Walrus storage cost of {} byte-epochs ({} bytes x {} epochs) exceeds the budget of {} (WALRUS_MAX_STORE_BYTE_EPOCHS)",
+                    byte_epochs, size_bytes, epochs, max
+                )));
+            }
+        }
+        Ok(StorageEstimate {
+            size_bytes,
+            epochs,
+            byte_epochs,
+        })
+    }
+}
+
+fn backoff(initial: Duration, attempt: u32) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_POLL_INTERVAL)
+}
+
+/// Result of storing a blob on Walrus.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredBlob {
+    pub blob_id: String,
+    /// Sui object ID of the blob, only present for newly created blobs.
+    pub object_id: Option<String>,
+    /// Epoch until which the blob is stored, if reported.
+    pub end_epoch: Option<u64>,
+    /// True if an identical blob was already certified.
+    pub already_certified: bool,
+    /// Epoch in which the blob was certified, once known.
+    pub certified_epoch: Option<u64>,
+}
+
+impl StoredBlob {
+    pub fn is_certified(&self) -> bool {
+        self.already_certified || self.certified_epoch.is_some()
+    }
+}
+
+/// Availability of a blob on the aggregator.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BlobStatus {
+    Available {
+        /// Blob size, if the aggregator reported it.
+        size_bytes: Option<u64>,
+    },
+    NotFound,
+}
+
+/// Parse the publisher's `PUT /v1/blobs` response.
+pub fn parse_store_response(body: &serde_json::Value) -> Result<StoredBlob, EnclaveError> {
+    if let Some(created) = body.get("newlyCreated") {
+        let blob = &created["blobObject"];
+        let blob_id = blob["blobId"].as_str().ok_or_else(|| {
+            EnclaveError::upstream("walrus", "Walrus response is missing blobObject.blobId")
+        })?;
+        return Ok(StoredBlob {
+            blob_id: blob_id.to_string(),
+            object_id: blob["id"].as_str().map(str::to_string),
+            end_epoch: blob["storage"]["endEpoch"].as_u64(),
+            already_certified: false,
+            certified_epoch: blob["certifiedEpoch"].as_u64(),
+        });
+    }
+    if let Some(certified) = body.get("alreadyCertified") {
+        let blob_id = certified["blobId"].as_str().ok_or_else(|| {
+            EnclaveError::upstream("walrus", "Walrus response is missing alreadyCertified.blobId")
+        })?;
+        return Ok(StoredBlob {
+            blob_id: blob_id.to_string(),
+            object_id: None,
+            end_epoch: certified["endEpoch"].as_u64(),
+            already_certified: true,
+            certified_epoch: None,
+        });
+    }
+    Err(EnclaveError::upstream("walrus", format!(
+        "Unexpected Walrus store response: {}",
+        body
+    )))
+}
+
+/// File to store in a quilt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuiltFile {
+    /// Name of the file within the quilt, unique in it
+    pub identifier: String,
+    pub bytes: Vec<u8>,
+}
+
+impl QuiltFile {
+    pub fn new(identifier: impl Into<String>, bytes: Vec<u8>) -> Self {
+        Self {
+            identifier: identifier.into(),
+            bytes,
+        }
+    }
+}
+
+/// File stored in a quilt.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuiltPatch {
+    pub identifier: String,
+    /// ID reading the file alone, with [WalrusClient::get_quilt_patch]
+    pub quilt_patch_id: String,
+}
+
+/// Result of storing a quilt on Walrus.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoredQuilt {
+    /// The quilt's blob; its blob ID is the quilt ID
+    pub blob: StoredBlob,
+    pub patches: Vec<QuiltPatch>,
+}
+
+impl StoredQuilt {
+    /// Patch ID of the file stored as `identifier`.
+    pub fn patch_id(&self, identifier: &str) -> Option<&str> {
+        self.patches
+            .iter()
+            .find(|patch| patch.identifier == identifier)
+            .map(|patch| patch.quilt_patch_id.as_str())
+    }
+}
+
+/// Parse the publisher's `PUT /v1/quilts` response.
+pub fn parse_quilt_store_response(body: &serde_json::Value) -> Result<StoredQuilt, EnclaveError> {
+    let blob = parse_store_response(&body["blobStoreResult"])?;
+    let patches = body["storedQuiltBlobs"]
+        .as_array()
+        .ok_or_else(|| EnclaveError::upstream("walrus", "Walrus response is missing storedQuiltBlobs"))?
+        .iter()
+        .map(|patch| match (patch["identifier"].as_str(), patch["quiltPatchId"].as_str()) {
+            (Some(identifier), Some(quilt_patch_id)) => Ok(QuiltPatch {
+                identifier: identifier.to_string(),
+                quilt_patch_id: quilt_patch_id.to_string(),
+            }),
+            _ => Err(EnclaveError::upstream("walrus", format!("Invalid quilt patch in Walrus response: {}", patch))),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(StoredQuilt { blob, patches })
+}
+
+/// `multipart/form-data` body with one part per file, named by its identifier, as the
+/// publisher's quilt endpoint expects.
+fn quilt_body(files: &[QuiltFile], boundary: &str) -> Result<Vec<u8>, EnclaveError> {
+    if files.is_empty() {
+        return Err(EnclaveError::BadRequest("A quilt needs at least one file".to_string()));
+    }
+    let mut seen = std::collections::HashSet::new();
+    let mut body = Vec::new();
+    for file in files {
+        let identifier = &file.identifier;
+        // Walrus reserves identifiers starting with an underscore for quilt metadata
+        if identifier.is_empty()
+            || identifier.starts_with('_')
+            || identifier.contains(|c: char| c == '"' || c.is_control())
+        {
+            return Err(EnclaveError::BadRequest(format!("Invalid quilt file identifier {:?}", identifier)));
+        }
+        if !seen.insert(identifier.as_str()) {
+            return Err(EnclaveError::BadRequest(format!("Duplicate quilt file identifier {}", identifier)));
+        }
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                boundary, identifier, identifier
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(&file.bytes);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    Ok(body)
+}
+
+/// Number in a Move struct field; u32 fields are JSON numbers, wider ones strings.
+fn move_u64(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// `certified_epoch` of a Walrus blob object, `None` while the blob is not certified yet.
+pub fn parse_certified_epoch(object: &SuiObject) -> Option<u64> {
+    move_u64(&object.fields["certified_epoch"])
+}
+
+fn http_client() -> Result<reqwest::Client, EnclaveError> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| EnclaveError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// Error of a single request attempt, and whether it is worth retrying.
+type AttemptError = (bool, EnclaveError);
+
+fn request_error(action: &str, e: reqwest::Error) -> AttemptError {
+    let retryable = e.is_connect() || e.is_timeout();
+    (retryable, EnclaveError::upstream("walrus", format!("Walrus {} request failed: {}", action, e)))
+}
+
+/// Turn a non-success response into an error, retryable for 5xx statuses.
+async fn check_status(action: &str, response: reqwest::Response) -> Result<reqwest::Response, AttemptError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err((
+        status.is_server_error(),
+        EnclaveError::upstream("walrus", format!("Walrus {} failed with HTTP {}: {}", action, status, body)),
+    ))
+}
+
+/// Async client for the Walrus aggregator and publisher.
+#[derive(Debug, Clone)]
+pub struct WalrusClient {
+    http: reqwest::Client,
+    aggregator_url: String,
+    /// Aggregators reads go to when `aggregator_url` is unavailable, in order
+    fallback_aggregator_urls: Vec<String>,
+    publisher_url: String,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    metrics: Option<Metrics>,
+    breakers: Option<CircuitBreakers>,
+}
+
+impl WalrusClient {
+    pub fn new(aggregator_url: &str, publisher_url: &str) -> Result<Self, EnclaveError> {
+        let defaults = StoreOptions::default();
+        Ok(Self {
+            http: http_client()?,
+            aggregator_url: aggregator_url.trim_end_matches('/').to_string(),
+            fallback_aggregator_urls: Vec::new(),
+            publisher_url: publisher_url.trim_end_matches('/').to_string(),
+            max_attempts: defaults.max_attempts,
+            initial_backoff: defaults.initial_backoff,
+            metrics: None,
+            breakers: None,
+        })
+    }
+
+    /// Client for the configured aggregator and publisher, recording call durations in the
+    /// server metrics and going through the Walrus circuit breaker.
+    pub fn from_state(state: &AppState) -> Result<Self, EnclaveError> {
+        let mut client = Self::new(state.walrus_aggregator_url(), state.walrus_publisher_url())?
+            .with_fallback_aggregators(&state.walrus_aggregator_fallback_urls());
+        client.metrics = Some(state.metrics.clone());
+        client.breakers = Some(state.breakers.clone());
+        Ok(client)
+    }
+
+    /// Aggregators reads fail over to, in order, while the aggregator is unavailable.
+    pub fn with_fallback_aggregators(mut self, urls: &[&str]) -> Self {
+        self.fallback_aggregator_urls = urls.iter().map(|url| url.trim_end_matches('/').to_string()).collect();
+        self
+    }
+
+    /// Attempts per request and the delay before the first retry, doubled each time.
+    pub fn with_retry(mut self, max_attempts: u32, initial_backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Run `request` with retries, failing at once while the circuit breaker is open. The
+    /// breaker sees the outcome after the last attempt.
+    async fn with_retries<T, F, Fut>(&self, call: &str, request: F) -> Result<T, EnclaveError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        match &self.breakers {
+            Some(breakers) => breakers.call("walrus", call, self.attempts(call, request)).await,
+            None => self.attempts(call, request).await,
+        }
+    }
+
+    async fn attempts<T, F, Fut>(&self, call: &str, mut request: F) -> Result<T, EnclaveError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let started = Instant::now();
+            let result = request().await;
+            if let Some(metrics) = &self.metrics {
+                metrics.observe_external_call("walrus", call, started.elapsed());
+            }
+            crate::telemetry::record_upstream_call("walrus", call, started.elapsed(), result.is_ok());
+            match result {
+                Ok(value) => return Ok(value),
+                Err((true, e)) if attempt < self.max_attempts => {
+                    warn!("Walrus {} attempt {} failed, retrying: {:?}", call, attempt, e);
+                    tokio::time::sleep(backoff(self.initial_backoff, attempt)).await;
+                    attempt += 1;
+                }
+                Err((_, e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Run a read against the aggregator, then against each fallback aggregator while the
+    /// previous one is unavailable. A missing blob is an answer and is not retried elsewhere.
+    /// Fallbacks bypass the circuit breaker, which tracks the aggregator.
+    async fn read<T, F, Fut>(&self, call: &str, request: F) -> Result<T, EnclaveError>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        let mut result = self.with_retries(call, || request(self.aggregator_url.clone())).await;
+        for fallback in &self.fallback_aggregator_urls {
+            match &result {
+                Err(e @ EnclaveError::UpstreamUnavailable { .. }) => {
+                    warn!("Walrus {} failed, reading from aggregator {}: {:?}", call, fallback, e)
+                }
+                _ => break,
+            }
+            result = self.attempts(call, || request(fallback.clone())).await;
+        }
+        result
+    }
+
+    /// Fetch the content of a blob from the aggregator.
+    pub async fn get_blob(&self, blob_id: &str) -> Result<Vec<u8>, EnclaveError> {
+        self.fetch(
+            |aggregator| Ok(format!("{}/v1/blobs/{}", aggregator, blob_id)),
+            || format!("Blob {} not found", blob_id),
+        )
+        .await
+    }
+
+    /// Fetch one file of a quilt by its patch ID.
+    pub async fn get_quilt_patch(&self, quilt_patch_id: &str) -> Result<Vec<u8>, EnclaveError> {
+        self.fetch(
+            |aggregator| Ok(format!("{}/v1/blobs/by-quilt-patch-id/{}", aggregator, quilt_patch_id)),
+            || format!("Quilt patch {} not found", quilt_patch_id),
+        )
+        .await
+    }
+
+    /// Fetch one file of a quilt by the quilt ID and the file's identifier.
+    pub async fn get_quilt_file(&self, quilt_id: &str, identifier: &str) -> Result<Vec<u8>, EnclaveError> {
+        let url = |aggregator: &str| {
+            let mut url = reqwest::Url::parse(aggregator)
+                .map_err(|e| EnclaveError::ConfigError(format!("Invalid Walrus aggregator URL: {}", e)))?;
+            url.path_segments_mut()
+                .map_err(|_| EnclaveError::ConfigError("Walrus aggregator URL cannot have a path".to_string()))?
+                .pop_if_empty()
+                .extend(["v1", "blobs", "by-quilt-id", quilt_id, identifier]);
+            Ok(url.to_string())
+        };
+        self.fetch(url, || format!("File {} not found in quilt {}", identifier, quilt_id))
+            .await
+    }
+
+    /// Fetch the URL `url` builds for an aggregator, failing over to the fallbacks.
+    async fn fetch(
+        &self,
+        url: impl Fn(&str) -> Result<String, EnclaveError>,
+        not_found: impl Fn() -> String,
+    ) -> Result<Vec<u8>, EnclaveError> {
+        let (url, not_found) = (&url, &not_found);
+        self.read("fetch", |aggregator| async move {
+            let url = url(&aggregator).map_err(|e| (false, e))?;
+            let response = self.http.get(url).send().await.map_err(|e| request_error("fetch", e))?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Err((false, EnclaveError::NotFound(not_found())));
+            }
+            let response = check_status("fetch", response).await?;
+            let bytes = response.bytes().await.map_err(|e| request_error("fetch", e))?;
+            Ok(bytes.to_vec())
+        })
+        .await
+    }
+
+    /// Store `bytes` as a blob for `epochs` epochs via the publisher.
+    pub async fn put_blob(&self, bytes: &[u8], epochs: u32) -> Result<StoredBlob, EnclaveError> {
+        let url = format!("{}/v1/blobs?epochs={}", self.publisher_url, epochs);
+        self.with_retries("store", || async {
+            let response = self
+                .http
+                .put(&url)
+                .body(bytes.to_vec())
+                .send()
+                .await
+                .map_err(|e| request_error("store", e))?;
+            let body: serde_json::Value = check_status("store", response)
+                .await?
+                .json()
+                .await
+                .map_err(|e| (false, EnclaveError::upstream("walrus", format!("Invalid Walrus store response: {}", e))))?;
+            parse_store_response(&body).map_err(|e| (false, e))
+        })
+        .await
+    }
+
+    /// Store `files` as one quilt for `epochs` epochs via the publisher.
+    pub async fn put_quilt(&self, files: &[QuiltFile], epochs: u32) -> Result<StoredQuilt, EnclaveError> {
+        let url = format!("{}/v1/quilts?epochs={}", self.publisher_url, epochs);
+        let boundary = format!("nautilus-{}", uuid::Uuid::new_v4().simple());
+        let body = quilt_body(files, &boundary)?;
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        self.with_retries("store_quilt", || async {
+            let response = self
+                .http
+                .put(&url)
+                .header(reqwest::header::CONTENT_TYPE, &content_type)
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(|e| request_error("store_quilt", e))?;
+            let body: serde_json::Value = check_status("store_quilt", response)
+                .await?
+                .json()
+                .await
+                .map_err(|e| (false, EnclaveError::upstream("walrus", format!("Invalid Walrus quilt response: {}", e))))?;
+            parse_quilt_store_response(&body).map_err(|e| (false, e))
+        })
+        .await
+    }
+
+    /// Check whether the aggregator, or a fallback while it is unavailable, can serve a
+    /// blob, without downloading it.
+    pub async fn blob_status(&self, blob_id: &str) -> Result<BlobStatus, EnclaveError> {
+        self.read("status", |aggregator| async move {
+            let url = format!("{}/v1/blobs/{}", aggregator, blob_id);
+            let response = self.http.head(&url).send().await.map_err(|e| request_error("status", e))?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(BlobStatus::NotFound);
+            }
+            let response = check_status("status", response).await?;
+            let size_bytes = response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+            Ok(BlobStatus::Available { size_bytes })
+        })
+        .await
+    }
+}
+
+/// Version of the Walrus system object. The system state itself is a dynamic field of the
+/// object, keyed by this version.
+pub fn parse_system_version(object: &SuiObject) -> Result<u64, EnclaveError> {
+    move_u64(&object.fields["version"]).ok_or_else(|| {
+        EnclaveError::upstream("sui", format!("Unexpected Walrus system object: {}", object.fields))
+    })
+}
+
+/// Current epoch from the system state dynamic field.
+pub fn parse_system_epoch(inner: &SuiObject) -> Result<u64, EnclaveError> {
+    move_u64(&inner.fields["value"]["fields"]["committee"]["fields"]["epoch"]).ok_or_else(|| {
+        EnclaveError::upstream("sui", "Walrus system state has no committee epoch")
+    })
+}
+
+/// Current Walrus epoch, read from the system object on Sui. The object only changes on
+/// upgrades, so it may come from the cache; the system state is read fresh.
+pub async fn current_epoch(sui: &SuiClient, system_object_id: &str) -> Result<u64, EnclaveError> {
+    let object = sui
+        .get_object(system_object_id)
+        .await?
+        .ok_or_else(|| EnclaveError::NotFound(format!("Walrus system object {} not found", system_object_id)))?;
+    let version = parse_system_version(&object)?;
+    let inner = sui
+        .get_dynamic_field_object(system_object_id, "u64", serde_json::json!(version.to_string()))
+        .await?
+        .ok_or_else(|| EnclaveError::upstream("sui", format!("Walrus system state {} not found", version)))?;
+    parse_system_epoch(&inner)
+}
+
+/// Poll the blob object until it is certified or the deadline passes.
+async fn wait_for_certification(
+    sui: &SuiClient,
+    blob: &mut StoredBlob,
+    options: &StoreOptions,
+) -> Result<(), EnclaveError> {
+    let object_id = blob.object_id.clone().ok_or_else(|| {
+        EnclaveError::upstream("walrus", "Walrus response has no blob object ID to poll")
+    })?;
+    let deadline = Instant::now() + options.certification_timeout;
+    let mut attempt = 1;
+    loop {
+        match sui.fetch_object(&object_id).await {
+            Ok(Some(object)) => {
+                if let Some(epoch) = parse_certified_epoch(&object) {
+                    blob.certified_epoch = Some(epoch);
+                    return Ok(());
+                }
+            }
+            Ok(None) => warn!("Blob object {} of blob {} not found yet", object_id, blob.blob_id),
+            Err(e) => warn!("Failed to poll certification of blob {}: {:?}", blob.blob_id, e),
+        }
+        let delay = backoff(options.initial_backoff, attempt);
+        if Instant::now() + delay > deadline {
+            return Err(EnclaveError::Timeout(format!(
+                "Blob {} was not certified within {}s",
+                blob.blob_id,
+                options.certification_timeout.as_secs()
+            )));
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Store `bytes` as a blob via the configured publisher, for `options.epochs` or
+/// `WALRUS_EPOCHS` epochs. Stores over the configured [StorageBudget] are rejected.
+pub async fn store_blob(
+    state: &AppState,
+    bytes: Vec<u8>,
+    options: &StoreOptions,
+) -> Result<StoredBlob, EnclaveError> {
+    let epochs = options.epochs.unwrap_or_else(|| state.walrus_epochs());
+    let estimate = state.walrus_budget.estimate(bytes.len() as u64, epochs)?;
+    info!(
+        "Storing {} bytes on Walrus for {} epochs ({} byte-epochs)",
+        estimate.size_bytes, estimate.epochs, estimate.byte_epochs
+    );
+    let client = WalrusClient::from_state(state)?.with_retry(options.max_attempts, options.initial_backoff);
+    let mut blob = client.put_blob(&bytes, epochs).await?;
+
+    if options.wait_for_certification && !blob.is_certified() {
+        wait_for_certification(&SuiClient::from_state(state)?, &mut blob, options).await?;
+    }
+    Ok(blob)
+}
+
+/// Store `files` as one quilt via the configured publisher, like [store_blob]. The budget
+/// is checked against the total size of the files.
+pub async fn store_quilt(
+    state: &AppState,
+    files: Vec<QuiltFile>,
+    options: &StoreOptions,
+) -> Result<StoredQuilt, EnclaveError> {
+    let epochs = options.epochs.unwrap_or_else(|| state.walrus_epochs());
+    let size_bytes = files.iter().map(|file| file.bytes.len() as u64).sum();
+    let estimate = state.walrus_budget.estimate(size_bytes, epochs)?;
+    info!(
+        "Storing {} files ({} bytes) as a Walrus quilt for {} epochs ({} byte-epochs)",
+        files.len(),
+        estimate.size_bytes,
+        estimate.epochs,
+        estimate.byte_epochs
+    );
+    let client = WalrusClient::from_state(state)?.with_retry(options.max_attempts, options.initial_backoff);
+    let mut quilt = client.put_quilt(&files, epochs).await?;
+
+    if options.wait_for_certification && !quilt.blob.is_certified() {
+        wait_for_certification(&SuiClient::from_state(state)?, &mut quilt.blob, options).await?;
+    }
+    Ok(quilt)
+}
+
+/// Refuse to ingest a blob the aggregator does not serve, or one over `max_bytes`. Blobs
+/// of unknown size pass.
+pub fn check_ingest_blob(
+    blob_id: &str,
+    aggregator_url: &str,
+    status: &BlobStatus,
+    max_bytes: Option<u64>,
+) -> Result<(), EnclaveError> {
+    match (status, max_bytes) {
+        (BlobStatus::NotFound, _) => Err(EnclaveError::NotFound(format!(
+            "Walrus blob {} not found on aggregator {}",
+            blob_id, aggregator_url
+        ))),
+        (BlobStatus::Available { size_bytes: Some(0) }, _) => Err(EnclaveError::BadRequest(format!(
+            "Walrus blob {} on aggregator {} is empty",
+            blob_id, aggregator_url
+        ))),
+        (BlobStatus::Available { size_bytes: Some(size) }, Some(max)) if *size > max => {
+            Err(EnclaveError::PayloadTooLarge(format!(
+                "Walrus blob {} is {} bytes, over WALRUS_MAX_INGEST_BLOB_BYTES of {}",
+                blob_id, size, max
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that the aggregator serves `blob_id` before a task spends minutes failing to fetch
+/// it. A failed check leaves the blob to the task, like an unknown size; disabled by
+/// `WALRUS_BLOB_PRECHECK=false`.
+pub async fn precheck_blob(state: &AppState, blob_id: &str) -> Result<(), EnclaveError> {
+    if !state.config.walrus_blob_precheck {
+        return Ok(());
+    }
+    match WalrusClient::from_state(state)?.blob_status(blob_id).await {
+        Ok(status) => check_ingest_blob(
+            blob_id,
+            state.walrus_aggregator_url(),
+            &status,
+            state.config.walrus_max_ingest_blob_bytes,
+        ),
+        Err(e) => {
+            warn!("Walrus blob {} not pre-checked, leaving it to the task: {:?}", blob_id, e);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_store_response() {
+        let created = json!({
+            "newlyCreated": {
+                "blobObject": {
+                    "id": "0xabc",
+                    "blobId": "blob-1",
+                    "certifiedEpoch": null,
+                    "storage": { "endEpoch": 42 }
+                }
+            }
+        });
+        let blob = parse_store_response(&created).unwrap();
+        assert_eq!(blob.blob_id, "blob-1");
+        assert_eq!(blob.object_id.as_deref(), Some("0xabc"));
+        assert_eq!(blob.end_epoch, Some(42));
+        assert!(!blob.is_certified());
+
+        let certified = json!({ "alreadyCertified": { "blobId": "blob-2", "endEpoch": 7 } });
+        let blob = parse_store_response(&certified).unwrap();
+        assert_eq!(blob.blob_id, "blob-2");
+        assert!(blob.is_certified());
+
+        assert!(parse_store_response(&json!({ "unexpected": true })).is_err());
+    }
+
+    fn sui_object(fields: serde_json::Value) -> SuiObject {
+        let result = json!({ "data": { "objectId": "0xabc", "version": "3", "digest": "d", "content": { "fields": fields } } });
+        SuiObject::from_result(&result).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_certified_epoch() {
+        assert_eq!(parse_certified_epoch(&sui_object(json!({ "certified_epoch": null }))), None);
+        assert_eq!(parse_certified_epoch(&sui_object(json!({ "certified_epoch": 155 }))), Some(155));
+        assert_eq!(parse_certified_epoch(&sui_object(json!({ "certified_epoch": "156" }))), Some(156));
+        assert_eq!(SuiObject::from_result(&json!({ "error": { "code": "notExists" } })).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_system_epoch() {
+        assert_eq!(parse_system_version(&sui_object(json!({ "version": "2" }))).unwrap(), 2);
+        let inner = sui_object(json!({ "value": { "fields": {
+            "committee": { "fields": { "epoch": 17, "n_shards": 1000 } }
+        }}}));
+        assert_eq!(parse_system_epoch(&inner).unwrap(), 17);
+        assert!(parse_system_epoch(&sui_object(json!({ "value": null }))).is_err());
+        assert!(parse_system_version(&sui_object(json!(null))).is_err());
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let initial = Duration::from_secs(1);
+        assert_eq!(backoff(initial, 1), Duration::from_secs(1));
+        assert_eq!(backoff(initial, 3), Duration::from_secs(4));
+        assert_eq!(backoff(initial, 10), MAX_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn test_storage_budget() {
+        let budget = StorageBudget {
+            max_epochs: 10,
+            max_byte_epochs: Some(1_000),
+        };
+        assert_eq!(budget.estimate(100, 10).unwrap().byte_epochs, 1_000);
+        assert!(budget.estimate(101, 10).is_err());
+        assert!(budget.estimate(1, 11).is_err());
+        assert!(budget.estimate(1, 0).is_err());
+        assert!(StorageBudget::default().estimate(u64::MAX, 5).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_client_retries_and_reports_status() {
+        use axum::extract::Path;
+        use axum::http::StatusCode;
+        use axum::routing::{get, put};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let fail_next = Arc::new(AtomicBool::new(true));
+        let app = axum::Router::new()
+            .route(
+                "/v1/blobs/:id",
+                get(move |Path(id): Path<String>| {
+                    let fail_next = fail_next.clone();
+                    async move {
+                        if id != "blob-1" {
+                            return Err(StatusCode::NOT_FOUND);
+                        }
+                        // Fail once to exercise the retry
+                        if fail_next.swap(false, Ordering::SeqCst) {
+                            return Err(StatusCode::SERVICE_UNAVAILABLE);
+                        }
+                        Ok("content")
+                    }
+                }),
+            )
+            .route(
+                "/v1/blobs",
+                put(|| async {
+                    axum::Json(json!({ "alreadyCertified": { "blobId": "blob-1", "endEpoch": 7 } }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = WalrusClient::new(&url, &url).unwrap().with_retry(2, Duration::from_millis(1));
+        assert_eq!(client.get_blob("blob-1").await.unwrap(), b"content");
+        assert!(client.get_blob("missing").await.is_err());
+        assert_eq!(
+            client.blob_status("blob-1").await.unwrap(),
+            BlobStatus::Available { size_bytes: Some(7) }
+        );
+        assert_eq!(client.blob_status("missing").await.unwrap(), BlobStatus::NotFound);
+        let stored = client.put_blob(b"content", 5).await.unwrap();
+        assert!(stored.already_certified);
+        assert_eq!(stored.end_epoch, Some(7));
+    }
+
+    #[test]
+    fn test_check_ingest_blob() {
+        let available = |size_bytes| BlobStatus::Available { size_bytes };
+        let aggregator = "https://aggregator.example.com";
+        assert!(check_ingest_blob("blob-1", aggregator, &available(Some(7)), None).is_ok());
+        assert!(check_ingest_blob("blob-1", aggregator, &available(Some(7)), Some(7)).is_ok());
+        assert!(check_ingest_blob("blob-1", aggregator, &available(None), Some(1)).is_ok());
+
+        // The 404 names the blob and the aggregator, so typos are obvious
+        match check_ingest_blob("blob-typo", aggregator, &BlobStatus::NotFound, None) {
+            Err(EnclaveError::NotFound(message)) => {
+                assert!(message.contains("blob-typo") && message.contains(aggregator), "{}", message)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            check_ingest_blob("blob-1", aggregator, &available(Some(8)), Some(7)),
+            Err(EnclaveError::PayloadTooLarge(_))
+        ));
+        assert!(matches!(
+            check_ingest_blob("blob-1", aggregator, &available(Some(0)), None),
+            Err(EnclaveError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_quilt_body_and_response() {
+        let files = [QuiltFile::new("a.json", b"{}".to_vec()), QuiltFile::new("b.json", b"[]".to_vec())];
+        let body = String::from_utf8(quilt_body(&files, "xyz").unwrap()).unwrap();
+        assert!(body.starts_with("--xyz\r\nContent-Disposition: form-data; name=\"a.json\"; filename=\"a.json\"\r\n"));
+        assert!(body.contains("\r\n\r\n[]\r\n--xyz--\r\n"));
+        for invalid in [vec![], vec![files[0].clone(), files[0].clone()], vec![QuiltFile::new("_meta", vec![])]] {
+            assert!(quilt_body(&invalid, "xyz").is_err());
+        }
+
+        let response = json!({
+            "blobStoreResult": { "alreadyCertified": { "blobId": "quilt-1", "endEpoch": 9 } },
+            "storedQuiltBlobs": [
+                { "identifier": "a.json", "quiltPatchId": "patch-a" },
+                { "identifier": "b.json", "quiltPatchId": "patch-b" }
+            ]
+        });
+        let quilt = parse_quilt_store_response(&response).unwrap();
+        assert_eq!(quilt.blob.blob_id, "quilt-1");
+        assert_eq!(quilt.patch_id("b.json"), Some("patch-b"));
+        assert_eq!(quilt.patch_id("c.json"), None);
+        assert!(parse_quilt_store_response(&json!({ "blobStoreResult": response["blobStoreResult"] })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_stores_and_reads_quilts() {
+        use axum::extract::Path;
+        use axum::http::{HeaderMap, StatusCode};
+        use axum::routing::{get, put};
+
+        let app = axum::Router::new()
+            .route(
+                "/v1/quilts",
+                put(|headers: HeaderMap, body: String| async move {
+                    let content_type = headers[axum::http::header::CONTENT_TYPE].to_str().unwrap().to_string();
+                    assert!(content_type.starts_with("multipart/form-data; boundary="));
+                    assert!(body.contains("name=\"note 1.txt\""));
+                    axum::Json(json!({
+                        "blobStoreResult": { "alreadyCertified": { "blobId": "quilt-1", "endEpoch": 9 } },
+                        "storedQuiltBlobs": [{ "identifier": "note 1.txt", "quiltPatchId": "patch-1" }]
+                    }))
+                }),
+            )
+            .route(
+                "/v1/blobs/by-quilt-patch-id/:id",
+                get(|Path(id): Path<String>| async move {
+                    if id == "patch-1" { Ok("hello") } else { Err(StatusCode::NOT_FOUND) }
+                }),
+            )
+            .route(
+                "/v1/blobs/by-quilt-id/:quilt/:identifier",
+                get(|Path((quilt, identifier)): Path<(String, String)>| async move {
+                    if quilt == "quilt-1" && identifier == "note 1.txt" { Ok("hello") } else { Err(StatusCode::NOT_FOUND) }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = WalrusClient::new(&url, &url).unwrap().with_retry(1, Duration::from_millis(1));
+        let quilt = client
+            .put_quilt(&[QuiltFile::new("note 1.txt", b"hello".to_vec())], 5)
+            .await
+            .unwrap();
+        let patch_id = quilt.patch_id("note 1.txt").unwrap();
+        assert_eq!(client.get_quilt_patch(patch_id).await.unwrap(), b"hello");
+        assert_eq!(client.get_quilt_file("quilt-1", "note 1.txt").await.unwrap(), b"hello");
+        assert!(matches!(
+            client.get_quilt_file("quilt-1", "missing").await,
+            Err(EnclaveError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reads_fail_over_to_fallback_aggregators() {
+        use axum::http::StatusCode;
+        use axum::routing::get;
+
+        async fn serve(app: axum::Router) -> String {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            url
+        }
+        let down = serve(axum::Router::new().route("/v1/blobs/:id", get(|| async { StatusCode::BAD_GATEWAY }))).await;
+        let missing = serve(axum::Router::new()).await;
+        let up = serve(axum::Router::new().route("/v1/blobs/:id", get(|| async { "content" }))).await;
+
+        let client = WalrusClient::new(&down, &down)
+            .unwrap()
+            .with_retry(1, Duration::from_millis(1))
+            .with_fallback_aggregators(&[&down, &format!("{}/", up)]);
+        assert_eq!(client.get_blob("blob-1").await.unwrap(), b"content");
+        assert_eq!(
+            client.blob_status("blob-1").await.unwrap(),
+            BlobStatus::Available { size_bytes: Some(7) }
+        );
+
+        // A blob the aggregator does not have is not looked up on the fallbacks
+        let client = WalrusClient::new(&missing, &missing)
+            .unwrap()
+            .with_fallback_aggregators(&[&up]);
+        assert!(matches!(client.get_blob("blob-1").await, Err(EnclaveError::NotFound(_))));
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Index warm-up after large ingests. Points written by a bulk ingest land in unoptimized
+//! segments that Qdrant indexes in the background, so the first searches after a backfill
+//! are slow. With `INGEST_WARMUP_MIN_POINTS` set, an `/embedding_ingest` job that stored at
+//! least that many points stays running while the server asks Qdrant to optimize the
+//! collection, waits up to `INGEST_WARMUP_TIMEOUT_SECS` for it to turn green and runs
+//! `INGEST_WARMUP_QUERIES` searches with stored vectors. The outcome is reported as the
+//! job's `warmup`; a failed warm-up never fails the job.
+
+use crate::collections::SearchParams;
+use crate::qdrant::QdrantClient;
+use crate::AppState;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Default `INGEST_WARMUP_TIMEOUT_SECS`.
+pub const DEFAULT_INGEST_WARMUP_TIMEOUT_SECS: u64 = 120;
+/// Default `INGEST_WARMUP_QUERIES`.
+pub const DEFAULT_INGEST_WARMUP_QUERIES: u32 = 8;
+/// Qdrant status of a collection whose segments are all optimized.
+const OPTIMIZED_STATUS: &str = "green";
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Neighbours fetched by each warm-up search.
+const WARMUP_SEARCH_LIMIT: u64 = 10;
+
+/// Which ingests are followed by a warm-up, and how long it may take.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmupPolicy {
+    /// Points an ingest has to store to be warmed up after, off when unset
+    pub min_points: Option<u64>,
+    /// Longest wait for the optimizers
+    pub timeout_secs: u64,
+    /// Warm-up searches run once the wait is over
+    pub queries: u32,
+}
+
+impl Default for WarmupPolicy {
+    fn default() -> Self {
+        Self {
+            min_points: None,
+            timeout_secs: DEFAULT_INGEST_WARMUP_TIMEOUT_SECS,
+            queries: DEFAULT_INGEST_WARMUP_QUERIES,
+        }
+    }
+}
+
+impl WarmupPolicy {
+    /// Whether an ingest that stored `points` is warmed up after.
+    pub fn applies_to(&self, points: u64) -> bool {
+        self.min_points.is_some_and(|min| points >= min)
+    }
+}
+
+/// Outcome of a warm-up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct WarmupReport {
+    pub collection: String,
+    /// Qdrant status of the collection when the wait ended
+    pub status: String,
+    /// The collection was optimized within `INGEST_WARMUP_TIMEOUT_SECS`
+    pub optimized: bool,
+    /// Time spent waiting for the optimizers
+    pub wait_ms: u64,
+    /// Warm-up searches run
+    pub searches: u32,
+    /// Why the warm-up stopped early
+    pub error: Option<String>,
+}
+
+/// Points an embedding ingest stored, from the `data` of its task response.
+pub fn stored_points(data: &serde_json::Value) -> u64 {
+    data["successfulEmbeddings"]
+        .as_u64()
+        .or_else(|| data["processedCount"].as_u64())
+        .unwrap_or(0)
+}
+
+/// Optimize `collection` and warm it up with searches, as [WarmupPolicy] allows.
+pub async fn warm_up(
+    client: &QdrantClient,
+    collection: &str,
+    policy: &WarmupPolicy,
+    params: &SearchParams,
+) -> WarmupReport {
+    let mut report = WarmupReport {
+        collection: collection.to_string(),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(policy.timeout_secs);
+    let result = async {
+        client.trigger_optimizers(collection).await?;
+        loop {
+            let info = client
+                .collection_info(collection)
+                .await?
+                .ok_or_else(|| EnclaveError::NotFound(format!("Collection {} does not exist", collection)))?;
+            report.status = info.status;
+            report.optimized = report.status == OPTIMIZED_STATUS;
+            if report.optimized || Instant::now() + STATUS_POLL_INTERVAL > deadline {
+                break;
+            }
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+        report.wait_ms = started.elapsed().as_millis() as u64;
+
+        for vector in client.sample_vectors(collection, u64::from(policy.queries)).await? {
+            client.search(collection, &vector, WARMUP_SEARCH_LIMIT, params).await?;
+            report.searches += 1;
+        }
+        Ok::<_, EnclaveError>(())
+    }
+    .await;
+    if let Err(e) = result {
+        report.error = Some(e.status_and_message().1);
+    }
+    report
+}
+
+/// Warm up `collection` after an ingest job whose response carries `data`, when the ingest
+/// was large enough. None when no warm-up was due.
+pub async fn after_ingest(state: &AppState, collection: &str, data: &serde_json::Value) -> Option<WarmupReport> {
+    let policy = &state.config.ingest_warmup;
+    let points = stored_points(data);
+    if !policy.applies_to(points) {
+        return None;
+    }
+    info!("Warming up collection {} after ingesting {} points", collection, points);
+    let report = match QdrantClient::from_state(state) {
+        Ok(client) => warm_up(&client, collection, policy, &state.collection_tuning.get(collection)).await,
+        Err(e) => WarmupReport {
+            collection: collection.to_string(),
+            error: Some(e.status_and_message().1),
+            ..Default::default()
+        },
+    };
+    match &report.error {
+        Some(error) => warn!("Warm-up of collection {} stopped: {}", collection, error),
+        None if !report.optimized => warn!(
+            "Collection {} still {} after {}ms, warmed up with {} searches",
+            collection, report.status, report.wait_ms, report.searches
+        ),
+        None => info!(
+            "Collection {} optimized in {}ms, warmed up with {} searches",
+            collection, report.wait_ms, report.searches
+        ),
+    }
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{patch, post};
+    use axum::Json;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_policy_and_stored_points() {
+        assert!(!WarmupPolicy::default().applies_to(1_000_000));
+        let policy = WarmupPolicy {
+            min_points: Some(1000),
+            ..Default::default()
+        };
+        assert!(policy.applies_to(1000));
+        assert!(!policy.applies_to(999));
+        assert_eq!(stored_points(&json!({ "successfulEmbeddings": 1200, "processedCount": 3 })), 1200);
+        assert_eq!(stored_points(&json!({ "processedCount": 3 })), 3);
+        assert_eq!(stored_points(&json!({})), 0);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_waits_for_optimizers_and_searches() {
+        let polls = Arc::new(AtomicU32::new(0));
+        let searches = Arc::new(AtomicU32::new(0));
+        let (polls_seen, searches_seen) = (polls.clone(), searches.clone());
+        let app = axum::Router::new()
+            .route(
+                "/collections/:name",
+                patch(|Json(body): Json<serde_json::Value>| async move {
+                    assert!(body["optimizers_config"].is_object());
+                    Json(json!({ "result": true }))
+                })
+                .get(move || {
+                    // Optimizing on the first poll, optimized on the next
+                    let status = if polls.fetch_add(1, Ordering::SeqCst) == 0 { "yellow" } else { "green" };
+                    async move { Json(json!({ "result": { "status": status, "config": {} } })) }
+                }),
+            )
+            .route(
+                "/collections/:name/points/scroll",
+                post(|| async { Json(json!({ "result": { "points": [{ "id": 1, "vector": [0.1, 0.2] }, { "id": 2, "vector": [0.3, 0.4] }] } })) }),
+            )
+            .route(
+                "/collections/:name/points/search",
+                post(move |Json(body): Json<serde_json::Value>| {
+                    searches.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        assert_eq!(body["params"]["hnsw_ef"], 64);
+                        Json(json!({ "result": [{ "id": 1, "score": 1.0 }] }))
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let client = QdrantClient::new(&url, None).unwrap();
+        let policy = WarmupPolicy {
+            min_points: Some(1),
+            timeout_secs: 10,
+            queries: 2,
+        };
+        let params = SearchParams { hnsw_ef: Some(64), exact: None };
+        let report = warm_up(&client, "messages", &policy, &params).await;
+        assert_eq!(report.error, None);
+        assert!(report.optimized);
+        assert_eq!(report.status, "green");
+        assert_eq!(report.searches, 2);
+        assert_eq!(polls_seen.load(Ordering::SeqCst), 2);
+        assert_eq!(searches_seen.load(Ordering::SeqCst), 2);
+    }
+}
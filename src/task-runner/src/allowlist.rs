@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Allowlist format read by the server: permitted versions by package name.
+#[derive(Debug, Default, Serialize)]
+pub struct DependencyAllowlist {
+    pub packages: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Build an allowlist permitting exactly the packages resolved in a `package-lock.json`.
+pub fn allowlist_from_lockfile(content: &str) -> Result<DependencyAllowlist> {
+    let lock: serde_json::Value = serde_json::from_str(content).context("Invalid package-lock.json")?;
+    let packages = lock["packages"]
+        .as_object()
+        .context("package-lock.json has no `packages` section (lockfileVersion >= 2 required)")?;
+
+    let mut allowlist = DependencyAllowlist::default();
+    for (path, entry) in packages.iter().filter(|(path, _)| !path.is_empty()) {
+        let name = match entry["name"].as_str() {
+            Some(name) => name.to_string(),
+            None => match path.rfind("node_modules/") {
+                Some(i) => path[i + "node_modules/".len()..].to_string(),
+                None => path.clone(),
+            },
+        };
+        let version = entry["version"].as_str().unwrap_or_default().to_string();
+        allowlist.packages.entry(name).or_default().insert(version);
+    }
+    Ok(allowlist)
+}
+
+/// Parse a hex encoded 32 byte Ed25519 secret key.
+pub fn parse_signing_key(hex_key: &str) -> Result<SigningKey> {
+    let bytes = hex::decode(hex_key.trim().trim_start_matches("0x")).context("Signing key is not valid hex")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Signing key must be 32 bytes"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Write the allowlist and its detached hex signature (`<output>.sig`).
+/// Returns the hex public key to configure as `DEPENDENCY_ALLOWLIST_PUBKEY`.
+pub fn write_signed_allowlist(
+    allowlist: &DependencyAllowlist,
+    output: &Path,
+    signing_key: &SigningKey,
+) -> Result<String> {
+    let content = serde_json::to_vec_pretty(allowlist)?;
+    let signature = signing_key.sign(&content);
+    std::fs::write(output, &content).with_context(|| format!("Failed to write {}", output.display()))?;
+
+    let mut signature_path = output.as_os_str().to_owned();
+    signature_path.push(".sig");
+    let signature_path = PathBuf::from(signature_path);
+    std::fs::write(&signature_path, hex::encode(signature.to_bytes()))
+        .with_context(|| format!("Failed to write {}", signature_path.display()))?;
+    Ok(hex::encode(signing_key.verifying_key().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signature, Verifier};
+
+    #[test]
+    fn test_signed_allowlist_from_lockfile() {
+        let lock = r#"{
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "name": "simple-nodejs-task", "version": "1.0.0" },
+                "node_modules/axios": { "version": "1.6.0" },
+                "node_modules/a/node_modules/axios": { "version": "0.27.2" }
+            }
+        }"#;
+        let allowlist = allowlist_from_lockfile(lock).unwrap();
+        assert_eq!(allowlist.packages.len(), 1);
+        assert_eq!(allowlist.packages["axios"].len(), 2);
+
+        let dir = std::env::temp_dir().join(format!("allowlist-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("dependency-allowlist.json");
+        let key = parse_signing_key(&hex::encode([5u8; 32])).unwrap();
+        let public_key = write_signed_allowlist(&allowlist, &output, &key).unwrap();
+
+        let content = std::fs::read(&output).unwrap();
+        let signature = hex::decode(std::fs::read_to_string(dir.join("dependency-allowlist.json.sig")).unwrap()).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        assert_eq!(public_key, hex::encode(key.verifying_key().as_bytes()));
+        assert!(key.verifying_key().verify(&content, &signature).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
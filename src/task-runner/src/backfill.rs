@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+
+/// Seconds each long-poll of an ingest job waits on the server.
+const JOB_WAIT_SECS: u64 = 120;
+
+/// One blob/file/policy tuple to ingest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackfillEntry {
+    #[serde(rename = "walrusBlobId")]
+    pub walrus_blob_id: String,
+    #[serde(rename = "onChainFileObjId")]
+    pub on_chain_file_obj_id: String,
+    #[serde(rename = "policyObjectId")]
+    pub policy_object_id: String,
+    /// Optional per-entry threshold, falls back to `--threshold`.
+    pub threshold: Option<String>,
+}
+
+impl BackfillEntry {
+    /// Key used to track progress in the state file.
+    pub fn key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.walrus_blob_id, self.on_chain_file_obj_id, self.policy_object_id
+        )
+    }
+}
+
+/// Where entries are ingested.
+#[derive(Debug, Clone)]
+pub enum BackfillTarget {
+    /// POST to the server's `/embedding_ingest` endpoint.
+    Server { url: String },
+    /// Run the embedding operation of a local Node.js task directory.
+    Local { task_path: PathBuf, timeout_secs: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillOptions {
+    pub input: PathBuf,
+    pub state_file: PathBuf,
+    pub target: BackfillTarget,
+    pub threshold: String,
+    pub concurrency: usize,
+    /// Maximum number of ingest calls started per second, 0 for unlimited.
+    pub rate_per_sec: f64,
+    pub resume: bool,
+}
+
+/// One line of the state file.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProgressRecord {
+    key: String,
+    succeeded: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BackfillSummary {
+    pub total: usize,
+    pub skipped: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Load entries from a JSON array (`.json`) or a CSV file with a header row.
+pub fn load_entries(path: &Path) -> Result<Vec<BackfillEntry>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read backfill input {}", path.display()))?;
+    if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_str(&content).context("Invalid JSON backfill input")
+    } else {
+        parse_csv(&content)
+    }
+}
+
+/// Parse CSV input. Required columns: walrusBlobId, onChainFileObjId, policyObjectId;
+/// optional column: threshold. Values must not contain commas.
+pub fn parse_csv(content: &str) -> Result<Vec<BackfillEntry>> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    let header: Vec<&str> = lines
+        .next()
+        .context("CSV input is empty")?
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let blob_col = column("walrusBlobId").context("CSV header is missing walrusBlobId")?;
+    let file_col = column("onChainFileObjId").context("CSV header is missing onChainFileObjId")?;
+    let policy_col = column("policyObjectId").context("CSV header is missing policyObjectId")?;
+    let threshold_col = column("threshold");
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |col: usize| {
+                fields
+                    .get(col)
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string())
+                    .with_context(|| format!("CSV row {} is missing column {}", i + 2, header[col]))
+            };
+            Ok(BackfillEntry {
+                walrus_blob_id: field(blob_col)?,
+                on_chain_file_obj_id: field(file_col)?,
+                policy_object_id: field(policy_col)?,
+                threshold: threshold_col.and_then(|col| field(col).ok()),
+            })
+        })
+        .collect()
+}
+
+/// Keys of entries that already succeeded according to the state file.
+fn load_completed(state_file: &Path) -> Result<HashSet<String>> {
+    if !state_file.exists() {
+        return Ok(HashSet::new());
+    }
+    let content = std::fs::read_to_string(state_file)
+        .with_context(|| format!("Failed to read state file {}", state_file.display()))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<ProgressRecord>(line).ok())
+        .filter(|record| record.succeeded)
+        .map(|record| record.key)
+        .collect())
+}
+
+/// Spaces out ingest calls to at most `rate_per_sec` starts per second.
+struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate_per_sec: f64) -> Self {
+        Self {
+            interval: (rate_per_sec > 0.0).then(|| Duration::from_secs_f64(1.0 / rate_per_sec)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let start_at = {
+            let mut next = self.next.lock().await;
+            let start_at = (*next).max(Instant::now());
+            *next = start_at + interval;
+            start_at
+        };
+        tokio::time::sleep_until(start_at.into()).await;
+    }
+}
+
+fn print_progress(done: usize, total: usize, failed: usize) {
+    const WIDTH: usize = 30;
+    let filled = if total == 0 { WIDTH } else { done * WIDTH / total };
+    eprint!(
+        "\r📦 [{}{}] {}/{} ({} failed)",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        done,
+        total,
+        failed
+    );
+    let _ = std::io::stderr().flush();
+}
+
+/// Send a request to the server and return the `data` of its response envelope.
+async fn server_data(request: reqwest::RequestBuilder) -> Result<serde_json::Value> {
+    let response = request.send().await.context("Request to server failed")?;
+    let status = response.status();
+    let mut envelope: serde_json::Value = response.json().await.context("Invalid server response")?;
+    if !status.is_success() || !envelope["error"].is_null() {
+        anyhow::bail!("Server returned {}: {}", status, envelope["error"]);
+    }
+    Ok(envelope["data"].take())
+}
+
+async fn ingest(client: &reqwest::Client, options: &BackfillOptions, entry: &BackfillEntry) -> Result<()> {
+    let threshold = entry.threshold.clone().unwrap_or_else(|| options.threshold.clone());
+    match &options.target {
+        BackfillTarget::Server { url } => {
+            let body = serde_json::json!({
+                "payload": {
+                    "walrusBlobId": entry.walrus_blob_id,
+                    "onChainFileObjId": entry.on_chain_file_obj_id,
+                    "policyObjectId": entry.policy_object_id,
+                    "threshold": threshold,
+                    // Backfills must not delay interactive requests.
+                    "priority": "low",
+                }
+            });
+            let url = url.trim_end_matches('/');
+            let job = server_data(client.post(format!("{}/embedding_ingest", url)).json(&body)).await?;
+            let job_id = job["id"]
+                .as_str()
+                .context("Server response is missing the job ID")?
+                .to_string();
+
+            // Long-poll the job until it finishes
+            let job = loop {
+                let wait_url = format!("{}/jobs/{}/wait?timeout={}", url, job_id, JOB_WAIT_SECS);
+                let wait = server_data(client.get(wait_url)).await?;
+                if wait["timed_out"] != true {
+                    break wait["job"].clone();
+                }
+            };
+            if job["status"] == "failed" {
+                anyhow::bail!("Ingest job {} failed: {}", job_id, job["error"]);
+            }
+            if job["result"]["data"]["status"] == "failed" {
+                anyhow::bail!("Ingest task failed: {}", job["result"]["data"]["error"]);
+            }
+            Ok(())
+        }
+        BackfillTarget::Local { task_path, timeout_secs } => {
+            let runner = crate::NodeTaskRunner::new(task_path.to_string_lossy().into_owned()).with_args(vec![
+                "--operation".to_string(),
+                "embedding".to_string(),
+                "--walrus-blob-id".to_string(),
+                entry.walrus_blob_id.clone(),
+                "--on-chain-file-obj-id".to_string(),
+                entry.on_chain_file_obj_id.clone(),
+                "--policy-object-id".to_string(),
+                entry.policy_object_id.clone(),
+                "--threshold".to_string(),
+                threshold,
+            ]);
+            let output = runner.run_quiet(*timeout_secs).await?;
+            if output.exit_code != 0 {
+                anyhow::bail!("Task exited with code {}: {}", output.exit_code, output.stderr.trim());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Run the backfill, appending each outcome to the state file so an interrupted
+/// or partially failed run can be resumed.
+pub async fn run_backfill(options: BackfillOptions) -> Result<BackfillSummary> {
+    let entries = load_entries(&options.input)?;
+    let completed = if options.resume {
+        load_completed(&options.state_file)?
+    } else {
+        HashSet::new()
+    };
+
+    let mut summary = BackfillSummary {
+        total: entries.len(),
+        ..Default::default()
+    };
+    let pending: Vec<BackfillEntry> = entries
+        .into_iter()
+        .filter(|entry| !completed.contains(&entry.key()))
+        .collect();
+    summary.skipped = summary.total - pending.len();
+    println!(
+        "🚚 Backfilling {} entries ({} already completed, concurrency {})",
+        pending.len(),
+        summary.skipped,
+        options.concurrency
+    );
+
+    let state_file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&options.state_file)
+        .await
+        .with_context(|| format!("Failed to open state file {}", options.state_file.display()))?;
+    let state_file = Arc::new(Mutex::new(state_file));
+    let options = Arc::new(options);
+    let client = reqwest::Client::new();
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let limiter = Arc::new(RateLimiter::new(options.rate_per_sec));
+
+    let total = pending.len();
+    let mut tasks = JoinSet::new();
+    for entry in pending {
+        let permit = semaphore.clone().acquire_owned().await?;
+        limiter.wait().await;
+        let client = client.clone();
+        let options = options.clone();
+        let state_file = state_file.clone();
+        tasks.spawn(async move {
+            let result = ingest(&client, &options, &entry).await;
+            let record = ProgressRecord {
+                key: entry.key(),
+                succeeded: result.is_ok(),
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            };
+            let mut line = serde_json::to_string(&record)?;
+            line.push('\n');
+            state_file.lock().await.write_all(line.as_bytes()).await?;
+            drop(permit);
+            if let Err(e) = &result {
+                eprintln!("\n❌ {}: {:#}", entry.key(), e);
+            }
+            anyhow::Ok(result.is_ok())
+        });
+
+        // Reap finished tasks so progress stays current.
+        while let Some(done) = tasks.try_join_next() {
+            record_outcome(&mut summary, done??);
+            print_progress(summary.succeeded + summary.failed, total, summary.failed);
+        }
+    }
+    while let Some(done) = tasks.join_next().await {
+        record_outcome(&mut summary, done??);
+        print_progress(summary.succeeded + summary.failed, total, summary.failed);
+    }
+    state_file.lock().await.flush().await?;
+    eprintln!();
+
+    Ok(summary)
+}
+
+fn record_outcome(summary: &mut BackfillSummary, succeeded: bool) {
+    if succeeded {
+        summary.succeeded += 1;
+    } else {
+        summary.failed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "walrusBlobId,onChainFileObjId,policyObjectId,threshold\n\
+                   blob-1,0xfile1,0xpolicy1,2\n\
+                   # comment lines are ignored\n\
+                   blob-2,0xfile2,0xpolicy2,\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].walrus_blob_id, "blob-1");
+        assert_eq!(entries[0].threshold.as_deref(), Some("2"));
+        assert_eq!(entries[1].policy_object_id, "0xpolicy2");
+        assert_eq!(entries[1].threshold, None);
+
+        assert!(parse_csv("walrusBlobId,policyObjectId\nblob,0xpolicy\n").is_err());
+        assert!(parse_csv("walrusBlobId,onChainFileObjId,policyObjectId\nblob,,0xpolicy\n").is_err());
+    }
+
+    #[test]
+    fn test_load_json_and_completed_state() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("entries.json");
+        std::fs::write(
+            &input,
+            r#"[{"walrusBlobId":"blob-1","onChainFileObjId":"0xf","policyObjectId":"0xp"}]"#,
+        )
+        .unwrap();
+        let entries = load_entries(&input).unwrap();
+        assert_eq!(entries[0].key(), "blob-1:0xf:0xp");
+
+        let state = dir.path().join("state.jsonl");
+        std::fs::write(
+            &state,
+            "{\"key\":\"a\",\"succeeded\":true,\"error\":null}\n\
+             {\"key\":\"b\",\"succeeded\":false,\"error\":\"boom\"}\n\
+             not json\n",
+        )
+        .unwrap();
+        let completed = load_completed(&state).unwrap();
+        assert!(completed.contains("a"));
+        assert!(!completed.contains("b"));
+    }
+}
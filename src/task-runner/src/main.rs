@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use std::path::PathBuf;
 use std::process::Stdio;
 use tokio::process::Command as TokioCommand;
@@ -7,6 +7,13 @@ use tokio::io::{AsyncBufReadExt, BufReader};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+mod allowlist;
+mod backfill;
+mod smoke;
+
+use backfill::{BackfillOptions, BackfillTarget};
+use smoke::SmokeOptions;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let matches = Command::new("Task Runner")
@@ -28,8 +35,134 @@ async fn main() -> Result<()> {
                 .help("Timeout for task execution in seconds")
                 .default_value("30")
         )
+        .subcommand(
+            Command::new("backfill")
+                .about("Ingest a CSV/JSON list of blob/file/policy tuples")
+                .arg(
+                    Arg::new("input")
+                        .required(true)
+                        .value_name("FILE")
+                        .help("CSV (walrusBlobId,onChainFileObjId,policyObjectId[,threshold]) or JSON array")
+                )
+                .arg(
+                    Arg::new("server")
+                        .long("server")
+                        .value_name("URL")
+                        .help("Nautilus server to send /embedding_ingest requests to")
+                        .conflicts_with("local")
+                        .required_unless_present("local")
+                )
+                .arg(
+                    Arg::new("local")
+                        .long("local")
+                        .value_name("PATH")
+                        .help("Run the embedding operation of a local Node.js task directory instead")
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .value_name("N")
+                        .help("Default Seal threshold for entries without one")
+                        .default_value("2")
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .short('c')
+                        .long("concurrency")
+                        .value_name("N")
+                        .help("Number of entries ingested in parallel")
+                        .default_value("2")
+                )
+                .arg(
+                    Arg::new("rate")
+                        .long("rate")
+                        .value_name("PER_SECOND")
+                        .help("Maximum ingest calls started per second (0 = unlimited)")
+                        .default_value("0")
+                )
+                .arg(
+                    Arg::new("state-file")
+                        .long("state-file")
+                        .value_name("FILE")
+                        .help("Progress file used to resume [default: <input>.progress.jsonl]")
+                )
+                .arg(
+                    Arg::new("no-resume")
+                        .long("no-resume")
+                        .action(ArgAction::SetTrue)
+                        .help("Ingest every entry even if the state file marks it completed")
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .short('T')
+                        .long("timeout")
+                        .value_name("SECONDS")
+                        .help("Per-entry timeout for --local runs")
+                        .default_value("360")
+                )
+        )
+        .subcommand(
+            Command::new("smoke")
+                .about("Post-release verification of a deployed Nautilus server")
+                .arg(
+                    Arg::new("server")
+                        .long("server")
+                        .value_name("URL")
+                        .required(true)
+                        .help("Base URL of the Nautilus server")
+                )
+                .arg(
+                    Arg::new("process-arg")
+                        .long("process-arg")
+                        .value_name("ARG")
+                        .action(ArgAction::Append)
+                        .allow_hyphen_values(true)
+                        .help("Argument for the /process_data check (repeatable); the check is skipped if none are given")
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .short('T')
+                        .long("timeout")
+                        .value_name("SECONDS")
+                        .help("Timeout for the /process_data task")
+                        .default_value("120")
+                )
+        )
+        .subcommand(
+            Command::new("sign-allowlist")
+                .about("Generate and sign the Node.js task dependency allowlist from package-lock.json")
+                .arg(
+                    Arg::new("lockfile")
+                        .long("lockfile")
+                        .value_name("FILE")
+                        .help("Lockfile to allowlist")
+                        .default_value("nodejs-task/package-lock.json")
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Allowlist to write; the signature is written to <FILE>.sig")
+                        .default_value("nodejs-task/dependency-allowlist.json")
+                )
+                .arg(
+                    Arg::new("key-file")
+                        .long("key-file")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("File containing the hex encoded Ed25519 signing key")
+                )
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("backfill", sub_matches)) => return backfill_command(sub_matches).await,
+        Some(("smoke", sub_matches)) => return smoke_command(sub_matches).await,
+        Some(("sign-allowlist", sub_matches)) => return sign_allowlist_command(sub_matches),
+        _ => {}
+    }
+
     let task_path = matches.get_one::<String>("task-path").unwrap();
     let timeout_secs: u64 = matches.get_one::<String>("timeout")
         .unwrap()
@@ -59,6 +192,94 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn sign_allowlist_command(matches: &ArgMatches) -> Result<()> {
+    let lockfile = PathBuf::from(matches.get_one::<String>("lockfile").unwrap());
+    let output = PathBuf::from(matches.get_one::<String>("output").unwrap());
+    let key_file = matches.get_one::<String>("key-file").unwrap();
+
+    let lock = std::fs::read_to_string(&lockfile)
+        .with_context(|| format!("Failed to read {}", lockfile.display()))?;
+    let key = std::fs::read_to_string(key_file).with_context(|| format!("Failed to read {}", key_file))?;
+    let allowlist = allowlist::allowlist_from_lockfile(&lock)?;
+    let public_key = allowlist::write_signed_allowlist(&allowlist, &output, &allowlist::parse_signing_key(&key)?)?;
+
+    println!("✅ Allowlisted {} packages in {}", allowlist.packages.len(), output.display());
+    println!("🔑 DEPENDENCY_ALLOWLIST_PUBKEY={}", public_key);
+    Ok(())
+}
+
+async fn smoke_command(matches: &ArgMatches) -> Result<()> {
+    let options = SmokeOptions {
+        server: matches.get_one::<String>("server").unwrap().clone(),
+        process_args: matches
+            .get_many::<String>("process-arg")
+            .map(|args| args.cloned().collect())
+            .unwrap_or_default(),
+        timeout_secs: matches.get_one::<String>("timeout")
+            .unwrap()
+            .parse()
+            .context("Invalid timeout value")?,
+    };
+
+    println!("🩺 Smoke testing {}", options.server);
+    let results = smoke::run_smoke(options).await?;
+    for result in &results {
+        let icon = if result.passed { "✅" } else { "❌" };
+        println!("{} {} ({} ms): {}", icon, result.name, result.elapsed_ms, result.detail);
+    }
+    if results.iter().any(|r| !r.passed) {
+        eprintln!("❌ Smoke test failed");
+        std::process::exit(1);
+    }
+    println!("✅ Smoke test passed");
+    Ok(())
+}
+
+async fn backfill_command(matches: &ArgMatches) -> Result<()> {
+    let input = PathBuf::from(matches.get_one::<String>("input").unwrap());
+    let state_file = match matches.get_one::<String>("state-file") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!("{}.progress.jsonl", input.display())),
+    };
+    let target = match matches.get_one::<String>("server") {
+        Some(url) => BackfillTarget::Server { url: url.clone() },
+        None => BackfillTarget::Local {
+            task_path: PathBuf::from(matches.get_one::<String>("local").unwrap()),
+            timeout_secs: matches.get_one::<String>("timeout")
+                .unwrap()
+                .parse()
+                .context("Invalid timeout value")?,
+        },
+    };
+
+    let options = BackfillOptions {
+        input,
+        state_file: state_file.clone(),
+        target,
+        threshold: matches.get_one::<String>("threshold").unwrap().clone(),
+        concurrency: matches.get_one::<String>("concurrency")
+            .unwrap()
+            .parse()
+            .context("Invalid concurrency value")?,
+        rate_per_sec: matches.get_one::<String>("rate")
+            .unwrap()
+            .parse()
+            .context("Invalid rate value")?,
+        resume: !matches.get_flag("no-resume"),
+    };
+
+    let summary = backfill::run_backfill(options).await?;
+    println!(
+        "✅ Backfill finished: {} total, {} skipped, {} succeeded, {} failed",
+        summary.total, summary.skipped, summary.succeeded, summary.failed
+    );
+    if summary.failed > 0 {
+        eprintln!("❌ Re-run the same command to retry failed entries (progress: {})", state_file.display());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct TaskOutput {
     pub stdout: String,
@@ -68,12 +289,33 @@ pub struct TaskOutput {
 
 pub struct NodeTaskRunner {
     task_path: PathBuf,
+    args: Vec<String>,
+    verbose: bool,
 }
 
 impl NodeTaskRunner {
     pub fn new(task_path: String) -> Self {
         Self {
             task_path: PathBuf::from(task_path),
+            args: vec![],
+            verbose: true,
+        }
+    }
+
+    /// Arguments passed to `index.js`.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Run without echoing progress and task output, e.g. for batch runs.
+    pub async fn run_quiet(mut self, timeout_secs: u64) -> Result<TaskOutput> {
+        self.verbose = false;
+        self.validate_task_directory()?;
+        let timeout_duration = std::time::Duration::from_secs(timeout_secs);
+        match tokio::time::timeout(timeout_duration, self.execute_task()).await {
+            Ok(result) => result,
+            Err(_) => anyhow::bail!("Task execution timed out after {} seconds", timeout_secs),
         }
     }
 
@@ -125,13 +367,16 @@ impl NodeTaskRunner {
             anyhow::bail!("index.js not found in task directory");
         }
 
-        println!("✅ Task directory validation passed");
+        if self.verbose {
+            println!("✅ Task directory validation passed");
+        }
         Ok(())
     }
 
     async fn execute_task(&self) -> Result<TaskOutput> {
         let mut child = TokioCommand::new("node")
             .arg("index.js")
+            .args(&self.args)
             .current_dir(&self.task_path)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -152,6 +397,7 @@ impl NodeTaskRunner {
         let stderr_lines_clone = Arc::clone(&stderr_lines);
 
         // Read stdout and stderr concurrently
+        let verbose = self.verbose;
         let stdout_task = async move {
             let mut stdout_reader = stdout_reader;
             let mut line = String::new();
@@ -160,7 +406,9 @@ impl NodeTaskRunner {
                 match stdout_reader.read_line(&mut line).await {
                     Ok(0) => break, // EOF
                     Ok(_) => {
-                        print!("📝 {}", line);
+                        if verbose {
+                            print!("📝 {}", line);
+                        }
                         stdout_lines_clone.lock().await.push(line.clone());
                     }
                     Err(e) => {
@@ -179,7 +427,9 @@ impl NodeTaskRunner {
                 match stderr_reader.read_line(&mut line).await {
                     Ok(0) => break, // EOF
                     Ok(_) => {
-                        eprint!("🔴 {}", line);
+                        if verbose {
+                            eprint!("🔴 {}", line);
+                        }
                         stderr_lines_clone.lock().await.push(line.clone());
                     }
                     Err(e) => {
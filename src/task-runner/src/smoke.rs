@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// BCS envelope returned by the server for `Accept: application/bcs`.
+#[derive(Debug, Deserialize)]
+pub struct BcsSignedEnvelope {
+    pub intent_message: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmokeOptions {
+    pub server: String,
+    /// Arguments for the `/process_data` check. The check is skipped when empty.
+    pub process_args: Vec<String>,
+    pub timeout_secs: u64,
+}
+
+/// Outcome of a single smoke check.
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+    pub elapsed_ms: u128,
+}
+
+/// Extract `data` from the server's response envelope, failing on transport or API errors.
+async fn envelope_data(response: reqwest::Response) -> Result<Value> {
+    let status = response.status();
+    let envelope: Value = response.json().await.context("Response is not valid JSON")?;
+    if !status.is_success() || !envelope["error"].is_null() {
+        anyhow::bail!("HTTP {}: {}", status, envelope["error"]);
+    }
+    Ok(envelope["data"].clone())
+}
+
+/// Parse a hex encoded Ed25519 public key.
+pub fn parse_public_key(pk_hex: &str) -> Result<VerifyingKey> {
+    let bytes = hex::decode(pk_hex.trim_start_matches("0x")).context("Public key is not valid hex")?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).context("Invalid Ed25519 public key")
+}
+
+/// Decode a BCS response body and verify its signature. Returns the signed timestamp.
+pub fn verify_bcs_envelope(body: &[u8], public_key: &VerifyingKey) -> Result<u64> {
+    let envelope: BcsSignedEnvelope = bcs::from_bytes(body).context("Body is not a BCS signed envelope")?;
+    let signature = Signature::from_slice(&envelope.signature).context("Invalid signature encoding")?;
+    public_key
+        .verify(&envelope.intent_message, &signature)
+        .context("Signature does not match the enclave public key")?;
+
+    // IntentMessage layout: intent scope (u8) followed by timestamp_ms (u64, little endian).
+    let timestamp = envelope
+        .intent_message
+        .get(1..9)
+        .context("Intent message is too short")?;
+    Ok(u64::from_le_bytes(timestamp.try_into()?))
+}
+
+async fn check_health(client: &reqwest::Client, base: &str) -> Result<(String, VerifyingKey)> {
+    let data = envelope_data(client.get(format!("{}/health_check", base)).send().await?).await?;
+    let pk_hex = data["pk"].as_str().context("health_check did not report a public key")?;
+    let public_key = parse_public_key(pk_hex)?;
+    let unreachable: Vec<&String> = data["endpoints_status"]
+        .as_object()
+        .map(|m| m.iter().filter(|(_, ok)| ok != &&Value::Bool(true)).map(|(k, _)| k).collect())
+        .unwrap_or_default();
+    if data["config_status"]["config_valid"] != Value::Bool(true) {
+        anyhow::bail!("configuration is invalid");
+    }
+    if !unreachable.is_empty() {
+        anyhow::bail!("unreachable endpoints: {:?}", unreachable);
+    }
+    Ok((format!("pk {}", pk_hex), public_key))
+}
+
+async fn check_attestation(client: &reqwest::Client, base: &str) -> Result<String> {
+    let data = envelope_data(client.get(format!("{}/get_attestation", base)).send().await?).await?;
+    let document = data["attestation"]["attestationDocument"].as_str().unwrap_or_default();
+    if data["success"] != Value::Bool(true) || document.is_empty() {
+        anyhow::bail!("no attestation document returned");
+    }
+    Ok(format!("enclave {}", data["attestation"]["enclaveId"]))
+}
+
+async fn check_process_data(
+    client: &reqwest::Client,
+    base: &str,
+    options: &SmokeOptions,
+    public_key: &VerifyingKey,
+) -> Result<String> {
+    let response = client
+        .post(format!("{}/process_data", base))
+        .header(reqwest::header::ACCEPT, "application/bcs")
+        .json(&serde_json::json!({
+            "payload": {
+                "timeout_secs": options.timeout_secs,
+                "args": options.process_args,
+                "priority": "high",
+            }
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        anyhow::bail!("HTTP {}: {}", status, response.text().await.unwrap_or_default());
+    }
+    let body = response.bytes().await?;
+    let timestamp = verify_bcs_envelope(&body, public_key)?;
+    Ok(format!("signature verified, signed at {} ms", timestamp))
+}
+
+async fn timed<T>(
+    name: &'static str,
+    results: &mut Vec<CheckResult>,
+    check: impl std::future::Future<Output = Result<T>>,
+    describe: impl FnOnce(&T) -> String,
+) -> Option<T> {
+    let start = Instant::now();
+    let outcome = check.await;
+    let (passed, detail, value) = match outcome {
+        Ok(value) => (true, describe(&value), Some(value)),
+        Err(e) => (false, format!("{:#}", e), None),
+    };
+    results.push(CheckResult {
+        name,
+        passed,
+        detail,
+        elapsed_ms: start.elapsed().as_millis(),
+    });
+    value
+}
+
+/// Run all smoke checks against a deployed server.
+pub async fn run_smoke(options: SmokeOptions) -> Result<Vec<CheckResult>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(options.timeout_secs + 10))
+        .build()?;
+    let base = options.server.trim_end_matches('/').to_string();
+    let mut results = Vec::new();
+
+    let health = timed("health_check", &mut results, check_health(&client, &base), |(d, _)| d.clone()).await;
+    timed("get_attestation", &mut results, check_attestation(&client, &base), |d| d.clone()).await;
+
+    if options.process_args.is_empty() {
+        println!("⚠️  No --process-arg given, skipping /process_data and signature verification");
+    } else if let Some((_, public_key)) = health {
+        timed(
+            "process_data",
+            &mut results,
+            check_process_data(&client, &base, &options, &public_key),
+            |d| d.clone(),
+        )
+        .await;
+    } else {
+        results.push(CheckResult {
+            name: "process_data",
+            passed: false,
+            detail: "no public key available to verify the signature".to_string(),
+            elapsed_ms: 0,
+        });
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn test_verify_bcs_envelope() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = parse_public_key(&hex::encode(signing_key.verifying_key().as_bytes())).unwrap();
+
+        // intent scope 0, timestamp 1744038900000, followed by some payload bytes
+        let mut intent_message = vec![0u8];
+        intent_message.extend_from_slice(&1744038900000u64.to_le_bytes());
+        intent_message.extend_from_slice(b"payload");
+        let signature = signing_key.sign(&intent_message).to_bytes().to_vec();
+
+        let body = bcs::to_bytes(&(intent_message.clone(), signature.clone())).unwrap();
+        assert_eq!(verify_bcs_envelope(&body, &public_key).unwrap(), 1744038900000);
+
+        let mut tampered = intent_message;
+        tampered[9] ^= 1;
+        let body = bcs::to_bytes(&(tampered, signature)).unwrap();
+        assert!(verify_bcs_envelope(&body, &public_key).is_err());
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        assert!(parse_public_key("zz").is_err());
+        assert!(parse_public_key("00").is_err());
+    }
+}